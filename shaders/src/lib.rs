@@ -1,7 +1,7 @@
 #![allow(clippy::too_many_arguments)]
 #![no_std]
-use spirv_std::glam::{Mat4, Vec2, Vec3, Vec4, Vec4Swizzles, vec4};
-use spirv_std::image::Image2d;
+use spirv_std::glam::{Mat4, Vec2, Vec3, Vec4, Vec4Swizzles};
+use spirv_std::image::{Image2d, Image2dArray};
 use spirv_std::num_traits::Float;
 use spirv_std::{Sampler, spirv};
 
@@ -20,22 +20,153 @@ pub struct ShaderConstants {
     pub total_buffer_size: u32,
     pub start_index: u32,
     pub end_index: u32,
-    pub use_relative_position: u32,
     pub min_circle_size: f32,
+    pub max_circle_size: f32,
     pub last_relative_position: Vec3,
+    /// Position of the body lighting the objects, the most massive one.
+    pub light_position: Vec3,
+    /// Simulated seconds at which the latest trail samples were taken.
+    pub trail_time: f32,
+    /// How trails fade towards their end, one of the `TRAIL_FADE_*` constants.
+    pub trail_fade: u32,
+    /// Simulated seconds over which trails fade, for the fades by age.
+    pub trail_fade_time: f32,
+    /// Opacity of the newest part of the trails.
+    pub trail_opacity: f32,
+    /// What the color of trails shows, one of the `TRAIL_COLOR_*` constants.
+    pub trail_color: u32,
+    /// Colormap for trails colored by motion, one of the `COLORMAP_*` constants.
+    pub colormap: u32,
+    /// Base 2 logarithms of the values at the ends of the colormap.
+    pub trail_color_min: f32,
+    pub trail_color_max: f32,
 }
 
-#[spirv(vertex)]
-pub fn line_vs(
-    #[spirv(push_constant)] constants: &ShaderConstants,
+/// Trails fade by position in the trail buffer, whatever the time between samples.
+pub const TRAIL_FADE_SAMPLES: u32 = 0;
+/// Trails fade linearly with age, gone after `trail_fade_time`.
+pub const TRAIL_FADE_LINEAR: u32 = 1;
+/// Trails halve in opacity every `trail_fade_time`.
+pub const TRAIL_FADE_EXPONENTIAL: u32 = 2;
+
+/// Trails have the color of their object.
+pub const TRAIL_COLOR_OBJECT: u32 = 0;
+/// Trails are colored by the speed of their object where it passed.
+pub const TRAIL_COLOR_SPEED: u32 = 1;
+/// Trails are colored by the acceleration of their object where it passed.
+pub const TRAIL_COLOR_ACCELERATION: u32 = 2;
+
+pub const COLORMAP_VIRIDIS: u32 = 0;
+pub const COLORMAP_PLASMA: u32 = 1;
+pub const COLORMAP_CIVIDIS: u32 = 2;
+
+/// Evenly spaced stops of the matplotlib colormaps, interpolated linearly in between.
+const VIRIDIS: [Vec3; 5] = [
+    Vec3::new(0.267, 0.005, 0.329),
+    Vec3::new(0.231, 0.322, 0.545),
+    Vec3::new(0.129, 0.569, 0.549),
+    Vec3::new(0.369, 0.788, 0.384),
+    Vec3::new(0.992, 0.906, 0.145),
+];
+const PLASMA: [Vec3; 5] = [
+    Vec3::new(0.051, 0.031, 0.529),
+    Vec3::new(0.494, 0.012, 0.659),
+    Vec3::new(0.800, 0.278, 0.471),
+    Vec3::new(0.973, 0.584, 0.251),
+    Vec3::new(0.941, 0.976, 0.129),
+];
+const CIVIDIS: [Vec3; 5] = [
+    Vec3::new(0.000, 0.133, 0.306),
+    Vec3::new(0.208, 0.271, 0.424),
+    Vec3::new(0.400, 0.412, 0.439),
+    Vec3::new(0.584, 0.561, 0.471),
+    Vec3::new(0.996, 0.910, 0.220),
+];
+
+/// Color at `t`, from 0 to 1, along the colormap `map`.
+fn colormap(map: u32, t: f32) -> Vec3 {
+    let stops = if map == COLORMAP_PLASMA {
+        PLASMA
+    } else if map == COLORMAP_CIVIDIS {
+        CIVIDIS
+    } else {
+        VIRIDIS
+    };
+    let x = t.clamp(0.0, 1.0) * (stops.len() - 1) as f32;
+    let i = (x as usize).min(stops.len() - 2);
+    stops[i].lerp(stops[i + 1], x - i as f32)
+}
+
+/// Parameters of the bloom passes, see `bloom.rs` in the renderer.
+#[repr(C)]
+pub struct BloomConstants {
+    /// Offset between blur taps in texture coordinates, along the direction of the blur.
+    pub step: Vec2,
+    /// Luminance above which the scene glows.
+    pub threshold: f32,
+    /// Strength of the glow added back onto the scene.
+    pub intensity: f32,
+}
+
+/// Generates an entry point for every combination of the options that are compiled into
+/// the shaders, instead of branching on push constants. Each calls the function of the
+/// same name with `_impl` appended, with the options as const generic parameters.
+///
+/// The options, and the suffix they add to the entry point when enabled, in order:
+/// - `RELATIVE`, `_relative`: positions are drawn relative to the target object.
+///
+/// The renderer picks the entry point in `ShaderOptions::entry_point`, which must be
+/// kept in sync with this.
+macro_rules! permutations {
+    (
+        #[spirv($stage:ident)]
+        fn $name:ident / $relative:ident = $body:ident(
+            $($(#[$($attr:tt)*])* $arg:ident: $ty:ty),* $(,)?
+        );
+    ) => {
+        #[spirv($stage)]
+        pub fn $name($($(#[$($attr)*])* $arg: $ty),*) {
+            $body::<false>($($arg),*)
+        }
+
+        #[spirv($stage)]
+        pub fn $relative($($(#[$($attr)*])* $arg: $ty),*) {
+            $body::<true>($($arg),*)
+        }
+    };
+}
+
+permutations! {
+    #[spirv(vertex)]
+    fn line_vs / line_vs_relative = line_vs_impl(
+        #[spirv(push_constant)] constants: &ShaderConstants,
+        input_pos: Vec3,
+        input_idx: u32,
+        input_time: f32,
+        input_motion: Vec2,
+        instance_color: Vec3,
+        instance_size: f32,
+        rel_input_pos: Vec3,
+        rel_input_idx: u32,
+        #[spirv(uniform, descriptor_set = 0, binding = 0)] camera_uniform: &CameraUniform,
+        #[spirv(position, invariant)] out_pos: &mut Vec4,
+        out_color: &mut Vec4,
+    );
+}
+
+#[inline(always)]
+fn line_vs_impl<const RELATIVE: bool>(
+    constants: &ShaderConstants,
     input_pos: Vec3,
     input_idx: u32,
+    input_time: f32,
+    input_motion: Vec2,
     instance_color: Vec3,
     _instance_size: f32,
     rel_input_pos: Vec3,
     _rel_input_idx: u32,
-    #[spirv(uniform, descriptor_set = 0, binding = 0)] camera_uniform: &CameraUniform,
-    #[spirv(position, invariant)] out_pos: &mut Vec4,
+    camera_uniform: &CameraUniform,
+    out_pos: &mut Vec4,
     out_color: &mut Vec4,
 ) {
     let index_offset = (input_idx + constants.total_buffer_size - constants.start_index)
@@ -45,22 +176,38 @@ pub fn line_vs(
         - constants.start_index)
         % constants.total_buffer_size;
 
-    let floating_offset = index_offset as f32 / current_vertex_count as f32;
+    let age = constants.trail_time - input_time;
+    let fade = if constants.trail_fade == TRAIL_FADE_LINEAR {
+        (1.0 - age / constants.trail_fade_time).max(0.0)
+    } else if constants.trail_fade == TRAIL_FADE_EXPONENTIAL {
+        (-age / constants.trail_fade_time).exp2()
+    } else {
+        index_offset as f32 / current_vertex_count as f32
+    };
     // For some reason, doing the multiplication in two stages is much more stable
     // when zoomed in.
-    let pos = if constants.use_relative_position != 0 {
+    let pos = if RELATIVE {
         input_pos - rel_input_pos
     } else {
         input_pos
     };
     let pos_view = camera_uniform.view * Vec4::from((pos, 1.0));
     *out_pos = camera_uniform.projection * pos_view;
-    *out_color = vec4(
-        instance_color.x,
-        instance_color.y,
-        instance_color.z,
-        floating_offset,
-    );
+    let color = if constants.trail_color == TRAIL_COLOR_OBJECT {
+        instance_color
+    } else {
+        let value = if constants.trail_color == TRAIL_COLOR_SPEED {
+            input_motion.x
+        } else {
+            input_motion.y
+        };
+        let range = constants.trail_color_max - constants.trail_color_min;
+        colormap(
+            constants.colormap,
+            (value.max(1e-30).log2() - constants.trail_color_min) / range.max(1e-6),
+        )
+    };
+    *out_color = color.extend(fade * constants.trail_opacity);
 }
 
 #[spirv(fragment)]
@@ -70,6 +217,10 @@ pub fn line_fs(
     output: &mut Vec4,
 ) {
     //*output = Vec4::new(1.0, 1.0, 1.0, 1.0);
+    // Faded out entirely, so it must not hide what is behind it either.
+    if in_color.w <= 0.0 {
+        spirv_std::arch::kill();
+    }
     *output = in_color.xyz().extend(in_color.w);
 }
 
@@ -81,18 +232,42 @@ const CLIP_SPACE_COORD_QUAD_CCW: [Vec2; 6] = {
     [bl, br, tr, tr, tl, bl]
 };
 
-#[spirv(vertex)]
-pub fn circle_vs(
-    #[spirv(push_constant)] constants: &ShaderConstants,
-    #[spirv(vertex_index)] vertex_id: u32,
+permutations! {
+    #[spirv(vertex)]
+    fn circle_vs / circle_vs_relative = circle_vs_impl(
+        #[spirv(push_constant)] constants: &ShaderConstants,
+        #[spirv(vertex_index)] vertex_id: u32,
+        input_instance_pos: Vec3,
+        input_idx: u32,
+        input_instance_color: Vec3,
+        input_instance_size: f32,
+        input_instance_texture: u32,
+        #[spirv(uniform, descriptor_set = 0, binding = 0)] camera_uniform: &CameraUniform,
+        #[spirv(position)] out_pos: &mut Vec4,
+        out_color: &mut Vec4,
+        out_uv: &mut Vec2,
+        #[spirv(flat)] out_light: &mut Vec3,
+        #[spirv(flat)] out_sphere: &mut Vec3,
+        #[spirv(flat)] out_texture: &mut u32,
+    );
+}
+
+#[inline(always)]
+fn circle_vs_impl<const RELATIVE: bool>(
+    constants: &ShaderConstants,
+    vertex_id: u32,
     input_instance_pos: Vec3,
     _input_idx: u32,
     input_instance_color: Vec3,
     input_instance_size: f32,
-    #[spirv(uniform, descriptor_set = 0, binding = 0)] camera_uniform: &CameraUniform,
-    #[spirv(position)] out_pos: &mut Vec4,
+    input_instance_texture: u32,
+    camera_uniform: &CameraUniform,
+    out_pos: &mut Vec4,
     out_color: &mut Vec4,
     out_uv: &mut Vec2,
+    out_light: &mut Vec3,
+    out_sphere: &mut Vec3,
+    out_texture: &mut u32,
 ) {
     let index = vertex_id as usize % 6;
     let raw = CLIP_SPACE_COORD_QUAD_CCW[index];
@@ -101,7 +276,7 @@ pub fn circle_vs(
         raw.y,
     );
 
-    let pos = if constants.use_relative_position != 0 {
+    let pos = if RELATIVE {
         input_instance_pos - constants.last_relative_position
     } else {
         input_instance_pos
@@ -122,23 +297,265 @@ pub fn circle_vs(
         * Vec4::new(input_instance_size, 0.0, 0.0, 1.0))
     .xy()
     .length();
+    // Objects large on screen are drawn as meshes instead, see `tessellation.rs`.
+    let w = center_proj.w;
+    let tessellated = w > -input_instance_size
+        && (w < input_instance_size || projected_size > constants.max_circle_size * w);
     let projected_size = projected_size.max(constants.min_circle_size);
 
-    *out_pos = Vec4::from((
-        center_proj.xy() + projected_size * raw_shifted,
-        center_proj.z,
-        center_proj.w,
-    ));
+    *out_pos = if tessellated {
+        Vec4::ZERO
+    } else {
+        Vec4::from((
+            center_proj.xy() + projected_size * raw_shifted,
+            center_proj.z,
+            center_proj.w,
+        ))
+    };
 
     *out_color = Vec4::from((input_instance_color, 1.0));
     *out_uv = raw;
+    *out_texture = input_instance_texture;
+    *out_light = light_direction::<RELATIVE>(
+        constants,
+        camera_uniform,
+        center_view.xyz(),
+        input_instance_size,
+    );
+    *out_sphere = Vec3::new(
+        center_proj.z / center_proj.w,
+        -center_view.z,
+        input_instance_size,
+    );
 }
 
-#[spirv(fragment)]
-pub fn circle_fs(in_color: Vec4, in_uv: Vec2, out_color: &mut Vec4) {
+/// Direction in view space from a sphere at `center_view` with `radius` to the light
+/// source, or zero for the light source itself, which is not lit from outside.
+fn light_direction<const RELATIVE: bool>(
+    constants: &ShaderConstants,
+    camera_uniform: &CameraUniform,
+    center_view: Vec3,
+    radius: f32,
+) -> Vec3 {
+    let light = if RELATIVE {
+        constants.light_position - constants.last_relative_position
+    } else {
+        constants.light_position
+    };
+    let light_view = (camera_uniform.view * Vec4::from((light, 1.0))).xyz();
+    let to_light = light_view - center_view;
+    if to_light.length() > radius {
+        to_light.normalize()
+    } else {
+        Vec3::ZERO
+    }
+}
+
+/// Color of a sphere at the point with `normal` in view space: from its map if it has
+/// one, as layer `texture - 1`, otherwise `color`, lit from the direction `light`.
+fn shade_sphere(
+    color: Vec3,
+    normal: Vec3,
+    light: Vec3,
+    texture: u32,
+    camera_uniform: &CameraUniform,
+    textures: &Image2dArray,
+    sampler: &Sampler,
+) -> Vec3 {
+    let color = if texture == 0 {
+        color
+    } else {
+        // Maps are equirectangular, with the poles along z in world space.
+        let world = (camera_uniform.view.transpose() * Vec4::from((normal, 0.0))).xyz();
+        let lon = Float::atan2(world.y, world.x);
+        let lat = Float::asin(world.z.clamp(-1.0, 1.0));
+        let uv = Vec3::new(
+            0.5 + lon / core::f32::consts::TAU,
+            0.5 - lat / core::f32::consts::PI,
+            (texture - 1) as f32,
+        );
+        // Without mipmaps, and the explicit level avoids artifacts along the seam.
+        textures.sample_by_lod(*sampler, uv, 0.0).xyz()
+    };
+    if light.length_squared() == 0.0 {
+        return color;
+    }
+    // Blinn-Phong, with the camera far enough away to look along -z everywhere.
+    let diffuse = normal.dot(light).max(0.0);
+    let half = (light + Vec3::Z).normalize();
+    let specular = if diffuse > 0.0 {
+        Float::powi(normal.dot(half).max(0.0), 32) * 0.3
+    } else {
+        0.0
+    };
+    color * (0.1 + 0.9 * diffuse) + Vec3::splat(specular)
+}
+
+/// Shade the quad as a sphere facing the camera, lit from the direction `in_light` in
+/// view space, or unlit if it is zero. `in_sphere` holds the depth and distance of the
+/// center, and the radius, so the depth of the surface can be written.
+#[spirv(fragment(depth_replacing))]
+pub fn circle_fs(
+    in_color: Vec4,
+    in_uv: Vec2,
+    #[spirv(flat)] in_light: Vec3,
+    #[spirv(flat)] in_sphere: Vec3,
+    #[spirv(flat)] in_texture: u32,
+    #[spirv(uniform, descriptor_set = 0, binding = 0)] camera_uniform: &CameraUniform,
+    #[spirv(descriptor_set = 1, binding = 0)] textures: &Image2dArray,
+    #[spirv(descriptor_set = 1, binding = 1)] sampler: &Sampler,
+    #[spirv(frag_depth)] out_depth: &mut f32,
+    out_color: &mut Vec4,
+) {
     let radius = in_uv.length_squared();
+    // Outside the circle, so it must not write depth either.
+    if radius >= 1.0 {
+        spirv_std::arch::kill();
+    }
+    let normal = Vec3::new(in_uv.x, in_uv.y, (1.0 - radius).sqrt());
+
+    // Depth is inversely proportional to distance, see `Camera::projection`.
+    let distance = in_sphere.y - in_sphere.z * normal.z;
+    *out_depth = in_sphere.x * in_sphere.y / distance.max(1e-30);
+
+    let shaded = shade_sphere(
+        in_color.xyz(),
+        normal,
+        in_light,
+        in_texture,
+        camera_uniform,
+        textures,
+        sampler,
+    );
+    *out_color = Vec4::from((shaded, in_color.w));
+}
+
+permutations! {
+    #[spirv(vertex)]
+    fn mesh_vs / mesh_vs_relative = mesh_vs_impl(
+        #[spirv(push_constant)] constants: &ShaderConstants,
+        input_pos: Vec3,
+        input_color: Vec4,
+        #[spirv(uniform, descriptor_set = 0, binding = 0)] camera_uniform: &CameraUniform,
+        #[spirv(position)] out_pos: &mut Vec4,
+        out_color: &mut Vec4,
+    );
+}
+
+#[inline(always)]
+fn mesh_vs_impl<const RELATIVE: bool>(
+    constants: &ShaderConstants,
+    input_pos: Vec3,
+    input_color: Vec4,
+    camera_uniform: &CameraUniform,
+    out_pos: &mut Vec4,
+    out_color: &mut Vec4,
+) {
+    let pos = if RELATIVE {
+        input_pos - constants.last_relative_position
+    } else {
+        input_pos
+    };
+    let pos_view = camera_uniform.view * Vec4::from((pos, 1.0));
+    *out_pos = camera_uniform.projection * pos_view;
+    *out_color = input_color;
+}
+
+#[spirv(fragment)]
+pub fn mesh_fs(in_color: Vec4, out_color: &mut Vec4) {
     *out_color = in_color;
-    out_color.w = (1.0 - Float::powi(radius, 2)).clamp(0.0, 1.0);
+}
+
+permutations! {
+    #[spirv(vertex)]
+    fn sphere_vs / sphere_vs_relative = sphere_vs_impl(
+        #[spirv(push_constant)] constants: &ShaderConstants,
+        input_pos: Vec3,
+        input_color: Vec4,
+        input_sphere: Vec4,
+        input_texture: u32,
+        #[spirv(uniform, descriptor_set = 0, binding = 0)] camera_uniform: &CameraUniform,
+        #[spirv(position)] out_pos: &mut Vec4,
+        out_color: &mut Vec4,
+        out_view_pos: &mut Vec3,
+        #[spirv(flat)] out_sphere: &mut Vec4,
+        #[spirv(flat)] out_light: &mut Vec3,
+        #[spirv(flat)] out_texture: &mut u32,
+    );
+}
+
+/// Vertices of a disc covering a sphere, see `horizon_triangles` in the renderer.
+/// `input_sphere` holds the center and radius of the sphere.
+#[inline(always)]
+fn sphere_vs_impl<const RELATIVE: bool>(
+    constants: &ShaderConstants,
+    input_pos: Vec3,
+    input_color: Vec4,
+    input_sphere: Vec4,
+    input_texture: u32,
+    camera_uniform: &CameraUniform,
+    out_pos: &mut Vec4,
+    out_color: &mut Vec4,
+    out_view_pos: &mut Vec3,
+    out_sphere: &mut Vec4,
+    out_light: &mut Vec3,
+    out_texture: &mut u32,
+) {
+    let (pos, center) = if RELATIVE {
+        (
+            input_pos - constants.last_relative_position,
+            input_sphere.xyz() - constants.last_relative_position,
+        )
+    } else {
+        (input_pos, input_sphere.xyz())
+    };
+    let pos_view = camera_uniform.view * Vec4::from((pos, 1.0));
+    let center_view = (camera_uniform.view * Vec4::from((center, 1.0))).xyz();
+    *out_pos = camera_uniform.projection * pos_view;
+    *out_color = input_color;
+    *out_view_pos = pos_view.xyz();
+    *out_sphere = Vec4::from((center_view, input_sphere.w));
+    *out_light =
+        light_direction::<RELATIVE>(constants, camera_uniform, center_view, input_sphere.w);
+    *out_texture = input_texture;
+}
+
+/// Shade the sphere seen through the disc, where the ray from the camera hits it.
+#[spirv(fragment(depth_replacing))]
+pub fn sphere_fs(
+    in_color: Vec4,
+    in_view_pos: Vec3,
+    #[spirv(flat)] in_sphere: Vec4,
+    #[spirv(flat)] in_light: Vec3,
+    #[spirv(flat)] in_texture: u32,
+    #[spirv(uniform, descriptor_set = 0, binding = 0)] camera_uniform: &CameraUniform,
+    #[spirv(descriptor_set = 1, binding = 0)] textures: &Image2dArray,
+    #[spirv(descriptor_set = 1, binding = 1)] sampler: &Sampler,
+    #[spirv(frag_depth)] out_depth: &mut f32,
+    out_color: &mut Vec4,
+) {
+    let center = in_sphere.xyz();
+    let radius = in_sphere.w;
+    let dir = in_view_pos.normalize();
+    // The disc lies inside the horizon, so the ray always hits the sphere.
+    let along = dir.dot(center);
+    let miss_sq = center.length_squared() - along * along;
+    let hit = dir * (along - (radius * radius - miss_sq).max(0.0).sqrt());
+    let normal = (hit - center) / radius;
+
+    let clip = camera_uniform.projection * Vec4::from((hit, 1.0));
+    *out_depth = clip.z / clip.w;
+
+    let shaded = shade_sphere(
+        in_color.xyz(),
+        normal,
+        in_light,
+        in_texture,
+        camera_uniform,
+        textures,
+        sampler,
+    );
+    *out_color = Vec4::from((shaded, in_color.w));
 }
 
 #[spirv(vertex)]
@@ -150,7 +567,8 @@ pub fn copy_texture_vs(
     let index = vertex_id as usize % 6;
     let raw = CLIP_SPACE_COORD_QUAD_CCW[index];
     *out_pos = Vec4::new(raw.x, raw.y, 0.0, 1.0);
-    *out_uv = (raw + Vec2::splat(1.0)) / 2.0;
+    // Texture coordinates point down, clip space up.
+    *out_uv = Vec2::new(raw.x + 1.0, 1.0 - raw.y) / 2.0;
 }
 
 #[spirv(fragment)]
@@ -162,3 +580,59 @@ pub fn copy_texture_fs(
 ) {
     *out_color = image.sample(*sampler, in_uv);
 }
+
+fn luminance(color: Vec3) -> f32 {
+    color.dot(Vec3::new(0.2126, 0.7152, 0.0722))
+}
+
+/// Keep the part of the scene brighter than the threshold, scaled down so colors keep
+/// their hue.
+#[spirv(fragment)]
+pub fn bloom_bright_fs(
+    in_uv: Vec2,
+    #[spirv(push_constant)] constants: &BloomConstants,
+    #[spirv(descriptor_set = 0, binding = 0)] image: &Image2d,
+    #[spirv(descriptor_set = 0, binding = 1)] sampler: &Sampler,
+    out_color: &mut Vec4,
+) {
+    let color = image.sample(*sampler, in_uv).xyz();
+    let lum = luminance(color);
+    let excess = (lum - constants.threshold).max(0.0) / lum.max(1e-4);
+    *out_color = Vec4::from((color * excess, 1.0));
+}
+
+/// Separable gaussian blur along `constants.step`.
+#[spirv(fragment)]
+pub fn bloom_blur_fs(
+    in_uv: Vec2,
+    #[spirv(push_constant)] constants: &BloomConstants,
+    #[spirv(descriptor_set = 0, binding = 0)] image: &Image2d,
+    #[spirv(descriptor_set = 0, binding = 1)] sampler: &Sampler,
+    out_color: &mut Vec4,
+) {
+    let weights = [0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216];
+    let mut color = image.sample(*sampler, in_uv).xyz() * weights[0];
+    let mut i = 1;
+    while i < 5 {
+        let offset = constants.step * i as f32;
+        color += image.sample(*sampler, in_uv + offset).xyz() * weights[i];
+        color += image.sample(*sampler, in_uv - offset).xyz() * weights[i];
+        i += 1;
+    }
+    *out_color = Vec4::from((color, 1.0));
+}
+
+/// Add the blurred bright parts onto the scene, clamped to what the output can show.
+#[spirv(fragment)]
+pub fn bloom_composite_fs(
+    in_uv: Vec2,
+    #[spirv(push_constant)] constants: &BloomConstants,
+    #[spirv(descriptor_set = 0, binding = 0)] scene: &Image2d,
+    #[spirv(descriptor_set = 0, binding = 1)] sampler: &Sampler,
+    #[spirv(descriptor_set = 0, binding = 2)] bloom: &Image2d,
+    out_color: &mut Vec4,
+) {
+    let color = scene.sample(*sampler, in_uv).xyz()
+        + bloom.sample(*sampler, in_uv).xyz() * constants.intensity;
+    *out_color = Vec4::from((color.min(Vec3::ONE), 1.0));
+}
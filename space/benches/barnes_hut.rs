@@ -1,8 +1,13 @@
 mod perf;
 
-use cgmath::{Point3, Vector3};
-use criterion::{Criterion, criterion_group, criterion_main};
-use space::{ObjectInfo, SimulationImpl, constants::AU};
+use cgmath::{InnerSpace, Point3, Vector3};
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use space::{
+    ObjectInfo, SimulationImpl,
+    constants::{AU, BARNES_HUT_COEFF},
+    presets::{Imf, plummer_cluster},
+    rng::RngService,
+};
 
 fn gen_random(count: usize) -> (Vec<ObjectInfo>, Vec<Vector3<f64>>) {
     let mut objs = Vec::new();
@@ -19,6 +24,8 @@ fn gen_random(count: usize) -> (Vec<ObjectInfo>, Vec<Vector3<f64>>) {
                 rand::random_range(-1e3..1e3) / AU,
             ),
             mass: rand::random_range(1000.0..1000000.0),
+            pinned: false,
+            test_particle: false,
         });
     }
 
@@ -38,6 +45,164 @@ fn bench_barnes_hut_random(c: &mut Criterion) {
     });
 }
 
+fn bench_barnes_hut_grouping(c: &mut Criterion) {
+    // One walk per object against one per group of nearby objects. The sim is kept
+    // between iterations, so its buffers are only allocated once, like in a run.
+    let mut group = c.benchmark_group("barnes_hut_grouping");
+    let count = 10000;
+    let (mut objs, mut out_buffer) = gen_random(count);
+    for size in [1, 4, 16, 64] {
+        let mut sim = space::BarnesHutSim::new(0.5).with_group_size(size);
+        group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, _| {
+            b.iter(|| sim.iter_single_threaded(&mut objs, &mut out_buffer))
+        });
+    }
+    group.finish();
+}
+
+fn bench_barnes_hut_incremental(c: &mut Criterion) {
+    // Steps of a Plummer sphere moving a little each time, building the tree from scratch
+    // against updating the tree of the last step.
+    let mut group = c.benchmark_group("barnes_hut_incremental");
+    group.sample_size(10);
+    let imf = Imf::Kroupa {
+        min: 0.1,
+        max: 10.0,
+    };
+    let count = 100_000;
+    let objs: Vec<_> = plummer_cluster(count, 1.0, imf, &RngService::new(7))
+        .into_iter()
+        .map(|obj| obj.dat)
+        .collect();
+    // The fastest object moves a ten-thousandth of the scale radius per step.
+    let max_speed = objs
+        .iter()
+        .map(|obj| obj.vel.magnitude())
+        .fold(0.0, f64::max);
+    let delta = 1e-4 / max_speed;
+    let mut out_buffer = vec![Vector3::new(0.0, 0.0, 0.0); count];
+    for (name, incremental) in [("rebuild", false), ("update", true)] {
+        let mut objs = objs.clone();
+        let mut sim = space::BarnesHutSim::new(BARNES_HUT_COEFF);
+        if incremental {
+            sim = sim.with_incremental_tree();
+        }
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                for obj in &mut objs {
+                    obj.pos += obj.vel * delta;
+                }
+                sim.iter_single_threaded(&mut objs, &mut out_buffer)
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_fmm_random(c: &mut Criterion) {
+    let (mut objs, mut out_buffer) = gen_random(1000);
+
+    c.bench_function("fmm_random_1k", |b| {
+        b.iter(|| {
+            let mut sim = space::FmmSim::new(0.5);
+            sim.iter_single_threaded(&mut objs, &mut out_buffer);
+        })
+    });
+}
+
+fn bench_fmm_scaling(c: &mut Criterion) {
+    // A Plummer sphere, so that most objects crowd into a small part of the tree, against
+    // Barnes-Hut on the same objects with the same theta.
+    let mut group = c.benchmark_group("fmm_scaling");
+    group.sample_size(10);
+    let imf = Imf::Kroupa {
+        min: 0.1,
+        max: 10.0,
+    };
+    for count in [10_000, 100_000, 1_000_000] {
+        let mut objs: Vec<_> = plummer_cluster(count, 1.0, imf, &RngService::new(7))
+            .into_iter()
+            .map(|obj| obj.dat)
+            .collect();
+        let mut out_buffer = vec![Vector3::new(0.0, 0.0, 0.0); count];
+        let mut fmm = space::FmmSim::new(BARNES_HUT_COEFF);
+        group.bench_with_input(BenchmarkId::new("fmm", count), &count, |b, _| {
+            b.iter(|| fmm.iter_single_threaded(&mut objs, &mut out_buffer))
+        });
+        group.bench_with_input(BenchmarkId::new("fmm_par", count), &count, |b, _| {
+            b.iter(|| fmm.iter(&mut objs, &mut out_buffer))
+        });
+        let mut barnes_hut = space::BarnesHutSim::new(BARNES_HUT_COEFF);
+        group.bench_with_input(BenchmarkId::new("barnes_hut", count), &count, |b, _| {
+            b.iter(|| barnes_hut.iter_single_threaded(&mut objs, &mut out_buffer))
+        });
+        group.bench_with_input(BenchmarkId::new("barnes_hut_par", count), &count, |b, _| {
+            b.iter(|| barnes_hut.iter(&mut objs, &mut out_buffer))
+        });
+    }
+    group.finish();
+}
+
+fn bench_kd_tree_random(c: &mut Criterion) {
+    let (mut objs, mut out_buffer) = gen_random(1000);
+
+    c.bench_function("kd_tree_random_1k", |b| {
+        b.iter(|| {
+            let mut sim = space::KdTreeSim::new(0.5);
+            sim.iter_single_threaded(&mut objs, &mut out_buffer);
+        })
+    });
+}
+
+fn bench_dual_tree_random(c: &mut Criterion) {
+    let (mut objs, mut out_buffer) = gen_random(1000);
+
+    c.bench_function("dual_tree_random_1k", |b| {
+        b.iter(|| {
+            let mut sim = space::DualTreeSim::new(0.5);
+            sim.iter_single_threaded(&mut objs, &mut out_buffer);
+        })
+    });
+}
+
+fn bench_direct_random_par(c: &mut Criterion) {
+    // Times the chunked parallel summation, from counts small enough that the
+    // PAR_MIN_CHUNK_WORK floor limits the number of chunks to larger ones.
+    let mut group = c.benchmark_group("direct_random_par");
+    for count in [256, 1024, 4096] {
+        let (mut objs, mut out_buffer) = gen_random(count);
+        group.bench_with_input(BenchmarkId::from_parameter(count), &count, |b, _| {
+            b.iter(|| space::BruteForceSim::new().iter(&mut objs, &mut out_buffer))
+        });
+    }
+    group.finish();
+}
+
+fn bench_direct_symmetric(c: &mut Criterion) {
+    // Each pair once against each pair from both sides, through the parallel paths and
+    // the single-threaded ones.
+    let mut group = c.benchmark_group("direct_symmetric");
+    for count in [1024, 4096] {
+        let (mut objs, mut out_buffer) = gen_random(count);
+        group.bench_with_input(BenchmarkId::new("direct", count), &count, |b, _| {
+            b.iter(|| space::BruteForceSim::new().iter(&mut objs, &mut out_buffer))
+        });
+        let mut sim = space::SymmetricBruteForceSim::new();
+        group.bench_with_input(BenchmarkId::new("symmetric", count), &count, |b, _| {
+            b.iter(|| sim.iter(&mut objs, &mut out_buffer))
+        });
+        group.bench_with_input(BenchmarkId::new("direct_single", count), &count, |b, _| {
+            b.iter(|| space::BruteForceSim::new().iter_single_threaded(&mut objs, &mut out_buffer))
+        });
+        group.bench_with_input(
+            BenchmarkId::new("symmetric_single", count),
+            &count,
+            |b, _| b.iter(|| sim.iter_single_threaded(&mut objs, &mut out_buffer)),
+        );
+    }
+    group.finish();
+}
+
 #[allow(unused)]
 fn bench_barnes_hut_random_par(c: &mut Criterion) {
     // This bench is rather unstable.
@@ -54,6 +219,6 @@ fn bench_barnes_hut_random_par(c: &mut Criterion) {
 criterion_group! {
     name = benches;
     config = Criterion::default().with_profiler(perf::FlamegraphProfiler::new(100));
-    targets = bench_barnes_hut_random/* bench_barnes_hut_random_par */
+    targets = bench_barnes_hut_random, bench_barnes_hut_grouping, bench_barnes_hut_incremental, bench_fmm_random, bench_fmm_scaling, bench_kd_tree_random, bench_dual_tree_random, bench_direct_random_par, bench_direct_symmetric/* bench_barnes_hut_random_par */
 }
 criterion_main!(benches);
@@ -0,0 +1,156 @@
+//! Bodies gaining or losing mass during a run.
+//!
+//! Mass changes in two ways. Bodies can have a steady rate, like the wind of a star or a
+//! planet feeding on a disk that is not simulated. With mergers enabled, bodies that touch
+//! also merge into one, which keeps the mass and momentum of both. The absorbed body is
+//! frozen as a pinned test particle, like an escaped one, since every other part of a run
+//! refers to objects by index.
+//!
+//! The solvers take the masses from the objects at every step, so the aggregated masses
+//! of tree nodes follow without anything else to update. Radiation pressure keeps using
+//! the mass a body started with.
+
+use std::fmt::Display;
+
+use cgmath::{EuclideanSpace, InnerSpace};
+
+use crate::{
+    Object,
+    constants::M0,
+    sim::{ObjectInfo, SimTime},
+};
+
+/// Two bodies that touched and became one.
+#[derive(Debug, Clone)]
+pub struct Merger {
+    pub survivor: usize,
+    pub absorbed: usize,
+    pub survivor_name: String,
+    pub absorbed_name: String,
+    pub time: SimTime,
+    /// Mass of the merged body, in earth masses.
+    pub mass: f64,
+}
+
+impl Display for Merger {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} merged into {} after {}, which now has {:.4e} earth masses",
+            self.absorbed_name,
+            self.survivor_name,
+            self.time.elapsed(),
+            self.mass,
+        )
+    }
+}
+
+/// Steady mass rates and mergers for a run.
+#[derive(Debug, Clone, Default)]
+pub struct MassEvolution {
+    names: Vec<String>,
+    /// Bodies with a steady change in mass, in earth masses per second.
+    rates: Vec<(usize, f64)>,
+    /// Radius of each body in AU, if touching bodies merge. Grows as bodies merge.
+    radii: Option<Vec<f64>>,
+}
+
+impl MassEvolution {
+    pub fn new(objects: &[Object], merge: bool) -> Self {
+        Self {
+            names: objects.iter().map(|o| o.name.clone()).collect(),
+            rates: objects
+                .iter()
+                .enumerate()
+                .filter(|(_, o)| o.mass_rate != 0.0)
+                .map(|(idx, o)| (idx, o.mass_rate / M0))
+                .collect(),
+            radii: merge.then(|| objects.iter().map(|o| o.radius as f64).collect()),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rates.is_empty() && self.radii.is_none()
+    }
+
+    /// Apply the steady rates over a step of `delta` seconds, backwards for negative
+    /// `delta`. Masses stop at zero.
+    pub fn step(&self, objects: &mut [ObjectInfo], delta: f64) {
+        for (idx, rate) in &self.rates {
+            let obj = &mut objects[*idx];
+            obj.mass = (obj.mass + rate * delta).max(0.0);
+        }
+    }
+
+    /// Merge every pair of bodies that touch, returning one event for each. The less
+    /// massive body of a pair is absorbed by the other. Test particles can be absorbed,
+    /// which removes them without adding any mass, but never absorb anything.
+    pub fn merge(&mut self, time: SimTime, objects: &mut [ObjectInfo]) -> Vec<Merger> {
+        let Some(radii) = &mut self.radii else {
+            return Vec::new();
+        };
+
+        // Sweep along the x axis, only comparing bodies whose extents overlap in x.
+        let mut order: Vec<_> = (0..objects.len())
+            .filter(|i| !objects[*i].is_frozen())
+            .collect();
+        let start = |objects: &[ObjectInfo], radii: &[f64], i: usize| objects[i].pos.x - radii[i];
+        order.sort_by(|a, b| start(objects, radii, *a).total_cmp(&start(objects, radii, *b)));
+
+        let mut mergers = Vec::new();
+        for (n, &i) in order.iter().enumerate() {
+            for &j in &order[n + 1..] {
+                if objects[i].is_frozen() {
+                    break;
+                }
+                if start(objects, radii, j) > objects[i].pos.x + radii[i] {
+                    break;
+                }
+                if objects[j].is_frozen()
+                    || (objects[i].pos - objects[j].pos).magnitude() >= radii[i] + radii[j]
+                {
+                    continue;
+                }
+                let (a, b) = (&objects[i], &objects[j]);
+                if a.gravitating_mass() == 0.0 && b.gravitating_mass() == 0.0 {
+                    continue;
+                }
+                let (survivor, absorbed) = if (b.gravitating_mass(), j) > (a.gravitating_mass(), i)
+                {
+                    (j, i)
+                } else {
+                    (i, j)
+                };
+                absorb(objects, survivor, absorbed);
+                radii[survivor] = (radii[survivor].powi(3) + radii[absorbed].powi(3)).cbrt();
+                mergers.push(Merger {
+                    survivor,
+                    absorbed,
+                    survivor_name: self.names.get(survivor).cloned().unwrap_or_default(),
+                    absorbed_name: self.names.get(absorbed).cloned().unwrap_or_default(),
+                    time,
+                    mass: objects[survivor].mass,
+                });
+            }
+        }
+        mergers
+    }
+}
+
+/// Move the mass and momentum of `absorbed` into `survivor`, which moves to their center
+/// of mass unless it is pinned, and freeze `absorbed`.
+fn absorb(objects: &mut [ObjectInfo], survivor: usize, absorbed: usize) {
+    let other = objects[absorbed].clone();
+    objects[absorbed].pinned = true;
+    objects[absorbed].test_particle = true;
+
+    let obj = &mut objects[survivor];
+    let (m1, m2) = (obj.gravitating_mass(), other.gravitating_mass());
+    let total = m1 + m2;
+    if total > 0.0 && !obj.pinned {
+        obj.pos =
+            EuclideanSpace::from_vec((obj.pos.to_vec() * m1 + other.pos.to_vec() * m2) / total);
+        obj.vel = (obj.vel * m1 + other.vel * m2) / total;
+    }
+    obj.mass += m2;
+}
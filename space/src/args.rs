@@ -0,0 +1,355 @@
+//! Command line of the `space` binary.
+
+use std::{fmt::Display, str::FromStr};
+
+use space::{
+    ic_import::IcFormat,
+    preset_registry::{self, PresetEntry, PresetParams},
+};
+
+#[derive(Default)]
+pub struct Args {
+    /// Play back this recording instead of running a simulation.
+    pub replay: Option<String>,
+    /// Compare the replayed recording against this one.
+    pub diff: Option<String>,
+    /// Record the samples of the simulation to this file.
+    pub record: Option<String>,
+    /// Read the scenario from this TOML or JSON file, or generate it with this Rhai
+    /// script, instead of the preset.
+    pub scenario: Option<String>,
+    /// Add the small bodies in this MPCORB file around the object called sun.
+    pub mpcorb: Option<String>,
+    /// Read at most this many small bodies from the MPCORB file.
+    pub mpcorb_limit: Option<usize>,
+    /// Read the scenario from this Gadget-2 or Tipsy snapshot, instead of the preset.
+    pub initial_conditions: Option<(IcFormat, String)>,
+    /// Generate this preset from the registry with these parameters, instead of the
+    /// default one. Set by `--preset`, or by `--cluster` and `--galaxies`.
+    pub preset: Option<(&'static PresetEntry, PresetParams)>,
+    /// List the presets and potentials in the registry and their parameters, and exit.
+    pub list_presets: bool,
+    /// Length unit of the snapshot, in parsecs.
+    pub ic_length: Option<f64>,
+    /// Mass unit of the snapshot, in solar masses.
+    pub ic_mass: Option<f64>,
+    /// Check the scenario and print a report, without starting the simulation.
+    pub validate: bool,
+    /// Cycle through the presets on a timer instead of running a single scenario.
+    pub demo: bool,
+    /// Master seed for everything random in the run.
+    pub seed: Option<u64>,
+    /// Add the first order post-Newtonian correction to gravity.
+    pub post_newtonian: bool,
+    /// Render these scenario files or Rhai scripts headlessly side by side, and exit.
+    pub sweep: Vec<String>,
+    /// Directory to save the images of the sweep into.
+    pub sweep_dir: Option<String>,
+    /// Simulated days to run each scenario in the sweep.
+    pub sweep_days: Option<f64>,
+    /// Profile the simulation for this many seconds, and write a flamegraph.
+    #[cfg(unix)]
+    pub profile_secs: Option<f64>,
+    /// Names of objects whose state is logged at every step.
+    pub watch: Vec<String>,
+    /// Where to write the trajectories of watched objects.
+    pub watch_log: Option<String>,
+    /// Log watched objects every this many steps, instead of every step.
+    pub watch_every: Option<u64>,
+    /// Where to write the virial ratio and other statistics as the run goes.
+    pub stats_log: Option<String>,
+    /// Directory to write Parquet snapshots of every object into.
+    pub snapshots: Option<String>,
+    /// Steps between Parquet snapshots.
+    pub snapshot_every: Option<u64>,
+    /// Directory to write checkpoints into, and to resume from.
+    pub checkpoints: Option<String>,
+    /// Minutes between checkpoints.
+    pub checkpoint_minutes: Option<f64>,
+    /// Resume from the latest checkpoint without asking.
+    pub resume: bool,
+    /// HORIZONS vector tables to compare objects against, as object name and path.
+    pub ephemerides: Vec<(String, String)>,
+    /// Estimate the impact probability of the object with this name, and exit.
+    pub impact: Option<String>,
+    /// Names of the objects it might hit. Defaults to every massive object.
+    pub impact_targets: Vec<String>,
+    /// Number of clones in the impact study.
+    pub impact_samples: Option<usize>,
+    /// Simulated days to follow the clones for.
+    pub impact_days: Option<f64>,
+    /// Standard deviation of the position in km, and of the velocity in m/s.
+    pub impact_sigma: Option<(f64, f64)>,
+    /// Stars to send past the most massive object, as solar masses, impact parameter in
+    /// AU, speed in km/s and time of injection in days.
+    pub flybys: Vec<[f64; 4]>,
+    /// Star clusters to drop into the scenario, as number of stars, scale radius in AU,
+    /// position in AU and time of injection in days.
+    pub clusters: Vec<[f64; 6]>,
+    /// Scenario files to drop into the scenario at the start, with their position in AU,
+    /// velocity in km/s and rotation around the z axis in degrees.
+    pub imports: Vec<(String, [f64; 7])>,
+    /// Analytic potentials from `preset_registry::POTENTIALS` acting on every object.
+    pub potential: Option<String>,
+    /// Side of the periodic box to wrap space into, in AU.
+    pub periodic: Option<f64>,
+    /// Propagate isolated binaries closer than this analytically, in AU.
+    pub kepler_pairs: Option<f64>,
+    /// Integrate two body systems like any other, instead of propagating them analytically.
+    pub no_kepler: bool,
+    /// Integrate with the adaptive Dormand-Prince method, with this relative tolerance.
+    pub rk45: Option<f64>,
+    /// Integrate with the fourth order Hermite scheme, with this accuracy parameter.
+    pub hermite: Option<f64>,
+    /// Freeze bodies further than this from the barycenter, in AU.
+    pub escape: Option<f64>,
+    /// Freeze bodies further than this from the barycenter that are also unbound, in AU.
+    pub escape_unbound: Option<f64>,
+    /// Merge bodies that touch, keeping their mass and momentum.
+    pub merge: bool,
+    /// Keep the Barnes-Hut tree between steps, only rebuilding the parts objects leave.
+    pub incremental_tree: bool,
+    /// Percentage of the cores the simulation may use.
+    pub cpu_budget: Option<f64>,
+    /// Number of simulation threads, 0 to pick them automatically.
+    pub threads: Option<usize>,
+    /// Ticks to run ahead of the last sample before waiting for the next, 0 for no limit.
+    pub max_unsampled_ticks: Option<u64>,
+    /// Run without a window until the end of the run, printing the progress.
+    pub headless: bool,
+    /// Tick to end a headless run at.
+    pub ticks: Option<u64>,
+    /// Simulated years to end a headless run after.
+    pub years: Option<f64>,
+    /// Address to stream the sampled positions from over WebSocket.
+    pub stream: Option<String>,
+    /// Address to answer control requests on.
+    pub control: Option<String>,
+    /// Slots to set aside for bodies added through the control API.
+    pub control_spares: Option<usize>,
+    /// Directory the control API writes snapshots to.
+    pub control_snapshots: Option<String>,
+    /// Let the control API listen on addresses other than loopback ones.
+    pub control_public: bool,
+}
+
+/// Parse `value`, given for `flag`.
+fn parse<T: FromStr>(value: &str, flag: &str) -> anyhow::Result<T>
+where
+    T::Err: Display,
+{
+    value
+        .parse()
+        .map_err(|e| anyhow::anyhow!("Invalid value {value} for {flag}: {e}"))
+}
+
+/// Comma separated numbers, like `1,2.5,-3`, given for `flag`.
+fn numbers(values: &str, flag: &str) -> anyhow::Result<Vec<f64>> {
+    values.split(',').map(|v| parse(v, flag)).collect()
+}
+
+/// The arguments after the name of the program, with the values of each flag taken
+/// from the argument after it.
+struct Flags<I> {
+    iter: I,
+}
+
+impl<I: Iterator<Item = String>> Flags<I> {
+    /// The value after `flag`, described by `usage` if it is missing.
+    fn value(&mut self, flag: &str, usage: &str) -> anyhow::Result<String> {
+        self.iter
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("{flag} requires {usage}"))
+    }
+
+    fn parsed<T: FromStr>(&mut self, flag: &str, usage: &str) -> anyhow::Result<T>
+    where
+        T::Err: Display,
+    {
+        parse(&self.value(flag, usage)?, flag)
+    }
+
+    /// Exactly `N` comma separated numbers after `flag`.
+    fn numbers<const N: usize>(&mut self, flag: &str, usage: &str) -> anyhow::Result<[f64; N]> {
+        numbers(&self.value(flag, usage)?, flag)?
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("{flag} requires {usage}"))
+    }
+}
+
+/// Preset from a flag that takes its parameters in order, like `--cluster`, instead of
+/// by name like `--preset`.
+fn positional_preset(
+    values: &str,
+    flag: &str,
+    preset: &str,
+    names: &[&str],
+    usage: &str,
+) -> anyhow::Result<(&'static PresetEntry, PresetParams)> {
+    let values = numbers(values, flag)?;
+    if values.len() != names.len() {
+        anyhow::bail!("{flag} requires {usage}");
+    }
+    let params = names.iter().map(|name| name.to_string()).zip(values);
+    Ok((preset_registry::find(preset)?, params.collect()))
+}
+
+impl Args {
+    pub fn parse() -> anyhow::Result<Self> {
+        let mut args = Args::default();
+        let mut flags = Flags {
+            iter: std::env::args().skip(1),
+        };
+        while let Some(arg) = flags.iter.next() {
+            let flag = arg.as_str();
+            match flag {
+                "--replay" => args.replay = Some(flags.value(flag, "a path")?),
+                "--record" => args.record = Some(flags.value(flag, "a path")?),
+                "--diff" => args.diff = Some(flags.value(flag, "a path")?),
+                "--scenario" => args.scenario = Some(flags.value(flag, "a path")?),
+                "--mpcorb" => args.mpcorb = Some(flags.value(flag, "a path")?),
+                "--mpcorb-limit" => args.mpcorb_limit = Some(flags.parsed(flag, "a count")?),
+                "--gadget" | "--tipsy" => {
+                    let format = if flag == "--gadget" {
+                        IcFormat::Gadget
+                    } else {
+                        IcFormat::Tipsy
+                    };
+                    args.initial_conditions = Some((format, flags.value(flag, "a path")?));
+                }
+                "--ic-length" => args.ic_length = Some(flags.parsed(flag, "parsecs")?),
+                "--ic-mass" => args.ic_mass = Some(flags.parsed(flag, "solar masses")?),
+                "--sweep" => args.sweep.push(flags.value(flag, "a path")?),
+                "--sweep-dir" => args.sweep_dir = Some(flags.value(flag, "a directory")?),
+                "--sweep-days" => args.sweep_days = Some(flags.parsed(flag, "a number")?),
+                #[cfg(unix)]
+                "--profile-secs" => args.profile_secs = Some(flags.parsed(flag, "a number")?),
+                "--watch" => args.watch.push(flags.value(flag, "an object name")?),
+                "--watch-every" => args.watch_every = Some(flags.parsed(flag, "a step count")?),
+                "--watch-log" => args.watch_log = Some(flags.value(flag, "a path")?),
+                "--stats-log" => args.stats_log = Some(flags.value(flag, "a path")?),
+                "--snapshots" => args.snapshots = Some(flags.value(flag, "a directory")?),
+                "--snapshot-every" => {
+                    args.snapshot_every = Some(flags.parsed(flag, "a step count")?);
+                }
+                "--checkpoints" => args.checkpoints = Some(flags.value(flag, "a directory")?),
+                "--checkpoint-minutes" => {
+                    args.checkpoint_minutes = Some(flags.parsed(flag, "a number of minutes")?);
+                }
+                "--resume" => args.resume = true,
+                "--ephemeris" => {
+                    const USAGE: &str = "<name>=<path>";
+                    let ephemeris = flags.value(flag, USAGE)?;
+                    let (name, path) = ephemeris
+                        .split_once('=')
+                        .ok_or_else(|| anyhow::anyhow!("{flag} requires {USAGE}"))?;
+                    args.ephemerides.push((name.to_owned(), path.to_owned()));
+                }
+                "--impact" => args.impact = Some(flags.value(flag, "an object name")?),
+                "--impact-target" => args
+                    .impact_targets
+                    .push(flags.value(flag, "an object name")?),
+                "--impact-samples" => args.impact_samples = Some(flags.parsed(flag, "a number")?),
+                "--impact-days" => args.impact_days = Some(flags.parsed(flag, "a number")?),
+                "--impact-sigma" => {
+                    let [pos, vel] = flags.numbers(flag, "<km>,<m/s>")?;
+                    args.impact_sigma = Some((pos, vel));
+                }
+                "--flyby" => args
+                    .flybys
+                    .push(flags.numbers(flag, "<solar masses>,<AU>,<km/s>,<days>")?),
+                "--preset" => {
+                    let preset = flags.value(flag, "<name>,<param>=<value>,...")?;
+                    args.set_preset(preset_registry::parse(&preset)?)?;
+                }
+                "--list-presets" => args.list_presets = true,
+                "--cluster" => {
+                    const USAGE: &str = "plummer,<stars>,<AU> or king,<stars>,<AU>,<W0>";
+                    let cluster = flags.value(flag, USAGE)?;
+                    let (model, values) = cluster
+                        .split_once(',')
+                        .ok_or_else(|| anyhow::anyhow!("{flag} requires {USAGE}"))?;
+                    let names: &[&str] = match model {
+                        "plummer" => &["stars", "radius"],
+                        "king" => &["stars", "radius", "w0"],
+                        _ => anyhow::bail!("{flag} requires {USAGE}"),
+                    };
+                    args.set_preset(positional_preset(values, flag, model, names, USAGE)?)?;
+                }
+                "--galaxies" => {
+                    const USAGE: &str = "<stars>,<mass ratio>,<kpc>,<degrees>";
+                    let galaxies = flags.value(flag, USAGE)?;
+                    let names = ["stars", "mass_ratio", "impact_kpc", "tilt_degrees"];
+                    args.set_preset(positional_preset(
+                        &galaxies,
+                        flag,
+                        "galaxy_collision",
+                        &names,
+                        USAGE,
+                    )?)?;
+                }
+                "--import-cluster" => args
+                    .clusters
+                    .push(flags.numbers(flag, "<stars>,<AU>,<x>,<y>,<z>,<days>")?),
+                "--import" => {
+                    const USAGE: &str = "<scenario file>,<x>,<y>,<z>,<vx>,<vy>,<vz>[,<degrees>]";
+                    let import = flags.value(flag, USAGE)?;
+                    let (path, values) = import
+                        .split_once(',')
+                        .ok_or_else(|| anyhow::anyhow!("{flag} requires {USAGE}"))?;
+                    let mut values = numbers(values, flag)?;
+                    if values.len() == 6 {
+                        values.push(0.0);
+                    }
+                    let values = values
+                        .try_into()
+                        .map_err(|_| anyhow::anyhow!("{flag} requires {USAGE}"))?;
+                    args.imports.push((path.to_owned(), values));
+                }
+                "--potential" => args.potential = Some(flags.value(flag, "a name")?),
+                "--periodic" => args.periodic = Some(flags.parsed(flag, "a size in AU")?),
+                "--kepler-pairs" => {
+                    args.kepler_pairs = Some(flags.parsed(flag, "a distance in AU")?)
+                }
+                "--no-kepler" => args.no_kepler = true,
+                "--rk45" => args.rk45 = Some(flags.parsed(flag, "a tolerance")?),
+                "--hermite" => args.hermite = Some(flags.parsed(flag, "an accuracy parameter")?),
+                "--escape" => args.escape = Some(flags.parsed(flag, "a distance in AU")?),
+                "--escape-unbound" => {
+                    args.escape_unbound = Some(flags.parsed(flag, "a distance in AU")?);
+                }
+                "--validate" => args.validate = true,
+                "--demo" => args.demo = true,
+                "--post-newtonian" => args.post_newtonian = true,
+                "--merge" => args.merge = true,
+                "--incremental-tree" => args.incremental_tree = true,
+                "--headless" => args.headless = true,
+                "--control" => args.control = Some(flags.value(flag, "an address")?),
+                "--control-spares" => args.control_spares = Some(flags.parsed(flag, "a count")?),
+                "--control-snapshots" => {
+                    args.control_snapshots = Some(flags.value(flag, "a directory")?);
+                }
+                "--control-public" => args.control_public = true,
+                "--stream" => args.stream = Some(flags.value(flag, "an address")?),
+                "--ticks" => args.ticks = Some(flags.parsed(flag, "a number")?),
+                "--years" => args.years = Some(flags.parsed(flag, "a number")?),
+                "--max-unsampled-ticks" => {
+                    args.max_unsampled_ticks = Some(flags.parsed(flag, "a number")?);
+                }
+                "--threads" => args.threads = Some(flags.parsed(flag, "a number")?),
+                "--cpu-budget" => args.cpu_budget = Some(flags.parsed(flag, "a percentage")?),
+                "--seed" => args.seed = Some(flags.parsed(flag, "a number")?),
+                _ => anyhow::bail!("Unknown argument: {arg}"),
+            }
+        }
+        Ok(args)
+    }
+
+    fn set_preset(&mut self, preset: (&'static PresetEntry, PresetParams)) -> anyhow::Result<()> {
+        if self.preset.is_some() {
+            anyhow::bail!("Only one of --preset, --cluster and --galaxies can be given");
+        }
+        self.preset = Some(preset);
+        Ok(())
+    }
+}
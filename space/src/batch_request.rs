@@ -1,26 +1,110 @@
-use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicU64, Ordering};
 
+use std::path::PathBuf;
 use std::sync::Mutex;
 
-use crate::constants::DELTA;
+use crate::Object;
+use crate::constants::{BARNES_HUT_COEFF, DELTA, MAX_UNSAMPLED_TICKS};
+use crate::control::BodyEdit;
 use crate::objects::Objects;
-use crate::sim::ObjectBuffer;
+use crate::orbit::RelativeState;
+use crate::sim::{FofGroups, ForceCheck, ObjectBuffer, SimTime, SolverKind, StepStats};
+use crate::stats::ClusterStats;
+
+/// Positions of every object at a single simulation tick.
+#[derive(Debug, Clone, Default)]
+pub struct FrameSnapshot {
+    pub time: SimTime,
+    pub positions: Vec<[f32; 3]>,
+}
 
 /// Primitive for communicating between simulation and graphics.
+///
+/// Samples are double buffered: the simulation writes into the back buffer, and swaps it
+/// with the front buffer once complete. The renderer only ever sees the front buffer, so
+/// a frame never mixes positions from two samples.
 pub struct BatchRequest {
-    sample: Mutex<Vec<[f32; 3]>>,
+    back: Mutex<FrameSnapshot>,
+    front: Mutex<FrameSnapshot>,
+    /// Whether a snapshot has been published since the last sample.
+    fresh: AtomicBool,
     should_sample: AtomicBool,
-    simulation_tick: AtomicU64,
     delta: AtomicU64,
+    theta: AtomicU64,
+    /// Solver chosen by hand, as one past its index in `SolverKind::ALL`, or 0 to pick
+    /// one by the number of objects.
+    solver: AtomicU8,
+    /// Simulation steps between samples, or 0 to run freely and sample whenever asked.
+    substeps: AtomicU64,
+    /// Ticks the simulation may run without a sample before waiting for one, or 0 to
+    /// never wait.
+    max_unsampled_ticks: AtomicU64,
+    /// Tick the simulation stops at, or 0 to run until it is stopped.
+    end_tick: AtomicU64,
+    paused: AtomicBool,
+    reversed: AtomicBool,
+    step_stats: Mutex<Option<StepStats>>,
+    cluster_stats: Mutex<Option<ClusterStats>>,
+    sim_memory: AtomicU64,
+    /// Share of the cores the simulation may use, as the bits of an `f64`.
+    cpu_budget: AtomicU64,
+    sim_threads: AtomicU64,
+    /// Number of threads set by hand, or 0 to pick them automatically.
+    fixed_threads: AtomicU64,
+    tree_dump: Mutex<Option<PathBuf>>,
+    /// Path to write a Parquet snapshot of every object to.
+    snapshot_request: Mutex<Option<PathBuf>>,
+    body_edits: Mutex<Vec<BodyEdit>>,
+    /// Path to save the scenario to, with the objects to fill in the state of.
+    scenario_export: Mutex<Option<(PathBuf, Vec<Object>)>>,
+    force_check_request: Mutex<Option<usize>>,
+    force_check: Mutex<Option<ForceCheck>>,
+    /// Linking factor of the friends-of-friends groups asked for.
+    groups_request: Mutex<Option<f64>>,
+    groups: Mutex<Option<FofGroups>>,
+    densities_wanted: AtomicBool,
+    densities: Mutex<Option<Vec<f32>>>,
+    orbit_focus: Mutex<Option<usize>>,
+    orbit: Mutex<Option<RelativeState>>,
 }
 
 impl BatchRequest {
     pub fn new(n_objects: usize) -> Self {
+        let empty = FrameSnapshot {
+            time: SimTime::new(0, DELTA),
+            positions: vec![[0.0, 0.0, 0.0]; n_objects],
+        };
         Self {
-            sample: Mutex::new(vec![[0.0, 0.0, 0.0]; n_objects]),
+            back: Mutex::new(empty.clone()),
+            front: Mutex::new(empty),
+            fresh: AtomicBool::new(false),
             should_sample: AtomicBool::new(true),
-            simulation_tick: AtomicU64::new(0),
             delta: AtomicU64::new(DELTA.to_bits()),
+            theta: AtomicU64::new(BARNES_HUT_COEFF.to_bits()),
+            solver: AtomicU8::new(0),
+            substeps: AtomicU64::new(0),
+            max_unsampled_ticks: AtomicU64::new(MAX_UNSAMPLED_TICKS),
+            end_tick: AtomicU64::new(0),
+            paused: AtomicBool::new(false),
+            reversed: AtomicBool::new(false),
+            step_stats: Mutex::new(None),
+            cluster_stats: Mutex::new(None),
+            sim_memory: AtomicU64::new(0),
+            cpu_budget: AtomicU64::new(1.0f64.to_bits()),
+            sim_threads: AtomicU64::new(0),
+            fixed_threads: AtomicU64::new(0),
+            tree_dump: Mutex::new(None),
+            snapshot_request: Mutex::new(None),
+            body_edits: Mutex::new(Vec::new()),
+            scenario_export: Mutex::new(None),
+            force_check_request: Mutex::new(None),
+            force_check: Mutex::new(None),
+            groups_request: Mutex::new(None),
+            groups: Mutex::new(None),
+            densities_wanted: AtomicBool::new(false),
+            densities: Mutex::new(None),
+            orbit_focus: Mutex::new(None),
+            orbit: Mutex::new(None),
         }
     }
 
@@ -32,6 +116,271 @@ impl BatchRequest {
         self.delta.store(rate.to_bits(), Ordering::Relaxed);
     }
 
+    /// Opening angle for tree solvers, picked up by the simulation between batches.
+    pub fn theta(&self) -> f64 {
+        f64::from_bits(self.theta.load(Ordering::Relaxed))
+    }
+
+    pub fn set_theta(&self, theta: f64) {
+        self.theta.store(theta.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Gravity solver chosen in the UI, picked up by the simulation between batches.
+    pub fn solver(&self) -> Option<SolverKind> {
+        match self.solver.load(Ordering::Relaxed) {
+            0 => None,
+            i => SolverKind::ALL.get(i as usize - 1).copied(),
+        }
+    }
+
+    pub fn set_solver(&self, solver: Option<SolverKind>) {
+        let value = solver.map_or(0, |kind| {
+            SolverKind::ALL.iter().position(|k| *k == kind).unwrap() as u8 + 1
+        });
+        self.solver.store(value, Ordering::Relaxed);
+    }
+
+    /// Number of steps the simulation takes between samples, waiting for each sample to
+    /// be requested before going on. With `None` the simulation runs as fast as it can,
+    /// and samples are taken whenever they are requested.
+    pub fn substeps(&self) -> Option<u64> {
+        match self.substeps.load(Ordering::Relaxed) {
+            0 => None,
+            n => Some(n),
+        }
+    }
+
+    pub fn set_substeps(&self, substeps: Option<u64>) {
+        let value = substeps.map_or(0, |n| n.max(1));
+        self.substeps.store(value, Ordering::Relaxed);
+    }
+
+    /// Ticks the simulation may run ahead of the last sample before it waits for the
+    /// next, or `None` to let it run freely.
+    pub fn max_unsampled_ticks(&self) -> Option<u64> {
+        match self.max_unsampled_ticks.load(Ordering::Relaxed) {
+            0 => None,
+            n => Some(n),
+        }
+    }
+
+    pub fn set_max_unsampled_ticks(&self, ticks: Option<u64>) {
+        let value = ticks.map_or(0, |n| n.max(1));
+        self.max_unsampled_ticks.store(value, Ordering::Relaxed);
+    }
+
+    /// Tick the simulation stops at, or `None` to run until it is stopped.
+    pub fn end_tick(&self) -> Option<u64> {
+        match self.end_tick.load(Ordering::Relaxed) {
+            0 => None,
+            n => Some(n),
+        }
+    }
+
+    pub fn set_end_tick(&self, tick: Option<u64>) {
+        self.end_tick.store(tick.unwrap_or(0), Ordering::Relaxed);
+    }
+
+    /// Whether the simulation is holding still, without stepping in either direction.
+    pub fn paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Relaxed);
+    }
+
+    /// Whether the simulation is stepping backwards, towards where it started.
+    pub fn reversed(&self) -> bool {
+        self.reversed.load(Ordering::Relaxed)
+    }
+
+    pub fn set_reversed(&self, reversed: bool) {
+        self.reversed.store(reversed, Ordering::Relaxed);
+    }
+
+    /// Step size and rejection rate of the adaptive integrator, if the simulation uses
+    /// one, as of the latest sample.
+    pub fn step_stats(&self) -> Option<StepStats> {
+        *self.step_stats.lock().unwrap()
+    }
+
+    pub fn set_step_stats(&self, stats: StepStats) {
+        *self.step_stats.lock().unwrap() = Some(stats);
+    }
+
+    /// Virial ratio and other statistics of the objects, as of the last time they were
+    /// computed. `None` with fewer than two massive objects.
+    pub fn cluster_stats(&self) -> Option<ClusterStats> {
+        *self.cluster_stats.lock().unwrap()
+    }
+
+    pub fn set_cluster_stats(&self, stats: ClusterStats) {
+        *self.cluster_stats.lock().unwrap() = Some(stats);
+    }
+
+    /// Bytes held by the simulation, as of the latest sample.
+    pub fn sim_memory(&self) -> u64 {
+        self.sim_memory.load(Ordering::Relaxed)
+    }
+
+    pub fn set_sim_memory(&self, bytes: u64) {
+        self.sim_memory.store(bytes, Ordering::Relaxed);
+    }
+
+    /// Share of the cores of the machine the simulation may use, in (0, 1].
+    pub fn cpu_budget(&self) -> f64 {
+        f64::from_bits(self.cpu_budget.load(Ordering::Relaxed))
+    }
+
+    pub fn set_cpu_budget(&self, budget: f64) {
+        self.cpu_budget.store(budget.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Number of threads the simulation runs on, as of the latest sample.
+    pub fn sim_threads(&self) -> usize {
+        self.sim_threads.load(Ordering::Relaxed) as usize
+    }
+
+    pub fn set_sim_threads(&self, threads: usize) {
+        self.sim_threads.store(threads as u64, Ordering::Relaxed);
+    }
+
+    /// Number of threads the simulation should run on, ignoring the CPU budget, or `None`
+    /// to let it pick them.
+    pub fn fixed_threads(&self) -> Option<usize> {
+        match self.fixed_threads.load(Ordering::Relaxed) {
+            0 => None,
+            n => Some(n as usize),
+        }
+    }
+
+    pub fn set_fixed_threads(&self, threads: Option<usize>) {
+        let value = threads.map_or(0, |n| n.max(1));
+        self.fixed_threads.store(value as u64, Ordering::Relaxed);
+    }
+
+    /// Ask the simulation to write its octree to `path` at the next sample.
+    pub fn request_tree_dump(&self, path: PathBuf) {
+        *self.tree_dump.lock().unwrap() = Some(path);
+    }
+
+    pub fn take_tree_dump(&self) -> Option<PathBuf> {
+        self.tree_dump.lock().unwrap().take()
+    }
+
+    /// Ask the simulation to write a Parquet snapshot of every object to `path` at the
+    /// next sample.
+    pub fn request_snapshot(&self, path: PathBuf) {
+        *self.snapshot_request.lock().unwrap() = Some(path);
+    }
+
+    pub fn take_snapshot_request(&self) -> Option<PathBuf> {
+        self.snapshot_request.lock().unwrap().take()
+    }
+
+    /// Queue a change to the simulated bodies, made at the next sample.
+    pub fn edit_body(&self, edit: BodyEdit) {
+        self.body_edits.lock().unwrap().push(edit);
+    }
+
+    pub fn take_body_edits(&self) -> Vec<BodyEdit> {
+        std::mem::take(&mut *self.body_edits.lock().unwrap())
+    }
+
+    /// Ask the simulation to save `objects` to `path` at the next sample, with their
+    /// positions, velocities and masses replaced by the current ones.
+    pub fn request_scenario_export(&self, path: PathBuf, objects: Vec<Object>) {
+        *self.scenario_export.lock().unwrap() = Some((path, objects));
+    }
+
+    pub fn take_scenario_export(&self) -> Option<(PathBuf, Vec<Object>)> {
+        self.scenario_export.lock().unwrap().take()
+    }
+
+    /// Ask the simulation to check the force on the object at `index` at the next sample.
+    pub fn request_force_check(&self, index: usize) {
+        *self.force_check_request.lock().unwrap() = Some(index);
+    }
+
+    pub fn take_force_check_request(&self) -> Option<usize> {
+        self.force_check_request.lock().unwrap().take()
+    }
+
+    /// Result of the last force check, if there is one that has not been taken yet.
+    pub fn take_force_check(&self) -> Option<ForceCheck> {
+        self.force_check.lock().unwrap().take()
+    }
+
+    pub fn set_force_check(&self, check: ForceCheck) {
+        *self.force_check.lock().unwrap() = Some(check);
+    }
+
+    /// Ask the simulation to find friends-of-friends groups at the next sample, with a
+    /// linking length of `linking_factor` times the mean separation of the objects.
+    pub fn request_groups(&self, linking_factor: f64) {
+        *self.groups_request.lock().unwrap() = Some(linking_factor);
+    }
+
+    pub fn take_groups_request(&self) -> Option<f64> {
+        self.groups_request.lock().unwrap().take()
+    }
+
+    /// Groups found for the last request, if they have not been taken yet.
+    pub fn take_groups(&self) -> Option<FofGroups> {
+        self.groups.lock().unwrap().take()
+    }
+
+    pub fn set_groups(&self, groups: FofGroups) {
+        *self.groups.lock().unwrap() = Some(groups);
+    }
+
+    /// Ask the simulation to estimate the local density around every object at each
+    /// sample, see `local_densities`, or to stop doing so.
+    pub fn set_densities_wanted(&self, wanted: bool) {
+        self.densities_wanted.store(wanted, Ordering::Relaxed);
+        if !wanted {
+            self.densities.lock().unwrap().take();
+        }
+    }
+
+    /// Whether densities are wanted and the last ones have been taken, so that the
+    /// simulation never spends time on densities nobody will look at.
+    pub fn wants_densities(&self) -> bool {
+        self.densities_wanted.load(Ordering::Relaxed) && self.densities.lock().unwrap().is_none()
+    }
+
+    /// Density around each object in earth masses per cubic AU, if there are new ones.
+    pub fn take_densities(&self) -> Option<Vec<f32>> {
+        self.densities.lock().unwrap().take()
+    }
+
+    pub fn set_densities(&self, densities: Vec<f32>) {
+        *self.densities.lock().unwrap() = Some(densities);
+    }
+
+    /// Ask the simulation for the state of the object at `index` relative to its dominant
+    /// attractor at each sample, or to stop with `None`.
+    pub fn set_orbit_focus(&self, index: Option<usize>) {
+        *self.orbit_focus.lock().unwrap() = index;
+        if index.is_none() {
+            self.orbit.lock().unwrap().take();
+        }
+    }
+
+    pub fn orbit_focus(&self) -> Option<usize> {
+        *self.orbit_focus.lock().unwrap()
+    }
+
+    /// Relative state of the object asked for, if there is a new one.
+    pub fn take_orbit(&self) -> Option<RelativeState> {
+        self.orbit.lock().unwrap().take()
+    }
+
+    pub fn set_orbit(&self, orbit: Option<RelativeState>) {
+        *self.orbit.lock().unwrap() = orbit;
+    }
+
     /// Return whether we are ready to a accept a new simulation batch.
     pub fn should_store(&self) -> bool {
         self.should_sample
@@ -39,25 +388,39 @@ impl BatchRequest {
             .is_ok()
     }
 
-    /// Store a sample of each simulated object, as well as the current tick.
-    pub fn store<R>(&self, sim: &ObjectBuffer<R>, tick: u64) {
-        self.simulation_tick.store(tick, Ordering::Relaxed);
-        let mut data = self.sample.lock().unwrap();
-        for (buff, obj) in data.iter_mut().zip(sim.objects.iter()) {
+    /// Store a sample of each simulated object, as well as the current time.
+    pub fn store<R>(&self, sim: &ObjectBuffer<R>, time: SimTime) {
+        let mut back = self.back.lock().unwrap();
+        back.time = time;
+        for (buff, obj) in back.positions.iter_mut().zip(sim.objects.iter()) {
             buff[0] = obj.pos.x as f32;
             buff[1] = obj.pos.y as f32;
             buff[2] = obj.pos.z as f32;
         }
+
+        // The renderer only holds the front buffer while reading it, so this is brief.
+        let mut front = self.front.lock().unwrap();
+        std::mem::swap(&mut *front, &mut *back);
+        self.fresh.store(true, Ordering::Release);
     }
 
-    /// Retrieve a sample, and request a new one from the simulation.
+    /// Retrieve a sample if a new one is available, and request a new one from the simulation.
     pub fn sample(&self, objects: &mut Objects) {
-        let data = self.sample.lock().unwrap();
-        objects.push_items(&data);
+        self.sample_with(|frame| objects.push_items(&frame.positions, frame.time));
+    }
+
+    /// Retrieve the latest snapshot, passing it to `f`, and request a new one from the
+    /// simulation. Returns `None` without calling `f` if there has been no new snapshot
+    /// since the last call.
+    pub fn sample_with<T>(&self, f: impl FnOnce(&FrameSnapshot) -> T) -> Option<T> {
+        let front = self.front.lock().unwrap();
+        let res = self.fresh.swap(false, Ordering::Acquire).then(|| f(&front));
         self.should_sample.store(true, Ordering::Relaxed);
+        res
     }
 
-    pub fn current_ticks(&self) -> u64 {
-        self.simulation_tick.load(Ordering::Relaxed)
+    /// Time of the latest published snapshot.
+    pub fn current_time(&self) -> SimTime {
+        self.front.lock().unwrap().time
     }
 }
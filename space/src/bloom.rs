@@ -0,0 +1,307 @@
+//! Glow around bright parts of the scene, added when it is copied to the output.
+//!
+//! The scene is drawn to an HDR texture. The parts of it brighter than a threshold are
+//! copied to a texture of half the size, blurred in two passes, one along each axis, and
+//! added back onto the scene as it is written to the output.
+
+use wgpu::{
+    BindGroup, BindGroupLayout, CommandEncoder, Device, Extent3d, PipelineLayout, RenderPipeline,
+    Sampler, Texture, TextureFormat, TextureView,
+};
+
+use crate::{
+    BloomConstants, pipeline_cache,
+    render::{HDR_FORMAT, get_or_init_shader, texture_memory},
+};
+
+pub(crate) struct BloomPipeline {
+    input_layout: BindGroupLayout,
+    composite_layout: BindGroupLayout,
+    sampler: Sampler,
+    bright: RenderPipeline,
+    blur: RenderPipeline,
+    composite: RenderPipeline,
+    targets: Option<BloomTargets>,
+}
+
+/// Textures the bloom is drawn to, and the bind groups reading them, for a scene of a
+/// given size.
+struct BloomTargets {
+    size: Extent3d,
+    textures: [(Texture, TextureView); 2],
+    scene: BindGroup,
+    blur: [BindGroup; 2],
+    composite: BindGroup,
+}
+
+fn texture_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    }
+}
+
+fn sampler_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+        count: None,
+    }
+}
+
+impl BloomPipeline {
+    /// Pipelines writing the result to textures of `output_format`.
+    pub fn new(device: &Device, output_format: TextureFormat) -> Self {
+        let input_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("bloom input layout"),
+            entries: &[texture_entry(0), sampler_entry(1)],
+        });
+        let composite_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("bloom composite layout"),
+            entries: &[texture_entry(0), sampler_entry(1), texture_entry(2)],
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("bloom sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let input_pipeline_layout = Self::pipeline_layout(device, &input_layout);
+        let composite_pipeline_layout = Self::pipeline_layout(device, &composite_layout);
+        Self {
+            bright: Self::build(
+                device,
+                &input_pipeline_layout,
+                "bloom_bright_fs",
+                HDR_FORMAT,
+            ),
+            blur: Self::build(device, &input_pipeline_layout, "bloom_blur_fs", HDR_FORMAT),
+            composite: Self::build(
+                device,
+                &composite_pipeline_layout,
+                "bloom_composite_fs",
+                output_format,
+            ),
+            input_layout,
+            composite_layout,
+            sampler,
+            targets: None,
+        }
+    }
+
+    fn pipeline_layout(device: &Device, layout: &BindGroupLayout) -> PipelineLayout {
+        device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[layout],
+            push_constant_ranges: &[wgpu::PushConstantRange {
+                stages: wgpu::ShaderStages::FRAGMENT,
+                range: 0..std::mem::size_of::<BloomConstants>() as u32,
+            }],
+        })
+    }
+
+    /// Pipeline drawing a quad over the whole target with the fragment shader `entry_point`.
+    fn build(
+        device: &Device,
+        layout: &PipelineLayout,
+        entry_point: &str,
+        format: TextureFormat,
+    ) -> RenderPipeline {
+        pipeline_cache::mark_built();
+        let shader_module = get_or_init_shader(device);
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(entry_point),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader_module,
+                entry_point: Some("copy_texture_vs"),
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            cache: pipeline_cache::get(),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: shader_module,
+                entry_point: Some(entry_point),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            multiview: None,
+        })
+    }
+
+    /// Read the scene from `scene`, of `size`, from now on.
+    pub fn set_scene(&mut self, device: &Device, scene: &TextureView, size: Extent3d) {
+        let size = Extent3d {
+            width: (size.width / 2).max(1),
+            height: (size.height / 2).max(1),
+            depth_or_array_layers: 1,
+        };
+        let textures = [0, 1].map(|_| {
+            let texture = device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("bloom texture"),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: HDR_FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            });
+            let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+            (texture, view)
+        });
+        let input = |view: &TextureView| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("bloom input"),
+                layout: &self.input_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&self.sampler),
+                    },
+                ],
+            })
+        };
+        let composite = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("bloom composite"),
+            layout: &self.composite_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(scene),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&textures[0].1),
+                },
+            ],
+        });
+        self.targets = Some(BloomTargets {
+            size,
+            scene: input(scene),
+            blur: [input(&textures[0].1), input(&textures[1].1)],
+            composite,
+            textures,
+        });
+    }
+
+    /// Bytes allocated on the GPU.
+    pub fn memory_usage(&self) -> u64 {
+        self.targets.as_ref().map_or(0, |targets| {
+            targets
+                .textures
+                .iter()
+                .map(|(texture, _)| texture_memory(texture))
+                .sum()
+        })
+    }
+
+    /// Write the scene to `output` with the glow added. Without `intensity` the scene is
+    /// copied as it is.
+    pub fn draw(
+        &self,
+        encoder: &mut CommandEncoder,
+        output: &TextureView,
+        threshold: f32,
+        intensity: f32,
+    ) {
+        let Some(targets) = &self.targets else {
+            return;
+        };
+        let texel = [
+            1.0 / targets.size.width as f32,
+            1.0 / targets.size.height as f32,
+        ];
+        let constants = |step| BloomConstants {
+            step,
+            threshold,
+            intensity,
+        };
+        let [(_, first), (_, second)] = &targets.textures;
+        if intensity > 0.0 {
+            Self::pass(
+                encoder,
+                first,
+                &self.bright,
+                &targets.scene,
+                constants([0.0, 0.0]),
+            );
+            Self::pass(
+                encoder,
+                second,
+                &self.blur,
+                &targets.blur[0],
+                constants([texel[0], 0.0]),
+            );
+            Self::pass(
+                encoder,
+                first,
+                &self.blur,
+                &targets.blur[1],
+                constants([0.0, texel[1]]),
+            );
+        }
+        Self::pass(
+            encoder,
+            output,
+            &self.composite,
+            &targets.composite,
+            constants([0.0, 0.0]),
+        );
+    }
+
+    fn pass(
+        encoder: &mut CommandEncoder,
+        target: &TextureView,
+        pipeline: &RenderPipeline,
+        input: &BindGroup,
+        constants: BloomConstants,
+    ) {
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            ..Default::default()
+        });
+        rpass.set_pipeline(pipeline);
+        rpass.set_bind_group(0, input, &[]);
+        rpass.set_push_constants(
+            wgpu::ShaderStages::FRAGMENT,
+            0,
+            bytemuck::bytes_of(&constants),
+        );
+        rpass.draw(0..6, 0..1);
+    }
+}
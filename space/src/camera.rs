@@ -1,13 +1,13 @@
 use std::mem::size_of;
 
-use cgmath::{InnerSpace, Matrix4, Rad, SquareMatrix, Vector3, Zero};
+use cgmath::{InnerSpace, Matrix4, Rad, SquareMatrix, Vector2, Vector3, Zero};
 use wgpu::{
     BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
     BindGroupLayoutEntry, Buffer, BufferDescriptor, BufferUsages, Device, Queue,
 };
 use winit::dpi::PhysicalSize;
 
-use crate::{event_loop::KeyboardState, objects::Objects};
+use crate::{constants::CAMERA_NEAR_PLANE, event_loop::KeyboardState, objects::Objects};
 
 pub struct Camera {
     pub eye: cgmath::Point3<f32>,
@@ -85,12 +85,14 @@ impl Camera {
 
         let e = 1.0 / ((self.fovy / 2.0).tan());
         let a = self.aspect;
-        let epsilon = 3e-7;
+        // Infinite far plane with reversed depth, which is the near plane over the distance:
+        // 1 at the near plane and falling towards 0 at infinity. Floats are densest near 0, so this
+        // keeps depth precise from the near plane out to galactic distances.
         #[rustfmt::skip]
         let mut inf_proj = cgmath::Matrix4::new(
             e, 0.0, 0.0, 0.0,
             0.0, e * a, 0.0, 0.0,
-            0.0, 0.0, epsilon-1.0, (epsilon - 2.0) * 1e-10,
+            0.0, 0.0, 0.0, CAMERA_NEAR_PLANE,
             0.0, 0.0, -1.0, 0.0);
         inf_proj.transpose_self();
 
@@ -112,7 +114,7 @@ impl Camera {
             label: Some("Camera buffer layout"),
             entries: &[BindGroupLayoutEntry {
                 binding: 0,
-                visibility: wgpu::ShaderStages::VERTEX,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
                 ty: wgpu::BindingType::Buffer {
                     ty: wgpu::BufferBindingType::Uniform,
                     has_dynamic_offset: false,
@@ -162,6 +164,19 @@ impl Camera {
         self.changed = true;
     }
 
+    /// Look at `target` from `eye`, with `up` pointing up on screen.
+    pub fn look_at(
+        &mut self,
+        eye: cgmath::Point3<f32>,
+        target: cgmath::Point3<f32>,
+        up: Vector3<f32>,
+    ) {
+        self.eye = eye;
+        self.target = target;
+        self.up = up;
+        self.changed = true;
+    }
+
     pub fn focus(&self) -> Option<i64> {
         self.focus
     }
@@ -287,6 +302,15 @@ impl Camera {
         self.changed = true;
     }
 
+    /// Where `pos` is drawn, with both coordinates in [-1, 1] inside the view and y
+    /// pointing up, or `None` if it is behind the camera. Positions relative to a target
+    /// object must have its position subtracted first, as the shaders do.
+    pub fn project(&self, pos: cgmath::Point3<f32>) -> Option<Vector2<f32>> {
+        // In two stages, like the shaders, which is more stable when zoomed in.
+        let clip = self.projection * (self.view * pos.to_homogeneous());
+        (clip.w > 0.0).then(|| Vector2::new(clip.x / clip.w, clip.y / clip.w))
+    }
+
     #[allow(unused)]
     pub fn matrix(&self) -> Matrix4<f32> {
         self.view_proj
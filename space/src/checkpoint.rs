@@ -0,0 +1,262 @@
+//! Periodic checkpoints of a running simulation, to resume from after a crash.
+//!
+//! A checkpoint holds the state of every simulated object, the simulated time and which
+//! injections are still waiting, but not the scenario itself. A run is resumed by
+//! starting it again with the same options, which builds the same objects, perturbations
+//! and injections, and then taking the state from the latest checkpoint.
+//!
+//! Layout, little endian: magic, version, tick, delta and simulated seconds, then the
+//! number of objects and for each its position, velocity and mass as `f64`s and a `u8`
+//! with the pinned and test particle flags, then the number of waiting injections and
+//! the index of each as `u32`s.
+//!
+//! Checkpoints are written to a temporary file and renamed into place, so that a crash
+//! while writing leaves the earlier ones intact. Only the latest few are kept.
+
+use std::{
+    fs::{self, File},
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, bail, ensure};
+
+use crate::{
+    Object,
+    constants::CHECKPOINTS_KEPT,
+    inject::InjectionSchedule,
+    sim::{ObjectInfo, SimTime},
+};
+
+const MAGIC: &[u8; 8] = b"NBODYCHK";
+const VERSION: u32 = 1;
+/// Size of each object, its position, velocity and mass and its flags.
+const OBJECT_LEN: u64 = 7 * 8 + 1;
+const EXTENSION: &str = "chk";
+
+/// State of a run at the time of a checkpoint.
+pub struct Checkpoint {
+    pub time: SimTime,
+    pub objects: Vec<ObjectInfo>,
+    /// Injections that had not happened yet, counted in the order they were added.
+    pub pending_injections: Vec<usize>,
+}
+
+/// Whether `reader` starts like a checkpoint.
+pub fn is_checkpoint(mut reader: impl Read) -> bool {
+    let mut magic = [0u8; 8];
+    reader.read_exact(&mut magic).is_ok() && &magic == MAGIC
+}
+
+impl Checkpoint {
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path)
+            .with_context(|| format!("Failed to open checkpoint {}", path.display()))?;
+        Self::read(BufReader::new(file))
+            .with_context(|| format!("Invalid checkpoint {}", path.display()))
+    }
+
+    pub fn read(mut reader: impl Read + Seek) -> anyhow::Result<Self> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            bail!("Not a checkpoint");
+        }
+        let version = read_u32(&mut reader)?;
+        if version != VERSION {
+            bail!("Unsupported checkpoint version {version}");
+        }
+        let time = SimTime {
+            ticks: read_u64(&mut reader)?,
+            delta: read_f64(&mut reader)?,
+            seconds: read_f64(&mut reader)?,
+        };
+        let count = read_u32(&mut reader)?;
+        // Checked against the size of the file before allocating, so a corrupt count
+        // fails instead of exhausting memory.
+        let start = reader.stream_position()?;
+        let len = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(start))?;
+        ensure!(
+            count as u64 * OBJECT_LEN <= len - start,
+            "Checkpoint is truncated, or has an invalid number of objects"
+        );
+        let mut objects = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let mut values = [0.0; 7];
+            for value in &mut values {
+                *value = read_f64(&mut reader)?;
+            }
+            let mut flags = [0];
+            reader.read_exact(&mut flags)?;
+            objects.push(ObjectInfo {
+                pos: [values[0], values[1], values[2]].into(),
+                vel: [values[3], values[4], values[5]].into(),
+                mass: values[6],
+                pinned: flags[0] & 1 != 0,
+                test_particle: flags[0] & 2 != 0,
+            });
+        }
+        let count = read_u32(&mut reader)?;
+        let pending_injections = (0..count)
+            .map(|_| Ok(read_u32(&mut reader)? as usize))
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self {
+            time,
+            objects,
+            pending_injections,
+        })
+    }
+
+    /// Put `objects` in the state of the checkpoint, and forget the injections that had
+    /// already happened. Fails if the checkpoint is for a different number of objects.
+    pub fn restore(
+        &self,
+        objects: &mut [Object],
+        injections: &mut InjectionSchedule,
+    ) -> anyhow::Result<()> {
+        if objects.len() != self.objects.len() {
+            bail!(
+                "Checkpoint has {} objects, but the scenario has {}. Resume with the same options as the run that wrote it",
+                self.objects.len(),
+                objects.len()
+            );
+        }
+        for (obj, info) in objects.iter_mut().zip(&self.objects) {
+            obj.dat = info.clone();
+        }
+        injections.retain_pending(&self.pending_injections);
+        Ok(())
+    }
+}
+
+/// Write a checkpoint of `objects` at `time`, with the injections still `pending`.
+pub fn write_checkpoint(
+    mut writer: impl Write,
+    time: SimTime,
+    objects: &[ObjectInfo],
+    pending: &[usize],
+) -> anyhow::Result<()> {
+    writer.write_all(MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&time.ticks.to_le_bytes())?;
+    writer.write_all(&time.delta.to_le_bytes())?;
+    writer.write_all(&time.seconds.to_le_bytes())?;
+    writer.write_all(&(objects.len() as u32).to_le_bytes())?;
+    for obj in objects {
+        for v in [
+            obj.pos.x, obj.pos.y, obj.pos.z, obj.vel.x, obj.vel.y, obj.vel.z, obj.mass,
+        ] {
+            writer.write_all(&v.to_le_bytes())?;
+        }
+        writer.write_all(&[u8::from(obj.pinned) | u8::from(obj.test_particle) << 1])?;
+    }
+    writer.write_all(&(pending.len() as u32).to_le_bytes())?;
+    for index in pending {
+        writer.write_all(&(*index as u32).to_le_bytes())?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Checkpoints in `dir`, oldest first by when they were written.
+fn list(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut found = Vec::new();
+    for entry in fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+        let entry = entry?;
+        let path = entry.path();
+        if path.extension().is_some_and(|ext| ext == EXTENSION) {
+            found.push((entry.metadata()?.modified()?, path));
+        }
+    }
+    found.sort();
+    Ok(found.into_iter().map(|(_, path)| path).collect())
+}
+
+/// The checkpoint written last in `dir`, if there is one.
+pub fn latest(dir: impl AsRef<Path>) -> anyhow::Result<Option<PathBuf>> {
+    Ok(list(dir.as_ref())?.pop())
+}
+
+/// Writes a checkpoint to a directory every so often.
+pub struct Checkpoints {
+    dir: PathBuf,
+    interval: Duration,
+    last: Instant,
+    resumed: Option<SimTime>,
+}
+
+impl Checkpoints {
+    /// Write checkpoints to `dir` every `interval` of wall clock time, creating it if
+    /// needed.
+    pub fn create(dir: impl AsRef<Path>, interval: Duration) -> anyhow::Result<Self> {
+        let dir = dir.as_ref().to_owned();
+        fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        Ok(Self {
+            dir,
+            interval,
+            last: Instant::now(),
+            resumed: None,
+        })
+    }
+
+    /// Continue the run from `time`, where a checkpoint was taken.
+    pub fn resumed_at(mut self, time: SimTime) -> Self {
+        self.resumed = Some(time);
+        self
+    }
+
+    /// Time the run continues from, if it was resumed.
+    pub fn resumed(&self) -> Option<SimTime> {
+        self.resumed
+    }
+
+    pub fn is_due(&self) -> bool {
+        self.last.elapsed() >= self.interval
+    }
+
+    /// Write a checkpoint, and remove all but the latest `CHECKPOINTS_KEPT`. Returns the
+    /// path written.
+    pub fn save(
+        &mut self,
+        time: SimTime,
+        objects: &[ObjectInfo],
+        injections: &InjectionSchedule,
+    ) -> anyhow::Result<PathBuf> {
+        self.last = Instant::now();
+        let path = self
+            .dir
+            .join(format!("checkpoint-{:010}.{EXTENSION}", time.ticks));
+        let partial = path.with_extension("partial");
+        let file = File::create(&partial)
+            .with_context(|| format!("Failed to create {}", partial.display()))?;
+        write_checkpoint(BufWriter::new(file), time, objects, &injections.pending())
+            .with_context(|| format!("Failed to write {}", partial.display()))?;
+        fs::rename(&partial, &path)
+            .with_context(|| format!("Failed to move checkpoint to {}", path.display()))?;
+
+        let written = list(&self.dir)?;
+        for old in &written[..written.len().saturating_sub(CHECKPOINTS_KEPT)] {
+            fs::remove_file(old).with_context(|| format!("Failed to remove {}", old.display()))?;
+        }
+        Ok(path)
+    }
+}
+
+fn read_u32(reader: &mut impl Read) -> anyhow::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(reader: &mut impl Read) -> anyhow::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_f64(reader: &mut impl Read) -> anyhow::Result<f64> {
+    Ok(f64::from_bits(read_u64(reader)?))
+}
@@ -1,46 +1,89 @@
 use wgpu::{
     BindGroup, BindGroupLayout, BlendComponent, BlendFactor, BlendState, Buffer, Device,
-    PipelineCompilationOptions, PipelineLayoutDescriptor, PrimitiveState, RenderPass,
-    RenderPipeline, RenderPipelineDescriptor, TextureFormat,
+    PipelineCompilationOptions, PipelineLayout, PipelineLayoutDescriptor, PrimitiveState,
+    RenderPass, RenderPipeline, RenderPipelineDescriptor, TextureFormat,
 };
 
 use crate::{
     ShaderConstants,
     objects::{ObjectInstance, Vertex},
-    render::get_or_init_shader,
+    permutations::{PipelineCache, ShaderOptions},
+    pipeline_cache,
+    render::{depth_stencil_state, get_or_init_shader},
 };
 
 pub(crate) struct CircleDrawPipeline {
-    pipeline: RenderPipeline,
+    layout: PipelineLayout,
+    texture_format: TextureFormat,
+    sample_count: u32,
+    pipelines: PipelineCache,
 }
 
 impl CircleDrawPipeline {
     pub fn new(
         device: &Device,
         texture_format: TextureFormat,
+        sample_count: u32,
         camera_layout: &BindGroupLayout,
+        textures_layout: &BindGroupLayout,
     ) -> Self {
         let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
             label: None,
-            bind_group_layouts: &[camera_layout],
+            bind_group_layouts: &[camera_layout, textures_layout],
             push_constant_ranges: &[wgpu::PushConstantRange {
                 stages: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
                 range: 0..std::mem::size_of::<ShaderConstants>() as u32,
             }],
         });
 
-        let shader_module = get_or_init_shader(device);
+        let mut pipeline = Self {
+            layout: pipeline_layout,
+            texture_format,
+            sample_count,
+            pipelines: PipelineCache::new(),
+        };
+        pipeline.prepare(device, ShaderOptions::default());
+        pipeline
+    }
+
+    /// Build the pipeline for `options`, if it has not been built yet.
+    pub fn prepare(&mut self, device: &Device, options: ShaderOptions) {
+        let (layout, texture_format, sample_count) =
+            (&self.layout, self.texture_format, self.sample_count);
+        self.pipelines.prepare(options, |options| {
+            Self::build(device, layout, texture_format, sample_count, options)
+        });
+    }
+
+    /// Render to targets with `sample_count` samples, rebuilding the pipelines if it changed.
+    pub fn set_sample_count(&mut self, sample_count: u32) {
+        if sample_count != self.sample_count {
+            self.sample_count = sample_count;
+            self.pipelines = PipelineCache::new();
+        }
+    }
 
-        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+    fn build(
+        device: &Device,
+        layout: &PipelineLayout,
+        texture_format: TextureFormat,
+        sample_count: u32,
+        options: ShaderOptions,
+    ) -> RenderPipeline {
+        let shader_module = get_or_init_shader(device);
+        device.create_render_pipeline(&RenderPipelineDescriptor {
             label: Some("circle pipeline"),
-            layout: Some(&pipeline_layout),
+            layout: Some(layout),
             vertex: wgpu::VertexState {
                 module: shader_module,
-                entry_point: Some("circle_vs"),
-                buffers: &[Vertex::layout::<false, 0>(), ObjectInstance::layout::<2>()],
+                entry_point: Some(&options.entry_point("circle_vs")),
+                buffers: &[
+                    Vertex::layout::<false, 0>(),
+                    ObjectInstance::textured_layout::<2>(),
+                ],
                 compilation_options: Default::default(),
             },
-            cache: None,
+            cache: pipeline_cache::get(),
             primitive: PrimitiveState {
                 topology: wgpu::PrimitiveTopology::TriangleList,
                 strip_index_format: None,
@@ -50,9 +93,9 @@ impl CircleDrawPipeline {
                 polygon_mode: wgpu::PolygonMode::Fill,
                 conservative: false,
             },
-            depth_stencil: None,
+            depth_stencil: Some(depth_stencil_state(true)),
             multisample: wgpu::MultisampleState {
-                count: 1,
+                count: sample_count,
                 mask: !0,
                 alpha_to_coverage_enabled: false,
             },
@@ -74,9 +117,7 @@ impl CircleDrawPipeline {
                 compilation_options: PipelineCompilationOptions::default(),
             }),
             multiview: None,
-        });
-
-        Self { pipeline }
+        })
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -84,20 +125,23 @@ impl CircleDrawPipeline {
         &self,
         rpass: &mut RenderPass<'_>,
         camera: &BindGroup,
+        textures: &BindGroup,
         last_batch_range: std::ops::Range<u64>,
         point_buffer: &Buffer,
         instance_buffer: &Buffer,
         push_constants: &ShaderConstants,
+        options: ShaderOptions,
         num_objects: usize,
     ) {
         let last_batch_range =
             (last_batch_range.start * Vertex::size())..(last_batch_range.end * Vertex::size());
 
-        rpass.set_pipeline(&self.pipeline);
+        rpass.set_pipeline(self.pipelines.get(options));
         rpass.set_vertex_buffer(0, point_buffer.slice(last_batch_range.clone()));
         rpass.set_vertex_buffer(1, instance_buffer.slice(..));
 
         rpass.set_bind_group(0, camera, &[]);
+        rpass.set_bind_group(1, textures, &[]);
 
         rpass.set_push_constants(
             wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
@@ -1,30 +1,216 @@
 // PHYSICAL
 /// Average distance between earth and the sun, in meters
 pub const AU: f64 = 1.495e11;
+/// Astronomical unit as defined by the IAU, in meters, used by ephemerides
+pub const IAU_AU: f64 = 1.495_978_707e11;
 /// Mass of earth, in kilograms
 pub const M0: f64 = 5.972e24;
 /// SI gravitational constant, in m^3 kg^-1 s^-2
 pub const G_ABS: f64 = 6.674e-11;
 /// Adjusted gravitational constant in earth masses and AU
 pub const G: f64 = G_ABS * M0 / (AU * AU * AU);
+/// Speed of light in AU per second
+pub const C: f64 = 299_792_458.0 / AU;
+/// Mass of the sun, in earth masses
+pub const SOLAR_MASS: f64 = 333000.0;
+/// Radius of the sun, in AU
+pub const SOLAR_RADIUS: f64 = 696340e3 / AU;
+/// Parsec, in AU
+pub const PARSEC: f64 = 206_264.8;
+/// Luminosity of the sun, in watts
+pub const L_SUN: f64 = 3.828e26;
 /// Seconds per computation (really!). Legacy only.
 pub const DELTA: f64 = 10.0;
 /// Padding between all objects to avoid division by zero, 10 meters.
 pub const COLLISION_EPSILON: f64 = 1e-15;
 
 // SIMULATION
-/// Hard cap on number of threads to use.
+/// Hard cap on number of threads to use, unless set by hand.
 pub const MAX_THREADS: usize = 20;
 /// Minimum number of objects per thread.
 pub const OBJECTS_PER_THREAD: usize = 2000;
+/// Chunks of objects per thread in parallel loops, to leave room for balancing the load
+pub const PAR_CHUNKS_PER_THREAD: usize = 4;
+/// Least work, in interactions or updates of single objects, handed to a thread at once
+pub const PAR_MIN_CHUNK_WORK: usize = 4096;
+/// Levels of the tree the FMM splits into node pairs walked in parallel
+pub const FMM_SPLIT_DEPTH: usize = 2;
+/// Seconds of steps timed before the number of threads is reconsidered
+pub const THREAD_SCALING_WINDOW: f64 = 0.25;
+/// Fewer threads are preferred while they are at most this much slower, as a fraction
+pub const THREAD_SCALING_TOLERANCE: f64 = 0.05;
+/// Windows between measuring the neighbouring numbers of threads again
+pub const THREAD_PROBE_INTERVAL: u32 = 16;
 /// Interval in ticks
 pub const CHECK_INTERVAL: u64 = 1;
+/// Ticks the simulation may run ahead of the last sample before it waits for the next,
+/// unless changed with `--max-unsampled-ticks`
+pub const MAX_UNSAMPLED_TICKS: u64 = 1_000_000;
+/// Times the simulation yields while waiting for a sample, before it starts sleeping
+pub const SAMPLE_WAIT_YIELDS: u32 = 64;
+/// Times the sleeps while waiting for a sample double, from 50 microseconds
+pub const SAMPLE_WAIT_MAX_DOUBLINGS: u32 = 7;
+/// Name of the simulation thread, and prefix of its worker threads
+pub const SIM_THREAD_NAME: &str = "sim";
+/// Name of the thread writing trajectories of watched objects
+pub const TRAJECTORY_THREAD_NAME: &str = "trajectory";
+/// Rows of watched trajectories handed to the writer at a time
+pub const TRAJECTORY_BATCH_ROWS: usize = 4096;
+/// Batches of watched trajectories that may wait for the writer before the simulation
+/// waits too
+pub const TRAJECTORY_QUEUE_BATCHES: usize = 64;
+/// Name of the thread writing Parquet snapshots of every object
+pub const PARTICLE_SNAPSHOT_THREAD_NAME: &str = "snapshots";
+/// Steps between Parquet snapshots, one simulated day at the default time step
+pub const PARTICLE_SNAPSHOT_INTERVAL: u64 = 8640;
+/// Objects per row group of a Parquet snapshot
+pub const PARTICLE_SNAPSHOT_ROW_GROUP_ROWS: usize = 1 << 20;
+/// Minutes of wall clock time between checkpoints
+pub const CHECKPOINT_INTERVAL_MINUTES: f64 = 10.0;
+/// Checkpoints kept in the checkpoint directory, older ones are removed
+pub const CHECKPOINTS_KEPT: usize = 3;
+/// Seconds between progress reports of a run without a window
+pub const HEADLESS_REPORT_SECS: f64 = 10.0;
+/// Name of the thread streaming positions over WebSocket, and prefix of the threads
+/// accepting clients
+pub const STREAM_THREAD_NAME: &str = "stream";
+/// Samples streamed per second at most
+pub const STREAM_MAX_RATE: f64 = 30.0;
+/// Milliseconds a stream client may take to complete the handshake or receive a sample
+/// before it is dropped
+pub const STREAM_TIMEOUT_MS: u64 = 1000;
+/// Name of the thread answering control requests
+pub const CONTROL_THREAD_NAME: &str = "control";
+/// Slots reserved for bodies added through the control API, unless changed with
+/// `--control-spares`
+pub const CONTROL_SPARE_BODIES: usize = 16;
+/// Samples per second taken by the `--profile-secs` profiler
+pub const PROFILE_FREQUENCY: i32 = 100;
 /// 30 seconds of trail
 pub const TRAIL_MAX_LENGTH: usize = 5;
+/// Maximum number of sampled states kept for scrubbing back through the run
+pub const HISTORY_MAX_FRAMES: usize = 512;
+/// Separate spans of changed object descriptions kept apart for uploading, beyond which
+/// they are uploaded as the one span that covers them all
+pub const MAX_DIRTY_SPANS: usize = 16;
+/// Quantization tolerance for recorded positions, relative to the distance of each object
+/// from the origin (about 150 km at 1 AU)
+pub const RECORDING_RELATIVE_TOLERANCE: f32 = 1e-6;
+/// Finest quantization tolerance for recorded positions, in AU (about 150 meters)
+pub const RECORDING_MIN_TOLERANCE: f32 = 1e-9;
+/// Number of frames between each full frame in recordings
+pub const RECORDING_KEYFRAME_INTERVAL: u32 = 64;
+/// Number of recorded frames shown per second when replaying at normal speed
+pub const REPLAY_FRAMES_PER_SECOND: f64 = 30.0;
+/// Maximum number of samples kept per probe
+pub const PROBE_MAX_SAMPLES: usize = 4096;
+/// Radius of newly placed probes, in AU
+pub const PROBE_DEFAULT_RADIUS: f64 = 1.0;
+/// Radius of the markers drawn at the dates of an ephemeris, in points
+pub const EPHEMERIS_MARKER_RADIUS: f32 = 3.0;
+/// Distance from the body they pass at which flyby stars are injected, in AU
+pub const FLYBY_START_DISTANCE: f64 = 100.0;
+/// Distance counted as a close approach in impact studies, in AU. Bodies passing within
+/// this distance of earth are considered potentially hazardous.
+pub const IMPACT_CLOSE_APPROACH: f64 = 0.05;
+/// Lowest density in kg/m^3 accepted when validating scenarios, about that of air
+pub const VALIDATE_MIN_DENSITY: f64 = 1.0;
+/// Highest density in kg/m^3 accepted when validating scenarios, above that of neutron stars
+pub const VALIDATE_MAX_DENSITY: f64 = 1e18;
+/// Number of issues of each kind printed in validation reports
+pub const VALIDATE_MAX_LISTED: usize = 10;
+/// Number of rings from pole to pole in rendered Roche lobes
+pub const ROCHE_LATITUDES: usize = 24;
+/// Number of segments around each ring in rendered Roche lobes
+pub const ROCHE_LONGITUDES: usize = 48;
+/// Opacity of rendered Roche lobes
+pub const ROCHE_ALPHA: f32 = 0.15;
+/// Bisection steps when solving for L1 and the lobe surface
+pub const ROCHE_BISECTION_STEPS: usize = 40;
 /// Minimum size of object when rendering circles
 pub const MIN_CIRCLE_SIZE: f32 = 0.05;
+/// Largest projected radius of an object rendered as a circle, relative to half the screen
+/// height. Larger objects are tessellated.
+pub const MAX_CIRCLE_SIZE: f32 = 0.25;
+/// Segments around the horizon of a tessellated object, per unit of projected radius
+pub const SPHERE_SEGMENTS_PER_SIZE: f32 = 256.0;
+/// Minimum number of segments around the horizon of a tessellated object
+pub const SPHERE_MIN_SEGMENTS: usize = 64;
+/// Maximum number of segments around the horizon of a tessellated object
+pub const SPHERE_MAX_SEGMENTS: usize = 2048;
+/// Distance to the near plane of the camera, in AU. Anything closer is clipped
+pub const CAMERA_NEAR_PLANE: f32 = 1e-10;
+/// Samples per pixel drawn by default, to smooth the edges of trails and circles. Every
+/// device supports 4
+pub const DEFAULT_SAMPLE_COUNT: u32 = 4;
+/// Luminance above which the scene glows, on the scale where white is 1
+pub const BLOOM_THRESHOLD: f32 = 0.7;
+/// Default strength of the glow around bright objects
+pub const BLOOM_INTENSITY: f32 = 1.0;
+/// Default simulated seconds over which trails fade when they fade by age, a year
+pub const TRAIL_FADE_TIME: f32 = 3.155_76e7;
+/// Range of the trail fade time that can be picked, in days, from an hour to a billion
+/// years
+pub const TRAIL_FADE_DAYS_RANGE: (f32, f32) = (1.0 / 24.0, 3.652_5e11);
+/// Directory the solar system presets look for maps of the planets in, relative to the
+/// working directory
+pub const PLANET_TEXTURE_DIR: &str = "textures";
+/// Size of each layer of the array holding the maps of the objects. Maps of other sizes are
+/// resampled to fit
+pub const PLANET_TEXTURE_SIZE: (u32, u32) = (1024, 512);
+/// Fraction of objects kept in view when framing headless snapshots
+pub const SNAPSHOT_FRAMING_QUANTILE: f32 = 0.95;
+/// Extra space around the framed objects in headless snapshots
+pub const SNAPSHOT_MARGIN: f32 = 1.1;
+/// Directory for compiled pipelines, within the cache directory of the user
+pub const PIPELINE_CACHE_DIR: &str = "space";
+
+/// Smallest mass, in earth masses, of bodies acting as sources for the post-Newtonian correction
+pub const POST_NEWTONIAN_MIN_MASS: f64 = 1000.0;
+
+/// Typical Yarkovsky acceleration of a 1 km asteroid at 1 AU, in m/s^2. Scales inversely with size.
+pub const YARKOVSKY_1KM: f64 = 2e-13;
 
 /// Use barnes-hut if there are more than this many objects
 pub const BARNES_HUT_CUTOFF: usize = 1000;
 /// Barnes-Hut coefficient (theta). Smaller values = more accurate, but slower.
 pub const BARNES_HUT_COEFF: f64 = 0.3;
+/// Number of nearby objects sharing a single Barnes-Hut tree walk
+pub const BARNES_HUT_GROUP_SIZE: usize = 16;
+/// Margin added on every side of the root region of Barnes-Hut trees that are updated
+/// between steps, as a fraction of its size, so that objects at the edge can move a
+/// little before the whole tree has to be rebuilt
+pub const BARNES_HUT_TREE_MARGIN: f64 = 0.05;
+/// Default linking length of friends-of-friends groups, as a fraction of the mean
+/// separation of the objects
+pub const FOF_LINKING_FACTOR: f64 = 0.2;
+/// Fewest objects counted as a friends-of-friends group
+pub const FOF_MIN_MEMBERS: usize = 10;
+/// Number of friends-of-friends groups listed in the UI
+pub const FOF_LISTED_GROUPS: usize = 8;
+/// Number of nearest neighbors the local density around an object is estimated from
+pub const DENSITY_NEIGHBORS: usize = 32;
+/// Share of objects at either end of the range of densities given the lowest or highest
+/// color, so that a few outliers do not wash out the rest
+pub const DENSITY_COLOR_CLIP: f64 = 0.02;
+/// Number of tree nodes listed by a force accuracy check
+pub const FORCE_CHECK_NODES: usize = 8;
+/// Number of bodies listed in the gravity breakdown of the focused object
+pub const GRAVITY_BREAKDOWN_BODIES: usize = 5;
+/// Maximum number of objects in a kd-tree leaf
+pub const KD_TREE_LEAF_SIZE: usize = 8;
+/// Fraction the number of objects must pass a solver cutoff by before switching solver
+/// during a run, so that a count hovering around the cutoff does not flip back and forth
+pub const SOLVER_HYSTERESIS: f64 = 0.1;
+/// How many times the largest separation of a pair other objects must stay away from it
+/// for the pair to be propagated analytically
+pub const KEPLER_ISOLATION: f64 = 10.0;
+/// Pairs coming closer than this are left to the force solver, which pads the distance
+/// with `COLLISION_EPSILON`, instead of being propagated analytically, in AU
+pub const KEPLER_MIN_PERIAPSIS: f64 = 1e-4;
+/// Steps between searches for pairs to propagate analytically
+pub const KEPLER_SEARCH_INTERVAL: u32 = 64;
+/// Newton iterations allowed when solving Kepler's equation
+pub const KEPLER_MAX_ITERATIONS: usize = 50;
+/// Ticks between computing the virial ratio and other statistics of a running cluster
+pub const CLUSTER_STATS_INTERVAL: u64 = 256;
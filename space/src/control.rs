@@ -0,0 +1,310 @@
+//! HTTP API to control a running simulation, for scripting experiments against it.
+//!
+//! Request and response bodies are JSON:
+//!
+//! - `GET /status` returns the tick, simulated seconds, time step, Barnes-Hut theta,
+//!   whether the run is paused and the number of objects.
+//! - `POST /pause` and `POST /resume` stop and continue the simulation.
+//! - `POST /settings` with `delta` in seconds and `theta`, both optional, changes them.
+//! - `POST /bodies` with `mass` in earth masses, and `pos` and `vel` in m and m/s like in
+//!   a scenario file, adds a body and returns its `index`. An optional `name` replaces
+//!   the name of the spare slot in the position stream.
+//! - `DELETE /bodies/<index>` removes a body.
+//! - `POST /snapshot` with `name` writes a Parquet snapshot of every object to the file of
+//!   that name in the directory given with `--control-snapshots`, in the format of
+//!   `particle_snapshots`. Only bare file names are accepted, so nothing outside the
+//!   directory can be written. Without the directory snapshots are refused.
+//!
+//! The API is unauthenticated: anyone who can reach it can change the run and write
+//! snapshots. It only listens on loopback addresses unless `--control-public` is given.
+//! Requests other than `GET` must have `Content-Type: application/json`, even those
+//! without a body. Browsers cannot send that to another site without asking it first,
+//! so a web page open on the same machine cannot change the run.
+//!
+//! The number of objects is fixed for a run, see `inject`. Bodies are added into spare
+//! slots set aside with `spare_bodies` at the start, and removed by freezing them like
+//! escaped bodies. Removed spares are used again. Changes other than pausing are made
+//! the next time the simulation is sampled.
+
+use std::{
+    net::ToSocketAddrs,
+    ops::Range,
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{Context, bail, ensure};
+use cgmath::{Point3, Vector3};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::{
+    BatchRequest, Object,
+    constants::{AU, CONTROL_THREAD_NAME},
+    sim::ObjectInfo,
+};
+
+/// A change to the simulated bodies, requested through the API.
+#[derive(Debug, Clone)]
+pub enum BodyEdit {
+    /// Put a body in the slot at `index`, with a new name if there is one.
+    Add {
+        index: usize,
+        state: ObjectInfo,
+        name: Option<String>,
+    },
+    /// Freeze the body at this index.
+    Remove(usize),
+}
+
+impl BodyEdit {
+    pub fn apply(&self, objects: &mut [ObjectInfo]) {
+        match self {
+            Self::Add { index, state, .. } => objects[*index] = state.clone(),
+            Self::Remove(index) => {
+                let obj = &mut objects[*index];
+                obj.vel = Vector3::new(0.0, 0.0, 0.0);
+                obj.pinned = true;
+                obj.test_particle = true;
+            }
+        }
+    }
+}
+
+/// `count` empty slots for bodies added through the API, frozen at the origin.
+pub fn spare_bodies(count: usize) -> Vec<Object> {
+    (0..count)
+        .map(|i| Object {
+            name: format!("spare_{i}"),
+            dat: ObjectInfo {
+                pos: Point3::new(0.0, 0.0, 0.0),
+                vel: Vector3::new(0.0, 0.0, 0.0),
+                mass: 0.0,
+                pinned: true,
+                test_particle: true,
+            },
+            color: Vector3::new(1.0, 1.0, 1.0),
+            // Drawn the size of the earth, whatever ends up in the slot.
+            radius: (6.371e6 / AU) as f32,
+            oblateness: None,
+            radiation: None,
+            maneuvers: Vec::new(),
+            mass_rate: 0.0,
+            texture: None,
+        })
+        .collect()
+}
+
+#[derive(Deserialize)]
+struct Settings {
+    delta: Option<f64>,
+    theta: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct NewBody {
+    mass: f64,
+    pos: [f64; 3],
+    vel: [f64; 3],
+    #[serde(default)]
+    name: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SnapshotRequest {
+    name: String,
+}
+
+/// Where the API may make changes beyond the simulation itself.
+#[derive(Debug, Clone, Default)]
+pub struct ControlAccess {
+    /// Directory snapshots are written to, none to refuse them.
+    pub snapshot_dir: Option<PathBuf>,
+    /// Listen on addresses other than loopback ones, reachable from other machines.
+    pub public: bool,
+}
+
+/// Path of the snapshot `name` in `dir`, if `name` is a bare file name.
+pub fn snapshot_path(dir: &Path, name: &str) -> anyhow::Result<PathBuf> {
+    let mut components = Path::new(name).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(file)), None) if file == name => Ok(dir.join(file)),
+        _ => bail!("Snapshot name must be a bare file name, got {name:?}"),
+    }
+}
+
+struct Control {
+    exchange: Arc<BatchRequest>,
+    num_objects: usize,
+    spares: Range<usize>,
+    /// Spare slots not holding a body, the next to use last.
+    free: Vec<usize>,
+    snapshot_dir: Option<PathBuf>,
+}
+
+/// Answer control requests on `addr` on a thread of its own, for a simulation of
+/// `num_objects` objects with the spare slots in `spares`. `addr` must be a loopback
+/// address unless `access` allows public ones.
+pub fn serve(
+    addr: &str,
+    exchange: Arc<BatchRequest>,
+    num_objects: usize,
+    spares: Range<usize>,
+    access: ControlAccess,
+) -> anyhow::Result<()> {
+    if !access.public {
+        let addrs: Vec<_> = addr
+            .to_socket_addrs()
+            .with_context(|| format!("Invalid control address {addr}"))?
+            .collect();
+        ensure!(
+            !addrs.is_empty() && addrs.iter().all(|a| a.ip().is_loopback()),
+            "Control address {addr} is not a loopback address. The control API is \
+             unauthenticated, pass --control-public to listen on it anyway"
+        );
+    }
+    if let Some(dir) = &access.snapshot_dir {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create snapshot directory {}", dir.display()))?;
+    }
+    let server = Server::http(addr)
+        .map_err(|e| anyhow::anyhow!("Failed to listen for control requests on {addr}: {e}"))?;
+    let mut control = Control {
+        exchange,
+        num_objects,
+        free: spares.clone().rev().collect(),
+        spares,
+        snapshot_dir: access.snapshot_dir,
+    };
+    std::thread::Builder::new()
+        .name(CONTROL_THREAD_NAME.to_owned())
+        .spawn(move || {
+            for mut request in server.incoming_requests() {
+                let (status, body) = match control.handle(&mut request) {
+                    Ok(Some(body)) => (200, body),
+                    Ok(None) => (404, json!({ "error": "No such endpoint" })),
+                    Err(e) => (400, json!({ "error": format!("{e:#}") })),
+                };
+                let response = Response::from_string(body.to_string())
+                    .with_status_code(status)
+                    .with_header(
+                        Header::from_bytes("Content-Type", "application/json")
+                            .expect("Header is valid"),
+                    );
+                if let Err(e) = request.respond(response) {
+                    println!("Failed to answer control request: {e}");
+                }
+            }
+        })?;
+    Ok(())
+}
+
+impl Control {
+    /// Carry out `request`, returning the body of the reply, or `None` if there is no
+    /// such endpoint.
+    fn handle(&mut self, request: &mut Request) -> anyhow::Result<Option<Value>> {
+        let url = request.url().to_owned();
+        ensure!(
+            *request.method() == Method::Get || is_json(request),
+            "Requests that change the run must have Content-Type application/json"
+        );
+        let reply = match (request.method(), url.as_str()) {
+            (Method::Get, "/status") => {
+                let time = self.exchange.current_time();
+                json!({
+                    "tick": time.ticks,
+                    "seconds": time.seconds(),
+                    "delta": self.exchange.delta(),
+                    "theta": self.exchange.theta(),
+                    "paused": self.exchange.paused(),
+                    "objects": self.num_objects,
+                })
+            }
+            (Method::Post, "/pause" | "/resume") => {
+                let paused = url == "/pause";
+                self.exchange.set_paused(paused);
+                json!({ "paused": paused })
+            }
+            (Method::Post, "/settings") => {
+                let settings: Settings = read_json(request)?;
+                if let Some(delta) = settings.delta {
+                    ensure!(
+                        delta.is_finite() && delta > 0.0,
+                        "Time step must be positive, got {delta}"
+                    );
+                    self.exchange.set_delta(delta);
+                }
+                if let Some(theta) = settings.theta {
+                    ensure!(
+                        theta.is_finite() && theta >= 0.0,
+                        "Theta must not be negative, got {theta}"
+                    );
+                    self.exchange.set_theta(theta);
+                }
+                json!({ "delta": self.exchange.delta(), "theta": self.exchange.theta() })
+            }
+            (Method::Post, "/bodies") => {
+                let body: NewBody = read_json(request)?;
+                ensure!(body.mass >= 0.0, "Mass must not be negative");
+                let index = self
+                    .free
+                    .pop()
+                    .context("No spare slots left for bodies, see --control-spares")?;
+                self.exchange.edit_body(BodyEdit::Add {
+                    index,
+                    state: ObjectInfo {
+                        pos: Point3::from(body.pos) / AU,
+                        vel: Vector3::from(body.vel) / AU,
+                        mass: body.mass,
+                        pinned: false,
+                        test_particle: false,
+                    },
+                    name: body.name,
+                });
+                json!({ "index": index })
+            }
+            (Method::Delete, path) if path.starts_with("/bodies/") => {
+                let index = &path["/bodies/".len()..];
+                let index: usize = index
+                    .parse()
+                    .with_context(|| format!("Invalid index {index}"))?;
+                ensure!(index < self.num_objects, "No object at index {index}");
+                self.exchange.edit_body(BodyEdit::Remove(index));
+                if self.spares.contains(&index) && !self.free.contains(&index) {
+                    self.free.push(index);
+                }
+                json!({ "index": index })
+            }
+            (Method::Post, "/snapshot") => {
+                let snapshot: SnapshotRequest = read_json(request)?;
+                let dir = self
+                    .snapshot_dir
+                    .as_ref()
+                    .context("Snapshots are disabled, see --control-snapshots")?;
+                let path = snapshot_path(dir, &snapshot.name)?;
+                self.exchange.request_snapshot(path.clone());
+                json!({ "path": path })
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(reply))
+    }
+}
+
+/// Whether `request` says its body is JSON.
+fn is_json(request: &Request) -> bool {
+    request.headers().iter().any(|header| {
+        header.field.equiv("Content-Type")
+            && header
+                .value
+                .as_str()
+                .split(';')
+                .next()
+                .is_some_and(|media| media.trim().eq_ignore_ascii_case("application/json"))
+    })
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(request: &mut Request) -> anyhow::Result<T> {
+    serde_json::from_reader(request.as_reader()).context("Invalid request body")
+}
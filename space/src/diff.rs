@@ -0,0 +1,135 @@
+use std::{fs::File, io::BufReader, path::Path};
+
+use anyhow::{Context, bail};
+
+use crate::recording::RecordingReader;
+
+fn open_reader(path: &Path) -> anyhow::Result<RecordingReader<BufReader<File>>> {
+    let file =
+        File::open(path).with_context(|| format!("Failed to open recording {}", path.display()))?;
+    RecordingReader::new(BufReader::new(file))
+        .with_context(|| format!("Failed to read recording {}", path.display()))
+}
+
+fn distance(a: &[f32; 3], b: &[f32; 3]) -> f32 {
+    let d: f64 = a
+        .iter()
+        .zip(b.iter())
+        .map(|(a, b)| (*a as f64 - *b as f64).powi(2))
+        .sum();
+    d.sqrt() as f32
+}
+
+/// Divergence between the two recordings at a single tick.
+#[derive(Debug, Clone)]
+pub struct DiffSample {
+    pub tick: u64,
+    pub delta: f64,
+    pub mean: f32,
+    pub max: f32,
+}
+
+/// Comparison of two recordings of the same scenario, e.g. runs with different
+/// solvers or parameters. Frames are matched by tick, so both runs must use the
+/// same time step for the comparison to be meaningful.
+pub struct RecordingDiff {
+    other: RecordingReader<BufReader<File>>,
+    samples: Vec<DiffSample>,
+    max: f32,
+    buffer: Vec<[f32; 3]>,
+}
+
+impl RecordingDiff {
+    /// Load both recordings, and compute the divergence at every tick they share.
+    pub fn open(path: impl AsRef<Path>, other: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let mut base = open_reader(path.as_ref())?;
+        let mut other = open_reader(other.as_ref())?;
+        if base.num_objects() != other.num_objects() {
+            bail!(
+                "Recordings have different numbers of objects: {} and {}",
+                base.num_objects(),
+                other.num_objects()
+            );
+        }
+
+        let mut samples = Vec::new();
+        let mut base_buf = Vec::new();
+        let mut other_buf = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        while i < base.num_frames() && j < other.num_frames() {
+            let (a, b) = (&base.frames()[i], &other.frames()[j]);
+            if a.tick < b.tick {
+                i += 1;
+                continue;
+            }
+            if b.tick < a.tick {
+                j += 1;
+                continue;
+            }
+            let (tick, delta) = (a.tick, a.delta);
+
+            base.read_frame(i, &mut base_buf)?;
+            other.read_frame(j, &mut other_buf)?;
+            let mut sum = 0.0f64;
+            let mut max = 0.0f32;
+            for (a, b) in base_buf.iter().zip(other_buf.iter()) {
+                let d = distance(a, b);
+                sum += d as f64;
+                max = max.max(d);
+            }
+            samples.push(DiffSample {
+                tick,
+                delta,
+                mean: (sum / base_buf.len().max(1) as f64) as f32,
+                max,
+            });
+            i += 1;
+            j += 1;
+        }
+
+        if samples.is_empty() {
+            bail!("Recordings have no ticks in common");
+        }
+        let max = samples.iter().map(|s| s.max).fold(0.0, f32::max);
+
+        Ok(Self {
+            other,
+            samples,
+            max,
+            buffer: Vec::new(),
+        })
+    }
+
+    pub fn samples(&self) -> &[DiffSample] {
+        &self.samples
+    }
+
+    /// Largest divergence of any object over the entire run.
+    pub fn max_divergence(&self) -> f32 {
+        self.max
+    }
+
+    /// Compute the distance of each object in `positions`, taken from the first
+    /// recording at `tick`, to the same object in the second recording.
+    /// Returns false if the second recording has no frame at `tick`.
+    pub fn divergence(
+        &mut self,
+        tick: u64,
+        positions: &[[f32; 3]],
+        out: &mut Vec<f32>,
+    ) -> anyhow::Result<bool> {
+        let Ok(idx) = self.other.frames().binary_search_by_key(&tick, |f| f.tick) else {
+            return Ok(false);
+        };
+        self.other.read_frame(idx, &mut self.buffer)?;
+
+        out.clear();
+        out.extend(
+            positions
+                .iter()
+                .zip(self.buffer.iter())
+                .map(|(a, b)| distance(a, b)),
+        );
+        Ok(true)
+    }
+}
@@ -0,0 +1,140 @@
+//! Reference positions from JPL HORIZONS.
+//!
+//! A scenario set up from HORIZONS state vectors at some date can be checked against
+//! where HORIZONS has the same bodies at later dates. Vector tables are read as saved
+//! from HORIZONS with CSV output, in any of its length and time units, and must use the
+//! same center as the scenario. The first record of a table is taken to be the start of
+//! the simulation.
+
+use std::{fs, path::Path};
+
+use anyhow::{Context, bail};
+use cgmath::{Point3, Vector3};
+
+use crate::constants::{AU, IAU_AU};
+
+const DAY: f64 = 24.0 * 3600.0;
+
+/// State of a body at a single date in a vector table.
+#[derive(Debug, Clone, Copy)]
+pub struct EphemerisRecord {
+    /// Seconds since the first record.
+    pub seconds: f64,
+    /// Position in AU.
+    pub pos: Point3<f64>,
+    /// Velocity in AU per second.
+    pub vel: Vector3<f64>,
+}
+
+/// Positions of a single body at the dates of a HORIZONS vector table.
+#[derive(Debug, Clone)]
+pub struct Ephemeris {
+    /// Name of the body, as given by HORIZONS.
+    pub target: String,
+    /// Julian date of the first record.
+    pub start_jd: f64,
+    records: Vec<EphemerisRecord>,
+}
+
+impl Ephemeris {
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read ephemeris {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("Invalid ephemeris {}", path.display()))
+    }
+
+    /// Read a vector table, with the header HORIZONS writes before it.
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let mut target = String::new();
+        // HORIZONS gives vectors in km and km/s unless asked otherwise.
+        let mut length = 1e3;
+        let mut time = 1.0;
+        let mut lines = text.lines();
+        for line in lines.by_ref() {
+            if line.starts_with("$$SOE") {
+                break;
+            }
+            if let Some(name) = line.strip_prefix("Target body name:") {
+                // The name is followed by the source of the data, in braces.
+                target = name.split('{').next().unwrap_or_default().trim().to_owned();
+            } else if let Some(units) = line.strip_prefix("Output units") {
+                let units = units.trim_start_matches([' ', ':']).trim();
+                (length, time) = match units.split_whitespace().next() {
+                    Some("KM-S") => (1e3, 1.0),
+                    Some("KM-D") => (1e3, DAY),
+                    Some("AU-D") => (IAU_AU, DAY),
+                    _ => bail!("Unsupported output units {units}"),
+                };
+            }
+        }
+
+        let mut start_jd = None;
+        let mut records = Vec::new();
+        for (i, line) in lines.take_while(|l| !l.starts_with("$$EOE")).enumerate() {
+            let fields: Vec<_> = line.split(',').map(str::trim).collect();
+            if fields.len() < 8 {
+                bail!(
+                    "Record {} has {} fields, expected at least 8",
+                    i + 1,
+                    fields.len()
+                );
+            }
+            let number = |idx: usize| -> anyhow::Result<f64> {
+                fields[idx]
+                    .parse()
+                    .with_context(|| format!("Invalid number {} in record {}", fields[idx], i + 1))
+            };
+            let jd = number(0)?;
+            let start = *start_jd.get_or_insert(jd);
+            let seconds = (jd - start) * DAY;
+            if records
+                .last()
+                .is_some_and(|last: &EphemerisRecord| last.seconds >= seconds)
+            {
+                bail!("Record {} is not after the one before it", i + 1);
+            }
+            // Converted to meters first, since the simulation has its own AU.
+            let pos = Point3::new(number(2)?, number(3)?, number(4)?) * (length / AU);
+            let vel = Vector3::new(number(5)?, number(6)?, number(7)?) * (length / AU / time);
+            records.push(EphemerisRecord { seconds, pos, vel });
+        }
+
+        let Some(start_jd) = start_jd else {
+            bail!("No records between $$SOE and $$EOE");
+        };
+        Ok(Self {
+            target,
+            start_jd,
+            records,
+        })
+    }
+
+    pub fn records(&self) -> &[EphemerisRecord] {
+        &self.records
+    }
+
+    /// Position `seconds` after the first record, or `None` outside the table. Between
+    /// records, the position is the cubic through the positions and velocities at both
+    /// ends.
+    pub fn position_at(&self, seconds: f64) -> Option<Point3<f64>> {
+        let first = self.records.first()?;
+        let last = self.records.last()?;
+        if seconds < first.seconds || seconds > last.seconds {
+            return None;
+        }
+        let next = self.records.partition_point(|r| r.seconds < seconds).max(1);
+        let Some(b) = self.records.get(next) else {
+            return Some(last.pos);
+        };
+        let a = &self.records[next - 1];
+        let h = b.seconds - a.seconds;
+        let t = (seconds - a.seconds) / h;
+        // Cubic Hermite basis, with the weights of both positions summing to one.
+        let (t2, t3) = (t * t, t * t * t);
+        let to_b = 3.0 * t2 - 2.0 * t3;
+        let from_a = t3 - 2.0 * t2 + t;
+        let into_b = t3 - t2;
+        Some(a.pos + (b.pos - a.pos) * to_b + (a.vel * from_a + b.vel * into_b) * h)
+    }
+}
@@ -0,0 +1,120 @@
+//! Detection of bodies leaving the system.
+//!
+//! A body thrown out of the system keeps going forever. Other than wasting time on a body
+//! nobody is looking at, it stretches the bounding box of the tree solvers until everything
+//! else is crammed into a handful of cells. Escaped bodies are frozen in place as pinned
+//! test particles instead, which the solvers leave out. They are not removed, since every
+//! other part of a run refers to objects by index.
+
+use std::fmt::Display;
+
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector3};
+
+use crate::{
+    Object,
+    constants::{AU, G},
+    sim::{ObjectInfo, SimTime},
+};
+
+/// A body found to have escaped, and frozen.
+#[derive(Debug, Clone)]
+pub struct Escape {
+    pub index: usize,
+    pub name: String,
+    pub time: SimTime,
+    /// Distance from the barycenter when it was caught, in AU.
+    pub distance: f64,
+    /// Speed relative to the barycenter when it was caught, in AU/s.
+    pub speed: f64,
+    /// Escape velocity at that distance, in AU/s.
+    pub escape_speed: f64,
+}
+
+impl Display for Escape {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} escaped after {}, {:.3} AU from the barycenter at {:.3} km/s (escape velocity {:.3} km/s)",
+            self.name,
+            self.time.elapsed(),
+            self.distance,
+            self.speed * AU / 1e3,
+            self.escape_speed * AU / 1e3,
+        )
+    }
+}
+
+/// Criteria for when a body has left the system.
+#[derive(Debug, Clone)]
+pub struct EscapeCheck {
+    names: Vec<String>,
+    /// Bodies further than this from the barycenter have escaped, in AU.
+    pub max_distance: f64,
+    /// Bodies further than this from the barycenter that are also moving faster than the
+    /// escape velocity have escaped, in AU. Close to the center, an unbound body may still
+    /// be in the middle of an encounter, so this should be well outside the system.
+    pub unbound_distance: Option<f64>,
+}
+
+impl EscapeCheck {
+    pub fn new(objects: &[Object], max_distance: f64, unbound_distance: Option<f64>) -> Self {
+        Self {
+            names: objects.iter().map(|o| o.name.clone()).collect(),
+            max_distance,
+            unbound_distance,
+        }
+    }
+
+    /// Freeze every body that has escaped, returning one event for each.
+    pub fn apply(&self, time: SimTime, objects: &mut [ObjectInfo]) -> Vec<Escape> {
+        let (center, center_vel, mass) = barycenter(objects);
+        if mass <= 0.0 {
+            return Vec::new();
+        }
+
+        let mut escapes = Vec::new();
+        for (index, obj) in objects.iter_mut().enumerate() {
+            if obj.is_frozen() {
+                continue;
+            }
+            let distance = (obj.pos - center).magnitude();
+            let speed = (obj.vel - center_vel).magnitude();
+            let escape_speed = (2.0 * G * mass / distance).sqrt();
+            let unbound = self
+                .unbound_distance
+                .is_some_and(|d| distance > d && speed > escape_speed);
+            if distance > self.max_distance || unbound {
+                obj.pinned = true;
+                obj.test_particle = true;
+                escapes.push(Escape {
+                    index,
+                    name: self.names.get(index).cloned().unwrap_or_default(),
+                    time,
+                    distance,
+                    speed,
+                    escape_speed,
+                });
+            }
+        }
+        escapes
+    }
+}
+
+/// Position and velocity of the center of mass of everything still attracting others,
+/// along with their total mass.
+fn barycenter(objects: &[ObjectInfo]) -> (Point3<f64>, Vector3<f64>, f64) {
+    let mut pos = Vector3::new(0.0, 0.0, 0.0);
+    let mut vel = Vector3::new(0.0, 0.0, 0.0);
+    let mut mass = 0.0;
+    for obj in objects {
+        let m = obj.gravitating_mass();
+        pos += obj.pos.to_vec() * m;
+        vel += obj.vel * m;
+        mass += m;
+    }
+    if mass > 0.0 {
+        (Point3::from_vec(pos / mass), vel / mass, mass)
+    } else {
+        (Point3::origin(), vel, mass)
+    }
+}
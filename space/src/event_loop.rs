@@ -1,6 +1,9 @@
-use std::sync::{
-    Arc,
-    atomic::{AtomicBool, Ordering},
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
 };
 
 use pollster::FutureExt;
@@ -13,13 +16,31 @@ use winit::{
 };
 
 use crate::{
+    accretion::MassEvolution,
     batch_request::BatchRequest,
     camera::Camera,
-    constants::{BARNES_HUT_COEFF, BARNES_HUT_CUTOFF, CHECK_INTERVAL, DELTA},
+    checkpoint::Checkpoints,
+    constants::{
+        BARNES_HUT_COEFF, CHECK_INTERVAL, CLUSTER_STATS_INTERVAL, DEFAULT_SAMPLE_COUNT,
+        DENSITY_NEIGHBORS, SAMPLE_WAIT_MAX_DOUBLINGS, SAMPLE_WAIT_YIELDS,
+    },
+    control::BodyEdit,
+    escape::EscapeCheck,
+    inject::InjectionSchedule,
     objects::Objects,
+    orbit::RelativeState,
+    particle_snapshots::{self, ParticleSnapshots},
+    position_stream::PositionStream,
     render::Renderer,
-    sim::{ObjectBuffer, ObjectInfo, SimulationImpl, compute_elapsed_time},
+    scenario_file,
+    sim::{
+        AdaptiveSim, DormandPrince, FofGroups, ForceProvider, ForcesSim, FrameTime, Hermite,
+        KeplerPairs, ObjectBuffer, ObjectInfo, Perturbations, SimTime, SimulationImpl,
+        local_densities,
+    },
+    stats::{ClusterStats, ClusterStatsLog},
     surface::{SurfaceState, WindowState, get_surface, get_window},
+    trajectory::TrajectoryLog,
 };
 
 #[derive(Debug, Default, Clone)]
@@ -67,6 +88,7 @@ pub struct KeyboardState {
     pub h: KeyTrigger,
     pub space: KeyTrigger,
     pub j: KeyTrigger,
+    pub r: KeyTrigger,
 
     pub o: bool,
     pub l: bool,
@@ -91,7 +113,7 @@ pub struct SpaceApp {
     size: LogicalSize<f32>,
     exchange: Arc<BatchRequest>,
     objects: Objects,
-    tick: u32,
+    frame: FrameTime,
     keyboard_state: KeyboardState,
 }
 
@@ -102,7 +124,7 @@ impl SpaceApp {
             size: LogicalSize::new(init_w, init_h),
             exchange,
             objects,
-            tick: 0,
+            frame: FrameTime::default(),
             keyboard_state: KeyboardState::default(),
         }
     }
@@ -128,6 +150,7 @@ impl SpaceAppInner {
         let renderer = Renderer::new(
             &surface.device,
             surface.texture_format(),
+            DEFAULT_SAMPLE_COUNT,
             window.window.inner_size(),
             &camera,
             objects,
@@ -202,6 +225,7 @@ impl ApplicationHandler<()> for SpaceApp {
                         "g" => self.keyboard_state.g.event(is_pressed),
                         "h" => self.keyboard_state.h.event(is_pressed),
                         "j" => self.keyboard_state.j.event(is_pressed),
+                        "r" => self.keyboard_state.r.event(is_pressed),
                         _ => (),
                     },
                     winit::keyboard::Key::Unidentified(_) => (),
@@ -223,7 +247,7 @@ impl ApplicationHandler<()> for SpaceApp {
                 //}
                 //elwt.set_control_flow(ControlFlow::WaitUntil(*next_tick_ref));
 
-                self.tick += 1;
+                self.frame.advance();
 
                 self.exchange.sample(&mut self.objects);
 
@@ -236,10 +260,13 @@ impl ApplicationHandler<()> for SpaceApp {
                 if self.keyboard_state.space.get_trigger() {
                     self.objects.clear();
                 }
+                if self.keyboard_state.r.get_trigger() {
+                    self.exchange.set_reversed(!self.exchange.reversed());
+                }
 
                 if let Some(texture) = inner.surface.get_current_texture() {
                     inner.renderer.redraw(
-                        self.tick,
+                        self.frame,
                         &mut inner.camera,
                         &mut self.objects,
                         &inner.surface.queue,
@@ -286,12 +313,11 @@ impl ApplicationHandler<()> for SpaceApp {
                 // let _last_draw = *next_tick_ref;
                 // *next_tick_ref = Instant::now();
 
-                if self.tick % 60 == 0 {
-                    let sim_ticks = self.exchange.current_ticks();
-                    let actual_time = compute_elapsed_time(sim_ticks as f64, DELTA);
+                if self.frame.every(60) {
+                    let time = self.exchange.current_time();
 
-                    println!("Elapsed time: {actual_time}");
-                    println!("Elapsed ticks: {sim_ticks}");
+                    println!("Elapsed time: {}", time.elapsed());
+                    println!("Elapsed ticks: {}", time.ticks);
                 }
                 // println!("Ticks since last: {:?}", *next_tick_ref - last_draw);
 
@@ -302,40 +328,340 @@ impl ApplicationHandler<()> for SpaceApp {
     }
 }
 
+/// Waits that start out yielding and back off to growing sleeps, so that a sample that is
+/// requested soon is picked up at once, without spinning while the renderer is stalled.
+#[derive(Default)]
+struct Backoff {
+    waits: u32,
+}
+
+impl Backoff {
+    fn wait(&mut self) {
+        if self.waits < SAMPLE_WAIT_YIELDS {
+            std::thread::yield_now();
+        } else {
+            let doublings = (self.waits - SAMPLE_WAIT_YIELDS).min(SAMPLE_WAIT_MAX_DOUBLINGS);
+            std::thread::sleep(Duration::from_micros(50 << doublings));
+        }
+        self.waits += 1;
+    }
+}
+
+/// Everything a simulation thread writes or changes as it goes, besides the samples in
+/// its `BatchRequest`. The default leaves all of it out.
+#[derive(Default)]
+pub struct SimOutputs {
+    /// Trajectories of watched objects.
+    pub watch: Option<TrajectoryLog>,
+    /// Objects added during the run.
+    pub injections: InjectionSchedule,
+    /// Freezes objects that leave the system.
+    pub escape: Option<EscapeCheck>,
+    /// Steady mass rates and mergers.
+    pub mass: MassEvolution,
+    /// Log of the cluster statistics.
+    pub stats_log: Option<ClusterStatsLog>,
+    /// Periodic snapshots of every object.
+    pub snapshots: Option<ParticleSnapshots>,
+    /// Checkpoints to resume the run from.
+    pub checkpoints: Option<Checkpoints>,
+    /// Positions sent to WebSocket clients with every sample.
+    pub stream: Option<PositionStream>,
+}
+
 pub fn run_sim_loop<R: SimulationImpl + Send + 'static>(
     mut sim: ObjectBuffer<R>,
     exchange: Arc<BatchRequest>,
     token: Arc<AtomicBool>,
+    outputs: SimOutputs,
 ) {
-    let mut i = 0u64;
-
-    let mut delta = exchange.delta();
+    let SimOutputs {
+        mut watch,
+        mut injections,
+        escape,
+        mut mass,
+        mut stats_log,
+        mut snapshots,
+        mut checkpoints,
+        mut stream,
+    } = outputs;
+    let mut time = checkpoints
+        .as_ref()
+        .and_then(Checkpoints::resumed)
+        .unwrap_or_else(|| SimTime::new(0, exchange.delta()));
+    // Steps since the last sample, in either direction.
+    let mut unsampled = 0u64;
+    let mut stats_tick = time.ticks;
+    publish_stats(&sim.objects, time, &exchange, &mut stats_log);
+    take_snapshot(&sim.objects, time, &mut snapshots);
 
     loop {
-        for _ in 0..CHECK_INTERVAL {
-            sim.exec_iter(delta);
+        let substeps = exchange.substeps();
+        let reversed = exchange.reversed();
+        let mut steps = substeps.unwrap_or(CHECK_INTERVAL);
+        if exchange.paused() {
+            steps = 0;
+        } else if reversed {
+            // Rewinding stops where the run started.
+            steps = steps.min(time.ticks);
+        } else if let Some(end) = exchange.end_tick() {
+            steps = steps.min(end.saturating_sub(time.ticks));
+        }
+        if steps == 0 {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        unsampled += steps;
+        for _ in 0..steps {
+            if reversed {
+                // Injected objects stay, frozen objects stay frozen and merged bodies stay
+                // merged when rewinding.
+                sim.set_time(time.previous());
+                sim.exec_iter(-time.delta);
+                mass.step(&mut sim.objects, -time.delta);
+                time.rewind();
+            } else {
+                if !injections.is_empty() {
+                    injections.apply(time, &mut sim.objects);
+                }
+                sim.set_time(time);
+                sim.exec_iter(time.delta);
+                time.advance();
+                if !mass.is_empty() {
+                    mass.step(&mut sim.objects, time.delta);
+                    for merger in mass.merge(time, &mut sim.objects) {
+                        println!("{merger}");
+                    }
+                }
+            }
+            if let Some(log) = &mut watch
+                && let Err(e) = log.log(time, &sim.objects)
+            {
+                println!("Stopped logging trajectories: {e}");
+                watch = None;
+            }
+            // Going back over ticks already written would only write them again.
+            if !reversed {
+                take_snapshot(&sim.objects, time, &mut snapshots);
+            }
+        }
+        if let Some(escape) = &escape {
+            for event in escape.apply(time, &mut sim.objects) {
+                println!("{event}");
+            }
+        }
+        if let Some(writer) = &mut checkpoints
+            && writer.is_due()
+        {
+            match writer.save(time, &sim.objects, &injections) {
+                Ok(path) => println!("Wrote checkpoint {}", path.display()),
+                Err(e) => {
+                    println!("Stopped writing checkpoints: {e:#}");
+                    checkpoints = None;
+                }
+            }
+        }
+        if time.ticks.abs_diff(stats_tick) >= CLUSTER_STATS_INTERVAL {
+            stats_tick = time.ticks;
+            publish_stats(&sim.objects, time, &exchange, &mut stats_log);
         }
-        i += CHECK_INTERVAL;
-        if exchange.should_store() {
-            exchange.store(&sim, i);
-            delta = exchange.delta();
+        if !reversed && exchange.end_tick().is_some_and(|end| time.ticks >= end) {
+            // Leave the final state where it can be seen, and in the statistics.
+            exchange.store(&sim, time);
+            if stats_tick != time.ticks {
+                publish_stats(&sim.objects, time, &exchange, &mut stats_log);
+            }
+            println!("Finished the run after {}", time.elapsed());
+            break;
+        }
+        let mut store = exchange.should_store();
+        // With a fixed number of steps per sample, hold off until the next sample is
+        // requested, so that samples are always the same number of steps apart. Otherwise
+        // only hold off once the renderer has fallen too far behind, like when the window
+        // is hidden, so that the simulation does not run far ahead of what is shown.
+        let behind = exchange
+            .max_unsampled_ticks()
+            .is_some_and(|max| unsampled >= max);
+        if !store && behind && substeps.is_none() {
+            println!(
+                "Paused after {unsampled} ticks without a sample, until the next is requested"
+            );
+        }
+        let mut backoff = Backoff::default();
+        while (substeps.is_some() || behind) && !store && !token.load(Ordering::Relaxed) {
+            backoff.wait();
+            store = exchange.should_store();
+        }
+        if store {
+            unsampled = 0;
+            exchange.store(&sim, time);
+            if let Some(stream) = &mut stream {
+                stream.publish(time, &sim.objects);
+            }
+            // Only the coming ticks use a new time step, the seconds so far are kept.
+            time.delta = exchange.delta();
+            sim.set_theta(exchange.theta());
+            sim.set_solver(exchange.solver());
+            if let Some(stats) = sim.take_step_stats() {
+                exchange.set_step_stats(stats);
+            }
+            exchange.set_sim_memory(sim.memory_usage() as u64);
+            sim.set_cpu_budget(exchange.cpu_budget());
+            sim.set_fixed_threads(exchange.fixed_threads());
+            exchange.set_sim_threads(sim.num_threads());
+            if let Some(path) = exchange.take_tree_dump() {
+                match sim.dump_tree(&path) {
+                    Ok(true) => println!("Wrote octree to {}", path.display()),
+                    Ok(false) => println!("The current solver does not build an octree"),
+                    Err(e) => println!("Failed to write octree: {e:#}"),
+                }
+            }
+            for edit in exchange.take_body_edits() {
+                edit.apply(&mut sim.objects);
+                if let (
+                    Some(stream),
+                    BodyEdit::Add {
+                        index,
+                        name: Some(name),
+                        ..
+                    },
+                ) = (&mut stream, &edit)
+                {
+                    stream.rename(*index, name);
+                }
+            }
+            if let Some(path) = exchange.take_snapshot_request() {
+                match particle_snapshots::write_single(&path, time, &sim.objects) {
+                    Ok(()) => println!("Wrote snapshot to {}", path.display()),
+                    Err(e) => println!("{e:#}"),
+                }
+            }
+            if let Some((path, mut objects)) = exchange.take_scenario_export() {
+                // Objects injected during the run have no description, and are left out.
+                for (obj, info) in objects.iter_mut().zip(&sim.objects) {
+                    obj.dat = info.clone();
+                }
+                match scenario_file::save_objects(&path, &objects) {
+                    Ok(()) => println!("Wrote scenario to {}", path.display()),
+                    Err(e) => println!("Failed to write scenario: {e:#}"),
+                }
+            }
+            if let Some(index) = exchange.take_force_check_request() {
+                match sim.check_force(index, exchange.theta()) {
+                    Some(check) => exchange.set_force_check(check),
+                    None => {
+                        println!("Object {index} is not simulated, so it has no force to check")
+                    }
+                }
+            }
+            if let Some(linking_factor) = exchange.take_groups_request() {
+                exchange.set_groups(FofGroups::find(&sim.objects, linking_factor));
+            }
+            if let Some(index) = exchange.orbit_focus() {
+                exchange.set_orbit(RelativeState::find(&sim.objects, index));
+            }
+            if exchange.wants_densities() {
+                let densities = local_densities(&sim.objects, DENSITY_NEIGHBORS);
+                exchange.set_densities(densities.into_iter().map(|d| d as f32).collect());
+            }
         } else if token.load(Ordering::Relaxed) {
             break;
         }
     }
+    if let Some(log) = &mut watch
+        && let Err(e) = log.flush()
+    {
+        println!("Failed to write trajectories: {e}");
+    }
+    if let Some(log) = &mut stats_log
+        && let Err(e) = log.flush()
+    {
+        println!("Failed to write cluster statistics: {e}");
+    }
+    if let Some(snapshots) = &mut snapshots
+        && let Err(e) = snapshots.flush()
+    {
+        println!("Failed to write snapshots: {e:#}");
+    }
     println!("Event loop terminated");
 }
 
+/// Write a snapshot of `objects` if one is due. Stops taking snapshots if writing fails.
+fn take_snapshot(objects: &[ObjectInfo], time: SimTime, snapshots: &mut Option<ParticleSnapshots>) {
+    if let Some(writer) = snapshots
+        && let Err(e) = writer.capture(time, objects)
+    {
+        println!("Stopped writing snapshots: {e:#}");
+        *snapshots = None;
+    }
+}
+
+/// Compute the statistics of `objects` for display, and write them to `log` if there is
+/// one. Stops logging if writing fails.
+fn publish_stats(
+    objects: &[ObjectInfo],
+    time: SimTime,
+    exchange: &BatchRequest,
+    log: &mut Option<ClusterStatsLog>,
+) {
+    let Some(stats) = ClusterStats::compute(objects, time) else {
+        return;
+    };
+    exchange.set_cluster_stats(stats);
+    if let Some(writer) = log
+        && let Err(e) = writer.log(&stats)
+    {
+        println!("Stopped logging cluster statistics: {e}");
+        *log = None;
+    }
+}
+
+/// Build the simulation for `objects`, with a gravity solver picked by the number of
+/// massive objects as the run goes, and any perturbations on top.
+pub(crate) fn build_sim(
+    objects: Vec<ObjectInfo>,
+    mut perturbations: Perturbations,
+) -> ObjectBuffer<ForcesSim> {
+    let periodic = perturbations.periodic;
+    let kepler = match perturbations.kepler.take() {
+        Some(kepler) => Some(kepler),
+        None if perturbations.no_kepler => None,
+        None => Some(KeplerPairs::two_body()),
+    };
+    let rk45 = perturbations.rk45.take();
+    let hermite = perturbations.hermite.take();
+    let gravity = AdaptiveSim::new(&objects, BARNES_HUT_COEFF, periodic);
+    let gravity: Box<dyn ForceProvider> = if perturbations.incremental_tree {
+        Box::new(gravity.with_incremental_tree())
+    } else {
+        Box::new(gravity)
+    };
+    let mut providers = vec![gravity];
+    providers.extend(perturbations.into_providers());
+    let sim = ObjectBuffer::new(objects, ForcesSim::new(providers));
+    let sim = match periodic {
+        Some(bounds) => sim.with_periodic_box(bounds),
+        None => sim,
+    };
+    let sim = match kepler {
+        Some(kepler) => sim.with_kepler_pairs(kepler),
+        None => sim,
+    };
+    let sim = match rk45 {
+        Some(tolerance) => sim.with_rk45(DormandPrince::new(tolerance)),
+        None => sim,
+    };
+    match hermite {
+        Some(accuracy) => sim.with_hermite(Hermite::new(accuracy)),
+        None => sim,
+    }
+}
+
 pub fn run_sim_loop_erased(
     objects: Vec<ObjectInfo>,
     exchange: Arc<BatchRequest>,
     token: Arc<AtomicBool>,
+    perturbations: Perturbations,
+    outputs: SimOutputs,
 ) {
-    if objects.len() > BARNES_HUT_CUTOFF {
-        let sim = ObjectBuffer::new(objects, crate::sim::BarnesHutSim::new(BARNES_HUT_COEFF));
-        run_sim_loop(sim, exchange, token);
-    } else {
-        let sim = ObjectBuffer::new(objects, crate::sim::BruteForceSim);
-        run_sim_loop(sim, exchange, token);
-    }
+    run_sim_loop(build_sim(objects, perturbations), exchange, token, outputs);
 }
@@ -0,0 +1,102 @@
+//! Stars passing through a system partway into a run.
+//!
+//! A passing star is injected at the chosen time, see `inject`, on a hyperbolic approach
+//! relative to the current state of the body it passes.
+
+use cgmath::{Matrix3, Point3, Rad, SquareMatrix, Vector3};
+
+use crate::{Object, constants::G, inject::Injection, sim::ObjectInfo};
+
+/// A star, or other body, sent past `center`.
+#[derive(Debug, Clone)]
+pub struct Flyby {
+    pub name: String,
+    /// Mass, in earth masses.
+    pub mass: f64,
+    /// Radius, in AU.
+    pub radius: f32,
+    pub color: Vector3<f32>,
+    /// Index of the body the star passes.
+    pub center: usize,
+    /// Closest approach if the star was not deflected, in AU.
+    pub impact_parameter: f64,
+    /// Speed relative to the center long before the encounter, in AU/s.
+    pub speed: f64,
+    /// Distance from the center the star is injected at, in AU.
+    pub start_distance: f64,
+    /// Inclination of the plane of the encounter against the xy plane, in radians.
+    pub inclination: f64,
+    /// Direction of closest approach in the xy plane, in radians.
+    pub longitude: f64,
+    /// Simulated time of injection, in seconds.
+    pub at: f64,
+}
+
+impl Flyby {
+    /// Position and velocity relative to the center when injected, on the incoming
+    /// branch of the hyperbola through the encounter.
+    fn relative_state(&self, center_mass: f64) -> anyhow::Result<(Vector3<f64>, Vector3<f64>)> {
+        anyhow::ensure!(
+            self.speed > 0.0 && self.impact_parameter > 0.0,
+            "Flyby {} needs a positive speed and impact parameter",
+            self.name
+        );
+        let mu = G * (center_mass + self.mass);
+        let v2 = self.speed * self.speed;
+        let e = (1.0 + (self.impact_parameter * v2 / mu).powi(2)).sqrt();
+        let periapsis = mu / v2 * (e - 1.0);
+        anyhow::ensure!(
+            self.start_distance > periapsis,
+            "Flyby {} starts at {} AU, inside its closest approach of {periapsis} AU",
+            self.name,
+            self.start_distance
+        );
+
+        let h = self.impact_parameter * self.speed;
+        let p = h * h / mu;
+        // Negative true anomaly, since the star has yet to reach periapsis.
+        let nu = -((p / self.start_distance - 1.0) / e)
+            .clamp(-1.0, 1.0)
+            .acos();
+        let pos = Vector3::new(nu.cos(), nu.sin(), 0.0) * self.start_distance;
+        let vel = Vector3::new(-nu.sin(), e + nu.cos(), 0.0) * (mu / h);
+
+        let rot = Matrix3::from_angle_z(Rad(self.longitude))
+            * Matrix3::from_angle_x(Rad(self.inclination));
+        Ok((rot * pos, rot * vel))
+    }
+
+    /// The star as an injection into `objects`.
+    pub fn injection(self, objects: &[Object]) -> anyhow::Result<Injection> {
+        let center = objects
+            .get(self.center)
+            .ok_or_else(|| anyhow::anyhow!("Flyby {} has no center", self.name))?;
+        let (pos, vel) = self.relative_state(center.dat.mass)?;
+        let star = Object {
+            name: self.name.clone(),
+            dat: ObjectInfo {
+                pos: Point3::new(0.0, 0.0, 0.0),
+                vel: Vector3::new(0.0, 0.0, 0.0),
+                mass: self.mass,
+                pinned: false,
+                test_particle: false,
+            },
+            color: self.color,
+            radius: self.radius,
+            oblateness: None,
+            radiation: None,
+            maneuvers: Vec::new(),
+            mass_rate: 0.0,
+            texture: None,
+        };
+        Ok(Injection {
+            name: self.name,
+            objects: vec![star],
+            center: Some(self.center),
+            rotation: Matrix3::identity(),
+            offset: pos,
+            velocity: vel,
+            at: self.at,
+        })
+    }
+}
@@ -0,0 +1,112 @@
+use crate::{objects::Objects, sim::SimTime};
+
+/// A single sample of the simulation, as seen by the renderer.
+pub struct HistoryFrame {
+    pub time: SimTime,
+    pub positions: Vec<[f32; 3]>,
+}
+
+/// Bounded in-memory record of sampled simulation states.
+///
+/// Once the history is full, every other frame is dropped and the recording interval
+/// is doubled, so the recorded frames always span the entire run, at a steadily
+/// coarser resolution.
+pub struct History {
+    frames: Vec<HistoryFrame>,
+    max_frames: usize,
+    interval: u64,
+    next_tick: u64,
+}
+
+impl History {
+    pub fn new(max_frames: usize) -> Self {
+        Self {
+            frames: Vec::new(),
+            max_frames: max_frames.max(2),
+            interval: 1,
+            next_tick: 0,
+        }
+    }
+
+    /// Record a sample, if enough ticks have passed since the last recorded frame.
+    pub fn record(&mut self, time: SimTime, positions: &[[f32; 3]]) {
+        if time.ticks < self.next_tick {
+            return;
+        }
+        if self.frames.len() >= self.max_frames {
+            self.decimate();
+        }
+
+        self.frames.push(HistoryFrame {
+            time,
+            positions: positions.to_vec(),
+        });
+        self.next_tick = time.ticks + self.interval;
+    }
+
+    fn decimate(&mut self) {
+        let mut idx = 0;
+        self.frames.retain(|_| {
+            idx += 1;
+            idx % 2 == 1
+        });
+        self.interval *= 2;
+    }
+
+    /// Keep at most `max_frames` frames, at least 2, dropping every other frame until
+    /// the recorded frames fit.
+    pub fn set_max_frames(&mut self, max_frames: usize) {
+        self.max_frames = max_frames.max(2);
+        while self.frames.len() > self.max_frames {
+            self.decimate();
+        }
+        self.frames.shrink_to(self.max_frames);
+    }
+
+    pub fn max_frames(&self) -> usize {
+        self.max_frames
+    }
+
+    /// Ticks between recorded frames.
+    pub fn interval(&self) -> u64 {
+        self.interval
+    }
+
+    /// Bytes held by the recorded frames.
+    pub fn memory_usage(&self) -> usize {
+        self.frames.capacity() * std::mem::size_of::<HistoryFrame>()
+            + self
+                .frames
+                .iter()
+                .map(|f| f.positions.capacity() * std::mem::size_of::<[f32; 3]>())
+                .sum::<usize>()
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn get(&self, idx: usize) -> Option<&HistoryFrame> {
+        self.frames.get(idx)
+    }
+
+    /// Index of the last frame recorded at or before `tick`.
+    pub fn index_at(&self, tick: u64) -> usize {
+        self.frames
+            .partition_point(|f| f.time.ticks <= tick)
+            .saturating_sub(1)
+    }
+
+    /// Replace the trails in `objects` with the frames leading up to and including `idx`.
+    pub fn restore(&self, idx: usize, objects: &mut Objects) {
+        objects.clear();
+        let start = (idx + 1).saturating_sub(objects.trail_length());
+        for frame in &self.frames[start..=idx] {
+            objects.push_items(&frame.positions, frame.time);
+        }
+    }
+}
@@ -0,0 +1,347 @@
+//! Initial conditions from snapshots of other N-body codes.
+//!
+//! Reads Gadget-2 binary snapshots, in both the plain and the labelled block format,
+//! and Tipsy binary files, in either byte order. Only the particles themselves are kept:
+//! positions, velocities and masses. Gas properties and the like are skipped.
+//!
+//! Neither format says what its units are, so they are given as `IcUnits`. Gadget
+//! velocities are in km/s, the default of the code. Tipsy files use units where `G = 1`,
+//! so their velocity unit follows from the length and mass units. Cosmological Gadget
+//! snapshots store comoving coordinates, and are read as if they were physical.
+
+use std::{fs, path::Path};
+
+use anyhow::{Context, bail};
+use cgmath::{Point3, Vector3};
+
+use crate::{
+    Object,
+    constants::{AU, G, PARSEC, SOLAR_MASS, SOLAR_RADIUS},
+    sim::ObjectInfo,
+};
+
+/// Names and colors of the six Gadget particle types.
+const GADGET_TYPES: [(&str, [f32; 3]); 6] = [
+    ("gas", [0.4, 0.6, 1.0]),
+    ("halo", [0.5, 0.4, 0.7]),
+    ("disk", [1.0, 0.95, 0.8]),
+    ("bulge", [1.0, 0.8, 0.5]),
+    ("star", [1.0, 1.0, 0.9]),
+    ("boundary", [0.6, 0.6, 0.6]),
+];
+
+/// Size of the Gadget header block.
+const GADGET_HEADER_BYTES: usize = 256;
+
+/// Floats per gas, dark matter and star particle in a Tipsy file, and the color of each.
+const TIPSY_TYPES: [(&str, usize, [f32; 3]); 3] = [
+    ("gas", 12, [0.4, 0.6, 1.0]),
+    ("dark", 9, [0.5, 0.4, 0.7]),
+    ("star", 11, [1.0, 1.0, 0.9]),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IcFormat {
+    Gadget,
+    Tipsy,
+}
+
+/// Units of the lengths and masses in a file.
+#[derive(Debug, Clone, Copy)]
+pub struct IcUnits {
+    /// In AU.
+    pub length: f64,
+    /// In earth masses.
+    pub mass: f64,
+}
+
+impl Default for IcUnits {
+    /// Kiloparsecs and 10^10 solar masses, the usual units of galaxy simulations.
+    fn default() -> Self {
+        Self {
+            length: 1e3 * PARSEC,
+            mass: 1e10 * SOLAR_MASS,
+        }
+    }
+}
+
+/// Read the particles of the snapshot at `path` as objects.
+pub fn open(
+    path: impl AsRef<Path>,
+    format: IcFormat,
+    units: IcUnits,
+) -> anyhow::Result<Vec<Object>> {
+    let path = path.as_ref();
+    let data = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    match format {
+        IcFormat::Gadget => parse_gadget(&data, units),
+        IcFormat::Tipsy => parse_tipsy(&data, units),
+    }
+    .with_context(|| format!("Invalid snapshot {}", path.display()))
+}
+
+/// Reads numbers of either byte order from the start of a slice.
+struct Bytes<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl<'a> Bytes<'a> {
+    fn take(&mut self, len: usize) -> anyhow::Result<&'a [u8]> {
+        if self.data.len() < len {
+            bail!("File ends {} bytes early", len - self.data.len());
+        }
+        let (head, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(head)
+    }
+
+    fn array<const N: usize>(&mut self) -> anyhow::Result<[u8; N]> {
+        let mut bytes: [u8; N] = self.take(N)?.try_into()?;
+        if self.big_endian != cfg!(target_endian = "big") {
+            bytes.reverse();
+        }
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> anyhow::Result<u32> {
+        Ok(u32::from_ne_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> anyhow::Result<u64> {
+        Ok(u64::from_ne_bytes(self.array()?))
+    }
+
+    fn f32(&mut self) -> anyhow::Result<f32> {
+        Ok(f32::from_ne_bytes(self.array()?))
+    }
+
+    fn f64(&mut self) -> anyhow::Result<f64> {
+        Ok(f64::from_ne_bytes(self.array()?))
+    }
+
+    /// Read a Fortran record, framed by its length on both sides.
+    fn record(&mut self) -> anyhow::Result<Bytes<'a>> {
+        let len = self.u32()?;
+        let data = self.take(len as usize)?;
+        if self.u32()? != len {
+            bail!("Block of {len} bytes is not closed by its length");
+        }
+        Ok(Bytes {
+            data,
+            big_endian: self.big_endian,
+        })
+    }
+}
+
+/// Read a Gadget-2 snapshot. Snapshots split over several files are not supported.
+pub fn parse_gadget(data: &[u8], units: IcUnits) -> anyhow::Result<Vec<Object>> {
+    let first = data.get(..4).context("File is empty")?;
+    let (big_endian, labelled) = match (
+        u32::from_le_bytes(first.try_into()?),
+        u32::from_be_bytes(first.try_into()?),
+    ) {
+        (256, _) => (false, false),
+        (8, _) => (false, true),
+        (_, 256) => (true, false),
+        (_, 8) => (true, true),
+        _ => bail!("Does not start with a Gadget header"),
+    };
+    let mut file = Bytes { data, big_endian };
+    let mut block = |name: &str| gadget_block(&mut file, labelled, name);
+
+    let mut header = block("HEAD")?;
+    if header.data.len() != GADGET_HEADER_BYTES {
+        bail!(
+            "Header is {} bytes, expected {GADGET_HEADER_BYTES}",
+            header.data.len()
+        );
+    }
+    let mut counts = [0usize; 6];
+    for count in &mut counts {
+        *count = header.u32()? as usize;
+    }
+    let mut masses = [0.0; 6];
+    for mass in &mut masses {
+        *mass = header.f64()?;
+    }
+    // Skip the time, redshift, star formation and feedback flags, total particle counts
+    // and cooling flag to get to the number of files.
+    header.take(8 + 8 + 4 + 4 + 24 + 4)?;
+    let num_files = header.u32()?;
+    if num_files > 1 {
+        bail!("Snapshot is split over {num_files} files, only single files are supported");
+    }
+    let total: usize = counts.iter().sum();
+
+    let vectors = |mut block: Bytes, name: &str| -> anyhow::Result<Vec<[f64; 3]>> {
+        if block.data.len() != total * 12 {
+            bail!(
+                "{name} block is {} bytes, expected {} for {total} particles",
+                block.data.len(),
+                total * 12
+            );
+        }
+        (0..total)
+            .map(|_| {
+                Ok([
+                    block.f32()? as f64,
+                    block.f32()? as f64,
+                    block.f32()? as f64,
+                ])
+            })
+            .collect()
+    };
+    let positions = vectors(block("POS ")?, "Position")?;
+    let velocities = vectors(block("VEL ")?, "Velocity")?;
+    let mut ids = block("ID  ")?;
+    let wide_ids = match ids.data.len() {
+        len if len == total * 4 => false,
+        len if len == total * 8 => true,
+        len => bail!("ID block is {len} bytes, expected 4 or 8 per particle"),
+    };
+    // Types without a mass in the header list a mass for each particle instead.
+    let per_particle = (0..6).any(|t| counts[t] > 0 && masses[t] == 0.0);
+    let mut mass_block = if per_particle {
+        Some(block("MASS")?)
+    } else {
+        None
+    };
+
+    let velocity = 1e3 / AU;
+    let mut objects = Vec::with_capacity(total);
+    let mut positions = positions.into_iter().zip(velocities);
+    for (t, &count) in counts.iter().enumerate() {
+        let (name, color) = GADGET_TYPES[t];
+        for _ in 0..count {
+            let id = if wide_ids {
+                ids.u64()?
+            } else {
+                ids.u32()? as u64
+            };
+            let mass = match &mut mass_block {
+                Some(block) if masses[t] == 0.0 => block.f32()? as f64,
+                _ => masses[t],
+            };
+            let (pos, vel) = positions.next().context("Ran out of particles")?;
+            objects.push(particle(
+                format!("{name}_{id}"),
+                Point3::from(pos) * units.length,
+                Vector3::from(vel) * velocity,
+                mass * units.mass,
+                color,
+            ));
+        }
+    }
+    if mass_block.is_some_and(|block| !block.data.is_empty()) {
+        bail!("Mass block has more masses than particles without one");
+    }
+    Ok(objects)
+}
+
+/// Read the Gadget block called `name`. The labelled format puts a record with the name
+/// before each block.
+fn gadget_block<'a>(file: &mut Bytes<'a>, labelled: bool, name: &str) -> anyhow::Result<Bytes<'a>> {
+    if labelled {
+        let label = file.record()?;
+        if !label.data.starts_with(name.as_bytes()) {
+            bail!(
+                "Expected block {name}, found {}",
+                String::from_utf8_lossy(&label.data[..label.data.len().min(4)])
+            );
+        }
+    }
+    file.record()
+        .with_context(|| format!("Invalid block {}", name.trim()))
+}
+
+/// Read a Tipsy binary file, with or without the padding after the header.
+pub fn parse_tipsy(data: &[u8], units: IcUnits) -> anyhow::Result<Vec<Object>> {
+    let ndim = data.get(12..16).context("File is too short for a header")?;
+    let big_endian = match (
+        u32::from_be_bytes(ndim.try_into()?),
+        u32::from_le_bytes(ndim.try_into()?),
+    ) {
+        (3, _) => true,
+        (_, 3) => false,
+        _ => bail!("Does not start with a Tipsy header for three dimensions"),
+    };
+    let mut file = Bytes { data, big_endian };
+    // Skip the time, the dimensions are already known.
+    file.take(8)?;
+    let nbodies = file.u32()? as usize;
+    file.take(4)?;
+    let counts = [
+        file.u32()? as usize,
+        file.u32()? as usize,
+        file.u32()? as usize,
+    ];
+    if counts.iter().sum::<usize>() != nbodies {
+        bail!("Header lists {nbodies} bodies, but {counts:?} of each type");
+    }
+    let body: usize = counts
+        .iter()
+        .zip(TIPSY_TYPES)
+        .map(|(count, (_, floats, _))| count * floats * 4)
+        .sum();
+    match file.data.len().checked_sub(body) {
+        Some(0) => {}
+        Some(4) => {
+            file.take(4)?;
+        }
+        _ => bail!(
+            "File has {} bytes of particles, expected {body}",
+            file.data.len()
+        ),
+    }
+
+    let velocity = (G * units.mass / units.length).sqrt();
+    let mut objects = Vec::with_capacity(nbodies);
+    for (count, (name, floats, color)) in counts.into_iter().zip(TIPSY_TYPES) {
+        for i in 0..count {
+            let mut values = Bytes {
+                data: file.take(floats * 4)?,
+                big_endian,
+            };
+            // Every type starts with the mass, position and velocity.
+            let mut next = || values.f32().map(|v| v as f64);
+            let mass = next()?;
+            let pos = Point3::new(next()?, next()?, next()?);
+            let vel = Vector3::new(next()?, next()?, next()?);
+            objects.push(particle(
+                format!("{name}_{i}"),
+                pos * units.length,
+                vel * velocity,
+                mass * units.mass,
+                color,
+            ));
+        }
+    }
+    Ok(objects)
+}
+
+fn particle(
+    name: String,
+    pos: Point3<f64>,
+    vel: Vector3<f64>,
+    mass: f64,
+    color: [f32; 3],
+) -> Object {
+    Object {
+        name,
+        dat: ObjectInfo {
+            pos,
+            vel,
+            mass,
+            pinned: false,
+            test_particle: false,
+        },
+        color: color.into(),
+        radius: SOLAR_RADIUS as f32,
+        oblateness: None,
+        radiation: None,
+        maneuvers: Vec::new(),
+        mass_rate: 0.0,
+        texture: None,
+    }
+}
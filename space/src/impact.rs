@@ -0,0 +1,258 @@
+//! Monte Carlo impact probabilities.
+//!
+//! The orbit of a newly discovered body is only known up to some uncertainty. To estimate
+//! the chance that it hits something, the body is cloned many times with its state drawn
+//! from that uncertainty, and each clone is followed to see whether it hits or passes
+//! close to any of the targets.
+//!
+//! The clones are added to a single simulation as test particles, so they feel the rest
+//! of the system without disturbing it or each other. This assumes the body is too light
+//! to noticeably move the targets, which holds for anything short of a planet.
+
+use std::fmt::Display;
+
+use anyhow::Context;
+use cgmath::{InnerSpace, Vector3};
+use rand::Rng;
+
+use crate::{
+    Object,
+    event_loop::build_sim,
+    rng::RngService,
+    sim::{Perturbations, SimTime},
+};
+
+/// Uncertainty in the state of a body, as a covariance matrix over
+/// `(x, y, z, vx, vy, vz)`, in AU and AU/s.
+#[derive(Debug, Clone)]
+pub struct Covariance {
+    pub matrix: [[f64; 6]; 6],
+}
+
+impl Covariance {
+    /// Independent errors with standard deviation `pos_sigma` along each axis of the
+    /// position, and `vel_sigma` along each axis of the velocity.
+    pub fn diagonal(pos_sigma: f64, vel_sigma: f64) -> Self {
+        let mut matrix = [[0.0; 6]; 6];
+        for (i, row) in matrix.iter_mut().enumerate() {
+            let sigma = if i < 3 { pos_sigma } else { vel_sigma };
+            row[i] = sigma * sigma;
+        }
+        Self { matrix }
+    }
+
+    /// Lower triangular `L` with `L L^T` equal to the covariance, used to turn independent
+    /// standard normal draws into correlated ones. Directions with zero variance are
+    /// allowed, and simply never perturbed.
+    fn cholesky(&self) -> anyhow::Result<[[f64; 6]; 6]> {
+        let m = &self.matrix;
+        let mut l = [[0.0; 6]; 6];
+        for i in 0..6 {
+            for j in 0..=i {
+                let sum: f64 = (0..j).map(|k| l[i][k] * l[j][k]).sum();
+                if i == j {
+                    let d = m[i][i] - sum;
+                    if d < -1e-12 * m[i][i].abs() {
+                        anyhow::bail!("Covariance is not positive semi-definite");
+                    }
+                    l[i][i] = d.max(0.0).sqrt();
+                } else if l[j][j] > 0.0 {
+                    l[i][j] = (m[i][j] - sum) / l[j][j];
+                }
+            }
+        }
+        Ok(l)
+    }
+}
+
+/// Setup of an impact probability estimate.
+#[derive(Debug, Clone)]
+pub struct ImpactStudy {
+    /// Index of the body to perturb.
+    pub body: usize,
+    /// Indices of the bodies it might hit.
+    pub targets: Vec<usize>,
+    pub covariance: Covariance,
+    /// Number of clones in the ensemble.
+    pub samples: usize,
+    /// Simulated time to follow the clones for, in seconds.
+    pub duration: f64,
+    /// Seconds per tick.
+    pub delta: f64,
+    /// Distance from the center of a target counted as a close approach, in AU.
+    pub close_approach: f64,
+    /// Add the first order post-Newtonian correction to gravity.
+    pub post_newtonian: bool,
+}
+
+/// Outcome of the ensemble for a single target.
+#[derive(Debug, Clone)]
+pub struct TargetStats {
+    pub name: String,
+    /// Number of clones that hit the target.
+    pub impacts: usize,
+    /// Number of clones that came within the close approach distance, including impacts.
+    pub close_approaches: usize,
+    /// Closest distance between any clone and the center of the target, in AU.
+    pub closest: f64,
+    /// Time of the earliest impact, if any.
+    pub first_impact: Option<SimTime>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ImpactReport {
+    pub body: String,
+    pub samples: usize,
+    /// Simulated time the clones were followed for.
+    pub duration: SimTime,
+    pub close_approach: f64,
+    pub targets: Vec<TargetStats>,
+}
+
+impl ImpactReport {
+    /// Estimated probability of an event seen `count` times, with its standard error.
+    pub fn probability(&self, count: usize) -> (f64, f64) {
+        let n = self.samples.max(1) as f64;
+        let p = count as f64 / n;
+        (p, (p * (1.0 - p) / n).sqrt())
+    }
+}
+
+impl Display for ImpactReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Impact study of {}: {} clones over {}",
+            self.body,
+            self.samples,
+            self.duration.elapsed()
+        )?;
+        for target in &self.targets {
+            let (p_impact, e_impact) = self.probability(target.impacts);
+            let (p_close, e_close) = self.probability(target.close_approaches);
+            writeln!(f, "\n{}:", target.name)?;
+            writeln!(
+                f,
+                "  impact: {} ({:.4} +- {:.4})",
+                target.impacts, p_impact, e_impact
+            )?;
+            writeln!(
+                f,
+                "  within {:.3e} AU: {} ({:.4} +- {:.4})",
+                self.close_approach, target.close_approaches, p_close, e_close
+            )?;
+            writeln!(f, "  closest approach: {:.3e} AU", target.closest)?;
+            if let Some(time) = target.first_impact {
+                writeln!(f, "  first impact after {}", time.elapsed())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ImpactStudy {
+    /// Run the ensemble starting from `objects`.
+    pub fn run(&self, objects: &[Object], rng: &RngService) -> anyhow::Result<ImpactReport> {
+        let body = objects
+            .get(self.body)
+            .context("Impact study body does not exist")?;
+        for target in &self.targets {
+            anyhow::ensure!(
+                *target < objects.len() && *target != self.body,
+                "Invalid impact study target {target}"
+            );
+        }
+        let l = self.covariance.cholesky()?;
+
+        let rng = rng.child("impact");
+        let first_clone = objects.len();
+        let mut all = objects.to_vec();
+        for i in 0..self.samples {
+            let mut rng = rng.stream(i as u64);
+            let z: [f64; 6] = std::array::from_fn(|_| standard_normal(&mut rng));
+            let d: [f64; 6] = std::array::from_fn(|r| (0..=r).map(|c| l[r][c] * z[c]).sum());
+
+            let mut clone = body.clone();
+            clone.name = format!("{}_{i}", body.name);
+            clone.dat.pos += Vector3::new(d[0], d[1], d[2]);
+            clone.dat.vel += Vector3::new(d[3], d[4], d[5]);
+            clone.dat.pinned = false;
+            clone.dat.test_particle = true;
+            all.push(clone);
+        }
+
+        let radii: Vec<f64> = self
+            .targets
+            .iter()
+            .map(|t| objects[*t].radius as f64)
+            .collect();
+        let mut targets: Vec<TargetStats> = self
+            .targets
+            .iter()
+            .map(|t| TargetStats {
+                name: objects[*t].name.clone(),
+                impacts: 0,
+                close_approaches: 0,
+                closest: f64::INFINITY,
+                first_impact: None,
+            })
+            .collect();
+        // Closest approach of each clone to each target, and whether the clone has hit
+        // anything. Clones are no longer followed after an impact.
+        let mut closest = vec![f64::INFINITY; self.samples * targets.len()];
+        let mut impacted = vec![false; self.samples];
+
+        let perturbations = Perturbations::from_objects(&all, self.post_newtonian);
+        let mut sim = build_sim(all.into_iter().map(|o| o.dat).collect(), perturbations);
+        let ticks = (self.duration / self.delta).ceil() as u64;
+        for tick in 1..=ticks {
+            sim.set_time(SimTime::new(tick - 1, self.delta));
+            sim.exec_iter(self.delta);
+            for (i, done) in impacted.iter_mut().enumerate() {
+                if *done {
+                    continue;
+                }
+                let pos = sim.objects[first_clone + i].pos;
+                for (t, stats) in targets.iter_mut().enumerate() {
+                    let dist = (pos - sim.objects[self.targets[t]].pos).magnitude();
+                    let closest = &mut closest[i * self.targets.len() + t];
+                    *closest = closest.min(dist);
+                    if dist < radii[t] {
+                        *done = true;
+                        stats.impacts += 1;
+                        stats
+                            .first_impact
+                            .get_or_insert(SimTime::new(tick, self.delta));
+                        break;
+                    }
+                }
+            }
+        }
+
+        for (t, stats) in targets.iter_mut().enumerate() {
+            for i in 0..self.samples {
+                let dist = closest[i * self.targets.len() + t];
+                stats.closest = stats.closest.min(dist);
+                if dist < self.close_approach {
+                    stats.close_approaches += 1;
+                }
+            }
+        }
+
+        Ok(ImpactReport {
+            body: body.name.clone(),
+            samples: self.samples,
+            duration: SimTime::new(ticks, self.delta),
+            close_approach: self.close_approach,
+            targets,
+        })
+    }
+}
+
+/// Draw from the standard normal distribution, using the Box-Muller transform.
+fn standard_normal(rng: &mut impl Rng) -> f64 {
+    // Shift away from zero, since ln(0) is infinite.
+    let u1 = 1.0 - rng.random::<f64>();
+    let u2 = rng.random::<f64>();
+    (-2.0 * u1.ln()).sqrt() * (std::f64::consts::TAU * u2).cos()
+}
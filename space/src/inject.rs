@@ -0,0 +1,163 @@
+//! Objects added to a simulation partway into a run.
+//!
+//! The number of objects is fixed for a run, since the renderer, the samples sent to it
+//! and the solvers all size their buffers up front. Objects that should appear later are
+//! instead added from the start, frozen in place as pinned test particles so they have no
+//! effect. When their time comes they are moved into position, and from then on take part
+//! in the simulation like any other object.
+
+use cgmath::{EuclideanSpace, InnerSpace, Matrix3, SquareMatrix, Vector3};
+
+use crate::{
+    Object,
+    sim::{ObjectInfo, SimTime},
+};
+
+/// A group of objects, like a whole scenario, to add to a running simulation.
+#[derive(Debug, Clone)]
+pub struct Injection {
+    pub name: String,
+    /// Objects to add, in their own coordinates.
+    pub objects: Vec<Object>,
+    /// Index of the object the others are placed relative to, using its state at the time
+    /// of injection. Without one, they are placed relative to the origin.
+    pub center: Option<usize>,
+    /// Rotation applied to the positions and velocities of the objects, around their
+    /// own origin.
+    pub rotation: Matrix3<f64>,
+    /// Position of the origin of the objects, in AU.
+    pub offset: Vector3<f64>,
+    /// Velocity added to every object, in AU/s.
+    pub velocity: Vector3<f64>,
+    /// Simulated time of injection, in seconds.
+    pub at: f64,
+}
+
+impl Injection {
+    /// Inject `objects` at the origin at time zero, without rotation.
+    pub fn new(name: impl Into<String>, objects: Vec<Object>) -> Self {
+        Self {
+            name: name.into(),
+            objects,
+            center: None,
+            rotation: Matrix3::identity(),
+            offset: Vector3::new(0.0, 0.0, 0.0),
+            velocity: Vector3::new(0.0, 0.0, 0.0),
+            at: 0.0,
+        }
+    }
+}
+
+struct PendingInjection {
+    /// Number of injections added before this one.
+    index: usize,
+    name: String,
+    center: Option<usize>,
+    at: f64,
+    /// Index of the first injected object among the simulated objects.
+    first: usize,
+    /// State of each object on injection, relative to the center.
+    states: Vec<ObjectInfo>,
+}
+
+/// Injections waiting for their time.
+#[derive(Default)]
+pub struct InjectionSchedule {
+    pending: Vec<PendingInjection>,
+    added: usize,
+}
+
+impl InjectionSchedule {
+    /// Add the objects of `injection` to `objects` frozen, and schedule their injection.
+    pub fn add(&mut self, injection: Injection, objects: &mut Vec<Object>) -> anyhow::Result<()> {
+        let origin = match injection.center {
+            Some(center) => {
+                objects
+                    .get(center)
+                    .ok_or_else(|| anyhow::anyhow!("Injection {} has no center", injection.name))?
+                    .dat
+                    .pos
+            }
+            None => EuclideanSpace::origin(),
+        };
+
+        let first = objects.len();
+        let mut states = Vec::with_capacity(injection.objects.len());
+        for mut obj in injection.objects {
+            let state = ObjectInfo {
+                pos: EuclideanSpace::from_vec(
+                    injection.rotation * obj.dat.pos.to_vec() + injection.offset,
+                ),
+                vel: injection.rotation * obj.dat.vel + injection.velocity,
+                ..obj.dat
+            };
+            // Park it where it will enter, rather than on top of something.
+            obj.dat = ObjectInfo {
+                pos: origin + state.pos.to_vec(),
+                vel: Vector3::new(0.0, 0.0, 0.0),
+                mass: state.mass,
+                pinned: true,
+                test_particle: true,
+            };
+            states.push(state);
+            objects.push(obj);
+        }
+
+        self.pending.push(PendingInjection {
+            index: self.added,
+            name: injection.name,
+            center: injection.center,
+            at: injection.at,
+            first,
+            states,
+        });
+        self.added += 1;
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Injections still waiting for their time, counted in the order they were added.
+    pub fn pending(&self) -> Vec<usize> {
+        self.pending.iter().map(|p| p.index).collect()
+    }
+
+    /// Forget the injections that are not in `pending`, for resuming a run where they
+    /// already happened.
+    pub fn retain_pending(&mut self, pending: &[usize]) {
+        self.pending.retain(|p| pending.contains(&p.index));
+    }
+
+    /// Inject everything whose time has come.
+    pub fn apply(&mut self, time: SimTime, objects: &mut [ObjectInfo]) {
+        self.pending.retain(|p| {
+            if time.seconds() < p.at {
+                return true;
+            }
+            let (center_pos, center_vel) = match p.center {
+                Some(center) => (objects[center].pos.to_vec(), objects[center].vel),
+                None => (Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 0.0)),
+            };
+            for (obj, state) in objects[p.first..].iter_mut().zip(&p.states) {
+                *obj = ObjectInfo {
+                    pos: state.pos + center_pos,
+                    vel: state.vel + center_vel,
+                    ..state.clone()
+                };
+            }
+            if let [state] = p.states.as_slice() {
+                println!(
+                    "Injected {} at {} AU from its center, moving at {:.3e} AU/s",
+                    p.name,
+                    state.pos.to_vec().magnitude(),
+                    state.vel.magnitude()
+                );
+            } else {
+                println!("Injected {} with {} objects", p.name, p.states.len());
+            }
+            false
+        });
+    }
+}
@@ -1,23 +1,71 @@
+pub mod accretion;
 pub mod batch_request;
+mod bloom;
 mod camera;
+pub mod checkpoint;
 mod circle_pipeline;
 pub mod constants;
+pub mod control;
+pub mod diff;
+pub mod ephemeris;
+pub mod escape;
 mod event_loop;
+pub mod flyby;
+mod history;
+pub mod ic_import;
+pub mod impact;
+pub mod inject;
+pub mod live;
+mod mesh_pipeline;
+pub mod mpcorb;
 mod objects;
+pub mod orbit;
+pub mod palette;
 pub mod parameters;
+pub mod particle_snapshots;
+mod permutations;
 mod pipeline;
+pub mod pipeline_cache;
+mod planet_textures;
+pub mod position_stream;
+pub mod preset_registry;
 pub mod presets;
+pub mod probes;
+#[cfg(unix)]
+pub mod profile;
+pub mod recording;
 mod render;
+pub mod replay;
+pub mod rng;
+pub mod roche;
+pub mod scaling;
+pub mod scenario_file;
+pub mod scenario_script;
 mod sim;
+pub mod snapshot;
+pub mod stats;
 mod surface;
+mod tessellation;
+pub mod trajectory;
 pub mod ui;
+pub mod validate;
+
+use std::path::PathBuf;
 
 pub use batch_request::BatchRequest;
 use bytemuck::{Pod, Zeroable};
 use cgmath::Vector3;
-pub use event_loop::{SpaceApp, run_sim_loop_erased};
+pub use event_loop::{SimOutputs, SpaceApp, run_sim_loop_erased};
 pub use objects::Objects;
-pub use sim::{BarnesHutSim, BruteForceSim, ObjectInfo, SimulationImpl};
+pub use sim::{
+    AdaptiveSim, AnalyticPotential, BarnesHutSim, BruteForceSim, DirectComparison, DormandPrince,
+    DualTreeSim, FmmSim, FmmTree, FofGroup, FofGroups, ForceCheck, ForceProvider, ForcesSim,
+    FrameTime, Hermite, KdTreeSim, KeplerPairs, Maneuver, NodeContribution, ObjectInfo, OblateBody,
+    Oblateness, OblatenessForce, PeriodicBox, PeriodicBruteForceSim, Perturbations,
+    PostNewtonianForce, Radiation, RadiationForce, RadiationSource, RadiationTarget, SimTime,
+    SimulationImpl, SolverKind, Spacecraft, StepStats, SymmetricBruteForceSim, ThrustAmount,
+    ThrustDirection, ThrustForce, kepler_drift, local_densities, validate_against_direct,
+};
 
 #[derive(Debug, Clone)]
 pub struct Object {
@@ -25,6 +73,15 @@ pub struct Object {
     pub dat: ObjectInfo,
     pub color: Vector3<f32>,
     pub radius: f32,
+    pub oblateness: Option<Oblateness>,
+    pub radiation: Option<Radiation>,
+    /// Scripted burns, which make the object a spacecraft. Empty for everything else.
+    pub maneuvers: Vec<Maneuver>,
+    /// Steady change in mass, in kg/s. Negative for mass loss, like a stellar wind.
+    pub mass_rate: f64,
+    /// Map of the surface, drawn on the object when it is close enough to see, as a binary
+    /// PPM image in the equirectangular projection.
+    pub texture: Option<PathBuf>,
 }
 
 #[derive(Copy, Clone, Pod, Zeroable)]
@@ -36,7 +93,24 @@ struct ShaderConstants {
     pub total_buffer_size: u32,
     pub start_index: u32,
     pub end_index: u32,
-    pub use_relative_position: u32,
     pub min_circle_size: f32,
+    pub max_circle_size: f32,
     pub last_relative_position: [f32; 3],
+    pub light_position: [f32; 3],
+    pub trail_time: f32,
+    pub trail_fade: u32,
+    pub trail_fade_time: f32,
+    pub trail_opacity: f32,
+    pub trail_color: u32,
+    pub colormap: u32,
+    pub trail_color_min: f32,
+    pub trail_color_max: f32,
+}
+
+#[derive(Copy, Clone, Pod, Zeroable)]
+#[repr(C)]
+struct BloomConstants {
+    pub step: [f32; 2],
+    pub threshold: f32,
+    pub intensity: f32,
 }
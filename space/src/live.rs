@@ -0,0 +1,61 @@
+//! Simulations started while the app is running.
+
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+
+use crate::{
+    BatchRequest, Object,
+    accretion::MassEvolution,
+    constants::SIM_THREAD_NAME,
+    event_loop::{SimOutputs, run_sim_loop_erased},
+    sim::Perturbations,
+};
+
+/// A simulation running on its own thread, sampled through `exchange`. The thread is
+/// told to stop when this is dropped, but not waited for.
+pub struct LiveSim {
+    exchange: Arc<BatchRequest>,
+    token: Arc<AtomicBool>,
+}
+
+impl LiveSim {
+    /// Start simulating `objects`, with the perturbations they ask for.
+    pub fn spawn(objects: &[Object]) -> anyhow::Result<Self> {
+        let exchange = Arc::new(BatchRequest::new(objects.len()));
+        let token = Arc::new(AtomicBool::new(false));
+        let perturbations = Perturbations::from_objects(objects, false);
+        let mass = MassEvolution::new(objects, false);
+        let infos = objects.iter().map(|o| o.dat.clone()).collect();
+
+        let exchange_clone = exchange.clone();
+        let token_clone = token.clone();
+        std::thread::Builder::new()
+            .name(SIM_THREAD_NAME.to_owned())
+            .spawn(move || {
+                run_sim_loop_erased(
+                    infos,
+                    exchange_clone,
+                    token_clone,
+                    perturbations,
+                    SimOutputs {
+                        mass,
+                        ..Default::default()
+                    },
+                )
+            })?;
+
+        Ok(Self { exchange, token })
+    }
+
+    pub fn exchange(&self) -> &Arc<BatchRequest> {
+        &self.exchange
+    }
+}
+
+impl Drop for LiveSim {
+    fn drop(&mut self) {
+        self.token.store(true, Ordering::Relaxed);
+    }
+}
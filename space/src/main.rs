@@ -1,11 +1,52 @@
-use std::sync::{Arc, atomic::AtomicBool};
+use std::{
+    io::{IsTerminal, Write},
+    path::Path,
+    sync::{Arc, atomic::AtomicBool},
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
 
+use cgmath::{Deg, Matrix3, Vector3};
 use eframe::egui;
 use egui_wgpu::{WgpuConfiguration, WgpuSetupCreateNew};
 
 use winit::event_loop::{ControlFlow, EventLoop};
 
-use space::{BatchRequest, Objects, SpaceApp, presets, run_sim_loop_erased, ui::SpaceEguiApp};
+mod args;
+use args::Args;
+
+use space::{
+    BatchRequest, KeplerPairs, Object, Objects, PeriodicBox, Perturbations, SimOutputs, SpaceApp,
+    accretion::MassEvolution,
+    checkpoint::{self, Checkpoint, Checkpoints},
+    constants::{
+        AU, CHECKPOINT_INTERVAL_MINUTES, CONTROL_SPARE_BODIES, DELTA, FLYBY_START_DISTANCE,
+        HEADLESS_REPORT_SECS, IMPACT_CLOSE_APPROACH, PARSEC, PARTICLE_SNAPSHOT_INTERVAL,
+        SIM_THREAD_NAME, SOLAR_MASS, SOLAR_RADIUS,
+    },
+    control,
+    diff::RecordingDiff,
+    ephemeris::Ephemeris,
+    escape::EscapeCheck,
+    flyby::Flyby,
+    ic_import::{self, IcUnits},
+    impact::{Covariance, ImpactStudy},
+    inject::{Injection, InjectionSchedule},
+    mpcorb,
+    particle_snapshots::ParticleSnapshots,
+    pipeline_cache,
+    position_stream::PositionStream,
+    preset_registry::{self, POTENTIALS, PRESETS},
+    presets::{self, Imf, Scenario},
+    replay::ReplayPlayer,
+    rng::RngService,
+    run_sim_loop_erased, scenario_file, scenario_script,
+    snapshot::{self, SnapshotJob},
+    stats::ClusterStatsLog,
+    trajectory::TrajectoryLog,
+    ui::{Demo, DemoStep, SpaceEguiApp},
+    validate,
+};
 
 fn graphics_direct(batch: Arc<BatchRequest>, objects: Objects) -> anyhow::Result<()> {
     let mut app = SpaceApp::new(1280.0, 640.0, objects, batch);
@@ -18,8 +59,8 @@ fn graphics_direct(batch: Arc<BatchRequest>, objects: Objects) -> anyhow::Result
     Ok(())
 }
 
-fn graphics_egui(batch: Arc<BatchRequest>, objects: Objects) -> anyhow::Result<()> {
-    let options = eframe::NativeOptions {
+fn native_options() -> eframe::NativeOptions {
+    eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_inner_size([1280.0, 1024.0])
             .with_drag_and_drop(true),
@@ -27,11 +68,12 @@ fn graphics_egui(batch: Arc<BatchRequest>, objects: Objects) -> anyhow::Result<(
         renderer: eframe::Renderer::Wgpu,
         wgpu_options: WgpuConfiguration {
             wgpu_setup: egui_wgpu::WgpuSetup::CreateNew(WgpuSetupCreateNew {
-                device_descriptor: Arc::new(|_| wgpu::DeviceDescriptor {
+                device_descriptor: Arc::new(|adapter| wgpu::DeviceDescriptor {
                     label: None,
                     required_features: wgpu::Features::PUSH_CONSTANTS
                         | wgpu::Features::SPIRV_SHADER_PASSTHROUGH
-                        | wgpu::Features::MAPPABLE_PRIMARY_BUFFERS,
+                        | wgpu::Features::MAPPABLE_PRIMARY_BUFFERS
+                        | pipeline_cache::features(adapter),
                     required_limits: wgpu::Limits {
                         max_push_constant_size: 128,
                         ..Default::default()
@@ -43,50 +85,658 @@ fn graphics_egui(batch: Arc<BatchRequest>, objects: Objects) -> anyhow::Result<(
             ..Default::default()
         },
         ..Default::default()
-    };
+    }
+}
+
+/// Ask on the terminal whether to resume from the checkpoint at `path`. Runs without a
+/// terminal start over, unless given `--resume`.
+fn ask_resume(path: &Path) -> anyhow::Result<bool> {
+    if !std::io::stdin().is_terminal() {
+        println!(
+            "Found checkpoint {}, starting over. Pass --resume to continue from it",
+            path.display()
+        );
+        return Ok(false);
+    }
+    print!(
+        "Found checkpoint {}, resume from it? [y/N] ",
+        path.display()
+    );
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+fn graphics_egui(
+    batch: Arc<BatchRequest>,
+    objects: Objects,
+    ephemerides: Vec<(usize, Ephemeris)>,
+    record: Option<String>,
+) -> anyhow::Result<()> {
+    eframe::run_native(
+        "space",
+        native_options(),
+        Box::new(|cc| {
+            let mut app = SpaceEguiApp::new(cc, batch, objects)
+                .unwrap()
+                .with_ephemerides(ephemerides);
+            if let Some(path) = record {
+                app = app.with_recording(path)?;
+            }
+            Ok(Box::new(app))
+        }),
+    )
+    .map_err(|e| anyhow::anyhow!("Err: {e}"))
+}
+
+fn diff_egui(path: &str, other: &str) -> anyhow::Result<()> {
+    let diff = RecordingDiff::open(path, other)?;
+    let mut player = ReplayPlayer::open(path)?;
+    let objects = Objects::new(&player.initial_objects()?);
+    println!(
+        "Comparing {} common frames, largest divergence {:.3e} AU",
+        diff.samples().len(),
+        diff.max_divergence()
+    );
+
+    eframe::run_native(
+        "space",
+        native_options(),
+        Box::new(|cc| {
+            Ok(Box::new(
+                SpaceEguiApp::new_diff(cc, player, diff, objects).unwrap(),
+            ))
+        }),
+    )
+    .map_err(|e| anyhow::anyhow!("Err: {e}"))
+}
+
+fn replay_egui(path: &str) -> anyhow::Result<()> {
+    let mut player = ReplayPlayer::open(path)?;
+    let objects = player.initial_objects()?;
+    println!(
+        "Replaying {} frames of {} objects",
+        player.num_frames(),
+        objects.len()
+    );
+    let objects = Objects::new(&objects);
+
+    eframe::run_native(
+        "space",
+        native_options(),
+        Box::new(|cc| {
+            Ok(Box::new(
+                SpaceEguiApp::new_replay(cc, player, objects).unwrap(),
+            ))
+        }),
+    )
+    .map_err(|e| anyhow::anyhow!("Err: {e}"))
+}
+
+fn demo_egui(rng: RngService) -> anyhow::Result<()> {
+    let (demo, objects) = Demo::start(DemoStep::presets(), rng)?;
 
     eframe::run_native(
         "space",
-        options,
-        Box::new(|cc| Ok(Box::new(SpaceEguiApp::new(cc, batch, objects).unwrap()))),
+        native_options(),
+        Box::new(|cc| Ok(Box::new(SpaceEguiApp::new_demo(cc, demo, objects).unwrap()))),
     )
     .map_err(|e| anyhow::anyhow!("Err: {e}"))
 }
 
+/// Print the progress of a run without a window every so often, until the simulation
+/// reaches `end_tick`.
+fn report_headless(batch: &BatchRequest, handle: &JoinHandle<()>, end_tick: u64) {
+    let started = Instant::now();
+    let mut last_report = started;
+    let mut first_tick = None;
+    while !handle.is_finished() {
+        std::thread::sleep(Duration::from_millis(100));
+        // Keep asking for samples, so that the time reported is a recent one.
+        let Some(time) = batch.sample_with(|frame| frame.time) else {
+            continue;
+        };
+        // A resumed run does not start at tick 0.
+        let first_tick = *first_tick.get_or_insert(time.ticks);
+        if last_report.elapsed().as_secs_f64() < HEADLESS_REPORT_SECS {
+            continue;
+        }
+        last_report = Instant::now();
+        let mut report = format!(
+            "{:.1}% at {}, {:.0} ticks/s",
+            100.0 * time.ticks as f64 / end_tick as f64,
+            time.elapsed(),
+            (time.ticks - first_tick) as f64 / started.elapsed().as_secs_f64()
+        );
+        if let Some(stats) = batch.cluster_stats() {
+            report += &format!(", virial ratio {:.3}", stats.virial_ratio());
+        }
+        println!("{report}");
+    }
+}
+
+/// Scenario files or scripts rendered side by side by `--sweep`, each named after its
+/// file.
+fn sweep_jobs(
+    paths: &[String],
+    rng: &RngService,
+    post_newtonian: bool,
+    duration: f64,
+) -> anyhow::Result<Vec<SnapshotJob>> {
+    let mut jobs: Vec<SnapshotJob> = Vec::with_capacity(paths.len());
+    for (i, path) in paths.iter().enumerate() {
+        let name = Path::new(path)
+            .file_stem()
+            .ok_or_else(|| anyhow::anyhow!("{path} is not a file"))?
+            .to_string_lossy()
+            .into_owned();
+        if jobs.iter().any(|job| job.name == name) {
+            anyhow::bail!("Two scenarios in the sweep are called {name}");
+        }
+        let rng = rng.child(&format!("sweep_{i}"));
+        let objects = load_scenario(path, &rng)?.into_objects();
+        jobs.push(SnapshotJob {
+            name,
+            perturbations: Perturbations::from_objects(&objects, post_newtonian),
+            objects,
+            duration,
+            delta: DELTA,
+        });
+    }
+    Ok(jobs)
+}
+
+/// Read a scenario file, or run a Rhai script generating one.
+fn load_scenario(path: &str, rng: &RngService) -> anyhow::Result<Scenario> {
+    if Path::new(path).extension().is_some_and(|ext| ext == "rhai") {
+        Ok(Scenario::Params(scenario_script::load_params(path, rng)?))
+    } else {
+        Ok(Scenario::Params(scenario_file::load_params(path)?))
+    }
+}
+
+/// Run the impact study requested on the command line, and print the report.
+fn impact_study(
+    args: &Args,
+    name: &str,
+    objects: &[Object],
+    rng: &RngService,
+) -> anyhow::Result<()> {
+    let find = |name: &str| {
+        objects
+            .iter()
+            .position(|o| o.name == name)
+            .ok_or_else(|| anyhow::anyhow!("No object named {name}"))
+    };
+    let body = find(name)?;
+    let targets = if args.impact_targets.is_empty() {
+        (0..objects.len())
+            .filter(|idx| *idx != body && !objects[*idx].dat.test_particle)
+            .collect()
+    } else {
+        args.impact_targets
+            .iter()
+            .map(|t| find(t))
+            .collect::<anyhow::Result<Vec<_>>>()?
+    };
+    let (pos_km, vel_m_s) = args.impact_sigma.unwrap_or((1000.0, 1.0));
+
+    let study = ImpactStudy {
+        body,
+        targets,
+        covariance: Covariance::diagonal(pos_km * 1e3 / AU, vel_m_s / AU),
+        samples: args.impact_samples.unwrap_or(1000),
+        duration: args.impact_days.unwrap_or(365.0) * 24.0 * 3600.0,
+        delta: DELTA,
+        close_approach: IMPACT_CLOSE_APPROACH,
+        post_newtonian: args.post_newtonian,
+    };
+    println!(
+        "Running {} clones of {name} for {} days",
+        study.samples,
+        study.duration / (24.0 * 3600.0)
+    );
+    let report = study.run(objects, rng)?;
+    println!("{report}");
+    Ok(())
+}
+
 fn main() -> anyhow::Result<()> {
     env_logger::init();
+    let args = Args::parse()?;
+    if args.periodic.is_some() && args.kepler_pairs.is_some() {
+        anyhow::bail!("--kepler-pairs cannot be used with --periodic");
+    }
+    if args.no_kepler && args.kepler_pairs.is_some() {
+        anyhow::bail!("--kepler-pairs cannot be used with --no-kepler");
+    }
+    if args.rk45.is_some() && args.kepler_pairs.is_some() {
+        anyhow::bail!("--kepler-pairs cannot be used with --rk45");
+    }
+    if let Some(tolerance) = args.rk45
+        && tolerance <= 0.0
+    {
+        anyhow::bail!("--rk45 requires a positive tolerance, got {tolerance}");
+    }
+    if args.hermite.is_some() && (args.rk45.is_some() || args.kepler_pairs.is_some()) {
+        anyhow::bail!("--hermite cannot be used with --rk45 or --kepler-pairs");
+    }
+    if let Some(accuracy) = args.hermite
+        && accuracy <= 0.0
+    {
+        anyhow::bail!("--hermite requires a positive accuracy parameter, got {accuracy}");
+    }
+    if let Some(budget) = args.cpu_budget
+        && !(budget > 0.0 && budget <= 100.0)
+    {
+        anyhow::bail!("--cpu-budget must be between 0 and 100 %, got {budget}");
+    }
+    if args.headless {
+        if args.ticks.is_none() == args.years.is_none() {
+            anyhow::bail!("--headless requires either --ticks or --years");
+        }
+        if args.record.is_some() || !args.ephemerides.is_empty() {
+            anyhow::bail!(
+                "--record and --ephemeris need a window, and cannot be used with --headless"
+            );
+        }
+    } else if args.ticks.is_some() || args.years.is_some() {
+        anyhow::bail!("--ticks and --years end a run without a window, see --headless");
+    }
+    match (&args.replay, &args.diff) {
+        (Some(path), Some(other)) => return diff_egui(path, other),
+        (Some(path), None) => return replay_egui(path),
+        (None, Some(_)) => anyhow::bail!("--diff requires --replay"),
+        (None, None) => (),
+    }
     // let window = get_window(1280.0, 640.0)?;
 
+    if args.list_presets {
+        for preset in PRESETS {
+            let params = preset
+                .params
+                .iter()
+                .map(|(name, default)| format!("{name}={default}"))
+                .collect::<Vec<_>>();
+            println!("{}: {}", preset.name, preset.description);
+            if !params.is_empty() {
+                println!("    {}", params.join(","));
+            }
+        }
+        println!();
+        println!("Potentials, for --potential:");
+        for potential in POTENTIALS {
+            println!("{}: {}", potential.name, potential.description);
+        }
+        return Ok(());
+    }
+
+    let rng = args
+        .seed
+        .map(RngService::new)
+        .unwrap_or_else(RngService::from_entropy);
+    println!("Using seed {}", rng.seed());
+
+    if args.demo {
+        return demo_egui(rng);
+    }
+
+    if !args.sweep.is_empty() {
+        let duration = args.sweep_days.unwrap_or(30.0) * 24.0 * 3600.0;
+        let jobs = sweep_jobs(&args.sweep, &rng, args.post_newtonian, duration)?;
+        let dir = args.sweep_dir.as_deref().unwrap_or("sweep");
+        return snapshot::run_sweep(jobs, dir, 640, 480, 3);
+    }
+
+    if args.preset.is_some() && (args.scenario.is_some() || args.initial_conditions.is_some()) {
+        anyhow::bail!(
+            "--preset, --cluster and --galaxies cannot be combined with --scenario, --gadget or --tipsy"
+        );
+    }
+    let scenario = match (&args.scenario, &args.initial_conditions) {
+        (Some(_), Some(_)) => {
+            anyhow::bail!("--scenario cannot be combined with --gadget or --tipsy")
+        }
+        (Some(path), None) => load_scenario(path, &rng)?,
+        (None, Some((format, path))) => {
+            let defaults = IcUnits::default();
+            let units = IcUnits {
+                length: args.ic_length.map_or(defaults.length, |pc| pc * PARSEC),
+                mass: args.ic_mass.map_or(defaults.mass, |m| m * SOLAR_MASS),
+            };
+            let objects = ic_import::open(path, *format, units)?;
+            println!("Read {} particles from {path}", objects.len());
+            Scenario::Objects(objects)
+        }
+        (None, None) => match &args.preset {
+            Some((preset, params)) => preset.generate(params, &rng)?,
+            None => Scenario::Objects(presets::fixed_cloud(
+                10000,
+                presets::CLOUD_PARTICLE_MASS,
+                &rng,
+            )),
+        },
+    };
+    let scenario = match &args.mpcorb {
+        Some(path) => {
+            let Scenario::Params(mut params) = scenario else {
+                anyhow::bail!("--mpcorb needs a scenario file with the sun, see --scenario");
+            };
+            if !params.iter().any(|p| p.name == "sun") {
+                anyhow::bail!("--mpcorb needs an object called sun in the scenario");
+            }
+            let orbits = mpcorb::open(path, args.mpcorb_limit)?;
+            println!("Read {} small bodies from {path}", orbits.len());
+            params.extend(mpcorb::into_params(&orbits, "sun"));
+            Scenario::Params(params)
+        }
+        None => scenario,
+    };
+    let potentials = match &args.potential {
+        Some(name) => preset_registry::potentials(name)?,
+        None => Vec::new(),
+    };
+
+    if args.validate {
+        let report = validate::validate(scenario);
+        println!("{report}");
+        if report.has_errors() {
+            anyhow::bail!("Scenario is not valid");
+        }
+        return Ok(());
+    }
+
+    if let Scenario::Params(params) = &scenario {
+        let report = validate::check_params(params);
+        if report.has_errors() {
+            println!("{report}");
+            anyhow::bail!("Scenario is not valid, see the errors above");
+        }
+    }
+
     #[allow(unused_mut)]
-    let mut objects = presets::fixed_cloud(10000);
-    // let mut objects = fixed_shell(100000);
-    // let mut objects = earth_sun_mars();
+    let mut objects = scenario.into_objects();
     // objects.push(big_boy_on_collision_course());
 
+    if let Some(name) = &args.impact {
+        return impact_study(&args, name, &objects, &rng);
+    }
+
     println!("Running with {} objects", objects.len());
 
+    let mut injections = InjectionSchedule::default();
+    for (i, [mass, impact_parameter, speed, days]) in args.flybys.iter().copied().enumerate() {
+        let center = objects
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.dat.mass.total_cmp(&b.1.dat.mass))
+            .map(|(idx, _)| idx)
+            .ok_or_else(|| anyhow::anyhow!("No object for the flyby to pass"))?;
+        let flyby = Flyby {
+            name: format!("flyby_{i}"),
+            mass: mass * SOLAR_MASS,
+            // Main sequence stars grow roughly as mass^0.8.
+            radius: (SOLAR_RADIUS * mass.powf(0.8)) as f32,
+            color: (1.0, 0.8, 0.5).into(),
+            center,
+            impact_parameter,
+            speed: speed * 1e3 / AU,
+            start_distance: FLYBY_START_DISTANCE,
+            inclination: 0.0,
+            longitude: 0.0,
+            at: days * 24.0 * 3600.0,
+        };
+        injections.add(flyby.injection(&objects)?, &mut objects)?;
+    }
+    for (i, [stars, scale_radius, x, y, z, days]) in args.clusters.iter().copied().enumerate() {
+        let name = format!("cluster_{i}");
+        let cluster = presets::plummer_cluster(
+            stars as usize,
+            scale_radius,
+            Imf::Fixed(1.0),
+            &rng.child(&name),
+        );
+        injections.add(
+            Injection {
+                offset: (x, y, z).into(),
+                at: days * 24.0 * 3600.0,
+                ..Injection::new(name, cluster)
+            },
+            &mut objects,
+        )?;
+    }
+    for (path, [x, y, z, vx, vy, vz, degrees]) in args.imports.iter().cloned() {
+        let params = scenario_file::load_params(&path)?;
+        let report = validate::check_params(&params);
+        if report.has_errors() {
+            println!("{report}");
+            anyhow::bail!("{path} is not valid, see the errors above");
+        }
+        let imported = Scenario::Params(params).into_objects();
+        println!("Importing {} objects from {path}", imported.len());
+        injections.add(
+            Injection {
+                rotation: Matrix3::from_angle_z(Deg(degrees)),
+                offset: (x, y, z).into(),
+                velocity: Vector3::new(vx, vy, vz) * 1e3 / AU,
+                ..Injection::new(path, imported)
+            },
+            &mut objects,
+        )?;
+    }
+
+    let spares = match &args.control {
+        Some(_) => {
+            let first = objects.len();
+            let count = args.control_spares.unwrap_or(CONTROL_SPARE_BODIES);
+            objects.extend(control::spare_bodies(count));
+            first..objects.len()
+        }
+        None => 0..0,
+    };
+
+    let checkpoints = match &args.checkpoints {
+        Some(dir) => {
+            let minutes = args
+                .checkpoint_minutes
+                .unwrap_or(CHECKPOINT_INTERVAL_MINUTES);
+            let checkpoints = Checkpoints::create(dir, Duration::from_secs_f64(minutes * 60.0))?;
+            println!("Writing checkpoints every {minutes} minutes to {dir}");
+            match checkpoint::latest(dir)? {
+                Some(path) if args.resume || ask_resume(&path)? => {
+                    let checkpoint = Checkpoint::open(&path)?;
+                    checkpoint.restore(&mut objects, &mut injections)?;
+                    println!(
+                        "Resuming from {} at tick {}",
+                        path.display(),
+                        checkpoint.time.ticks
+                    );
+                    Some(checkpoints.resumed_at(checkpoint.time))
+                }
+                None if args.resume => anyhow::bail!("No checkpoint to resume from in {dir}"),
+                _ => Some(checkpoints),
+            }
+        }
+        None if args.resume => anyhow::bail!("--resume needs a directory, see --checkpoints"),
+        None => None,
+    };
+
+    let watch = if args.watch.is_empty() {
+        None
+    } else {
+        let path = args.watch_log.as_deref().unwrap_or("trajectories.csv");
+        let every = args.watch_every.unwrap_or(1);
+        match every {
+            0 | 1 => println!("Logging every step of {} to {path}", args.watch.join(", ")),
+            _ => println!(
+                "Logging every {every} steps of {} to {path}",
+                args.watch.join(", ")
+            ),
+        }
+        Some(TrajectoryLog::create(path, &args.watch, &objects, every)?)
+    };
+
+    let ephemerides = args
+        .ephemerides
+        .iter()
+        .map(|(name, path)| {
+            let idx = objects
+                .iter()
+                .position(|o| o.name == *name)
+                .ok_or_else(|| anyhow::anyhow!("No object named {name}"))?;
+            let ephemeris = Ephemeris::open(path)?;
+            println!(
+                "Comparing {name} against {} records of {}",
+                ephemeris.records().len(),
+                ephemeris.target
+            );
+            Ok((idx, ephemeris))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let stats_log = args
+        .stats_log
+        .as_deref()
+        .map(ClusterStatsLog::create)
+        .transpose()?;
+
+    let snapshots = match &args.snapshots {
+        Some(dir) => {
+            let every = args.snapshot_every.unwrap_or(PARTICLE_SNAPSHOT_INTERVAL);
+            println!("Writing snapshots every {every} steps to {dir}");
+            Some(ParticleSnapshots::create(dir, every)?)
+        }
+        None => None,
+    };
+
+    let stream = match &args.stream {
+        Some(addr) => {
+            let stream = PositionStream::bind(addr.as_str(), &objects)?;
+            println!("Streaming positions over WebSocket on {addr}");
+            Some(stream)
+        }
+        None => None,
+    };
+
+    let escape = match (args.escape, args.escape_unbound) {
+        (None, None) => None,
+        (max_distance, unbound_distance) => Some(EscapeCheck::new(
+            &objects,
+            max_distance.unwrap_or(f64::INFINITY),
+            unbound_distance,
+        )),
+    };
+
+    let mass = MassEvolution::new(&objects, args.merge);
+
     let num_objects = objects.len();
 
     let mut object_infos = Vec::new();
     let mut buffer_data = Objects::new(&objects);
     let descs = buffer_data.descriptions_mut();
+    let mut perturbations = Perturbations::from_objects(&objects, args.post_newtonian);
+    perturbations.potentials = potentials;
+    perturbations.periodic = args.periodic.map(PeriodicBox::new);
+    perturbations.kepler = args.kepler_pairs.map(KeplerPairs::new);
+    perturbations.no_kepler = args.no_kepler;
+    perturbations.rk45 = args.rk45;
+    perturbations.hermite = args.hermite;
+    perturbations.incremental_tree = args.incremental_tree;
+    if args.hermite.is_some() && perturbations.adds_forces() {
+        anyhow::bail!(
+            "--hermite only supports Newtonian gravity, but the scenario adds other forces"
+        );
+    }
 
     for (idx, obj) in objects.into_iter().enumerate() {
         object_infos.push(obj.dat);
         descs[idx].color = obj.color.into();
     }
     let batch = Arc::new(BatchRequest::new(num_objects));
+    if let Some(budget) = args.cpu_budget {
+        batch.set_cpu_budget(budget / 100.0);
+    }
+    if let Some(threads) = args.threads {
+        batch.set_fixed_threads((threads > 0).then_some(threads));
+    }
+    if let Some(ticks) = args.max_unsampled_ticks {
+        batch.set_max_unsampled_ticks((ticks > 0).then_some(ticks));
+    }
+    if let Some(time) = checkpoints.as_ref().and_then(Checkpoints::resumed) {
+        batch.set_delta(time.delta);
+    }
+    let end_tick = match (args.ticks, args.years) {
+        (Some(ticks), _) => Some(ticks),
+        (None, Some(years)) => Some((years * 365.25 * 24.0 * 3600.0 / batch.delta()).ceil() as u64),
+        (None, None) => None,
+    };
+    if let Some(end) = end_tick {
+        batch.set_end_tick(Some(end.max(1)));
+        // Nothing samples a run without a window often enough to keep it going otherwise.
+        if args.max_unsampled_ticks.is_none() {
+            batch.set_max_unsampled_ticks(None);
+        }
+        println!("Running headless until tick {end}");
+    }
+    if let Some(addr) = &args.control {
+        control::serve(
+            addr,
+            batch.clone(),
+            num_objects,
+            spares,
+            control::ControlAccess {
+                snapshot_dir: args.control_snapshots.as_ref().map(Into::into),
+                public: args.control_public,
+            },
+        )?;
+        println!("Answering control requests on {addr}");
+    }
     let batch_clone = batch.clone();
     let token = Arc::new(AtomicBool::new(false));
     let token_clone = token.clone();
 
-    let handle = std::thread::spawn(|| run_sim_loop_erased(object_infos, batch_clone, token_clone));
+    let handle = std::thread::Builder::new()
+        .name(SIM_THREAD_NAME.to_owned())
+        .spawn(move || {
+            run_sim_loop_erased(
+                object_infos,
+                batch_clone,
+                token_clone,
+                perturbations,
+                SimOutputs {
+                    watch,
+                    injections,
+                    escape,
+                    mass,
+                    stats_log,
+                    snapshots,
+                    checkpoints,
+                    stream,
+                },
+            )
+        })?;
+
+    #[cfg(unix)]
+    if let Some(secs) = args.profile_secs {
+        std::thread::spawn(move || {
+            if let Err(e) =
+                space::profile::profile_sim_threads(Duration::from_secs_f64(secs), "flamegraph.svg")
+            {
+                println!("Profiling failed: {e}");
+            }
+        });
+    }
 
-    let egui = true;
-    if egui {
-        graphics_egui(batch, buffer_data)?;
+    if let Some(end) = end_tick {
+        report_headless(&batch, &handle, end);
     } else {
-        graphics_direct(batch, buffer_data)?;
+        let egui = true;
+        if egui {
+            graphics_egui(batch, buffer_data, ephemerides, args.record)?;
+        } else {
+            graphics_direct(batch, buffer_data)?;
+        }
     }
 
     token.store(true, std::sync::atomic::Ordering::Relaxed);
@@ -0,0 +1,249 @@
+use wgpu::{
+    BindGroup, BindGroupLayout, BlendComponent, BlendFactor, BlendState, Buffer, BufferDescriptor,
+    BufferUsages, Device, PipelineCompilationOptions, PipelineLayout, PipelineLayoutDescriptor,
+    PrimitiveState, Queue, RenderPass, RenderPipeline, RenderPipelineDescriptor, TextureFormat,
+    VertexAttribute, VertexBufferLayout,
+};
+
+use crate::{
+    ShaderConstants,
+    permutations::{PipelineCache, ShaderOptions},
+    pipeline_cache,
+    render::{depth_stencil_state, get_or_init_shader},
+};
+
+/// Vertex of a mesh, in world space.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MeshVertex {
+    pub pos: [f32; 3],
+    pub color: [f32; 4],
+    /// Center and radius of the sphere the vertex covers, only used by spheres.
+    pub sphere: [f32; 4],
+    /// Layer of the map of the sphere plus one, see `ObjectInstance::texture`.
+    pub texture: u32,
+}
+
+impl MeshVertex {
+    pub const fn layout() -> VertexBufferLayout<'static> {
+        VertexBufferLayout {
+            array_stride: std::mem::size_of::<MeshVertex>() as u64,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x3,
+                    offset: 0,
+                    shader_location: 0,
+                },
+                VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x4,
+                    offset: 3 * std::mem::size_of::<f32>() as u64,
+                    shader_location: 1,
+                },
+                VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x4,
+                    offset: 7 * std::mem::size_of::<f32>() as u64,
+                    shader_location: 2,
+                },
+                VertexAttribute {
+                    format: wgpu::VertexFormat::Uint32,
+                    offset: 11 * std::mem::size_of::<f32>() as u64,
+                    shader_location: 3,
+                },
+            ],
+        }
+    }
+}
+
+/// What a `MeshDrawPipeline` draws.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum MeshKind {
+    /// Surfaces like Roche lobes. They don't write depth, so they are blended on top of
+    /// each other in draw order.
+    Translucent,
+    /// Discs covering spheres, shaded as the spheres behind them with their maps.
+    Sphere,
+}
+
+impl MeshKind {
+    fn entry_points(self) -> (&'static str, &'static str) {
+        match self {
+            MeshKind::Translucent => ("mesh_vs", "mesh_fs"),
+            MeshKind::Sphere => ("sphere_vs", "sphere_fs"),
+        }
+    }
+}
+
+/// Draws triangle meshes that change every frame, like Roche lobes.
+pub(crate) struct MeshDrawPipeline {
+    layout: PipelineLayout,
+    texture_format: TextureFormat,
+    sample_count: u32,
+    kind: MeshKind,
+    pipelines: PipelineCache,
+    buffer: Option<Buffer>,
+    num_vertices: u32,
+}
+
+impl MeshDrawPipeline {
+    pub fn new(
+        device: &Device,
+        texture_format: TextureFormat,
+        sample_count: u32,
+        camera_layout: &BindGroupLayout,
+        textures_layout: &BindGroupLayout,
+        kind: MeshKind,
+    ) -> Self {
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[camera_layout, textures_layout],
+            push_constant_ranges: &[wgpu::PushConstantRange {
+                stages: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                range: 0..std::mem::size_of::<ShaderConstants>() as u32,
+            }],
+        });
+
+        let mut pipeline = Self {
+            layout: pipeline_layout,
+            texture_format,
+            sample_count,
+            kind,
+            pipelines: PipelineCache::new(),
+            buffer: None,
+            num_vertices: 0,
+        };
+        pipeline.prepare(device, ShaderOptions::default());
+        pipeline
+    }
+
+    /// Build the pipeline for `options`, if it has not been built yet.
+    pub fn prepare(&mut self, device: &Device, options: ShaderOptions) {
+        let (layout, texture_format, sample_count, kind) = (
+            &self.layout,
+            self.texture_format,
+            self.sample_count,
+            self.kind,
+        );
+        self.pipelines.prepare(options, |options| {
+            Self::build(device, layout, texture_format, sample_count, kind, options)
+        });
+    }
+
+    /// Render to targets with `sample_count` samples, rebuilding the pipelines if it changed.
+    pub fn set_sample_count(&mut self, sample_count: u32) {
+        if sample_count != self.sample_count {
+            self.sample_count = sample_count;
+            self.pipelines = PipelineCache::new();
+        }
+    }
+
+    fn build(
+        device: &Device,
+        layout: &PipelineLayout,
+        texture_format: TextureFormat,
+        sample_count: u32,
+        kind: MeshKind,
+        options: ShaderOptions,
+    ) -> RenderPipeline {
+        let shader_module = get_or_init_shader(device);
+        let (vertex_entry, fragment_entry) = kind.entry_points();
+        device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("mesh pipeline"),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader_module,
+                entry_point: Some(&options.entry_point(vertex_entry)),
+                buffers: &[MeshVertex::layout()],
+                compilation_options: Default::default(),
+            },
+            cache: pipeline_cache::get(),
+            primitive: PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                unclipped_depth: false,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                conservative: false,
+            },
+            depth_stencil: Some(depth_stencil_state(kind == MeshKind::Sphere)),
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader_module,
+                entry_point: Some(fragment_entry),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: texture_format,
+                    blend: Some(BlendState {
+                        color: BlendComponent {
+                            src_factor: BlendFactor::SrcAlpha,
+                            dst_factor: BlendFactor::OneMinusSrcAlpha,
+                            operation: wgpu::BlendOperation::Add,
+                        },
+                        alpha: BlendComponent::OVER,
+                    }),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: PipelineCompilationOptions::default(),
+            }),
+            multiview: None,
+        })
+    }
+
+    /// Replace the mesh, growing the vertex buffer if needed.
+    pub fn set_vertices(&mut self, device: &Device, queue: &Queue, vertices: &[MeshVertex]) {
+        self.num_vertices = vertices.len() as u32;
+        if vertices.is_empty() {
+            return;
+        }
+        let size = std::mem::size_of_val(vertices) as u64;
+        if self.buffer.as_ref().is_none_or(|b| b.size() < size) {
+            self.buffer = Some(device.create_buffer(&BufferDescriptor {
+                label: Some("mesh buffer"),
+                size,
+                usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }));
+        }
+        if let Some(buffer) = &self.buffer {
+            queue.write_buffer(buffer, 0, bytemuck::cast_slice(vertices));
+        }
+    }
+
+    /// Bytes allocated on the GPU.
+    pub fn memory_usage(&self) -> u64 {
+        self.buffer.as_ref().map_or(0, Buffer::size)
+    }
+
+    pub fn draw(
+        &self,
+        rpass: &mut RenderPass<'_>,
+        camera: &BindGroup,
+        textures: &BindGroup,
+        push_constants: &ShaderConstants,
+        options: ShaderOptions,
+    ) {
+        let Some(buffer) = &self.buffer else {
+            return;
+        };
+        if self.num_vertices == 0 {
+            return;
+        }
+
+        rpass.set_pipeline(self.pipelines.get(options));
+        rpass.set_vertex_buffer(0, buffer.slice(..));
+        rpass.set_bind_group(0, camera, &[]);
+        rpass.set_bind_group(1, textures, &[]);
+
+        rpass.set_push_constants(
+            wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+            0,
+            bytemuck::bytes_of(push_constants),
+        );
+
+        rpass.draw(0..self.num_vertices, 0..1);
+    }
+}
@@ -0,0 +1,201 @@
+//! Orbital elements of small bodies from the Minor Planet Center.
+//!
+//! Reads files in the fixed column format of MPCORB.DAT, with or without its header, and
+//! turns the orbits into `RelativeCoords` around the sun. The elements are heliocentric,
+//! referred to the ecliptic and equinox of J2000, which is the frame the presets use.
+//!
+//! Orbits are given at an epoch each, but nearly all of a file shares the same one. Every
+//! orbit is moved to the most common epoch along its mean motion, so that the bodies
+//! start out where they are at the same date.
+
+use std::{collections::HashMap, fs, path::Path};
+
+use anyhow::{Context, bail};
+
+use crate::{
+    constants::{AU, IAU_AU},
+    parameters::{Anomaly, RelativeCoords, RelativeOrAbsolute, StandardParams},
+};
+
+/// Geometric albedo assumed when estimating the size of a body from its magnitude. About
+/// the average of the main belt.
+const MPCORB_ALBEDO: f64 = 0.14;
+
+/// Color of the small bodies.
+const MPCORB_COLOR: [f32; 3] = [0.6, 0.6, 0.6];
+
+/// Orbit of a single small body, at its own epoch.
+#[derive(Debug, Clone)]
+pub struct MpcOrbit {
+    /// Readable designation, like `(1) Ceres`, or the packed one if there is none.
+    pub name: String,
+    /// Absolute magnitude, if known.
+    pub magnitude: Option<f64>,
+    /// Julian date of the epoch of the elements.
+    pub epoch_jd: f64,
+    /// In degrees, like the other angles.
+    pub mean_anomaly: f64,
+    pub arg_periapsis: f64,
+    pub long_asc_node: f64,
+    pub inclination: f64,
+    pub eccentricity: f64,
+    /// In degrees per day.
+    pub mean_motion: f64,
+    /// In AU.
+    pub semi_major_axis: f64,
+}
+
+impl MpcOrbit {
+    /// Diameter estimated from the magnitude, in km.
+    pub fn diameter(&self) -> Option<f64> {
+        self.magnitude
+            .map(|h| 1329.0 / MPCORB_ALBEDO.sqrt() * 10f64.powf(-h / 5.0))
+    }
+}
+
+/// Read at most `limit` orbits from an MPCORB file.
+pub fn open(path: impl AsRef<Path>, limit: Option<usize>) -> anyhow::Result<Vec<MpcOrbit>> {
+    let path = path.as_ref();
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read orbits {}", path.display()))?;
+    parse(&text, limit).with_context(|| format!("Invalid orbits {}", path.display()))
+}
+
+/// Read at most `limit` orbits. Everything up to the line of dashes that ends the header
+/// of MPCORB.DAT is skipped, as are blank lines.
+pub fn parse(text: &str, limit: Option<usize>) -> anyhow::Result<Vec<MpcOrbit>> {
+    let body = match text.find("\n-----") {
+        Some(start) => text[start + 1..]
+            .split_once('\n')
+            .map_or("", |(_, rest)| rest),
+        None => text,
+    };
+    let mut orbits = Vec::new();
+    for (i, line) in body.lines().enumerate() {
+        if orbits.len() >= limit.unwrap_or(usize::MAX) {
+            break;
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        orbits.push(parse_line(line).with_context(|| format!("Invalid orbit on line {}", i + 1))?);
+    }
+    Ok(orbits)
+}
+
+fn parse_line(line: &str) -> anyhow::Result<MpcOrbit> {
+    // Columns are counted from one in the documentation of the format.
+    let field = |start: usize, end: usize| line.get(start - 1..end.min(line.len())).map(str::trim);
+    let number = |start: usize, end: usize, what: &str| -> anyhow::Result<f64> {
+        let text = field(start, end).unwrap_or_default();
+        text.parse()
+            .with_context(|| format!("Invalid {what} {text:?}"))
+    };
+    if line.len() < 103 {
+        bail!(
+            "Line is {} characters long, expected at least 103",
+            line.len()
+        );
+    }
+    let packed = field(1, 7).unwrap_or_default();
+    let name = field(167, 194)
+        .filter(|name| !name.is_empty())
+        .unwrap_or(packed)
+        .to_owned();
+    let epoch = field(21, 25).unwrap_or_default();
+    let eccentricity = number(71, 79, "eccentricity")?;
+    if !(0.0..1.0).contains(&eccentricity) {
+        bail!("Eccentricity {eccentricity} is not an elliptical orbit");
+    }
+    Ok(MpcOrbit {
+        name,
+        magnitude: number(9, 13, "magnitude").ok(),
+        epoch_jd: packed_epoch_jd(epoch).with_context(|| format!("Invalid epoch {epoch:?}"))?,
+        mean_anomaly: number(27, 35, "mean anomaly")?,
+        arg_periapsis: number(38, 46, "argument of perihelion")?,
+        long_asc_node: number(49, 57, "longitude of the ascending node")?,
+        inclination: number(60, 68, "inclination")?,
+        eccentricity,
+        mean_motion: number(81, 91, "mean motion")?,
+        semi_major_axis: number(93, 103, "semi-major axis")?,
+    })
+}
+
+/// Julian date at midnight of a date packed the way the Minor Planet Center does, like
+/// `K2555` for 2025-05-05.
+fn packed_epoch_jd(packed: &str) -> Option<f64> {
+    let digit = |c: u8| match c {
+        b'0'..=b'9' => Some((c - b'0') as i64),
+        b'A'..=b'V' => Some((c - b'A') as i64 + 10),
+        _ => None,
+    };
+    let &[century, y1, y2, month, day] = packed.as_bytes() else {
+        return None;
+    };
+    let year = digit(century)? * 100 + digit(y1)? * 10 + digit(y2)?;
+    let (month, day) = (digit(month)?, digit(day)?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    // Gregorian calendar date to Julian day, from Meeus, Astronomical Algorithms.
+    let (y, m) = if month <= 2 {
+        (year - 1, month + 12)
+    } else {
+        (year, month)
+    };
+    let a = y / 100;
+    let b = 2 - a + a / 4;
+    Some(
+        (365.25 * (y + 4716) as f64).floor()
+            + (30.6001 * (m + 1) as f64).floor()
+            + day as f64
+            + b as f64
+            - 1524.5,
+    )
+}
+
+/// Parameters for massless bodies on `orbits` around the object called `parent`, all
+/// moved to the most common epoch among them.
+pub fn into_params(orbits: &[MpcOrbit], parent: &str) -> Vec<StandardParams> {
+    let mut epochs: HashMap<u64, usize> = HashMap::new();
+    for orbit in orbits {
+        *epochs.entry(orbit.epoch_jd.to_bits()).or_default() += 1;
+    }
+    // The earliest of the most common, so that ties go the same way every time.
+    let epoch = epochs
+        .into_iter()
+        .max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(&a.0)))
+        .map_or(0.0, |(bits, _)| f64::from_bits(bits));
+
+    orbits
+        .iter()
+        .map(|orbit| {
+            let mean_anomaly = orbit.mean_anomaly + orbit.mean_motion * (epoch - orbit.epoch_jd);
+            let radius = orbit.diameter().unwrap_or_default() * 1e3 / 2.0 / AU;
+            StandardParams {
+                name: orbit.name.clone(),
+                coordinates: RelativeOrAbsolute::Relative(RelativeCoords {
+                    parent: parent.to_owned(),
+                    semi_major_axis: orbit.semi_major_axis * IAU_AU,
+                    eccentricity: orbit.eccentricity,
+                    inclination: orbit.inclination,
+                    arg_periapsis: orbit.arg_periapsis,
+                    long_asc_node: orbit.long_asc_node,
+                    // Already moved to the epoch, along the mean motion of the file.
+                    anomaly: Anomaly::Mean {
+                        mean_anomaly,
+                        time_since_epoch: 0.0,
+                    },
+                }),
+                mass: 0.0,
+                radius: radius as f32,
+                color: MPCORB_COLOR,
+                oblateness: None,
+                radiation: None,
+                maneuvers: Vec::new(),
+                mass_rate: 0.0,
+                texture: None,
+            }
+        })
+        .collect()
+}
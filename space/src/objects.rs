@@ -1,18 +1,27 @@
 use std::ops::Range;
 
+use cgmath::{InnerSpace, Vector3};
 use wgpu::{Buffer, Queue, VertexAttribute, VertexBufferLayout};
 
-use crate::{Object, constants::TRAIL_MAX_LENGTH};
+use crate::{
+    Object,
+    constants::{MAX_DIRTY_SPANS, TRAIL_MAX_LENGTH},
+    palette::Palette,
+    sim::SimTime,
+};
 
 pub type Vec3 = [f32; 3];
 
-pub const OBJECT_STRIDE: usize = TRAIL_MAX_LENGTH * std::mem::size_of::<Vertex>();
-
 #[repr(C)]
 #[derive(Clone, Copy, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Vertex {
     pub pos: Vec3,
     pub idx: u32,
+    /// Simulated seconds at which the position was sampled.
+    pub time: f32,
+    /// Speed and magnitude of acceleration, from the samples leading up to this one. 0
+    /// until there are enough samples.
+    pub motion: [f32; 2],
 }
 
 impl Vertex {
@@ -40,18 +49,59 @@ impl Vertex {
         }
     }
 
+    /// Like `layout` for vertices, with the sample time and motion as well.
+    pub const fn trail_layout<const LOC_OFFSET: u32>() -> VertexBufferLayout<'static> {
+        VertexBufferLayout {
+            array_stride: std::mem::size_of::<Vertex>() as u64,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x3,
+                    offset: 0,
+                    shader_location: LOC_OFFSET,
+                },
+                VertexAttribute {
+                    format: wgpu::VertexFormat::Uint32,
+                    offset: 3 * std::mem::size_of::<f32>() as u64,
+                    shader_location: LOC_OFFSET + 1,
+                },
+                VertexAttribute {
+                    format: wgpu::VertexFormat::Float32,
+                    offset: 4 * std::mem::size_of::<f32>() as u64,
+                    shader_location: LOC_OFFSET + 2,
+                },
+                VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x2,
+                    offset: 5 * std::mem::size_of::<f32>() as u64,
+                    shader_location: LOC_OFFSET + 3,
+                },
+            ],
+        }
+    }
+
     pub const fn size() -> u64 {
         std::mem::size_of::<Vertex>() as u64
     }
 }
 
+/// Position of an object in a batch, and its velocity if it is known.
+type MotionState = (Vector3<f32>, Option<Vector3<f32>>);
+
 pub struct ObjectVertexCache {
     buff: Vec<Vertex>,
     num_objects: usize,
+    trail_length: usize,
     head: usize,
     tail: usize,
     pending_head: usize,
     pending_tail: usize,
+    /// Time of the latest batch.
+    time: f32,
+    /// Simulated seconds of the previous batch, and the position and velocity of each
+    /// object in it, for the motion of the next.
+    previous: Option<(f64, Vec<MotionState>)>,
+    /// Smallest and largest nonzero speed, and acceleration, in the latest batch.
+    motion_ranges: [(f32, f32); 2],
 }
 
 #[repr(C)]
@@ -59,6 +109,8 @@ pub struct ObjectVertexCache {
 pub struct ObjectInstance {
     pub color: [f32; 3],
     pub radius: f32,
+    /// Layer of the map of the object in the texture array plus one, 0 for none.
+    pub texture: u32,
 }
 
 impl ObjectInstance {
@@ -80,19 +132,48 @@ impl ObjectInstance {
             ],
         }
     }
+
+    /// Like `layout`, with the texture layer as well.
+    pub const fn textured_layout<const LOC_OFFSET: u32>() -> VertexBufferLayout<'static> {
+        VertexBufferLayout {
+            array_stride: std::mem::size_of::<ObjectInstance>() as u64,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &[
+                VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x3,
+                    offset: 0,
+                    shader_location: LOC_OFFSET,
+                },
+                VertexAttribute {
+                    format: wgpu::VertexFormat::Float32,
+                    offset: (std::mem::size_of::<f32>() * 3) as u64,
+                    shader_location: LOC_OFFSET + 1,
+                },
+                VertexAttribute {
+                    format: wgpu::VertexFormat::Uint32,
+                    offset: (std::mem::size_of::<f32>() * 4) as u64,
+                    shader_location: LOC_OFFSET + 2,
+                },
+            ],
+        }
+    }
 }
 
 pub type PointBatch<'a> = &'a [Vec3];
 
 impl ObjectVertexCache {
-    pub fn new(num_objects: usize) -> Self {
+    pub fn new(num_objects: usize, trail_length: usize) -> Self {
         Self {
-            buff: vec![Default::default(); num_objects * TRAIL_MAX_LENGTH],
+            buff: vec![Default::default(); num_objects * trail_length],
             num_objects,
+            trail_length,
             head: 0,
             tail: 0,
             pending_head: 0,
             pending_tail: 0,
+            time: 0.0,
+            previous: None,
+            motion_ranges: [(0.0, 0.0); 2],
         }
     }
 
@@ -103,23 +184,49 @@ impl ObjectVertexCache {
         }
     }
 
-    pub fn push_items(&mut self, batch: &PointBatch) {
+    pub fn push_items(&mut self, batch: &PointBatch, time: SimTime) {
         debug_assert!(batch.len() == self.num_objects);
 
-        for point in batch.iter() {
+        self.time = time.seconds() as f32;
+        // Motion is only known from the difference to the previous batch.
+        let (dt, mut states) = match self.previous.take() {
+            Some((last, states)) if time.seconds() > last => {
+                (Some((time.seconds() - last) as f32), states)
+            }
+            _ => (None, vec![(Vector3::new(0.0, 0.0, 0.0), None); batch.len()]),
+        };
+        self.motion_ranges = [(f32::INFINITY, 0.0); 2];
+        for (point, state) in batch.iter().zip(&mut states) {
+            let pos = Vector3::from(*point);
+            let velocity = dt.map(|dt| (pos - state.0) / dt);
+            let acceleration = match (velocity, state.1, dt) {
+                (Some(velocity), Some(last), Some(dt)) => Some((velocity - last) / dt),
+                _ => None,
+            };
+            let motion = [velocity, acceleration].map(|v| v.map_or(0.0, |v| v.magnitude()));
+            for (range, value) in self.motion_ranges.iter_mut().zip(motion) {
+                if value > 0.0 {
+                    *range = (range.0.min(value), range.1.max(value));
+                }
+            }
+            *state = (pos, velocity);
+
             self.buff[self.pending_tail] = Vertex {
                 pos: *point,
                 idx: self.tail as u32,
+                time: self.time,
+                motion,
             };
 
             Self::inc_circular(
                 &mut self.pending_head,
                 &mut self.pending_tail,
-                TRAIL_MAX_LENGTH * self.num_objects,
+                self.trail_length * self.num_objects,
             );
         }
 
-        Self::inc_circular(&mut self.head, &mut self.tail, TRAIL_MAX_LENGTH);
+        Self::inc_circular(&mut self.head, &mut self.tail, self.trail_length);
+        self.previous = Some((time.seconds(), states));
     }
 
     pub fn flush_to_buffer(&mut self, buffer: &Buffer, queue: &Queue) {
@@ -153,7 +260,7 @@ impl ObjectVertexCache {
     pub fn position_of(&self, idx: usize) -> &[f32; 3] {
         let mut vertex_idx_raw = idx as i64 - self.num_objects as i64 + self.pending_tail as i64;
         if vertex_idx_raw < 0 {
-            vertex_idx_raw += TRAIL_MAX_LENGTH as i64 * self.num_objects as i64;
+            vertex_idx_raw += self.trail_length as i64 * self.num_objects as i64;
         }
         &self.buff[vertex_idx_raw as usize].pos
     }
@@ -163,13 +270,63 @@ impl ObjectVertexCache {
         self.tail = 0;
         self.pending_head = 0;
         self.pending_tail = 0;
+        self.previous = None;
+    }
+}
+
+/// Spans of object descriptions changed since they were last uploaded.
+///
+/// Changes tend to touch a few objects at a time, like recoloring a single body, so only
+/// those spans are written to the GPU instead of every description.
+#[derive(Debug, Default)]
+struct DirtySpans {
+    spans: Vec<Range<usize>>,
+}
+
+impl DirtySpans {
+    fn mark(&mut self, span: Range<usize>) {
+        if span.is_empty() {
+            return;
+        }
+        self.spans.push(span);
+        if self.spans.len() > MAX_DIRTY_SPANS {
+            self.coalesce();
+        }
+        if self.spans.len() > MAX_DIRTY_SPANS {
+            let start = self.spans.first().unwrap().start;
+            let end = self.spans.last().unwrap().end;
+            self.spans.clear();
+            self.spans.push(start..end);
+        }
+    }
+
+    /// Sort the spans and merge those that overlap or touch.
+    fn coalesce(&mut self) {
+        self.spans.sort_unstable_by_key(|s| s.start);
+        let mut merged: Vec<Range<usize>> = Vec::with_capacity(self.spans.len());
+        for span in self.spans.drain(..) {
+            match merged.last_mut() {
+                Some(last) if span.start <= last.end => last.end = last.end.max(span.end),
+                _ => merged.push(span),
+            }
+        }
+        self.spans = merged;
+    }
+
+    /// Remove and return the changed spans, sorted and without overlaps.
+    fn take(&mut self) -> Vec<Range<usize>> {
+        self.coalesce();
+        std::mem::take(&mut self.spans)
     }
 }
 
 pub struct Objects {
     vertices: ObjectVertexCache,
     descriptions: Vec<ObjectInstance>,
+    dirty: DirtySpans,
     infos: Vec<Object>,
+    /// Whether the trail of each object is drawn.
+    trails_shown: Vec<bool>,
     target_object: Option<usize>,
 }
 
@@ -182,14 +339,17 @@ impl Objects {
             descriptions.push(ObjectInstance {
                 color: obj.color.into(),
                 radius: obj.radius,
+                texture: 0,
             });
             infos.push(obj.clone());
         }
 
         Self {
-            vertices: ObjectVertexCache::new(num_objects),
+            vertices: ObjectVertexCache::new(num_objects, TRAIL_MAX_LENGTH),
             descriptions,
+            dirty: DirtySpans::default(),
             target_object: None,
+            trails_shown: vec![true; num_objects],
             infos,
         }
     }
@@ -198,8 +358,69 @@ impl Objects {
         self.vertices.flush_to_buffer(buffer, queue);
     }
 
-    pub fn push_items(&mut self, batch: PointBatch) {
-        self.vertices.push_items(&batch);
+    /// Upload the object descriptions that have changed since the last flush.
+    pub fn flush_descriptions(&mut self, buffer: &Buffer, queue: &Queue) {
+        for span in self.dirty.take() {
+            let offset = (span.start * std::mem::size_of::<ObjectInstance>()) as u64;
+            queue.write_buffer(
+                buffer,
+                offset,
+                bytemuck::cast_slice(&self.descriptions[span]),
+            );
+        }
+    }
+
+    pub fn set_color(&mut self, idx: usize, color: [f32; 3]) {
+        if self.descriptions[idx].color != color {
+            self.descriptions[idx].color = color;
+            self.dirty.mark(idx..idx + 1);
+        }
+    }
+
+    /// Draw the object with the map in layer `texture - 1` of the texture array, or without
+    /// a map if it is 0.
+    pub fn set_texture(&mut self, idx: usize, texture: u32) {
+        if self.descriptions[idx].texture != texture {
+            self.descriptions[idx].texture = texture;
+            self.dirty.mark(idx..idx + 1);
+        }
+    }
+
+    /// Recolor every object from its original color with `palette`.
+    pub fn apply_palette(&mut self, palette: Palette) {
+        for idx in 0..self.descriptions.len() {
+            self.set_color(idx, palette.map(self.infos[idx].color.into()));
+        }
+    }
+
+    /// Add the positions of every object sampled at `time` to the trails.
+    pub fn push_items(&mut self, batch: PointBatch, time: SimTime) {
+        self.vertices.push_items(&batch, time);
+    }
+
+    /// Simulated seconds at which the latest positions were sampled.
+    pub fn latest_time(&self) -> f32 {
+        self.vertices.time
+    }
+
+    /// Smallest and largest nonzero speed, and magnitude of acceleration, of the objects
+    /// in the latest batch. The smallest is infinite if none are known yet.
+    pub fn motion_ranges(&self) -> [(f32, f32); 2] {
+        self.vertices.motion_ranges
+    }
+
+    /// Whether the trail of each object is drawn.
+    pub fn trails_shown(&self) -> &[bool] {
+        &self.trails_shown
+    }
+
+    /// Draw the trail of the object at `idx` or not. The samples are kept either way.
+    pub fn set_trail_shown(&mut self, idx: usize, shown: bool) {
+        self.trails_shown[idx] = shown;
+    }
+
+    pub fn show_all_trails(&mut self) {
+        self.trails_shown.fill(true);
     }
 
     pub fn set_target_object(&mut self, idx: Option<usize>) {
@@ -215,14 +436,14 @@ impl Objects {
         if self.vertices.tail >= self.vertices.head {
             head..(head + self.vertices.tail as u32)
         } else {
-            head..((TRAIL_MAX_LENGTH + self.vertices.tail) as u32)
+            head..((self.trail_length() + self.vertices.tail) as u32)
         }
     }
 
     pub fn get_last_batch_range(&self) -> Range<u64> {
+        let len = self.trail_length() * self.num_objects();
         if self.vertices.pending_tail < self.num_objects() {
-            ((TRAIL_MAX_LENGTH * self.num_objects() - self.num_objects()) as u64)
-                ..((TRAIL_MAX_LENGTH * self.num_objects()) as u64)
+            ((len - self.num_objects()) as u64)..(len as u64)
         } else {
             ((self.vertices.pending_tail - self.num_objects()) as u64)
                 ..(self.vertices.pending_tail as u64)
@@ -233,7 +454,13 @@ impl Objects {
         self.descriptions.len()
     }
 
+    pub fn descriptions(&self) -> &[ObjectInstance] {
+        &self.descriptions
+    }
+
+    /// Every object description, which are all uploaded again at the next flush.
     pub fn descriptions_mut(&mut self) -> &mut [ObjectInstance] {
+        self.dirty.mark(0..self.descriptions.len());
         self.descriptions.as_mut_slice()
     }
 
@@ -249,4 +476,24 @@ impl Objects {
     pub fn clear(&mut self) {
         self.vertices.clear();
     }
+
+    /// Number of samples kept of each trail, including the current position.
+    pub fn trail_length(&self) -> usize {
+        self.vertices.trail_length
+    }
+
+    /// Keep `length` samples of each trail, at least 2. The trails are cleared if the
+    /// length changes.
+    pub fn set_trail_length(&mut self, length: usize) {
+        let length = length.max(2);
+        if length != self.trail_length() {
+            self.vertices = ObjectVertexCache::new(self.num_objects(), length);
+        }
+    }
+
+    /// Bytes held by the trails on the CPU side. The renderer keeps a copy of the same
+    /// size on the GPU.
+    pub fn trail_memory(&self) -> usize {
+        self.vertices.buff.capacity() * std::mem::size_of::<Vertex>()
+    }
 }
@@ -0,0 +1,88 @@
+//! Osculating orbits of live bodies.
+//!
+//! The orbit of a body is taken around its dominant attractor, the heavier body it is
+//! most tightly bound to. That is the planet for a moon, even though the star pulls
+//! harder on it, and the star for a planet. A body bound to nothing heavier is on an
+//! open orbit around whatever pulls hardest on it.
+
+use cgmath::{InnerSpace, Vector3};
+
+use crate::{
+    constants::{AU, G, G_ABS, M0},
+    parameters::{RelativeCoords, compute_orbital_params},
+    sim::ObjectInfo,
+};
+
+/// State of a body relative to its dominant attractor, in SI units like scenario files.
+#[derive(Debug, Clone)]
+pub struct RelativeState {
+    pub object: usize,
+    pub attractor: usize,
+    /// Standard gravitational parameter of the pair, in m^3/s^2.
+    pub mu: f64,
+    /// In meters.
+    pub pos: Vector3<f64>,
+    /// In m/s.
+    pub vel: Vector3<f64>,
+}
+
+impl RelativeState {
+    /// State of the object at `index` relative to its dominant attractor, if it has one.
+    pub fn find(objects: &[ObjectInfo], index: usize) -> Option<Self> {
+        let attractor = dominant_attractor(objects, index)?;
+        let (obj, other) = (&objects[index], &objects[attractor]);
+        Some(Self {
+            object: index,
+            attractor,
+            mu: G_ABS * M0 * (obj.gravitating_mass() + other.gravitating_mass()),
+            pos: (obj.pos - other.pos) * AU,
+            vel: (obj.vel - other.vel) * AU,
+        })
+    }
+
+    /// Osculating orbital elements, around the attractor called `parent`.
+    pub fn elements(&self, parent: String) -> RelativeCoords {
+        compute_orbital_params(parent, self.mu, self.pos, self.vel)
+    }
+
+    /// Orbital period in seconds, for a closed orbit.
+    pub fn period(&self) -> Option<f64> {
+        let energy = self.vel.magnitude2() / 2.0 - self.mu / self.pos.magnitude();
+        (energy < 0.0).then(|| {
+            let a = -self.mu / (2.0 * energy);
+            std::f64::consts::TAU * (a.powi(3) / self.mu).sqrt()
+        })
+    }
+}
+
+/// Heavier body the object at `index` has the smallest bound orbit around, or the one
+/// pulling hardest on it if it is bound to none. Frozen bodies have no attractor.
+pub fn dominant_attractor(objects: &[ObjectInfo], index: usize) -> Option<usize> {
+    let obj = objects.get(index)?;
+    if obj.is_frozen() {
+        return None;
+    }
+    let mass = obj.gravitating_mass();
+    let candidates = objects.iter().enumerate().filter(|(i, other)| {
+        *i != index && !other.is_frozen() && other.gravitating_mass() > mass && other.pos != obj.pos
+    });
+
+    let mut tightest: Option<(usize, f64)> = None;
+    let mut strongest: Option<(usize, f64)> = None;
+    for (i, other) in candidates {
+        let mu = G * (mass + other.gravitating_mass());
+        let rel = obj.pos - other.pos;
+        let energy = (obj.vel - other.vel).magnitude2() / 2.0 - mu / rel.magnitude();
+        if energy < 0.0 {
+            let a = -mu / (2.0 * energy);
+            if tightest.is_none_or(|(_, best)| a < best) {
+                tightest = Some((i, a));
+            }
+        }
+        let pull = other.gravitating_mass() / rel.magnitude2();
+        if strongest.is_none_or(|(_, best)| pull > best) {
+            strongest = Some((i, pull));
+        }
+    }
+    tightest.or(strongest).map(|(i, _)| i)
+}
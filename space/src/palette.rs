@@ -0,0 +1,95 @@
+//! Color palettes for objects and the UI.
+//!
+//! Scenarios pick object colors freely, which often means telling red from green. The
+//! other palettes replace every saturated color with the closest of a few colors chosen
+//! to stay distinct for everyone. Grays are left alone, since they are only told apart
+//! by brightness anyway.
+
+/// Okabe & Ito (2008), with black left out since the background is black.
+const OKABE_ITO: [[f32; 3]; 7] = [
+    [230.0 / 255.0, 159.0 / 255.0, 0.0],
+    [86.0 / 255.0, 180.0 / 255.0, 233.0 / 255.0],
+    [0.0, 158.0 / 255.0, 115.0 / 255.0],
+    [240.0 / 255.0, 228.0 / 255.0, 66.0 / 255.0],
+    [0.0, 114.0 / 255.0, 178.0 / 255.0],
+    [213.0 / 255.0, 94.0 / 255.0, 0.0],
+    [204.0 / 255.0, 121.0 / 255.0, 167.0 / 255.0],
+];
+
+const HIGH_CONTRAST: [[f32; 3]; 5] = [
+    [1.0, 1.0, 0.0],
+    [0.0, 1.0, 1.0],
+    [1.0, 0.0, 1.0],
+    [0.0, 1.0, 0.0],
+    [1.0, 0.4, 0.0],
+];
+
+/// Colors with less saturation than this are treated as gray.
+const GRAY_SATURATION: f32 = 0.25;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Palette {
+    /// The colors given by the scenario.
+    #[default]
+    Scenario,
+    /// Colors that stay distinct with any kind of color blindness.
+    ColorblindSafe,
+    /// Fully saturated colors, with grays turned white.
+    HighContrast,
+}
+
+impl Palette {
+    pub const ALL: [Palette; 3] = [
+        Palette::Scenario,
+        Palette::ColorblindSafe,
+        Palette::HighContrast,
+    ];
+
+    fn colors(self) -> &'static [[f32; 3]] {
+        match self {
+            Self::Scenario => &[],
+            Self::ColorblindSafe => &OKABE_ITO,
+            Self::HighContrast => &HIGH_CONTRAST,
+        }
+    }
+
+    /// Color used to highlight things in the UI, if the palette has one.
+    pub fn accent(self) -> Option<[f32; 3]> {
+        match self {
+            Self::Scenario => None,
+            Self::ColorblindSafe => Some(OKABE_ITO[4]),
+            Self::HighContrast => Some(HIGH_CONTRAST[0]),
+        }
+    }
+
+    /// Color to show in place of `color`.
+    pub fn map(self, color: [f32; 3]) -> [f32; 3] {
+        if self == Self::Scenario {
+            return color;
+        }
+        let max = color.into_iter().fold(0.0, f32::max);
+        let min = color.into_iter().fold(1.0, f32::min);
+        if max <= 0.0 || (max - min) / max < GRAY_SATURATION {
+            return match self {
+                Self::HighContrast => [1.0; 3],
+                _ => color,
+            };
+        }
+
+        // Compare by hue rather than brightness, so dim colors map like bright ones.
+        let normalize = |c: [f32; 3]| {
+            let max = c.into_iter().fold(0.0, f32::max);
+            c.map(|v| v / max)
+        };
+        let target = normalize(color);
+        let distance = |c: &[f32; 3]| {
+            let c = normalize(*c);
+            (0..3).map(|i| (c[i] - target[i]).powi(2)).sum::<f32>()
+        };
+        *self
+            .colors()
+            .iter()
+            .min_by(|a, b| distance(a).total_cmp(&distance(b)))
+            .unwrap_or(&color)
+    }
+}
@@ -1,11 +1,15 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    f64::consts::{PI, TAU},
+    path::PathBuf,
+};
 
-use cgmath::{Angle, Deg, InnerSpace, Point3, Rad, Vector3, Zero, num_traits::Pow};
+use cgmath::{Angle, Deg, InnerSpace, Point3, Rad, Vector3, Zero};
 
 use crate::{
     Object,
-    constants::{AU, G_ABS, M0},
-    sim::ObjectInfo,
+    constants::{AU, G_ABS, KEPLER_MAX_ITERATIONS, M0},
+    sim::{Maneuver, ObjectInfo, Oblateness, Radiation},
 };
 
 pub struct ConvertedOrbitalParams {
@@ -17,11 +21,22 @@ pub struct ConvertedOrbitalParams {
     color: Vector3<f32>,
     radius: f32,
     mass: f64,
+    oblateness: Option<Oblateness>,
+    radiation: Option<Radiation>,
+    maneuvers: Vec<Maneuver>,
+    mass_rate: f64,
+    texture: Option<PathBuf>,
     children_mass: f64,
     children_relative_momentum: Vector3<f64>,
     children: Vec<usize>,
 }
 
+impl ConvertedOrbitalParams {
+    pub fn parent_index(&self) -> Option<usize> {
+        self.parent_index
+    }
+}
+
 impl From<ConvertedOrbitalParams> for Object {
     fn from(value: ConvertedOrbitalParams) -> Self {
         Self {
@@ -30,9 +45,16 @@ impl From<ConvertedOrbitalParams> for Object {
                 pos: value.pos / AU,
                 vel: value.vel / AU,
                 mass: value.mass,
+                pinned: false,
+                test_particle: false,
             },
             color: value.color,
             radius: value.radius,
+            oblateness: value.oblateness,
+            radiation: value.radiation,
+            maneuvers: value.maneuvers,
+            mass_rate: value.mass_rate,
+            texture: value.texture,
         }
     }
 }
@@ -46,9 +68,10 @@ pub struct AbsoluteCoords {
 #[derive(Debug)]
 pub struct RelativeCoords {
     pub parent: String,
-    /// In meters
+    /// In meters. Negative for hyperbolic orbits, as is the convention. Parabolic orbits
+    /// have no finite semi-major axis, so for those this is the periapsis distance.
     pub semi_major_axis: f64,
-    /// [0, 1]
+    /// 0 for circular orbits, 1 for parabolic orbits, above 1 for hyperbolic orbits.
     pub eccentricity: f64,
     /// In degrees
     pub inclination: f64,
@@ -56,8 +79,104 @@ pub struct RelativeCoords {
     pub arg_periapsis: f64,
     /// In degrees
     pub long_asc_node: f64,
-    /// In degrees
-    pub true_an: f64,
+    pub anomaly: Anomaly,
+}
+
+/// Position of an object along its orbit.
+#[derive(Debug, Clone, Copy)]
+pub enum Anomaly {
+    /// True anomaly, in degrees.
+    True(f64),
+    /// Mean anomaly in degrees at some epoch, the way ephemeris services publish orbits.
+    /// It is moved along the orbit to the start of the simulation, `time_since_epoch`
+    /// seconds after the epoch.
+    Mean {
+        mean_anomaly: f64,
+        time_since_epoch: f64,
+    },
+}
+
+impl RelativeCoords {
+    /// `a (1 - e^2)`, or twice the periapsis distance for a parabolic orbit.
+    pub fn semi_latus_rectum(&self) -> f64 {
+        if self.eccentricity == 1.0 {
+            2.0 * self.semi_major_axis
+        } else {
+            self.semi_major_axis * (1.0 - self.eccentricity.powi(2))
+        }
+    }
+
+    /// True anomaly in degrees at the start of the simulation, for an orbit around a
+    /// parent with standard gravitational parameter `mu`, in m^3/s^2.
+    pub fn true_anomaly(&self, mu: f64) -> f64 {
+        match self.anomaly {
+            Anomaly::True(true_an) => true_an,
+            Anomaly::Mean {
+                mean_anomaly,
+                time_since_epoch,
+            } => {
+                let e = self.eccentricity;
+                let a = self.semi_major_axis.abs();
+                // Parabolic orbits are given by their periapsis, and follow Barker's equation.
+                let mean_motion = if e == 1.0 {
+                    (mu / (2.0 * a.powi(3))).sqrt()
+                } else {
+                    (mu / a.powi(3)).sqrt()
+                };
+                true_anomaly_from_mean(
+                    mean_anomaly.to_radians() + mean_motion * time_since_epoch,
+                    e,
+                )
+            }
+        }
+    }
+
+    /// Largest true anomaly reached, in degrees. Open orbits only reach towards the
+    /// direction of their asymptotes.
+    pub fn max_true_anomaly(&self) -> f64 {
+        if self.eccentricity < 1.0 {
+            180.0
+        } else {
+            (-1.0 / self.eccentricity).acos().to_degrees()
+        }
+    }
+}
+
+/// True anomaly in degrees for `mean_anomaly` in radians, on an orbit with eccentricity
+/// `e`, by solving Kepler's equation.
+pub fn true_anomaly_from_mean(mean_anomaly: f64, e: f64) -> f64 {
+    if e < 1.0 {
+        let mean = mean_anomaly.rem_euclid(TAU);
+        let mut ecc = if e > 0.8 { PI } else { mean };
+        for _ in 0..KEPLER_MAX_ITERATIONS {
+            let step = (ecc - e * ecc.sin() - mean) / (1.0 - e * ecc.cos());
+            ecc -= step;
+            if step.abs() < 1e-14 {
+                break;
+            }
+        }
+        let (sin, cos) = (ecc / 2.0).sin_cos();
+        (2.0 * f64::atan2((1.0 + e).sqrt() * sin, (1.0 - e).sqrt() * cos)).to_degrees()
+    } else if e == 1.0 {
+        // Barker's equation is a cubic in tan(v / 2), solved directly. The roots are
+        // combined as u - 1 / u, which loses no precision for large mean anomalies.
+        let s = 1.5 * mean_anomaly.abs();
+        let u = (s + (s * s + 1.0).sqrt()).cbrt();
+        let d = (u - 1.0 / u).copysign(mean_anomaly);
+        (2.0 * d.atan()).to_degrees()
+    } else {
+        // Hyperbolic anomaly, from a starting guess close enough that Newton's method
+        // converges for mean anomalies of any size.
+        let mut hyp = (mean_anomaly / e).asinh();
+        for _ in 0..KEPLER_MAX_ITERATIONS {
+            let step = (e * hyp.sinh() - hyp - mean_anomaly) / (e * hyp.cosh() - 1.0);
+            hyp -= step;
+            if step.abs() < 1e-14 * (1.0 + hyp.abs()) {
+                break;
+            }
+        }
+        (2.0 * (((e + 1.0) / (e - 1.0)).sqrt() * (hyp / 2.0).tanh()).atan()).to_degrees()
+    }
 }
 
 pub enum RelativeOrAbsolute {
@@ -71,6 +190,16 @@ pub struct StandardParams {
     pub mass: f64,
     pub radius: f32,
     pub color: [f32; 3],
+    /// Equatorial bulge, for bodies where it noticeably perturbs their satellites.
+    pub oblateness: Option<Oblateness>,
+    /// Radiation emitted or felt by the body, for non-gravitational forces.
+    pub radiation: Option<Radiation>,
+    /// Scripted burns, for spacecraft.
+    pub maneuvers: Vec<Maneuver>,
+    /// Steady change in mass, in kg/s.
+    pub mass_rate: f64,
+    /// Map of the surface, see `Object::texture`.
+    pub texture: Option<PathBuf>,
 }
 
 fn compute_from_orbital_params(
@@ -79,17 +208,11 @@ fn compute_from_orbital_params(
     mass: f64,
 ) -> AbsoluteCoords {
     let mu = G_ABS * (parent.mass * M0 + mass * M0);
-    let true_anom: Rad<f64> = Deg(coords.true_an).into();
-    let ecc_squared: f64 = coords.eccentricity.powi(2);
-    let ecc_anomaly = Rad(f64::atan2(
-        (1.0 - ecc_squared).sqrt() * true_anom.0.sin(),
-        coords.eccentricity + true_anom.0.cos(),
-    ));
-
-    let radius = coords.semi_major_axis * (1.0 - coords.eccentricity * ecc_anomaly.cos());
-    let angular_momentum_sq: f64 =
-        mu * coords.semi_major_axis * (1.0f64 - coords.eccentricity.pow(2));
-    let angular_momentum = angular_momentum_sq.sqrt();
+    let true_anom: Rad<f64> = Deg(coords.true_anomaly(mu)).into();
+    // The equation of a conic section, so that this works for open orbits too.
+    let p = coords.semi_latus_rectum();
+    let radius = p / (1.0 + coords.eccentricity * true_anom.cos());
+    let angular_momentum = (mu * p).sqrt();
     let l_an: Rad<f64> = Deg(coords.long_asc_node).into();
     let arg_per: Rad<f64> = Deg(coords.arg_periapsis).into();
     let inclination: Rad<f64> = Deg(coords.inclination).into();
@@ -99,9 +222,8 @@ fn compute_from_orbital_params(
         * (l_an.cos() * real_angle.cos() - l_an.sin() * real_angle.sin() * inclination.cos());
     let p_y = radius
         * (l_an.sin() * real_angle.cos() + l_an.cos() * real_angle.sin() * inclination.cos());
-    let p_z = radius * inclination.sin() * real_angle.cos();
+    let p_z = radius * inclination.sin() * real_angle.sin();
 
-    let p = coords.semi_major_axis * (1.0 - coords.eccentricity.pow(2));
     let velocity_basis = angular_momentum * coords.eccentricity / (radius * p) * true_anom.sin();
 
     let v_x = p_x * velocity_basis
@@ -113,25 +235,73 @@ fn compute_from_orbital_params(
     let v_z =
         p_z * velocity_basis + angular_momentum / radius * inclination.sin() * real_angle.cos();
 
-    println!("Angular momentum: {}", angular_momentum / radius);
-    println!("Radius: {radius}");
-    let v_vec = Vector3::new(v_x, v_y, v_z);
-    println!("Direction: {:?}", v_vec.normalize());
-    let p_vec = Point3::new(p_x, p_y, p_z);
-    println!("Vector to parent: {:?}", (parent.pos - p_vec).normalize());
-    println!("Velocity basis: {velocity_basis}");
-
-    println!(
-        "Velocity cross direction: {:?}",
-        (parent.pos - p_vec).normalize().cross(v_vec.normalize())
-    );
-
     AbsoluteCoords {
         pos: [p_x + parent.pos.x, p_y + parent.pos.y, p_z + parent.pos.z],
         vel: [v_x + parent.vel.x, v_y + parent.vel.y, v_z + parent.vel.z],
     }
 }
 
+/// Osculating orbit around `parent` of a body at `pos` in meters and `vel` in m/s
+/// relative to it, with standard gravitational parameter `mu` in m^3/s^2. The inverse of
+/// `compute_from_orbital_params`, with the same conventions. Angles that are undefined
+/// for circular or equatorial orbits are zero, and the rest measured from the x axis.
+pub fn compute_orbital_params(
+    parent: String,
+    mu: f64,
+    pos: Vector3<f64>,
+    vel: Vector3<f64>,
+) -> RelativeCoords {
+    let radius = pos.magnitude();
+    let h = pos.cross(vel);
+    let node = Vector3::new(-h.y, h.x, 0.0);
+    let ecc_vec = (pos * (vel.magnitude2() - mu / radius) - vel * pos.dot(vel)) / mu;
+    let eccentricity = ecc_vec.magnitude();
+    let energy = vel.magnitude2() / 2.0 - mu / radius;
+    // Parabolic orbits are given by their periapsis, see `RelativeCoords`.
+    let semi_major_axis = if energy == 0.0 {
+        h.magnitude2() / (2.0 * mu)
+    } else {
+        -mu / (2.0 * energy)
+    };
+
+    let normal = h.normalize();
+    // Angle from `a` to `b` around the orbit normal.
+    let angle = |a: Vector3<f64>, b: Vector3<f64>| {
+        Rad::atan2(normal.dot(a.cross(b)), a.dot(b))
+            .normalize()
+            .0
+            .to_degrees()
+    };
+    let equatorial = node.magnitude2() <= f64::EPSILON * h.magnitude2();
+    let circular = eccentricity <= 1e-12;
+    // Reference direction for the angles within the orbital plane.
+    let ascending = if equatorial { Vector3::unit_x() } else { node };
+    let arg_periapsis = if circular {
+        0.0
+    } else {
+        angle(ascending, ecc_vec)
+    };
+    let true_an = if circular {
+        angle(ascending, pos)
+    } else {
+        angle(ecc_vec, pos)
+    };
+
+    RelativeCoords {
+        parent,
+        semi_major_axis,
+        eccentricity,
+        inclination: (h.z / h.magnitude()).clamp(-1.0, 1.0).acos().to_degrees(),
+        arg_periapsis,
+        long_asc_node: if equatorial {
+            0.0
+        } else {
+            Rad::atan2(node.y, node.x).normalize().0.to_degrees()
+        },
+        anomaly: Anomaly::True(true_an),
+    }
+}
+
 fn apply_vdiff_rec(objects: &mut [ConvertedOrbitalParams], idx: usize, v_diff: Vector3<f64>) {
     let obj = &mut objects[idx];
     obj.vel -= v_diff;
@@ -140,6 +310,8 @@ fn apply_vdiff_rec(objects: &mut [ConvertedOrbitalParams], idx: usize, v_diff: V
     }
 }
 
+/// Place every object at absolute coordinates. Parents must be listed before their
+/// children, which `validate::check_params` makes sure of for scenarios from outside.
 pub fn convert_params(
     items: impl IntoIterator<Item = StandardParams>,
 ) -> Vec<ConvertedOrbitalParams> {
@@ -150,7 +322,9 @@ pub fn convert_params(
         let (absolute_coords, parent_idx) = match item.coordinates {
             RelativeOrAbsolute::Absolute(x) => (x, None),
             RelativeOrAbsolute::Relative(r) => {
-                let parent = map.get(&r.parent).expect("Parent not found");
+                let parent = map
+                    .get(&r.parent)
+                    .expect("Parent is listed before the object");
                 (
                     compute_from_orbital_params(parent, r, item.mass),
                     Some(parent.index),
@@ -158,10 +332,6 @@ pub fn convert_params(
             }
         };
 
-        println!(
-            "object: {:?}, {:?}, {:?}",
-            item.name, absolute_coords.pos, absolute_coords.vel
-        );
         let params = ConvertedOrbitalParams {
             name: item.name,
             index: idx,
@@ -171,6 +341,11 @@ pub fn convert_params(
             color: item.color.into(),
             radius: item.radius,
             mass: item.mass,
+            oblateness: item.oblateness,
+            radiation: item.radiation,
+            maneuvers: item.maneuvers,
+            mass_rate: item.mass_rate,
+            texture: item.texture,
             children_mass: 0.0,
             children_relative_momentum: Vector3::zero(),
             children: Vec::new(),
@@ -198,9 +373,14 @@ pub fn convert_params(
     // Note that we iterate in reverse. The construction above guarantees that
     // the input is already topologically sorted.
     for i in (0..final_vec.len()).rev() {
-        // Get the delta velocity to apply.
-        let v_diff = final_vec[i].children_relative_momentum
-            / (final_vec[i].mass + final_vec[i].children_mass);
+        // Get the delta velocity to apply. Massless systems, like test particles, have no
+        // momentum to make up for.
+        let system_mass = final_vec[i].mass + final_vec[i].children_mass;
+        let v_diff = if system_mass > 0.0 {
+            final_vec[i].children_relative_momentum / system_mass
+        } else {
+            Vector3::zero()
+        };
 
         // Recursively apply the delta-v.
         apply_vdiff_rec(&mut final_vec, i, v_diff);
@@ -0,0 +1,172 @@
+//! Full particle state written out as Parquet files, for analysis after the run.
+//!
+//! Every `every` steps the state of all objects is written to `snapshot-{tick}.parquet`
+//! in a directory, with the tick padded to ten digits so that the files sort in order.
+//! There is one row per object, in the order of the scenario. Positions are in
+//! AU, velocities in AU/s and masses in earth masses. The tick and simulated time of the
+//! snapshot are stored as key-value metadata of the file. In Python the files read
+//! straight into a data frame with `pandas.read_parquet`.
+//!
+//! Encoding a large system takes a while, so it is done on a thread of its own. The
+//! simulation only copies the state, and waits when the writer is still busy with the
+//! snapshot before the last.
+
+use std::{
+    fs::{self, File},
+    path::Path,
+    sync::{
+        Arc,
+        mpsc::{Receiver, SyncSender, sync_channel},
+    },
+    thread::JoinHandle,
+};
+
+use anyhow::Context;
+use parquet::{
+    data_type::{BoolType, DoubleType},
+    file::{metadata::KeyValue, properties::WriterProperties, writer::SerializedFileWriter},
+    schema::{parser::parse_message_type, types::Type},
+};
+
+use crate::{
+    constants::{PARTICLE_SNAPSHOT_ROW_GROUP_ROWS, PARTICLE_SNAPSHOT_THREAD_NAME},
+    sim::{ObjectInfo, SimTime},
+};
+
+const SCHEMA: &str = "
+message snapshot {
+    REQUIRED DOUBLE x;
+    REQUIRED DOUBLE y;
+    REQUIRED DOUBLE z;
+    REQUIRED DOUBLE vx;
+    REQUIRED DOUBLE vy;
+    REQUIRED DOUBLE vz;
+    REQUIRED DOUBLE mass;
+    REQUIRED BOOLEAN test_particle;
+    REQUIRED BOOLEAN pinned;
+}
+";
+
+/// Parquet snapshots of every object, taken every `every` simulation steps.
+pub struct ParticleSnapshots {
+    every: u64,
+    sender: Option<SyncSender<(SimTime, Vec<ObjectInfo>)>>,
+    writer: Option<JoinHandle<anyhow::Result<()>>>,
+}
+
+impl ParticleSnapshots {
+    /// Write snapshots into `dir` every `every` steps, creating it if needed.
+    pub fn create(dir: impl AsRef<Path>, every: u64) -> anyhow::Result<Self> {
+        let dir = dir.as_ref().to_owned();
+        fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        let schema = Arc::new(parse_message_type(SCHEMA)?);
+        let (sender, receiver) = sync_channel(1);
+        let writer = std::thread::Builder::new()
+            .name(PARTICLE_SNAPSHOT_THREAD_NAME.to_owned())
+            .spawn(move || write_snapshots(&dir, schema, receiver))?;
+        Ok(Self {
+            every: every.max(1),
+            sender: Some(sender),
+            writer: Some(writer),
+        })
+    }
+
+    /// Queue a snapshot of `objects`, if this is one of the steps to write. Fails if
+    /// writing has failed since the last call.
+    pub fn capture(&mut self, time: SimTime, objects: &[ObjectInfo]) -> anyhow::Result<()> {
+        if !time.ticks.is_multiple_of(self.every) {
+            return Ok(());
+        }
+        let sent = self
+            .sender
+            .as_ref()
+            .is_some_and(|sender| sender.send((time, objects.to_vec())).is_ok());
+        if sent { Ok(()) } else { self.flush() }
+    }
+
+    /// Wait for the queued snapshots to be written, and stop taking more.
+    pub fn flush(&mut self) -> anyhow::Result<()> {
+        self.sender = None;
+        match self.writer.take() {
+            Some(writer) => writer
+                .join()
+                .map_err(|_| anyhow::anyhow!("Snapshot writer panicked"))?,
+            None => anyhow::bail!("Snapshots are closed"),
+        }
+    }
+}
+
+/// Write a single snapshot of `objects` to `path`, in the same format as the series.
+pub fn write_single(path: &Path, time: SimTime, objects: &[ObjectInfo]) -> anyhow::Result<()> {
+    write_snapshot(path, Arc::new(parse_message_type(SCHEMA)?), time, objects)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Write every snapshot from `receiver` to its own file in `dir`, until the simulation
+/// hangs up.
+fn write_snapshots(
+    dir: &Path,
+    schema: Arc<Type>,
+    receiver: Receiver<(SimTime, Vec<ObjectInfo>)>,
+) -> anyhow::Result<()> {
+    for (time, objects) in receiver {
+        let path = dir.join(format!("snapshot-{:010}.parquet", time.ticks));
+        write_snapshot(&path, schema.clone(), time, &objects)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+    Ok(())
+}
+
+fn write_snapshot(
+    path: &Path,
+    schema: Arc<Type>,
+    time: SimTime,
+    objects: &[ObjectInfo],
+) -> anyhow::Result<()> {
+    let props = WriterProperties::builder()
+        .set_key_value_metadata(Some(vec![
+            KeyValue::new("tick".to_owned(), time.ticks.to_string()),
+            KeyValue::new("time_s".to_owned(), time.seconds().to_string()),
+        ]))
+        .build();
+    let mut writer = SerializedFileWriter::new(File::create(path)?, schema, Arc::new(props))?;
+    for chunk in objects.chunks(PARTICLE_SNAPSHOT_ROW_GROUP_ROWS) {
+        let doubles = |f: fn(&ObjectInfo) -> f64| chunk.iter().map(f).collect::<Vec<_>>();
+        let bools = |f: fn(&ObjectInfo) -> bool| chunk.iter().map(f).collect::<Vec<_>>();
+        let mut columns = [
+            doubles(|o| o.pos.x),
+            doubles(|o| o.pos.y),
+            doubles(|o| o.pos.z),
+            doubles(|o| o.vel.x),
+            doubles(|o| o.vel.y),
+            doubles(|o| o.vel.z),
+            doubles(|o| o.mass),
+        ]
+        .into_iter();
+        let mut flags = [bools(|o| o.test_particle), bools(|o| o.pinned)].into_iter();
+
+        let mut row_group = writer.next_row_group()?;
+        while let Some(mut column) = row_group.next_column()? {
+            // The doubles come first in the schema, then the flags.
+            match columns.next() {
+                Some(values) => {
+                    column
+                        .typed::<DoubleType>()
+                        .write_batch(&values, None, None)?;
+                }
+                None => {
+                    let values = flags
+                        .next()
+                        .context("Schema has more columns than written")?;
+                    column
+                        .typed::<BoolType>()
+                        .write_batch(&values, None, None)?;
+                }
+            }
+            column.close()?;
+        }
+        row_group.close()?;
+    }
+    writer.close()?;
+    Ok(())
+}
@@ -0,0 +1,63 @@
+//! Rendering options compiled into the shaders.
+//!
+//! Options that change what a shader computes, rather than the values it computes with,
+//! are compiled into a separate entry point for each combination, see `permutations!` in
+//! the shaders crate. Each pipeline keeps one `RenderPipeline` per set of options it has
+//! been drawn with, built the first time the set is used.
+
+use std::collections::HashMap;
+
+use wgpu::RenderPipeline;
+
+use crate::pipeline_cache;
+
+/// Set of options that select a shader permutation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct ShaderOptions {
+    /// Draw positions relative to the target object.
+    pub relative_position: bool,
+}
+
+impl ShaderOptions {
+    /// Name of the entry point generated for these options from the entry point `base`.
+    /// Entry points without permutations, like the fragment shaders, keep `base`.
+    pub fn entry_point(&self, base: &str) -> String {
+        let mut name = base.to_owned();
+        if self.relative_position {
+            name.push_str("_relative");
+        }
+        name
+    }
+}
+
+/// Pipelines of a single kind, one for each set of options.
+pub struct PipelineCache {
+    pipelines: HashMap<ShaderOptions, RenderPipeline>,
+}
+
+impl PipelineCache {
+    pub fn new() -> Self {
+        Self {
+            pipelines: HashMap::new(),
+        }
+    }
+
+    /// Build the pipeline for `options` with `build`, unless there already is one.
+    pub fn prepare(
+        &mut self,
+        options: ShaderOptions,
+        build: impl FnOnce(ShaderOptions) -> RenderPipeline,
+    ) {
+        self.pipelines.entry(options).or_insert_with(|| {
+            pipeline_cache::mark_built();
+            build(options)
+        });
+    }
+
+    /// Pipeline for `options`, which must have been prepared.
+    pub fn get(&self, options: ShaderOptions) -> &RenderPipeline {
+        self.pipelines
+            .get(&options)
+            .expect("Pipeline drawn with options it was not prepared for")
+    }
+}
@@ -2,28 +2,34 @@ use std::ops::Range;
 
 use wgpu::{
     BindGroup, BindGroupLayout, BlendComponent, BlendFactor, BlendState, Buffer, Device,
-    PipelineCompilationOptions, PipelineLayoutDescriptor, PrimitiveState, RenderPass,
-    RenderPipeline, RenderPipelineDescriptor, TextureFormat, util::DeviceExt,
+    PipelineCompilationOptions, PipelineLayout, PipelineLayoutDescriptor, PrimitiveState,
+    RenderPass, RenderPipeline, RenderPipelineDescriptor, TextureFormat, util::DeviceExt,
 };
 
 use crate::{
     ShaderConstants,
-    constants::TRAIL_MAX_LENGTH,
     objects::{ObjectInstance, Vertex},
-    render::get_or_init_shader,
+    permutations::{PipelineCache, ShaderOptions},
+    pipeline_cache,
+    render::{depth_stencil_state, get_or_init_shader},
 };
 
 pub(crate) struct LineDrawPipeline {
     index_buffer: Buffer,
-    pipeline: RenderPipeline,
+    layout: PipelineLayout,
+    texture_format: TextureFormat,
+    sample_count: u32,
+    pipelines: PipelineCache,
 }
 
 impl LineDrawPipeline {
     pub fn new(
         device: &Device,
         texture_format: TextureFormat,
+        sample_count: u32,
         camera_layout: &BindGroupLayout,
         num_objects: usize,
+        trail_length: usize,
     ) -> Self {
         let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
             label: None,
@@ -34,35 +40,58 @@ impl LineDrawPipeline {
             }],
         });
 
-        let mut index_list: Vec<u32> = Vec::with_capacity(TRAIL_MAX_LENGTH * 2);
+        let index_buffer = Self::index_buffer(device, num_objects, trail_length);
 
-        for _ in 0..2 {
-            for i in 0..TRAIL_MAX_LENGTH {
-                index_list.push((i * num_objects) as u32);
-            }
-        }
+        let mut pipeline = Self {
+            index_buffer,
+            layout: pipeline_layout,
+            texture_format,
+            sample_count,
+            pipelines: PipelineCache::new(),
+        };
+        pipeline.prepare(device, ShaderOptions::default());
+        pipeline
+    }
 
-        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Index Buffer"),
-            contents: bytemuck::cast_slice(&index_list),
-            usage: wgpu::BufferUsages::INDEX,
+    /// Build the pipeline for `options`, if it has not been built yet.
+    pub fn prepare(&mut self, device: &Device, options: ShaderOptions) {
+        let (layout, texture_format, sample_count) =
+            (&self.layout, self.texture_format, self.sample_count);
+        self.pipelines.prepare(options, |options| {
+            Self::build(device, layout, texture_format, sample_count, options)
         });
+    }
+
+    /// Render to targets with `sample_count` samples, rebuilding the pipelines if it changed.
+    pub fn set_sample_count(&mut self, sample_count: u32) {
+        if sample_count != self.sample_count {
+            self.sample_count = sample_count;
+            self.pipelines = PipelineCache::new();
+        }
+    }
 
+    fn build(
+        device: &Device,
+        layout: &PipelineLayout,
+        texture_format: TextureFormat,
+        sample_count: u32,
+        options: ShaderOptions,
+    ) -> RenderPipeline {
         let shader_module = get_or_init_shader(device);
-        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+        device.create_render_pipeline(&RenderPipelineDescriptor {
             label: Some("line pipeline"),
-            layout: Some(&pipeline_layout),
+            layout: Some(layout),
             vertex: wgpu::VertexState {
                 module: shader_module,
-                entry_point: Some("line_vs"),
+                entry_point: Some(&options.entry_point("line_vs")),
                 buffers: &[
-                    Vertex::layout::<true, 0>(),
-                    ObjectInstance::layout::<2>(),
-                    Vertex::layout::<true, 4>(),
+                    Vertex::trail_layout::<0>(),
+                    ObjectInstance::layout::<4>(),
+                    Vertex::layout::<true, 6>(),
                 ],
                 compilation_options: PipelineCompilationOptions::default(),
             },
-            cache: None,
+            cache: pipeline_cache::get(),
             primitive: PrimitiveState {
                 topology: wgpu::PrimitiveTopology::LineStrip,
                 strip_index_format: None,
@@ -72,9 +101,9 @@ impl LineDrawPipeline {
                 polygon_mode: wgpu::PolygonMode::Fill,
                 conservative: false,
             },
-            depth_stencil: None,
+            depth_stencil: Some(depth_stencil_state(true)),
             multisample: wgpu::MultisampleState {
-                count: 1,
+                count: sample_count,
                 mask: !0,
                 alpha_to_coverage_enabled: false,
             },
@@ -96,12 +125,35 @@ impl LineDrawPipeline {
                 compilation_options: PipelineCompilationOptions::default(),
             }),
             multiview: None,
-        });
+        })
+    }
 
-        Self {
-            pipeline,
-            index_buffer,
+    /// Indices of the samples of the first object's trail, twice over, so that a range
+    /// wrapping around the end of the ring buffer can be drawn in one go.
+    fn index_buffer(device: &Device, num_objects: usize, trail_length: usize) -> Buffer {
+        let mut index_list: Vec<u32> = Vec::with_capacity(trail_length * 2);
+
+        for _ in 0..2 {
+            for i in 0..trail_length {
+                index_list.push((i * num_objects) as u32);
+            }
         }
+
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Index Buffer"),
+            contents: bytemuck::cast_slice(&index_list),
+            usage: wgpu::BufferUsages::INDEX,
+        })
+    }
+
+    /// Draw trails of a different length from now on.
+    pub fn set_trail_length(&mut self, device: &Device, num_objects: usize, trail_length: usize) {
+        self.index_buffer = Self::index_buffer(device, num_objects, trail_length);
+    }
+
+    /// Bytes allocated on the GPU.
+    pub fn memory_usage(&self) -> u64 {
+        self.index_buffer.size()
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -112,11 +164,12 @@ impl LineDrawPipeline {
         buffer: &Buffer,
         instance_buffer: &Buffer,
         push_constants: &ShaderConstants,
+        options: ShaderOptions,
         index_range: Range<u32>,
-        num_objects: usize,
+        trails_shown: &[bool],
         target_object: Option<usize>,
     ) {
-        rpass.set_pipeline(&self.pipeline);
+        rpass.set_pipeline(self.pipelines.get(options));
         rpass.set_vertex_buffer(0, buffer.slice(..));
         rpass.set_vertex_buffer(1, instance_buffer.slice(..));
         if let Some(target) = target_object {
@@ -139,7 +192,7 @@ impl LineDrawPipeline {
 
         if target_object.is_some() {
             // re-bind the vertex buffer for each object, since we can't use base_vertex.
-            for idx in 0..num_objects {
+            for idx in (0..trails_shown.len()).filter(|idx| trails_shown[*idx]) {
                 let idxu = idx as u32;
                 rpass.set_vertex_buffer(
                     0,
@@ -149,7 +202,7 @@ impl LineDrawPipeline {
                 rpass.draw_indexed(index_range.clone(), 0, idxu..(idxu + 1));
             }
         } else {
-            for idx in 0..num_objects {
+            for idx in (0..trails_shown.len()).filter(|idx| trails_shown[*idx]) {
                 let idxu = idx as u32;
 
                 rpass.draw_indexed(index_range.clone(), idx as i32, idxu..(idxu + 1));
@@ -0,0 +1,114 @@
+//! Compiled pipelines kept on disk between runs.
+//!
+//! Building a pipeline compiles its shaders for the GPU, and every shader permutation is
+//! a pipeline of its own, see `permutations`. Where the backend supports it, which is
+//! only Vulkan for now, the compiled pipelines are saved to a file in the cache directory
+//! of the user, and handed back to the driver on the next launch so that it can skip
+//! compiling them again.
+
+use std::{
+    fs,
+    path::PathBuf,
+    sync::{
+        OnceLock,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use wgpu::{Adapter, AdapterInfo, Device, Features, PipelineCache, PipelineCacheDescriptor};
+
+use crate::constants::PIPELINE_CACHE_DIR;
+
+static CACHE: OnceLock<Option<DiskCache>> = OnceLock::new();
+
+struct DiskCache {
+    cache: PipelineCache,
+    path: PathBuf,
+    /// Whether pipelines have been built since the cache was last saved.
+    dirty: AtomicBool,
+}
+
+/// Features to request for the pipeline cache, where `adapter` supports it.
+pub fn features(adapter: &Adapter) -> Features {
+    adapter.features() & Features::PIPELINE_CACHE
+}
+
+/// Load the cache for `device`, created on the adapter described by `info`. As with the
+/// shader module, only the first device gets a cache.
+pub(crate) fn init(device: &Device, info: &AdapterInfo) {
+    CACHE.get_or_init(|| load(device, info));
+}
+
+fn load(device: &Device, info: &AdapterInfo) -> Option<DiskCache> {
+    if !device.features().contains(Features::PIPELINE_CACHE) {
+        return None;
+    }
+    let path = cache_dir()?.join(wgpu::util::pipeline_cache_key(info)?);
+    // A missing or unreadable file just means compiling everything once more.
+    let data = fs::read(&path).ok();
+    // SAFETY: The data was saved from a cache for an adapter with the same key, which
+    // names the vendor and device. The driver checks its version against the header,
+    // and with `fallback` a cache that does not match starts out empty instead.
+    let cache = unsafe {
+        device.create_pipeline_cache(&PipelineCacheDescriptor {
+            label: Some("pipeline cache"),
+            data: data.as_deref(),
+            fallback: true,
+        })
+    };
+    Some(DiskCache {
+        cache,
+        path,
+        dirty: AtomicBool::new(false),
+    })
+}
+
+/// The cache to build pipelines with, if there is one.
+pub(crate) fn get() -> Option<&'static PipelineCache> {
+    CACHE.get()?.as_ref().map(|disk| &disk.cache)
+}
+
+/// Note that a pipeline was built, so that the cache is saved again.
+pub(crate) fn mark_built() {
+    if let Some(Some(disk)) = CACHE.get() {
+        disk.dirty.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Write the cache to disk, if pipelines have been built since it was last written.
+pub(crate) fn save() -> anyhow::Result<()> {
+    let Some(Some(disk)) = CACHE.get() else {
+        return Ok(());
+    };
+    if !disk.dirty.swap(false, Ordering::Relaxed) {
+        return Ok(());
+    }
+    let Some(data) = disk.cache.get_data() else {
+        return Ok(());
+    };
+    if let Some(dir) = disk.path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    // Replace the file in one go, so that a crash while writing leaves the old cache.
+    let tmp = disk.path.with_extension("tmp");
+    fs::write(&tmp, data)?;
+    fs::rename(&tmp, &disk.path)?;
+    Ok(())
+}
+
+/// Directory for the cache, in the usual place for caches on each platform.
+fn cache_dir() -> Option<PathBuf> {
+    let var = |name| {
+        std::env::var_os(name)
+            .filter(|v| !v.is_empty())
+            .map(PathBuf::from)
+    };
+    let base = if cfg!(windows) {
+        var("LOCALAPPDATA")
+    } else if cfg!(target_os = "macos") {
+        var("HOME").map(|home| home.join("Library/Caches"))
+    } else {
+        var("XDG_CACHE_HOME").or_else(|| var("HOME").map(|home| home.join(".cache")))
+    };
+    Some(base?.join(PIPELINE_CACHE_DIR))
+}
@@ -0,0 +1,181 @@
+//! Maps of the surfaces of objects, drawn on them when they are close enough to see.
+//!
+//! Every map is resampled to `PLANET_TEXTURE_SIZE` and stored as a layer of a single
+//! texture array, so all objects are drawn with the same bind group. Objects refer to
+//! their layer in `ObjectInstance::texture`.
+
+use std::{collections::HashMap, path::PathBuf};
+
+use wgpu::{BindGroup, BindGroupLayout, Device, Extent3d, Queue, Texture};
+
+use crate::{
+    constants::PLANET_TEXTURE_SIZE, objects::Objects, render::texture_memory, snapshot::Image,
+};
+
+pub(crate) struct PlanetTextures {
+    layout: BindGroupLayout,
+    bind_group: BindGroup,
+    texture: Texture,
+    /// Pixels of every layer, until they are uploaded.
+    pending: Option<Vec<u8>>,
+}
+
+impl PlanetTextures {
+    /// Load the maps of `objects`, one layer for each file, and point every object at its
+    /// layer. Maps that fail to load are left out, and the objects drawn without them.
+    pub fn new(device: &Device, objects: &mut Objects) -> Self {
+        let (width, height) = PLANET_TEXTURE_SIZE;
+        let mut layers: HashMap<PathBuf, u32> = HashMap::new();
+        let mut pixels = Vec::new();
+        for idx in 0..objects.num_objects() {
+            let Some(path) = objects.objects()[idx].texture.clone() else {
+                continue;
+            };
+            let layer = match layers.get(&path) {
+                Some(layer) => *layer,
+                None => {
+                    let layer = match Image::load_ppm(&path) {
+                        Ok(image) => {
+                            Self::resample(&image, &mut pixels);
+                            (pixels.len() / (width * height * 4) as usize) as u32
+                        }
+                        Err(e) => {
+                            println!("Failed to load texture: {e:#}");
+                            0
+                        }
+                    };
+                    layers.insert(path, layer);
+                    layer
+                }
+            };
+            objects.set_texture(idx, layer);
+        }
+
+        let num_layers = (pixels.len() / (width * height * 4) as usize) as u32;
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("planet textures"),
+            // A single pixel when there are no maps, since the array can't be empty.
+            size: if num_layers == 0 {
+                Extent3d {
+                    width: 1,
+                    height: 1,
+                    depth_or_array_layers: 1,
+                }
+            } else {
+                Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: num_layers,
+                }
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("planet texture sampler"),
+            // Longitude wraps around, latitude stops at the poles.
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("planet texture layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("planet textures"),
+            layout: &layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+        });
+
+        Self {
+            layout,
+            bind_group,
+            texture,
+            pending: (num_layers > 0).then_some(pixels),
+        }
+    }
+
+    /// Append `image` as RGBA pixels of a layer of `PLANET_TEXTURE_SIZE`.
+    fn resample(image: &Image, out: &mut Vec<u8>) {
+        let (width, height) = PLANET_TEXTURE_SIZE;
+        for y in 0..height {
+            for x in 0..width {
+                let color = image.sample(
+                    (x as f32 + 0.5) / width as f32,
+                    (y as f32 + 0.5) / height as f32,
+                );
+                out.extend(color.map(|c| (c * 255.0).round() as u8));
+                out.push(255);
+            }
+        }
+    }
+
+    pub fn layout(&self) -> &BindGroupLayout {
+        &self.layout
+    }
+
+    pub fn bind_group(&self) -> &BindGroup {
+        &self.bind_group
+    }
+
+    /// Copy the maps to the GPU, if they haven't been yet.
+    pub fn upload(&mut self, queue: &Queue) {
+        let Some(pixels) = self.pending.take() else {
+            return;
+        };
+        let size = self.texture.size();
+        queue.write_texture(
+            self.texture.as_image_copy(),
+            &pixels,
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(size.width * 4),
+                rows_per_image: Some(size.height),
+            },
+            size,
+        );
+    }
+
+    /// Bytes allocated on the GPU.
+    pub fn memory_usage(&self) -> u64 {
+        texture_memory(&self.texture)
+    }
+}
@@ -0,0 +1,250 @@
+//! Sampled positions streamed over WebSocket, for dashboards and web front-ends.
+//!
+//! A client is first sent a text message with a JSON description of the objects, their
+//! names and colors in the order of the scenario. Every sample after that is a binary
+//! message, little endian: the tick as a `u64`, the simulated seconds as an `f64`, the
+//! number of objects as a `u32` and then the position of each in AU as three `f32`s.
+//! Objects injected during the run are in the description from the start, parked where
+//! they will enter. When the number of objects or their names change, like when a body
+//! is added through the control API, the description is sent again before the next
+//! sample, so every sample matches the last description.
+//!
+//! Samples are sent on a thread of their own, at most `STREAM_MAX_RATE` a second. While
+//! that thread is busy, new samples are dropped rather than holding up the simulation.
+//! Clients that take longer than `STREAM_TIMEOUT_MS` to receive a sample are dropped.
+//! Each client completes the handshake on a thread of its own, so a slow one does not
+//! hold up the others.
+
+use std::{
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        Arc, Mutex,
+        mpsc::{Receiver, SyncSender, sync_channel},
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use cgmath::Vector3;
+use tungstenite::{Message, WebSocket};
+
+use crate::{
+    Object,
+    constants::{STREAM_MAX_RATE, STREAM_THREAD_NAME, STREAM_TIMEOUT_MS},
+    sim::{ObjectInfo, SimTime},
+};
+
+/// JSON description of the objects, numbered so clients can tell which one they have.
+struct Description {
+    version: u64,
+    text: String,
+}
+
+impl Description {
+    fn new(version: u64, objects: &[(String, Vector3<f32>)]) -> Arc<Self> {
+        let text = serde_json::json!({
+            "objects": objects
+                .iter()
+                .map(|(name, color)| serde_json::json!({
+                    "name": name,
+                    "color": [color.x, color.y, color.z],
+                }))
+                .collect::<Vec<_>>(),
+        })
+        .to_string();
+        Arc::new(Self { version, text })
+    }
+}
+
+struct Client {
+    socket: WebSocket<TcpStream>,
+    /// Version of the last description sent to the client.
+    described: u64,
+}
+
+impl Client {
+    fn describe(&mut self, description: &Description) -> anyhow::Result<()> {
+        self.socket
+            .send(Message::text(description.text.clone()))
+            .context("Failed to send the description")?;
+        self.described = description.version;
+        Ok(())
+    }
+
+    /// Send `sample`, after `description` if the client has another one.
+    fn send(&mut self, description: &Description, sample: Message) -> anyhow::Result<()> {
+        if self.described != description.version {
+            self.describe(description)?;
+        }
+        self.socket.send(sample).context("Failed to send a sample")
+    }
+}
+
+type Clients = Arc<Mutex<Vec<Client>>>;
+
+/// Server sending the positions of every sample to the clients connected to it.
+pub struct PositionStream {
+    clients: Clients,
+    sender: SyncSender<(Arc<Description>, Vec<u8>)>,
+    last_sent: Option<Instant>,
+    /// Name and color of every object.
+    objects: Vec<(String, Vector3<f32>)>,
+    /// Description of `objects`, also given to clients as they connect.
+    description: Arc<Mutex<Arc<Description>>>,
+    /// Whether `objects` changed since `description` was made.
+    changed: bool,
+}
+
+impl PositionStream {
+    /// Listen for clients on `addr`, describing `objects` to each as it connects.
+    pub fn bind(addr: impl ToSocketAddrs, objects: &[Object]) -> anyhow::Result<Self> {
+        let listener = TcpListener::bind(addr).context("Failed to listen for stream clients")?;
+        let objects: Vec<_> = objects
+            .iter()
+            .map(|obj| (obj.name.clone(), obj.color))
+            .collect();
+        let description = Arc::new(Mutex::new(Description::new(0, &objects)));
+
+        let clients = Clients::default();
+        let accepted = clients.clone();
+        let described = description.clone();
+        std::thread::Builder::new()
+            .name(format!("{STREAM_THREAD_NAME}-accept"))
+            .spawn(move || accept_clients(listener, described, accepted))?;
+        let (sender, receiver) = sync_channel(1);
+        let sending = clients.clone();
+        std::thread::Builder::new()
+            .name(STREAM_THREAD_NAME.to_owned())
+            .spawn(move || send_samples(receiver, sending))?;
+        Ok(Self {
+            clients,
+            sender,
+            last_sent: None,
+            objects,
+            description,
+            changed: false,
+        })
+    }
+
+    /// Give the object at `index` a new name, sent to the clients with the next sample.
+    pub fn rename(&mut self, index: usize, name: &str) {
+        if let Some((old, _)) = self.objects.get_mut(index)
+            && old != name
+        {
+            *old = name.to_owned();
+            self.changed = true;
+        }
+    }
+
+    /// Send the positions of `objects` to every client, unless a sample was sent too
+    /// recently or the last one is still being sent.
+    pub fn publish(&mut self, time: SimTime, objects: &[ObjectInfo]) {
+        if objects.len() != self.objects.len() {
+            let described = self.objects.len();
+            self.objects.truncate(objects.len());
+            self.objects.extend(
+                (described..objects.len())
+                    .map(|idx| (format!("object_{idx}"), Vector3::new(1.0, 1.0, 1.0))),
+            );
+            self.changed = true;
+        }
+        if self.changed {
+            let mut description = self.description.lock().unwrap();
+            *description = Description::new(description.version + 1, &self.objects);
+            self.changed = false;
+        }
+
+        let too_soon = self
+            .last_sent
+            .is_some_and(|last| last.elapsed().as_secs_f64() < 1.0 / STREAM_MAX_RATE);
+        // The clients are locked while a sample is sent to them, which skips this one.
+        let listened_to = self
+            .clients
+            .try_lock()
+            .is_ok_and(|clients| !clients.is_empty());
+        if too_soon || !listened_to {
+            return;
+        }
+        self.last_sent = Some(Instant::now());
+        let mut sample = Vec::with_capacity(20 + objects.len() * 12);
+        sample.extend_from_slice(&time.ticks.to_le_bytes());
+        sample.extend_from_slice(&time.seconds().to_le_bytes());
+        sample.extend_from_slice(&(objects.len() as u32).to_le_bytes());
+        for obj in objects {
+            for v in [obj.pos.x, obj.pos.y, obj.pos.z] {
+                sample.extend_from_slice(&(v as f32).to_le_bytes());
+            }
+        }
+        let description = self.description.lock().unwrap().clone();
+        // Fails when the last sample has not been picked up yet, and this one is skipped.
+        let _ = self.sender.try_send((description, sample));
+    }
+}
+
+/// Accept every client that connects, completing the handshake on a thread of its own.
+fn accept_clients(
+    listener: TcpListener,
+    description: Arc<Mutex<Arc<Description>>>,
+    clients: Clients,
+) {
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                println!("Failed to accept stream client: {e}");
+                continue;
+            }
+        };
+        let description = description.clone();
+        let clients = clients.clone();
+        let spawned = std::thread::Builder::new()
+            .name(format!("{STREAM_THREAD_NAME}-handshake"))
+            .spawn(move || match handshake(stream, &description) {
+                Ok((peer, client)) => {
+                    println!("Streaming positions to {peer}");
+                    clients.lock().unwrap().push(client);
+                }
+                Err(e) => println!("Failed to accept stream client: {e:#}"),
+            });
+        if let Err(e) = spawned {
+            println!("Failed to accept stream client: {e}");
+        }
+    }
+}
+
+/// Complete the handshake with a client, and send it the current description.
+fn handshake(
+    stream: TcpStream,
+    description: &Mutex<Arc<Description>>,
+) -> anyhow::Result<(SocketAddr, Client)> {
+    let timeout = Some(Duration::from_millis(STREAM_TIMEOUT_MS));
+    stream.set_read_timeout(timeout)?;
+    stream.set_write_timeout(timeout)?;
+    let peer = stream.peer_addr()?;
+    let socket = tungstenite::accept(stream)
+        .map_err(|e| anyhow::anyhow!("Handshake with {peer} failed: {e}"))?;
+    let mut client = Client {
+        socket,
+        described: 0,
+    };
+    let description = description.lock().unwrap().clone();
+    client.describe(&description)?;
+    Ok((peer, client))
+}
+
+/// Send every sample from `receiver` to all clients, along with its description to those
+/// that have another, dropping those that fail, until the simulation hangs up.
+fn send_samples(receiver: Receiver<(Arc<Description>, Vec<u8>)>, clients: Clients) {
+    for (description, sample) in receiver {
+        let message = Message::binary(sample);
+        clients.lock().unwrap().retain_mut(|client| {
+            match client.send(&description, message.clone()) {
+                Ok(()) => true,
+                Err(e) => {
+                    println!("Stopped streaming to a client: {e:#}");
+                    false
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,324 @@
+//! Presets looked up by name, so the command line and scenario scripts can list and
+//! generate them without a case for each.
+//!
+//! Every preset takes a map of numeric parameters, each with a default, so a preset is
+//! generated from just its name or with some of them changed, like `plummer` with
+//! `stars = 1000`. Lengths are in AU unless the name of the parameter says otherwise.
+//!
+//! Presets of stars with `min_mass` and `max_mass` draw their masses, in solar masses,
+//! from the Kroupa IMF between the two, or give every star the same mass if they are
+//! equal.
+
+use std::collections::BTreeMap;
+
+use anyhow::{bail, ensure};
+use cgmath::Vector3;
+
+use crate::{
+    AnalyticPotential,
+    constants::{PARSEC, SOLAR_MASS},
+    presets::{self, DiskGalaxy, GalaxyCollision, Imf, Scenario},
+    rng::RngService,
+};
+
+/// Parameters of a preset by name. Those left out take their defaults.
+pub type PresetParams = BTreeMap<String, f64>;
+
+/// A preset that can be generated by name.
+pub struct PresetEntry {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Parameters the preset takes, with their defaults.
+    pub params: &'static [(&'static str, f64)],
+    /// Generate the preset, given every one of `params`.
+    generate: fn(&PresetParams, &RngService) -> anyhow::Result<Scenario>,
+}
+
+impl PresetEntry {
+    /// Generate the preset with `params`, taking the defaults for those left out.
+    pub fn generate(&self, params: &PresetParams, rng: &RngService) -> anyhow::Result<Scenario> {
+        let mut all: PresetParams = self
+            .params
+            .iter()
+            .map(|(name, default)| (name.to_string(), *default))
+            .collect();
+        for (name, value) in params {
+            let Some(slot) = all.get_mut(name) else {
+                bail!(
+                    "Preset {} has no parameter {name}, it takes {}",
+                    self.name,
+                    self.param_names()
+                );
+            };
+            *slot = *value;
+        }
+        (self.generate)(&all, rng)
+    }
+
+    fn param_names(&self) -> String {
+        if self.params.is_empty() {
+            return "none".to_owned();
+        }
+        self.params
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Every preset that can be generated by name.
+pub static PRESETS: &[PresetEntry] = &[
+    PresetEntry {
+        name: "earth_sun_mars",
+        description: "The sun with earth and mars on their orbits",
+        params: &[],
+        generate: |_, _| Ok(Scenario::Params(presets::earth_sun_mars_params())),
+    },
+    PresetEntry {
+        name: "earth_sun_mars_ast",
+        description: "Earth, sun and mars with an asteroid belt of test particles",
+        params: &[],
+        generate: |_, rng| Ok(Scenario::Objects(presets::earth_sun_mars_ast(rng))),
+    },
+    PresetEntry {
+        name: "hohmann_to_mars",
+        description: "A spacecraft on a Hohmann transfer from earth to mars",
+        params: &[],
+        generate: |_, _| Ok(Scenario::Objects(presets::hohmann_to_mars())),
+    },
+    PresetEntry {
+        name: "plummer",
+        description: "Star cluster with a Plummer profile, in equilibrium",
+        params: &[
+            ("stars", 300.0),
+            ("radius", 100.0),
+            ("min_mass", 1.0),
+            ("max_mass", 1.0),
+        ],
+        generate: |params, rng| {
+            Ok(Scenario::Objects(presets::plummer_cluster(
+                count(params, "stars")?,
+                positive(params, "radius")?,
+                imf(params)?,
+                rng,
+            )))
+        },
+    },
+    PresetEntry {
+        name: "king",
+        description: "Star cluster following a King model, in equilibrium",
+        params: &[
+            ("stars", 300.0),
+            ("radius", 100.0),
+            ("w0", 6.0),
+            ("min_mass", 1.0),
+            ("max_mass", 1.0),
+        ],
+        generate: |params, rng| {
+            let w0 = positive(params, "w0")?;
+            ensure!(w0 <= 20.0, "w0 must be at most 20, got {w0}");
+            Ok(Scenario::Objects(presets::king_cluster(
+                count(params, "stars")?,
+                positive(params, "radius")?,
+                w0,
+                imf(params)?,
+                rng,
+            )))
+        },
+    },
+    PresetEntry {
+        name: "wide_binaries",
+        description: "Binary stars on circular orbits spread over a cube",
+        params: &[
+            ("binaries", 100.0),
+            ("min_separation", 1e3),
+            ("max_separation", 1e4),
+            ("extent", 2e5),
+        ],
+        generate: |params, rng| {
+            let separation = (
+                positive(params, "min_separation")?,
+                positive(params, "max_separation")?,
+            );
+            ensure!(
+                separation.0 < separation.1,
+                "min_separation must be below max_separation"
+            );
+            Ok(Scenario::Objects(presets::wide_binaries(
+                count(params, "binaries")?,
+                separation,
+                positive(params, "extent")?,
+                rng,
+            )))
+        },
+    },
+    PresetEntry {
+        name: "disk_galaxy",
+        description: "Exponential disk and bulge of a galaxy like the milky way",
+        params: &[("stars", 5000.0), ("halo", 0.0)],
+        generate: |params, rng| {
+            let mut galaxy = DiskGalaxy::milky_way(count(params, "stars")?);
+            // Orbits for the halo of `--potential milky_way_halo`, which must be given too.
+            if switch(params, "halo")? {
+                galaxy.halo = vec![presets::milky_way_halo()];
+            }
+            Ok(Scenario::Objects(presets::disk_galaxy(&galaxy, rng)))
+        },
+    },
+    PresetEntry {
+        name: "galaxy_collision",
+        description: "Two disk galaxies merging on a parabolic orbit",
+        params: &[
+            ("stars", 2000.0),
+            ("mass_ratio", 1.0),
+            ("impact_kpc", 5.0),
+            ("tilt_degrees", 60.0),
+        ],
+        generate: |params, rng| {
+            let ratio = positive(params, "mass_ratio")?;
+            ensure!(ratio <= 1.0, "mass_ratio must be at most 1, got {ratio}");
+            let collision = GalaxyCollision::new(count(params, "stars")?);
+            let impact = positive(params, "impact_kpc")? * 1e3 * PARSEC;
+            ensure!(
+                impact < collision.separation,
+                "impact_kpc must be below the starting separation of {} kpc",
+                collision.separation / 1e3 / PARSEC
+            );
+            let tilt = params["tilt_degrees"].to_radians();
+            let collision = GalaxyCollision {
+                mass_ratio: ratio,
+                impact_parameter: impact,
+                normals: [
+                    Vector3::unit_z(),
+                    Vector3::new(0.0, -tilt.sin(), tilt.cos()),
+                ],
+                ..collision
+            };
+            Ok(Scenario::Objects(presets::galaxy_collision(
+                &collision, rng,
+            )))
+        },
+    },
+    PresetEntry {
+        name: "cloud",
+        description: "Grid of particles orbiting a pinned mass",
+        params: &[
+            ("objects", 10000.0),
+            ("min_mass", 1e4 / SOLAR_MASS),
+            ("max_mass", 1e4 / SOLAR_MASS),
+        ],
+        generate: |params, rng| {
+            Ok(Scenario::Objects(presets::fixed_cloud(
+                count(params, "objects")?,
+                imf(params)?,
+                rng,
+            )))
+        },
+    },
+];
+
+/// Look up the preset called `name`.
+pub fn find(name: &str) -> anyhow::Result<&'static PresetEntry> {
+    PRESETS
+        .iter()
+        .find(|entry| entry.name == name)
+        .ok_or_else(|| {
+            let names = PRESETS.iter().map(|e| e.name).collect::<Vec<_>>();
+            anyhow::anyhow!("No preset {name}, there are {}", names.join(", "))
+        })
+}
+
+/// Parse a preset and its parameters from `name,key=value,...`, like
+/// `plummer,stars=1000`.
+pub fn parse(spec: &str) -> anyhow::Result<(&'static PresetEntry, PresetParams)> {
+    let mut parts = spec.split(',');
+    let entry = find(parts.next().unwrap_or_default())?;
+    let params = parts
+        .map(|part| {
+            let (name, value) = part
+                .split_once('=')
+                .ok_or_else(|| anyhow::anyhow!("Preset parameter {part} must be name=value"))?;
+            let value = value
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid value for {name}: {e}"))?;
+            Ok((name.to_owned(), value))
+        })
+        .collect::<anyhow::Result<PresetParams>>()?;
+    Ok((entry, params))
+}
+
+/// Parameter `name` as a count of something.
+fn count(params: &PresetParams, name: &str) -> anyhow::Result<usize> {
+    let value = params[name];
+    ensure!(
+        value >= 0.0 && value.fract() == 0.0 && value.is_finite(),
+        "{name} must be a whole number, got {value}"
+    );
+    Ok(value as usize)
+}
+
+/// Parameter `name`, which must be positive.
+fn positive(params: &PresetParams, name: &str) -> anyhow::Result<f64> {
+    let value = params[name];
+    ensure!(
+        value > 0.0 && value.is_finite(),
+        "{name} must be positive, got {value}"
+    );
+    Ok(value)
+}
+
+/// Parameter `name`, which must be 0 or 1.
+fn switch(params: &PresetParams, name: &str) -> anyhow::Result<bool> {
+    let value = params[name];
+    ensure!(
+        value == 0.0 || value == 1.0,
+        "{name} must be 0 or 1, got {value}"
+    );
+    Ok(value == 1.0)
+}
+
+/// Mass function from `min_mass` and `max_mass`, see the module documentation.
+fn imf(params: &PresetParams) -> anyhow::Result<Imf> {
+    let min = positive(params, "min_mass")?;
+    let max = positive(params, "max_mass")?;
+    ensure!(min <= max, "min_mass must be at most max_mass");
+    Ok(if min == max {
+        Imf::Fixed(min)
+    } else {
+        Imf::Kroupa { min, max }
+    })
+}
+
+/// Analytic potentials that can be added to a run by name, see `POTENTIALS`.
+pub struct PotentialEntry {
+    pub name: &'static str,
+    pub description: &'static str,
+    generate: fn() -> Vec<AnalyticPotential>,
+}
+
+/// Every set of potentials that can be added by name.
+pub static POTENTIALS: &[PotentialEntry] = &[
+    PotentialEntry {
+        name: "milky_way_halo",
+        description: "Dark matter halo of the milky way, for the disk_galaxy preset with halo=1",
+        generate: || vec![presets::milky_way_halo()],
+    },
+    PotentialEntry {
+        name: "milky_way",
+        description: "Dark matter halo and stellar disk of the milky way, for tracer stars",
+        generate: presets::milky_way_potentials,
+    },
+];
+
+/// Analytic potentials called `name` in `POTENTIALS`.
+pub fn potentials(name: &str) -> anyhow::Result<Vec<AnalyticPotential>> {
+    POTENTIALS
+        .iter()
+        .find(|entry| entry.name == name)
+        .map(|entry| (entry.generate)())
+        .ok_or_else(|| {
+            let names: Vec<_> = POTENTIALS.iter().map(|entry| entry.name).collect();
+            anyhow::anyhow!("No potential {name}, there are {}", names.join(", "))
+        })
+}
@@ -1,13 +1,78 @@
-use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector3};
+//! Ready-made scenarios.
+//!
+//! Presets never draw from the thread RNG. The random ones take an [`RngService`] and
+//! derive their own child from it, so a scenario is reproduced exactly by running with
+//! the same `--seed`, whatever else the run generates.
+
+use std::path::{Path, PathBuf};
+
+use cgmath::{EuclideanSpace, InnerSpace, Matrix3, Point3, Vector3};
+use rand::Rng;
 
 use crate::{
-    Object, ObjectInfo,
-    constants::{AU, G, M0},
+    AnalyticPotential, Maneuver, Object, ObjectInfo, Oblateness, Radiation, ThrustAmount,
+    ThrustDirection,
+    constants::{
+        AU, G, L_SUN, M0, PARSEC, PLANET_TEXTURE_DIR, SOLAR_MASS, SOLAR_RADIUS, YARKOVSKY_1KM,
+    },
     parameters::{
-        AbsoluteCoords, RelativeCoords, RelativeOrAbsolute, StandardParams, convert_params,
+        AbsoluteCoords, Anomaly, RelativeCoords, RelativeOrAbsolute, StandardParams, convert_params,
     },
+    rng::RngService,
 };
 
+/// Starting state for a simulation, either as orbital parameters relative to other
+/// bodies or as absolute objects.
+pub enum Scenario {
+    Params(Vec<StandardParams>),
+    Objects(Vec<Object>),
+}
+
+impl Scenario {
+    pub fn into_objects(self) -> Vec<Object> {
+        match self {
+            Scenario::Params(params) => convert_params(params)
+                .into_iter()
+                .map(|o| o.into())
+                .collect(),
+            Scenario::Objects(objects) => objects,
+        }
+    }
+
+    /// Objects as parameters at absolute coordinates, where they are not already
+    /// parameters. Test particles become massless, so they still attract nothing, and
+    /// pinning is lost.
+    pub fn into_params(self) -> Vec<StandardParams> {
+        match self {
+            Scenario::Params(params) => params,
+            Scenario::Objects(objects) => objects
+                .into_iter()
+                .map(|obj| StandardParams {
+                    coordinates: RelativeOrAbsolute::Absolute(AbsoluteCoords {
+                        pos: (obj.dat.pos * AU).into(),
+                        vel: (obj.dat.vel * AU).into(),
+                    }),
+                    mass: obj.dat.gravitating_mass(),
+                    radius: obj.radius,
+                    color: obj.color.into(),
+                    name: obj.name,
+                    oblateness: obj.oblateness,
+                    radiation: obj.radiation,
+                    maneuvers: obj.maneuvers,
+                    mass_rate: obj.mass_rate,
+                    texture: obj.texture,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Map of the surface of `name`, as `<name>.ppm` in `PLANET_TEXTURE_DIR`, if there is one.
+fn planet_texture(name: &str) -> Option<PathBuf> {
+    let path = Path::new(PLANET_TEXTURE_DIR).join(format!("{name}.ppm"));
+    path.exists().then_some(path)
+}
+
 pub fn earth_sun_basic() -> Vec<Object> {
     vec![
         Object {
@@ -16,9 +81,16 @@ pub fn earth_sun_basic() -> Vec<Object> {
                 pos: (0.0, 0.0, 0.0).into(),
                 vel: (0.0, 1e3 / AU, 0.0).into(),
                 mass: 333000.0,
+                pinned: false,
+                test_particle: false,
             },
             color: (1.0, 1.0, 0.0).into(),
             radius: (696340e3 / AU) as f32,
+            oblateness: None,
+            radiation: None,
+            maneuvers: Vec::new(),
+            mass_rate: 0.0,
+            texture: planet_texture("sun"),
         },
         Object {
             name: "earth".to_owned(),
@@ -26,9 +98,16 @@ pub fn earth_sun_basic() -> Vec<Object> {
                 pos: (1.0, 0.0, 0.0).into(),
                 vel: (0.0, (29.8e3 + 1e3) / AU, 0.0).into(),
                 mass: 1.0,
+                pinned: false,
+                test_particle: false,
             },
             color: (0.0, 0.0, 1.0).into(),
             radius: (6371e3 / AU) as f32,
+            oblateness: None,
+            radiation: None,
+            maneuvers: Vec::new(),
+            mass_rate: 0.0,
+            texture: planet_texture("earth"),
         },
     ]
 }
@@ -44,6 +123,11 @@ pub fn earth_sun_mars_params() -> Vec<StandardParams> {
             mass: 333000.0,
             radius: (696340e3 / AU) as f32,
             color: (1.0, 1.0, 0.0).into(),
+            oblateness: None,
+            radiation: Some(Radiation::Source { luminosity: L_SUN }),
+            maneuvers: Vec::new(),
+            mass_rate: 0.0,
+            texture: planet_texture("sun"),
         },
         StandardParams {
             name: "earth".to_owned(),
@@ -54,7 +138,7 @@ pub fn earth_sun_mars_params() -> Vec<StandardParams> {
                 inclination: 3.670030330713475E-03,
                 arg_periapsis: 2.557573855355361E+02,
                 long_asc_node: 2.087400227953831E+02,
-                true_an: 3.450278328909303E+02,
+                anomaly: Anomaly::True(3.450278328909303E+02),
             }),
             /* coordinates: RelativeOrAbsolute::Absolute(AbsoluteCoords {
                 pos: [0.0, 0.0, 0.0],
@@ -63,21 +147,36 @@ pub fn earth_sun_mars_params() -> Vec<StandardParams> {
             mass: 1.0,
             radius: (6371e3 / AU) as f32,
             color: (0.0, 0.0, 1.0).into(),
+            oblateness: Some(Oblateness {
+                j2: 1.08263e-3,
+                equatorial_radius: 6378.137e3 / AU,
+                // Tilted 23.44 degrees from the ecliptic pole, towards the summer solstice.
+                pole: Vector3::new(0.0, 0.3978, 0.9175),
+            }),
+            radiation: None,
+            maneuvers: Vec::new(),
+            mass_rate: 0.0,
+            texture: planet_texture("earth"),
         },
         StandardParams {
             name: "moon".to_owned(),
             coordinates: RelativeOrAbsolute::Relative(RelativeCoords {
                 parent: "earth".to_owned(),
-                semi_major_axis: 3.815880763110870E+05 * 1e3,
+                semi_major_axis: 3.81588076311087E+05 * 1e3,
                 eccentricity: 3.179523012872624E-02,
                 inclination: 5.064604179512905E+00,
                 arg_periapsis: 3.012277898101174E+02,
                 long_asc_node: 2.229402837659016E+01,
-                true_an: 6.454243862420770E+01,
+                anomaly: Anomaly::True(6.45424386242077E+01),
             }),
             mass: 7.349e22 / M0,
             radius: (1737e3 / AU) as f32,
             color: (1.0, 1.0, 1.0).into(),
+            oblateness: None,
+            radiation: None,
+            maneuvers: Vec::new(),
+            mass_rate: 0.0,
+            texture: planet_texture("moon"),
         },
         StandardParams {
             name: "mars".to_owned(),
@@ -88,11 +187,16 @@ pub fn earth_sun_mars_params() -> Vec<StandardParams> {
                 inclination: 1.848,
                 arg_periapsis: 286.5,
                 long_asc_node: 49.578,
-                true_an: 0.0, // TOOD
+                anomaly: Anomaly::True(0.0), // TOOD
             }),
             mass: 0.107,
             radius: (3396.2e3 / AU) as f32,
             color: (1.0, 0.0, 0.0).into(),
+            oblateness: None,
+            radiation: None,
+            maneuvers: Vec::new(),
+            mass_rate: 0.0,
+            texture: planet_texture("mars"),
         },
     ]
 }
@@ -112,138 +216,901 @@ pub fn big_boy_on_collision_course() -> Object {
             pos: (3.0, 0.0, 0.0).into(),
             vel: (-0.5e5 / AU, -0.2e5 / AU, 0.0).into(),
             mass: 100000.0,
+            pinned: false,
+            test_particle: false,
         },
         color: (0.0, 1.0, 0.0).into(),
         radius: (1e6 / AU) as f32,
+        oblateness: None,
+        radiation: None,
+        maneuvers: Vec::new(),
+        mass_rate: 0.0,
+        texture: None,
     }
 }
 
-pub fn earth_sun_mars_ast() -> Vec<Object> {
+/// Spacecraft flying a Hohmann transfer from the orbit of the earth to that of mars, both
+/// taken as circular. Each burn lasts a day, and mars starts out ahead by the angle that
+/// has it arrive at the same time as the spacecraft.
+pub fn hohmann_to_mars() -> Vec<Object> {
+    const DAY: f64 = 24.0 * 3600.0;
+    let mu = G * SOLAR_MASS;
+    let (r1, r2) = (1.0, 1.524);
+    let transfer_axis = (r1 + r2) / 2.0;
+    let departure = (mu / r1).sqrt() * ((r2 / transfer_axis).sqrt() - 1.0);
+    let arrival = (mu / r2).sqrt() * (1.0 - (r1 / transfer_axis).sqrt());
+    let transfer_time = std::f64::consts::PI * (transfer_axis.powi(3) / mu).sqrt();
+    let mars_lead = std::f64::consts::PI - (mu / r2.powi(3)).sqrt() * transfer_time;
+
+    let circular = |radius: f64, angle: f64| {
+        let (sin, cos) = angle.sin_cos();
+        let speed = (mu / radius).sqrt();
+        (
+            Point3::new(radius * cos, radius * sin, 0.0),
+            Vector3::new(-speed * sin, speed * cos, 0.0),
+        )
+    };
+    let burn = |start: f64, delta_v: f64| Maneuver {
+        start,
+        duration: DAY,
+        direction: ThrustDirection::Prograde,
+        amount: ThrustAmount::DeltaV(delta_v * AU),
+        reference: Some(0),
+    };
+
+    let (mars_pos, mars_vel) = circular(r2, mars_lead);
+    let (craft_pos, craft_vel) = circular(r1, 0.0);
+    vec![
+        Object {
+            name: "sun".to_owned(),
+            dat: ObjectInfo {
+                pos: Point3::new(0.0, 0.0, 0.0),
+                vel: Vector3::new(0.0, 0.0, 0.0),
+                mass: SOLAR_MASS,
+                pinned: false,
+                test_particle: false,
+            },
+            color: (1.0, 1.0, 0.0).into(),
+            radius: (696340e3 / AU) as f32,
+            oblateness: None,
+            radiation: None,
+            maneuvers: Vec::new(),
+            mass_rate: 0.0,
+            texture: planet_texture("sun"),
+        },
+        Object {
+            name: "mars".to_owned(),
+            dat: ObjectInfo {
+                pos: mars_pos,
+                vel: mars_vel,
+                mass: 0.107,
+                pinned: false,
+                test_particle: false,
+            },
+            color: (1.0, 0.0, 0.0).into(),
+            radius: (3396.2e3 / AU) as f32,
+            oblateness: None,
+            radiation: None,
+            maneuvers: Vec::new(),
+            mass_rate: 0.0,
+            texture: planet_texture("mars"),
+        },
+        Object {
+            name: "spacecraft".to_owned(),
+            dat: ObjectInfo {
+                pos: craft_pos,
+                vel: craft_vel,
+                mass: 0.0,
+                pinned: false,
+                test_particle: true,
+            },
+            color: (1.0, 1.0, 1.0).into(),
+            radius: (10.0 / AU) as f32,
+            oblateness: None,
+            radiation: None,
+            maneuvers: vec![burn(0.0, departure), burn(transfer_time, arrival)],
+            mass_rate: 0.0,
+            texture: None,
+        },
+    ]
+}
+
+pub fn earth_sun_mars_ast(rng: &RngService) -> Vec<Object> {
     let mut objs = earth_sun_mars_params();
-    objs.append(&mut asteroid_belt(10000));
-    convert_params(objs).into_iter().map(|o| o.into()).collect()
+    let n_planets = objs.len();
+    objs.append(&mut asteroid_belt(10000, rng));
+    let mut objs: Vec<Object> = convert_params(objs).into_iter().map(|o| o.into()).collect();
+    // The asteroids are far too light to matter to each other, or to the planets.
+    for obj in &mut objs[n_planets..] {
+        obj.dat.test_particle = true;
+    }
+    objs
 }
 
-pub fn asteroid_belt(n_asteroids: usize) -> Vec<StandardParams> {
+/// Each asteroid draws from its own stream, so the first `n` asteroids are the same
+/// regardless of how many are generated.
+pub fn asteroid_belt(n_asteroids: usize, rng: &RngService) -> Vec<StandardParams> {
+    let rng = rng.child("asteroid_belt");
     let mut objs = Vec::new();
     for i in 0..n_asteroids {
-        let col = 0.5 + rand::random_range(-0.2..0.2);
+        let mut rng = rng.stream(i as u64);
+        let col = 0.5 + rng.random_range(-0.2..0.2);
+        let radius = rng.random_range((1e3 / AU)..(1e6 / AU));
+        // Prograde and retrograde rotators drift in opposite directions.
+        let spin = if rng.random_bool(0.5) { 1.0 } else { -1.0 };
         objs.push(StandardParams {
             name: format!("asteroid_{i}"),
             coordinates: RelativeOrAbsolute::Relative(RelativeCoords {
                 parent: "sun".to_owned(),
-                semi_major_axis: 300000E+6 + rand::random_range(-1.0..1.0) * 25_000E+6,
-                eccentricity: rand::random_range(0.0..0.15),
-                inclination: rand::random_range(0.0..10.0),
-                arg_periapsis: rand::random_range(0.0..360.0),
-                long_asc_node: rand::random_range(0.0..360.0),
-                true_an: rand::random_range(0.0..360.0),
+                semi_major_axis: 300000E+6 + rng.random_range(-1.0..1.0) * 25_000E+6,
+                eccentricity: rng.random_range(0.0..0.15),
+                inclination: rng.random_range(0.0..10.0),
+                arg_periapsis: rng.random_range(0.0..360.0),
+                long_asc_node: rng.random_range(0.0..360.0),
+                anomaly: Anomaly::True(rng.random_range(0.0..360.0)),
             }),
-            mass: rand::random_range(1e-10..1e-6),
-            radius: rand::random_range((1e3 / AU)..(1e6 / AU)) as f32,
+            mass: rng.random_range(1e-10..1e-6),
+            radius: radius as f32,
             color: (col, col, col).into(),
+            oblateness: None,
+            radiation: Some(Radiation::Target {
+                reflectivity: 1.2,
+                yarkovsky: spin * YARKOVSKY_1KM * 1e3 / (2.0 * radius * AU),
+            }),
+            maneuvers: Vec::new(),
+            mass_rate: 0.0,
+            texture: None,
         });
     }
     objs
 }
 
-pub fn fixed_cloud(n_objects: usize) -> Vec<Object> {
-    let min = -10.0;
-    let max = 10.0;
-    let idx_step = (n_objects as f64).cbrt().ceil() as usize;
-    let step = (max - min) / (idx_step as f64);
+/// Dark matter halo of a galaxy like the milky way, roughly following Bovy (2015).
+pub fn milky_way_halo() -> AnalyticPotential {
+    AnalyticPotential::Nfw {
+        center: Point3::new(0.0, 0.0, 0.0),
+        mass: 4.4e11 * SOLAR_MASS,
+        scale_radius: 16e3 * PARSEC,
+    }
+}
 
-    let mut objs = Vec::new();
-    objs.push(Object {
-        name: "Center".to_owned(),
-        dat: ObjectInfo {
-            pos: Point3::new(-15.0, 0.0, 0.0),
-            vel: Vector3::new(0.0, 0.0, 0.0),
-            mass: 1e7,
+/// Halo and disk of a galaxy like the milky way, roughly following Bovy (2015).
+pub fn milky_way_potentials() -> Vec<AnalyticPotential> {
+    vec![
+        milky_way_halo(),
+        AnalyticPotential::MiyamotoNagai {
+            center: Point3::new(0.0, 0.0, 0.0),
+            normal: Vector3::unit_z(),
+            mass: 6.8e10 * SOLAR_MASS,
+            scale_length: 3e3 * PARSEC,
+            scale_height: 280.0 * PARSEC,
         },
-        color: Vector3::new(1.0, 1.0, 1.0),
-        radius: (1e5 / AU) as f32,
-    });
+    ]
+}
 
-    for i in 0..n_objects {
-        let pos = Point3::new(
-            min + (i % idx_step) as f64 * step,
-            min + ((i / idx_step) % idx_step) as f64 * step,
-            min + ((i / (idx_step * idx_step)) % idx_step) as f64 * step,
-        );
-        let rotate_around = Vector3::new(0.0, 1.0, 1.0).normalize();
-        let radius = (pos - Point3::new(-15.0, 0.0, 0.0)).magnitude();
-        let norm_pos = (pos - Point3::new(-15.0, 0.0, 0.0)).normalize();
-        // let vel = rotate_around.cross(norm_pos) * (1e6 / AU) * (radius);
-        let vel_basis = (G * 1e7 / radius).sqrt();
-        let vel = rotate_around.cross(norm_pos) * vel_basis;
+/// Bulge of a [`DiskGalaxy`], following a Hernquist (1990) profile.
+pub struct Bulge {
+    pub n_stars: usize,
+    /// Total mass, in earth masses.
+    pub mass: f64,
+    /// Scale radius, in AU. Stars are drawn out to twenty times this.
+    pub scale_radius: f64,
+}
+
+/// Disk galaxy with an exponential surface density, see [`disk_galaxy`].
+pub struct DiskGalaxy {
+    pub n_stars: usize,
+    /// Total mass of the disk, in earth masses.
+    pub mass: f64,
+    /// Radial scale length of the surface density, in AU. Stars are drawn out to ten
+    /// times this.
+    pub scale_length: f64,
+    /// Vertical scale height of the `sech^2` profile, in AU.
+    pub scale_height: f64,
+    pub bulge: Option<Bulge>,
+    /// Analytic halo the galaxy sits in, which must also be added to the simulation for
+    /// the galaxy to stay in equilibrium. Empty to go by the mass of the stars alone,
+    /// which is what a galaxy that moves needs.
+    pub halo: Vec<AnalyticPotential>,
+}
 
-        let col = (pos.to_vec() - Vector3::new(min, min, min))
-            .normalize()
-            .cast()
-            .unwrap();
+impl DiskGalaxy {
+    /// Disk and bulge roughly like the milky way, with no halo.
+    pub fn milky_way(n_stars: usize) -> Self {
+        Self {
+            n_stars,
+            mass: 5e10 * SOLAR_MASS,
+            scale_length: 2.6e3 * PARSEC,
+            scale_height: 300.0 * PARSEC,
+            bulge: Some(Bulge {
+                n_stars: n_stars / 5,
+                mass: 1e10 * SOLAR_MASS,
+                scale_radius: 500.0 * PARSEC,
+            }),
+            halo: Vec::new(),
+        }
+    }
+
+    /// Mass of the disk and bulge within `radius` AU of the center, counting the disk as
+    /// if it were spherical.
+    fn enclosed_mass(&self, radius: f64) -> f64 {
+        let disk = |r: f64| {
+            let x = r / self.scale_length;
+            1.0 - (1.0 + x) * (-x).exp()
+        };
+        let mut mass =
+            self.mass * disk(radius.min(10.0 * self.scale_length)) / disk(10.0 * self.scale_length);
+        if let Some(bulge) = &self.bulge {
+            let fraction = |r: f64| (r / (r + bulge.scale_radius)).powi(2);
+            let max = 20.0 * bulge.scale_radius;
+            mass += bulge.mass * fraction(radius.min(max)) / fraction(max);
+        }
+        mass
+    }
+
+    /// Square of the speed of a circular orbit `radius` AU from the center, in the plane
+    /// of the disk.
+    fn circular_speed_sq(&self, radius: f64) -> f64 {
+        G * self.enclosed_mass(radius) / radius
+            + self
+                .halo
+                .iter()
+                .map(|p| p.circular_speed(radius, Vector3::unit_z()).powi(2))
+                .sum::<f64>()
+    }
+}
+
+/// Stars of the disk galaxy `galaxy`, centered at the origin with the disk in the xy
+/// plane, rotating counterclockwise seen from above. Disk stars are on circular orbits
+/// with the speed of the rotation curve, from the enclosed mass and the halo, and with
+/// the vertical velocity dispersion of an isothermal sheet. Bulge stars have isotropic
+/// velocities from the Jeans equation in the same rotation curve.
+pub fn disk_galaxy(galaxy: &DiskGalaxy, rng: &RngService) -> Vec<Object> {
+    let rng = rng.child("disk_galaxy");
+    let mut objs = Vec::new();
+    let star_mass = galaxy.mass / galaxy.n_stars as f64;
+    let disk_rng = rng.child("disk");
+    for i in 0..galaxy.n_stars {
+        let mut rng = disk_rng.stream(i as u64);
+        // Mass at each radius goes as r e^(-r / h), a gamma distribution with shape 2.
+        let radius = loop {
+            let u: f64 = rng.random_range(f64::EPSILON..1.0);
+            let v: f64 = rng.random_range(f64::EPSILON..1.0);
+            let r = -galaxy.scale_length * (u * v).ln();
+            if r < 10.0 * galaxy.scale_length {
+                break r;
+            }
+        };
+        let angle = rng.random_range(0.0..std::f64::consts::TAU);
+        let height = galaxy.scale_height * rng.random_range(f64::EPSILON - 1.0..1.0).atanh();
+        let surface_density = galaxy.mass / (std::f64::consts::TAU * galaxy.scale_length.powi(2))
+            * (-radius / galaxy.scale_length).exp();
+        let vertical_dispersion =
+            (std::f64::consts::PI * G * surface_density * galaxy.scale_height).sqrt();
+        let dir = Vector3::new(angle.cos(), angle.sin(), 0.0);
+        let speed = galaxy.circular_speed_sq(radius).sqrt();
         objs.push(Object {
-            name: format!("particle_{i}"),
+            name: format!("disk_star_{i}"),
             dat: ObjectInfo {
-                pos: pos,
-                vel: vel,
-                mass: 1e4,
+                pos: Point3::from_vec(dir * radius + Vector3::unit_z() * height),
+                vel: Vector3::unit_z().cross(dir) * speed
+                    + Vector3::unit_z() * (vertical_dispersion * random_gaussian(&mut rng)),
+                mass: star_mass,
+                pinned: false,
+                test_particle: false,
             },
-            color: col,
-            radius: (1e4 / AU) as f32,
+            color: Vector3::new(0.8, 0.9, 1.0),
+            radius: (696340e3 / AU) as f32,
+            oblateness: None,
+            radiation: None,
+            maneuvers: Vec::new(),
+            mass_rate: 0.0,
+            texture: None,
         });
     }
+    if let Some(bulge) = &galaxy.bulge {
+        let bulge_rng = rng.child("bulge");
+        let a = bulge.scale_radius;
+        let density = |r: f64| 1.0 / (r * (r + a).powi(3));
+        // Fraction of the mass within twenty scale radii.
+        let max_fraction = (20.0f64 / 21.0).powi(2);
+        for i in 0..bulge.n_stars {
+            let mut rng = bulge_rng.stream(i as u64);
+            let s = rng.random_range(f64::EPSILON..max_fraction).sqrt();
+            let radius = a * s / (1.0 - s);
+            // Isotropic Jeans equation, sigma^2 rho = int_r^inf rho v_c^2 / r dr, summed
+            // over logarithmic steps out to where the density no longer matters.
+            let steps = 200;
+            let step = (1e4 * a / radius).ln().max(0.0) / steps as f64;
+            let integral = (0..=steps)
+                .map(|k| {
+                    let r = radius * (step * k as f64).exp();
+                    let weight = if k == 0 || k == steps { 0.5 } else { 1.0 };
+                    weight * density(r) * galaxy.circular_speed_sq(r) * step
+                })
+                .sum::<f64>();
+            let dispersion = (integral / density(radius)).sqrt();
+            objs.push(Object {
+                name: format!("bulge_star_{i}"),
+                dat: ObjectInfo {
+                    pos: Point3::from_vec(random_direction(&mut rng) * radius),
+                    vel: Vector3::new(
+                        random_gaussian(&mut rng),
+                        random_gaussian(&mut rng),
+                        random_gaussian(&mut rng),
+                    ) * dispersion,
+                    mass: bulge.mass / bulge.n_stars as f64,
+                    pinned: false,
+                    test_particle: false,
+                },
+                color: Vector3::new(1.0, 0.85, 0.6),
+                radius: (696340e3 / AU) as f32,
+                oblateness: None,
+                radiation: None,
+                maneuvers: Vec::new(),
+                mass_rate: 0.0,
+                texture: None,
+            });
+        }
+    }
+    to_center_of_mass(&mut objs);
+    objs
+}
+
+/// Two disk galaxies merging, see [`galaxy_collision`].
+pub struct GalaxyCollision {
+    /// Disk stars of the larger galaxy. The smaller one has fewer in proportion to its
+    /// mass, so that all disk stars weigh the same.
+    pub n_stars: usize,
+    /// Mass of the smaller galaxy over the mass of the larger, in (0, 1].
+    pub mass_ratio: f64,
+    /// Closest approach of the centers of the galaxies if they were point masses, in AU.
+    pub impact_parameter: f64,
+    /// Distance between the centers at the start, in AU.
+    pub separation: f64,
+    /// Normals of the disks of the larger and the smaller galaxy. The orbit is in the xy
+    /// plane, counterclockwise seen from above, with the closest approach along the x
+    /// axis.
+    pub normals: [Vector3<f64>; 2],
+}
+
+impl GalaxyCollision {
+    /// Two equal galaxies starting 40 kpc apart and passing within 5 kpc, one rotating
+    /// with the orbit and the other tilted 60 degrees against it.
+    pub fn new(n_stars: usize) -> Self {
+        let tilt = 60f64.to_radians();
+        Self {
+            n_stars,
+            mass_ratio: 1.0,
+            impact_parameter: 5e3 * PARSEC,
+            separation: 40e3 * PARSEC,
+            normals: [
+                Vector3::unit_z(),
+                Vector3::new(0.0, -tilt.sin(), tilt.cos()),
+            ],
+        }
+    }
+}
+
+/// Two galaxies like the milky way falling towards each other on the parabolic orbit of
+/// `collision`, with their center of mass at rest at the origin. The smaller galaxy is
+/// the larger one scaled down, its sizes going as the square root of the mass ratio. The
+/// first pass of equal galaxies with the defaults is after about 200 million years.
+pub fn galaxy_collision(collision: &GalaxyCollision, rng: &RngService) -> Vec<Object> {
+    let rng = rng.child("galaxy_collision");
+    let ratio = collision.mass_ratio;
+    let size = ratio.sqrt();
+    let scaled = |n: usize| ((n as f64 * ratio).round() as usize).max(1);
+    let larger = DiskGalaxy::milky_way(collision.n_stars);
+    let smaller = DiskGalaxy {
+        n_stars: scaled(larger.n_stars),
+        mass: larger.mass * ratio,
+        scale_length: larger.scale_length * size,
+        scale_height: larger.scale_height * size,
+        bulge: larger.bulge.as_ref().map(|bulge| Bulge {
+            n_stars: scaled(bulge.n_stars),
+            mass: bulge.mass * ratio,
+            scale_radius: bulge.scale_radius * size,
+        }),
+        halo: Vec::new(),
+    };
+    let masses = [&larger, &smaller].map(|g| g.mass + g.bulge.as_ref().map_or(0.0, |b| b.mass));
+    let total = masses[0] + masses[1];
 
+    // Incoming branch of the parabola r = 2 q / (1 + cos(nu)) of the smaller galaxy
+    // relative to the larger, with speed sqrt(2 G M / r).
+    let periapsis = collision.impact_parameter;
+    let anomaly = -(2.0 * periapsis / collision.separation - 1.0)
+        .clamp(-1.0, 1.0)
+        .acos();
+    let (sin, cos) = anomaly.sin_cos();
+    let rel_pos = Vector3::new(cos, sin, 0.0) * collision.separation;
+    let rel_vel = Vector3::new(-sin, 1.0 + cos, 0.0) * (G * total / (2.0 * periapsis)).sqrt();
+
+    let mut objs = Vec::new();
+    for (i, (side, galaxy)) in [("a", &larger), ("b", &smaller)].into_iter().enumerate() {
+        // Each galaxy is offset from the center of mass by the share of the other.
+        let share = if i == 0 {
+            -masses[1] / total
+        } else {
+            masses[0] / total
+        };
+        let rotation = disk_rotation(collision.normals[i]);
+        for mut obj in disk_galaxy(galaxy, &rng.child(side)) {
+            obj.name = format!("{}_{side}", obj.name);
+            obj.dat.pos = Point3::from_vec(rotation * obj.dat.pos.to_vec() + rel_pos * share);
+            obj.dat.vel = rotation * obj.dat.vel + rel_vel * share;
+            objs.push(obj);
+        }
+    }
     objs
 }
 
-#[allow(unused)]
-fn fixed_shell(n_objects: usize) -> Vec<Object> {
-    let idx_step = (n_objects as f64).sqrt().ceil() as usize;
-    let pi_step = std::f64::consts::PI / (idx_step as f64);
+/// Rotation taking the z axis to `normal`.
+fn disk_rotation(normal: Vector3<f64>) -> Matrix3<f64> {
+    let normal = normal.normalize();
+    let helper = if normal.x.abs() < 0.9 {
+        Vector3::unit_x()
+    } else {
+        Vector3::unit_y()
+    };
+    let u = helper.cross(normal).normalize();
+    Matrix3::from_cols(u, normal.cross(u), normal)
+}
+
+/// Star cluster of main sequence stars with masses drawn from `imf`, following a Plummer
+/// profile with scale radius `scale_radius` in AU, sampled as in Aarseth, Henon & Wielen
+/// (1974) and centered at the origin, at rest. Stars further out than ten scale radii are
+/// drawn again.
+pub fn plummer_cluster(
+    n_stars: usize,
+    scale_radius: f64,
+    imf: Imf,
+    rng: &RngService,
+) -> Vec<Object> {
+    let rng = rng.child("plummer_cluster");
+    let masses = imf.sample_many(n_stars, &rng);
+    let total_mass = masses.iter().sum::<f64>() * SOLAR_MASS;
+    let mut objs = Vec::new();
+    for (i, &mass) in masses.iter().enumerate() {
+        let mut rng = rng.stream(i as u64);
+        let dir = random_direction(&mut rng);
+        let radius = loop {
+            let u: f64 = rng.random_range(f64::EPSILON..1.0);
+            let r = scale_radius / (u.powf(-2.0 / 3.0) - 1.0).sqrt();
+            if r < 10.0 * scale_radius {
+                break r;
+            }
+        };
+        // Fraction of the escape velocity, drawn from q^2 (1 - q^2)^3.5 by rejection.
+        let q = loop {
+            let q: f64 = rng.random_range(0.0..1.0);
+            let g: f64 = rng.random_range(0.0..0.1);
+            if g < q * q * (1.0 - q * q).powf(3.5) {
+                break q;
+            }
+        };
+        let escape =
+            (2.0 * G * total_mass / (radius * radius + scale_radius * scale_radius).sqrt()).sqrt();
+        let vel_dir = random_direction(&mut rng);
+        let (star_radius, color) = main_sequence_star(mass);
+        objs.push(Object {
+            name: format!("cluster_star_{i}"),
+            dat: ObjectInfo {
+                pos: Point3::from_vec(dir * radius),
+                vel: vel_dir * q * escape,
+                mass: mass * SOLAR_MASS,
+                pinned: false,
+                test_particle: false,
+            },
+            color,
+            radius: star_radius,
+            oblateness: None,
+            radiation: None,
+            maneuvers: Vec::new(),
+            mass_rate: 0.0,
+            texture: None,
+        });
+    }
+    to_center_of_mass(&mut objs);
+    objs
+}
+
+/// Star cluster of main sequence stars with masses drawn from `imf`, following a King
+/// (1966) model with core radius `core_radius` in AU, centered at the origin and at rest. `w0` is the potential at the
+/// center in units of the velocity dispersion squared, larger is more concentrated, and
+/// globular clusters are mostly between 3 and 9. Velocities are drawn from the lowered
+/// Maxwellian of the model, so the cluster starts out in equilibrium, and no star is
+/// beyond the tidal radius.
+pub fn king_cluster(
+    n_stars: usize,
+    core_radius: f64,
+    w0: f64,
+    imf: Imf,
+    rng: &RngService,
+) -> Vec<Object> {
+    let rng = rng.child("king_cluster");
+    let profile = KingProfile::solve(w0);
+    let masses = imf.sample_many(n_stars, &rng);
+    let total_mass = masses.iter().sum::<f64>() * SOLAR_MASS;
+    // Velocity dispersion parameter that gives the profile its mass at this core radius.
+    let sigma = (G * total_mass / (core_radius * profile.total_mass())).sqrt();
+    let mut objs = Vec::new();
+    for (i, &mass) in masses.iter().enumerate() {
+        let mut rng = rng.stream(i as u64);
+        let (radius, w) = profile.sample(rng.random_range(0.0..1.0));
+        // Speed in units of sigma, drawn from v^2 (e^(w - v^2 / 2) - 1) by rejection under
+        // whichever of two bounds on it is tighter.
+        let max_speed = (2.0 * w).sqrt();
+        let bound = (2.0 * (w - 1.0).exp()).min(2.0 * w * w.exp_m1());
+        let speed = loop {
+            let v: f64 = rng.random_range(0.0..max_speed);
+            let g: f64 = rng.random_range(0.0..bound);
+            if g < v * v * (w - v * v / 2.0).exp_m1() {
+                break v;
+            }
+        };
+        let dir = random_direction(&mut rng);
+        let vel_dir = random_direction(&mut rng);
+        let (star_radius, color) = main_sequence_star(mass);
+        objs.push(Object {
+            name: format!("cluster_star_{i}"),
+            dat: ObjectInfo {
+                pos: Point3::from_vec(dir * radius * core_radius),
+                vel: vel_dir * speed * sigma,
+                mass: mass * SOLAR_MASS,
+                pinned: false,
+                test_particle: false,
+            },
+            color,
+            radius: star_radius,
+            oblateness: None,
+            radiation: None,
+            maneuvers: Vec::new(),
+            mass_rate: 0.0,
+            texture: None,
+        });
+    }
+    to_center_of_mass(&mut objs);
+    objs
+}
+
+/// Dimensionless King model, with radii in core radii and the potential in units of the
+/// velocity dispersion squared, from the center out to the tidal radius.
+struct KingProfile {
+    radius: Vec<f64>,
+    potential: Vec<f64>,
+    /// Mass within each radius, as G M / (sigma^2 r0).
+    mass: Vec<f64>,
+}
+
+impl KingProfile {
+    /// Integrate Poisson's equation outwards from the center, where the potential is
+    /// `w0`, until it reaches zero at the tidal radius.
+    fn solve(w0: f64) -> Self {
+        let central = king_density(w0);
+        // w'' + 2 w' / x = -9 rho(w) / rho(w0), in units where this is 1 at the center.
+        let derivative = |x: f64, (w, dw): (f64, f64)| {
+            (dw, -9.0 * king_density(w.max(0.0)) / central - 2.0 * dw / x)
+        };
+        // Start just off the center, from the series solution there.
+        let mut x = 1e-4;
+        let mut state = (w0 - 1.5 * x * x, -3.0 * x);
+        let mut profile = Self {
+            radius: vec![0.0],
+            potential: vec![w0],
+            mass: vec![0.0],
+        };
+        while state.0 > 0.0 {
+            profile.radius.push(x);
+            profile.potential.push(state.0);
+            profile.mass.push(-x * x * state.1);
+
+            // Fourth order Runge-Kutta, with steps growing with the radius.
+            let h = 1e-3 * (1.0 + x);
+            let add = |s: (f64, f64), d: (f64, f64), f: f64| (s.0 + d.0 * f, s.1 + d.1 * f);
+            let k1 = derivative(x, state);
+            let k2 = derivative(x + h / 2.0, add(state, k1, h / 2.0));
+            let k3 = derivative(x + h / 2.0, add(state, k2, h / 2.0));
+            let k4 = derivative(x + h, add(state, k3, h));
+            let next = (
+                state.0 + h / 6.0 * (k1.0 + 2.0 * k2.0 + 2.0 * k3.0 + k4.0),
+                state.1 + h / 6.0 * (k1.1 + 2.0 * k2.1 + 2.0 * k3.1 + k4.1),
+            );
+            if next.0 <= 0.0 {
+                // The tidal radius, between the last two points.
+                let t = state.0 / (state.0 - next.0);
+                let tidal = x + t * h;
+                let dw = state.1 + t * (next.1 - state.1);
+                profile.radius.push(tidal);
+                profile.potential.push(0.0);
+                profile.mass.push(-tidal * tidal * dw);
+            }
+            x += h;
+            state = next;
+        }
+        profile
+    }
+
+    fn total_mass(&self) -> f64 {
+        *self.mass.last().unwrap()
+    }
+
+    /// Radius and potential within which `fraction` of the mass lies.
+    fn sample(&self, fraction: f64) -> (f64, f64) {
+        let target = fraction * self.total_mass();
+        let i = self
+            .mass
+            .partition_point(|m| *m < target)
+            .clamp(1, self.mass.len() - 1);
+        let t = (target - self.mass[i - 1]) / (self.mass[i] - self.mass[i - 1]);
+        let lerp = |v: &[f64]| v[i - 1] + t * (v[i] - v[i - 1]);
+        (lerp(&self.radius), lerp(&self.potential))
+    }
+}
+
+/// Density of a King model at potential `w`, up to a constant factor. This is
+/// `e^w erf(sqrt(w)) - sqrt(4 w / pi) (1 + 2 w / 3)`, summed as the series of the error
+/// function without its first two terms, which the rest cancels exactly.
+fn king_density(w: f64) -> f64 {
+    let mut term = 4.0 / 15.0 * w.powf(2.5);
+    let mut sum = 0.0;
+    let mut n = 2.0;
+    while term > sum * 1e-16 {
+        sum += term;
+        term *= 2.0 * w / (2.0 * n + 3.0);
+        n += 1.0;
+    }
+    2.0 / std::f64::consts::PI.sqrt() * sum
+}
+
+/// Move `objects` into the frame of their center of mass, so that a generated cluster
+/// neither drifts nor starts off center.
+fn to_center_of_mass(objects: &mut [Object]) {
+    let total: f64 = objects.iter().map(|obj| obj.dat.mass).sum();
+    if total <= 0.0 {
+        return;
+    }
+    let center: Vector3<f64> = objects
+        .iter()
+        .map(|obj| obj.dat.pos.to_vec() * obj.dat.mass)
+        .sum::<Vector3<f64>>()
+        / total;
+    let drift: Vector3<f64> = objects
+        .iter()
+        .map(|obj| obj.dat.vel * obj.dat.mass)
+        .sum::<Vector3<f64>>()
+        / total;
+    for obj in objects {
+        obj.dat.pos -= center;
+        obj.dat.vel -= drift;
+    }
+}
+
+/// Binaries of two sun-like stars on circular orbits, with separations between
+/// `separation.0` and `separation.1` AU and random orientations, spread uniformly over a
+/// cube with sides of `extent` AU centered at the origin.
+pub fn wide_binaries(
+    n_binaries: usize,
+    separation: (f64, f64),
+    extent: f64,
+    rng: &RngService,
+) -> Vec<Object> {
+    let rng = rng.child("wide_binaries");
+    let mut objs = Vec::new();
+    for i in 0..n_binaries {
+        let mut rng = rng.stream(i as u64);
+        let center = Vector3::new(
+            rng.random_range(-0.5..0.5),
+            rng.random_range(-0.5..0.5),
+            rng.random_range(-0.5..0.5),
+        ) * extent;
+        let separation = rng.random_range(separation.0..separation.1);
+        let dir = random_direction(&mut rng);
+        let normal = dir.cross(random_direction(&mut rng)).normalize();
+        let speed = (G * 2.0 * SOLAR_MASS / separation).sqrt() / 2.0;
+        for (side, sign) in [("a", 1.0), ("b", -1.0)] {
+            objs.push(Object {
+                name: format!("binary_{i}_{side}"),
+                dat: ObjectInfo {
+                    pos: Point3::from_vec(center + dir * (sign * separation / 2.0)),
+                    vel: normal.cross(dir) * (sign * speed),
+                    mass: SOLAR_MASS,
+                    pinned: false,
+                    test_particle: false,
+                },
+                color: Vector3::new(1.0, 0.9, 0.7),
+                radius: (696340e3 / AU) as f32,
+                oblateness: None,
+                radiation: None,
+                maneuvers: Vec::new(),
+                mass_rate: 0.0,
+                texture: None,
+            });
+        }
+    }
+    objs
+}
+
+/// Mass of the particles of [`fixed_cloud`] before it took an IMF, 1e4 earth masses.
+pub const CLOUD_PARTICLE_MASS: Imf = Imf::Fixed(1e4 / SOLAR_MASS);
+
+/// Initial mass function, the distribution stars are born with, in solar masses.
+#[derive(Debug, Clone, Copy)]
+pub enum Imf {
+    /// Every star has this mass.
+    Fixed(f64),
+    /// Salpeter (1955), `dN/dm ~ m^-2.35` between `min`, which must be above zero, and
+    /// `max`.
+    Salpeter { min: f64, max: f64 },
+    /// Kroupa (2001), `dN/dm ~ m^-alpha` with alpha 0.3 below 0.08 solar masses, 1.3 up to
+    /// 0.5 and 2.3 above, between `min` and `max`.
+    Kroupa { min: f64, max: f64 },
+}
+
+impl Imf {
+    /// Upper ends and exponents of the pieces of the power law.
+    fn pieces(&self) -> &'static [(f64, f64)] {
+        match self {
+            Imf::Fixed(_) => &[],
+            Imf::Salpeter { .. } => &[(f64::INFINITY, 2.35)],
+            Imf::Kroupa { .. } => &[(0.08, 0.3), (0.5, 1.3), (f64::INFINITY, 2.3)],
+        }
+    }
+
+    /// Draw a mass, in solar masses.
+    pub fn sample(&self, rng: &mut impl Rng) -> f64 {
+        let (min, max) = match *self {
+            Imf::Fixed(mass) => return mass,
+            Imf::Salpeter { min, max } | Imf::Kroupa { min, max } => (min, max),
+        };
+        // Number of stars in each piece within (min, max), with the pieces scaled to meet.
+        let mut scale = 1.0;
+        let mut lower: f64 = 0.0;
+        let mut pieces = Vec::new();
+        for (i, &(upper, alpha)) in self.pieces().iter().enumerate() {
+            if i > 0 {
+                let previous = self.pieces()[i - 1].1;
+                scale *= lower.powf(alpha - previous);
+            }
+            let (lo, hi) = (lower.max(min), upper.min(max));
+            if lo < hi {
+                let e = 1.0 - alpha;
+                let count = scale * (hi.powf(e) - lo.powf(e)) / e;
+                pieces.push((lo, hi, e, count));
+            }
+            lower = upper;
+        }
+        let total: f64 = pieces.iter().map(|p| p.3).sum();
+        let mut u = rng.random_range(0.0..total);
+        for &(lo, hi, e, count) in &pieces {
+            if u < count {
+                let f = u / count;
+                return (lo.powf(e) + f * (hi.powf(e) - lo.powf(e))).powf(1.0 / e);
+            }
+            u -= count;
+        }
+        max
+    }
+
+    /// Draw `n` masses in solar masses, each from its own stream of `rng`, so the masses
+    /// do not change the numbers drawn for anything else.
+    fn sample_many(&self, n: usize, rng: &RngService) -> Vec<f64> {
+        let rng = rng.child("masses");
+        (0..n)
+            .map(|i| self.sample(&mut rng.stream(i as u64)))
+            .collect()
+    }
+}
+
+/// Radius in AU and color of a main sequence star of `mass` solar masses, from the
+/// usual power laws in mass, with the color going from red through the sun to blue with
+/// the temperature.
+pub fn main_sequence_star(mass: f64) -> (f32, Vector3<f32>) {
+    let radius = if mass < 1.0 {
+        mass.powf(0.8)
+    } else {
+        mass.powf(0.57)
+    };
+    // Luminosity goes as m^3.5, so the temperature as (L / R^2)^(1 / 4).
+    let temperature = 5772.0 * (mass.powf(3.5) / (radius * radius)).powf(0.25);
+    let cool = Vector3::new(1.0, 0.55, 0.35);
+    let sun = Vector3::new(1.0, 0.95, 0.85);
+    let hot = Vector3::new(0.65, 0.75, 1.0);
+    // Fully red at half the temperature of the sun, and fully blue at twice it.
+    let t = (temperature / 5772.0).log2().clamp(-1.0, 1.0) as f32;
+    let color = if t < 0.0 {
+        sun + (cool - sun) * -t
+    } else {
+        sun + (hot - sun) * t
+    };
+    ((SOLAR_RADIUS * radius) as f32, color)
+}
+
+/// Uniformly distributed unit vector.
+fn random_direction(rng: &mut impl Rng) -> Vector3<f64> {
+    let z: f64 = rng.random_range(-1.0..1.0);
+    let angle = rng.random_range(0.0..std::f64::consts::TAU);
+    let r = (1.0 - z * z).sqrt();
+    Vector3::new(r * angle.cos(), r * angle.sin(), z)
+}
+
+/// Normally distributed number with mean zero and standard deviation one, by the
+/// Box-Muller transform.
+fn random_gaussian(rng: &mut impl Rng) -> f64 {
+    let u: f64 = rng.random_range(f64::EPSILON..1.0);
+    let angle = rng.random_range(0.0..std::f64::consts::TAU);
+    (-2.0 * u.ln()).sqrt() * angle.cos()
+}
+
+/// Grid of particles orbiting a pinned mass, with masses drawn from `imf`. With
+/// [`Imf::Fixed`] the particles are colored by their place in the grid, and otherwise
+/// like main sequence stars of their mass.
+pub fn fixed_cloud(n_objects: usize, imf: Imf, rng: &RngService) -> Vec<Object> {
+    let masses = imf.sample_many(n_objects, &rng.child("fixed_cloud"));
+    let min = -10.0;
+    let max = 10.0;
+    let idx_step = (n_objects as f64).cbrt().ceil() as usize;
+    let step = (max - min) / (idx_step as f64);
 
     let mut objs = Vec::new();
     objs.push(Object {
         name: "Center".to_owned(),
         dat: ObjectInfo {
-            pos: Point3::new(0.0, 0.0, 0.0),
+            pos: Point3::new(-15.0, 0.0, 0.0),
             vel: Vector3::new(0.0, 0.0, 0.0),
             mass: 1e7,
+            pinned: true,
+            test_particle: false,
         },
         color: Vector3::new(1.0, 1.0, 1.0),
         radius: (1e5 / AU) as f32,
+        oblateness: None,
+        radiation: None,
+        maneuvers: Vec::new(),
+        mass_rate: 0.0,
+        texture: None,
     });
-    for i in 0..n_objects {
-        let theta = pi_step * ((i / idx_step) % idx_step) as f64;
-        let phi = 2.0 * pi_step * (i % idx_step) as f64;
-        let radius = 10.0;
 
+    for (i, &mass) in masses.iter().enumerate() {
         let pos = Point3::new(
-            radius * theta.sin() * phi.cos(),
-            radius * theta.sin() * phi.sin(),
-            radius * theta.cos(),
+            min + (i % idx_step) as f64 * step,
+            min + ((i / idx_step) % idx_step) as f64 * step,
+            min + ((i / (idx_step * idx_step)) % idx_step) as f64 * step,
         );
-
         let rotate_around = Vector3::new(0.0, 1.0, 1.0).normalize();
-        let radius = pos.to_vec().magnitude();
-        let norm_pos = pos.to_vec().normalize();
+        let radius = (pos - Point3::new(-15.0, 0.0, 0.0)).magnitude();
+        let norm_pos = (pos - Point3::new(-15.0, 0.0, 0.0)).normalize();
         // let vel = rotate_around.cross(norm_pos) * (1e6 / AU) * (radius);
         let vel_basis = (G * 1e7 / radius).sqrt();
-        let vel = rotate_around.cross(norm_pos).normalize() * vel_basis;
+        let vel = rotate_around.cross(norm_pos) * vel_basis;
 
-        let col = (pos.to_vec() + Vector3::new(radius, radius, radius))
-            .normalize()
-            .cast()
-            .unwrap();
+        let (radius, col) = match imf {
+            Imf::Fixed(_) => (
+                (1e4 / AU) as f32,
+                (pos.to_vec() - Vector3::new(min, min, min))
+                    .normalize()
+                    .cast()
+                    .unwrap(),
+            ),
+            _ => main_sequence_star(mass),
+        };
         objs.push(Object {
             name: format!("particle_{i}"),
             dat: ObjectInfo {
-                pos: pos,
+                pos,
                 vel,
-                mass: 0.0,
+                mass: mass * SOLAR_MASS,
+                pinned: false,
+                test_particle: false,
             },
             color: col,
-            radius: (1e4 / AU) as f32,
+            radius,
+            oblateness: None,
+            radiation: None,
+            maneuvers: Vec::new(),
+            mass_rate: 0.0,
+            texture: None,
         });
     }
 
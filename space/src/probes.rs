@@ -0,0 +1,215 @@
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::Context;
+use cgmath::{InnerSpace, Point3};
+
+use crate::{
+    constants::{AU, G, PROBE_MAX_SAMPLES},
+    objects::Objects,
+    sim::SimTime,
+};
+
+/// Where a probe is placed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProbeAnchor {
+    /// Fixed point in world space, in AU.
+    Point(Point3<f64>),
+    /// Follows the object with this index.
+    Body(usize),
+}
+
+/// Quantities measured by a probe at a single tick.
+#[derive(Debug, Clone)]
+pub struct ProbeSample {
+    pub time: SimTime,
+    /// Gravitational potential in J/kg. An attached body does not count towards its
+    /// own probe.
+    pub potential: f64,
+    /// Number of objects within the probe radius.
+    pub inside: usize,
+    /// Number of objects that entered the probe radius since the previous sample.
+    pub entered: usize,
+    /// Number of objects that left the probe radius since the previous sample.
+    pub left: usize,
+}
+
+/// Virtual instrument recording local quantities over time.
+pub struct Probe {
+    pub name: String,
+    pub anchor: ProbeAnchor,
+    /// Radius in AU used for counting objects.
+    pub radius: f64,
+    samples: VecDeque<ProbeSample>,
+    /// Which objects were within the radius at the previous sample.
+    inside: Vec<bool>,
+}
+
+impl Probe {
+    pub fn new(name: String, anchor: ProbeAnchor, radius: f64) -> Self {
+        Self {
+            name,
+            anchor,
+            radius,
+            samples: VecDeque::new(),
+            inside: Vec::new(),
+        }
+    }
+
+    pub fn samples(&self) -> &VecDeque<ProbeSample> {
+        &self.samples
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+        self.inside.clear();
+    }
+
+    /// Current position of the probe, or `None` if it is attached to an object that
+    /// does not exist.
+    pub fn position(&self, objects: &Objects) -> Option<Point3<f64>> {
+        match self.anchor {
+            ProbeAnchor::Point(pos) => Some(pos),
+            ProbeAnchor::Body(idx) if idx < objects.num_objects() => {
+                Some(Point3::from(*objects.position_of(idx)).cast().unwrap())
+            }
+            ProbeAnchor::Body(_) => None,
+        }
+    }
+
+    fn sample(&mut self, time: SimTime, objects: &Objects) {
+        let Some(pos) = self.position(objects) else {
+            return;
+        };
+        let own = match self.anchor {
+            ProbeAnchor::Body(idx) => Some(idx),
+            ProbeAnchor::Point(_) => None,
+        };
+        // The first sample has nothing to compare against, so it counts no crossings.
+        let first = self.inside.len() != objects.num_objects();
+        if first {
+            self.inside = vec![false; objects.num_objects()];
+        }
+
+        let radius_sq = self.radius * self.radius;
+        let mut sample = ProbeSample {
+            time,
+            potential: 0.0,
+            inside: 0,
+            entered: 0,
+            left: 0,
+        };
+        for (idx, obj) in objects.objects().iter().enumerate() {
+            if Some(idx) == own {
+                continue;
+            }
+            let obj_pos: Point3<f64> = Point3::from(*objects.position_of(idx)).cast().unwrap();
+            let dist_sq = (obj_pos - pos).magnitude2();
+            if dist_sq > 0.0 {
+                sample.potential -= G * obj.dat.mass / dist_sq.sqrt();
+            }
+
+            let inside = dist_sq <= radius_sq;
+            if inside {
+                sample.inside += 1;
+            }
+            if !first && inside != self.inside[idx] {
+                if inside {
+                    sample.entered += 1;
+                } else {
+                    sample.left += 1;
+                }
+            }
+            self.inside[idx] = inside;
+        }
+        // G is in AU^3 / (M0 s^2), so the potential is in AU^2 / s^2.
+        sample.potential *= AU * AU;
+
+        if self.samples.len() == PROBE_MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+}
+
+/// All probes placed by the user. Probes are sampled from the displayed object
+/// positions, so they work the same for live simulations and replays.
+#[derive(Default)]
+pub struct ProbeSet {
+    probes: Vec<Probe>,
+    last_tick: Option<u64>,
+}
+
+impl ProbeSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn probes(&self) -> &[Probe] {
+        &self.probes
+    }
+
+    pub fn probes_mut(&mut self) -> &mut [Probe] {
+        &mut self.probes
+    }
+
+    pub fn add(&mut self, probe: Probe) {
+        self.probes.push(probe);
+    }
+
+    pub fn remove(&mut self, idx: usize) {
+        self.probes.remove(idx);
+    }
+
+    /// Sample every probe, if the objects have moved to a new tick. Going back in time,
+    /// e.g. by seeking in a replay, starts all series over.
+    pub fn update(&mut self, time: SimTime, objects: &Objects) {
+        match self.last_tick {
+            Some(last) if last == time.ticks => return,
+            Some(last) if time.ticks < last => {
+                for probe in &mut self.probes {
+                    probe.clear();
+                }
+            }
+            _ => (),
+        }
+        self.last_tick = Some(time.ticks);
+
+        for probe in &mut self.probes {
+            probe.sample(time, objects);
+        }
+    }
+
+    /// Write all recorded samples to a CSV file.
+    pub fn export_csv(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let file =
+            File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+        let mut writer = BufWriter::new(file);
+        writeln!(
+            writer,
+            "probe,tick,time_s,potential_j_kg,inside,entered,left"
+        )?;
+        for probe in &self.probes {
+            for s in &probe.samples {
+                writeln!(
+                    writer,
+                    "{},{},{},{},{},{},{}",
+                    probe.name,
+                    s.time.ticks,
+                    s.time.seconds(),
+                    s.potential,
+                    s.inside,
+                    s.entered,
+                    s.left
+                )?;
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,28 @@
+//! Sampling profiler for real runs, as opposed to the benchmarks.
+
+use std::{fs::File, path::Path, time::Duration};
+
+use crate::constants::{PROFILE_FREQUENCY, SIM_THREAD_NAME};
+
+/// Sample the whole process for `duration`, then write a flamegraph of the simulation
+/// threads to `path`. Blocks for the whole duration, so run it on its own thread.
+pub fn profile_sim_threads(duration: Duration, path: impl AsRef<Path>) -> anyhow::Result<()> {
+    let guard = pprof::ProfilerGuardBuilder::default()
+        .frequency(PROFILE_FREQUENCY)
+        // Unwinding through these can deadlock in the signal handler.
+        .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+        .build()?;
+    std::thread::sleep(duration);
+
+    let mut report = guard.report().build()?;
+    // The UI and render threads are sampled too, but are not what we are after here.
+    report
+        .data
+        .retain(|frames, _| frames.thread_name.starts_with(SIM_THREAD_NAME));
+    if report.data.is_empty() {
+        anyhow::bail!("No samples from the simulation threads");
+    }
+    report.flamegraph(File::create(path.as_ref())?)?;
+    println!("Wrote flamegraph to {}", path.as_ref().display());
+    Ok(())
+}
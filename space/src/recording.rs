@@ -0,0 +1,410 @@
+//! Compact on-disk format for sampled trajectories.
+//!
+//! Positions are quantized per object to a tolerance scaled to its orbit, then stored as
+//! zigzag varint deltas from the previous frame. Every `keyframe_interval` frames
+//! the absolute quantized positions are stored instead, so that readers can seek
+//! without decoding the entire file.
+//!
+//! Layout:
+//! - header: magic, version, number of objects, keyframe interval, then for each
+//!   object its tolerance, color and radius as `f32`s and its name.
+//! - frames: `u32` body length, `u64` tick, `f64` delta, `f64` simulated seconds,
+//!   `u8` keyframe flag, then three varints per object.
+//!
+//! Version 1 headers had only the tolerance of each object, and its frames no simulated
+//! seconds. Such files are refused rather than misread.
+
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use anyhow::{Context, bail, ensure};
+
+use cgmath::{EuclideanSpace, InnerSpace};
+
+use crate::{
+    Object,
+    constants::{RECORDING_MIN_TOLERANCE, RECORDING_RELATIVE_TOLERANCE},
+    sim::SimTime,
+};
+
+const MAGIC: &[u8; 8] = b"NBODYREC";
+const VERSION: u32 = 2;
+
+/// Size of the fixed part of a frame, after the length prefix.
+const FRAME_HEADER_LEN: usize = 8 + 8 + 8 + 1;
+/// Smallest size of an object in the header, with an empty name.
+const OBJECT_HEADER_LEN: u64 = 5 * 4 + 4;
+
+/// Whether `reader` starts with the header of a recording.
+pub fn is_recording(mut reader: impl Read) -> bool {
+    let mut magic = [0u8; 8];
+    reader.read_exact(&mut magic).is_ok() && &magic == MAGIC
+}
+
+fn quantize(value: f32, tolerance: f32) -> i64 {
+    (value as f64 / tolerance as f64).round() as i64
+}
+
+fn dequantize(value: i64, tolerance: f32) -> f32 {
+    (value as f64 * tolerance as f64) as f32
+}
+
+fn write_varint(out: &mut Vec<u8>, value: i64) {
+    // Zigzag encode, so that small negative numbers are small as well.
+    let mut v = ((value << 1) ^ (value >> 63)) as u64;
+    while v >= 0x80 {
+        out.push((v as u8) | 0x80);
+        v >>= 7;
+    }
+    out.push(v as u8);
+}
+
+fn read_varint(input: &[u8], pos: &mut usize) -> anyhow::Result<i64> {
+    let mut v = 0u64;
+    let mut shift = 0;
+    loop {
+        let Some(&byte) = input.get(*pos) else {
+            bail!("Truncated frame");
+        };
+        *pos += 1;
+        if shift >= 64 {
+            bail!("Invalid varint");
+        }
+        v |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+    }
+    Ok(((v >> 1) as i64) ^ -((v & 1) as i64))
+}
+
+/// Static description of a recorded object, stored in the header.
+#[derive(Debug, Clone)]
+pub struct RecordedObject {
+    pub name: String,
+    pub color: [f32; 3],
+    pub radius: f32,
+    /// Positions of this object are stored to within half of this value.
+    pub tolerance: f32,
+}
+
+impl RecordedObject {
+    /// Describe `obj`, with a tolerance scaled to the size of its orbit, or of the object
+    /// itself for those near the origin. Anything finer would be lost to `f32` precision
+    /// once the object is that far out.
+    pub fn from_object(obj: &Object) -> Self {
+        let scale = (obj.dat.pos.to_vec().magnitude() as f32).max(obj.radius);
+        Self {
+            name: obj.name.clone(),
+            color: obj.color.into(),
+            radius: obj.radius,
+            tolerance: (scale * RECORDING_RELATIVE_TOLERANCE).max(RECORDING_MIN_TOLERANCE),
+        }
+    }
+}
+
+/// Writer for the recording format.
+pub struct RecordingWriter<W: Write> {
+    writer: W,
+    tolerances: Vec<f32>,
+    keyframe_interval: u32,
+    frames_written: u64,
+    last: Vec<[i64; 3]>,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> RecordingWriter<W> {
+    pub fn new(
+        mut writer: W,
+        objects: &[RecordedObject],
+        keyframe_interval: u32,
+    ) -> anyhow::Result<Self> {
+        if objects
+            .iter()
+            .any(|o| o.tolerance.is_nan() || o.tolerance <= 0.0)
+        {
+            bail!("Tolerances must be positive");
+        }
+        let keyframe_interval = keyframe_interval.max(1);
+
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&(objects.len() as u32).to_le_bytes())?;
+        writer.write_all(&keyframe_interval.to_le_bytes())?;
+        for obj in objects {
+            for v in [
+                obj.tolerance,
+                obj.color[0],
+                obj.color[1],
+                obj.color[2],
+                obj.radius,
+            ] {
+                writer.write_all(&v.to_le_bytes())?;
+            }
+            writer.write_all(&(obj.name.len() as u32).to_le_bytes())?;
+            writer.write_all(obj.name.as_bytes())?;
+        }
+        let tolerances: Vec<_> = objects.iter().map(|o| o.tolerance).collect();
+
+        Ok(Self {
+            writer,
+            last: vec![[0; 3]; tolerances.len()],
+            tolerances,
+            keyframe_interval,
+            frames_written: 0,
+            buffer: Vec::new(),
+        })
+    }
+
+    pub fn num_objects(&self) -> usize {
+        self.tolerances.len()
+    }
+
+    pub fn write_frame(&mut self, time: SimTime, positions: &[[f32; 3]]) -> anyhow::Result<()> {
+        if positions.len() != self.tolerances.len() {
+            bail!(
+                "Expected {} positions, got {}",
+                self.tolerances.len(),
+                positions.len()
+            );
+        }
+
+        let keyframe = self
+            .frames_written
+            .is_multiple_of(self.keyframe_interval as u64);
+
+        self.buffer.clear();
+        self.buffer.extend_from_slice(&time.ticks.to_le_bytes());
+        self.buffer.extend_from_slice(&time.delta.to_le_bytes());
+        self.buffer.extend_from_slice(&time.seconds.to_le_bytes());
+        self.buffer.push(u8::from(keyframe));
+
+        for ((pos, tol), last) in positions
+            .iter()
+            .zip(self.tolerances.iter())
+            .zip(self.last.iter_mut())
+        {
+            for i in 0..3 {
+                let q = quantize(pos[i], *tol);
+                // Wrapping, so that corrupt or saturated values still round-trip.
+                let value = if keyframe { q } else { q.wrapping_sub(last[i]) };
+                write_varint(&mut self.buffer, value);
+                last[i] = q;
+            }
+        }
+
+        self.writer
+            .write_all(&(self.buffer.len() as u32).to_le_bytes())?;
+        self.writer.write_all(&self.buffer)?;
+        self.frames_written += 1;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> anyhow::Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FrameInfo {
+    pub tick: u64,
+    pub delta: f64,
+    /// Simulated seconds since the start of the run.
+    pub seconds: f64,
+    offset: u64,
+    len: u32,
+    keyframe: bool,
+}
+
+impl FrameInfo {
+    pub fn time(&self) -> SimTime {
+        SimTime {
+            ticks: self.tick,
+            delta: self.delta,
+            seconds: self.seconds,
+        }
+    }
+}
+
+/// Reader for the recording format, with random access to frames.
+pub struct RecordingReader<R: Read + Seek> {
+    reader: R,
+    objects: Vec<RecordedObject>,
+    tolerances: Vec<f32>,
+    frames: Vec<FrameInfo>,
+    current: Vec<[i64; 3]>,
+    current_frame: Option<usize>,
+    buffer: Vec<u8>,
+}
+
+impl<R: Read + Seek> RecordingReader<R> {
+    /// Open a recording, scanning it once to index the frames.
+    pub fn new(mut reader: R) -> anyhow::Result<Self> {
+        let mut magic = [0u8; 8];
+        reader
+            .read_exact(&mut magic)
+            .context("Failed to read recording header")?;
+        if &magic != MAGIC {
+            bail!("Not a recording file");
+        }
+        let version = read_u32(&mut reader)?;
+        if version != VERSION {
+            bail!("Unsupported recording version {version}, expected {VERSION}");
+        }
+        let num_objects = read_u32(&mut reader)? as u64;
+        let _keyframe_interval = read_u32(&mut reader)?;
+        let header_end = reader.stream_position()?;
+        let file_len = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(header_end))?;
+        // Counts in the header are checked against the size of the file before anything
+        // is allocated for them, so a corrupt file fails instead of exhausting memory.
+        ensure!(
+            num_objects * OBJECT_HEADER_LEN <= file_len - header_end,
+            "Truncated recording header"
+        );
+        let mut objects = Vec::with_capacity(num_objects as usize);
+        for _ in 0..num_objects {
+            let mut values = [0.0; 5];
+            for v in values.iter_mut() {
+                *v = f32::from_bits(read_u32(&mut reader)?);
+            }
+            let name_len = read_u32(&mut reader)? as u64;
+            ensure!(
+                name_len <= file_len - reader.stream_position()?,
+                "Truncated recording header"
+            );
+            let mut name = vec![0u8; name_len as usize];
+            reader.read_exact(&mut name)?;
+            objects.push(RecordedObject {
+                name: String::from_utf8(name).context("Invalid object name")?,
+                color: [values[1], values[2], values[3]],
+                radius: values[4],
+                tolerance: values[0],
+            });
+        }
+        let tolerances: Vec<_> = objects.iter().map(|o| o.tolerance).collect();
+
+        let mut frames = Vec::new();
+        let mut offset = reader.stream_position()?;
+        let mut header = [0u8; FRAME_HEADER_LEN];
+        loop {
+            let Ok(len) = read_u32(&mut reader) else {
+                break;
+            };
+            let body_start = offset + 4;
+            let end = body_start + len as u64;
+            // A partially written frame at the end, e.g. after a crash, is ignored.
+            if (len as usize) < FRAME_HEADER_LEN || end > file_len {
+                break;
+            }
+            reader.read_exact(&mut header)?;
+            reader.seek(SeekFrom::Start(end))?;
+            let tick = u64::from_le_bytes(header[0..8].try_into().unwrap());
+            let delta = f64::from_le_bytes(header[8..16].try_into().unwrap());
+            let seconds = f64::from_le_bytes(header[16..24].try_into().unwrap());
+            frames.push(FrameInfo {
+                tick,
+                delta,
+                seconds,
+                keyframe: header[FRAME_HEADER_LEN - 1] != 0,
+                offset: body_start,
+                len,
+            });
+            offset = end;
+        }
+
+        Ok(Self {
+            reader,
+            objects,
+            current: vec![[0; 3]; tolerances.len()],
+            tolerances,
+            frames,
+            current_frame: None,
+            buffer: Vec::new(),
+        })
+    }
+
+    pub fn num_objects(&self) -> usize {
+        self.tolerances.len()
+    }
+
+    pub fn objects(&self) -> &[RecordedObject] {
+        &self.objects
+    }
+
+    pub fn num_frames(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn frame_info(&self, idx: usize) -> Option<&FrameInfo> {
+        self.frames.get(idx)
+    }
+
+    pub fn frames(&self) -> &[FrameInfo] {
+        &self.frames
+    }
+
+    /// Decode frame `idx` into `out`. Reading frames in order only decodes each
+    /// frame once, seeking backwards restarts from the nearest preceding keyframe.
+    pub fn read_frame(&mut self, idx: usize, out: &mut Vec<[f32; 3]>) -> anyhow::Result<()> {
+        if idx >= self.frames.len() {
+            bail!("Frame {idx} out of range");
+        }
+
+        let start = match self.current_frame {
+            Some(cur) if cur <= idx && !self.frames[cur + 1..=idx].iter().any(|f| f.keyframe) => {
+                cur + 1
+            }
+            _ => {
+                let Some(key) = self.frames[..=idx].iter().rposition(|f| f.keyframe) else {
+                    bail!("No keyframe before frame {idx}");
+                };
+                key
+            }
+        };
+
+        for i in start..=idx {
+            self.decode(i)?;
+        }
+
+        out.clear();
+        out.extend(
+            self.current
+                .iter()
+                .zip(self.tolerances.iter())
+                .map(|(q, tol)| q.map(|v| dequantize(v, *tol))),
+        );
+        Ok(())
+    }
+
+    fn decode(&mut self, idx: usize) -> anyhow::Result<()> {
+        let info = &self.frames[idx];
+        self.buffer.resize(info.len as usize, 0);
+        self.reader.seek(SeekFrom::Start(info.offset))?;
+        self.reader.read_exact(&mut self.buffer)?;
+
+        // Until this frame is fully decoded, `current` holds no frame that later deltas
+        // could be applied to.
+        self.current_frame = None;
+        let mut pos = FRAME_HEADER_LEN;
+        for cur in self.current.iter_mut() {
+            for v in cur.iter_mut() {
+                let val = read_varint(&self.buffer, &mut pos)?;
+                if info.keyframe {
+                    *v = val;
+                } else {
+                    *v = v.wrapping_add(val);
+                }
+            }
+        }
+        self.current_frame = Some(idx);
+        Ok(())
+    }
+}
+
+fn read_u32(reader: &mut impl Read) -> anyhow::Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
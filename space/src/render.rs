@@ -1,20 +1,32 @@
 use std::sync::OnceLock;
 
 use bytemuck::cast_slice;
+use cgmath::{Point3, Vector3, Zero};
 use wgpu::{
-    BindGroup, Buffer, BufferDescriptor, BufferUsages, CommandEncoder, Device, Queue,
-    RenderPassDescriptor, ShaderModule, Texture, TextureFormat, TextureView,
+    Adapter, BindGroup, Buffer, BufferDescriptor, BufferUsages, CommandEncoder, CompareFunction,
+    DepthBiasState, DepthStencilState, Device, Extent3d, Features, Queue, RenderPassDescriptor,
+    ShaderModule, StencilState, Texture, TextureDescriptor, TextureFormat, TextureUsages,
+    TextureView,
     util::{BufferInitDescriptor, DeviceExt},
 };
 use winit::dpi::PhysicalSize;
 
 use crate::{
     ShaderConstants,
+    bloom::BloomPipeline,
     camera::Camera,
     circle_pipeline::CircleDrawPipeline,
-    constants::{MIN_CIRCLE_SIZE, TRAIL_MAX_LENGTH},
-    objects::{OBJECT_STRIDE, Objects},
+    constants::{
+        BLOOM_INTENSITY, BLOOM_THRESHOLD, MAX_CIRCLE_SIZE, MIN_CIRCLE_SIZE, TRAIL_FADE_TIME,
+    },
+    mesh_pipeline::{MeshDrawPipeline, MeshKind, MeshVertex},
+    objects::{Objects, Vertex},
+    permutations::ShaderOptions,
     pipeline::LineDrawPipeline,
+    pipeline_cache,
+    planet_textures::PlanetTextures,
+    sim::FrameTime,
+    tessellation::{horizon_triangles, large_projected_size},
 };
 
 pub static SHADER: OnceLock<ShaderModule> = OnceLock::new();
@@ -26,66 +38,407 @@ pub fn get_or_init_shader(device: &Device) -> &ShaderModule {
     })
 }
 
+/// Format of the depth buffer shared by every pipeline.
+pub(crate) const DEPTH_FORMAT: TextureFormat = TextureFormat::Depth32Float;
+
+/// Depth test for the pipelines. Depth is reversed, see `Camera::projection`, so nearer
+/// fragments have greater depth. Translucent surfaces are tested without writing depth,
+/// so they don't hide what is drawn behind them later.
+pub(crate) fn depth_stencil_state(write: bool) -> DepthStencilState {
+    DepthStencilState {
+        format: DEPTH_FORMAT,
+        depth_write_enabled: write,
+        depth_compare: CompareFunction::GreaterEqual,
+        stencil: StencilState::default(),
+        bias: DepthBiasState::default(),
+    }
+}
+
+/// Format the scene is drawn to before bloom, so bright parts can exceed what the output
+/// can show.
+pub(crate) const HDR_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+
+/// Sample counts the renderer can draw with on `device`, always including 1.
+pub fn supported_sample_counts(adapter: &Adapter, device: &Device) -> Vec<u32> {
+    // Without this feature only the counts every device supports may be used.
+    if !device
+        .features()
+        .contains(Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES)
+    {
+        return vec![1, 4];
+    }
+    let color = adapter.get_texture_format_features(HDR_FORMAT).flags;
+    let depth = adapter.get_texture_format_features(DEPTH_FORMAT).flags;
+    [1, 2, 4, 8, 16]
+        .into_iter()
+        .filter(|&count| color.sample_count_supported(count) && depth.sample_count_supported(count))
+        .collect()
+}
+
+/// Bytes allocated on the GPU for `texture`.
+pub(crate) fn texture_memory(texture: &Texture) -> u64 {
+    let size = texture.size();
+    size.width as u64
+        * size.height as u64
+        * texture.sample_count() as u64
+        * texture.format().block_copy_size(None).unwrap_or(4) as u64
+}
+
+/// How trails fade towards their end. The values match the `TRAIL_FADE_*` constants of
+/// the shaders.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum TrailFade {
+    /// By position in the trail, so every trail is drawn at full length.
+    Samples = 0,
+    /// Linearly with simulated age, gone after the fade time.
+    Linear = 1,
+    /// Halving in opacity with every fade time of simulated age.
+    Exponential = 2,
+}
+
+/// What the color of trails shows. The values match the `TRAIL_COLOR_*` constants of the
+/// shaders.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum TrailColor {
+    /// The color of the object.
+    Object = 0,
+    /// Speed of the object, on a logarithmic scale over the speeds of the latest samples.
+    Speed = 1,
+    /// Acceleration of the object, scaled like `Speed`.
+    Acceleration = 2,
+}
+
+/// Colormaps for trails colored by motion. The values match the `COLORMAP_*` constants of
+/// the shaders.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum Colormap {
+    Viridis = 0,
+    Plasma = 1,
+    /// Also readable with red-green color blindness.
+    Cividis = 2,
+}
+
+impl Colormap {
+    pub const ALL: [Colormap; 3] = [Colormap::Viridis, Colormap::Plasma, Colormap::Cividis];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Colormap::Viridis => "Viridis",
+            Colormap::Plasma => "Plasma",
+            Colormap::Cividis => "Cividis",
+        }
+    }
+}
+
+/// How trails are drawn.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TrailStyle {
+    pub fade: TrailFade,
+    /// Simulated seconds over which trails fade by age.
+    pub fade_time: f32,
+    /// Opacity of the newest part of the trails, from 0 to 1.
+    pub opacity: f32,
+    pub color: TrailColor,
+    pub colormap: Colormap,
+}
+
+impl Default for TrailStyle {
+    fn default() -> Self {
+        Self {
+            fade: TrailFade::Samples,
+            fade_time: TRAIL_FADE_TIME,
+            opacity: 1.0,
+            color: TrailColor::Object,
+            colormap: Colormap::Viridis,
+        }
+    }
+}
+
+/// Textures the scene is drawn to: the HDR texture read by the bloom, the depth buffer,
+/// and with multisampling the samples that are resolved into the HDR texture at the end
+/// of the pass.
+struct RenderTargets {
+    hdr: Texture,
+    hdr_view: TextureView,
+    depth: Texture,
+    depth_view: TextureView,
+    color: Option<(Texture, TextureView)>,
+}
+
+impl RenderTargets {
+    fn new(device: &Device, size: Extent3d, sample_count: u32) -> Self {
+        let target = |label, format, sample_count, usage| {
+            let texture = device.create_texture(&TextureDescriptor {
+                label: Some(label),
+                size: Extent3d {
+                    width: size.width.max(1),
+                    height: size.height.max(1),
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: TextureUsages::RENDER_ATTACHMENT | usage,
+                view_formats: &[],
+            });
+            let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+            (texture, view)
+        };
+        let (hdr, hdr_view) = target("hdr texture", HDR_FORMAT, 1, TextureUsages::TEXTURE_BINDING);
+        let (depth, depth_view) = target(
+            "depth texture",
+            DEPTH_FORMAT,
+            sample_count,
+            TextureUsages::empty(),
+        );
+        let color = (sample_count > 1).then(|| {
+            target(
+                "multisampled texture",
+                HDR_FORMAT,
+                sample_count,
+                TextureUsages::empty(),
+            )
+        });
+        Self {
+            hdr,
+            hdr_view,
+            depth,
+            depth_view,
+            color,
+        }
+    }
+
+    /// Whether these are the targets for drawing to `output` with `sample_count`.
+    fn matches(&self, output: &Texture, sample_count: u32) -> bool {
+        self.hdr.size() == output.size() && self.depth.sample_count() == sample_count
+    }
+
+    /// Bytes allocated on the GPU.
+    fn memory_usage(&self) -> u64 {
+        [&self.hdr, &self.depth]
+            .into_iter()
+            .chain(self.color.as_ref().map(|(color, _)| color))
+            .map(texture_memory)
+            .sum()
+    }
+}
+
 pub struct Renderer {
     window_size: PhysicalSize<u32>,
+    trail_length: usize,
     point_buffer: Buffer,
     instance_buffer: Buffer,
     camera_bind_group: BindGroup,
+    sample_count: u32,
+    targets: RenderTargets,
+    bloom: BloomPipeline,
+    bloom_intensity: f32,
+    trail_style: TrailStyle,
+    textures: PlanetTextures,
     line_pipeline: LineDrawPipeline,
     circle_pipeline: CircleDrawPipeline,
+    mesh_pipeline: MeshDrawPipeline,
+    /// Objects too large on screen to draw as circles.
+    sphere_pipeline: MeshDrawPipeline,
+    sphere_vertices: Vec<MeshVertex>,
+    /// The most massive object, which lights the others.
+    light_source: Option<usize>,
 }
 
 impl Renderer {
+    /// Renderer drawing to textures of `texture_format`.
     pub fn new(
         device: &Device,
         texture_format: TextureFormat,
+        sample_count: u32,
         size: PhysicalSize<u32>,
         camera: &Camera,
         objects: &mut Objects,
     ) -> Self {
+        // Before the instance buffer is filled, since it refers to the layers.
+        let textures = PlanetTextures::new(device, objects);
         let instance_buffer = device.create_buffer_init(&BufferInitDescriptor {
             label: Some("instance buffer"),
-            contents: cast_slice(objects.descriptions_mut()),
-            usage: BufferUsages::VERTEX,
+            contents: cast_slice(objects.descriptions()),
+            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
         });
         let num_objects = objects.num_objects();
+        let trail_length = objects.trail_length();
 
         let camera_layout = device.create_bind_group_layout(&Camera::bind_group_layout());
         let camera_bind_group = camera.create_bind_group(&camera_layout, device);
 
-        let line_pipeline =
-            LineDrawPipeline::new(device, texture_format, &camera_layout, num_objects);
+        let line_pipeline = LineDrawPipeline::new(
+            device,
+            HDR_FORMAT,
+            sample_count,
+            &camera_layout,
+            num_objects,
+            trail_length,
+        );
+        let point_buffer = Self::point_buffer(device, num_objects, trail_length);
 
-        let point_buffer = device.create_buffer(&BufferDescriptor {
-            label: Some("pos_buffer"),
-            size: (num_objects * OBJECT_STRIDE) as u64,
-            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
+        let circle_pipeline = CircleDrawPipeline::new(
+            device,
+            HDR_FORMAT,
+            sample_count,
+            &camera_layout,
+            textures.layout(),
+        );
+        let mesh_pipeline = MeshDrawPipeline::new(
+            device,
+            HDR_FORMAT,
+            sample_count,
+            &camera_layout,
+            textures.layout(),
+            MeshKind::Translucent,
+        );
+        let sphere_pipeline = MeshDrawPipeline::new(
+            device,
+            HDR_FORMAT,
+            sample_count,
+            &camera_layout,
+            textures.layout(),
+            MeshKind::Sphere,
+        );
 
-        let circle_pipeline = CircleDrawPipeline::new(device, texture_format, &camera_layout);
+        let target_size = Extent3d {
+            width: size.width,
+            height: size.height,
+            depth_or_array_layers: 1,
+        };
+        let targets = RenderTargets::new(device, target_size, sample_count);
+        let mut bloom = BloomPipeline::new(device, texture_format);
+        bloom.set_scene(device, &targets.hdr_view, target_size);
 
         Self {
             window_size: size,
+            trail_length,
             instance_buffer,
             camera_bind_group,
+            sample_count,
+            targets,
+            bloom,
+            bloom_intensity: BLOOM_INTENSITY,
+            trail_style: TrailStyle::default(),
+            textures,
             point_buffer,
             line_pipeline,
             circle_pipeline,
+            mesh_pipeline,
+            sphere_pipeline,
+            sphere_vertices: Vec::new(),
+            light_source: objects
+                .objects()
+                .iter()
+                .enumerate()
+                .max_by(|(_, a), (_, b)| a.dat.mass.total_cmp(&b.dat.mass))
+                .map(|(idx, _)| idx),
         }
     }
 
+    fn point_buffer(device: &Device, num_objects: usize, trail_length: usize) -> Buffer {
+        device.create_buffer(&BufferDescriptor {
+            label: Some("pos_buffer"),
+            size: (num_objects * trail_length) as u64 * Vertex::size(),
+            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Samples per pixel, 1 without multisampling.
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    /// Draw with `sample_count` samples per pixel from the next frame on. It must be one
+    /// of `supported_sample_counts`.
+    pub fn set_sample_count(&mut self, sample_count: u32) {
+        self.sample_count = sample_count;
+        self.line_pipeline.set_sample_count(sample_count);
+        self.circle_pipeline.set_sample_count(sample_count);
+        self.mesh_pipeline.set_sample_count(sample_count);
+        self.sphere_pipeline.set_sample_count(sample_count);
+    }
+
+    /// Strength of the glow around bright objects, 0 without any.
+    pub fn bloom_intensity(&self) -> f32 {
+        self.bloom_intensity
+    }
+
+    pub fn set_bloom_intensity(&mut self, intensity: f32) {
+        self.bloom_intensity = intensity;
+    }
+
+    /// How trails fade towards their end, and how opaque they are.
+    pub fn trail_style(&self) -> TrailStyle {
+        self.trail_style
+    }
+
+    pub fn set_trail_style(&mut self, style: TrailStyle) {
+        self.trail_style = style;
+    }
+
+    /// Bytes allocated in GPU buffers and textures.
+    pub fn gpu_memory(&self) -> u64 {
+        self.targets.memory_usage()
+            + self.bloom.memory_usage()
+            + self.textures.memory_usage()
+            + self.point_buffer.size()
+            + self.instance_buffer.size()
+            + self.line_pipeline.memory_usage()
+            + self.mesh_pipeline.memory_usage()
+            + self.sphere_pipeline.memory_usage()
+    }
+
+    /// Set the translucent surfaces drawn over the objects, hidden only by what is in front
+    /// of them. They stay until replaced.
+    pub fn set_mesh(&mut self, device: &Device, queue: &Queue, vertices: &[MeshVertex]) {
+        self.mesh_pipeline.set_vertices(device, queue, vertices);
+    }
+
     pub fn redraw(
         &mut self,
-        tick: u32,
+        frame: FrameTime,
         camera: &mut Camera,
         objects: &mut Objects,
         queue: &Queue,
         output: &Texture,
         device: &Device,
     ) {
+        if objects.trail_length() != self.trail_length {
+            // The trails were cleared along with the change, so nothing is lost.
+            self.trail_length = objects.trail_length();
+            self.point_buffer =
+                Self::point_buffer(device, objects.num_objects(), self.trail_length);
+            self.line_pipeline
+                .set_trail_length(device, objects.num_objects(), self.trail_length);
+        }
+        if !self.targets.matches(output, self.sample_count) {
+            self.targets = RenderTargets::new(device, output.size(), self.sample_count);
+            self.bloom
+                .set_scene(device, &self.targets.hdr_view, output.size());
+        }
+        self.textures.upload(queue);
         objects.flush_to_buffer(&self.point_buffer, queue);
+        objects.flush_descriptions(&self.instance_buffer, queue);
         camera.flush_if_needed(queue);
+        self.tessellate_large_objects(camera, objects, device, queue);
+
+        let options = ShaderOptions {
+            relative_position: objects.target_object().is_some(),
+        };
+        self.line_pipeline.prepare(device, options);
+        self.circle_pipeline.prepare(device, options);
+        self.mesh_pipeline.prepare(device, options);
+        self.sphere_pipeline.prepare(device, options);
+        if let Err(e) = pipeline_cache::save() {
+            println!("Failed to save pipeline cache: {e}");
+        }
 
         /* let epos = objects.descriptions_mut()[1].position;
         let radius = objects.descriptions_mut()[1].radius;
@@ -94,15 +447,56 @@ impl Renderer {
         println!("{:?}", proj_epos);
         println!("{}", radius / proj_epos.z); */
 
-        let mut output_view = output.create_view(&wgpu::TextureViewDescriptor::default());
+        let output_view = output.create_view(&wgpu::TextureViewDescriptor::default());
         let mut encoder =
             device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
 
-        self.pass(&mut encoder, &mut output_view, tick, objects);
+        self.pass(&mut encoder, frame, objects, options);
+        self.bloom.draw(
+            &mut encoder,
+            &output_view,
+            BLOOM_THRESHOLD,
+            self.bloom_intensity,
+        );
 
         queue.submit(Some(encoder.finish()));
     }
 
+    /// Build meshes for the objects that are too large on screen to draw as circles.
+    fn tessellate_large_objects(
+        &mut self,
+        camera: &Camera,
+        objects: &Objects,
+        device: &Device,
+        queue: &Queue,
+    ) {
+        let relative = objects
+            .target_object()
+            .map(|target| Vector3::from(*objects.position_of(target)))
+            .unwrap_or(Vector3::zero());
+        self.sphere_vertices.clear();
+        for (idx, desc) in objects.descriptions().iter().enumerate() {
+            // Everything is drawn relative to the target object, see the shaders.
+            let center = Point3::from(*objects.position_of(idx)) - relative;
+            let Some(size) =
+                large_projected_size(center, desc.radius, camera.matrix(), camera.projection())
+            else {
+                continue;
+            };
+            horizon_triangles(
+                center + relative,
+                desc.radius,
+                camera.eye + relative,
+                size,
+                desc.color,
+                desc.texture,
+                &mut self.sphere_vertices,
+            );
+        }
+        self.sphere_pipeline
+            .set_vertices(device, queue, &self.sphere_vertices);
+    }
+
     pub fn resize(&mut self, size: PhysicalSize<u32>) {
         if size.width != 0 && size.height != 0 {
             // Recreate the swap chain with the new size
@@ -110,49 +504,82 @@ impl Renderer {
         }
     }
 
+    /// Draw the scene to the HDR texture.
     fn pass(
         &self,
         encoder: &mut CommandEncoder,
-        output_view: &mut TextureView,
-        tick: u32,
+        frame: FrameTime,
         objects: &Objects,
+        options: ShaderOptions,
     ) {
         let mut rpass = encoder.begin_render_pass(&RenderPassDescriptor {
             label: None,
-            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: output_view,
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
-                    store: wgpu::StoreOp::Store,
+            color_attachments: &[Some(match &self.targets.color {
+                // The samples are only needed until they are resolved.
+                Some((_, color_view)) => wgpu::RenderPassColorAttachment {
+                    view: color_view,
+                    resolve_target: Some(&self.targets.hdr_view),
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: wgpu::StoreOp::Discard,
+                    },
+                },
+                None => wgpu::RenderPassColorAttachment {
+                    view: &self.targets.hdr_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: wgpu::StoreOp::Store,
+                    },
                 },
             })],
-            depth_stencil_attachment: None,
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &self.targets.depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    // Reversed depth, so everything is nearer than the cleared value.
+                    load: wgpu::LoadOp::Clear(0.0),
+                    store: wgpu::StoreOp::Discard,
+                }),
+                stencil_ops: None,
+            }),
             ..Default::default()
         });
         // Useful to not render the part of the screen where the UI is.
         // rpass.set_scissor_rect(0, 0, self.window_size.width, self.window_size.height - 50);
 
         let index_range = objects.get_index_range();
+        let [speeds, accelerations] = objects.motion_ranges();
+        let (min, max) = match self.trail_style.color {
+            TrailColor::Object | TrailColor::Speed => speeds,
+            TrailColor::Acceleration => accelerations,
+        };
 
         let push_constants = ShaderConstants {
             width: self.window_size.width,
             height: self.window_size.height,
-            time: tick,
-            total_buffer_size: TRAIL_MAX_LENGTH as u32,
+            time: frame.0,
+            total_buffer_size: self.trail_length as u32,
             start_index: index_range.start,
             end_index: index_range.end,
-            use_relative_position: if objects.target_object().is_some() {
-                1
-            } else {
-                0
-            },
             last_relative_position: if let Some(target) = objects.target_object() {
                 *objects.position_of(target)
             } else {
                 [0.0, 0.0, 0.0]
             },
             min_circle_size: MIN_CIRCLE_SIZE,
+            max_circle_size: MAX_CIRCLE_SIZE,
+            light_position: self
+                .light_source
+                .map_or([0.0, 0.0, 0.0], |light| *objects.position_of(light)),
+            trail_time: objects.latest_time(),
+            trail_fade: self.trail_style.fade as u32,
+            trail_fade_time: self.trail_style.fade_time,
+            trail_opacity: self.trail_style.opacity,
+            trail_color: self.trail_style.color as u32,
+            colormap: self.trail_style.colormap as u32,
+            // Before any motion is known everything is at the low end.
+            trail_color_min: if min <= max { min.log2() } else { 0.0 },
+            trail_color_max: if min <= max { max.log2() } else { 0.0 },
         };
 
         self.line_pipeline.draw(
@@ -161,19 +588,39 @@ impl Renderer {
             &self.point_buffer,
             &self.instance_buffer,
             &push_constants,
+            options,
             index_range,
-            objects.num_objects(),
+            objects.trails_shown(),
             objects.target_object(),
         );
 
         self.circle_pipeline.draw(
             &mut rpass,
             &self.camera_bind_group,
+            self.textures.bind_group(),
             objects.get_last_batch_range(),
             &self.point_buffer,
             &self.instance_buffer,
             &push_constants,
+            options,
             objects.num_objects(),
         );
+
+        self.sphere_pipeline.draw(
+            &mut rpass,
+            &self.camera_bind_group,
+            self.textures.bind_group(),
+            &push_constants,
+            options,
+        );
+
+        // Translucent, so drawn last over everything it doesn't hide behind.
+        self.mesh_pipeline.draw(
+            &mut rpass,
+            &self.camera_bind_group,
+            self.textures.bind_group(),
+            &push_constants,
+            options,
+        );
     }
 }
@@ -0,0 +1,157 @@
+use std::{fs::File, io::BufReader, path::Path, time::Instant};
+
+use anyhow::Context;
+use cgmath::Vector3;
+
+use crate::{
+    Object, ObjectInfo, constants::REPLAY_FRAMES_PER_SECOND, objects::Objects,
+    recording::RecordingReader, sim::SimTime,
+};
+
+/// Plays back a recording into `Objects`, in place of a live simulation.
+pub struct ReplayPlayer {
+    reader: RecordingReader<BufReader<File>>,
+    /// Current playback position, in frames. Fractional so that slow playback works.
+    position: f64,
+    shown: Option<usize>,
+    playing: bool,
+    speed: f64,
+    last_update: Instant,
+    buffer: Vec<[f32; 3]>,
+}
+
+impl ReplayPlayer {
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path)
+            .with_context(|| format!("Failed to open recording {}", path.display()))?;
+        let reader = RecordingReader::new(BufReader::new(file))?;
+        if reader.num_frames() == 0 {
+            anyhow::bail!("Recording {} contains no frames", path.display());
+        }
+
+        Ok(Self {
+            reader,
+            position: 0.0,
+            shown: None,
+            playing: true,
+            speed: 1.0,
+            last_update: Instant::now(),
+            buffer: Vec::new(),
+        })
+    }
+
+    /// Objects described by the recording, placed at their positions in the first frame.
+    pub fn initial_objects(&mut self) -> anyhow::Result<Vec<Object>> {
+        self.reader.read_frame(0, &mut self.buffer)?;
+        Ok(self
+            .reader
+            .objects()
+            .iter()
+            .zip(self.buffer.iter())
+            .map(|(obj, pos)| Object {
+                name: obj.name.clone(),
+                dat: ObjectInfo {
+                    pos: pos.map(|v| v as f64).into(),
+                    vel: Vector3::new(0.0, 0.0, 0.0),
+                    mass: 0.0,
+                    pinned: false,
+                    test_particle: false,
+                },
+                color: obj.color.into(),
+                radius: obj.radius,
+                oblateness: None,
+                radiation: None,
+                maneuvers: Vec::new(),
+                mass_rate: 0.0,
+                texture: None,
+            })
+            .collect())
+    }
+
+    pub fn num_frames(&self) -> usize {
+        self.reader.num_frames()
+    }
+
+    pub fn frame(&self) -> usize {
+        (self.position as usize).min(self.num_frames() - 1)
+    }
+
+    pub fn current_ticks(&self) -> u64 {
+        self.reader.frames()[self.frame()].tick
+    }
+
+    pub fn current_time(&self) -> SimTime {
+        self.reader.frames()[self.frame()].time()
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    pub fn set_playing(&mut self, playing: bool) {
+        self.playing = playing;
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    pub fn set_speed(&mut self, speed: f64) {
+        self.speed = speed;
+    }
+
+    /// Seek to the last frame at or before `tick`.
+    pub fn seek_tick(&mut self, tick: u64) {
+        let idx = self.reader.frames().partition_point(|f| f.tick <= tick);
+        self.seek(idx.saturating_sub(1));
+    }
+
+    /// Positions in the frame most recently pushed by `update`.
+    pub fn current_positions(&self) -> &[[f32; 3]] {
+        &self.buffer
+    }
+
+    pub fn seek(&mut self, frame: usize) {
+        self.position = frame.min(self.num_frames() - 1) as f64;
+    }
+
+    /// Advance playback and push any new frames to `objects`.
+    pub fn update(&mut self, objects: &mut Objects) -> anyhow::Result<()> {
+        let now = Instant::now();
+        if self.playing {
+            let elapsed = now.duration_since(self.last_update).as_secs_f64();
+            self.position += elapsed * REPLAY_FRAMES_PER_SECOND * self.speed;
+            let last = (self.num_frames() - 1) as f64;
+            if self.position >= last {
+                self.position = last;
+                self.playing = false;
+            } else if self.position < 0.0 {
+                self.position = 0.0;
+                self.playing = false;
+            }
+        }
+        self.last_update = now;
+
+        let frame = self.frame();
+        if self.shown == Some(frame) {
+            return Ok(());
+        }
+        let oldest = (frame + 1).saturating_sub(objects.trail_length());
+        // Continue the existing trails when moving forward, otherwise rebuild them.
+        let start = match self.shown {
+            Some(shown) if shown < frame && shown + 1 >= oldest => shown + 1,
+            _ => {
+                objects.clear();
+                oldest
+            }
+        };
+
+        for idx in start..=frame {
+            self.reader.read_frame(idx, &mut self.buffer)?;
+            objects.push_items(&self.buffer, self.reader.frames()[idx].time());
+        }
+        self.shown = Some(frame);
+        Ok(())
+    }
+}
@@ -0,0 +1,61 @@
+//! Seeded random number generation.
+//!
+//! Everything random in a run, like generated presets or stochastic effects in the
+//! simulation, draws from an [`RngService`] created from a single master seed.
+//! Rather than sharing one generator, each consumer derives its own independent stream,
+//! keyed by something that does not depend on scheduling, such as the object index or
+//! the chunk of objects being processed. Parallel work then produces the same numbers
+//! no matter which thread ends up running it, so a run can be reproduced from its seed.
+//!
+//! Streams are ChaCha8 rather than `StdRng`, whose algorithm may change between
+//! versions of `rand`. ChaCha8 gives the same numbers on every platform and version.
+
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+/// Source of reproducible random streams, derived from a master seed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RngService {
+    seed: u64,
+}
+
+/// SplitMix64 finalizer, used to spread out derived seeds. Neighbouring keys, like
+/// consecutive object indices, end up with unrelated seeds.
+fn mix(mut z: u64) -> u64 {
+    z = z.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+impl RngService {
+    pub fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+
+    /// Create a service with a random master seed. Use [`RngService::seed`] to
+    /// reproduce the run later.
+    pub fn from_entropy() -> Self {
+        Self::new(rand::random())
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Child service for a separate part of the program, so that adding random draws
+    /// in one place does not change the numbers seen elsewhere.
+    pub fn child(&self, name: &str) -> Self {
+        // FNV-1a, since the standard library hasher is not guaranteed to be stable.
+        let hash = name.bytes().fold(0xcbf29ce484222325u64, |hash, b| {
+            (hash ^ b as u64).wrapping_mul(0x100000001b3)
+        });
+        Self::new(mix(self.seed ^ mix(hash)))
+    }
+
+    /// Independent generator for stream `key`. The same seed and key always give the
+    /// same sequence, regardless of which thread asks for it.
+    pub fn stream(&self, key: u64) -> ChaCha8Rng {
+        ChaCha8Rng::seed_from_u64(mix(self.seed ^ mix(key)))
+    }
+}
@@ -0,0 +1,154 @@
+//! Roche lobes of binary systems.
+//!
+//! In a frame rotating with a circular binary, the effective potential combines the
+//! gravity of both bodies with the centrifugal potential. The equipotential surface
+//! through the inner Lagrange point L1 bounds the two Roche lobes, the regions where
+//! material is bound to either body. Eccentric orbits have no such static frame, so
+//! the lobes are computed from the instantaneous separation, which is the usual
+//! approximation.
+
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector3};
+
+use crate::constants::{G, ROCHE_BISECTION_STEPS};
+
+/// Which body of a binary a lobe belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lobe {
+    Primary,
+    Secondary,
+}
+
+/// Snapshot of a binary, in AU and earth masses.
+#[derive(Debug, Clone)]
+pub struct Binary {
+    pub primary: Point3<f64>,
+    pub primary_mass: f64,
+    pub secondary: Point3<f64>,
+    pub secondary_mass: f64,
+    /// Normal of the orbital plane.
+    pub normal: Vector3<f64>,
+}
+
+impl Binary {
+    fn separation(&self) -> f64 {
+        (self.secondary - self.primary).magnitude()
+    }
+
+    fn barycenter(&self) -> Point3<f64> {
+        let total = self.primary_mass + self.secondary_mass;
+        Point3::from_vec(
+            (self.primary.to_vec() * self.primary_mass
+                + self.secondary.to_vec() * self.secondary_mass)
+                / total,
+        )
+    }
+
+    /// Squared angular velocity of the rotating frame.
+    fn omega_sq(&self) -> f64 {
+        G * (self.primary_mass + self.secondary_mass) / self.separation().powi(3)
+    }
+
+    /// Whether the lobes are well defined, i.e. both bodies have mass and are apart.
+    pub fn is_valid(&self) -> bool {
+        self.primary_mass > 0.0
+            && self.secondary_mass > 0.0
+            && self.separation() > 0.0
+            && self.normal.magnitude2() > 0.0
+    }
+
+    /// Effective potential in the rotating frame.
+    pub fn potential(&self, pos: Point3<f64>) -> f64 {
+        let normal = self.normal.normalize();
+        let rel = pos - self.barycenter();
+        let in_plane = rel - normal * rel.dot(normal);
+        -G * self.primary_mass / (pos - self.primary).magnitude()
+            - G * self.secondary_mass / (pos - self.secondary).magnitude()
+            - 0.5 * self.omega_sq() * in_plane.magnitude2()
+    }
+
+    /// The inner Lagrange point, where the two lobes touch.
+    pub fn l1(&self) -> Point3<f64> {
+        let a = self.separation();
+        let axis = (self.secondary - self.primary) / a;
+        let center = a * self.secondary_mass / (self.primary_mass + self.secondary_mass);
+        let omega_sq = self.omega_sq();
+        // The force along the axis goes from pointing towards the secondary near the
+        // primary, to pointing towards the primary near the secondary, and is zero at L1.
+        let force = |x: f64| {
+            G * self.primary_mass / (x * x)
+                - G * self.secondary_mass / ((a - x) * (a - x))
+                - omega_sq * (x - center)
+        };
+        let (mut lo, mut hi) = (0.0, a);
+        for _ in 0..ROCHE_BISECTION_STEPS {
+            let mid = 0.5 * (lo + hi);
+            if force(mid) > 0.0 {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+        self.primary + axis * (0.5 * (lo + hi))
+    }
+
+    /// Triangulated surface of a Roche lobe, with `latitudes * longitudes` quads. Each
+    /// triangle is pushed to `out` as three consecutive vertices.
+    pub fn lobe_triangles(
+        &self,
+        lobe: Lobe,
+        latitudes: usize,
+        longitudes: usize,
+        out: &mut Vec<[f32; 3]>,
+    ) {
+        let (center, other) = match lobe {
+            Lobe::Primary => (self.primary, self.secondary),
+            Lobe::Secondary => (self.secondary, self.primary),
+        };
+        let l1 = self.l1();
+        let critical = self.potential(l1);
+        // The lobe is furthest from its body at L1, so every other point of the
+        // surface lies within this distance.
+        let max_radius = (l1 - center).magnitude();
+
+        // Spherical coordinates with the pole pointing at the other body, so that the
+        // cusp at L1 is a single vertex.
+        let e1 = (other - center).normalize();
+        let e2 = (self.normal - e1 * self.normal.dot(e1)).normalize();
+        let e3 = e1.cross(e2);
+
+        let point = |lat: usize, lon: usize| {
+            let theta = std::f64::consts::PI * lat as f64 / latitudes as f64;
+            let phi = 2.0 * std::f64::consts::PI * lon as f64 / longitudes as f64;
+            let dir = e1 * theta.cos() + (e2 * phi.cos() + e3 * phi.sin()) * theta.sin();
+            let radius = if lat == 0 {
+                max_radius
+            } else {
+                // The potential increases monotonically along each ray from the body.
+                let (mut lo, mut hi) = (0.0, max_radius);
+                for _ in 0..ROCHE_BISECTION_STEPS {
+                    let mid = 0.5 * (lo + hi);
+                    if self.potential(center + dir * mid) < critical {
+                        lo = mid;
+                    } else {
+                        hi = mid;
+                    }
+                }
+                0.5 * (lo + hi)
+            };
+            let p = center + dir * radius;
+            [p.x as f32, p.y as f32, p.z as f32]
+        };
+
+        let grid: Vec<_> = (0..=latitudes)
+            .flat_map(|lat| (0..longitudes).map(move |lon| (lat, lon)))
+            .map(|(lat, lon)| point(lat, lon))
+            .collect();
+        let at = |lat: usize, lon: usize| grid[lat * longitudes + lon % longitudes];
+        for lat in 0..latitudes {
+            for lon in 0..longitudes {
+                out.extend([at(lat, lon), at(lat + 1, lon), at(lat + 1, lon + 1)]);
+                out.extend([at(lat, lon), at(lat + 1, lon + 1), at(lat, lon + 1)]);
+            }
+        }
+    }
+}
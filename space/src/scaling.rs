@@ -0,0 +1,103 @@
+//! Rescaling scenarios.
+//!
+//! Multiplying every length by `length` and every mass by `mass` leaves the orbits
+//! unchanged apart from how fast they play out: gravity only sets a time scale, which
+//! changes by `sqrt(length^3 / mass)`. Velocities are scaled to match, so the same
+//! scenario can be moved to a different unit regime, or shrunk or grown to be merged
+//! with another.
+//!
+//! The post-Newtonian correction depends on the speed of light, and is the one thing
+//! that cannot be preserved.
+
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector3};
+
+use crate::{Object, Radiation, ThrustAmount};
+
+/// Consistent scale factors for lengths and masses.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Scale {
+    pub length: f64,
+    pub mass: f64,
+}
+
+impl Scale {
+    pub fn new(length: f64, mass: f64) -> Self {
+        Self { length, mass }
+    }
+
+    /// Scale that gives `objects` the given total mass, and puts the object furthest from
+    /// their center of mass at `extent` from it.
+    pub fn to_fit(objects: &[Object], extent: f64, total_mass: f64) -> Self {
+        let mass: f64 = objects.iter().map(|o| o.dat.mass).sum();
+        let center = if mass > 0.0 {
+            objects
+                .iter()
+                .map(|o| o.dat.pos.to_vec() * o.dat.mass)
+                .sum::<Vector3<f64>>()
+                / mass
+        } else {
+            Vector3::new(0.0, 0.0, 0.0)
+        };
+        let current = objects
+            .iter()
+            .map(|o| (o.dat.pos.to_vec() - center).magnitude())
+            .fold(0.0, f64::max);
+        Self {
+            length: if current > 0.0 { extent / current } else { 1.0 },
+            mass: if mass > 0.0 { total_mass / mass } else { 1.0 },
+        }
+    }
+
+    /// Factor simulated time is scaled by. An orbit that took a year takes this many years
+    /// after scaling.
+    pub fn time(&self) -> f64 {
+        (self.length.powi(3) / self.mass).sqrt()
+    }
+
+    pub fn velocity(&self) -> f64 {
+        self.length / self.time()
+    }
+
+    /// Scale `objects` in place, around the origin.
+    pub fn apply(&self, objects: &mut [Object]) {
+        let velocity = self.velocity();
+        let time = self.time();
+        for obj in objects {
+            obj.dat.pos = Point3::from_vec(obj.dat.pos.to_vec() * self.length);
+            obj.dat.vel *= velocity;
+            obj.dat.mass *= self.mass;
+            obj.mass_rate *= self.mass / time;
+            obj.radius *= self.length as f32;
+            if let Some(shape) = &mut obj.oblateness {
+                shape.equatorial_radius *= self.length;
+            }
+            // Keep radiation pressure in proportion to gravity. Cross sections grow with
+            // length squared, which cancels the inverse square law.
+            match &mut obj.radiation {
+                Some(Radiation::Source { luminosity }) => {
+                    *luminosity *= (self.mass / self.length).powi(2)
+                }
+                // Given at 1 AU, which is now `length` AU away.
+                Some(Radiation::Target { yarkovsky, .. }) => *yarkovsky *= self.mass,
+                None => (),
+            }
+            for maneuver in &mut obj.maneuvers {
+                maneuver.start *= time;
+                maneuver.duration *= time;
+                match &mut maneuver.amount {
+                    ThrustAmount::Acceleration(acc) => *acc *= velocity / time,
+                    ThrustAmount::DeltaV(dv) => *dv *= velocity,
+                }
+            }
+        }
+    }
+}
+
+/// Move `objects` by `offset` and add `velocity` to them, for placing one scenario
+/// inside another.
+pub fn translate(objects: &mut [Object], offset: Vector3<f64>, velocity: Vector3<f64>) {
+    for obj in objects {
+        obj.dat.pos += offset;
+        obj.dat.vel += velocity;
+    }
+}
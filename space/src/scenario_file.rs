@@ -0,0 +1,276 @@
+//! Scenarios read from TOML or JSON files.
+//!
+//! A file lists objects under `objects`, each either at absolute coordinates or on an
+//! orbit around an object listed before it, like `StandardParams`:
+//!
+//! ```toml
+//! [[objects]]
+//! name = "sun"
+//! mass = 333000.0          # earth masses
+//! radius = 0.00465         # AU
+//! color = [1.0, 1.0, 0.0]
+//! pos = [0.0, 0.0, 0.0]    # m
+//! vel = [0.0, 0.0, 0.0]    # m/s
+//!
+//! [[objects]]
+//! name = "earth"
+//! mass = 1.0
+//! radius = 4.26e-5
+//! color = [0.0, 0.0, 1.0]
+//! parent = "sun"
+//! semi_major_axis = 1.496e11   # m
+//! eccentricity = 0.0167
+//! inclination = 0.0            # degrees, like the angles below
+//! arg_periapsis = 0.0
+//! long_asc_node = 0.0
+//! true_anomaly = 0.0
+//! ```
+//!
+//! Instead of the true anomaly, an orbit can be given by its `mean_anomaly` in degrees
+//! at an `epoch`, as a Julian date, the way ephemeris services publish elements. The
+//! object is then moved along its orbit to the start of the simulation, which is the
+//! Julian date `epoch` at the top of the file. Without an epoch, mean anomalies are taken
+//! to be at the start.
+//!
+//! The eccentricity and angles default to zero. Orbits with an eccentricity of 1 or more
+//! are open, parabolic or hyperbolic flybys. Those can give the closest approach as
+//! `periapsis` in meters instead of the semi-major axis, which is negative for hyperbolic
+//! orbits and undefined for parabolic ones. JSON files hold the same fields, as
+//! `{"objects": [...]}`. The format is picked by the file extension.
+//!
+//! An object can have a `texture`, the path to a map of its surface relative to the file,
+//! see `Object::texture`.
+//!
+//! A running system can be saved in the same format, with every object at absolute
+//! coordinates, to be loaded again later.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Object,
+    constants::AU,
+    parameters::{AbsoluteCoords, Anomaly, RelativeCoords, RelativeOrAbsolute, StandardParams},
+};
+
+const DAY: f64 = 24.0 * 3600.0;
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct ScenarioFile {
+    /// Julian date the simulation starts at.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    epoch: Option<f64>,
+    objects: Vec<ObjectEntry>,
+}
+
+/// An object as listed in a scenario file.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ObjectEntry {
+    name: String,
+    mass: f64,
+    radius: f32,
+    color: [f32; 3],
+    /// In kg/s.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    mass_rate: f64,
+    /// Equirectangular map of the surface as a binary PPM image, relative to the file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    texture: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pos: Option<[f64; 3]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    vel: Option<[f64; 3]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parent: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    semi_major_axis: Option<f64>,
+    /// In meters, instead of the semi-major axis.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    periapsis: Option<f64>,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    eccentricity: f64,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    inclination: f64,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    arg_periapsis: f64,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    long_asc_node: f64,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    true_anomaly: f64,
+    /// In degrees, instead of the true anomaly.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mean_anomaly: Option<f64>,
+    /// Julian date of the mean anomaly.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    epoch: Option<f64>,
+}
+
+fn is_zero(value: &f64) -> bool {
+    *value == 0.0
+}
+
+impl ObjectEntry {
+    /// Parameters of the object, for a simulation starting at the Julian date `start`.
+    fn into_params(self, start: Option<f64>) -> anyhow::Result<StandardParams> {
+        let anomaly = match (self.mean_anomaly, self.epoch, start) {
+            (Some(_), _, _) if self.true_anomaly != 0.0 => {
+                bail!("{} has both a true_anomaly and a mean_anomaly", self.name)
+            }
+            (None, Some(_), _) => bail!("{} has an epoch, but no mean_anomaly", self.name),
+            (Some(_), Some(_), None) => bail!(
+                "{} has an epoch, but the scenario has no epoch to start at",
+                self.name
+            ),
+            (Some(mean_anomaly), Some(epoch), Some(start)) => Anomaly::Mean {
+                mean_anomaly,
+                time_since_epoch: (start - epoch) * DAY,
+            },
+            (Some(mean_anomaly), None, _) => Anomaly::Mean {
+                mean_anomaly,
+                time_since_epoch: 0.0,
+            },
+            (None, None, _) => Anomaly::True(self.true_anomaly),
+        };
+        let size = match (self.semi_major_axis, self.periapsis) {
+            (Some(_), Some(_)) => {
+                bail!("{} has both a semi_major_axis and a periapsis", self.name)
+            }
+            (Some(semi_major_axis), None) => Some(semi_major_axis),
+            // Parabolic orbits are given by their periapsis, see `RelativeCoords`.
+            (None, Some(periapsis)) if self.eccentricity == 1.0 => Some(periapsis),
+            (None, Some(periapsis)) => Some(periapsis / (1.0 - self.eccentricity)),
+            (None, None) => None,
+        };
+        let coordinates = match (self.parent, size) {
+            (Some(parent), Some(semi_major_axis)) => {
+                if self.pos.is_some() || self.vel.is_some() {
+                    bail!("{} has both a parent and absolute coordinates", self.name);
+                }
+                RelativeOrAbsolute::Relative(RelativeCoords {
+                    parent,
+                    semi_major_axis,
+                    eccentricity: self.eccentricity,
+                    inclination: self.inclination,
+                    arg_periapsis: self.arg_periapsis,
+                    long_asc_node: self.long_asc_node,
+                    anomaly,
+                })
+            }
+            (Some(_), None) => bail!(
+                "{} has a parent, but no semi_major_axis or periapsis",
+                self.name
+            ),
+            (None, Some(_)) => bail!(
+                "{} has a semi_major_axis or periapsis, but no parent",
+                self.name
+            ),
+            (None, None) => RelativeOrAbsolute::Absolute(AbsoluteCoords {
+                pos: self.pos.unwrap_or_default(),
+                vel: self.vel.unwrap_or_default(),
+            }),
+        };
+        Ok(StandardParams {
+            name: self.name,
+            coordinates,
+            mass: self.mass,
+            radius: self.radius,
+            color: self.color,
+            oblateness: None,
+            radiation: None,
+            maneuvers: Vec::new(),
+            mass_rate: self.mass_rate,
+            texture: self.texture,
+        })
+    }
+}
+
+/// Read the objects of a scenario file, see the module documentation for the format.
+/// Every parent must be listed before the objects orbiting it, which is left to
+/// `validate::check_params`.
+pub fn load_params(path: impl AsRef<Path>) -> anyhow::Result<Vec<StandardParams>> {
+    let path = path.as_ref();
+    let text =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let file: ScenarioFile = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => {
+            toml::from_str(&text).with_context(|| format!("Failed to parse {}", path.display()))?
+        }
+        Some("json") => serde_json::from_str(&text)
+            .with_context(|| format!("Failed to parse {}", path.display()))?,
+        _ => bail!(
+            "Scenario file {} must end in .toml or .json",
+            path.display()
+        ),
+    };
+    let mut objects = file.objects;
+    let dir = path.parent().unwrap_or(Path::new(""));
+    for obj in &mut objects {
+        if let Some(texture) = &mut obj.texture {
+            *texture = dir.join(&*texture);
+        }
+    }
+    entries_into_params(objects, file.epoch)
+}
+
+/// Convert the objects of a scenario starting at the Julian date `start` to orbital
+/// parameters.
+pub(crate) fn entries_into_params(
+    entries: Vec<ObjectEntry>,
+    start: Option<f64>,
+) -> anyhow::Result<Vec<StandardParams>> {
+    entries
+        .into_iter()
+        .map(|entry| entry.into_params(start))
+        .collect()
+}
+
+/// Write `objects` to `path` at absolute coordinates, as TOML or JSON by the extension.
+/// Objects that have left the simulation are skipped, and test particles are written as
+/// massless, which makes them test particles again when loaded. Oblateness, radiation,
+/// maneuvers and pinning are not part of the format, and are lost.
+pub fn save_objects(path: impl AsRef<Path>, objects: &[Object]) -> anyhow::Result<()> {
+    let path = path.as_ref();
+    let file = ScenarioFile {
+        epoch: None,
+        objects: objects
+            .iter()
+            .filter(|obj| !obj.dat.is_frozen())
+            .map(|obj| ObjectEntry {
+                name: obj.name.clone(),
+                mass: obj.dat.gravitating_mass(),
+                radius: obj.radius,
+                color: obj.color.into(),
+                mass_rate: obj.mass_rate,
+                texture: obj.texture.clone(),
+                pos: Some((obj.dat.pos * AU).into()),
+                vel: Some((obj.dat.vel * AU).into()),
+                parent: None,
+                semi_major_axis: None,
+                periapsis: None,
+                eccentricity: 0.0,
+                inclination: 0.0,
+                arg_periapsis: 0.0,
+                long_asc_node: 0.0,
+                true_anomaly: 0.0,
+                mean_anomaly: None,
+                epoch: None,
+            })
+            .collect(),
+    };
+    let text = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => toml::to_string(&file)?,
+        Some("json") => serde_json::to_string_pretty(&file)?,
+        _ => bail!(
+            "Scenario file {} must end in .toml or .json",
+            path.display()
+        ),
+    };
+    fs::write(path, text).with_context(|| format!("Failed to write {}", path.display()))
+}
@@ -0,0 +1,151 @@
+//! Scenarios generated by Rhai scripts, for initial conditions built with loops, random
+//! distributions or hierarchies that would be tedious to list in a scenario file.
+//!
+//! A script calls `add` with a map for each object, with the same fields as an object in
+//! a scenario file. Parents must be added before the objects orbiting them. There is no
+//! start date, so mean anomalies are at the start of the simulation and objects have no
+//! `epoch`:
+//!
+//! ```rhai
+//! add(#{ name: "sun", mass: SOLAR_MASS, radius: SOLAR_RADIUS, color: [1.0, 1.0, 0.0] });
+//! for i in 0..100 {
+//!     add(#{
+//!         name: `asteroid_${i}`,
+//!         mass: 1e-9,
+//!         radius: 1e-8,
+//!         color: [0.5, 0.5, 0.5],
+//!         parent: "sun",
+//!         semi_major_axis: random(2.2, 3.2) * AU,
+//!         eccentricity: random(0.0, 0.1),
+//!         true_anomaly: random(0.0, 360.0),
+//!     });
+//! }
+//! ```
+//!
+//! `random()` draws uniformly from `[0, 1)`, `random(low, high)` from `[low, high)` and
+//! `gauss(mean, sd)` from a normal distribution. They take floats, and draw from the
+//! seed of the run. The constants `AU` and `PARSEC` are in meters, `SOLAR_MASS` is in
+//! earth masses and `SOLAR_RADIUS` in AU, the units of scenario files.
+//!
+//! `preset(name)` adds the objects of a preset from the registry, and
+//! `preset(name, #{ stars: 1000 })` changes some of its parameters, see
+//! [`preset_registry`](crate::preset_registry). Objects added later can orbit the objects
+//! of a preset by name, like the `sun` of `earth_sun_mars`.
+
+use std::{cell::RefCell, f64::consts::TAU, fs, path::Path, rc::Rc};
+
+use anyhow::Context;
+use rand::Rng;
+use rhai::{Dynamic, Engine, EvalAltResult, ImmutableString, Map, Scope};
+
+use crate::{
+    constants::{AU, PARSEC, SOLAR_MASS, SOLAR_RADIUS},
+    parameters::StandardParams,
+    preset_registry::{self, PresetParams},
+    rng::RngService,
+    scenario_file::{self, ObjectEntry},
+};
+
+/// Something the script added, in the order it was added.
+enum Added {
+    Object(Map),
+    Preset(Vec<StandardParams>),
+}
+
+/// Run the script at `path`, and return the objects it added.
+pub fn load_params(
+    path: impl AsRef<Path>,
+    rng: &RngService,
+) -> anyhow::Result<Vec<StandardParams>> {
+    let path = path.as_ref();
+    let script =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    run(&script, rng).with_context(|| format!("Failed to run {}", path.display()))
+}
+
+/// Run `script`, and return the objects it added.
+pub fn run(script: &str, rng: &RngService) -> anyhow::Result<Vec<StandardParams>> {
+    let added = Rc::new(RefCell::new(Vec::new()));
+    let rng_service = *rng;
+    let rng = Rc::new(RefCell::new(rng.child("scenario_script").stream(0)));
+    let mut engine = Engine::new();
+
+    let objects = added.clone();
+    engine.register_fn("add", move |object: Map| {
+        objects.borrow_mut().push(Added::Object(object))
+    });
+    let presets = added.clone();
+    let preset_rng = rng_service.child("scenario_script_presets");
+    let add_preset = move |name: ImmutableString, params: Map| -> Result<(), Box<EvalAltResult>> {
+        let params = params
+            .into_iter()
+            .map(|(key, value)| {
+                let value = value
+                    .as_float()
+                    .or_else(|_| value.as_int().map(|v| v as f64))
+                    .map_err(|_| format!("Preset parameter {key} must be a number"))?;
+                Ok((key.to_string(), value))
+            })
+            .collect::<Result<PresetParams, Box<EvalAltResult>>>()?;
+        let mut presets = presets.borrow_mut();
+        // Each call draws from its own child, keyed by its place in the script, so the
+        // same preset added twice differs.
+        let rng = preset_rng.child(&presets.len().to_string());
+        let scenario = preset_registry::find(&name)
+            .and_then(|preset| preset.generate(&params, &rng))
+            .map_err(|e| format!("{e:#}"))?;
+        presets.push(Added::Preset(scenario.into_params()));
+        Ok(())
+    };
+    let add = add_preset.clone();
+    engine.register_fn("preset", move |name: ImmutableString| add(name, Map::new()));
+    engine.register_fn("preset", add_preset);
+    let r = rng.clone();
+    engine.register_fn("random", move || r.borrow_mut().random::<f64>());
+    let r = rng.clone();
+    engine.register_fn(
+        "random",
+        move |low: f64, high: f64| -> Result<f64, Box<EvalAltResult>> {
+            if low < high {
+                Ok(r.borrow_mut().random_range(low..high))
+            } else {
+                Err(format!("random needs low < high, got {low} and {high}").into())
+            }
+        },
+    );
+    let r = rng.clone();
+    engine.register_fn("gauss", move |mean: f64, sd: f64| {
+        let mut rng = r.borrow_mut();
+        // Box-Muller, with the first number in (0, 1] to keep the logarithm finite.
+        let u = 1.0 - rng.random::<f64>();
+        let v = rng.random::<f64>();
+        mean + sd * (-2.0 * u.ln()).sqrt() * (TAU * v).cos()
+    });
+
+    let mut scope = Scope::new();
+    scope.push_constant("AU", AU);
+    scope.push_constant("PARSEC", PARSEC * AU);
+    scope.push_constant("SOLAR_MASS", SOLAR_MASS);
+    scope.push_constant("SOLAR_RADIUS", SOLAR_RADIUS);
+    engine
+        .run_with_scope(&mut scope, script)
+        .map_err(|e| anyhow::anyhow!("{e}"))?;
+
+    let mut params = Vec::new();
+    let mut objects = 0;
+    for item in added.take() {
+        match item {
+            Added::Object(object) => {
+                // Going through JSON rather than straight from the map lets integers stand
+                // in for floats, and floats for the single precision fields.
+                let entry = serde_json::to_value(Dynamic::from_map(object))
+                    .and_then(serde_json::from_value::<ObjectEntry>)
+                    .with_context(|| format!("Object {objects} added by the script is invalid"))?;
+                params.extend(scenario_file::entries_into_params(vec![entry], None)?);
+                objects += 1;
+            }
+            Added::Preset(preset) => params.extend(preset),
+        }
+    }
+    Ok(params)
+}
@@ -0,0 +1,193 @@
+//! Gravity solver picked by the number of objects during a run.
+//!
+//! Escapes and injections change how many objects attract others while the simulation
+//! is running, so the solver is picked again before every step. A solver is only
+//! replaced once the count is clear of the cutoff by `SOLVER_HYSTERESIS`.
+//!
+//! The solver can also be chosen by hand while running, which is the only way to get
+//! the fast multipole method. Solvers keep nothing between steps that the objects
+//! don't, so swapping one for another loses no state.
+
+use std::path::Path;
+
+use cgmath::Vector3;
+
+use crate::{
+    constants::{BARNES_HUT_CUTOFF, SOLVER_HYSTERESIS},
+    sim::{
+        BarnesHutSim, BruteForceSim, FmmSim, ObjectInfo, PeriodicBox, PeriodicBruteForceSim,
+        SimulationImpl,
+    },
+};
+
+/// The gravity solvers, from cheapest per object to cheapest in total.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SolverKind {
+    BruteForce,
+    BarnesHut,
+    Fmm,
+}
+
+impl SolverKind {
+    pub const ALL: [SolverKind; 3] = [
+        SolverKind::BruteForce,
+        SolverKind::BarnesHut,
+        SolverKind::Fmm,
+    ];
+
+    /// Whether the solver can wrap forces around a periodic box.
+    pub fn supports_periodic(self) -> bool {
+        self != Self::Fmm
+    }
+
+    /// Best solver for `n_massive` attracting objects. The fast multipole method is
+    /// never picked by count, it has to be chosen by hand.
+    pub fn for_count(n_massive: f64) -> Self {
+        if n_massive > BARNES_HUT_CUTOFF as f64 {
+            Self::BarnesHut
+        } else {
+            Self::BruteForce
+        }
+    }
+
+    /// Solver to switch to from `self` with `n_massive` attracting objects, which is
+    /// `self` unless the count is well past a cutoff.
+    pub fn next(self, n_massive: usize) -> Self {
+        let n = n_massive as f64;
+        let up = Self::for_count(n / (1.0 + SOLVER_HYSTERESIS));
+        let down = Self::for_count(n * (1.0 + SOLVER_HYSTERESIS));
+        if up > self {
+            up
+        } else if down < self {
+            down
+        } else {
+            self
+        }
+    }
+}
+
+/// Gravity, computed by whichever solver suits the current number of objects.
+pub struct AdaptiveSim {
+    kind: SolverKind,
+    /// Solver chosen by hand, used regardless of the number of objects.
+    forced: Option<SolverKind>,
+    solver: Box<dyn SimulationImpl + Send>,
+    theta: f64,
+    periodic: Option<PeriodicBox>,
+    /// Keep Barnes-Hut trees between steps, see `BarnesHutSim::with_incremental_tree`.
+    incremental_tree: bool,
+}
+
+impl AdaptiveSim {
+    pub fn new(objects: &[ObjectInfo], theta: f64, periodic: Option<PeriodicBox>) -> Self {
+        let kind = SolverKind::for_count(count_massive(objects) as f64);
+        Self {
+            kind,
+            forced: None,
+            solver: Self::solver(kind, theta, periodic, false),
+            theta,
+            periodic,
+            incremental_tree: false,
+        }
+    }
+
+    /// Update the tree of the last step when using Barnes-Hut, rather than building a
+    /// new one every step.
+    pub fn with_incremental_tree(mut self) -> Self {
+        self.incremental_tree = true;
+        self.solver = Self::solver(self.kind, self.theta, self.periodic, true);
+        self
+    }
+
+    pub fn kind(&self) -> SolverKind {
+        self.kind
+    }
+
+    fn solver(
+        kind: SolverKind,
+        theta: f64,
+        periodic: Option<PeriodicBox>,
+        incremental_tree: bool,
+    ) -> Box<dyn SimulationImpl + Send> {
+        let barnes_hut = |sim: BarnesHutSim| {
+            if incremental_tree {
+                sim.with_incremental_tree()
+            } else {
+                sim
+            }
+        };
+        match (kind, periodic) {
+            (SolverKind::BruteForce, None) => Box::new(BruteForceSim::new()),
+            (SolverKind::BruteForce, Some(bounds)) => Box::new(PeriodicBruteForceSim::new(bounds)),
+            (SolverKind::BarnesHut, None) => Box::new(barnes_hut(BarnesHutSim::new(theta))),
+            (SolverKind::BarnesHut, Some(bounds)) => Box::new(barnes_hut(
+                BarnesHutSim::new(theta).with_periodic_box(bounds),
+            )),
+            (SolverKind::Fmm, _) => Box::new(FmmSim::new(theta)),
+        }
+    }
+
+    /// Switch solver if one was chosen by hand, or if the number of objects has moved
+    /// far enough past a cutoff.
+    fn update(&mut self, objects: &[ObjectInfo]) {
+        let n_massive = count_massive(objects);
+        let periodic = self.periodic.is_some();
+        let kind = match self.forced {
+            Some(kind) if kind.supports_periodic() || !periodic => kind,
+            _ => self.kind.next(n_massive),
+        };
+        if kind != self.kind {
+            println!(
+                "Switching gravity solver from {:?} to {kind:?} for {n_massive} massive objects",
+                self.kind
+            );
+            self.kind = kind;
+            self.solver = Self::solver(kind, self.theta, self.periodic, self.incremental_tree);
+        }
+    }
+}
+
+/// Direct summation only pays for massive sources, so test particles are nearly free.
+fn count_massive(objects: &[ObjectInfo]) -> usize {
+    objects.iter().filter(|o| !o.test_particle).count()
+}
+
+impl SimulationImpl for AdaptiveSim {
+    fn iter(&mut self, objects: &mut [ObjectInfo], out_buffer: &mut [Vector3<f64>]) {
+        self.update(objects);
+        self.solver.iter(objects, out_buffer);
+    }
+
+    fn iter_single_threaded(
+        &mut self,
+        objects: &mut [ObjectInfo],
+        out_buffer: &mut [Vector3<f64>],
+    ) {
+        self.update(objects);
+        self.solver.iter_single_threaded(objects, out_buffer);
+    }
+
+    fn set_theta(&mut self, theta: f64) {
+        self.theta = theta;
+        self.solver.set_theta(theta);
+    }
+
+    fn set_solver(&mut self, solver: Option<SolverKind>) {
+        if let Some(kind) = solver
+            && solver != self.forced
+            && self.periodic.is_some()
+            && !kind.supports_periodic()
+        {
+            println!("{kind:?} does not support periodic boxes, picking a solver by count");
+        }
+        self.forced = solver;
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.solver.memory_usage()
+    }
+
+    fn dump_tree(&self, path: &Path) -> anyhow::Result<bool> {
+        self.solver.dump_tree(path)
+    }
+}
@@ -0,0 +1,247 @@
+//! Checks of the tree approximation against direct summation, either in detail for a
+//! single object or summarized over every object.
+
+use cgmath::{InnerSpace, Point3, Vector3, Zero};
+
+use super::{
+    FmmTree, bounding_sphere, spatial_order,
+    tree::{GroupBuffers, NodeData, NodeId},
+    walk,
+};
+use crate::{
+    constants::{BARNES_HUT_GROUP_SIZE, FORCE_CHECK_NODES},
+    sim::{BruteForceSim, ObjectInfo, PeriodicBox, SimulationImpl},
+};
+
+/// A node the object interacted with directly.
+#[derive(Debug, Clone)]
+pub struct NodeContribution {
+    pub center_mass: Point3<f64>,
+    pub mass: f64,
+    /// Side length of the node, zero for a single object.
+    pub size: f64,
+    pub distance: f64,
+    /// Acceleration towards the node as the tree computes it.
+    pub acc: Vector3<f64>,
+    /// Difference from the acceleration towards each object in the node on its own.
+    pub error: Vector3<f64>,
+}
+
+/// Acceleration of one object by the tree and by direct summation, with the nodes that
+/// are furthest off.
+#[derive(Debug, Clone)]
+pub struct ForceCheck {
+    pub object: usize,
+    pub theta: f64,
+    pub tree: Vector3<f64>,
+    pub exact: Vector3<f64>,
+    /// Number of nodes the object interacted with.
+    pub interactions: usize,
+    /// The `FORCE_CHECK_NODES` nodes with the largest error, largest first.
+    pub worst_nodes: Vec<NodeContribution>,
+}
+
+impl ForceCheck {
+    /// Check the gravity on `objects[index]`, walking the tree for the same group of
+    /// objects as a step of the simulation would. Other forces are left out. Returns
+    /// `None` if the object is not simulated.
+    pub fn run(
+        objects: &[ObjectInfo],
+        index: usize,
+        theta: f64,
+        bounds: Option<&PeriodicBox>,
+    ) -> Option<Self> {
+        let obj = objects.get(index).filter(|obj| !obj.is_frozen())?;
+        let mut buffers = GroupBuffers::default();
+        spatial_order(objects, &mut buffers);
+        let group = buffers
+            .order
+            .chunks(BARNES_HUT_GROUP_SIZE)
+            .find(|group| group.contains(&index))?;
+
+        let mut tree = FmmTree::new();
+        tree.build_tree(objects);
+        let offset = |to: Point3<f64>| {
+            let rel = to - obj.pos;
+            bounds.map_or(rel, |b| b.min_image(rel))
+        };
+        let acc_towards = |id: NodeId| {
+            let (_, data) = tree.get(id);
+            let rel = offset(data.center_mass);
+            let mut acc = Vector3::zero();
+            obj.get_acc_towards_raw(data.mass, rel, rel.magnitude2(), &mut acc);
+            acc
+        };
+
+        let mut nodes = Vec::new();
+        if !tree.is_empty() {
+            let (center, radius) = bounding_sphere(objects, group);
+            let mut stack = Vec::new();
+            walk(
+                &tree,
+                center,
+                radius,
+                theta * theta,
+                bounds,
+                &mut stack,
+                |id| nodes.push(id),
+            );
+        }
+
+        let mut contributions: Vec<_> = nodes
+            .iter()
+            .map(|id| {
+                let (node, data) = tree.get(*id);
+                let acc = acc_towards(*id);
+                let (size, exact) = match &node.data {
+                    NodeData::External => (0.0, acc),
+                    NodeData::Internal { region, .. } => {
+                        let exact = leaves(&tree, *id).map(acc_towards).sum();
+                        (region.size_sq().sqrt(), exact)
+                    }
+                };
+                NodeContribution {
+                    center_mass: data.center_mass,
+                    mass: data.mass,
+                    size,
+                    distance: offset(data.center_mass).magnitude(),
+                    acc,
+                    error: acc - exact,
+                }
+            })
+            .collect();
+
+        let mut exact = Vector3::zero();
+        for other in objects.iter().filter(|other| !other.is_frozen()) {
+            match bounds {
+                Some(bounds) => obj.get_acc_towards_periodic(other, bounds, &mut exact),
+                None => obj.get_acc_towards(other, &mut exact),
+            }
+        }
+
+        let tree_acc = contributions.iter().map(|c| c.acc).sum();
+        contributions.sort_by(|a, b| b.error.magnitude2().total_cmp(&a.error.magnitude2()));
+        contributions.truncate(FORCE_CHECK_NODES);
+        Some(Self {
+            object: index,
+            theta,
+            tree: tree_acc,
+            exact,
+            interactions: nodes.len(),
+            worst_nodes: contributions,
+        })
+    }
+
+    /// Size of the difference between the tree and direct summation, relative to the
+    /// acceleration by direct summation.
+    pub fn relative_error(&self) -> f64 {
+        (self.tree - self.exact).magnitude() / self.exact.magnitude()
+    }
+}
+
+/// Accelerations of every object by the tree and by direct summation, from one step on
+/// the same state.
+#[derive(Debug, Clone)]
+pub struct DirectComparison {
+    pub theta: f64,
+    /// Acceleration of each object by direct summation, in AU/s^2.
+    pub exact: Vec<Vector3<f64>>,
+    /// Tree acceleration minus the acceleration by direct summation, for each object.
+    pub errors: Vec<Vector3<f64>>,
+    /// Whether each object is simulated. Frozen objects are left out of the statistics.
+    simulated: Vec<bool>,
+}
+
+impl DirectComparison {
+    /// Size of the error of `objects[index]`, relative to its acceleration by direct
+    /// summation. `None` for frozen objects and objects with no acceleration at all.
+    pub fn relative_error(&self, index: usize) -> Option<f64> {
+        let exact = self.exact[index].magnitude();
+        (self.simulated[index] && exact > 0.0).then(|| self.errors[index].magnitude() / exact)
+    }
+
+    /// Root mean square of the size of the errors, in AU/s^2.
+    pub fn rms_error(&self) -> f64 {
+        rms(self.simulated_indices().map(|i| self.errors[i].magnitude()))
+    }
+
+    /// Root mean square of the relative errors.
+    pub fn rms_relative_error(&self) -> f64 {
+        rms(self
+            .simulated_indices()
+            .filter_map(|i| self.relative_error(i)))
+    }
+
+    /// Largest relative error, and the object it belongs to.
+    pub fn max_relative_error(&self) -> Option<(usize, f64)> {
+        self.simulated_indices()
+            .filter_map(|i| Some((i, self.relative_error(i)?)))
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+    }
+
+    fn simulated_indices(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.simulated.len()).filter(|i| self.simulated[*i])
+    }
+}
+
+fn rms(values: impl Iterator<Item = f64>) -> f64 {
+    let (sum, count) = values.fold((0.0, 0usize), |(sum, count), v| (sum + v * v, count + 1));
+    if count == 0 {
+        0.0
+    } else {
+        (sum / count as f64).sqrt()
+    }
+}
+
+/// Run one Barnes-Hut step with opening angle `theta` and one direct step on `objects`,
+/// and compare the gravity each object gets, for choosing `theta`. Only gravity is
+/// compared, without moving anything.
+pub fn validate_against_direct(objects: &[ObjectInfo], theta: f64) -> DirectComparison {
+    let mut objects = objects.to_vec();
+    let mut tree_acc = vec![Vector3::zero(); objects.len()];
+    let mut exact = vec![Vector3::zero(); objects.len()];
+    super::iter(
+        &mut objects,
+        &mut tree_acc,
+        &mut FmmTree::new(),
+        theta,
+        None,
+        false,
+        BARNES_HUT_GROUP_SIZE,
+    );
+    BruteForceSim::new().iter(&mut objects, &mut exact);
+
+    let simulated: Vec<_> = objects.iter().map(|obj| !obj.is_frozen()).collect();
+    let errors = tree_acc
+        .iter()
+        .zip(&exact)
+        .zip(&simulated)
+        .map(|((tree, exact), simulated)| {
+            if *simulated {
+                tree - exact
+            } else {
+                Vector3::zero()
+            }
+        })
+        .collect();
+    DirectComparison {
+        theta,
+        exact,
+        errors,
+        simulated,
+    }
+}
+
+/// The leaves below `id`.
+fn leaves(tree: &FmmTree, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+    let mut stack = vec![id];
+    std::iter::from_fn(move || {
+        while let Some(id) = stack.pop() {
+            match &tree.get(id).0.data {
+                NodeData::External => return Some(id),
+                NodeData::Internal { children, .. } => stack.extend(children.iter().flatten()),
+            }
+        }
+        None
+    })
+}
@@ -0,0 +1,36 @@
+//! Local density around every object, from the sphere holding its nearest neighbors.
+//!
+//! The density at an object is the mass of the `k` objects nearest to it over the volume
+//! of the smallest sphere around it that holds them, the object itself included if it
+//! attracts others. Only objects that attract others count as neighbors, so test
+//! particles get the density of the massive objects around them. Neighbors are found
+//! with the octree.
+
+use std::{collections::BinaryHeap, f64::consts::PI};
+
+use rayon::iter::{IndexedParallelIterator, IntoParallelRefMutIterator, ParallelIterator};
+
+use super::FmmTree;
+use crate::sim::ObjectInfo;
+
+/// Density around each of `objects` from its `k` nearest neighbors, in earth masses per
+/// cubic AU. Objects with all their neighbors at their own position get an infinite
+/// density, and every object gets zero if none of them attract others.
+pub fn local_densities(objects: &[ObjectInfo], k: usize) -> Vec<f64> {
+    let mut tree = FmmTree::new();
+    tree.build_tree(objects);
+    let mut densities = vec![0.0; objects.len()];
+    densities.par_iter_mut().enumerate().for_each_init(
+        || (Vec::new(), BinaryHeap::new()),
+        |(stack, nearest), (idx, density)| {
+            tree.nearest(objects[idx].pos, k, stack, nearest);
+            let Some(furthest) = nearest.peek() else {
+                return;
+            };
+            let mass: f64 = nearest.iter().map(|n| n.mass).sum();
+            let radius = furthest.dist_sq.sqrt();
+            *density = mass / (4.0 / 3.0 * PI * radius * radius * radius);
+        },
+    );
+    densities
+}
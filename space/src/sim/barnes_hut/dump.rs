@@ -0,0 +1,145 @@
+//! Binary dumps of an octree, so that a tree that misbehaves can be looked at offline.
+//!
+//! Layout: magic, version, `u64` number of nodes, then for each node its center of
+//! mass and mass as `f64`s and a `u8` tag, 0 for a leaf and 1 for an internal node.
+//! Internal nodes go on with the ranges and squared size of their region as `f64`s,
+//! and the index of each of their eight children as a `u64`, `u64::MAX` for none.
+//! Everything is little endian.
+
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use anyhow::{Context, bail};
+use cgmath::Point3;
+
+use super::tree::{Data, FmmNode, FmmTree, NodeData, NodeId, Region};
+
+const MAGIC: &[u8; 8] = b"NBODYOCT";
+const VERSION: u32 = 1;
+const NO_CHILD: u64 = u64::MAX;
+
+fn read_bytes<const N: usize>(reader: &mut impl Read) -> anyhow::Result<[u8; N]> {
+    let mut buf = [0u8; N];
+    reader.read_exact(&mut buf).context("Truncated octree")?;
+    Ok(buf)
+}
+
+fn read_f64(reader: &mut impl Read) -> anyhow::Result<f64> {
+    Ok(f64::from_le_bytes(read_bytes(reader)?))
+}
+
+fn read_u64(reader: &mut impl Read) -> anyhow::Result<u64> {
+    Ok(u64::from_le_bytes(read_bytes(reader)?))
+}
+
+impl FmmTree {
+    /// Write the nodes of the tree to `path`, see the module docs for the format.
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let file =
+            File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+        let mut writer = BufWriter::new(file);
+        self.write_to(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Read a tree written by `save`.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let file =
+            File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        Self::read_from(BufReader::new(file))
+            .with_context(|| format!("Failed to read octree from {}", path.display()))
+    }
+
+    pub fn write_to(&self, mut writer: impl Write) -> anyhow::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&(self.nodes.len() as u64).to_le_bytes())?;
+        for (node, data) in self.nodes.iter().zip(&self.data) {
+            for v in [
+                data.center_mass.x,
+                data.center_mass.y,
+                data.center_mass.z,
+                data.mass,
+            ] {
+                writer.write_all(&v.to_le_bytes())?;
+            }
+            match &node.data {
+                NodeData::External => writer.write_all(&[0])?,
+                NodeData::Internal { children, region } => {
+                    writer.write_all(&[1])?;
+                    for v in [
+                        region.x_range.0,
+                        region.x_range.1,
+                        region.y_range.0,
+                        region.y_range.1,
+                        region.z_range.0,
+                        region.z_range.1,
+                        region.size_sq,
+                    ] {
+                        writer.write_all(&v.to_le_bytes())?;
+                    }
+                    for child in children {
+                        let idx = child.map_or(NO_CHILD, |id| id.0 as u64);
+                        writer.write_all(&idx.to_le_bytes())?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read_from(mut reader: impl Read) -> anyhow::Result<Self> {
+        if &read_bytes::<8>(&mut reader)? != MAGIC {
+            bail!("Not an octree dump");
+        }
+        let version = u32::from_le_bytes(read_bytes(&mut reader)?);
+        if version != VERSION {
+            bail!("Unsupported octree version {version}");
+        }
+        let count = read_u64(&mut reader)?;
+
+        let mut tree = FmmTree::new();
+        for idx in 0..count {
+            tree.data.push(Data {
+                center_mass: Point3::new(
+                    read_f64(&mut reader)?,
+                    read_f64(&mut reader)?,
+                    read_f64(&mut reader)?,
+                ),
+                mass: read_f64(&mut reader)?,
+            });
+            let node = match read_bytes::<1>(&mut reader)?[0] {
+                0 => FmmNode::new_external(),
+                1 => {
+                    let region = Region {
+                        x_range: (read_f64(&mut reader)?, read_f64(&mut reader)?),
+                        y_range: (read_f64(&mut reader)?, read_f64(&mut reader)?),
+                        z_range: (read_f64(&mut reader)?, read_f64(&mut reader)?),
+                        size_sq: read_f64(&mut reader)?,
+                    };
+                    let mut children = [None; 8];
+                    for child in &mut children {
+                        let child_idx = read_u64(&mut reader)?;
+                        if child_idx == NO_CHILD {
+                            continue;
+                        }
+                        // Children are always pushed after their parent, so this also
+                        // rules out cycles.
+                        if child_idx <= idx || child_idx >= count {
+                            bail!("Node {idx} has invalid child {child_idx}");
+                        }
+                        *child = Some(NodeId(child_idx as usize));
+                    }
+                    FmmNode::new_internal(region, children)
+                }
+                tag => bail!("Node {idx} has invalid tag {tag}"),
+            };
+            tree.nodes.push(node);
+        }
+        Ok(tree)
+    }
+}
@@ -0,0 +1,158 @@
+//! Friends-of-friends groups, for finding the clumps that form in a collapsing cloud.
+//!
+//! Two objects closer than the linking length are friends, and a group is every object
+//! that can be reached from another through friends. The linking length is given as a
+//! fraction of the mean separation of the objects, which is what makes the groups
+//! roughly the regions above a certain density. Neighbors are found with the octree.
+
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector3};
+
+use super::FmmTree;
+use crate::{constants::FOF_MIN_MEMBERS, sim::ObjectInfo};
+
+/// A group found by friends-of-friends.
+#[derive(Debug, Clone)]
+pub struct FofGroup {
+    /// Number of objects in the group.
+    pub members: usize,
+    /// Total mass, in earth masses.
+    pub mass: f64,
+    pub center_mass: Point3<f64>,
+    /// Distance from the center of mass to the furthest member, in AU.
+    pub radius: f64,
+}
+
+/// Groups of the objects that attract others, with at least `FOF_MIN_MEMBERS` each.
+#[derive(Debug, Clone)]
+pub struct FofGroups {
+    /// Linking length the groups were found with, in AU.
+    pub linking_length: f64,
+    /// Groups, most massive first.
+    pub groups: Vec<FofGroup>,
+    /// Index into `groups` of the group of each object, or `None` for objects in no group.
+    pub membership: Vec<Option<usize>>,
+}
+
+impl FofGroups {
+    /// Find the groups of `objects` with a linking length of `linking_factor` times their
+    /// mean separation, which is taken over their bounding box. Test particles are not
+    /// part of any group.
+    pub fn find(objects: &[ObjectInfo], linking_factor: f64) -> Self {
+        let massive: Vec<_> = (0..objects.len())
+            .filter(|idx| objects[*idx].gravitating_mass() > 0.0)
+            .collect();
+        let linking_length = linking_factor * mean_separation(objects, &massive);
+
+        let mut tree = FmmTree::new();
+        tree.build_tree(objects);
+        let mut parent: Vec<_> = (0..objects.len()).collect();
+        let mut stack = Vec::new();
+        let mut friends = Vec::new();
+        for &idx in &massive {
+            friends.clear();
+            tree.for_each_within(objects[idx].pos, linking_length, &mut stack, |other| {
+                if other > idx {
+                    friends.push(other);
+                }
+            });
+            for &other in &friends {
+                union(&mut parent, idx, other);
+            }
+        }
+
+        // Collect the members of each root, dropping the ones too small to count.
+        let mut members: Vec<Vec<usize>> = vec![Vec::new(); objects.len()];
+        for &idx in &massive {
+            let root = find(&mut parent, idx);
+            members[root].push(idx);
+        }
+        let mut groups: Vec<_> = members
+            .into_iter()
+            .filter(|members| members.len() >= FOF_MIN_MEMBERS)
+            .map(|members| (group(objects, &members), members))
+            .collect();
+        groups.sort_by(|a, b| b.0.mass.total_cmp(&a.0.mass));
+
+        let mut membership = vec![None; objects.len()];
+        for (group, (_, members)) in groups.iter().enumerate() {
+            for &idx in members {
+                membership[idx] = Some(group);
+            }
+        }
+        Self {
+            linking_length,
+            groups: groups.into_iter().map(|(group, _)| group).collect(),
+            membership,
+        }
+    }
+
+    /// Number of objects in any group.
+    pub fn grouped(&self) -> usize {
+        self.groups.iter().map(|group| group.members).sum()
+    }
+}
+
+/// Side of the cube that each of `indices` would have to itself if they were spread
+/// evenly over their bounding box.
+fn mean_separation(objects: &[ObjectInfo], indices: &[usize]) -> f64 {
+    if indices.len() < 2 {
+        return 0.0;
+    }
+    let mut min = Point3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+    let mut max = Point3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
+    for &idx in indices {
+        let pos = objects[idx].pos;
+        for i in 0..3 {
+            min[i] = min[i].min(pos[i]);
+            max[i] = max[i].max(pos[i]);
+        }
+    }
+    let size = max - min;
+    // Flat or linear arrangements have no volume, so measure them in fewer dimensions.
+    let extents: Vec<_> = [size.x, size.y, size.z]
+        .into_iter()
+        .filter(|s| *s > 0.0)
+        .collect();
+    if extents.is_empty() {
+        return 0.0;
+    }
+    let volume: f64 = extents.iter().product();
+    (volume / indices.len() as f64).powf(1.0 / extents.len() as f64)
+}
+
+fn group(objects: &[ObjectInfo], members: &[usize]) -> FofGroup {
+    let mass: f64 = members.iter().map(|idx| objects[*idx].mass).sum();
+    let center_mass = Point3::from_vec(
+        members
+            .iter()
+            .map(|idx| objects[*idx].pos.to_vec() * objects[*idx].mass)
+            .sum::<Vector3<f64>>()
+            / mass,
+    );
+    let radius = members
+        .iter()
+        .map(|idx| (objects[*idx].pos - center_mass).magnitude())
+        .fold(0.0, f64::max);
+    FofGroup {
+        members: members.len(),
+        mass,
+        center_mass,
+        radius,
+    }
+}
+
+fn find(parent: &mut [usize], mut idx: usize) -> usize {
+    while parent[idx] != idx {
+        // Halve the path on the way up, so that later lookups are shorter.
+        parent[idx] = parent[parent[idx]];
+        idx = parent[idx];
+    }
+    idx
+}
+
+fn union(parent: &mut [usize], a: usize, b: usize) {
+    let (a, b) = (find(parent, a), find(parent, b));
+    if a != b {
+        parent[a.max(b)] = a.min(b);
+    }
+}
@@ -1,31 +1,74 @@
-use cgmath::{InnerSpace, Vector3};
-use rayon::iter::{
-    IndexedParallelIterator, IntoParallelRefIterator, IntoParallelRefMutIterator, ParallelIterator,
+//! Barnes-Hut on an octree.
+//!
+//! The tree is walked once per group of nearby objects rather than once per object.
+//! Nodes that are far enough away from every object in the group are collected in an
+//! interaction list, which is then evaluated for each object in a tight loop.
+
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector3, Zero};
+use rayon::{
+    iter::{IndexedParallelIterator, ParallelIterator},
+    slice::{ParallelSlice, ParallelSliceMut},
 };
 
-use crate::sim::ObjectInfo;
+use crate::{
+    constants::{BARNES_HUT_GROUP_SIZE, COLLISION_EPSILON, G},
+    sim::{ObjectInfo, PeriodicBox},
+};
 
+mod accuracy;
+mod density;
+mod dump;
+mod groups;
 mod tree;
 
-pub(super) use tree::FmmTree;
+pub use accuracy::{DirectComparison, ForceCheck, NodeContribution, validate_against_direct};
+pub use density::local_densities;
+pub use groups::{FofGroup, FofGroups};
+pub use tree::FmmTree;
+use tree::GroupBuffers;
+pub(crate) use tree::{NodeData, NodeId};
 
-pub fn iter(info: &mut [ObjectInfo], out: &mut [Vector3<f64>], tree: &mut FmmTree, theta: f64) {
-    tree.clear();
-    tree.build_tree(info);
+/// With `bounds`, the tree is built from the objects as they are, which must be inside
+/// the box, and every offset in the walk and the interactions is a minimum image.
+///
+/// With `incremental`, `tree` must be the tree of the last step, and is updated for the
+/// objects rather than rebuilt, see `FmmTree::update_tree`.
+///
+/// The tree is walked once for every `group_size` objects.
+pub fn iter(
+    info: &mut [ObjectInfo],
+    out: &mut [Vector3<f64>],
+    tree: &mut FmmTree,
+    theta: f64,
+    bounds: Option<&PeriodicBox>,
+    incremental: bool,
+    group_size: usize,
+) {
+    prepare_tree(info, tree, incremental);
     // Edge-case. The Barnes-Hut algorithm does not register massless particles,
     // which elegantly just means that we skip the computation of attraction _towards_
     // these. If there are no massive particles at all, we can skip the entire
     // acceleration computation.
-    if tree.len() == 0 {
+    if tree.is_empty() {
         return;
     }
     let theta_sq = theta * theta;
+    // Borrowed for the duration of the step, so the tree can be read meanwhile.
+    let mut buffers = std::mem::take(&mut tree.groups);
+    spatial_order(info, &mut buffers);
+    let GroupBuffers { order, acc, .. } = &mut buffers;
 
-    info.par_iter()
-        .zip(out.par_iter_mut())
-        .for_each(|(obj, out_acc)| {
-            compute_acc(&tree, obj, out_acc, theta_sq);
+    order
+        .par_chunks(group_size)
+        .zip(acc.par_chunks_mut(group_size))
+        .for_each_init(Scratch::default, |scratch, (group, acc)| {
+            compute_group_acc(tree, info, group, acc, theta_sq, bounds, scratch);
         });
+
+    for (idx, acc) in order.iter().zip(acc.iter()) {
+        out[*idx] += *acc;
+    }
+    tree.groups = buffers;
 }
 
 pub fn iter_single_threaded(
@@ -33,19 +76,253 @@ pub fn iter_single_threaded(
     out: &mut [Vector3<f64>],
     tree: &mut FmmTree,
     theta: f64,
+    bounds: Option<&PeriodicBox>,
+    incremental: bool,
+    group_size: usize,
 ) {
-    tree.clear();
+    prepare_tree(info, tree, incremental);
+    if tree.is_empty() {
+        return;
+    }
+    let theta_sq = theta * theta;
+    let mut buffers = std::mem::take(&mut tree.groups);
+    spatial_order(info, &mut buffers);
+    let GroupBuffers { order, acc, .. } = &mut buffers;
+    let mut scratch = Scratch::default();
+
+    for (group, acc) in order.chunks(group_size).zip(acc.chunks_mut(group_size)) {
+        compute_group_acc(tree, info, group, acc, theta_sq, bounds, &mut scratch);
+    }
+
+    for (idx, acc) in order.iter().zip(acc.iter()) {
+        out[*idx] += *acc;
+    }
+    tree.groups = buffers;
+}
+
+fn prepare_tree(info: &[ObjectInfo], tree: &mut FmmTree, incremental: bool) {
+    if incremental {
+        tree.update_tree(info);
+    } else {
+        tree.clear();
+        tree.build_tree(info);
+    }
+}
+
+/// Total potential energy of the objects, in earth masses AU^2/s^2, with the tree
+/// standing in for distant objects like in `iter`. Ignores periodic boxes.
+pub fn potential_energy(info: &[ObjectInfo], theta: f64) -> f64 {
+    let mut tree = FmmTree::new();
     tree.build_tree(info);
+    if tree.is_empty() {
+        return 0.0;
+    }
     let theta_sq = theta * theta;
+    let mut buffers = GroupBuffers::default();
+    spatial_order(info, &mut buffers);
+    let mut order = buffers.order;
+    order.retain(|idx| info[*idx].gravitating_mass() > 0.0);
+
+    let sum: f64 = order
+        .par_chunks(BARNES_HUT_GROUP_SIZE)
+        .map_init(Scratch::default, |scratch, group| {
+            let (center, radius) = bounding_sphere(info, group);
+            let Scratch { stack, list } = scratch;
+            list.clear();
+            walk(&tree, center, radius, theta_sq, None, stack, |id| {
+                let (_, data) = tree.get(id);
+                list.push(data.center_mass, data.mass);
+            });
+            group
+                .iter()
+                .map(|idx| info[*idx].mass * list.potential(info[*idx].pos))
+                .sum::<f64>()
+        })
+        .sum();
+    // Every pair is counted from both ends.
+    sum / 2.0
+}
 
-    for (obj, out_acc) in info.iter().zip(out.iter_mut()) {
-        compute_acc(tree, obj, out_acc, theta_sq);
+/// Fill `buffers.order` with the indices of all objects that are not frozen, sorted along
+/// a Morton curve so that consecutive objects are close to each other, and `buffers.acc`
+/// with a zero acceleration for each.
+fn spatial_order(info: &[ObjectInfo], buffers: &mut GroupBuffers) {
+    let mut min = Point3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+    let mut max = Point3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
+    for obj in info.iter().filter(|obj| !obj.is_frozen()) {
+        for i in 0..3 {
+            min[i] = min[i].min(obj.pos[i]);
+            max[i] = max[i].max(obj.pos[i]);
+        }
     }
+    let size = (0..3).map(|i| max[i] - min[i]).fold(0.0, f64::max);
+    let scale = if size > 0.0 { 1023.0 / size } else { 0.0 };
+
+    let GroupBuffers { keyed, order, acc } = buffers;
+    keyed.clear();
+    keyed.extend(
+        info.iter()
+            .enumerate()
+            .filter(|(_, obj)| !obj.is_frozen())
+            .map(|(idx, obj)| {
+                let mut key = 0u32;
+                for i in 0..3 {
+                    let cell = ((obj.pos[i] - min[i]) * scale) as u32;
+                    for bit in 0..10 {
+                        key |= ((cell >> bit) & 1) << (bit * 3 + i);
+                    }
+                }
+                (key, idx)
+            }),
+    );
+    keyed.sort_unstable();
+    order.clear();
+    order.extend(keyed.iter().map(|(_, idx)| *idx));
+    acc.clear();
+    acc.resize(order.len(), Vector3::zero());
+}
+
+/// Number of interactions evaluated together.
+const LANES: usize = 4;
+
+/// Nodes accepted by the tree walk for a group of objects. The components are stored
+/// as separate arrays, so that evaluating the list is a loop the compiler can vectorize.
+#[derive(Default)]
+struct InteractionList {
+    x: Vec<f64>,
+    y: Vec<f64>,
+    z: Vec<f64>,
+    mass: Vec<f64>,
 }
 
-fn compute_acc(tree: &FmmTree, obj: &ObjectInfo, out: &mut Vector3<f64>, theta_sq: f64) {
-    let estimate = 8 * (tree.len() as f32).ln() as usize;
-    let mut stack = Vec::with_capacity(estimate);
+impl InteractionList {
+    fn clear(&mut self) {
+        self.x.clear();
+        self.y.clear();
+        self.z.clear();
+        self.mass.clear();
+    }
+
+    fn push(&mut self, pos: Point3<f64>, mass: f64) {
+        self.x.push(pos.x);
+        self.y.push(pos.y);
+        self.z.push(pos.z);
+        self.mass.push(mass);
+    }
+
+    /// Potential at `pos` of every entry in the list, per unit mass. Entries at `pos`
+    /// itself are left out.
+    fn potential(&self, pos: Point3<f64>) -> f64 {
+        let mut potential = 0.0;
+        for i in 0..self.mass.len() {
+            let rel = Vector3::new(self.x[i] - pos.x, self.y[i] - pos.y, self.z[i] - pos.z);
+            let dist = rel.magnitude();
+            if dist > 0.0 {
+                potential -= self.mass[i] * G / dist;
+            }
+        }
+        potential
+    }
+
+    /// Acceleration at `pos` towards every entry in the list. Entries at `pos` itself
+    /// contribute nothing, since the offset to them is zero.
+    fn eval(&self, pos: Point3<f64>, bounds: Option<&PeriodicBox>) -> Vector3<f64> {
+        // Monomorphized separately, so the usual case pays nothing for periodic boxes.
+        match bounds {
+            Some(bounds) => self.eval_with(pos, |rel| bounds.min_image(rel)),
+            None => self.eval_with(pos, |rel| rel),
+        }
+    }
+
+    #[inline(always)]
+    fn eval_with(
+        &self,
+        pos: Point3<f64>,
+        offset: impl Fn(Vector3<f64>) -> Vector3<f64>,
+    ) -> Vector3<f64> {
+        // Independent accumulators per lane, so that the loop can be vectorized
+        // without reordering any individual sum.
+        let mut acc = [[0.0; LANES]; 3];
+        let x = self.x.chunks_exact(LANES);
+        let y = self.y.chunks_exact(LANES);
+        let z = self.z.chunks_exact(LANES);
+        let m = self.mass.chunks_exact(LANES);
+        let rest = m.remainder().len();
+        for (((x, y), z), m) in x.zip(y).zip(z).zip(m) {
+            for l in 0..LANES {
+                let Vector3 {
+                    x: dx,
+                    y: dy,
+                    z: dz,
+                } = offset(Vector3::new(x[l] - pos.x, y[l] - pos.y, z[l] - pos.z));
+                let mag_sq = dx * dx + dy * dy + dz * dz;
+                let f = m[l] * G / (mag_sq * mag_sq.sqrt() + COLLISION_EPSILON);
+                acc[0][l] += dx * f;
+                acc[1][l] += dy * f;
+                acc[2][l] += dz * f;
+            }
+        }
+        for i in self.mass.len() - rest..self.mass.len() {
+            let Vector3 {
+                x: dx,
+                y: dy,
+                z: dz,
+            } = offset(Vector3::new(
+                self.x[i] - pos.x,
+                self.y[i] - pos.y,
+                self.z[i] - pos.z,
+            ));
+            let mag_sq = dx * dx + dy * dy + dz * dz;
+            let f = self.mass[i] * G / (mag_sq * mag_sq.sqrt() + COLLISION_EPSILON);
+            acc[0][0] += dx * f;
+            acc[1][0] += dy * f;
+            acc[2][0] += dz * f;
+        }
+        Vector3::new(
+            acc[0].iter().sum(),
+            acc[1].iter().sum(),
+            acc[2].iter().sum(),
+        )
+    }
+}
+
+/// Center and radius of the bounding sphere of `group`.
+fn bounding_sphere(info: &[ObjectInfo], group: &[usize]) -> (Point3<f64>, f64) {
+    let center = Point3::from_vec(
+        group
+            .iter()
+            .map(|i| info[*i].pos.to_vec())
+            .sum::<Vector3<f64>>()
+            / group.len() as f64,
+    );
+    let radius = group
+        .iter()
+        .map(|i| (info[*i].pos - center).magnitude())
+        .fold(0.0, f64::max);
+    (center, radius)
+}
+
+/// Space reused between the groups handled by a thread, so that walking the tree does not
+/// allocate once the first few groups have grown it.
+#[derive(Default)]
+struct Scratch {
+    stack: Vec<Option<NodeId>>,
+    list: InteractionList,
+}
+
+/// Walk the tree for a group of objects within `radius` of `center`, calling `accept`
+/// with every node that the group interacts with directly.
+#[inline(always)]
+fn walk(
+    tree: &FmmTree,
+    center: Point3<f64>,
+    radius: f64,
+    theta_sq: f64,
+    bounds: Option<&PeriodicBox>,
+    stack: &mut Vec<Option<NodeId>>,
+    mut accept: impl FnMut(NodeId),
+) {
+    stack.clear();
     stack.push(Some(tree.root_id()));
 
     while let Some(node_id) = stack.pop() {
@@ -55,22 +332,49 @@ fn compute_acc(tree: &FmmTree, obj: &ObjectInfo, out: &mut Vector3<f64>, theta_s
 
         let (node, data) = tree.get(id);
 
-        let rel = data.center_mass - obj.pos;
-        let dist_sq = rel.magnitude2();
-        if dist_sq == 0.0 {
-            continue;
-        }
-
         match &node.data {
-            tree::NodeData::Internal { children, region }
-                if theta_sq * dist_sq < region.size_sq() =>
-            {
-                stack.extend(children);
-            }
-            _ => {
-                // Treat this node as a single body
-                obj.get_acc_towards_raw(data.mass, rel, dist_sq, out);
+            tree::NodeData::Internal { children, region } => {
+                // A node is accepted only if it is far enough away from every object in the group.
+                let rel = data.center_mass - center;
+                let rel = bounds.map_or(rel, |b| b.min_image(rel));
+                let dist = (rel.magnitude() - radius).max(0.0);
+                // In a periodic box, the nearest copies of the objects in a node may be
+                // on different sides of the group, and then the node cannot stand in for them.
+                let straddles = bounds.is_some_and(|b| {
+                    let rel = b.min_image(region.center() - center);
+                    let half = region.half_extent();
+                    (0..3).any(|i| rel[i].abs() + half[i] + radius > b.size / 2.0)
+                });
+                if straddles || theta_sq * dist * dist < region.size_sq() {
+                    stack.extend(children);
+                } else {
+                    accept(id);
+                }
             }
+            tree::NodeData::External => accept(id),
         }
     }
 }
+
+fn compute_group_acc(
+    tree: &FmmTree,
+    info: &[ObjectInfo],
+    group: &[usize],
+    out: &mut [Vector3<f64>],
+    theta_sq: f64,
+    bounds: Option<&PeriodicBox>,
+    scratch: &mut Scratch,
+) {
+    let (center, radius) = bounding_sphere(info, group);
+    let Scratch { stack, list } = scratch;
+
+    list.clear();
+    walk(tree, center, radius, theta_sq, bounds, stack, |id| {
+        let (_, data) = tree.get(id);
+        list.push(data.center_mass, data.mass);
+    });
+
+    for (idx, out) in group.iter().zip(out.iter_mut()) {
+        *out += list.eval(info[*idx].pos, bounds);
+    }
+}
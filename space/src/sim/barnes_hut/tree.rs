@@ -1,11 +1,23 @@
-use cgmath::{EuclideanSpace, Point3};
+use std::{collections::BinaryHeap, ops::Range};
 
-use crate::sim::ObjectInfo;
+use cgmath::{InnerSpace, Point3, Vector3};
+
+use crate::{
+    constants::BARNES_HUT_TREE_MARGIN,
+    sim::{ObjectInfo, capacity_bytes},
+};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub struct NodeId(usize);
+pub struct NodeId(pub(super) usize);
 
-#[derive(Debug, Clone)]
+impl NodeId {
+    /// Position of the node in the arena, for keeping data alongside the tree.
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Region {
     pub x_range: (f64, f64),
     pub y_range: (f64, f64),
@@ -34,9 +46,54 @@ impl Region {
             (self.z_range.0 + self.z_range.1) / 2.0,
         )
     }
+
+    pub fn half_extent(&self) -> Vector3<f64> {
+        Vector3::new(
+            (self.x_range.1 - self.x_range.0) / 2.0,
+            (self.y_range.1 - self.y_range.0) / 2.0,
+            (self.z_range.1 - self.z_range.0) / 2.0,
+        )
+    }
+
+    /// Squared distance from `pos` to the closest point of the region, zero inside it.
+    pub fn dist_sq(&self, pos: Point3<f64>) -> f64 {
+        let rel = pos - self.center();
+        let half = self.half_extent();
+        (0..3)
+            .map(|i| (rel[i].abs() - half[i]).max(0.0).powi(2))
+            .sum()
+    }
+}
+
+/// An object found by `FmmTree::nearest`. Ordered by distance, so that a `BinaryHeap`
+/// of them has the furthest on top.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Neighbor {
+    pub dist_sq: f64,
+    pub mass: f64,
 }
 
-#[derive(Debug)]
+impl PartialEq for Neighbor {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for Neighbor {}
+
+impl PartialOrd for Neighbor {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Neighbor {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.dist_sq.total_cmp(&other.dist_sq)
+    }
+}
+
+#[derive(Debug, PartialEq)]
 pub enum NodeData {
     External,
     Internal {
@@ -45,7 +102,7 @@ pub enum NodeData {
     },
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct FmmNode {
     pub data: NodeData,
 }
@@ -64,31 +121,85 @@ impl FmmNode {
     }
 }
 
-#[derive(Debug)]
+/// Octree stored as a flat arena. Nodes, node data and the scratch buffer used
+/// while building are all kept between ticks, so once the buffers have grown to
+/// fit the simulation, rebuilding the tree does not allocate.
+///
+/// The build leaves the objects in the scratch buffer sorted by node, with the objects
+/// under each node in the range `spans` has for it. This is what lets `update_tree`
+/// follow objects as they move, without sorting them again.
+#[derive(Debug, Default)]
 pub struct FmmTree {
-    nodes: Vec<FmmNode>,
-    data: Vec<Data>,
+    pub(super) nodes: Vec<FmmNode>,
+    pub(super) data: Vec<Data>,
+    scratch: Vec<Body>,
+    spans: Vec<Range<usize>>,
+    /// Number of nodes after the last full build. Subtrees rebuilt by `update_tree` are
+    /// pushed to the end of the arena, leaving the nodes they replace unused.
+    built_len: usize,
     shared_stack: Vec<Option<NodeId>>,
+    pub(super) groups: GroupBuffers,
+}
+
+/// Buffers for evaluating the tree for groups of nearby objects, see `barnes_hut::iter`.
+/// Kept along with the tree, so that they don't need allocating every step either.
+#[derive(Debug, Default)]
+pub(super) struct GroupBuffers {
+    /// Morton key of each object, for sorting them.
+    pub keyed: Vec<(u32, usize)>,
+    /// Indices of the objects, in the order they are grouped in.
+    pub order: Vec<usize>,
+    /// Acceleration of each object in `order`.
+    pub acc: Vec<Vector3<f64>>,
 }
 
+impl GroupBuffers {
+    fn memory_usage(&self) -> usize {
+        capacity_bytes(&self.keyed) + capacity_bytes(&self.order) + capacity_bytes(&self.acc)
+    }
+}
+
+/// An object in the scratch buffer, with its index in the objects the tree was built for.
 #[derive(Debug, Clone)]
+struct Body {
+    pos: Point3<f64>,
+    mass: f64,
+    idx: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Data {
     pub center_mass: Point3<f64>,
     pub mass: f64,
 }
 
+/// Trees are equal if they have the same nodes, regardless of the scratch buffers.
+impl PartialEq for FmmTree {
+    fn eq(&self, other: &Self) -> bool {
+        self.nodes == other.nodes && self.data == other.data
+    }
+}
+
 impl FmmTree {
     pub fn new() -> Self {
         Self {
             nodes: Vec::new(),
             data: Vec::new(),
+            scratch: Vec::new(),
+            spans: Vec::new(),
+            built_len: 0,
             shared_stack: Vec::new(),
+            groups: GroupBuffers::default(),
         }
     }
 
+    /// Remove all nodes from the tree, keeping the allocated memory.
     pub fn clear(&mut self) {
         self.nodes.clear();
         self.data.clear();
+        self.scratch.clear();
+        self.spans.clear();
+        self.built_len = 0;
         self.shared_stack.clear();
     }
 
@@ -100,19 +211,49 @@ impl FmmTree {
         self.nodes.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Bytes allocated by the arena and its scratch buffers.
+    pub fn memory_usage(&self) -> usize {
+        capacity_bytes(&self.nodes)
+            + capacity_bytes(&self.data)
+            + capacity_bytes(&self.scratch)
+            + capacity_bytes(&self.spans)
+            + capacity_bytes(&self.shared_stack)
+            + self.groups.memory_usage()
+    }
+
     pub fn get(&self, id: NodeId) -> (&FmmNode, &Data) {
         (&self.nodes[id.0], &self.data[id.0])
     }
 
+    /// Index, position and mass of each object under the node `id`, as of the build.
+    pub fn objects_under(
+        &self,
+        id: NodeId,
+    ) -> impl Iterator<Item = (usize, Point3<f64>, f64)> + Clone + '_ {
+        self.scratch[self.spans[id.0].clone()]
+            .iter()
+            .map(|body| (body.idx, body.pos, body.mass))
+    }
+
     pub fn shared_stack(&mut self) -> &mut Vec<Option<NodeId>> {
         &mut self.shared_stack
     }
 
     pub fn build_tree(&mut self, objects: &[ObjectInfo]) {
-        // Compute the bounding box of all objects
+        self.build_tree_with_margin(objects, 0.0);
+    }
+
+    /// Build the tree with the root region grown by `margin` times its size on every
+    /// side, so that objects can move a little before they leave it.
+    fn build_tree_with_margin(&mut self, objects: &[ObjectInfo], margin: f64) {
+        // Compute the bounding box of the objects in the tree
         let mut min = Point3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
         let mut max = Point3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
-        for obj in objects {
+        for obj in objects.iter().filter(|obj| obj.gravitating_mass() > 0.0) {
             min.x = min.x.min(obj.pos.x);
             min.y = min.y.min(obj.pos.y);
             min.z = min.z.min(obj.pos.z);
@@ -120,17 +261,27 @@ impl FmmTree {
             max.y = max.y.max(obj.pos.y);
             max.z = max.z.max(obj.pos.z);
         }
+        let pad = (max - min) * margin;
+        let (min, max) = (min - pad, max + pad);
 
-        let data = objects
-            .iter()
-            .filter(|obj| obj.mass > 0.0)
-            .map(|obj| Data {
-                center_mass: obj.pos,
-                mass: obj.mass,
-            })
-            .collect::<Vec<_>>();
+        // Borrow the scratch buffer for the duration of the build, so that the
+        // recursion can partition it in place while pushing to the arena.
+        let mut bodies = std::mem::take(&mut self.scratch);
+        bodies.clear();
+        bodies.extend(
+            objects
+                .iter()
+                .enumerate()
+                .filter(|(_, obj)| obj.gravitating_mass() > 0.0)
+                .map(|(idx, obj)| Body {
+                    pos: obj.pos,
+                    mass: obj.mass,
+                    idx,
+                }),
+        );
         self.build_node(
-            &data,
+            &mut bodies,
+            0,
             Region {
                 x_range: (min.x, max.x),
                 y_range: (min.y, max.y),
@@ -138,9 +289,12 @@ impl FmmTree {
                 size_sq: (min.x - max.x).powi(2),
             },
         );
+        self.scratch = bodies;
+        self.built_len = self.nodes.len();
     }
 
-    fn build_node(&mut self, input: &[Data], region: Region) -> Option<NodeId> {
+    /// Build the node for `input`, which starts at `start` in the scratch buffer.
+    fn build_node(&mut self, input: &mut [Body], start: usize, region: Region) -> Option<NodeId> {
         if input.is_empty() {
             return None;
         }
@@ -148,50 +302,294 @@ impl FmmTree {
         let id = self.nodes.len();
         self.nodes.push(FmmNode::new_external());
         self.data.push(Self::get_data(input));
+        self.spans.push(start..start + input.len());
 
-        if input
-            .windows(2)
-            .any(|w| w[0].center_mass != w[1].center_mass)
-        {
+        if input.windows(2).any(|w| w[0].pos != w[1].pos) {
             let center = region.center();
-            let mut result = octants(&region).map(|r| (Vec::new(), r));
+            let bounds = partition_octants(input, &center);
+            let mut children = [None; 8];
 
-            for o in input {
-                let position = &o.center_mass;
-                let index = (0..3).fold(0, |index, i| {
-                    index + (usize::from(position[i] < center[i]) << i)
-                });
-                result[index].0.push(o.clone());
+            for (i, child_region) in octants(&region).into_iter().enumerate() {
+                children[i] = self.build_node(
+                    &mut input[bounds[i]..bounds[i + 1]],
+                    start + bounds[i],
+                    child_region,
+                );
             }
 
-            self.nodes[id] = FmmNode::new_internal(
-                region,
-                result.map(|(data, region)| self.build_node(&data, region)),
-            );
+            self.nodes[id] = FmmNode::new_internal(region, children);
         }
 
         Some(NodeId(id))
     }
 
-    fn get_data(input: &[Data]) -> Data {
-        let mut center_mass = Point3::new(0.0, 0.0, 0.0);
-        let mut total_mass = 0.0;
-        for obj in input {
-            center_mass += obj.center_mass.to_vec() * obj.mass;
-            total_mass += obj.mass;
+    fn get_data(input: &[Body]) -> Data {
+        center_of_mass(input.iter().map(|body| (body.pos, body.mass)))
+    }
+
+    /// Bring the tree up to date with `objects`, which must be the objects it was built
+    /// for, after they have moved. Subtrees that no object has left keep their nodes and
+    /// only have their centers of mass recomputed. Subtrees that an object has left are
+    /// rebuilt, and the whole tree is rebuilt if the objects that attract others have
+    /// changed, if any of them has left the root region, or if the rebuilt subtrees have
+    /// grown the arena to twice its size.
+    ///
+    /// The root region of trees built here has a margin of `BARNES_HUT_TREE_MARGIN`, so
+    /// the nodes are not the same as those `build_tree` would make for the objects.
+    pub fn update_tree(&mut self, objects: &[ObjectInfo]) {
+        if !self.can_update(objects) {
+            self.clear();
+            self.build_tree_with_margin(objects, BARNES_HUT_TREE_MARGIN);
+            return;
         }
-        center_mass /= total_mass;
-        Data {
-            center_mass,
-            mass: total_mass,
+        for body in &mut self.scratch {
+            let obj = &objects[body.idx];
+            body.pos = obj.pos;
+            body.mass = obj.mass;
+        }
+        let NodeData::Internal { region, .. } = &self.nodes[0].data else {
+            unreachable!("Only trees with an internal root are updated");
+        };
+        self.update_node(0, region.clone());
+        if self.nodes.len() > 2 * self.built_len {
+            self.clear();
+            self.build_tree_with_margin(objects, BARNES_HUT_TREE_MARGIN);
+        }
+    }
+
+    /// Whether the tree was built for objects like `objects`, and they are all still in
+    /// the root region.
+    fn can_update(&self, objects: &[ObjectInfo]) -> bool {
+        // Trees read from a dump have no objects to follow.
+        if self.spans.len() != self.nodes.len() {
+            return false;
+        }
+        let Some(FmmNode {
+            data: NodeData::Internal { region, .. },
+        }) = self.nodes.first()
+        else {
+            // A single leaf holds objects at a single position, so it is cheap to rebuild.
+            return false;
+        };
+        let contains = |pos: Point3<f64>| {
+            (region.x_range.0..=region.x_range.1).contains(&pos.x)
+                && (region.y_range.0..=region.y_range.1).contains(&pos.y)
+                && (region.z_range.0..=region.z_range.1).contains(&pos.z)
+        };
+        // The objects in the scratch buffer are distinct, so if they all still attract
+        // others and there are as many such objects as before, they are the same set.
+        let gravitating = objects
+            .iter()
+            .filter(|obj| obj.gravitating_mass() > 0.0)
+            .count();
+        gravitating == self.scratch.len()
+            && self.scratch.iter().all(|body| {
+                objects
+                    .get(body.idx)
+                    .is_some_and(|obj| obj.gravitating_mass() > 0.0 && contains(obj.pos))
+            })
+    }
+
+    /// Update the node `id`, which covers `region`, and everything under it.
+    fn update_node(&mut self, id: usize, region: Region) {
+        match &self.nodes[id].data {
+            NodeData::Internal { children, region } => {
+                let (children, region) = (*children, region.clone());
+                let center = region.center();
+                let crossed = children.iter().enumerate().any(|(i, child)| {
+                    child.is_some_and(|child| {
+                        self.scratch[self.spans[child.0].clone()]
+                            .iter()
+                            .any(|body| octant_index(&body.pos, &center) != i)
+                    })
+                });
+                if crossed {
+                    self.rebuild_node(id, region);
+                    return;
+                }
+                for (child, child_region) in children.into_iter().zip(octants(&region)) {
+                    if let Some(child) = child {
+                        self.update_node(child.0, child_region);
+                    }
+                }
+                self.data[id] = center_of_mass(
+                    children
+                        .into_iter()
+                        .flatten()
+                        .map(|child| (self.data[child.0].center_mass, self.data[child.0].mass)),
+                );
+            }
+            NodeData::External => {
+                let bodies = &self.scratch[self.spans[id].clone()];
+                if bodies.windows(2).any(|w| w[0].pos != w[1].pos) {
+                    self.rebuild_node(id, region);
+                } else {
+                    self.data[id] = Self::get_data(bodies);
+                }
+            }
+        }
+    }
+
+    /// Call `f` with the index of every object the tree was built for that was within
+    /// `radius` of `pos`, as of the build. Only objects that attract others are in the
+    /// tree.
+    pub(super) fn for_each_within(
+        &self,
+        pos: Point3<f64>,
+        radius: f64,
+        stack: &mut Vec<NodeId>,
+        mut f: impl FnMut(usize),
+    ) {
+        stack.clear();
+        if !self.is_empty() {
+            stack.push(self.root_id());
+        }
+        while let Some(id) = stack.pop() {
+            match &self.nodes[id.0].data {
+                NodeData::Internal { children, region } => {
+                    if region.dist_sq(pos) <= radius * radius {
+                        stack.extend(children.iter().flatten());
+                    }
+                }
+                NodeData::External => {
+                    for body in &self.scratch[self.spans[id.0].clone()] {
+                        if (body.pos - pos).magnitude2() <= radius * radius {
+                            f(body.idx);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Collect the `k` objects the tree was built for that were nearest to `pos` as of
+    /// the build in `nearest`, or all of them if there are fewer. Objects at `pos` itself
+    /// count too.
+    pub(super) fn nearest(
+        &self,
+        pos: Point3<f64>,
+        k: usize,
+        stack: &mut Vec<NodeId>,
+        nearest: &mut BinaryHeap<Neighbor>,
+    ) {
+        stack.clear();
+        nearest.clear();
+        if self.is_empty() || k == 0 {
+            return;
+        }
+        stack.push(self.root_id());
+        // Nodes are only opened if they can hold something nearer than the furthest
+        // object found so far.
+        let cutoff = |nearest: &BinaryHeap<Neighbor>| {
+            if nearest.len() < k {
+                f64::INFINITY
+            } else {
+                nearest.peek().map_or(f64::INFINITY, |n| n.dist_sq)
+            }
+        };
+        while let Some(id) = stack.pop() {
+            match &self.nodes[id.0].data {
+                NodeData::Internal { children, region } => {
+                    if region.dist_sq(pos) > cutoff(nearest) {
+                        continue;
+                    }
+                    // Nearest children last, so that they are opened first and the
+                    // cutoff shrinks quickly.
+                    let start = stack.len();
+                    stack.extend(children.iter().flatten());
+                    stack[start..].sort_by(|a, b| {
+                        let dist = |id: &NodeId| (self.data[id.0].center_mass - pos).magnitude2();
+                        dist(b).total_cmp(&dist(a))
+                    });
+                }
+                NodeData::External => {
+                    for body in &self.scratch[self.spans[id.0].clone()] {
+                        let neighbor = Neighbor {
+                            dist_sq: (body.pos - pos).magnitude2(),
+                            mass: body.mass,
+                        };
+                        if nearest.len() < k {
+                            nearest.push(neighbor);
+                        } else if neighbor.dist_sq < cutoff(nearest) {
+                            nearest.pop();
+                            nearest.push(neighbor);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Replace the subtree at `id` with one built from the objects under it now.
+    fn rebuild_node(&mut self, id: usize, region: Region) {
+        let span = self.spans[id].clone();
+        let mut bodies = std::mem::take(&mut self.scratch);
+        let new = self
+            .build_node(&mut bodies[span.clone()], span.start, region)
+            .expect("Rebuilt a node without objects");
+        self.scratch = bodies;
+        // Move the new node into the place of the old one, so that its parent points at
+        // it. What is left behind is an unused leaf rather than the old node, so that no
+        // node in the arena points at a node before it.
+        self.nodes.swap(id, new.0);
+        self.data.swap(id, new.0);
+        self.nodes[new.0] = FmmNode::new_external();
+    }
+}
+
+/// Center of mass and total mass of `points`, given as position and mass.
+fn center_of_mass(mut points: impl Iterator<Item = (Point3<f64>, f64)>) -> Data {
+    // Sum offsets from the first object rather than positions, so that the center of
+    // a leaf is exactly where its objects are. Otherwise rounding puts it a tiny
+    // distance away, and the softening turns that into a pull of an object on itself.
+    let (origin, mut total_mass) = points.next().expect("Center of mass of no points");
+    let mut offset = Vector3::new(0.0, 0.0, 0.0);
+    for (pos, mass) in points {
+        offset += (pos - origin) * mass;
+        total_mass += mass;
+    }
+    Data {
+        center_mass: origin + offset / total_mass,
+        mass: total_mass,
+    }
+}
+
+fn octant_index(position: &Point3<f64>, center: &Point3<f64>) -> usize {
+    (0..3).fold(0, |index, i| {
+        index + (usize::from(position[i] < center[i]) << i)
+    })
+}
+
+/// Sort `input` in place by octant, returning the start of each octant in the slice,
+/// with `bounds[8] == input.len()`.
+fn partition_octants(input: &mut [Body], center: &Point3<f64>) -> [usize; 9] {
+    let mut counts = [0; 8];
+    for o in input.iter() {
+        counts[octant_index(&o.pos, center)] += 1;
+    }
+    let mut bounds = [0; 9];
+    for i in 0..8 {
+        bounds[i + 1] = bounds[i] + counts[i];
+    }
+
+    // Swap each element directly into the next free slot of its octant.
+    let mut next = bounds;
+    for octant in 0..8 {
+        while next[octant] < bounds[octant + 1] {
+            let target = octant_index(&input[next[octant]].pos, center);
+            if target != octant {
+                input.swap(next[octant], next[target]);
+            }
+            next[target] += 1;
         }
     }
+
+    bounds
 }
 
 fn octants(parent: &Region) -> [Region; 8] {
-    let mut result = std::array::from_fn(|_| Region::zero());
     let center = parent.center();
-    for i in 0..8 {
+    std::array::from_fn(|i| {
         let (x_min, x_max) = if (i & 0b001) != 0 {
             (parent.x_range.0, center.x)
         } else {
@@ -207,12 +605,11 @@ fn octants(parent: &Region) -> [Region; 8] {
         } else {
             (center.z, parent.z_range.1)
         };
-        result[i] = Region {
+        Region {
             x_range: (x_min, x_max),
             y_range: (y_min, y_max),
             z_range: (z_min, z_max),
             size_sq: (x_max - x_min).powi(2),
-        };
-    }
-    result
+        }
+    })
 }
@@ -1,50 +1,306 @@
-use cgmath::Vector3;
-use rayon::iter::{
-    IndexedParallelIterator, IntoParallelRefIterator, IntoParallelRefMutIterator, ParallelIterator,
+use cgmath::{InnerSpace, Vector3, Zero};
+use rayon::{
+    iter::{IndexedParallelIterator, IntoParallelIterator, ParallelIterator},
+    slice::{ParallelSlice, ParallelSliceMut},
 };
 
-use crate::sim::ObjectInfo;
+use crate::{
+    constants::{COLLISION_EPSILON, G, PAR_CHUNKS_PER_THREAD, PAR_MIN_CHUNK_WORK},
+    sim::{ObjectInfo, PeriodicBox},
+};
+
+/// Number of objects to hand to a thread at once, when each object takes `work`
+/// interactions or updates. A few chunks per thread leave room to balance the load, and
+/// chunks are never so small that scheduling them costs more than the work.
+pub fn chunk_size(len: usize, work: usize) -> usize {
+    let per_thread = len.div_ceil(rayon::current_num_threads() * PAR_CHUNKS_PER_THREAD);
+    per_thread
+        .max(PAR_MIN_CHUNK_WORK.div_ceil(work.max(1)))
+        .max(1)
+}
 
 pub fn par_add_rec(objects: &mut [ObjectInfo], acc: &mut [Vector3<f64>], delta: f64) {
+    let chunk = chunk_size(objects.len(), 1);
     objects
-        .par_iter_mut()
-        .zip(acc.par_iter_mut())
-        .for_each(|(obj, acc)| {
-            // Integrate the acceleration by multiplying it with the time step
-            // and add it to the velocity
-            obj.vel += *acc * delta;
-            // Integrate the velocity by multiplying it with the time step
-            // and add it to the position
+        .par_chunks_mut(chunk)
+        .zip(acc.par_chunks_mut(chunk))
+        .for_each(|(objects, acc)| {
+            for (obj, acc) in objects.iter_mut().zip(acc) {
+                if !obj.pinned {
+                    // Integrate the acceleration by multiplying it with the time step
+                    // and add it to the velocity
+                    obj.vel += *acc * delta;
+                    // Integrate the velocity by multiplying it with the time step
+                    // and add it to the position
+                    obj.pos += obj.vel * delta;
+                }
+                // We keep the acceleration object for the next iteration, but we need to
+                // reset it.
+                *acc = Vector3::new(0.0, 0.0, 0.0);
+            }
+        });
+}
+
+/// Move each object along its velocity for `delta`, leaving pinned objects in place.
+pub fn par_drift(objects: &mut [ObjectInfo], delta: f64) {
+    let chunk = chunk_size(objects.len(), 1);
+    objects.par_chunks_mut(chunk).for_each(|objects| {
+        for obj in objects.iter_mut().filter(|obj| !obj.pinned) {
             obj.pos += obj.vel * delta;
-            // We keep the acceleration object for the next iteration, but we need to reset it.
-            acc.x = 0.0;
-            acc.y = 0.0;
-            acc.z = 0.0;
+        }
+    });
+}
+
+/// Add the acceleration in `acc` over `delta` to each velocity, and reset `acc`.
+pub fn par_kick(objects: &mut [ObjectInfo], acc: &mut [Vector3<f64>], delta: f64) {
+    let chunk = chunk_size(objects.len(), 1);
+    objects
+        .par_chunks_mut(chunk)
+        .zip(acc.par_chunks_mut(chunk))
+        .for_each(|(objects, acc)| {
+            for (obj, acc) in objects.iter_mut().zip(acc) {
+                if !obj.pinned {
+                    obj.vel += *acc * delta;
+                }
+                *acc = Vector3::new(0.0, 0.0, 0.0);
+            }
         });
 }
 
-pub fn iter(objects: &mut [ObjectInfo], out_buffer: &mut [Vector3<f64>]) {
+/// Indices of the objects that attract others, kept between evaluations. Test particles
+/// are left out, so the cost of a step is the number of objects times the number of
+/// massive objects.
+#[derive(Debug, Default)]
+pub struct MassiveIndices {
+    indices: Vec<usize>,
+    /// `test_particle` of each object when `indices` was built.
+    test_particles: Vec<bool>,
+}
+
+impl MassiveIndices {
+    /// Indices of the massive objects in `objects`. They are only found again if objects
+    /// were added or removed, or became test particles or stopped being one, since the
+    /// last call.
+    pub fn update(&mut self, objects: &[ObjectInfo]) -> &[usize] {
+        let unchanged = self
+            .test_particles
+            .iter()
+            .copied()
+            .eq(objects.iter().map(|obj| obj.test_particle));
+        if !unchanged {
+            self.test_particles.clear();
+            self.test_particles
+                .extend(objects.iter().map(|obj| obj.test_particle));
+            self.indices.clear();
+            self.indices.extend(
+                objects
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, obj)| !obj.test_particle)
+                    .map(|(idx, _)| idx),
+            );
+        }
+        &self.indices
+    }
+}
+
+#[inline]
+fn acc_towards(
+    obj: &ObjectInfo,
+    other: &ObjectInfo,
+    bounds: Option<&PeriodicBox>,
+    out: &mut Vector3<f64>,
+) {
+    match bounds {
+        Some(bounds) => obj.get_acc_towards_periodic(other, bounds, out),
+        None => obj.get_acc_towards(other, out),
+    }
+}
+
+/// Set `acc` and `jerk` to the acceleration and jerk of each object, by direct summation
+/// over the objects at `massive`.
+pub fn par_acc_jerk(
+    objects: &[ObjectInfo],
+    massive: &[usize],
+    acc: &mut [Vector3<f64>],
+    jerk: &mut [Vector3<f64>],
+    bounds: Option<&PeriodicBox>,
+) {
+    let chunk = chunk_size(objects.len(), massive.len());
     objects
-        .par_iter()
-        .zip(out_buffer.par_iter_mut())
+        .par_chunks(chunk)
+        .zip(acc.par_chunks_mut(chunk))
+        .zip(jerk.par_chunks_mut(chunk))
         .enumerate()
-        .for_each(|(i, (obj, out))| {
-            for (other_idx, other) in objects.iter().enumerate() {
-                if other_idx == i {
-                    continue;
+        .for_each(|(c, ((chunk_objects, acc), jerk))| {
+            for (k, ((obj, acc), jerk)) in chunk_objects.iter().zip(acc).zip(jerk).enumerate() {
+                let i = c * chunk + k;
+                *acc = Vector3::new(0.0, 0.0, 0.0);
+                *jerk = Vector3::new(0.0, 0.0, 0.0);
+                for &other_idx in massive {
+                    if other_idx == i {
+                        continue;
+                    }
+                    let other = &objects[other_idx];
+                    let rel = match bounds {
+                        Some(bounds) => bounds.min_image(other.pos - obj.pos),
+                        None => other.pos - obj.pos,
+                    };
+                    obj.get_acc_jerk_towards(other, rel, acc, jerk);
                 }
-                obj.get_acc_towards(other, out);
             }
         });
 }
 
-pub fn iter_single_threaded(objects: &mut [ObjectInfo], out_buffer: &mut [Vector3<f64>]) {
+/// Add the attraction of the objects at `massive` to every object, see
+/// `MassiveIndices`.
+pub fn iter(
+    objects: &mut [ObjectInfo],
+    massive: &[usize],
+    out_buffer: &mut [Vector3<f64>],
+    bounds: Option<&PeriodicBox>,
+) {
+    let objects = &*objects;
+    let chunk = chunk_size(objects.len(), massive.len());
+    objects
+        .par_chunks(chunk)
+        .zip(out_buffer.par_chunks_mut(chunk))
+        .enumerate()
+        .for_each(|(c, (chunk_objects, out))| {
+            for (k, (obj, out)) in chunk_objects.iter().zip(out).enumerate() {
+                let i = c * chunk + k;
+                for &other_idx in massive {
+                    if other_idx == i {
+                        continue;
+                    }
+                    acc_towards(obj, &objects[other_idx], bounds, out);
+                }
+            }
+        });
+}
+
+pub fn iter_single_threaded(
+    objects: &mut [ObjectInfo],
+    massive: &[usize],
+    out_buffer: &mut [Vector3<f64>],
+    bounds: Option<&PeriodicBox>,
+) {
     for (i, (obj, out)) in objects.iter().zip(out_buffer.iter_mut()).enumerate() {
-        for (other_idx, other) in objects.iter().enumerate() {
+        for &other_idx in massive {
             if other_idx == i {
                 continue;
             }
-            obj.get_acc_towards(other, out);
+            acc_towards(obj, &objects[other_idx], bounds, out);
+        }
+    }
+}
+
+/// Offset from `obj` to `other` times G over the softened cube of their distance. Each is
+/// accelerated towards the other by this times the mass of the other.
+#[inline]
+fn pair_factor(obj: &ObjectInfo, other: &ObjectInfo, bounds: Option<&PeriodicBox>) -> Vector3<f64> {
+    let rel = match bounds {
+        Some(bounds) => bounds.min_image(other.pos - obj.pos),
+        None => other.pos - obj.pos,
+    };
+    rel * G / (rel.magnitude2() * rel.magnitude() + COLLISION_EPSILON)
+}
+
+/// Add the pairs of `massive[row]` with every later massive object to both objects of
+/// the pair, in `acc`, which is indexed like `massive`.
+fn add_pair_row(
+    objects: &[ObjectInfo],
+    massive: &[usize],
+    row: usize,
+    bounds: Option<&PeriodicBox>,
+    acc: &mut [Vector3<f64>],
+) {
+    let obj = &objects[massive[row]];
+    let mut own = Vector3::zero();
+    for (col, &other_idx) in massive.iter().enumerate().skip(row + 1) {
+        let other = &objects[other_idx];
+        let factor = pair_factor(obj, other, bounds);
+        own += factor * other.gravitating_mass();
+        acc[col] -= factor * obj.gravitating_mass();
+    }
+    acc[row] += own;
+}
+
+/// Like `iter`, but each pair of massive objects is evaluated once and added to both by
+/// Newton's third law. Every task sums its rows of pairs into its own accelerations of
+/// the massive objects, and those are added together at the end, so no two threads
+/// write to the same object. Test particles attract nothing, and still sum the massive
+/// objects one way.
+pub fn iter_symmetric(
+    objects: &mut [ObjectInfo],
+    massive: &[usize],
+    out_buffer: &mut [Vector3<f64>],
+    bounds: Option<&PeriodicBox>,
+) {
+    let objects = &*objects;
+    let len = massive.len();
+    // Row `k` has `len - 1 - k` pairs, so it is taken together with row `len - 1 - k`
+    // to give every task the same work.
+    let rows = len.div_ceil(2);
+    let acc = (0..rows)
+        .into_par_iter()
+        .with_min_len(chunk_size(rows, len))
+        .fold(
+            || vec![Vector3::zero(); len],
+            |mut acc, row| {
+                add_pair_row(objects, massive, row, bounds, &mut acc);
+                if len - 1 - row != row {
+                    add_pair_row(objects, massive, len - 1 - row, bounds, &mut acc);
+                }
+                acc
+            },
+        )
+        .reduce_with(|mut acc, other| {
+            for (acc, other) in acc.iter_mut().zip(other) {
+                *acc += other;
+            }
+            acc
+        });
+    if let Some(acc) = acc {
+        for (&idx, acc) in massive.iter().zip(acc) {
+            out_buffer[idx] += acc;
+        }
+    }
+
+    let chunk = chunk_size(objects.len(), len);
+    objects
+        .par_chunks(chunk)
+        .zip(out_buffer.par_chunks_mut(chunk))
+        .for_each(|(chunk_objects, out)| {
+            for (obj, out) in chunk_objects.iter().zip(out) {
+                if obj.test_particle {
+                    for &other_idx in massive {
+                        acc_towards(obj, &objects[other_idx], bounds, out);
+                    }
+                }
+            }
+        });
+}
+
+pub fn iter_symmetric_single_threaded(
+    objects: &mut [ObjectInfo],
+    massive: &[usize],
+    out_buffer: &mut [Vector3<f64>],
+    bounds: Option<&PeriodicBox>,
+) {
+    let mut acc = vec![Vector3::zero(); massive.len()];
+    for row in 0..massive.len() {
+        add_pair_row(objects, massive, row, bounds, &mut acc);
+    }
+    for (&idx, acc) in massive.iter().zip(acc) {
+        out_buffer[idx] += acc;
+    }
+
+    for (obj, out) in objects.iter().zip(out_buffer.iter_mut()) {
+        if obj.test_particle {
+            for &other_idx in massive {
+                acc_towards(obj, &objects[other_idx], bounds, out);
+            }
         }
     }
 }
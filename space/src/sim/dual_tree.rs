@@ -0,0 +1,201 @@
+//! Dual tree walk on the kd-tree.
+//!
+//! Rather than walking the tree once per object, pairs of nodes are walked together.
+//! When two nodes are well separated their mutual interaction is computed once, as a
+//! local expansion (acceleration and tidal tensor) around each node's center of mass,
+//! and later pushed down to the objects they contain. Nodes that are too close are split,
+//! and pairs of nearby leaves interact directly. Every interaction is symmetric, so each
+//! pair of nodes or objects is only visited once.
+//!
+//! For clustered distributions most of the work ends up in a few large node pairs,
+//! which makes this considerably cheaper than a per-object Barnes-Hut walk. With the
+//! same theta the error is somewhat larger, around one percent at a theta of 0.5.
+
+use cgmath::{InnerSpace, Matrix3, Point3, SquareMatrix, Vector3, Zero};
+
+use crate::{
+    constants::{COLLISION_EPSILON, G},
+    sim::{
+        ObjectInfo, capacity_bytes,
+        kd_tree::{Body, KdNode, KdTree},
+    },
+};
+
+#[derive(Debug, Clone, Copy)]
+struct Local {
+    acc: Vector3<f64>,
+    tidal: Matrix3<f64>,
+}
+
+impl Local {
+    fn zero() -> Self {
+        Self {
+            acc: Vector3::zero(),
+            tidal: Matrix3::zero(),
+        }
+    }
+}
+
+/// Reusable state for the dual tree walk.
+#[derive(Debug, Default)]
+pub struct DualTree {
+    tree: KdTree,
+    locals: Vec<Local>,
+    /// Acceleration of each body, in tree order.
+    acc: Vec<Vector3<f64>>,
+}
+
+impl DualTree {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bytes allocated by the tree and the walk.
+    pub fn memory_usage(&self) -> usize {
+        self.tree.memory_usage() + capacity_bytes(&self.locals) + capacity_bytes(&self.acc)
+    }
+}
+
+struct Walk<'a> {
+    nodes: &'a [KdNode],
+    bodies: &'a [Body],
+    locals: &'a mut [Local],
+    acc: &'a mut [Vector3<f64>],
+    theta_sq: f64,
+}
+
+impl Walk<'_> {
+    fn interact(&mut self, a: usize, b: usize) {
+        let (na, nb) = (&self.nodes[a], &self.nodes[b]);
+        if na.mass == 0.0 && nb.mass == 0.0 {
+            return;
+        }
+
+        if a == b {
+            if na.is_leaf() {
+                self.direct_self(a);
+            } else {
+                let (l, r) = (a + 1, na.right);
+                self.interact(l, l);
+                self.interact(r, r);
+                self.interact(l, r);
+            }
+            return;
+        }
+
+        let rel = nb.center_mass - na.center_mass;
+        let dist_sq = rel.magnitude2();
+        // Same opening criterion as Barnes-Hut, applied to the larger of the two nodes.
+        let size = na.size_sq.max(nb.size_sq).sqrt();
+        if self.theta_sq * dist_sq > size * size {
+            self.approximate(a, b, rel, dist_sq);
+            return;
+        }
+
+        match (na.is_leaf(), nb.is_leaf()) {
+            (true, true) => self.direct(a, b),
+            // Split the larger node, unless it is a leaf.
+            (false, leaf_b) if leaf_b || na.size_sq >= nb.size_sq => {
+                self.interact(a + 1, b);
+                self.interact(na.right, b);
+            }
+            _ => {
+                self.interact(a, b + 1);
+                self.interact(a, nb.right);
+            }
+        }
+    }
+
+    /// Mutual interaction between two well separated nodes, `rel` pointing from `a` to `b`.
+    fn approximate(&mut self, a: usize, b: usize, rel: Vector3<f64>, dist_sq: f64) {
+        let inv_r3 = 1.0 / (dist_sq * dist_sq.sqrt());
+        let inv_r5 = inv_r3 / dist_sq;
+        // The tidal tensor only depends on the direction up to sign, so both nodes share it.
+        let tidal = (Matrix3::from_cols(rel * rel.x, rel * rel.y, rel * rel.z) * 3.0
+            - Matrix3::identity() * dist_sq)
+            * (G * inv_r5);
+
+        let (ma, mb) = (self.nodes[a].mass, self.nodes[b].mass);
+        self.locals[a].acc += rel * (G * mb * inv_r3);
+        self.locals[a].tidal += tidal * mb;
+        self.locals[b].acc -= rel * (G * ma * inv_r3);
+        self.locals[b].tidal += tidal * ma;
+    }
+
+    fn pair(&mut self, i: usize, j: usize) {
+        let (bi, bj) = (&self.bodies[i], &self.bodies[j]);
+        let rel = bj.pos - bi.pos;
+        let dist_sq = rel.magnitude2();
+        if dist_sq == 0.0 {
+            return;
+        }
+        let f = G / (dist_sq * dist_sq.sqrt() + COLLISION_EPSILON);
+        self.acc[i] += rel * (bj.mass * f);
+        self.acc[j] -= rel * (bi.mass * f);
+    }
+
+    fn direct(&mut self, a: usize, b: usize) {
+        let (na, nb) = (&self.nodes[a], &self.nodes[b]);
+        for i in na.start..na.end {
+            for j in nb.start..nb.end {
+                self.pair(i, j);
+            }
+        }
+    }
+
+    fn direct_self(&mut self, a: usize) {
+        let node = &self.nodes[a];
+        for i in node.start..node.end {
+            for j in (i + 1)..node.end {
+                self.pair(i, j);
+            }
+        }
+    }
+
+    /// Shift the local expansions down the tree, and evaluate them at each body.
+    fn push_down(&mut self, id: usize, parent: Local, parent_center: Point3<f64>) {
+        let node = &self.nodes[id];
+        let local = &mut self.locals[id];
+        local.acc += parent.acc + parent.tidal * (node.center_mass - parent_center);
+        local.tidal += parent.tidal;
+        let local = *local;
+
+        if node.is_leaf() {
+            for i in node.start..node.end {
+                self.acc[i] += local.acc + local.tidal * (self.bodies[i].pos - node.center_mass);
+            }
+        } else {
+            self.push_down(id + 1, local, node.center_mass);
+            self.push_down(node.right, local, node.center_mass);
+        }
+    }
+}
+
+/// The walk itself is sequential, since the symmetric updates write to both sides of
+/// each pair, so there is no separate parallel implementation.
+pub fn iter(info: &mut [ObjectInfo], out: &mut [Vector3<f64>], state: &mut DualTree, theta: f64) {
+    state.tree.build_tree(info);
+    if state.tree.len() == 0 {
+        return;
+    }
+
+    state.locals.clear();
+    state.locals.resize(state.tree.len(), Local::zero());
+    state.acc.clear();
+    state.acc.resize(info.len(), Vector3::zero());
+
+    let nodes = state.tree.nodes();
+    let mut walk = Walk {
+        nodes,
+        bodies: state.tree.bodies(),
+        locals: &mut state.locals,
+        acc: &mut state.acc,
+        theta_sq: theta * theta,
+    };
+    walk.interact(0, 0);
+    walk.push_down(0, Local::zero(), nodes[0].center_mass);
+
+    for (body, acc) in state.tree.bodies().iter().zip(state.acc.iter()) {
+        out[body.idx] += *acc;
+    }
+}
@@ -0,0 +1,470 @@
+//! Fast multipole method on the adaptive octree of Barnes-Hut.
+//!
+//! Each node stores a multipole expansion (mass, center of mass and second mass moment)
+//! and a local expansion (acceleration and tidal tensor around its center of mass).
+//! Multipoles are built bottom-up. Pairs of nodes are then walked together, like in the
+//! dual tree walk: well separated pairs add each other's multipole to their local
+//! expansions, pairs that are too close are split, and pairs of leaves interact directly.
+//! Finally the local expansions are shifted down the tree to the objects.
+//!
+//! The tree splits wherever objects crowd together, so clustered distributions get
+//! deeper subtrees rather than crowded cells, and the cost stays linear in the number of
+//! objects. Pairs are separated by the opening criterion of Barnes-Hut, applied to the
+//! larger node, so theta trades accuracy for speed like it does there.
+//!
+//! The multipoles are second order, but the local expansions only carry the tidal
+//! tensor of the monopole, so the error is comparable to that of the dual tree walk.
+//!
+//! Objects that attract nothing are not in the tree. They walk it one by one instead,
+//! like in Barnes-Hut, using the multipoles of the nodes far enough away from them.
+
+use cgmath::{EuclideanSpace, InnerSpace, Matrix3, Point3, SquareMatrix, Vector3, Zero};
+use rayon::iter::{
+    IndexedParallelIterator, IntoParallelRefIterator, IntoParallelRefMutIterator, ParallelIterator,
+};
+
+use crate::{
+    constants::{COLLISION_EPSILON, FMM_SPLIT_DEPTH, G},
+    sim::{
+        ObjectInfo,
+        barnes_hut::{FmmTree, NodeData, NodeId},
+        capacity_bytes,
+    },
+};
+
+#[derive(Debug, Clone, Copy)]
+struct Multipole {
+    mass: f64,
+    center_mass: Point3<f64>,
+    /// Second moment of mass around the center of mass.
+    quad: Matrix3<f64>,
+}
+
+impl Multipole {
+    fn zero() -> Self {
+        Self {
+            mass: 0.0,
+            center_mass: Point3::origin(),
+            quad: Matrix3::zero(),
+        }
+    }
+
+    fn from_parts(parts: impl Iterator<Item = (f64, Point3<f64>, Matrix3<f64>)> + Clone) -> Self {
+        let mut mass = 0.0;
+        let mut weighted = Vector3::zero();
+        for (m, c, _) in parts.clone() {
+            mass += m;
+            weighted += c.to_vec() * m;
+        }
+        if mass == 0.0 {
+            return Self::zero();
+        }
+        let center_mass = Point3::from_vec(weighted / mass);
+
+        let mut quad = Matrix3::zero();
+        for (m, c, q) in parts {
+            let d = c - center_mass;
+            quad += q + outer(d) * m;
+        }
+
+        Self {
+            mass,
+            center_mass,
+            quad,
+        }
+    }
+
+    /// Acceleration towards the expansion at `pos`, which must be well outside it.
+    fn acc_at(&self, pos: Point3<f64>) -> Vector3<f64> {
+        let r = pos - self.center_mass;
+        let r2 = r.magnitude2();
+        let inv_r3 = 1.0 / (r2 * r2.sqrt());
+        let inv_r5 = inv_r3 / r2;
+        let inv_r7 = inv_r5 / r2;
+
+        let sr = self.quad * r;
+        let tr = self.quad.trace();
+        let rsr = r.dot(sr);
+
+        (-r * self.mass * inv_r3 + (sr * 3.0 - r * tr) * inv_r5
+            - r * (2.5 * (3.0 * rsr - r2 * tr) * inv_r7))
+            * G
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Local {
+    acc: Vector3<f64>,
+    tidal: Matrix3<f64>,
+}
+
+impl Local {
+    fn zero() -> Self {
+        Self {
+            acc: Vector3::zero(),
+            tidal: Matrix3::zero(),
+        }
+    }
+
+    /// Shift the expansion from its center by `offset`.
+    fn shifted(&self, offset: Vector3<f64>) -> Self {
+        Self {
+            acc: self.acc + self.tidal * offset,
+            tidal: self.tidal,
+        }
+    }
+
+    /// Add the contribution of a distant multipole to the expansion around `center`.
+    fn add_multipole(&mut self, center: Point3<f64>, source: &Multipole) {
+        let r = center - source.center_mass;
+        let r2 = r.magnitude2();
+        let inv_r5 = 1.0 / (r2 * r2 * r2.sqrt());
+
+        self.acc += source.acc_at(center);
+        self.tidal += (outer(r) * 3.0 - Matrix3::identity() * r2) * (G * source.mass * inv_r5);
+    }
+}
+
+fn outer(v: Vector3<f64>) -> Matrix3<f64> {
+    Matrix3::from_cols(v * v.x, v * v.y, v * v.z)
+}
+
+/// Octree and expansions for the FMM, kept between steps so that they only allocate
+/// while the simulation grows.
+#[derive(Debug, Default)]
+pub struct MultipoleTree {
+    tree: FmmTree,
+    multipoles: Vec<Multipole>,
+    locals: Vec<Local>,
+    /// Squared size of each node, zero for leaves.
+    size_sq: Vec<f64>,
+}
+
+impl MultipoleTree {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bytes allocated by the tree and the expansions.
+    pub fn memory_usage(&self) -> usize {
+        self.tree.memory_usage()
+            + capacity_bytes(&self.multipoles)
+            + capacity_bytes(&self.locals)
+            + capacity_bytes(&self.size_sq)
+    }
+
+    fn children(&self, id: NodeId) -> Option<&[Option<NodeId>; 8]> {
+        match &self.tree.get(id).0.data {
+            NodeData::Internal { children, .. } => Some(children),
+            NodeData::External => None,
+        }
+    }
+
+    fn build(&mut self, objects: &[ObjectInfo]) {
+        self.tree.clear();
+        self.tree.build_tree(objects);
+        let len = self.tree.len();
+        self.multipoles.clear();
+        self.multipoles.resize(len, Multipole::zero());
+        self.locals.clear();
+        self.locals.resize(len, Local::zero());
+        self.size_sq.clear();
+        self.size_sq.resize(len, 0.0);
+        if !self.tree.is_empty() {
+            self.upward(self.tree.root_id());
+        }
+    }
+
+    fn upward(&mut self, id: NodeId) {
+        let (node, data) = self.tree.get(id);
+        self.multipoles[id.index()] = match &node.data {
+            NodeData::Internal { children, region } => {
+                let children = *children;
+                self.size_sq[id.index()] = region.size_sq();
+                for child in children.iter().flatten() {
+                    self.upward(*child);
+                }
+                Multipole::from_parts(children.iter().flatten().map(|child| {
+                    let m = &self.multipoles[child.index()];
+                    (m.mass, m.center_mass, m.quad)
+                }))
+            }
+            // All objects in a leaf are at the same position.
+            NodeData::External => Multipole {
+                mass: data.mass,
+                center_mass: data.center_mass,
+                quad: Matrix3::zero(),
+            },
+        };
+    }
+
+    /// Gravity at `pos` of an object that is not in the tree.
+    fn eval_outside(
+        &self,
+        pos: Point3<f64>,
+        theta_sq: f64,
+        stack: &mut Vec<NodeId>,
+    ) -> Vector3<f64> {
+        let mut acc = Vector3::zero();
+        stack.clear();
+        stack.push(self.tree.root_id());
+        while let Some(id) = stack.pop() {
+            match self.children(id) {
+                None => acc += direct(self.tree.objects_under(id), pos),
+                Some(children) => {
+                    let m = &self.multipoles[id.index()];
+                    if theta_sq * (m.center_mass - pos).magnitude2() > self.size_sq[id.index()] {
+                        acc += m.acc_at(pos);
+                    } else {
+                        stack.extend(children.iter().flatten());
+                    }
+                }
+            }
+        }
+        acc
+    }
+}
+
+/// Gravity at `pos` of `sources`, given as index, position and mass.
+fn direct(
+    sources: impl Iterator<Item = (usize, Point3<f64>, f64)>,
+    pos: Point3<f64>,
+) -> Vector3<f64> {
+    let mut acc = Vector3::zero();
+    for (_, source, mass) in sources {
+        let rel = source - pos;
+        let dist_sq = rel.magnitude2();
+        acc += rel * (G * mass / (dist_sq * dist_sq.sqrt() + COLLISION_EPSILON));
+    }
+    acc
+}
+
+struct Walk<'a> {
+    state: &'a MultipoleTree,
+    locals: &'a mut [Local],
+    out: &'a mut [Vector3<f64>],
+    theta_sq: f64,
+}
+
+impl Walk<'_> {
+    fn interact(&mut self, a: NodeId, b: NodeId) {
+        let state = self.state;
+        let (ca, cb) = (state.children(a), state.children(b));
+
+        if a == b {
+            // Objects in the same leaf are at the same position, and do not pull on
+            // each other.
+            if let Some(children) = ca {
+                for (i, c) in children.iter().enumerate() {
+                    for d in &children[i..] {
+                        if let (Some(c), Some(d)) = (c, d) {
+                            self.interact(*c, *d);
+                        }
+                    }
+                }
+            }
+            return;
+        }
+
+        let (ma, mb) = (&state.multipoles[a.index()], &state.multipoles[b.index()]);
+        let (sa, sb) = (state.size_sq[a.index()], state.size_sq[b.index()]);
+        match (ca, cb) {
+            (None, None) => self.direct(a, b),
+            _ if self.theta_sq * (mb.center_mass - ma.center_mass).magnitude2() > sa.max(sb) => {
+                self.locals[a.index()].add_multipole(ma.center_mass, mb);
+                self.locals[b.index()].add_multipole(mb.center_mass, ma);
+            }
+            // Split the larger node, unless it is a leaf.
+            (Some(children), None) => {
+                for c in children.iter().flatten() {
+                    self.interact(*c, b);
+                }
+            }
+            (Some(children), Some(_)) if sa >= sb => {
+                for c in children.iter().flatten() {
+                    self.interact(*c, b);
+                }
+            }
+            (_, Some(children)) => {
+                for c in children.iter().flatten() {
+                    self.interact(a, *c);
+                }
+            }
+        }
+    }
+
+    fn direct(&mut self, a: NodeId, b: NodeId) {
+        let tree = &self.state.tree;
+        for (i, pi, mi) in tree.objects_under(a) {
+            for (j, pj, mj) in tree.objects_under(b) {
+                let rel = pj - pi;
+                let dist_sq = rel.magnitude2();
+                let f = G / (dist_sq * dist_sq.sqrt() + COLLISION_EPSILON);
+                self.out[i] += rel * (mj * f);
+                self.out[j] -= rel * (mi * f);
+            }
+        }
+    }
+
+    /// Shift the local expansions down the tree, and evaluate them at each object.
+    fn push_down(&mut self, id: NodeId, parent: Local, parent_center: Point3<f64>) {
+        let state = self.state;
+        let center = state.multipoles[id.index()].center_mass;
+        let local = &mut self.locals[id.index()];
+        let shifted = parent.shifted(center - parent_center);
+        local.acc += shifted.acc;
+        local.tidal += shifted.tidal;
+        let local = *local;
+
+        match state.children(id) {
+            Some(children) => {
+                for c in children.iter().flatten() {
+                    self.push_down(*c, local, center);
+                }
+            }
+            None => {
+                for (i, pos, _) in state.tree.objects_under(id) {
+                    self.out[i] += local.acc + local.tidal * (pos - center);
+                }
+            }
+        }
+    }
+}
+
+/// Node pairs that walking the root against itself starts from, splitting the nodes
+/// paired with themselves `depth` levels down. Walking each of them gives the same
+/// interactions as walking the root.
+fn split_pairs(state: &MultipoleTree, id: NodeId, depth: usize, pairs: &mut Vec<(NodeId, NodeId)>) {
+    match state.children(id) {
+        Some(children) if depth > 0 => {
+            for (i, c) in children.iter().enumerate() {
+                let Some(c) = c else {
+                    continue;
+                };
+                split_pairs(state, *c, depth - 1, pairs);
+                for d in children[i + 1..].iter().flatten() {
+                    pairs.push((*c, *d));
+                }
+            }
+        }
+        _ => pairs.push((id, id)),
+    }
+}
+
+/// Build the tree and walk it for the objects in it. Every interaction writes to both
+/// sides, so in parallel each task walks its node pairs into its own local expansions
+/// and accelerations, and those are added together at the end.
+fn walk_tree(
+    objects: &[ObjectInfo],
+    out: &mut [Vector3<f64>],
+    state: &mut MultipoleTree,
+    theta: f64,
+    parallel: bool,
+) {
+    state.build(objects);
+    if state.tree.is_empty() {
+        return;
+    }
+    let mut locals = std::mem::take(&mut state.locals);
+    let root = state.tree.root_id();
+    let theta_sq = theta * theta;
+    if parallel {
+        let mut pairs = Vec::new();
+        split_pairs(state, root, FMM_SPLIT_DEPTH, &mut pairs);
+        let state = &*state;
+        let walked = pairs
+            .par_iter()
+            .fold(
+                || {
+                    (
+                        vec![Local::zero(); locals.len()],
+                        vec![Vector3::zero(); out.len()],
+                    )
+                },
+                |(mut locals, mut out), &(a, b)| {
+                    let mut walk = Walk {
+                        state,
+                        locals: &mut locals,
+                        out: &mut out,
+                        theta_sq,
+                    };
+                    walk.interact(a, b);
+                    (locals, out)
+                },
+            )
+            .reduce_with(|(mut locals, mut out), (other_locals, other_out)| {
+                for (local, other) in locals.iter_mut().zip(other_locals) {
+                    local.acc += other.acc;
+                    local.tidal += other.tidal;
+                }
+                for (acc, other) in out.iter_mut().zip(other_out) {
+                    *acc += other;
+                }
+                (locals, out)
+            });
+        if let Some((walked_locals, walked_out)) = walked {
+            locals = walked_locals;
+            for (acc, walked) in out.iter_mut().zip(walked_out) {
+                *acc += walked;
+            }
+        }
+    }
+    let mut walk = Walk {
+        state,
+        locals: &mut locals,
+        out,
+        theta_sq,
+    };
+    if !parallel {
+        walk.interact(root, root);
+    }
+    walk.push_down(
+        root,
+        Local::zero(),
+        state.multipoles[root.index()].center_mass,
+    );
+    state.locals = locals;
+}
+
+/// Objects the tree leaves out, but that still feel its gravity.
+fn is_outside(obj: &ObjectInfo) -> bool {
+    obj.gravitating_mass() <= 0.0 && !obj.is_frozen()
+}
+
+pub fn iter(
+    objects: &mut [ObjectInfo],
+    out: &mut [Vector3<f64>],
+    state: &mut MultipoleTree,
+    theta: f64,
+) {
+    walk_tree(objects, out, state, theta, true);
+    if state.tree.is_empty() {
+        return;
+    }
+    let state = &*state;
+    let objects = &*objects;
+    out.par_iter_mut()
+        .enumerate()
+        .filter(|(i, _)| is_outside(&objects[*i]))
+        .for_each_init(Vec::new, |stack, (i, acc)| {
+            *acc += state.eval_outside(objects[i].pos, theta * theta, stack);
+        });
+}
+
+pub fn iter_single_threaded(
+    objects: &mut [ObjectInfo],
+    out: &mut [Vector3<f64>],
+    state: &mut MultipoleTree,
+    theta: f64,
+) {
+    walk_tree(objects, out, state, theta, false);
+    if state.tree.is_empty() {
+        return;
+    }
+    let mut stack = Vec::new();
+    for (obj, acc) in objects.iter().zip(out.iter_mut()) {
+        if is_outside(obj) {
+            *acc += state.eval_outside(obj.pos, theta * theta, &mut stack);
+        }
+    }
+}
@@ -0,0 +1,195 @@
+//! Fourth order Hermite integrator.
+//!
+//! The predictor-corrector scheme of Makino & Aarseth (1992), the usual choice for
+//! collisional systems like dense star clusters. Besides the acceleration, each force
+//! evaluation gives the jerk, so a step only needs one evaluation for fourth order
+//! accuracy. Gravity is computed by direct summation, which the scheme needs anyway to
+//! get the jerk of every pair.
+//!
+//! All objects share the same step, picked with the criterion of Aarseth (1985) from
+//! the higher derivatives of the acceleration found by the corrector. As with the
+//! adaptive Dormand-Prince integrator, each tick still advances by exactly `delta`.
+
+use cgmath::{InnerSpace, Vector3, Zero};
+use rayon::iter::{
+    IndexedParallelIterator, IntoParallelRefIterator, IntoParallelRefMutIterator, ParallelIterator,
+};
+
+use crate::sim::{
+    ObjectInfo, PeriodicBox,
+    direct::{MassiveIndices, par_acc_jerk},
+};
+
+/// The first step is this fraction of the accuracy parameter times the time scale of
+/// the jerk, since the higher derivatives are not known yet.
+const START_ACCURACY_FRACTION: f64 = 0.5;
+
+/// Largest factor the step can grow by from one step to the next.
+const MAX_STEP_GROWTH: f64 = 2.0;
+
+/// Steps shorter than this fraction of a tick are taken as if they were this long, so
+/// a close encounter can not stall the simulation.
+const MIN_STEP_FRACTION: f64 = 1e-9;
+
+pub struct Hermite {
+    /// Accuracy parameter of the step criterion, usually around 0.01 to 0.03. Smaller is
+    /// more accurate.
+    pub accuracy: f64,
+    next_step: Option<f64>,
+    start: Vec<ObjectInfo>,
+    acc: Vec<Vector3<f64>>,
+    jerk: Vec<Vector3<f64>>,
+    new_acc: Vec<Vector3<f64>>,
+    new_jerk: Vec<Vector3<f64>>,
+    massive: MassiveIndices,
+}
+
+impl Hermite {
+    pub fn new(accuracy: f64) -> Self {
+        Self {
+            accuracy,
+            next_step: None,
+            start: Vec::new(),
+            acc: Vec::new(),
+            jerk: Vec::new(),
+            new_acc: Vec::new(),
+            new_jerk: Vec::new(),
+            massive: MassiveIndices::default(),
+        }
+    }
+
+    /// Advance `objects` by `delta` seconds, backwards for negative `delta`.
+    pub fn advance(
+        &mut self,
+        objects: &mut [ObjectInfo],
+        periodic: Option<&PeriodicBox>,
+        delta: f64,
+    ) {
+        let n = objects.len();
+        for buf in [
+            &mut self.acc,
+            &mut self.jerk,
+            &mut self.new_acc,
+            &mut self.new_jerk,
+        ] {
+            buf.resize(n, Vector3::zero());
+        }
+        // Objects may have changed since the last tick, so the forces are never carried
+        // over from it.
+        let massive = self.massive.update(objects);
+        par_acc_jerk(objects, massive, &mut self.acc, &mut self.jerk, periodic);
+
+        let direction = delta.signum();
+        let min_step = delta.abs() * MIN_STEP_FRACTION;
+        let mut remaining = delta.abs();
+        while remaining > 0.0 {
+            let proposed = match self.next_step {
+                Some(step) => step,
+                None => self.start_step(objects).unwrap_or(delta.abs()),
+            }
+            .max(min_step);
+            let clipped = proposed >= remaining;
+            let h = proposed.min(remaining);
+            let next = self.step(objects, periodic, h * direction);
+            remaining -= h;
+            // A step cut short to end on the tick says little about the next one.
+            let next = next.unwrap_or(h * MAX_STEP_GROWTH).min(h * MAX_STEP_GROWTH);
+            if !clipped || next < proposed {
+                self.next_step = Some(next);
+            }
+        }
+    }
+
+    /// Predict, evaluate and correct a step of `h` seconds. Returns the step the
+    /// criterion asks for next, if any object feels a force.
+    fn step(
+        &mut self,
+        objects: &mut [ObjectInfo],
+        periodic: Option<&PeriodicBox>,
+        h: f64,
+    ) -> Option<f64> {
+        self.start.clear();
+        self.start.extend_from_slice(objects);
+
+        objects
+            .par_iter_mut()
+            .zip(self.acc.par_iter().zip(self.jerk.par_iter()))
+            .for_each(|(obj, (acc, jerk))| {
+                if obj.pinned {
+                    return;
+                }
+                obj.pos += obj.vel * h + acc * (h * h / 2.0) + jerk * (h * h * h / 6.0);
+                obj.vel += acc * h + jerk * (h * h / 2.0);
+            });
+
+        let massive = self.massive.update(objects);
+        par_acc_jerk(
+            objects,
+            massive,
+            &mut self.new_acc,
+            &mut self.new_jerk,
+            periodic,
+        );
+
+        objects
+            .par_iter_mut()
+            .zip(self.start.par_iter())
+            .zip(self.acc.par_iter().zip(self.jerk.par_iter()))
+            .zip(self.new_acc.par_iter().zip(self.new_jerk.par_iter()))
+            .for_each(|(((obj, start), (acc, jerk)), (new_acc, new_jerk))| {
+                if obj.pinned {
+                    return;
+                }
+                obj.vel =
+                    start.vel + (acc + new_acc) * (h / 2.0) + (jerk - new_jerk) * (h * h / 12.0);
+                obj.pos = start.pos
+                    + (start.vel + obj.vel) * (h / 2.0)
+                    + (acc - new_acc) * (h * h / 12.0);
+            });
+
+        let next = self.criterion(objects, h);
+        std::mem::swap(&mut self.acc, &mut self.new_acc);
+        std::mem::swap(&mut self.jerk, &mut self.new_jerk);
+        next
+    }
+
+    /// Shortest step any object asks for by the Aarseth criterion, using the second and
+    /// third derivatives of the acceleration that fit the step just taken.
+    fn criterion(&self, objects: &[ObjectInfo], h: f64) -> Option<f64> {
+        objects
+            .par_iter()
+            .zip(self.acc.par_iter().zip(self.jerk.par_iter()))
+            .zip(self.new_acc.par_iter().zip(self.new_jerk.par_iter()))
+            .filter(|((obj, _), _)| !obj.pinned)
+            .filter_map(|((_, (acc, jerk)), (new_acc, new_jerk))| {
+                let diff = acc - new_acc;
+                let snap = (diff * -6.0 - (jerk * 4.0 + new_jerk * 2.0) * h) / (h * h);
+                let crackle = (diff * 12.0 + (jerk + new_jerk) * (6.0 * h)) / (h * h * h);
+                // The snap at the end of the step, where the next one starts.
+                let snap = snap + crackle * h;
+                let (a, j, s, c) = (
+                    new_acc.magnitude(),
+                    new_jerk.magnitude(),
+                    snap.magnitude(),
+                    crackle.magnitude(),
+                );
+                let step = (self.accuracy * (a * s + j * j) / (j * c + s * s)).sqrt();
+                (step.is_finite() && step > 0.0).then_some(step)
+            })
+            .min_by(f64::total_cmp)
+    }
+
+    /// First step, from the acceleration and jerk alone.
+    fn start_step(&self, objects: &[ObjectInfo]) -> Option<f64> {
+        objects
+            .iter()
+            .zip(self.acc.iter().zip(&self.jerk))
+            .filter(|(obj, _)| !obj.pinned)
+            .filter_map(|(_, (acc, jerk))| {
+                let step =
+                    START_ACCURACY_FRACTION * self.accuracy * acc.magnitude() / jerk.magnitude();
+                (step.is_finite() && step > 0.0).then_some(step)
+            })
+            .min_by(f64::total_cmp)
+    }
+}
@@ -0,0 +1,207 @@
+//! Barnes-Hut style approximation on a kd-tree.
+//!
+//! Instead of splitting space into octants, each node splits its objects in two at the
+//! median along the longest axis of their bounding box. The tree is always balanced,
+//! regardless of how clustered the objects are, and the bounding boxes are tight, so
+//! nodes are opened less often than octree cells of the same size.
+
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector3};
+use rayon::iter::{
+    IndexedParallelIterator, IntoParallelRefIterator, IntoParallelRefMutIterator, ParallelIterator,
+};
+
+use crate::{
+    constants::KD_TREE_LEAF_SIZE,
+    sim::{ObjectInfo, capacity_bytes},
+};
+
+#[derive(Debug, Clone)]
+pub(super) struct Body {
+    pub pos: Point3<f64>,
+    pub mass: f64,
+    /// Index of the object in the simulation.
+    pub idx: usize,
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct KdNode {
+    /// Center of mass, or the center of the bounding box if the node has no mass.
+    pub center_mass: Point3<f64>,
+    pub mass: f64,
+    /// Squared length of the longest side of the bounding box.
+    pub size_sq: f64,
+    /// Range of this node's bodies in the sorted body list.
+    pub start: usize,
+    pub end: usize,
+    /// Index of the second child. The first child always directly follows its parent.
+    /// Zero for leaves.
+    pub right: usize,
+}
+
+impl KdNode {
+    pub fn is_leaf(&self) -> bool {
+        self.right == 0
+    }
+}
+
+/// Tree stored as a flat arena in depth-first order. Both buffers are kept between
+/// ticks, so rebuilding the tree does not allocate once they have grown to fit.
+#[derive(Debug, Default)]
+pub struct KdTree {
+    nodes: Vec<KdNode>,
+    bodies: Vec<Body>,
+}
+
+impl KdTree {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Bytes allocated by the arena.
+    pub fn memory_usage(&self) -> usize {
+        capacity_bytes(&self.nodes) + capacity_bytes(&self.bodies)
+    }
+
+    pub(super) fn nodes(&self) -> &[KdNode] {
+        &self.nodes
+    }
+
+    pub(super) fn bodies(&self) -> &[Body] {
+        &self.bodies
+    }
+
+    pub fn build_tree(&mut self, objects: &[ObjectInfo]) {
+        self.nodes.clear();
+        self.bodies.clear();
+        self.bodies
+            .extend(objects.iter().enumerate().map(|(idx, obj)| Body {
+                pos: obj.pos,
+                mass: obj.gravitating_mass(),
+                idx,
+            }));
+        if !self.bodies.is_empty() {
+            self.build_node(0, self.bodies.len());
+        }
+    }
+
+    fn build_node(&mut self, start: usize, end: usize) -> usize {
+        let bodies = &mut self.bodies[start..end];
+
+        let mut min = Point3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+        let mut max = Point3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
+        let mut weighted = Vector3::new(0.0, 0.0, 0.0);
+        let mut mass = 0.0;
+        for body in bodies.iter() {
+            for i in 0..3 {
+                min[i] = min[i].min(body.pos[i]);
+                max[i] = max[i].max(body.pos[i]);
+            }
+            weighted += body.pos.to_vec() * body.mass;
+            mass += body.mass;
+        }
+        let extent = max - min;
+        let axis = (0..3)
+            .max_by(|a, b| extent[*a].total_cmp(&extent[*b]))
+            .unwrap();
+
+        let id = self.nodes.len();
+        self.nodes.push(KdNode {
+            center_mass: if mass > 0.0 {
+                Point3::from_vec(weighted / mass)
+            } else {
+                min + extent / 2.0
+            },
+            mass,
+            size_sq: extent[axis].powi(2),
+            start,
+            end,
+            right: 0,
+        });
+
+        if bodies.len() > KD_TREE_LEAF_SIZE && extent[axis] > 0.0 {
+            let mid = bodies.len() / 2;
+            bodies.select_nth_unstable_by(mid, |a, b| a.pos[axis].total_cmp(&b.pos[axis]));
+
+            self.build_node(start, start + mid);
+            let right = self.build_node(start + mid, end);
+            self.nodes[id].right = right;
+        }
+
+        id
+    }
+
+    /// `stack` is only scratch space, passed in so that it is allocated once per thread
+    /// instead of once per object.
+    fn compute_acc(
+        &self,
+        obj: &ObjectInfo,
+        out: &mut Vector3<f64>,
+        theta_sq: f64,
+        stack: &mut Vec<usize>,
+    ) {
+        stack.clear();
+        stack.push(0);
+
+        while let Some(id) = stack.pop() {
+            let node = &self.nodes[id];
+            // Massless objects do not attract anything.
+            if node.mass == 0.0 {
+                continue;
+            }
+            let rel = node.center_mass - obj.pos;
+            let dist_sq = rel.magnitude2();
+
+            if theta_sq * dist_sq >= node.size_sq {
+                // Far enough away to treat as a single body
+                obj.get_acc_towards_raw(node.mass, rel, dist_sq, out);
+            } else if !node.is_leaf() {
+                stack.push(id + 1);
+                stack.push(node.right);
+            } else {
+                for body in &self.bodies[node.start..node.end] {
+                    let rel = body.pos - obj.pos;
+                    let dist_sq = rel.magnitude2();
+                    if dist_sq > 0.0 {
+                        obj.get_acc_towards_raw(body.mass, rel, dist_sq, out);
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub fn iter(info: &mut [ObjectInfo], out: &mut [Vector3<f64>], tree: &mut KdTree, theta: f64) {
+    tree.build_tree(info);
+    if tree.len() == 0 {
+        return;
+    }
+    let theta_sq = theta * theta;
+
+    info.par_iter()
+        .zip(out.par_iter_mut())
+        .for_each_init(Vec::new, |stack, (obj, out_acc)| {
+            tree.compute_acc(obj, out_acc, theta_sq, stack);
+        });
+}
+
+pub fn iter_single_threaded(
+    info: &mut [ObjectInfo],
+    out: &mut [Vector3<f64>],
+    tree: &mut KdTree,
+    theta: f64,
+) {
+    tree.build_tree(info);
+    if tree.len() == 0 {
+        return;
+    }
+    let theta_sq = theta * theta;
+    let mut stack = Vec::new();
+
+    for (obj, out_acc) in info.iter().zip(out.iter_mut()) {
+        tree.compute_acc(obj, out_acc, theta_sq, &mut stack);
+    }
+}
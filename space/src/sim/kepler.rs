@@ -0,0 +1,344 @@
+//! Analytic propagation of isolated binaries.
+//!
+//! A tight binary needs a time step far shorter than its period to be integrated
+//! accurately, which is far shorter than the rest of a scenario usually needs. Instead,
+//! bound pairs that are far from every other object are split off: the pull of the two
+//! members on each other is left out of the accelerations, and their relative orbit is
+//! advanced along the exact two body solution during the drift. Everything else still
+//! acts on both members through the regular force solver, as a kick like any other.
+//!
+//! The split is the same as in Wisdom & Holman (1991), only applied to pairs found while
+//! running. It stays symplectic, and an isolated binary is followed exactly with any
+//! time step. Pairs are searched for again every `KEPLER_SEARCH_INTERVAL` steps.
+//!
+//! A system with exactly two objects that attract others, like a planet and its star, is
+//! propagated as a pair by default, bound or not and however far apart. There is then
+//! nothing left for the force solver to do for them, and the orbit does not drift over
+//! time like an integrated one does. Test particles around them are still integrated as
+//! usual. `--no-kepler` turns this off, and `--kepler-pairs` searches for other pairs as
+//! well.
+//!
+//! The exact solution has no softening, so pairs that come closer than
+//! `KEPLER_MIN_PERIAPSIS`, like a head-on collision, are always left to the force solver.
+
+use std::collections::HashMap;
+
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector3};
+
+use crate::{
+    constants::{
+        G, KEPLER_ISOLATION, KEPLER_MAX_ITERATIONS, KEPLER_MIN_PERIAPSIS, KEPLER_SEARCH_INTERVAL,
+    },
+    sim::ObjectInfo,
+};
+
+/// Isolated bound pairs, propagated analytically by the integrator.
+#[derive(Debug, Clone)]
+pub struct KeplerPairs {
+    /// Pairs whose orbit can reach further apart than this are left to the force
+    /// solver, in AU. Also bounds the cost of the search.
+    pub max_separation: f64,
+    /// Every other massive object must be further from both members than this many
+    /// times the largest separation of the pair.
+    pub isolation: f64,
+    pairs: Vec<(usize, usize)>,
+    steps_until_search: u32,
+    /// Only look for the pair of a two body system, see `two_body`.
+    two_body_only: bool,
+}
+
+impl KeplerPairs {
+    pub fn new(max_separation: f64) -> Self {
+        Self {
+            max_separation,
+            isolation: KEPLER_ISOLATION,
+            pairs: Vec::new(),
+            steps_until_search: 0,
+            two_body_only: false,
+        }
+    }
+
+    /// Only propagate the two objects of a system where exactly two attract others, and
+    /// leave every other system to the force solver.
+    pub fn two_body() -> Self {
+        Self {
+            two_body_only: true,
+            ..Self::new(0.0)
+        }
+    }
+
+    /// The pairs currently propagated analytically.
+    pub fn pairs(&self) -> &[(usize, usize)] {
+        &self.pairs
+    }
+
+    /// Look for pairs again if it is time to, and drop pairs where a member has been
+    /// pinned or turned into a test particle since.
+    pub fn update(&mut self, objects: &[ObjectInfo]) {
+        if self.steps_until_search == 0 {
+            self.pairs = self.find_pairs(objects);
+            self.steps_until_search = KEPLER_SEARCH_INTERVAL;
+        } else {
+            self.pairs
+                .retain(|&(a, b)| can_pair(&objects[a]) && can_pair(&objects[b]));
+        }
+        self.steps_until_search -= 1;
+    }
+
+    /// Take the pull of the members of each pair on each other back out of `acc`.
+    pub fn remove_mutual_acc(&self, objects: &[ObjectInfo], acc: &mut [Vector3<f64>]) {
+        for &(a, b) in &self.pairs {
+            let mut towards_b = Vector3::new(0.0, 0.0, 0.0);
+            objects[a].get_acc_towards(&objects[b], &mut towards_b);
+            let mut towards_a = Vector3::new(0.0, 0.0, 0.0);
+            objects[b].get_acc_towards(&objects[a], &mut towards_a);
+            acc[a] -= towards_b;
+            acc[b] -= towards_a;
+        }
+    }
+
+    /// Positions and velocities of the pair members after `delta` seconds of drifting,
+    /// with the center of mass moving in a straight line and the members orbiting it.
+    /// Works backwards in time for negative `delta`.
+    pub fn drift(
+        &self,
+        objects: &[ObjectInfo],
+        delta: f64,
+    ) -> Vec<(usize, Point3<f64>, Vector3<f64>)> {
+        let mut res = Vec::with_capacity(self.pairs.len() * 2);
+        for &(a, b) in &self.pairs {
+            let (oa, ob) = (&objects[a], &objects[b]);
+            let total = oa.mass + ob.mass;
+            let rel = ob.pos - oa.pos;
+            let rel_vel = ob.vel - oa.vel;
+            let com = oa.pos + rel * (ob.mass / total);
+            let com_vel = (oa.vel * oa.mass + ob.vel * ob.mass) / total;
+
+            let com = com + com_vel * delta;
+            let (rel, rel_vel) = kepler_drift(G * total, rel, rel_vel, delta);
+            res.push((
+                a,
+                com - rel * (ob.mass / total),
+                com_vel - rel_vel * (ob.mass / total),
+            ));
+            res.push((
+                b,
+                com + rel * (oa.mass / total),
+                com_vel + rel_vel * (oa.mass / total),
+            ));
+        }
+        res
+    }
+
+    /// Mutually nearest massive objects that are bound to each other, and isolated from
+    /// everything else. Only objects within `isolation * max_separation` of each other
+    /// are compared, using a grid of that size.
+    fn find_pairs(&self, objects: &[ObjectInfo]) -> Vec<(usize, usize)> {
+        if let Some(pair) = two_body_pair(objects) {
+            return vec![pair];
+        }
+        if self.two_body_only {
+            return Vec::new();
+        }
+        let radius = self.isolation * self.max_separation;
+        let cell_of = |pos: Point3<f64>| {
+            let pos = pos.to_vec() / radius;
+            (
+                pos.x.floor() as i64,
+                pos.y.floor() as i64,
+                pos.z.floor() as i64,
+            )
+        };
+        let mut grid: HashMap<_, Vec<usize>> = HashMap::new();
+        for (idx, obj) in objects.iter().enumerate() {
+            if obj.gravitating_mass() > 0.0 && !obj.is_frozen() {
+                grid.entry(cell_of(obj.pos)).or_default().push(idx);
+            }
+        }
+        // Nearest and second nearest massive object within `radius` of each object.
+        let mut nearest = vec![None; objects.len()];
+        for (&(x, y, z), members) in &grid {
+            for &idx in members {
+                let mut first: Option<(usize, f64)> = None;
+                let mut second = f64::INFINITY;
+                for cell in neighbor_cells(x, y, z) {
+                    for &other in grid.get(&cell).into_iter().flatten() {
+                        if other == idx {
+                            continue;
+                        }
+                        let dist = (objects[other].pos - objects[idx].pos).magnitude();
+                        match first {
+                            Some((_, d)) if dist >= d => second = second.min(dist),
+                            _ => {
+                                if let Some((_, d)) = first {
+                                    second = d;
+                                }
+                                first = Some((other, dist));
+                            }
+                        }
+                    }
+                }
+                nearest[idx] = first.map(|(other, _)| (other, second));
+            }
+        }
+
+        let mut pairs = Vec::new();
+        for (a, entry) in nearest.iter().enumerate() {
+            let Some((b, second_a)) = *entry else {
+                continue;
+            };
+            let Some((back, second_b)) = nearest[b] else {
+                continue;
+            };
+            if back != a || b < a {
+                continue;
+            }
+            let (oa, ob) = (&objects[a], &objects[b]);
+            if !can_pair(oa) || !can_pair(ob) {
+                continue;
+            }
+            let Some(apoapsis) =
+                apoapsis(G * (oa.mass + ob.mass), ob.pos - oa.pos, ob.vel - oa.vel)
+            else {
+                continue;
+            };
+            if apoapsis <= self.max_separation
+                && second_a.min(second_b) > self.isolation * apoapsis
+                && clear_of_softening(oa, ob)
+            {
+                pairs.push((a, b));
+            }
+        }
+        pairs
+    }
+}
+
+/// The two objects that attract others, if there are exactly two and both can be paired.
+fn two_body_pair(objects: &[ObjectInfo]) -> Option<(usize, usize)> {
+    let mut massive = (0..objects.len()).filter(|idx| objects[*idx].gravitating_mass() > 0.0);
+    let (a, b) = (massive.next()?, massive.next()?);
+    let (oa, ob) = (&objects[a], &objects[b]);
+    if massive.next().is_some() || !can_pair(oa) || !can_pair(ob) || !clear_of_softening(oa, ob) {
+        return None;
+    }
+    Some((a, b))
+}
+
+fn neighbor_cells(x: i64, y: i64, z: i64) -> impl Iterator<Item = (i64, i64, i64)> {
+    (-1..=1).flat_map(move |dx| {
+        (-1..=1).flat_map(move |dy| (-1..=1).map(move |dz| (x + dx, y + dy, z + dz)))
+    })
+}
+
+/// Pinned objects do not drift, and test particles do not pull back on their partner.
+fn can_pair(obj: &ObjectInfo) -> bool {
+    !obj.pinned && !obj.test_particle
+}
+
+/// Whether the pair never comes within `KEPLER_MIN_PERIAPSIS` of each other, where the
+/// force solver would pad the distance and the exact solution would not.
+fn clear_of_softening(oa: &ObjectInfo, ob: &ObjectInfo) -> bool {
+    periapsis(G * (oa.mass + ob.mass), ob.pos - oa.pos, ob.vel - oa.vel) >= KEPLER_MIN_PERIAPSIS
+}
+
+/// Smallest separation of a pair with gravitational parameter `mu`, bound or not. Zero
+/// for pairs falling straight at each other.
+fn periapsis(mu: f64, rel: Vector3<f64>, rel_vel: Vector3<f64>) -> f64 {
+    let energy = rel_vel.magnitude2() / 2.0 - mu / rel.magnitude();
+    let ang_mom = rel.cross(rel_vel).magnitude2();
+    let eccentricity = (1.0 + 2.0 * energy * ang_mom / (mu * mu)).max(0.0).sqrt();
+    ang_mom / (mu * (1.0 + eccentricity))
+}
+
+/// Largest separation of a pair with gravitational parameter `mu`, or `None` if the
+/// pair is not bound.
+fn apoapsis(mu: f64, rel: Vector3<f64>, rel_vel: Vector3<f64>) -> Option<f64> {
+    let energy = rel_vel.magnitude2() / 2.0 - mu / rel.magnitude();
+    if energy >= 0.0 {
+        return None;
+    }
+    let semi_major_axis = -mu / (2.0 * energy);
+    let ang_mom = rel.cross(rel_vel).magnitude2();
+    let eccentricity = (1.0 - ang_mom / (mu * semi_major_axis)).max(0.0).sqrt();
+    Some(semi_major_axis * (1.0 + eccentricity))
+}
+
+/// Stumpff functions C(z) and S(z), with series expansions near zero where the closed
+/// forms lose all precision.
+fn stumpff(z: f64) -> (f64, f64) {
+    if z.abs() < 1e-4 {
+        (
+            0.5 - z / 24.0 + z * z / 720.0,
+            1.0 / 6.0 - z / 120.0 + z * z / 5040.0,
+        )
+    } else if z > 0.0 {
+        let s = z.sqrt();
+        ((1.0 - s.cos()) / z, (s - s.sin()) / (s * s * s))
+    } else {
+        let s = (-z).sqrt();
+        ((s.cosh() - 1.0) / -z, (s.sinh() - s) / (s * s * s))
+    }
+}
+
+/// Relative position and velocity of a two body orbit with gravitational parameter `mu`,
+/// `dt` seconds after `rel` and `rel_vel`. Uses the universal variable formulation, as
+/// in Curtis, Orbital Mechanics for Engineering Students, algorithms 3.3 and 3.4.
+///
+/// Bodies that start or end up on top of each other have no orbit to follow, and move in
+/// a straight line instead.
+pub fn kepler_drift(
+    mu: f64,
+    rel: Vector3<f64>,
+    rel_vel: Vector3<f64>,
+    dt: f64,
+) -> (Vector3<f64>, Vector3<f64>) {
+    let straight = (rel + rel_vel * dt, rel_vel);
+    let r0 = rel.magnitude();
+    if r0 == 0.0 {
+        return straight;
+    }
+    let vr0 = rel.dot(rel_vel) / r0;
+    let alpha = 2.0 / r0 - rel_vel.magnitude2() / mu;
+    let sqrt_mu = mu.sqrt();
+
+    // Whole periods of a bound orbit change nothing, and would slow down the solver.
+    let dt = if alpha > 0.0 {
+        let period = std::f64::consts::TAU / (sqrt_mu * alpha.powf(1.5));
+        dt % period
+    } else {
+        dt
+    };
+
+    let mut chi = if alpha > 0.0 {
+        sqrt_mu * alpha * dt
+    } else {
+        sqrt_mu * dt / r0
+    };
+    for _ in 0..KEPLER_MAX_ITERATIONS {
+        let z = alpha * chi * chi;
+        let (c, s) = stumpff(z);
+        let f = r0 * vr0 / sqrt_mu * chi * chi * c
+            + (1.0 - alpha * r0) * chi * chi * chi * s
+            + r0 * chi
+            - sqrt_mu * dt;
+        let df = r0 * vr0 / sqrt_mu * chi * (1.0 - z * s) + (1.0 - alpha * r0) * chi * chi * c + r0;
+        let step = f / df;
+        chi -= step;
+        if step.abs() <= 1e-12 * chi.abs().max(1e-12) {
+            break;
+        }
+    }
+
+    let z = alpha * chi * chi;
+    let (c, s) = stumpff(z);
+    let f = 1.0 - chi * chi / r0 * c;
+    let g = dt - chi * chi * chi / sqrt_mu * s;
+    let new_rel = rel * f + rel_vel * g;
+    let r = new_rel.magnitude();
+    if r == 0.0 {
+        return straight;
+    }
+    let f_dot = sqrt_mu / (r * r0) * (z * chi * s - chi);
+    let g_dot = 1.0 - chi * chi / r * c;
+    (new_rel, rel * f_dot + rel_vel * g_dot)
+}
@@ -1,28 +1,113 @@
-use std::fmt::Display;
+use std::{fmt::Display, path::Path, time::Instant};
 
 use cgmath::{InnerSpace, Point3, Vector3, Zero};
-use rayon::{ThreadPool, ThreadPoolBuilder};
+use rayon::{iter::ParallelIterator, slice::ParallelSliceMut};
 
 use crate::{
-    constants::{COLLISION_EPSILON, G, MAX_THREADS, OBJECTS_PER_THREAD},
-    sim::direct::par_add_rec,
+    Object,
+    constants::{AU, BARNES_HUT_GROUP_SIZE, COLLISION_EPSILON, G, M0},
+    sim::{
+        direct::{MassiveIndices, chunk_size, par_add_rec, par_drift, par_kick},
+        threads::ThreadScaler,
+    },
 };
 
+mod adaptive;
 pub mod barnes_hut;
 mod direct;
+mod dual_tree;
+mod fmm;
+mod hermite;
+mod kd_tree;
+mod kepler;
+mod oblateness;
+mod periodic;
+mod post_newtonian;
+mod potential;
+mod radiation;
+mod rk45;
+mod threads;
+mod thrust;
+
+pub use adaptive::{AdaptiveSim, SolverKind};
+pub use barnes_hut::{
+    DirectComparison, FmmTree, FofGroup, FofGroups, ForceCheck, NodeContribution, local_densities,
+    validate_against_direct,
+};
+pub use hermite::Hermite;
+pub use kepler::{KeplerPairs, kepler_drift};
+pub use oblateness::{OblateBody, Oblateness};
+pub use periodic::PeriodicBox;
+pub use potential::AnalyticPotential;
+pub use radiation::{Radiation, RadiationSource, RadiationTarget};
+pub use rk45::{DormandPrince, StepStats};
+pub use thrust::{Maneuver, Spacecraft, ThrustAmount, ThrustDirection};
 
 #[derive(Debug, Clone)]
 pub struct ObjectInfo {
     pub pos: Point3<f64>,
     pub vel: Vector3<f64>,
     pub mass: f64,
+    /// Held in place by the integrator, while still attracting other objects.
+    pub pinned: bool,
+    /// Massless test particle, which feels the gravity of massive objects but does not
+    /// attract anything itself. Much cheaper to simulate in large numbers, since there is
+    /// no need to compute forces between test particles.
+    pub test_particle: bool,
 }
 
 impl ObjectInfo {
+    /// Mass this object attracts others with, which is zero for test particles.
+    #[inline]
+    pub fn gravitating_mass(&self) -> f64 {
+        if self.test_particle { 0.0 } else { self.mass }
+    }
+
+    /// Pinned test particles neither move nor attract anything, so they can be left out
+    /// of the simulation entirely.
+    #[inline]
+    pub fn is_frozen(&self) -> bool {
+        self.pinned && self.test_particle
+    }
+
     #[inline]
     pub fn get_acc_towards(&self, other: &ObjectInfo, out: &mut Vector3<f64>) {
         let rel = other.pos - self.pos;
-        *out += rel * other.mass * G / (rel.magnitude2() * rel.magnitude() + COLLISION_EPSILON);
+        *out += rel * other.gravitating_mass() * G
+            / (rel.magnitude2() * rel.magnitude() + COLLISION_EPSILON);
+    }
+
+    /// Like `get_acc_towards`, also adding the jerk, the rate of change of the
+    /// acceleration, to `jerk`. `rel` is the offset to `other`, so that the nearest copy
+    /// can be passed in a periodic box. The jerk is the exact derivative of the softened
+    /// acceleration, which keeps a Hermite integrator consistent at close range.
+    #[inline]
+    pub fn get_acc_jerk_towards(
+        &self,
+        other: &ObjectInfo,
+        rel: Vector3<f64>,
+        acc: &mut Vector3<f64>,
+        jerk: &mut Vector3<f64>,
+    ) {
+        let rel_vel = other.vel - self.vel;
+        let dist = rel.magnitude();
+        let denom = rel.magnitude2() * dist + COLLISION_EPSILON;
+        let gm = other.gravitating_mass() * G;
+        *acc += rel * (gm / denom);
+        *jerk += (rel_vel - rel * (3.0 * dist * rel.dot(rel_vel) / denom)) * (gm / denom);
+    }
+
+    /// Like `get_acc_towards`, but towards the nearest copy of `other` in a periodic box.
+    #[inline]
+    pub fn get_acc_towards_periodic(
+        &self,
+        other: &ObjectInfo,
+        bounds: &PeriodicBox,
+        out: &mut Vector3<f64>,
+    ) {
+        let rel = bounds.min_image(other.pos - self.pos);
+        *out += rel * other.gravitating_mass() * G
+            / (rel.magnitude2() * rel.magnitude() + COLLISION_EPSILON);
     }
 
     #[inline]
@@ -37,34 +122,181 @@ impl ObjectInfo {
     }
 }
 
-fn compute_target_threads(n_objects: usize) -> usize {
-    assert!(n_objects > 0);
-    n_objects.div_ceil(OBJECTS_PER_THREAD).min(MAX_THREADS)
-}
-
 impl<R: SimulationImpl + Send> ObjectBuffer<R> {
     pub fn new(objects: Vec<ObjectInfo>, simulation: R) -> Self {
         let len = objects.len();
         let out_buffer = vec![Vector3::<f64>::zero(); len];
-        let n_threads = compute_target_threads(objects.len());
+        let threads = ThreadScaler::new(objects.len());
 
         Self {
             objects,
             out_buffer,
-            pool: ThreadPoolBuilder::new()
-                .num_threads(n_threads)
-                .build()
-                .unwrap(),
+            threads,
             simulation,
+            periodic: None,
+            kepler: None,
+            rk45: None,
+            hermite: None,
         }
     }
 
+    /// Keep every object inside `bounds`, moving them to the opposite side when they
+    /// leave. The simulation should use the same box for its forces.
+    pub fn with_periodic_box(mut self, bounds: PeriodicBox) -> Self {
+        for obj in &mut self.objects {
+            obj.pos = bounds.wrap(obj.pos);
+        }
+        self.periodic = Some(bounds);
+        self
+    }
+
+    /// Propagate isolated bound pairs analytically, see `KeplerPairs`. Ignored in a
+    /// periodic box, where pairs may straddle the edge.
+    pub fn with_kepler_pairs(mut self, kepler: KeplerPairs) -> Self {
+        if self.periodic.is_none() {
+            self.kepler = Some(kepler);
+        }
+        self
+    }
+
+    /// Integrate with an adaptive Dormand-Prince method instead of symplectic Euler, see
+    /// `DormandPrince`. Pairs propagated analytically are left to it as well.
+    pub fn with_rk45(mut self, rk45: DormandPrince) -> Self {
+        self.kepler = None;
+        self.hermite = None;
+        self.rk45 = Some(rk45);
+        self
+    }
+
+    /// Integrate with the fourth order Hermite scheme instead of symplectic Euler, see
+    /// `Hermite`. Gravity is then computed by direct summation, and the simulation is
+    /// not used at all, so other forces are left out.
+    pub fn with_hermite(mut self, hermite: Hermite) -> Self {
+        self.kepler = None;
+        self.rk45 = None;
+        self.hermite = Some(hermite);
+        self
+    }
+
+    /// Step size and rejection rate of the adaptive integrator since the last call, if
+    /// there is one.
+    pub fn take_step_stats(&mut self) -> Option<StepStats> {
+        self.rk45.as_mut().map(DormandPrince::take_stats)
+    }
+
+    /// See `SimulationImpl::set_theta`.
+    pub fn set_theta(&mut self, theta: f64) {
+        SimulationImpl::set_theta(&mut self.simulation, theta);
+    }
+
+    /// See `SimulationImpl::set_solver`.
+    pub fn set_solver(&mut self, solver: Option<SolverKind>) {
+        SimulationImpl::set_solver(&mut self.simulation, solver);
+    }
+
+    /// See `SimulationImpl::dump_tree`.
+    pub fn dump_tree(&self, path: &Path) -> anyhow::Result<bool> {
+        SimulationImpl::dump_tree(&self.simulation, path)
+    }
+
+    /// See `SimulationImpl::set_time`.
+    pub fn set_time(&mut self, time: SimTime) {
+        SimulationImpl::set_time(&mut self.simulation, time);
+    }
+
+    /// Compare the gravity on `objects[index]` from a tree with opening angle `theta` to
+    /// direct summation, whichever solver the simulation uses.
+    pub fn check_force(&self, index: usize, theta: f64) -> Option<ForceCheck> {
+        ForceCheck::run(&self.objects, index, theta, self.periodic.as_ref())
+    }
+
+    /// Use at most `budget` of the cores of the machine, as a fraction.
+    pub fn set_cpu_budget(&mut self, budget: f64) {
+        self.threads.set_budget(budget);
+    }
+
+    /// Run on exactly `threads` threads, or pick them by itself with `None`.
+    pub fn set_fixed_threads(&mut self, threads: Option<usize>) {
+        self.threads.set_fixed(threads);
+    }
+
+    /// Number of threads the simulation currently runs on.
+    pub fn num_threads(&self) -> usize {
+        self.threads.threads()
+    }
+
+    /// Bytes held by the objects, the acceleration buffer and the simulation.
+    pub fn memory_usage(&self) -> usize {
+        capacity_bytes(&self.objects)
+            + capacity_bytes(&self.out_buffer)
+            + SimulationImpl::memory_usage(&self.simulation)
+    }
+
+    /// Advance the objects by `delta` seconds. A negative `delta` steps backwards,
+    /// undoing a step forward of the same length up to rounding, as long as every
+    /// force only depends on the positions.
     pub fn exec_iter(&mut self, delta: f64) {
-        // Number of objects per thread is equal to ceil[num_objects / num_threads]
-        self.pool.install(|| {
-            self.simulation
-                .iter(&mut self.objects, &mut self.out_buffer);
-            par_add_rec(&mut self.objects, &mut self.out_buffer, delta);
+        let start = Instant::now();
+        self.threads.pool().install(|| {
+            if let Some(hermite) = &mut self.hermite {
+                hermite.advance(&mut self.objects, self.periodic.as_ref(), delta);
+                wrap_periodic(&mut self.objects, self.periodic.as_ref());
+                return;
+            }
+            if let Some(rk45) = &mut self.rk45 {
+                rk45.advance(&mut self.objects, &mut self.simulation, delta);
+                wrap_periodic(&mut self.objects, self.periodic.as_ref());
+                return;
+            }
+            if let Some(kepler) = &mut self.kepler {
+                kepler.update(&self.objects);
+            }
+            if delta < 0.0 {
+                // A step forward kicks and then drifts, so the inverse drifts and then
+                // kicks, with the acceleration at the position it started from.
+                drift(&mut self.objects, self.kepler.as_ref(), delta);
+                wrap_periodic(&mut self.objects, self.periodic.as_ref());
+                self.simulation
+                    .iter(&mut self.objects, &mut self.out_buffer);
+                if let Some(kepler) = &self.kepler {
+                    kepler.remove_mutual_acc(&self.objects, &mut self.out_buffer);
+                }
+                par_kick(&mut self.objects, &mut self.out_buffer, delta);
+            } else if let Some(kepler) = &self.kepler {
+                self.simulation
+                    .iter(&mut self.objects, &mut self.out_buffer);
+                kepler.remove_mutual_acc(&self.objects, &mut self.out_buffer);
+                par_kick(&mut self.objects, &mut self.out_buffer, delta);
+                drift(&mut self.objects, Some(kepler), delta);
+            } else {
+                self.simulation
+                    .iter(&mut self.objects, &mut self.out_buffer);
+                par_add_rec(&mut self.objects, &mut self.out_buffer, delta);
+                wrap_periodic(&mut self.objects, self.periodic.as_ref());
+            }
+        });
+        self.threads.record(start.elapsed());
+    }
+}
+
+/// Move every object along its velocity, except for the pairs in `kepler`, which follow
+/// their orbits.
+fn drift(objects: &mut [ObjectInfo], kepler: Option<&KeplerPairs>, delta: f64) {
+    let orbits = kepler.map(|k| k.drift(objects, delta)).unwrap_or_default();
+    par_drift(objects, delta);
+    for (idx, pos, vel) in orbits {
+        objects[idx].pos = pos;
+        objects[idx].vel = vel;
+    }
+}
+
+fn wrap_periodic(objects: &mut [ObjectInfo], periodic: Option<&PeriodicBox>) {
+    if let Some(bounds) = periodic {
+        let chunk = chunk_size(objects.len(), 1);
+        objects.par_chunks_mut(chunk).for_each(|objects| {
+            for obj in objects {
+                obj.pos = bounds.wrap(obj.pos);
+            }
         });
     }
 }
@@ -73,11 +305,44 @@ pub trait SimulationImpl {
     fn iter(&mut self, objects: &mut [ObjectInfo], out_buffer: &mut [Vector3<f64>]);
 
     fn iter_single_threaded(&mut self, objects: &mut [ObjectInfo], out_buffer: &mut [Vector3<f64>]);
+
+    /// Change the opening angle of the tree approximation, if there is one. Takes effect
+    /// from the next step.
+    fn set_theta(&mut self, _theta: f64) {}
+
+    /// Use `solver` for gravity from the next step, or go back to picking one by the
+    /// number of objects with `None`. Ignored by simulations with a fixed solver.
+    fn set_solver(&mut self, _solver: Option<SolverKind>) {}
+
+    /// Bytes kept between steps, like the arena of a tree. Only counts buffers that grow
+    /// with the number of objects.
+    fn memory_usage(&self) -> usize {
+        0
+    }
+
+    /// Write the octree of the last step to `path`, see `FmmTree::save`. Returns whether
+    /// there was an octree to write.
+    fn dump_tree(&self, _path: &Path) -> anyhow::Result<bool> {
+        Ok(false)
+    }
+
+    /// Simulated time at the start of the next step, for forces that follow a schedule.
+    fn set_time(&mut self, _time: SimTime) {}
+}
+
+/// Bytes allocated by `v`, whether in use or not.
+pub(crate) fn capacity_bytes<T>(v: &Vec<T>) -> usize {
+    v.capacity() * std::mem::size_of::<T>()
 }
 
 pub struct BarnesHutSim {
     pub theta: f64,
     pub tree: barnes_hut::FmmTree,
+    pub periodic: Option<PeriodicBox>,
+    /// Update the tree of the last step instead of building a new one every step.
+    pub incremental: bool,
+    /// Number of nearby objects sharing a walk of the tree.
+    pub group_size: usize,
 }
 
 impl BarnesHutSim {
@@ -85,13 +350,45 @@ impl BarnesHutSim {
         Self {
             theta,
             tree: barnes_hut::FmmTree::new(),
+            periodic: None,
+            incremental: false,
+            group_size: BARNES_HUT_GROUP_SIZE,
         }
     }
+
+    /// Keep the tree between steps, only rebuilding the parts of it that objects have
+    /// moved out of. Pays off when the objects move little from step to step compared to
+    /// the size of the cells they are in, like in a cluster that is close to equilibrium.
+    pub fn with_incremental_tree(mut self) -> Self {
+        self.incremental = true;
+        self
+    }
+
+    /// Use the minimum image convention in `bounds`. The objects must be kept inside it.
+    pub fn with_periodic_box(mut self, bounds: PeriodicBox) -> Self {
+        self.periodic = Some(bounds);
+        self
+    }
+
+    /// Walk the tree once for every `size` nearby objects, at least 1. Larger groups walk
+    /// the tree less often, but open more nodes to be close enough for all of them.
+    pub fn with_group_size(mut self, size: usize) -> Self {
+        self.group_size = size.max(1);
+        self
+    }
 }
 
 impl SimulationImpl for BarnesHutSim {
     fn iter(&mut self, objects: &mut [ObjectInfo], out_buffer: &mut [Vector3<f64>]) {
-        barnes_hut::iter(objects, out_buffer, &mut self.tree, self.theta);
+        barnes_hut::iter(
+            objects,
+            out_buffer,
+            &mut self.tree,
+            self.theta,
+            self.periodic.as_ref(),
+            self.incremental,
+            self.group_size,
+        );
     }
 
     fn iter_single_threaded(
@@ -99,15 +396,520 @@ impl SimulationImpl for BarnesHutSim {
         objects: &mut [ObjectInfo],
         out_buffer: &mut [Vector3<f64>],
     ) {
-        barnes_hut::iter_single_threaded(objects, out_buffer, &mut self.tree, self.theta);
+        barnes_hut::iter_single_threaded(
+            objects,
+            out_buffer,
+            &mut self.tree,
+            self.theta,
+            self.periodic.as_ref(),
+            self.incremental,
+            self.group_size,
+        );
+    }
+
+    fn set_theta(&mut self, theta: f64) {
+        self.theta = theta;
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.tree.memory_usage()
+    }
+
+    fn dump_tree(&self, path: &Path) -> anyhow::Result<bool> {
+        self.tree.save(path)?;
+        Ok(true)
     }
 }
 
-pub struct BruteForceSim;
+pub struct FmmSim {
+    pub theta: f64,
+    tree: fmm::MultipoleTree,
+}
+
+impl FmmSim {
+    pub fn new(theta: f64) -> Self {
+        Self {
+            theta,
+            tree: fmm::MultipoleTree::new(),
+        }
+    }
+}
+
+impl SimulationImpl for FmmSim {
+    fn iter(&mut self, objects: &mut [ObjectInfo], out_buffer: &mut [Vector3<f64>]) {
+        fmm::iter(objects, out_buffer, &mut self.tree, self.theta);
+    }
+
+    fn iter_single_threaded(
+        &mut self,
+        objects: &mut [ObjectInfo],
+        out_buffer: &mut [Vector3<f64>],
+    ) {
+        fmm::iter_single_threaded(objects, out_buffer, &mut self.tree, self.theta);
+    }
+
+    fn set_theta(&mut self, theta: f64) {
+        self.theta = theta;
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.tree.memory_usage()
+    }
+}
+
+/// Barnes-Hut approximation on a kd-tree with median splits, rather than an octree.
+pub struct KdTreeSim {
+    pub theta: f64,
+    tree: kd_tree::KdTree,
+}
+
+impl KdTreeSim {
+    pub fn new(theta: f64) -> Self {
+        Self {
+            theta,
+            tree: kd_tree::KdTree::new(),
+        }
+    }
+}
+
+impl SimulationImpl for KdTreeSim {
+    fn iter(&mut self, objects: &mut [ObjectInfo], out_buffer: &mut [Vector3<f64>]) {
+        kd_tree::iter(objects, out_buffer, &mut self.tree, self.theta);
+    }
+
+    fn iter_single_threaded(
+        &mut self,
+        objects: &mut [ObjectInfo],
+        out_buffer: &mut [Vector3<f64>],
+    ) {
+        kd_tree::iter_single_threaded(objects, out_buffer, &mut self.tree, self.theta);
+    }
+
+    fn set_theta(&mut self, theta: f64) {
+        self.theta = theta;
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.tree.memory_usage()
+    }
+}
+
+/// Barnes-Hut approximation using a dual tree walk, where well separated pairs of nodes
+/// interact directly instead of once per object.
+pub struct DualTreeSim {
+    pub theta: f64,
+    state: dual_tree::DualTree,
+}
+
+impl DualTreeSim {
+    pub fn new(theta: f64) -> Self {
+        Self {
+            theta,
+            state: dual_tree::DualTree::new(),
+        }
+    }
+}
+
+impl SimulationImpl for DualTreeSim {
+    fn iter(&mut self, objects: &mut [ObjectInfo], out_buffer: &mut [Vector3<f64>]) {
+        dual_tree::iter(objects, out_buffer, &mut self.state, self.theta);
+    }
+
+    fn iter_single_threaded(
+        &mut self,
+        objects: &mut [ObjectInfo],
+        out_buffer: &mut [Vector3<f64>],
+    ) {
+        dual_tree::iter(objects, out_buffer, &mut self.state, self.theta);
+    }
+
+    fn set_theta(&mut self, theta: f64) {
+        self.theta = theta;
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.state.memory_usage()
+    }
+}
+
+/// Source of accelerations on the simulated objects. Every simulation is a force
+/// provider, so gravity can be stacked with other forces in a `ForcesSim`.
+pub trait ForceProvider: Send {
+    /// Add the acceleration of each object to `out`.
+    fn add_acc(&mut self, objects: &mut [ObjectInfo], out: &mut [Vector3<f64>]);
+
+    fn add_acc_single_threaded(&mut self, objects: &mut [ObjectInfo], out: &mut [Vector3<f64>]);
+
+    /// See `SimulationImpl::set_theta`.
+    fn set_theta(&mut self, _theta: f64) {}
+
+    /// See `SimulationImpl::set_solver`.
+    fn set_solver(&mut self, _solver: Option<SolverKind>) {}
+
+    /// See `SimulationImpl::memory_usage`.
+    fn memory_usage(&self) -> usize {
+        0
+    }
+
+    /// See `SimulationImpl::dump_tree`.
+    fn dump_tree(&self, _path: &Path) -> anyhow::Result<bool> {
+        Ok(false)
+    }
+
+    /// See `SimulationImpl::set_time`.
+    fn set_time(&mut self, _time: SimTime) {}
+}
+
+impl<T: SimulationImpl + Send> ForceProvider for T {
+    fn add_acc(&mut self, objects: &mut [ObjectInfo], out: &mut [Vector3<f64>]) {
+        self.iter(objects, out);
+    }
+
+    fn add_acc_single_threaded(&mut self, objects: &mut [ObjectInfo], out: &mut [Vector3<f64>]) {
+        self.iter_single_threaded(objects, out);
+    }
+
+    fn set_theta(&mut self, theta: f64) {
+        SimulationImpl::set_theta(self, theta);
+    }
+
+    fn set_solver(&mut self, solver: Option<SolverKind>) {
+        SimulationImpl::set_solver(self, solver);
+    }
+
+    fn memory_usage(&self) -> usize {
+        SimulationImpl::memory_usage(self)
+    }
+
+    fn dump_tree(&self, path: &Path) -> anyhow::Result<bool> {
+        SimulationImpl::dump_tree(self, path)
+    }
+
+    fn set_time(&mut self, time: SimTime) {
+        SimulationImpl::set_time(self, time);
+    }
+}
+
+/// Simulation summing the accelerations from a stack of force providers, usually
+/// starting with gravity.
+pub struct ForcesSim {
+    pub providers: Vec<Box<dyn ForceProvider>>,
+}
+
+impl ForcesSim {
+    pub fn new(providers: Vec<Box<dyn ForceProvider>>) -> Self {
+        Self { providers }
+    }
+
+    pub fn push(&mut self, provider: impl ForceProvider + 'static) {
+        self.providers.push(Box::new(provider));
+    }
+}
+
+impl SimulationImpl for ForcesSim {
+    fn iter(&mut self, objects: &mut [ObjectInfo], out_buffer: &mut [Vector3<f64>]) {
+        for provider in &mut self.providers {
+            provider.add_acc(objects, out_buffer);
+        }
+    }
+
+    fn iter_single_threaded(
+        &mut self,
+        objects: &mut [ObjectInfo],
+        out_buffer: &mut [Vector3<f64>],
+    ) {
+        for provider in &mut self.providers {
+            provider.add_acc_single_threaded(objects, out_buffer);
+        }
+    }
+
+    fn set_theta(&mut self, theta: f64) {
+        for provider in &mut self.providers {
+            provider.set_theta(theta);
+        }
+    }
+
+    fn set_solver(&mut self, solver: Option<SolverKind>) {
+        for provider in &mut self.providers {
+            provider.set_solver(solver);
+        }
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.providers.iter().map(|p| p.memory_usage()).sum()
+    }
+
+    fn dump_tree(&self, path: &Path) -> anyhow::Result<bool> {
+        for provider in &self.providers {
+            if provider.dump_tree(path)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn set_time(&mut self, time: SimTime) {
+        for provider in &mut self.providers {
+            provider.set_time(time);
+        }
+    }
+}
+
+/// First order post-Newtonian correction to the gravity of massive bodies.
+#[derive(Default)]
+pub struct PostNewtonianForce {
+    sources: Vec<usize>,
+}
+
+impl PostNewtonianForce {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ForceProvider for PostNewtonianForce {
+    fn add_acc(&mut self, objects: &mut [ObjectInfo], out: &mut [Vector3<f64>]) {
+        post_newtonian::iter(objects, out, &mut self.sources);
+    }
+
+    fn add_acc_single_threaded(&mut self, objects: &mut [ObjectInfo], out: &mut [Vector3<f64>]) {
+        post_newtonian::iter_single_threaded(objects, out, &mut self.sources);
+    }
+}
+
+/// J2 perturbation from oblate bodies.
+pub struct OblatenessForce {
+    pub bodies: Vec<OblateBody>,
+}
+
+impl OblatenessForce {
+    pub fn new(bodies: Vec<OblateBody>) -> Self {
+        Self { bodies }
+    }
+}
+
+impl ForceProvider for OblatenessForce {
+    fn add_acc(&mut self, objects: &mut [ObjectInfo], out: &mut [Vector3<f64>]) {
+        oblateness::iter(objects, out, &self.bodies);
+    }
+
+    fn add_acc_single_threaded(&mut self, objects: &mut [ObjectInfo], out: &mut [Vector3<f64>]) {
+        oblateness::iter_single_threaded(objects, out, &self.bodies);
+    }
+}
+
+/// Radiation pressure and the Yarkovsky effect.
+pub struct RadiationForce {
+    pub sources: Vec<RadiationSource>,
+    pub targets: Vec<RadiationTarget>,
+}
+
+impl RadiationForce {
+    pub fn new(sources: Vec<RadiationSource>, targets: Vec<RadiationTarget>) -> Self {
+        Self { sources, targets }
+    }
+}
+
+impl ForceProvider for RadiationForce {
+    fn add_acc(&mut self, objects: &mut [ObjectInfo], out: &mut [Vector3<f64>]) {
+        radiation::iter(objects, out, &self.sources, &self.targets);
+    }
+
+    fn add_acc_single_threaded(&mut self, objects: &mut [ObjectInfo], out: &mut [Vector3<f64>]) {
+        radiation::iter_single_threaded(objects, out, &self.sources, &self.targets);
+    }
+}
+
+/// Scripted thrust of spacecraft.
+pub struct ThrustForce {
+    pub spacecraft: Vec<Spacecraft>,
+    time: SimTime,
+}
+
+impl ThrustForce {
+    pub fn new(spacecraft: Vec<Spacecraft>) -> Self {
+        Self {
+            spacecraft,
+            time: SimTime::default(),
+        }
+    }
+}
+
+impl ForceProvider for ThrustForce {
+    fn add_acc(&mut self, objects: &mut [ObjectInfo], out: &mut [Vector3<f64>]) {
+        thrust::iter(objects, out, &self.spacecraft, self.time);
+    }
+
+    fn add_acc_single_threaded(&mut self, objects: &mut [ObjectInfo], out: &mut [Vector3<f64>]) {
+        thrust::iter(objects, out, &self.spacecraft, self.time);
+    }
+
+    fn set_time(&mut self, time: SimTime) {
+        self.time = time;
+    }
+}
+
+/// Optional additions to Newtonian gravity.
+#[derive(Debug, Clone, Default)]
+pub struct Perturbations {
+    /// Add the first order post-Newtonian correction.
+    pub post_newtonian: bool,
+    /// Bodies with a J2 term.
+    pub oblate: Vec<OblateBody>,
+    /// Bodies emitting light.
+    pub radiation_sources: Vec<RadiationSource>,
+    /// Bodies pushed by light.
+    pub radiation_targets: Vec<RadiationTarget>,
+    /// Static potentials acting on every object, like a dark matter halo.
+    pub potentials: Vec<AnalyticPotential>,
+    /// Objects with scripted thrust.
+    pub spacecraft: Vec<Spacecraft>,
+    /// Wrap space into a periodic box. Not a force as such, but it changes how gravity
+    /// is computed, and only applies to gravity.
+    pub periodic: Option<PeriodicBox>,
+    /// Propagate isolated binaries analytically. Not a force either, but it changes how
+    /// the objects are integrated. With `None`, only two body systems are, see
+    /// `KeplerPairs::two_body`, unless `no_kepler` is set.
+    pub kepler: Option<KeplerPairs>,
+    /// Integrate two body systems like any other when `kepler` is `None`.
+    pub no_kepler: bool,
+    /// Integrate with an adaptive Dormand-Prince method with this tolerance, instead of
+    /// symplectic Euler.
+    pub rk45: Option<f64>,
+    /// Integrate with the fourth order Hermite scheme with this accuracy parameter. Only
+    /// supports Newtonian gravity, see `adds_forces`.
+    pub hermite: Option<f64>,
+    /// Keep the Barnes-Hut tree between steps, only rebuilding the parts objects have
+    /// moved out of. Only changes how gravity is computed.
+    pub incremental_tree: bool,
+}
+
+impl Perturbations {
+    /// Collect the perturbations described by the objects in a scenario.
+    pub fn from_objects(objects: &[Object], post_newtonian: bool) -> Self {
+        let mut res = Self {
+            post_newtonian,
+            ..Default::default()
+        };
+        for (index, obj) in objects.iter().enumerate() {
+            if let Some(shape) = &obj.oblateness {
+                res.oblate.push(OblateBody {
+                    index,
+                    shape: shape.clone(),
+                });
+            }
+            match &obj.radiation {
+                Some(Radiation::Source { luminosity }) => {
+                    res.radiation_sources.push(RadiationSource {
+                        index,
+                        luminosity: *luminosity,
+                    })
+                }
+                // Massless objects are only tracers, they would be accelerated infinitely.
+                Some(Radiation::Target {
+                    reflectivity,
+                    yarkovsky,
+                }) if obj.dat.mass > 0.0 => {
+                    let radius = obj.radius as f64 * AU;
+                    let area = std::f64::consts::PI * radius * radius;
+                    res.radiation_targets.push(RadiationTarget {
+                        index,
+                        pressure: reflectivity * area / (obj.dat.mass * M0),
+                        yarkovsky: *yarkovsky,
+                    });
+                }
+                _ => (),
+            }
+            if !obj.maneuvers.is_empty() {
+                res.spacecraft.push(Spacecraft {
+                    index,
+                    maneuvers: obj.maneuvers.clone(),
+                });
+            }
+        }
+        res
+    }
+
+    /// Whether any force is added on top of Newtonian gravity.
+    pub fn adds_forces(&self) -> bool {
+        self.post_newtonian
+            || !self.oblate.is_empty()
+            || (!self.radiation_sources.is_empty() && !self.radiation_targets.is_empty())
+            || !self.potentials.is_empty()
+            || !self.spacecraft.is_empty()
+    }
+
+    /// Force providers for the perturbations, to be added after gravity.
+    pub fn into_providers(self) -> Vec<Box<dyn ForceProvider>> {
+        let mut providers: Vec<Box<dyn ForceProvider>> = Vec::new();
+        if !self.oblate.is_empty() {
+            providers.push(Box::new(OblatenessForce::new(self.oblate)));
+        }
+        if !self.radiation_sources.is_empty() && !self.radiation_targets.is_empty() {
+            providers.push(Box::new(RadiationForce::new(
+                self.radiation_sources,
+                self.radiation_targets,
+            )));
+        }
+        for potential in self.potentials {
+            providers.push(Box::new(potential));
+        }
+        if !self.spacecraft.is_empty() {
+            providers.push(Box::new(ThrustForce::new(self.spacecraft)));
+        }
+        if self.post_newtonian {
+            providers.push(Box::new(PostNewtonianForce::new()));
+        }
+        providers
+    }
+}
+
+#[derive(Default)]
+pub struct BruteForceSim {
+    massive: MassiveIndices,
+}
+
+impl BruteForceSim {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
 
 impl SimulationImpl for BruteForceSim {
     fn iter(&mut self, objects: &mut [ObjectInfo], out_buffer: &mut [Vector3<f64>]) {
-        direct::iter(objects, out_buffer);
+        let massive = self.massive.update(objects);
+        direct::iter(objects, massive, out_buffer, None);
+    }
+
+    fn iter_single_threaded(
+        &mut self,
+        objects: &mut [ObjectInfo],
+        out_buffer: &mut [Vector3<f64>],
+    ) {
+        let massive = self.massive.update(objects);
+        direct::iter_single_threaded(objects, massive, out_buffer, None);
+    }
+}
+
+/// Brute force in a periodic box, see `PeriodicBox`.
+pub struct PeriodicBruteForceSim {
+    pub bounds: PeriodicBox,
+    massive: MassiveIndices,
+}
+
+impl PeriodicBruteForceSim {
+    pub fn new(bounds: PeriodicBox) -> Self {
+        Self {
+            bounds,
+            massive: MassiveIndices::default(),
+        }
+    }
+}
+
+impl SimulationImpl for PeriodicBruteForceSim {
+    fn iter(&mut self, objects: &mut [ObjectInfo], out_buffer: &mut [Vector3<f64>]) {
+        let massive = self.massive.update(objects);
+        direct::iter(objects, massive, out_buffer, Some(&self.bounds));
     }
 
     fn iter_single_threaded(
@@ -115,15 +917,61 @@ impl SimulationImpl for BruteForceSim {
         objects: &mut [ObjectInfo],
         out_buffer: &mut [Vector3<f64>],
     ) {
-        direct::iter_single_threaded(objects, out_buffer);
+        let massive = self.massive.update(objects);
+        direct::iter_single_threaded(objects, massive, out_buffer, Some(&self.bounds));
+    }
+}
+
+/// Brute force that evaluates each pair of massive objects once, see
+/// `direct::iter_symmetric`.
+#[derive(Default)]
+pub struct SymmetricBruteForceSim {
+    pub periodic: Option<PeriodicBox>,
+    massive: MassiveIndices,
+}
+
+impl SymmetricBruteForceSim {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use the minimum image convention in `bounds`. The objects must be kept inside it.
+    pub fn with_periodic_box(mut self, bounds: PeriodicBox) -> Self {
+        self.periodic = Some(bounds);
+        self
+    }
+}
+
+impl SimulationImpl for SymmetricBruteForceSim {
+    fn iter(&mut self, objects: &mut [ObjectInfo], out_buffer: &mut [Vector3<f64>]) {
+        let massive = self.massive.update(objects);
+        direct::iter_symmetric(objects, massive, out_buffer, self.periodic.as_ref());
+    }
+
+    fn iter_single_threaded(
+        &mut self,
+        objects: &mut [ObjectInfo],
+        out_buffer: &mut [Vector3<f64>],
+    ) {
+        let massive = self.massive.update(objects);
+        direct::iter_symmetric_single_threaded(
+            objects,
+            massive,
+            out_buffer,
+            self.periodic.as_ref(),
+        );
     }
 }
 
 pub struct ObjectBuffer<R> {
     pub objects: Vec<ObjectInfo>,
     out_buffer: Vec<Vector3<f64>>,
-    pool: ThreadPool,
+    threads: ThreadScaler,
     simulation: R,
+    periodic: Option<PeriodicBox>,
+    kepler: Option<KeplerPairs>,
+    rk45: Option<DormandPrince>,
+    hermite: Option<Hermite>,
 }
 
 const SEC_PER_HOUR: f64 = 60.0 * 60.0;
@@ -150,9 +998,85 @@ impl Display for ElapsedTime {
     }
 }
 
+/// Point in simulated time, counted in simulation ticks, the latest of which are `delta`
+/// seconds long.
+///
+/// This is also the clock of a running simulation. The seconds are added up tick by tick,
+/// so that they stay right when the time step changes during a run, and every frontend
+/// and recording shows the same time.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SimTime {
+    pub ticks: u64,
+    pub delta: f64,
+    /// Simulated seconds since the start.
+    pub seconds: f64,
+}
+
+impl SimTime {
+    /// Time after `ticks` ticks that were all `delta` seconds long.
+    pub fn new(ticks: u64, delta: f64) -> Self {
+        Self {
+            ticks,
+            delta,
+            seconds: ticks as f64 * delta,
+        }
+    }
+
+    /// Simulated time in seconds.
+    pub fn seconds(&self) -> f64 {
+        self.seconds
+    }
+
+    /// Move on by one tick of `delta`.
+    pub fn advance(&mut self) {
+        self.ticks += 1;
+        self.seconds += self.delta;
+    }
+
+    /// Go back by one tick of `delta`, the inverse of `advance`. Panics at tick 0.
+    pub fn rewind(&mut self) {
+        *self = self.previous();
+    }
+
+    /// Time one tick of `delta` earlier. Panics at tick 0, since there is no time before the
+    /// start of a run to go back to.
+    pub fn previous(&self) -> Self {
+        Self {
+            ticks: self
+                .ticks
+                .checked_sub(1)
+                .expect("Cannot go back from the start of a run"),
+            delta: self.delta,
+            seconds: self.seconds - self.delta,
+        }
+    }
+
+    pub fn elapsed(&self) -> ElapsedTime {
+        elapsed_from_seconds(self.seconds, self.ticks as f64)
+    }
+}
+
+/// Number of frames rendered by the viewer. This is unrelated to simulated time, which
+/// may advance by any number of ticks between two frames.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FrameTime(pub u32);
+
+impl FrameTime {
+    pub fn advance(&mut self) {
+        self.0 = self.0.wrapping_add(1);
+    }
+
+    /// Whether this is one of every `n` frames.
+    pub fn every(&self, n: u32) -> bool {
+        self.0.is_multiple_of(n)
+    }
+}
+
 pub fn compute_elapsed_time(ticks: f64, delta: f64) -> ElapsedTime {
-    let mut time_s = ticks * delta;
+    elapsed_from_seconds(ticks * delta, ticks)
+}
 
+fn elapsed_from_seconds(mut time_s: f64, ticks: f64) -> ElapsedTime {
     let years = (time_s / SEC_PER_YEAR).floor();
     time_s -= years * SEC_PER_YEAR;
     let days = (time_s / SEC_PER_DAY).floor();
@@ -0,0 +1,96 @@
+//! Zonal harmonic (J2) perturbation from oblate bodies.
+//!
+//! A rotating body bulges at the equator, which adds a quadrupole term to its gravity.
+//! The main effect on anything orbiting it is that inclined orbits precess around the
+//! body's pole, like the nodal regression of satellites around the earth.
+
+use cgmath::{InnerSpace, Vector3};
+use rayon::iter::{
+    IndexedParallelIterator, IntoParallelRefIterator, IntoParallelRefMutIterator, ParallelIterator,
+};
+
+use crate::{constants::G, sim::ObjectInfo};
+
+/// Shape of an oblate body.
+#[derive(Debug, Clone)]
+pub struct Oblateness {
+    /// Second zonal harmonic coefficient, 1.083e-3 for the earth.
+    pub j2: f64,
+    /// In AU
+    pub equatorial_radius: f64,
+    /// Direction of the rotation axis.
+    pub pole: Vector3<f64>,
+}
+
+/// Oblate body in a running simulation.
+#[derive(Debug, Clone)]
+pub struct OblateBody {
+    /// Index of the body among the simulated objects.
+    pub index: usize,
+    pub shape: Oblateness,
+}
+
+/// Acceleration from the J2 term of `body` on `obj`. The expansion is not valid inside
+/// the body, so nothing is added there.
+fn j2_acc(obj: &ObjectInfo, body: &ObjectInfo, shape: &Oblateness) -> Vector3<f64> {
+    let rel = obj.pos - body.pos;
+    let dist_sq = rel.magnitude2();
+    let radius_sq = shape.equatorial_radius * shape.equatorial_radius;
+    if dist_sq < radius_sq {
+        return Vector3::new(0.0, 0.0, 0.0);
+    }
+    let pole = shape.pole.normalize();
+    let z = rel.dot(pole);
+    let factor = -1.5 * shape.j2 * G * body.gravitating_mass() * radius_sq
+        / (dist_sq * dist_sq * dist_sq.sqrt());
+    (rel * (1.0 - 5.0 * z * z / dist_sq) + pole * (2.0 * z)) * factor
+}
+
+/// Acceleration of `body` from the equal and opposite force on everything it perturbs.
+fn reaction(objects: &[ObjectInfo], body: &OblateBody) -> Vector3<f64> {
+    let center = &objects[body.index];
+    let momentum: Vector3<f64> = objects
+        .par_iter()
+        .enumerate()
+        .filter(|(idx, obj)| *idx != body.index && !obj.is_frozen())
+        .map(|(_, obj)| j2_acc(obj, center, &body.shape) * obj.gravitating_mass())
+        .sum();
+    -momentum / center.gravitating_mass()
+}
+
+pub fn iter(objects: &[ObjectInfo], out: &mut [Vector3<f64>], bodies: &[OblateBody]) {
+    for body in bodies {
+        // Test particles and frozen bodies don't perturb anything.
+        if objects[body.index].gravitating_mass() == 0.0 {
+            continue;
+        }
+        out.par_iter_mut().enumerate().for_each(|(idx, out)| {
+            if idx != body.index && !objects[idx].is_frozen() {
+                *out += j2_acc(&objects[idx], &objects[body.index], &body.shape);
+            }
+        });
+        out[body.index] += reaction(objects, body);
+    }
+}
+
+pub fn iter_single_threaded(
+    objects: &[ObjectInfo],
+    out: &mut [Vector3<f64>],
+    bodies: &[OblateBody],
+) {
+    for body in bodies {
+        let center = &objects[body.index];
+        if center.gravitating_mass() == 0.0 {
+            continue;
+        }
+        let mut momentum = Vector3::new(0.0, 0.0, 0.0);
+        for (idx, (obj, out)) in objects.iter().zip(out.iter_mut()).enumerate() {
+            if idx != body.index && !obj.is_frozen() {
+                let acc = j2_acc(obj, center, &body.shape);
+                *out += acc;
+                momentum += acc * obj.gravitating_mass();
+            }
+        }
+        out[body.index] -= momentum / center.gravitating_mass();
+    }
+}
@@ -0,0 +1,48 @@
+//! Periodic boundary conditions.
+//!
+//! Space is tiled with copies of a cubic box centered on the origin, so objects leaving
+//! through one face come back through the opposite one, and a homogeneous cloud has no
+//! edge to collapse from. Gravity follows the minimum image convention: each object is
+//! only attracted by the nearest copy of every other object. There is no Ewald sum for
+//! the copies further out, so this works best for clouds that are roughly uniform on
+//! the scale of the box.
+
+use cgmath::{Point3, Vector3};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PeriodicBox {
+    /// Length of each side, in AU.
+    pub size: f64,
+}
+
+impl PeriodicBox {
+    pub fn new(size: f64) -> Self {
+        Self { size }
+    }
+
+    /// Shift `x` by a whole number of box sizes, into `[-size / 2, size / 2]`.
+    #[inline]
+    fn nearest(&self, x: f64) -> f64 {
+        x - self.size * (x / self.size).round()
+    }
+
+    /// The copy of `pos` inside the box.
+    #[inline]
+    pub fn wrap(&self, pos: Point3<f64>) -> Point3<f64> {
+        Point3::new(
+            self.nearest(pos.x),
+            self.nearest(pos.y),
+            self.nearest(pos.z),
+        )
+    }
+
+    /// Shortest offset between any copies of two points `rel` apart.
+    #[inline]
+    pub fn min_image(&self, rel: Vector3<f64>) -> Vector3<f64> {
+        Vector3::new(
+            self.nearest(rel.x),
+            self.nearest(rel.y),
+            self.nearest(rel.z),
+        )
+    }
+}
@@ -0,0 +1,75 @@
+//! First order post-Newtonian (1PN) correction to gravity.
+//!
+//! Uses the test-particle limit of the Einstein-Infeld-Hoffmann equations, which is
+//! enough to reproduce the relativistic perihelion precession of planets, about 43
+//! arcseconds per century for Mercury. Only bodies of at least
+//! [`POST_NEWTONIAN_MIN_MASS`] act as sources, since the correction from anything
+//! lighter is far below the error of the Newtonian approximation itself.
+
+use cgmath::{InnerSpace, Vector3};
+use rayon::iter::{IndexedParallelIterator, IntoParallelRefMutIterator, ParallelIterator};
+
+use crate::{
+    constants::{C, G, POST_NEWTONIAN_MIN_MASS},
+    sim::ObjectInfo,
+};
+
+fn correction(obj: &ObjectInfo, source: &ObjectInfo) -> Vector3<f64> {
+    let rel = obj.pos - source.pos;
+    let vel = obj.vel - source.vel;
+    let dist_sq = rel.magnitude2();
+    if dist_sq == 0.0 {
+        return Vector3::new(0.0, 0.0, 0.0);
+    }
+    let dist = dist_sq.sqrt();
+    let gm = G * source.gravitating_mass();
+    (rel * (4.0 * gm / dist - vel.magnitude2()) + vel * (4.0 * rel.dot(vel)))
+        * (gm / (C * C * dist_sq * dist))
+}
+
+fn find_sources(objects: &[ObjectInfo], sources: &mut Vec<usize>) {
+    sources.clear();
+    sources.extend(
+        objects
+            .iter()
+            .enumerate()
+            .filter(|(_, obj)| {
+                !obj.is_frozen() && obj.gravitating_mass() >= POST_NEWTONIAN_MIN_MASS
+            })
+            .map(|(idx, _)| idx),
+    );
+}
+
+pub fn iter(objects: &[ObjectInfo], out: &mut [Vector3<f64>], sources: &mut Vec<usize>) {
+    find_sources(objects, sources);
+    if sources.is_empty() {
+        return;
+    }
+
+    out.par_iter_mut().enumerate().for_each(|(idx, out)| {
+        // Frozen bodies stay where they are.
+        if objects[idx].is_frozen() {
+            return;
+        }
+        for source in sources.iter().filter(|s| **s != idx) {
+            *out += correction(&objects[idx], &objects[*source]);
+        }
+    });
+}
+
+pub fn iter_single_threaded(
+    objects: &[ObjectInfo],
+    out: &mut [Vector3<f64>],
+    sources: &mut Vec<usize>,
+) {
+    find_sources(objects, sources);
+
+    for (idx, out) in out.iter_mut().enumerate() {
+        if objects[idx].is_frozen() {
+            continue;
+        }
+        for source in sources.iter().filter(|s| **s != idx) {
+            *out += correction(&objects[idx], &objects[*source]);
+        }
+    }
+}
@@ -0,0 +1,141 @@
+//! Static analytic potentials.
+//!
+//! Galaxies are dominated by their dark matter halo and stellar disk, which would take
+//! millions of particles to represent directly. Instead, they can be modelled as fixed
+//! potentials acting on every simulated object, with only the interesting bodies
+//! simulated as particles.
+
+use cgmath::{InnerSpace, Point3, Vector3};
+use rayon::iter::{
+    IndexedParallelIterator, IntoParallelRefIterator, IntoParallelRefMutIterator, ParallelIterator,
+};
+
+use crate::{
+    constants::G,
+    sim::{ForceProvider, ObjectInfo},
+};
+
+/// Fixed potential not made of simulated objects, in AU and earth masses.
+#[derive(Debug, Clone)]
+pub enum AnalyticPotential {
+    /// Navarro-Frenk-White profile, the usual model of dark matter halos.
+    Nfw {
+        center: Point3<f64>,
+        /// Characteristic mass, `4 pi rho_0 r_s^3`. The enclosed mass grows without bound,
+        /// roughly as `mass * ln(r / scale_radius)`.
+        mass: f64,
+        scale_radius: f64,
+    },
+    /// Miyamoto-Nagai potential of a flattened disk.
+    MiyamotoNagai {
+        center: Point3<f64>,
+        /// Normal of the plane of the disk.
+        normal: Vector3<f64>,
+        mass: f64,
+        /// Radial scale length.
+        scale_length: f64,
+        /// Vertical scale height.
+        scale_height: f64,
+    },
+}
+
+impl AnalyticPotential {
+    /// Gravitational potential at `pos`, in AU^2/s^2.
+    pub fn potential(&self, pos: Point3<f64>) -> f64 {
+        match self {
+            AnalyticPotential::Nfw {
+                center,
+                mass,
+                scale_radius,
+            } => {
+                let r = (pos - center).magnitude();
+                if r == 0.0 {
+                    return -G * mass / scale_radius;
+                }
+                -G * mass * (1.0 + r / scale_radius).ln() / r
+            }
+            AnalyticPotential::MiyamotoNagai {
+                center,
+                normal,
+                mass,
+                scale_length,
+                scale_height,
+            } => {
+                let (in_plane, z) = split(pos - center, *normal);
+                let zeta = (z * z + scale_height * scale_height).sqrt();
+                -G * mass / (in_plane.magnitude2() + (scale_length + zeta).powi(2)).sqrt()
+            }
+        }
+    }
+
+    /// Acceleration at `pos`, in AU/s^2.
+    pub fn acceleration(&self, pos: Point3<f64>) -> Vector3<f64> {
+        match self {
+            AnalyticPotential::Nfw {
+                center,
+                mass,
+                scale_radius,
+            } => {
+                let rel = pos - center;
+                let r = rel.magnitude();
+                if r == 0.0 {
+                    return Vector3::new(0.0, 0.0, 0.0);
+                }
+                let x = r / scale_radius;
+                let enclosed = mass * ((1.0 + x).ln() - x / (1.0 + x));
+                -rel * (G * enclosed / (r * r * r))
+            }
+            AnalyticPotential::MiyamotoNagai {
+                center,
+                normal,
+                mass,
+                scale_length,
+                scale_height,
+            } => {
+                let normal = normal.normalize();
+                let (in_plane, z) = split(pos - center, normal);
+                let zeta = (z * z + scale_height * scale_height).sqrt();
+                let denom = (in_plane.magnitude2() + (scale_length + zeta).powi(2)).powf(1.5);
+                -(in_plane + normal * (z * (scale_length + zeta) / zeta)) * (G * mass / denom)
+            }
+        }
+    }
+
+    /// Speed of a circular orbit in the plane through the center with normal `normal`,
+    /// at distance `radius`. Only exact for orbits in the plane of a disk.
+    pub fn circular_speed(&self, radius: f64, normal: Vector3<f64>) -> f64 {
+        let center = match self {
+            AnalyticPotential::Nfw { center, .. }
+            | AnalyticPotential::MiyamotoNagai { center, .. } => *center,
+        };
+        let helper = if normal.x.abs() < 0.9 {
+            Vector3::unit_x()
+        } else {
+            Vector3::unit_y()
+        };
+        let dir = normal.cross(helper).normalize();
+        let acc = self.acceleration(center + dir * radius);
+        (radius * -acc.dot(dir)).max(0.0).sqrt()
+    }
+}
+
+/// Split `rel` into the part in the plane with normal `normal`, and the height above it.
+fn split(rel: Vector3<f64>, normal: Vector3<f64>) -> (Vector3<f64>, f64) {
+    let normal = normal.normalize();
+    let z = rel.dot(normal);
+    (rel - normal * z, z)
+}
+
+impl ForceProvider for AnalyticPotential {
+    fn add_acc(&mut self, objects: &mut [ObjectInfo], out: &mut [Vector3<f64>]) {
+        out.par_iter_mut()
+            .zip(objects.par_iter())
+            .for_each(|(acc, obj)| *acc += self.acceleration(obj.pos));
+    }
+
+    fn add_acc_single_threaded(&mut self, objects: &mut [ObjectInfo], out: &mut [Vector3<f64>]) {
+        for (acc, obj) in out.iter_mut().zip(objects.iter()) {
+            *acc += self.acceleration(obj.pos);
+        }
+    }
+}
@@ -0,0 +1,124 @@
+//! Non-gravitational forces from sunlight.
+//!
+//! Light from luminous bodies pushes on everything it hits, with an acceleration
+//! proportional to the area over mass of the target. This is negligible for planets,
+//! but over long runs it noticeably changes the orbits of small bodies. Rotating bodies
+//! also re-emit the absorbed heat unevenly, which gives a small push along the orbit,
+//! the Yarkovsky effect. It is modelled the usual way, as a transverse acceleration
+//! falling off with the square of the distance.
+
+use cgmath::{InnerSpace, Vector3};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+
+use crate::{
+    constants::{AU, C},
+    sim::ObjectInfo,
+};
+
+/// How an object takes part in radiation forces.
+#[derive(Debug, Clone)]
+pub enum Radiation {
+    /// Emits light, in watts.
+    Source { luminosity: f64 },
+    /// Is pushed by light from sources.
+    Target {
+        /// Radiation pressure coefficient, 1 for a black body and 2 for a perfect mirror.
+        reflectivity: f64,
+        /// Transverse acceleration at 1 AU from a source, in m/s^2. Positive for
+        /// bodies rotating in the same direction as they orbit.
+        yarkovsky: f64,
+    },
+}
+
+/// Luminous body in a running simulation.
+#[derive(Debug, Clone)]
+pub struct RadiationSource {
+    pub index: usize,
+    /// In watts
+    pub luminosity: f64,
+}
+
+/// Body pushed by radiation in a running simulation.
+#[derive(Debug, Clone)]
+pub struct RadiationTarget {
+    pub index: usize,
+    /// Reflectivity times cross section over mass, in m^2/kg.
+    pub pressure: f64,
+    /// In m/s^2 at 1 AU
+    pub yarkovsky: f64,
+}
+
+fn radiation_acc(
+    obj: &ObjectInfo,
+    target: &RadiationTarget,
+    source: &ObjectInfo,
+    luminosity: f64,
+) -> Vector3<f64> {
+    let rel = obj.pos - source.pos;
+    let dist_sq = rel.magnitude2();
+    if dist_sq == 0.0 {
+        return Vector3::new(0.0, 0.0, 0.0);
+    }
+    let dir = rel / dist_sq.sqrt();
+    // Both accelerations are computed in m/s^2, then converted to AU/s^2.
+    let dist_m_sq = dist_sq * AU * AU;
+    let pressure = luminosity * target.pressure / (4.0 * std::f64::consts::PI * C * AU * dist_m_sq);
+    let mut acc = dir * (pressure / AU);
+
+    if target.yarkovsky != 0.0 {
+        // Direction of motion, perpendicular to the source.
+        let transverse = rel.cross(obj.vel - source.vel).cross(rel);
+        if transverse.magnitude2() > 0.0 {
+            acc += transverse.normalize() * (target.yarkovsky / dist_sq / AU);
+        }
+    }
+    acc
+}
+
+fn target_acc(
+    objects: &[ObjectInfo],
+    target: &RadiationTarget,
+    sources: &[RadiationSource],
+) -> Vector3<f64> {
+    sources
+        .iter()
+        .filter(|s| s.index != target.index)
+        .map(|s| {
+            radiation_acc(
+                &objects[target.index],
+                target,
+                &objects[s.index],
+                s.luminosity,
+            )
+        })
+        .sum()
+}
+
+pub fn iter(
+    objects: &[ObjectInfo],
+    out: &mut [Vector3<f64>],
+    sources: &[RadiationSource],
+    targets: &[RadiationTarget],
+) {
+    if sources.is_empty() {
+        return;
+    }
+    let acc: Vec<_> = targets
+        .par_iter()
+        .map(|t| target_acc(objects, t, sources))
+        .collect();
+    for (target, acc) in targets.iter().zip(acc) {
+        out[target.index] += acc;
+    }
+}
+
+pub fn iter_single_threaded(
+    objects: &[ObjectInfo],
+    out: &mut [Vector3<f64>],
+    sources: &[RadiationSource],
+    targets: &[RadiationTarget],
+) {
+    for target in targets {
+        out[target.index] += target_acc(objects, target, sources);
+    }
+}
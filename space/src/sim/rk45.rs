@@ -0,0 +1,255 @@
+//! Adaptive Dormand-Prince integrator.
+//!
+//! An alternative to the fixed step symplectic Euler integrator, for scenarios where
+//! accuracy matters more than long term stability, like close encounters. Each tick
+//! still advances the simulation by exactly `delta`, but in as many internal steps as
+//! the embedded error estimate asks for. The step size carries over between ticks, so
+//! a tick longer than the step needs is split up, and a quiet stretch is crossed in one
+//! step per tick.
+//!
+//! Uses the RK5(4)7M coefficients from Dormand & Prince (1980), with the first same as
+//! last property, so an accepted step costs six force evaluations.
+
+use cgmath::{EuclideanSpace, InnerSpace, Vector3, Zero};
+use rayon::iter::{
+    IndexedParallelIterator, IntoParallelRefIterator, IntoParallelRefMutIterator, ParallelIterator,
+};
+
+use crate::sim::{ObjectInfo, SimulationImpl};
+
+const STAGES: usize = 7;
+
+const A: [[f64; STAGES - 1]; STAGES] = [
+    [0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+    [1.0 / 5.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+    [3.0 / 40.0, 9.0 / 40.0, 0.0, 0.0, 0.0, 0.0],
+    [44.0 / 45.0, -56.0 / 15.0, 32.0 / 9.0, 0.0, 0.0, 0.0],
+    [
+        19372.0 / 6561.0,
+        -25360.0 / 2187.0,
+        64448.0 / 6561.0,
+        -212.0 / 729.0,
+        0.0,
+        0.0,
+    ],
+    [
+        9017.0 / 3168.0,
+        -355.0 / 33.0,
+        46732.0 / 5247.0,
+        49.0 / 176.0,
+        -5103.0 / 18656.0,
+        0.0,
+    ],
+    // The fifth order solution, which is also where the last stage is evaluated.
+    [
+        35.0 / 384.0,
+        0.0,
+        500.0 / 1113.0,
+        125.0 / 192.0,
+        -2187.0 / 6784.0,
+        11.0 / 84.0,
+    ],
+];
+
+/// Difference between the fifth and fourth order weights.
+const E: [f64; STAGES] = [
+    71.0 / 57600.0,
+    0.0,
+    -71.0 / 16695.0,
+    71.0 / 1920.0,
+    -17253.0 / 339200.0,
+    22.0 / 525.0,
+    -1.0 / 40.0,
+];
+
+/// Steps shorter than this fraction of a tick are accepted whatever their error, so a
+/// singularity can not stall the simulation.
+const MIN_STEP_FRACTION: f64 = 1e-9;
+
+/// Step size and rejections since the last time they were reported.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct StepStats {
+    /// Length of the last accepted step, in seconds.
+    pub step: f64,
+    /// Fraction of attempted steps that were rejected.
+    pub rejection_rate: f64,
+}
+
+pub struct DormandPrince {
+    /// Largest error allowed in a step, relative to the size of the values.
+    pub tolerance: f64,
+    next_step: Option<f64>,
+    last_step: f64,
+    accepted: u64,
+    rejected: u64,
+    start: Vec<ObjectInfo>,
+    vel: [Vec<Vector3<f64>>; STAGES],
+    acc: [Vec<Vector3<f64>>; STAGES],
+}
+
+impl DormandPrince {
+    pub fn new(tolerance: f64) -> Self {
+        Self {
+            tolerance,
+            next_step: None,
+            last_step: 0.0,
+            accepted: 0,
+            rejected: 0,
+            start: Vec::new(),
+            vel: Default::default(),
+            acc: Default::default(),
+        }
+    }
+
+    /// Step size and rejection rate since the last call.
+    pub fn take_stats(&mut self) -> StepStats {
+        let attempts = self.accepted + self.rejected;
+        let stats = StepStats {
+            step: self.last_step,
+            rejection_rate: if attempts == 0 {
+                0.0
+            } else {
+                self.rejected as f64 / attempts as f64
+            },
+        };
+        self.accepted = 0;
+        self.rejected = 0;
+        stats
+    }
+
+    /// Advance `objects` by `delta` seconds, backwards for negative `delta`.
+    pub fn advance(
+        &mut self,
+        objects: &mut [ObjectInfo],
+        simulation: &mut impl SimulationImpl,
+        delta: f64,
+    ) {
+        let n = objects.len();
+        for buf in self.vel.iter_mut().chain(self.acc.iter_mut()) {
+            buf.resize(n, Vector3::zero());
+        }
+        // Objects may have changed since the last tick, so the first stage is never
+        // carried over from it.
+        self.start.clear();
+        self.start.extend_from_slice(objects);
+        evaluate(simulation, objects, &mut self.vel[0], &mut self.acc[0]);
+
+        let direction = delta.signum();
+        let mut remaining = delta.abs();
+        while remaining > 0.0 {
+            let proposed = self.next_step.unwrap_or(delta.abs());
+            let clipped = proposed >= remaining;
+            let h = proposed.min(remaining);
+            let error = self.try_step(objects, simulation, h * direction);
+            let factor = if error == 0.0 {
+                5.0
+            } else {
+                (0.9 * error.powf(-0.2)).clamp(0.2, 5.0)
+            };
+
+            if error <= 1.0 || h <= delta.abs() * MIN_STEP_FRACTION {
+                self.accepted += 1;
+                self.last_step = h;
+                remaining -= h;
+                self.start.clone_from_slice(objects);
+                // First same as last: the last stage was evaluated at the new state.
+                self.vel.swap(0, STAGES - 1);
+                self.acc.swap(0, STAGES - 1);
+                // A step cut short to end on the tick says little about the next one.
+                if !clipped || factor < 1.0 {
+                    self.next_step = Some(h * factor);
+                }
+            } else {
+                self.rejected += 1;
+                objects.clone_from_slice(&self.start);
+                self.next_step = Some(h * factor);
+            }
+        }
+    }
+
+    /// Take a step of `h` seconds from `self.start`, leaving the result in `objects`.
+    /// Returns the estimated error, relative to the tolerance.
+    fn try_step(
+        &mut self,
+        objects: &mut [ObjectInfo],
+        simulation: &mut impl SimulationImpl,
+        h: f64,
+    ) -> f64 {
+        for (stage, weights) in A.iter().enumerate().skip(1) {
+            let (done, rest) = self.vel.split_at_mut(stage);
+            let (done_acc, rest_acc) = self.acc.split_at_mut(stage);
+            objects
+                .par_iter_mut()
+                .zip(self.start.par_iter())
+                .enumerate()
+                .for_each(|(i, (obj, start))| {
+                    let mut pos = start.pos;
+                    let mut vel = start.vel;
+                    for j in 0..stage {
+                        pos += done[j][i] * (h * weights[j]);
+                        vel += done_acc[j][i] * (h * weights[j]);
+                    }
+                    obj.pos = pos;
+                    obj.vel = vel;
+                });
+            evaluate(simulation, objects, &mut rest[0], &mut rest_acc[0]);
+        }
+        self.error(objects, h)
+    }
+
+    /// Largest error of any moving object, relative to the tolerance. Values are
+    /// compared to their own size, and to the typical size across the system, so that
+    /// an object at rest at the origin does not ask for ever shorter steps.
+    fn error(&self, objects: &[ObjectInfo], h: f64) -> f64 {
+        let moving = self.start.iter().filter(|o| !o.pinned);
+        let count = moving.clone().count().max(1) as f64;
+        let (pos_sq, vel_sq) = moving.fold((0.0, 0.0), |(p, v), o| {
+            (p + o.pos.to_vec().magnitude2(), v + o.vel.magnitude2())
+        });
+        let pos_scale = (pos_sq / count).sqrt();
+        let vel_scale = (vel_sq / count).sqrt();
+
+        let mut error: f64 = 0.0;
+        for (i, (start, end)) in self.start.iter().zip(objects).enumerate() {
+            if start.pinned {
+                continue;
+            }
+            let mut pos_err = Vector3::zero();
+            let mut vel_err = Vector3::zero();
+            for ((vel, acc), weight) in self.vel.iter().zip(&self.acc).zip(E) {
+                pos_err += vel[i] * (h * weight);
+                vel_err += acc[i] * (h * weight);
+            }
+            let pos_size = pos_scale
+                + start
+                    .pos
+                    .to_vec()
+                    .magnitude()
+                    .max(end.pos.to_vec().magnitude());
+            let vel_size = vel_scale + start.vel.magnitude().max(end.vel.magnitude());
+            error = error
+                .max(pos_err.magnitude() / (self.tolerance * pos_size))
+                .max(vel_err.magnitude() / (self.tolerance * vel_size));
+        }
+        error
+    }
+}
+
+/// Velocity and acceleration of every object, zero for pinned ones.
+fn evaluate(
+    simulation: &mut impl SimulationImpl,
+    objects: &mut [ObjectInfo],
+    vel: &mut [Vector3<f64>],
+    acc: &mut [Vector3<f64>],
+) {
+    acc.iter_mut().for_each(|a| *a = Vector3::zero());
+    simulation.iter(objects, acc);
+    for ((obj, vel), acc) in objects.iter().zip(vel).zip(acc) {
+        if obj.pinned {
+            *vel = Vector3::zero();
+            *acc = Vector3::zero();
+        } else {
+            *vel = obj.vel;
+        }
+    }
+}
@@ -0,0 +1,162 @@
+//! Choosing how many threads a simulation uses while it runs.
+//!
+//! More threads only help while there is enough work to split between them, and past
+//! that they just keep cores busy. The scaler times steps with the current number of
+//! threads, now and then tries one more and one fewer, and settles on the fewest threads
+//! that are about as fast as the best it has seen. A CPU budget caps the number of
+//! threads to a share of the cores, to keep the machine responsive during long runs.
+//!
+//! The number of threads can also be fixed by hand, which turns all of this off. Fixed
+//! counts are not held to `MAX_THREADS` or the budget, so that big machines can be used
+//! fully.
+
+use std::time::Duration;
+
+use rayon::{ThreadPool, ThreadPoolBuilder};
+
+use crate::constants::{
+    MAX_THREADS, OBJECTS_PER_THREAD, SIM_THREAD_NAME, THREAD_PROBE_INTERVAL,
+    THREAD_SCALING_TOLERANCE, THREAD_SCALING_WINDOW,
+};
+
+/// Number of threads to start with for `n_objects` objects.
+fn compute_target_threads(n_objects: usize) -> usize {
+    n_objects.div_ceil(OBJECTS_PER_THREAD).clamp(1, MAX_THREADS)
+}
+
+fn build_pool(threads: usize) -> ThreadPool {
+    ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .thread_name(|i| format!("{SIM_THREAD_NAME}-{i}"))
+        .build()
+        .unwrap()
+}
+
+pub struct ThreadScaler {
+    pool: ThreadPool,
+    threads: usize,
+    /// Share of the cores of the machine that may be used, in (0, 1].
+    budget: f64,
+    cores: usize,
+    /// Number of threads set by hand, instead of scaling them.
+    fixed: Option<usize>,
+    /// Average step time with each number of threads, indexed by the number of threads,
+    /// as last measured.
+    timings: Vec<Option<Duration>>,
+    window: Duration,
+    window_steps: u32,
+    windows: u32,
+}
+
+impl ThreadScaler {
+    pub fn new(n_objects: usize) -> Self {
+        let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let threads = compute_target_threads(n_objects).min(cores);
+        Self {
+            pool: build_pool(threads),
+            threads,
+            budget: 1.0,
+            cores,
+            fixed: None,
+            timings: vec![None; MAX_THREADS + 1],
+            window: Duration::ZERO,
+            window_steps: 0,
+            windows: 0,
+        }
+    }
+
+    pub fn pool(&self) -> &ThreadPool {
+        &self.pool
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    /// Most threads allowed by the budget.
+    fn max_threads(&self) -> usize {
+        ((self.cores as f64 * self.budget) as usize).clamp(1, MAX_THREADS)
+    }
+
+    /// Use at most `budget` of the cores, as a fraction. Fewer threads are used at once
+    /// if there are too many for the new budget, unless the number is fixed.
+    pub fn set_budget(&mut self, budget: f64) {
+        if budget == self.budget || budget.is_nan() || budget <= 0.0 {
+            return;
+        }
+        self.budget = budget.min(1.0);
+        if self.fixed.is_none() && self.threads > self.max_threads() {
+            self.switch(self.max_threads());
+        }
+    }
+
+    /// Use exactly `threads` threads, or scale them within the budget again with `None`.
+    pub fn set_fixed(&mut self, threads: Option<usize>) {
+        let threads = threads.map(|n| n.max(1));
+        if threads == self.fixed {
+            return;
+        }
+        self.fixed = threads;
+        match threads {
+            Some(n) => self.switch(n),
+            None => self.switch(self.threads.min(self.max_threads())),
+        }
+    }
+
+    /// Record a step that took `elapsed`, and pick a new number of threads at the end of
+    /// a window.
+    pub fn record(&mut self, elapsed: Duration) {
+        if self.fixed.is_some() {
+            return;
+        }
+        self.window += elapsed;
+        self.window_steps += 1;
+        if self.window.as_secs_f64() < THREAD_SCALING_WINDOW {
+            return;
+        }
+        self.timings[self.threads] = Some(self.window / self.window_steps);
+        self.windows += 1;
+        if self.windows.is_multiple_of(THREAD_PROBE_INTERVAL) {
+            // The best number of threads changes with the load, so measure the
+            // neighbours again now and then.
+            self.timings[self.threads - 1] = None;
+            self.timings[(self.threads + 1).min(MAX_THREADS)] = None;
+        }
+
+        let max = self.max_threads();
+        let candidates = (self.threads.saturating_sub(1).max(1))..=(self.threads + 1).min(max);
+        // Try more threads first, since that is the way to go faster.
+        if let Some(untried) = candidates
+            .clone()
+            .rev()
+            .find(|n| self.timings[*n].is_none())
+        {
+            self.switch(untried);
+            return;
+        }
+        let best = candidates
+            .clone()
+            .filter_map(|n| self.timings[n])
+            .min()
+            .unwrap_or_default();
+        let fewest = candidates
+            .into_iter()
+            .find(|n| {
+                self.timings[*n].is_some_and(|t| {
+                    t.as_secs_f64() <= best.as_secs_f64() * (1.0 + THREAD_SCALING_TOLERANCE)
+                })
+            })
+            .unwrap_or(self.threads);
+        self.switch(fewest);
+    }
+
+    /// Start a new window with `threads` threads.
+    fn switch(&mut self, threads: usize) {
+        if threads != self.threads {
+            self.pool = build_pool(threads);
+            self.threads = threads;
+        }
+        self.window = Duration::ZERO;
+        self.window_steps = 0;
+    }
+}
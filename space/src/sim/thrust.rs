@@ -0,0 +1,143 @@
+//! Spacecraft following a scripted thrust schedule.
+//!
+//! A spacecraft is any object with maneuvers. Each maneuver is a burn over a window of
+//! simulated time, in a direction relative to the orbit around a reference body, so
+//! that transfer orbits can be flown on top of the gravity simulation. The thrust is
+//! applied as the average acceleration over each tick, weighted by how much of the tick
+//! the burn covers, so the delta-v of a burn does not depend on the time step. Burns
+//! shorter than a tick, down to an instantaneous kick, work the same way.
+
+use cgmath::{EuclideanSpace, InnerSpace, Vector3, Zero};
+
+use crate::{
+    constants::AU,
+    sim::{ObjectInfo, SimTime},
+};
+
+/// Direction of a burn. All but `Fixed` are relative to the position and velocity of
+/// the spacecraft around the reference body of the maneuver.
+#[derive(Debug, Clone, Copy)]
+pub enum ThrustDirection {
+    /// Along the velocity.
+    Prograde,
+    /// Against the velocity.
+    Retrograde,
+    /// Away from the reference body.
+    RadialOut,
+    /// Towards the reference body.
+    RadialIn,
+    /// Along the orbital angular momentum.
+    Normal,
+    /// Against the orbital angular momentum.
+    AntiNormal,
+    /// Fixed direction in the simulation frame. Need not be normalized.
+    Fixed(Vector3<f64>),
+}
+
+/// Strength of a burn.
+#[derive(Debug, Clone, Copy)]
+pub enum ThrustAmount {
+    /// Constant acceleration, in m/s^2.
+    Acceleration(f64),
+    /// Total change in velocity over the burn, in m/s, spread evenly over it.
+    DeltaV(f64),
+}
+
+/// A single burn.
+#[derive(Debug, Clone)]
+pub struct Maneuver {
+    /// Simulated time the burn starts, in seconds.
+    pub start: f64,
+    /// Length of the burn in seconds. Zero for an instantaneous kick, which only makes
+    /// sense with `ThrustAmount::DeltaV`.
+    pub duration: f64,
+    pub direction: ThrustDirection,
+    pub amount: ThrustAmount,
+    /// Index of the body that directions are relative to. Without one, they are
+    /// relative to the origin at rest.
+    pub reference: Option<usize>,
+}
+
+impl Maneuver {
+    /// Total change in velocity of the burn, in m/s.
+    pub fn delta_v(&self) -> f64 {
+        match self.amount {
+            ThrustAmount::Acceleration(acc) => acc * self.duration,
+            ThrustAmount::DeltaV(dv) => dv,
+        }
+    }
+
+    /// Change in velocity, in m/s, over the part of the burn between `from` and `to`.
+    fn delta_v_between(&self, from: f64, to: f64) -> f64 {
+        if self.duration <= 0.0 {
+            return if (from..to).contains(&self.start) {
+                self.delta_v()
+            } else {
+                0.0
+            };
+        }
+        let overlap = (to.min(self.start + self.duration) - from.max(self.start)).max(0.0);
+        self.delta_v() * overlap / self.duration
+    }
+
+    /// Unit vector of the burn for `obj`, or `None` if the direction is undefined, like
+    /// prograde while at rest relative to the reference.
+    fn direction(&self, obj: &ObjectInfo, objects: &[ObjectInfo]) -> Option<Vector3<f64>> {
+        let (rel_pos, rel_vel) = match self.reference.and_then(|r| objects.get(r)) {
+            Some(reference) => (obj.pos - reference.pos, obj.vel - reference.vel),
+            None => (obj.pos.to_vec(), obj.vel),
+        };
+        let dir = match self.direction {
+            ThrustDirection::Prograde => rel_vel,
+            ThrustDirection::Retrograde => -rel_vel,
+            ThrustDirection::RadialOut => rel_pos,
+            ThrustDirection::RadialIn => -rel_pos,
+            ThrustDirection::Normal => rel_pos.cross(rel_vel),
+            ThrustDirection::AntiNormal => rel_vel.cross(rel_pos),
+            ThrustDirection::Fixed(dir) => dir,
+        };
+        (dir.magnitude2() > 0.0).then(|| dir.normalize())
+    }
+}
+
+/// Spacecraft in a running simulation.
+#[derive(Debug, Clone)]
+pub struct Spacecraft {
+    pub index: usize,
+    pub maneuvers: Vec<Maneuver>,
+}
+
+impl Spacecraft {
+    /// Average acceleration from every burn between `time` and one tick later, in AU/s^2.
+    fn acc(&self, objects: &[ObjectInfo], time: SimTime) -> Vector3<f64> {
+        let from = time.seconds();
+        let to = from + time.delta;
+        let obj = &objects[self.index];
+        let mut acc = Vector3::zero();
+        for maneuver in &self.maneuvers {
+            let dv = maneuver.delta_v_between(from, to);
+            if dv == 0.0 {
+                continue;
+            }
+            if let Some(dir) = maneuver.direction(obj, objects) {
+                acc += dir * (dv / time.delta / AU);
+            }
+        }
+        acc
+    }
+}
+
+pub fn iter(
+    objects: &[ObjectInfo],
+    out: &mut [Vector3<f64>],
+    spacecraft: &[Spacecraft],
+    time: SimTime,
+) {
+    // Ticks of zero length, or no tick at all yet, apply no thrust.
+    if time.delta <= 0.0 {
+        return;
+    }
+    for craft in spacecraft {
+        out[craft.index] += craft.acc(objects, time);
+    }
+}
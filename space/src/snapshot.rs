@@ -0,0 +1,390 @@
+//! Headless rendering of scenarios to images.
+//!
+//! Each job is simulated to a target time without a window, rendered offscreen and read
+//! back, so parameter sweeps can be compared side by side on a contact sheet.
+
+use std::{fs, io::Write, path::Path};
+
+use anyhow::{Context, ensure};
+use cgmath::{Point3, Vector3};
+use wgpu::{Device, Queue, TextureFormat};
+use winit::dpi::PhysicalSize;
+
+use crate::{
+    Object,
+    camera::Camera,
+    constants::{DEFAULT_SAMPLE_COUNT, SNAPSHOT_FRAMING_QUANTILE, SNAPSHOT_MARGIN},
+    event_loop::build_sim,
+    objects::Objects,
+    pipeline_cache,
+    render::Renderer,
+    sim::{FrameTime, Perturbations, SimTime},
+};
+
+/// A single scenario to simulate and render.
+pub struct SnapshotJob {
+    /// Name of the job, used for the image file.
+    pub name: String,
+    pub objects: Vec<Object>,
+    pub perturbations: Perturbations,
+    /// Simulated time to run before taking the snapshot, in seconds.
+    pub duration: f64,
+    /// Seconds per tick.
+    pub delta: f64,
+}
+
+/// An RGB image, row by row from the top left.
+#[derive(Debug, Clone)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![0; (width * height * 3) as usize],
+        }
+    }
+
+    /// Write the image as a binary PPM file.
+    pub fn save_ppm(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let mut file = std::io::BufWriter::new(fs::File::create(path)?);
+        write!(file, "P6\n{} {}\n255\n", self.width, self.height)?;
+        file.write_all(&self.pixels)?;
+        file.flush()?;
+        Ok(())
+    }
+
+    /// Read a binary PPM file, like those written by `save_ppm`.
+    pub fn load_ppm(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let data = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        // The header is the magic number and three numbers, separated by whitespace and
+        // comments, followed by a single whitespace character before the pixels.
+        let mut pos = 0;
+        let mut fields = Vec::with_capacity(4);
+        while fields.len() < 4 {
+            while data.get(pos).is_some_and(|c| c.is_ascii_whitespace()) {
+                pos += 1;
+            }
+            if data.get(pos) == Some(&b'#') {
+                while data.get(pos).is_some_and(|&c| c != b'\n') {
+                    pos += 1;
+                }
+                continue;
+            }
+            let start = pos;
+            while data.get(pos).is_some_and(|c| !c.is_ascii_whitespace()) {
+                pos += 1;
+            }
+            ensure!(pos > start, "{} ends in the header", path.display());
+            fields.push(std::str::from_utf8(&data[start..pos])?);
+        }
+        ensure!(
+            fields[0] == "P6",
+            "{} is not a binary PPM image",
+            path.display()
+        );
+        let [width, height, max] = [fields[1], fields[2], fields[3]].map(str::parse::<u32>);
+        let (width, height, max) = (width?, height?, max?);
+        ensure!(
+            max == 255,
+            "{} must have 8 bits per channel",
+            path.display()
+        );
+        ensure!(width > 0 && height > 0, "{} is empty", path.display());
+        let pixels = data
+            .get(pos + 1..)
+            .and_then(|rest| rest.get(..width as usize * height as usize * 3))
+            .with_context(|| format!("{} is missing pixels", path.display()))?;
+        Ok(Self {
+            width,
+            height,
+            pixels: pixels.to_vec(),
+        })
+    }
+
+    /// Color of the image at `u`, `v` in 0..1 from the top left, interpolating between the
+    /// nearest pixels.
+    pub fn sample(&self, u: f32, v: f32) -> [f32; 3] {
+        let x = (u * self.width as f32 - 0.5).clamp(0.0, (self.width - 1) as f32);
+        let y = (v * self.height as f32 - 0.5).clamp(0.0, (self.height - 1) as f32);
+        let (x0, y0) = (x as u32, y as u32);
+        let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.height - 1));
+        let (fx, fy) = (x.fract(), y.fract());
+        let pixel = |x: u32, y: u32, c: usize| self.pixels[((y * self.width + x) * 3) as usize + c];
+        std::array::from_fn(|c| {
+            let top = pixel(x0, y0, c) as f32 * (1.0 - fx) + pixel(x1, y0, c) as f32 * fx;
+            let bottom = pixel(x0, y1, c) as f32 * (1.0 - fx) + pixel(x1, y1, c) as f32 * fx;
+            (top * (1.0 - fy) + bottom * fy) / 255.0
+        })
+    }
+
+    /// Copy `other` into this image with its top left corner at `x`, `y`, clipping
+    /// anything outside.
+    fn blit(&mut self, other: &Image, x: u32, y: u32) {
+        let width = other.width.min(self.width.saturating_sub(x));
+        for row in 0..other.height.min(self.height.saturating_sub(y)) {
+            let src = (row * other.width * 3) as usize;
+            let dst = (((y + row) * self.width + x) * 3) as usize;
+            let len = (width * 3) as usize;
+            self.pixels[dst..dst + len].copy_from_slice(&other.pixels[src..src + len]);
+        }
+    }
+
+    /// Tile `images` into a grid with `columns` columns, separated by a thin gray border.
+    /// Each cell is the size of the largest image.
+    pub fn contact_sheet(images: &[Image], columns: usize) -> Image {
+        const BORDER: u32 = 2;
+        let columns = columns.clamp(1, images.len().max(1)) as u32;
+        let rows = images.len().div_ceil(columns as usize) as u32;
+        let cell_w = images.iter().map(|i| i.width).max().unwrap_or(0);
+        let cell_h = images.iter().map(|i| i.height).max().unwrap_or(0);
+
+        let mut sheet = Image::new(
+            columns * (cell_w + BORDER) + BORDER,
+            rows * (cell_h + BORDER) + BORDER,
+        );
+        sheet.pixels.fill(64);
+        for (idx, image) in images.iter().enumerate() {
+            let idx = idx as u32;
+            sheet.blit(
+                image,
+                BORDER + (idx % columns) * (cell_w + BORDER),
+                BORDER + (idx / columns) * (cell_h + BORDER),
+            );
+        }
+        sheet
+    }
+}
+
+/// Renders snapshot jobs offscreen, without a window or surface.
+pub struct SnapshotRenderer {
+    device: Device,
+    queue: Queue,
+    size: PhysicalSize<u32>,
+}
+
+impl SnapshotRenderer {
+    pub fn new(width: u32, height: u32) -> anyhow::Result<Self> {
+        pollster::block_on(Self::new_async(PhysicalSize { width, height }))
+    }
+
+    async fn new_async(size: PhysicalSize<u32>) -> anyhow::Result<Self> {
+        let backends = wgpu::Backends::from_env().unwrap_or(wgpu::Backends::VULKAN);
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
+            backends,
+            ..Default::default()
+        });
+        let adapter = wgpu::util::initialize_adapter_from_env_or_default(&instance, None).await?;
+        println!("using: {:?}", adapter.get_info());
+
+        let (device, queue) = adapter
+            .request_device(&wgpu::DeviceDescriptor {
+                label: None,
+                required_features: wgpu::Features::PUSH_CONSTANTS
+                    | wgpu::Features::SPIRV_SHADER_PASSTHROUGH
+                    | wgpu::Features::MAPPABLE_PRIMARY_BUFFERS
+                    | pipeline_cache::features(&adapter),
+                required_limits: wgpu::Limits {
+                    max_push_constant_size: 128,
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .await?;
+        pipeline_cache::init(&device, &adapter.get_info());
+
+        Ok(Self {
+            device,
+            queue,
+            size,
+        })
+    }
+
+    /// Simulate `job` to its target time and render the result.
+    pub fn render(&self, job: SnapshotJob) -> anyhow::Result<Image> {
+        let mut objects = Objects::new(&job.objects);
+        let mut sim = build_sim(
+            job.objects.into_iter().map(|o| o.dat).collect(),
+            job.perturbations,
+        );
+
+        // Spread the trail samples out over the run, so the trails show where objects came from.
+        let ticks = (job.duration / job.delta).ceil().max(1.0) as u64;
+        let sample_every = (ticks / objects.trail_length() as u64).max(1);
+        let mut positions = Vec::with_capacity(objects.num_objects());
+        for tick in 1..=ticks {
+            sim.set_time(SimTime::new(tick - 1, job.delta));
+            sim.exec_iter(job.delta);
+            if tick.is_multiple_of(sample_every) || tick == ticks {
+                positions.clear();
+                positions.extend(
+                    sim.objects
+                        .iter()
+                        .map(|o| [o.pos.x as f32, o.pos.y as f32, o.pos.z as f32]),
+                );
+                objects.push_items(&positions, SimTime::new(tick, job.delta));
+            }
+        }
+
+        let mut camera = Camera::new(self.size, &self.device);
+        frame_objects(&mut camera, &positions);
+
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Snapshot Texture"),
+            size: wgpu::Extent3d {
+                width: self.size.width,
+                height: self.size.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: TextureFormat::Bgra8Unorm,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let mut renderer = Renderer::new(
+            &self.device,
+            TextureFormat::Bgra8Unorm,
+            DEFAULT_SAMPLE_COUNT,
+            self.size,
+            &camera,
+            &mut objects,
+        );
+        renderer.redraw(
+            FrameTime::default(),
+            &mut camera,
+            &mut objects,
+            &self.queue,
+            &texture,
+            &self.device,
+        );
+
+        self.read_back(&texture)
+    }
+
+    /// Copy `texture` to the CPU, dropping the row padding and converting to RGB.
+    fn read_back(&self, texture: &wgpu::Texture) -> anyhow::Result<Image> {
+        let PhysicalSize { width, height } = self.size;
+        let padded_row = (width * 4).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Snapshot readback"),
+            size: (padded_row * height) as u64,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        encoder.copy_texture_to_buffer(
+            wgpu::TexelCopyTextureInfo {
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::TexelCopyBufferInfo {
+                buffer: &buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.queue.submit(Some(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |res| {
+            let _ = sender.send(res);
+        });
+        self.device.poll(wgpu::PollType::Wait)?;
+        receiver.recv()??;
+
+        let mut image = Image::new(width, height);
+        {
+            let data = slice.get_mapped_range();
+            for (row, out) in data
+                .chunks_exact(padded_row as usize)
+                .zip(image.pixels.chunks_exact_mut((width * 3) as usize))
+            {
+                for (bgra, rgb) in row.chunks_exact(4).zip(out.chunks_exact_mut(3)) {
+                    rgb.copy_from_slice(&[bgra[2], bgra[1], bgra[0]]);
+                }
+            }
+        }
+        buffer.unmap();
+        Ok(image)
+    }
+}
+
+/// Point the camera down the z axis at the objects, far enough back that most of them
+/// are in view. The furthest objects are left out, so a few escaping bodies don't shrink
+/// everything else to a dot.
+fn frame_objects(camera: &mut Camera, positions: &[[f32; 3]]) {
+    if positions.is_empty() {
+        return;
+    }
+    let n = positions.len() as f32;
+    let center = positions
+        .iter()
+        .fold(Vector3::new(0.0, 0.0, 0.0), |acc, p| {
+            acc + Vector3::from(*p)
+        })
+        / n;
+
+    let mut extents: Vec<f32> = positions
+        .iter()
+        .map(|p| (p[0] - center.x).abs().max((p[1] - center.y).abs()))
+        .collect();
+    let idx = ((n - 1.0) * SNAPSHOT_FRAMING_QUANTILE) as usize;
+    let (_, extent, _) = extents.select_nth_unstable_by(idx, f32::total_cmp);
+    let extent = extent.max(f32::EPSILON) * SNAPSHOT_MARGIN;
+
+    // Matches the projection in `Camera`: x is scaled by `e`, y by `e * aspect`.
+    let e = 1.0 / (camera.fovy / 2.0).tan();
+    let distance = e * extent * camera.aspect.max(1.0);
+    camera.target = Point3::new(center.x, center.y, center.z);
+    camera.eye = Point3::new(center.x, center.y, center.z + distance);
+}
+
+/// Render every job, saving each as `<name>.ppm` in `dir`, followed by all of them tiled
+/// into `contact_sheet.ppm`.
+pub fn run_sweep(
+    jobs: Vec<SnapshotJob>,
+    dir: impl AsRef<Path>,
+    width: u32,
+    height: u32,
+    columns: usize,
+) -> anyhow::Result<()> {
+    let dir = dir.as_ref();
+    fs::create_dir_all(dir)?;
+    let renderer = SnapshotRenderer::new(width, height)?;
+
+    let mut images = Vec::with_capacity(jobs.len());
+    for job in jobs {
+        let name = job.name.clone();
+        println!("Rendering {name}");
+        let image = renderer.render(job)?;
+        image.save_ppm(dir.join(format!("{name}.ppm")))?;
+        images.push(image);
+    }
+
+    let path = dir.join("contact_sheet.ppm");
+    Image::contact_sheet(&images, columns).save_ppm(&path)?;
+    println!("Saved contact sheet to {}", path.display());
+    Ok(())
+}
@@ -0,0 +1,169 @@
+//! Statistics of star clusters, for judging whether a cluster is in equilibrium.
+//!
+//! A cluster in virial equilibrium has a kinetic energy of half the magnitude of its
+//! potential energy, so its virial ratio `2K / |W|` is one. Clusters with a larger ratio
+//! expand and those with a smaller one collapse, both of which shows as a changing
+//! half-mass radius. The statistics are computed every `CLUSTER_STATS_INTERVAL` ticks
+//! while running, and can be written to a CSV file as they are.
+//!
+//! The crossing and relaxation times give the scale a time step should be picked on. A
+//! cluster needs many steps per crossing time to be followed accurately, and changes
+//! shape over a few relaxation times.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::Context;
+use cgmath::{EuclideanSpace, InnerSpace, Vector3};
+
+use crate::{
+    constants::{AU, BARNES_HUT_COEFF, G, M0},
+    sim::{ObjectInfo, SimTime, barnes_hut},
+};
+
+/// The Coulomb logarithm of the relaxation time is `ln(COULOMB_FACTOR * N)`, as fitted to
+/// clusters of equal masses by Giersz & Heggie (1994).
+const COULOMB_FACTOR: f64 = 0.11;
+
+/// Energies and sizes of the objects that attract others, in the frame of their center
+/// of mass. Test particles are left out.
+#[derive(Debug, Clone, Copy)]
+pub struct ClusterStats {
+    pub time: SimTime,
+    /// Number of objects that attract others.
+    pub members: usize,
+    /// Total mass, in earth masses.
+    pub mass: f64,
+    /// Kinetic energy, in earth masses AU^2/s^2.
+    pub kinetic: f64,
+    /// Potential energy, in earth masses AU^2/s^2. Approximated with a Barnes-Hut tree.
+    pub potential: f64,
+    /// Radius around the center of mass that holds half the mass, in AU.
+    pub half_mass_radius: f64,
+    /// Mass weighted root mean square of the speeds, in AU/s. This is the dispersion in
+    /// three dimensions, which is `sqrt(3)` times that along a single line of sight.
+    pub velocity_dispersion: f64,
+}
+
+impl ClusterStats {
+    /// Statistics of `objects` at `time`, or `None` if fewer than two of them attract
+    /// others.
+    pub fn compute(objects: &[ObjectInfo], time: SimTime) -> Option<Self> {
+        let massive: Vec<_> = objects
+            .iter()
+            .filter(|obj| obj.gravitating_mass() > 0.0)
+            .collect();
+        if massive.len() < 2 {
+            return None;
+        }
+        let total_mass: f64 = massive.iter().map(|obj| obj.mass).sum();
+        let center = massive
+            .iter()
+            .map(|obj| obj.pos.to_vec() * obj.mass)
+            .sum::<Vector3<f64>>()
+            / total_mass;
+        let momentum = massive
+            .iter()
+            .map(|obj| obj.vel * obj.mass)
+            .sum::<Vector3<f64>>();
+        let drift = momentum / total_mass;
+
+        let speed_sq: f64 = massive
+            .iter()
+            .map(|obj| obj.mass * (obj.vel - drift).magnitude2())
+            .sum();
+
+        let mut distances: Vec<_> = massive
+            .iter()
+            .map(|obj| ((obj.pos.to_vec() - center).magnitude(), obj.mass))
+            .collect();
+        distances.sort_unstable_by(|a, b| a.0.total_cmp(&b.0));
+        let mut enclosed = 0.0;
+        let half_mass_radius = distances
+            .iter()
+            .find(|(_, mass)| {
+                enclosed += mass;
+                enclosed >= total_mass / 2.0
+            })
+            .map_or(0.0, |(distance, _)| *distance);
+
+        Some(Self {
+            time,
+            members: massive.len(),
+            mass: total_mass,
+            kinetic: speed_sq / 2.0,
+            potential: barnes_hut::potential_energy(objects, BARNES_HUT_COEFF),
+            half_mass_radius,
+            velocity_dispersion: (speed_sq / total_mass).sqrt(),
+        })
+    }
+
+    /// `2K / |W|`, which is one for a cluster in equilibrium.
+    pub fn virial_ratio(&self) -> f64 {
+        2.0 * self.kinetic / self.potential.abs()
+    }
+
+    /// Time to cross the half-mass radius at the speed of a cluster of this mass and size
+    /// in equilibrium, in seconds. Does not depend on the actual speeds, so that it is
+    /// also known for a cluster starting at rest.
+    pub fn crossing_time(&self) -> f64 {
+        (self.half_mass_radius.powi(3) / (G * self.mass)).sqrt()
+    }
+
+    /// Estimate of the time for encounters between members to change their speeds as much
+    /// as the speeds are, at the half-mass radius, in seconds. This is the half-mass
+    /// relaxation time of Spitzer (1987), which assumes members of similar mass. `None`
+    /// for clusters too small to relax.
+    pub fn relaxation_time(&self) -> Option<f64> {
+        let n = self.members as f64;
+        let coulomb = (COULOMB_FACTOR * n).ln();
+        (coulomb > 0.0).then(|| 0.138 * n / coulomb * self.crossing_time())
+    }
+}
+
+/// CSV log of the statistics of a run.
+pub struct ClusterStatsLog {
+    writer: BufWriter<File>,
+}
+
+impl ClusterStatsLog {
+    pub fn create(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let file =
+            File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+        let mut writer = BufWriter::new(file);
+        writeln!(
+            writer,
+            "tick,time_s,virial_ratio,kinetic_j,potential_j,half_mass_radius_au,velocity_dispersion_km_s,crossing_time_s,relaxation_time_s"
+        )?;
+        Ok(Self { writer })
+    }
+
+    pub fn log(&mut self, stats: &ClusterStats) -> anyhow::Result<()> {
+        let joules = M0 * AU * AU;
+        writeln!(
+            self.writer,
+            "{},{},{},{},{},{},{},{},{}",
+            stats.time.ticks,
+            stats.time.seconds(),
+            stats.virial_ratio(),
+            stats.kinetic * joules,
+            stats.potential * joules,
+            stats.half_mass_radius,
+            stats.velocity_dispersion * AU / 1e3,
+            stats.crossing_time(),
+            stats
+                .relaxation_time()
+                .map_or(String::new(), |time| time.to_string())
+        )?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> anyhow::Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
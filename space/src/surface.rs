@@ -10,6 +10,8 @@ use winit::{
     window::Window,
 };
 
+use crate::pipeline_cache;
+
 pub struct WindowState {
     pub window: Arc<Window>,
 }
@@ -109,7 +111,8 @@ pub async fn get_surface(window: Arc<Window>) -> anyhow::Result<SurfaceState> {
             label: None,
             required_features: wgpu::Features::PUSH_CONSTANTS
                 | wgpu::Features::SPIRV_SHADER_PASSTHROUGH
-                | wgpu::Features::MAPPABLE_PRIMARY_BUFFERS,
+                | wgpu::Features::MAPPABLE_PRIMARY_BUFFERS
+                | pipeline_cache::features(&adapter),
             required_limits: wgpu::Limits {
                 max_push_constant_size: 128,
                 ..Default::default()
@@ -118,6 +121,8 @@ pub async fn get_surface(window: Arc<Window>) -> anyhow::Result<SurfaceState> {
         })
         .await?;
 
+    pipeline_cache::init(&device, &adapter.get_info());
+
     let surface = surface
         .map(|surface| auto_configure_surface(&adapter, &device, surface, window.inner_size()));
 
@@ -0,0 +1,85 @@
+//! Meshes for bodies too large on screen to draw as quads.
+//!
+//! The quad path scales a disc by the projected radius at the center of the body, which
+//! is only right for bodies far away compared to their size. Up close the visible part
+//! of a sphere is the cap bounded by its horizon, the circle where lines from the eye
+//! touch the surface. Bodies are drawn with a flat color, so the cap looks exactly like
+//! the disc spanning the horizon, which is what is tessellated here.
+
+use cgmath::{InnerSpace, Matrix4, Point3, Vector3, Vector4};
+
+use crate::{
+    constants::{
+        MAX_CIRCLE_SIZE, SPHERE_MAX_SEGMENTS, SPHERE_MIN_SEGMENTS, SPHERE_SEGMENTS_PER_SIZE,
+    },
+    mesh_pipeline::MeshVertex,
+};
+
+/// Projected radius of a body, relative to half the screen height, if it is too large
+/// to draw as a quad. Must match the test in `circle_vs`.
+pub(crate) fn large_projected_size(
+    center: Point3<f32>,
+    radius: f32,
+    view_proj: Matrix4<f32>,
+    projection: Matrix4<f32>,
+) -> Option<f32> {
+    let w = (view_proj * Vector4::new(center.x, center.y, center.z, 1.0)).w;
+    let size = (projection * Vector4::new(radius, 0.0, 0.0, 1.0))
+        .truncate()
+        .truncate()
+        .magnitude();
+    // Bodies right next to the camera have no meaningful projected size.
+    if w > -radius && (w < radius || size > MAX_CIRCLE_SIZE * w) {
+        Some(if w > 0.0 { size / w } else { f32::INFINITY })
+    } else {
+        None
+    }
+}
+
+/// Triangles of the disc bounded by the horizon of a sphere seen from `eye`, with more
+/// segments for larger projected sizes. Nothing is pushed if the eye is inside the sphere.
+pub(crate) fn horizon_triangles(
+    center: Point3<f32>,
+    radius: f32,
+    eye: Point3<f32>,
+    projected_size: f32,
+    color: [f32; 3],
+    texture: u32,
+    out: &mut Vec<MeshVertex>,
+) {
+    let to_eye = eye - center;
+    let dist = to_eye.magnitude();
+    if dist <= radius {
+        return;
+    }
+    let normal = to_eye / dist;
+    // The horizon is a circle at distance r^2/d from the center towards the eye, with
+    // radius r * sqrt(1 - r^2/d^2).
+    let cos_horizon = radius / dist;
+    let disc_center = center + normal * (radius * cos_horizon);
+    let disc_radius = radius * (1.0 - cos_horizon * cos_horizon).sqrt();
+
+    let helper = if normal.x.abs() < 0.9 {
+        Vector3::unit_x()
+    } else {
+        Vector3::unit_y()
+    };
+    let e1 = normal.cross(helper).normalize();
+    let e2 = normal.cross(e1);
+
+    let segments = ((projected_size * SPHERE_SEGMENTS_PER_SIZE) as usize)
+        .clamp(SPHERE_MIN_SEGMENTS, SPHERE_MAX_SEGMENTS);
+    let vertex = |pos: Point3<f32>| MeshVertex {
+        pos: pos.into(),
+        color: [color[0], color[1], color[2], 1.0],
+        sphere: [center.x, center.y, center.z, radius],
+        texture,
+    };
+    let rim = |i: usize| {
+        let phi = 2.0 * std::f32::consts::PI * (i % segments) as f32 / segments as f32;
+        disc_center + (e1 * phi.cos() + e2 * phi.sin()) * disc_radius
+    };
+    for i in 0..segments {
+        out.extend([vertex(disc_center), vertex(rim(i)), vertex(rim(i + 1))]);
+    }
+}
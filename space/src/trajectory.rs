@@ -0,0 +1,175 @@
+//! Full resolution trajectories of a few selected objects.
+//!
+//! Samples sent to the renderer skip most simulation steps, which can hide short events
+//! like close encounters. Objects on the watch list have their state written out after
+//! every single step instead, or every few steps for long runs.
+//!
+//! The simulation only copies the states into batches. Formatting and writing them is
+//! left to a thread of its own, so that a slow disk does not hold up the steps until
+//! `TRAJECTORY_QUEUE_BATCHES` batches are waiting.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    sync::mpsc::{Receiver, SyncSender, sync_channel},
+    thread::JoinHandle,
+};
+
+use anyhow::Context;
+use cgmath::{Point3, Vector3};
+
+use crate::{
+    Object,
+    constants::{TRAJECTORY_BATCH_ROWS, TRAJECTORY_QUEUE_BATCHES, TRAJECTORY_THREAD_NAME},
+    sim::{ObjectInfo, SimTime},
+};
+
+/// State of a watched object at a single step.
+struct Row {
+    /// Index into the watch list.
+    watched: usize,
+    time: SimTime,
+    pos: Point3<f64>,
+    vel: Vector3<f64>,
+}
+
+/// CSV log of the state of watched objects at every `every` simulation steps.
+pub struct TrajectoryLog {
+    /// Index of each watched object.
+    watched: Vec<usize>,
+    every: u64,
+    batch: Vec<Row>,
+    sender: Option<SyncSender<Vec<Row>>>,
+    writer: Option<JoinHandle<anyhow::Result<()>>>,
+}
+
+impl TrajectoryLog {
+    /// Create a log at `path` for the objects named in `names`, written every `every`
+    /// steps.
+    pub fn create(
+        path: impl AsRef<Path>,
+        names: &[String],
+        objects: &[Object],
+        every: u64,
+    ) -> anyhow::Result<Self> {
+        let watched = names
+            .iter()
+            .map(|name| {
+                objects
+                    .iter()
+                    .position(|o| &o.name == name)
+                    .ok_or_else(|| anyhow::anyhow!("No object named {name} to watch"))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let path = path.as_ref();
+        let file =
+            File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+        let mut writer = BufWriter::new(file);
+        writeln!(
+            writer,
+            "object,tick,time_s,x_au,y_au,z_au,vx_au_s,vy_au_s,vz_au_s"
+        )?;
+        let (sender, receiver) = sync_channel(TRAJECTORY_QUEUE_BATCHES);
+        let names: Vec<_> = names.iter().map(|name| csv_field(name)).collect();
+        let writer = std::thread::Builder::new()
+            .name(TRAJECTORY_THREAD_NAME.to_owned())
+            .spawn(move || write_rows(writer, &names, receiver))?;
+        Ok(Self {
+            watched,
+            every: every.max(1),
+            batch: Vec::with_capacity(TRAJECTORY_BATCH_ROWS),
+            sender: Some(sender),
+            writer: Some(writer),
+        })
+    }
+
+    /// Queue the state of every watched object, if this is one of the steps to log.
+    /// Fails if writing has failed since the last call.
+    pub fn log(&mut self, time: SimTime, objects: &[ObjectInfo]) -> anyhow::Result<()> {
+        if !time.ticks.is_multiple_of(self.every) {
+            return Ok(());
+        }
+        for (watched, idx) in self.watched.iter().enumerate() {
+            let obj = &objects[*idx];
+            self.batch.push(Row {
+                watched,
+                time,
+                pos: obj.pos,
+                vel: obj.vel,
+            });
+        }
+        if self.batch.len() >= TRAJECTORY_BATCH_ROWS {
+            self.send()?;
+        }
+        Ok(())
+    }
+
+    /// Hand the rows so far to the writer. It only hangs up after failing.
+    fn send(&mut self) -> anyhow::Result<()> {
+        let batch = std::mem::replace(&mut self.batch, Vec::with_capacity(TRAJECTORY_BATCH_ROWS));
+        let sent = self
+            .sender
+            .as_ref()
+            .is_some_and(|sender| sender.send(batch).is_ok());
+        if sent { Ok(()) } else { self.finish() }
+    }
+
+    /// Wait for the writer to finish, and return how it went.
+    fn finish(&mut self) -> anyhow::Result<()> {
+        self.sender = None;
+        match self.writer.take() {
+            Some(writer) => writer
+                .join()
+                .map_err(|_| anyhow::anyhow!("Trajectory writer panicked"))?,
+            None => anyhow::bail!("Trajectory log is closed"),
+        }
+    }
+
+    /// Write out everything logged, and close the log.
+    pub fn flush(&mut self) -> anyhow::Result<()> {
+        if !self.batch.is_empty() {
+            self.send()?;
+        }
+        self.finish()
+    }
+}
+
+/// `field` as a CSV field, as in RFC 4180. Fields with a comma, quote or line break are
+/// put in quotes, and quotes inside them are doubled.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+/// Write batches from `receiver` until the log hangs up, with `names` already made into
+/// CSV fields. Values are written with enough digits to read back the exact same numbers.
+fn write_rows(
+    mut writer: BufWriter<File>,
+    names: &[String],
+    receiver: Receiver<Vec<Row>>,
+) -> anyhow::Result<()> {
+    for batch in receiver {
+        for row in batch {
+            writeln!(
+                writer,
+                "{},{},{},{},{},{},{},{},{}",
+                names[row.watched],
+                row.time.ticks,
+                row.time.seconds(),
+                row.pos.x,
+                row.pos.y,
+                row.pos.z,
+                row.vel.x,
+                row.vel.y,
+                row.vel.z
+            )?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
@@ -1,225 +1,430 @@
-use std::sync::Arc;
+use std::{path::Path, sync::Arc};
 
-use eframe::egui::{self, Image, Key, TextureId, Vec2, load::SizedTexture};
+use eframe::egui::{self, Vec2};
 use egui_wgpu::RenderState;
-use wgpu::{FilterMode, TextureFormat, wgt::TextureViewDescriptor};
-use winit::dpi::PhysicalSize;
 
 use crate::{
-    batch_request::BatchRequest, camera::Camera, event_loop::KeyboardState, objects::Objects,
-    render::Renderer,
+    batch_request::BatchRequest, checkpoint::Checkpoint, constants::HISTORY_MAX_FRAMES,
+    diff::RecordingDiff, ephemeris::Ephemeris, history::History, inject::InjectionSchedule,
+    live::LiveSim, objects::Objects, presets::Scenario, replay::ReplayPlayer, scenario_file,
+    validate,
 };
 
+mod accessibility;
+mod accuracy;
+mod demo;
+mod density;
+mod diff;
+mod drop;
+mod ephemeris;
+mod groups;
 mod info;
+mod locale;
+mod probes;
+mod record;
+mod rendering;
+mod replay;
+mod resources;
+mod roche;
+mod timeline;
+mod view;
+
+pub use demo::{CameraPath, Demo, DemoStep};
+pub use locale::{Locale, Msg};
+pub use view::SpaceViewWidget;
+
+/// Where the displayed object positions come from.
+enum Source {
+    /// Samples from a running simulation.
+    Live(Arc<BatchRequest>),
+    /// Frames from a recording, optionally compared against a second recording.
+    Replay {
+        player: Box<ReplayPlayer>,
+        controls: replay::ReplayControls,
+        diff: Option<Box<diff::DiffPanel>>,
+    },
+}
 
 pub struct SpaceEguiApp {
-    camera: Camera,
-    exchange: Arc<BatchRequest>,
+    view: view::SpaceViewWidget,
+    source: Source,
     objects: Objects,
-    tick: u32,
-    keyboard_state: KeyboardState,
-    renderer: Renderer,
-    texture: IntermediateTexture,
     info_panel: info::InfoPanel,
+    history: History,
+    timeline: timeline::Timeline,
+    probes: probes::ProbePanel,
+    roche: roche::RochePanel,
+    drop: drop::DropHandler,
+    locale: Locale,
+    accessibility: accessibility::AccessibilityPanel,
+    rendering: rendering::RenderingPanel,
+    resources: resources::ResourcesPanel,
+    accuracy: accuracy::AccuracyPanel,
+    ephemeris: ephemeris::EphemerisPanel,
+    groups: groups::GroupsPanel,
+    density: density::DensityPanel,
+    record: record::RecordPanel,
+    /// Scenarios cycled through on a timer, replacing the source as they go.
+    demo: Option<Demo>,
+    /// Simulation started from a dropped file, which stops when it is replaced.
+    dropped_sim: Option<LiveSim>,
 }
 
 impl SpaceEguiApp {
     pub fn new(
         cc: &eframe::CreationContext<'_>,
         exchange: Arc<BatchRequest>,
-        mut objects: Objects,
+        objects: Objects,
     ) -> Option<Self> {
-        let wgpu_render_state = cc.wgpu_render_state.as_ref()?;
+        Self::with_source(cc, Source::Live(exchange), objects)
+    }
 
-        let initial_size = Vec2::splat(300.0);
-        let camera = Camera::new(
-            PhysicalSize {
-                width: initial_size.x as u32,
-                height: initial_size.y as u32,
-            },
-            &wgpu_render_state.device,
-        );
-        let renderer = Renderer::new(
-            &wgpu_render_state.device,
-            TextureFormat::Bgra8Unorm,
-            PhysicalSize {
-                width: initial_size.x as u32,
-                height: initial_size.y as u32,
+    /// Create an app that cycles through the steps of `demo`, starting with `objects`.
+    pub fn new_demo(
+        cc: &eframe::CreationContext<'_>,
+        demo: Demo,
+        objects: Objects,
+    ) -> Option<Self> {
+        let exchange = demo.exchange().clone();
+        let mut app = Self::with_source(cc, Source::Live(exchange), objects)?;
+        app.demo = Some(demo);
+        Some(app)
+    }
+
+    /// Create an app that plays back a recording instead of showing a live simulation.
+    pub fn new_replay(
+        cc: &eframe::CreationContext<'_>,
+        player: ReplayPlayer,
+        objects: Objects,
+    ) -> Option<Self> {
+        Self::with_source(
+            cc,
+            Source::Replay {
+                player: Box::new(player),
+                controls: replay::ReplayControls::new(),
+                diff: None,
             },
-            &camera,
-            &mut objects,
-        );
-        let texture = IntermediateTexture::new(
-            &wgpu_render_state.device,
-            PhysicalSize {
-                width: initial_size.x as u32,
-                height: initial_size.y as u32,
+            objects,
+        )
+    }
+
+    /// Create an app that plays back a recording, with objects colored by how far
+    /// they diverge from the same objects in `diff`.
+    pub fn new_diff(
+        cc: &eframe::CreationContext<'_>,
+        player: ReplayPlayer,
+        diff: RecordingDiff,
+        objects: Objects,
+    ) -> Option<Self> {
+        Self::with_source(
+            cc,
+            Source::Replay {
+                player: Box::new(player),
+                controls: replay::ReplayControls::new(),
+                diff: Some(Box::new(diff::DiffPanel::new(diff))),
             },
-            wgpu_render_state,
-        );
+            objects,
+        )
+    }
+
+    fn with_source(
+        cc: &eframe::CreationContext<'_>,
+        source: Source,
+        mut objects: Objects,
+    ) -> Option<Self> {
+        let wgpu_render_state = cc.wgpu_render_state.as_ref()?;
+        let view = view::SpaceViewWidget::new(wgpu_render_state, &mut objects);
 
         Some(Self {
-            camera,
-            exchange,
+            view,
+            source,
             objects,
-            tick: 0,
-            keyboard_state: KeyboardState::default(),
-            renderer,
-            texture,
             info_panel: info::InfoPanel::new(),
+            history: History::new(HISTORY_MAX_FRAMES),
+            timeline: timeline::Timeline::new(),
+            probes: probes::ProbePanel::new(),
+            roche: roche::RochePanel::new(),
+            drop: drop::DropHandler::new(),
+            locale: Locale::from_env(),
+            accessibility: accessibility::AccessibilityPanel::new(),
+            rendering: rendering::RenderingPanel::new(wgpu_render_state),
+            resources: resources::ResourcesPanel::new(),
+            accuracy: accuracy::AccuracyPanel::new(),
+            ephemeris: ephemeris::EphemerisPanel::new(Vec::new()),
+            groups: groups::GroupsPanel::new(),
+            density: density::DensityPanel::new(),
+            record: record::RecordPanel::new(),
+            demo: None,
+            dropped_sim: None,
         })
     }
+
+    /// Compare the objects against reference positions, each with the index of the
+    /// object it is for.
+    pub fn with_ephemerides(mut self, tracks: Vec<(usize, Ephemeris)>) -> Self {
+        self.ephemeris = ephemeris::EphemerisPanel::new(tracks);
+        self
+    }
+
+    /// Record the samples of the simulation to `path` from the start.
+    pub fn with_recording(mut self, path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        self.record.start(path, &self.objects)?;
+        Ok(self)
+    }
+
+    /// Show `objects` from `source` in place of whatever is shown now. Everything that
+    /// refers to the old objects is reset. A live simulation that is replaced keeps
+    /// running in the background, without being sampled.
+    fn replace_source(&mut self, state: &RenderState, source: Source, mut objects: Objects) {
+        let (sample_count, bloom, trail_style) = (
+            self.view.sample_count(),
+            self.view.bloom_intensity(),
+            self.view.trail_style(),
+        );
+        self.view = view::SpaceViewWidget::new(state, &mut objects);
+        self.view.set_sample_count(sample_count);
+        self.view.set_bloom_intensity(bloom);
+        self.view.set_trail_style(trail_style);
+        self.source = source;
+        self.objects = objects;
+        self.history = History::new(HISTORY_MAX_FRAMES);
+        self.timeline = timeline::Timeline::new();
+        self.probes = probes::ProbePanel::new();
+        self.roche = roche::RochePanel::new();
+        self.accuracy = accuracy::AccuracyPanel::new();
+        self.ephemeris = ephemeris::EphemerisPanel::new(Vec::new());
+        self.groups = groups::GroupsPanel::new();
+        self.density = density::DensityPanel::new();
+        self.record.stop();
+        self.record = record::RecordPanel::new();
+        self.accessibility.reapply();
+    }
+
+    /// Open a file dropped onto the window.
+    fn open_dropped(
+        &mut self,
+        state: &RenderState,
+        path: &Path,
+        kind: drop::DroppedKind,
+    ) -> anyhow::Result<()> {
+        let (source, objects, sim) = match kind {
+            drop::DroppedKind::Recording => {
+                let mut player = ReplayPlayer::open(path)?;
+                let objects = Objects::new(&player.initial_objects()?);
+                let source = Source::Replay {
+                    player: Box::new(player),
+                    controls: replay::ReplayControls::new(),
+                    diff: None,
+                };
+                (source, objects, None)
+            }
+            drop::DroppedKind::Scenario => {
+                let params = scenario_file::load_params(path)?;
+                let report = validate::check_params(&params);
+                if report.has_errors() {
+                    anyhow::bail!("{report}");
+                }
+                let objects = Scenario::Params(params).into_objects();
+                let sim = LiveSim::spawn(&objects)?;
+                let source = Source::Live(sim.exchange().clone());
+                (source, Objects::new(&objects), Some(sim))
+            }
+            drop::DroppedKind::Checkpoint => {
+                // Checkpoints only hold the state of each object, so they are restored
+                // onto the objects shown. The clock starts over, and injections still
+                // pending in the checkpoint stay frozen.
+                let checkpoint = Checkpoint::open(path)?;
+                let mut objects = self.objects.objects().to_vec();
+                checkpoint.restore(&mut objects, &mut InjectionSchedule::default())?;
+                let sim = LiveSim::spawn(&objects)?;
+                sim.exchange().set_delta(checkpoint.time.delta);
+                let source = Source::Live(sim.exchange().clone());
+                (source, Objects::new(&objects), Some(sim))
+            }
+        };
+        // A file opened by hand is not replaced by the next demo step.
+        self.demo = None;
+        self.replace_source(state, source, objects);
+        // Replaced only now, so that a checkpoint that fails to open leaves the old
+        // simulation running.
+        self.dropped_sim = sim;
+        Ok(())
+    }
 }
 
 impl eframe::App for SpaceEguiApp {
     fn update(&mut self, ctx: &eframe::egui::Context, frame: &mut eframe::Frame) {
-        self.tick += 1;
-        egui::CentralPanel::default().show(ctx, |ui| {
-            ui.label("Neato space sim");
-
-            let psize = PhysicalSize {
-                width: ui.available_width() as u32 - 300,
-                height: ui.available_height() as u32,
-            };
-
-            self.camera.resize(psize);
-            self.renderer.resize(psize);
+        if let Some((path, kind)) = self.drop.update(ctx, self.locale) {
             let state = frame.wgpu_render_state().unwrap();
-            self.texture.resize(&state.device, psize, state);
-
-            ui.input(|i| {
-                for evt in &i.events {
-                    if let egui::Event::Key { key, pressed, .. } = evt { match key {
-                        Key::ArrowUp => self.keyboard_state.up = *pressed,
-                        Key::ArrowDown => self.keyboard_state.down = *pressed,
-                        Key::ArrowLeft => self.keyboard_state.left = *pressed,
-                        Key::ArrowRight => self.keyboard_state.right = *pressed,
-                        Key::Home => self.keyboard_state.home = *pressed,
-                        Key::PageUp => self.keyboard_state.pgup = *pressed,
-                        Key::Space => self.keyboard_state.space.event(*pressed),
-                        Key::W => self.keyboard_state.w = *pressed,
-                        Key::S => self.keyboard_state.s = *pressed,
-                        Key::A => self.keyboard_state.a = *pressed,
-                        Key::D => self.keyboard_state.d = *pressed,
-                        Key::Minus => self.keyboard_state.minus = *pressed,
-                        Key::Plus => self.keyboard_state.plus = *pressed,
-                        Key::F => self.keyboard_state.f.event(*pressed),
-                        Key::G => self.keyboard_state.g.event(*pressed),
-                        Key::H => self.keyboard_state.h.event(*pressed),
-                        Key::J => self.keyboard_state.j.event(*pressed),
-                        Key::O => self.keyboard_state.o = *pressed,
-                        Key::L => self.keyboard_state.l = *pressed,
-                        _ => (),
-                    } }
+            if let Err(e) = self.open_dropped(state, &path, kind) {
+                self.drop.set_error(e);
+            }
+        }
+
+        if let Some(demo) = &mut self.demo {
+            match demo.update() {
+                Ok(Some(objects)) => {
+                    let source = Source::Live(demo.exchange().clone());
+                    let state = frame.wgpu_render_state().unwrap();
+                    self.replace_source(state, source, objects);
                 }
+                Ok(None) => (),
+                Err(e) => println!("Failed to start the next demo step: {e:#}"),
+            }
+        }
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label(self.locale.text(Msg::Title));
+                egui::ComboBox::from_label(self.locale.text(Msg::Language))
+                    .selected_text(self.locale.name())
+                    .show_ui(ui, |ui| {
+                        for locale in Locale::ALL {
+                            ui.selectable_value(&mut self.locale, locale, locale.name());
+                        }
+                    });
             });
 
-            if self.keyboard_state.space.get_trigger() {
-                self.objects.clear();
-            }
-            self.exchange.sample(&mut self.objects);
+            let state = frame.wgpu_render_state().unwrap();
+            self.view.handle_input(ui);
+            let focus = self.view.camera().focus().map(|f| f as usize);
+            let keyboard_state = self.view.keyboard_state();
 
-            self.camera.move_relative(&self.keyboard_state);
-            self.camera.zoom(&self.keyboard_state);
-            self.camera
-                .set_focus(&mut self.keyboard_state, &mut self.objects);
-            self.camera.rot(&self.keyboard_state);
+            match &mut self.source {
+                Source::Live(exchange) => {
+                    let live = self.timeline.is_live();
+                    if keyboard_state.space.get_trigger() && live {
+                        self.objects.clear();
+                    }
+                    exchange.sample_with(|frame| {
+                        self.history.record(frame.time, &frame.positions);
+                        self.record.record(frame.time, &frame.positions);
+                        if live {
+                            self.objects.push_items(&frame.positions, frame.time);
+                            self.probes.update(frame.time, &self.objects);
+                        }
+                    });
 
-            if self.keyboard_state.l {
-                self.exchange.set_delta(self.exchange.delta() * 0.9);
-            }
-            if self.keyboard_state.o {
-                self.exchange.set_delta(self.exchange.delta() * 1.1);
+                    if self.groups.update(exchange, &mut self.objects) {
+                        self.accessibility.reapply();
+                    }
+                    self.density.update(exchange, &mut self.objects);
+                    self.info_panel.update_orbit(exchange, focus);
+
+                    if keyboard_state.r.get_trigger() {
+                        exchange.set_reversed(!exchange.reversed());
+                    }
+                    if keyboard_state.l {
+                        exchange.set_delta(exchange.delta() * 0.9);
+                    }
+                    if keyboard_state.o {
+                        exchange.set_delta(exchange.delta() * 1.1);
+                    }
+                }
+                Source::Replay {
+                    player,
+                    controls,
+                    diff,
+                } => {
+                    if keyboard_state.space.get_trigger() {
+                        player.set_playing(!player.is_playing());
+                    }
+                    controls.update(player, &mut self.objects);
+                    if let Some(diff) = diff {
+                        diff.update(player, &mut self.objects);
+                    }
+                    self.probes.update(player.current_time(), &self.objects);
+                }
             }
 
-            self.renderer.redraw(
-                self.tick,
-                &mut self.camera,
-                &mut self.objects,
-                &state.queue,
-                &self.texture.texture,
-                &state.device,
-            );
+            if let Some(demo) = &self.demo {
+                demo.animate(self.view.camera_mut());
+            }
+            self.accessibility.update(ctx, &mut self.objects);
+            self.resources.update(&mut self.objects, &mut self.history);
+            if let Some(mesh) = self.roche.update(&self.objects) {
+                self.view.set_mesh(state, mesh);
+            }
 
             let outer_height = ui.available_height();
 
             ui.horizontal(|ui| {
-                ui.add(Image::new(SizedTexture::new(
-                    self.texture.id,
-                    Vec2::new(ui.available_width() - 300.0, outer_height),
-                )));
-                self.info_panel.render(
-                    ui,
+                let size = Vec2::new(ui.available_width() - 300.0, outer_height);
+                let response = self.view.show(ui, state, &mut self.objects, size);
+                let time = match &self.source {
+                    Source::Live(exchange) => exchange.current_time(),
+                    Source::Replay { player, .. } => player.current_time(),
+                };
+                self.ephemeris.paint(
+                    &ui.painter_at(response.rect),
+                    response.rect,
+                    self.view.camera(),
                     &self.objects,
-                    self.exchange.current_ticks(),
-                    &self.camera,
-                    self.tick,
-                    self.exchange.delta(),
+                    time,
                 );
+                ui.vertical(|ui| {
+                    let camera = self.view.camera();
+                    self.info_panel.render(
+                        ui,
+                        &self.objects,
+                        time,
+                        camera,
+                        self.view.frame(),
+                        self.locale,
+                    );
+                    match &mut self.source {
+                        Source::Live(exchange) => {
+                            self.info_panel.render_controls(
+                                ui,
+                                exchange,
+                                &self.objects,
+                                self.locale,
+                            );
+                            self.timeline
+                                .render(ui, &self.history, &mut self.objects, self.locale);
+                            self.accuracy
+                                .render(ui, exchange, &self.objects, camera, self.locale);
+                            self.groups.render(ui, exchange, self.locale);
+                            if self.density.render(ui, exchange, self.locale) {
+                                self.accessibility.reapply();
+                            }
+                            self.record.render(ui, exchange, &self.objects, self.locale);
+                        }
+                        Source::Replay {
+                            player,
+                            controls,
+                            diff,
+                        } => {
+                            controls.render(ui, player, self.locale);
+                            if let Some(diff) = diff {
+                                diff.render(ui, player, self.locale);
+                            }
+                        }
+                    }
+                    self.probes.render(ui, &self.objects, camera, self.locale);
+                    self.roche.render(ui, &self.objects, camera, self.locale);
+                    self.ephemeris.render(ui, &self.objects, time, self.locale);
+                    self.accessibility.render(ui, self.locale);
+                    self.rendering
+                        .render(ui, &mut self.view, &mut self.objects, self.locale);
+                    let sim_memory = match &self.source {
+                        Source::Live(exchange) => Some(exchange.sim_memory()),
+                        Source::Replay { .. } => None,
+                    };
+                    self.resources.render(
+                        ui,
+                        &self.objects,
+                        &self.history,
+                        sim_memory,
+                        self.view.gpu_memory(),
+                        self.locale,
+                    );
+                    if let Some(demo) = &mut self.demo {
+                        demo.render(ui, self.locale);
+                    }
+                });
             });
         });
         ctx.request_repaint();
     }
 }
-
-#[derive(Clone)]
-struct IntermediateTexture {
-    texture: wgpu::Texture,
-    size: PhysicalSize<u32>,
-    id: TextureId,
-}
-
-impl IntermediateTexture {
-    pub fn new(device: &wgpu::Device, size: PhysicalSize<u32>, state: &RenderState) -> Self {
-        let texture = device.create_texture(&wgpu::TextureDescriptor {
-            label: Some("Intermediate Texture"),
-            size: wgpu::Extent3d {
-                width: size.width,
-                height: size.height,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Bgra8Unorm,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
-            view_formats: &[],
-        });
-        let id = state.renderer.write().register_native_texture(
-            device,
-            &texture.create_view(&TextureViewDescriptor::default()),
-            FilterMode::Nearest,
-        );
-
-        Self { texture, id, size }
-    }
-
-    pub fn resize(&mut self, device: &wgpu::Device, size: PhysicalSize<u32>, state: &RenderState) {
-        if self.size != size {
-            self.size = size;
-            self.texture.destroy();
-            self.texture = device.create_texture(&wgpu::TextureDescriptor {
-                label: Some("Intermediate Texture"),
-                size: wgpu::Extent3d {
-                    width: size.width,
-                    height: size.height,
-                    depth_or_array_layers: 1,
-                },
-                mip_level_count: 1,
-                sample_count: 1,
-                dimension: wgpu::TextureDimension::D2,
-                format: wgpu::TextureFormat::Bgra8Unorm,
-                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
-                    | wgpu::TextureUsages::TEXTURE_BINDING,
-                view_formats: &[],
-            });
-            let mut renderer = state.renderer.write();
-            renderer.free_texture(&self.id);
-            self.id = renderer.register_native_texture(
-                device,
-                &self.texture.create_view(&TextureViewDescriptor::default()),
-                FilterMode::Nearest,
-            );
-        }
-    }
-}
@@ -0,0 +1,97 @@
+use eframe::egui::{self, Color32, Stroke, Visuals};
+
+use crate::{
+    objects::Objects,
+    palette::Palette,
+    ui::locale::{Locale, Msg},
+};
+
+/// Color palette and UI scale, for people who find the defaults hard to see.
+pub struct AccessibilityPanel {
+    palette: Palette,
+    scale: f32,
+    dirty: bool,
+}
+
+fn palette_name(palette: Palette, locale: Locale) -> &'static str {
+    locale.text(match palette {
+        Palette::Scenario => Msg::PaletteScenario,
+        Palette::ColorblindSafe => Msg::PaletteColorblindSafe,
+        Palette::HighContrast => Msg::PaletteHighContrast,
+    })
+}
+
+fn visuals(palette: Palette) -> Visuals {
+    let mut visuals = Visuals::dark();
+    if let Some([r, g, b]) = palette.accent() {
+        let accent = Color32::from_rgb((r * 255.0) as u8, (g * 255.0) as u8, (b * 255.0) as u8);
+        visuals.selection.bg_fill = accent.linear_multiply(0.6);
+        visuals.selection.stroke = Stroke::new(1.0, accent);
+        visuals.hyperlink_color = accent;
+    }
+    if palette == Palette::HighContrast {
+        visuals.override_text_color = Some(Color32::WHITE);
+        visuals.panel_fill = Color32::BLACK;
+        visuals.window_fill = Color32::BLACK;
+        visuals.extreme_bg_color = Color32::BLACK;
+        for widget in [
+            &mut visuals.widgets.noninteractive,
+            &mut visuals.widgets.inactive,
+            &mut visuals.widgets.hovered,
+            &mut visuals.widgets.active,
+        ] {
+            widget.bg_stroke = Stroke::new(1.0, Color32::WHITE);
+            widget.fg_stroke = Stroke::new(1.5, Color32::WHITE);
+        }
+    }
+    visuals
+}
+
+impl AccessibilityPanel {
+    pub fn new() -> Self {
+        Self {
+            palette: Palette::default(),
+            scale: 1.0,
+            dirty: false,
+        }
+    }
+
+    /// Apply the settings again, for objects that replaced the ones they were applied to.
+    pub fn reapply(&mut self) {
+        self.dirty = true;
+    }
+
+    /// Recolor the objects and restyle the UI, if anything changed.
+    pub fn update(&mut self, ctx: &egui::Context, objects: &mut Objects) {
+        if !self.dirty {
+            return;
+        }
+        self.dirty = false;
+        objects.apply_palette(self.palette);
+        ctx.set_visuals(visuals(self.palette));
+        ctx.set_zoom_factor(self.scale);
+    }
+
+    pub fn render(&mut self, ui: &mut egui::Ui, locale: Locale) {
+        ui.separator();
+        ui.label(locale.text(Msg::Accessibility));
+
+        let before = self.palette;
+        egui::ComboBox::from_label(locale.text(Msg::Palette))
+            .selected_text(palette_name(self.palette, locale))
+            .show_ui(ui, |ui| {
+                for palette in Palette::ALL {
+                    ui.selectable_value(&mut self.palette, palette, palette_name(palette, locale));
+                }
+            });
+        self.dirty |= before != self.palette;
+
+        // Only applied once the slider is let go, since the slider moves as the UI scales.
+        let response = ui.add(
+            egui::Slider::new(&mut self.scale, 0.5..=3.0)
+                .step_by(0.25)
+                .text(locale.text(Msg::UiScale)),
+        );
+        self.dirty |= response.drag_stopped() || (response.changed() && !response.dragged());
+    }
+}
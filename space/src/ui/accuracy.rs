@@ -0,0 +1,101 @@
+use cgmath::InnerSpace;
+use eframe::egui;
+
+use crate::{
+    batch_request::BatchRequest,
+    camera::Camera,
+    objects::Objects,
+    sim::ForceCheck,
+    ui::locale::{Locale, Msg},
+};
+
+/// Compares the tree force on the focused object to direct summation, listing the tree
+/// nodes that are furthest off.
+pub struct AccuracyPanel {
+    check: Option<ForceCheck>,
+}
+
+impl AccuracyPanel {
+    pub fn new() -> Self {
+        Self { check: None }
+    }
+
+    pub fn render(
+        &mut self,
+        ui: &mut egui::Ui,
+        exchange: &BatchRequest,
+        objects: &Objects,
+        camera: &Camera,
+        locale: Locale,
+    ) {
+        if let Some(check) = exchange.take_force_check() {
+            self.check = Some(check);
+        }
+
+        ui.separator();
+        ui.label(locale.text(Msg::ForceAccuracy));
+        let focus = camera
+            .focus()
+            .map(|f| f as usize)
+            .filter(|f| *f < objects.num_objects());
+        if ui
+            .add_enabled(
+                focus.is_some(),
+                egui::Button::new(locale.text(Msg::CheckForce)),
+            )
+            .on_hover_text(locale.text(Msg::CheckForceHint))
+            .clicked()
+            && let Some(focus) = focus
+        {
+            exchange.request_force_check(focus);
+        }
+
+        let Some(check) = &self.check else {
+            return;
+        };
+        let Some(desc) = objects.objects().get(check.object) else {
+            return;
+        };
+        ui.label(format!(
+            "{}, θ = {}",
+            desc.name,
+            locale.number(check.theta, 2)
+        ));
+        ui.label(format!(
+            "{}: {}",
+            locale.text(Msg::RelativeError),
+            locale.scientific(check.relative_error(), 2)
+        ));
+        ui.label(format!(
+            "{}: {}",
+            locale.text(Msg::Interactions),
+            check.interactions
+        ));
+
+        let exact = check.exact.magnitude();
+        ui.label(locale.text(Msg::WorstNodes))
+            .on_hover_text(locale.text(Msg::WorstNodesHint));
+        egui::Grid::new("force_check_nodes")
+            .striped(true)
+            .show(ui, |ui| {
+                for msg in [
+                    Msg::NodeMass,
+                    Msg::NodeDistance,
+                    Msg::NodeSize,
+                    Msg::NodeForce,
+                    Msg::NodeError,
+                ] {
+                    ui.label(locale.text(msg));
+                }
+                ui.end_row();
+                for node in &check.worst_nodes {
+                    ui.label(locale.scientific(node.mass, 2));
+                    ui.label(locale.scientific(node.distance, 2));
+                    ui.label(locale.scientific(node.size, 2));
+                    ui.label(locale.scientific(node.acc.magnitude() / exact, 2));
+                    ui.label(locale.scientific(node.error.magnitude() / exact, 2));
+                    ui.end_row();
+                }
+            });
+    }
+}
@@ -0,0 +1,217 @@
+use std::{
+    f32::consts::TAU,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use cgmath::{Point3, Vector3};
+use eframe::egui;
+
+use crate::{
+    Object,
+    batch_request::BatchRequest,
+    camera::Camera,
+    constants::DELTA,
+    live::LiveSim,
+    objects::Objects,
+    presets,
+    rng::RngService,
+    ui::locale::{Locale, Msg},
+};
+
+/// Camera circling the origin during a demo step.
+#[derive(Debug, Clone, Copy)]
+pub struct CameraPath {
+    /// Distance from the origin at the start and end of the step, in AU.
+    pub distance: (f32, f32),
+    /// Angle above the xy plane, in radians.
+    pub elevation: f32,
+    /// Turns around the z axis over the whole step.
+    pub turns: f32,
+}
+
+impl CameraPath {
+    fn apply(&self, camera: &mut Camera, progress: f32) {
+        // Ease in and out, so steps don't start or end with a jolt.
+        let eased = progress * progress * (3.0 - 2.0 * progress);
+        let distance = self.distance.0 + (self.distance.1 - self.distance.0) * eased;
+        let azimuth = TAU * self.turns * eased;
+        let horizontal = distance * self.elevation.cos();
+        camera.look_at(
+            Point3::new(
+                horizontal * azimuth.cos(),
+                horizontal * azimuth.sin(),
+                distance * self.elevation.sin(),
+            ),
+            Point3::new(0.0, 0.0, 0.0),
+            Vector3::unit_z(),
+        );
+    }
+}
+
+/// One scenario shown by the demo, and how to show it.
+pub struct DemoStep {
+    pub name: &'static str,
+    pub scenario: fn(&RngService) -> Vec<Object>,
+    pub duration: Duration,
+    pub camera: CameraPath,
+    /// Time per tick at the start and end of the step, sped up gradually in between.
+    pub delta: (f64, f64),
+}
+
+impl DemoStep {
+    /// The presets shown by `--demo`.
+    pub fn presets() -> Vec<DemoStep> {
+        vec![
+            DemoStep {
+                name: "Earth, Sun and Mars",
+                scenario: |_| presets::earth_sun_mars(),
+                duration: Duration::from_secs(45),
+                camera: CameraPath {
+                    distance: (4.0, 2.5),
+                    elevation: 0.5,
+                    turns: 0.25,
+                },
+                delta: (DELTA * 100.0, DELTA * 1000.0),
+            },
+            DemoStep {
+                name: "Asteroid belt",
+                scenario: presets::earth_sun_mars_ast,
+                duration: Duration::from_secs(60),
+                camera: CameraPath {
+                    distance: (9.0, 5.0),
+                    elevation: 0.9,
+                    turns: 0.5,
+                },
+                delta: (DELTA * 100.0, DELTA * 500.0),
+            },
+            DemoStep {
+                name: "Star cluster",
+                scenario: |rng| {
+                    let imf = presets::Imf::Kroupa {
+                        min: 0.1,
+                        max: 20.0,
+                    };
+                    presets::plummer_cluster(300, 100.0, imf, rng)
+                },
+                duration: Duration::from_secs(60),
+                camera: CameraPath {
+                    distance: (1500.0, 600.0),
+                    elevation: 0.3,
+                    turns: 1.0,
+                },
+                delta: (DELTA * 1e3, DELTA * 1e4),
+            },
+            DemoStep {
+                name: "Galaxy collision",
+                scenario: |rng| {
+                    presets::galaxy_collision(&presets::GalaxyCollision::new(2000), rng)
+                },
+                duration: Duration::from_secs(60),
+                camera: CameraPath {
+                    distance: (1.6e10, 8e9),
+                    elevation: 0.6,
+                    turns: 0.25,
+                },
+                delta: (DELTA * 1e10, DELTA * 1e11),
+            },
+            DemoStep {
+                name: "Rotating cloud",
+                scenario: |rng| presets::fixed_cloud(10000, presets::CLOUD_PARTICLE_MASS, rng),
+                duration: Duration::from_secs(60),
+                camera: CameraPath {
+                    distance: (70.0, 40.0),
+                    elevation: 0.2,
+                    turns: 0.5,
+                },
+                delta: (DELTA, DELTA * 10.0),
+            },
+        ]
+    }
+}
+
+/// Cycles through a list of scenarios on a timer, moving the camera and speeding up
+/// time as it goes. Meant to be left running unattended.
+pub struct Demo {
+    steps: Vec<DemoStep>,
+    rng: RngService,
+    current: usize,
+    started: Instant,
+    sim: LiveSim,
+    skip: bool,
+}
+
+impl Demo {
+    /// Start the first step, returning the demo along with the objects to show.
+    pub fn start(steps: Vec<DemoStep>, rng: RngService) -> anyhow::Result<(Self, Objects)> {
+        anyhow::ensure!(!steps.is_empty(), "The demo has no steps");
+        let (sim, objects) = Self::spawn(&steps[0], &rng)?;
+        let demo = Self {
+            steps,
+            rng,
+            current: 0,
+            started: Instant::now(),
+            sim,
+            skip: false,
+        };
+        Ok((demo, objects))
+    }
+
+    fn spawn(step: &DemoStep, rng: &RngService) -> anyhow::Result<(LiveSim, Objects)> {
+        let objects = (step.scenario)(&rng.child(step.name));
+        println!("Demo showing {} with {} objects", step.name, objects.len());
+        let sim = LiveSim::spawn(&objects)?;
+        sim.exchange().set_delta(step.delta.0);
+        Ok((sim, Objects::new(&objects)))
+    }
+
+    pub fn exchange(&self) -> &Arc<BatchRequest> {
+        self.sim.exchange()
+    }
+
+    fn progress(&self) -> f32 {
+        let duration = self.steps[self.current].duration.as_secs_f32();
+        (self.started.elapsed().as_secs_f32() / duration).min(1.0)
+    }
+
+    /// Move on to the next step once the current one is over. Returns the objects of
+    /// the new step, which are simulated through `exchange`.
+    pub fn update(&mut self) -> anyhow::Result<Option<Objects>> {
+        if !self.skip && self.progress() < 1.0 {
+            return Ok(None);
+        }
+        self.skip = false;
+        self.started = Instant::now();
+        let next = (self.current + 1) % self.steps.len();
+        let (sim, objects) = Self::spawn(&self.steps[next], &self.rng)?;
+        // The old simulation stops when it is replaced.
+        self.sim = sim;
+        self.current = next;
+        Ok(Some(objects))
+    }
+
+    /// Move the camera along the path of the current step, and set its time per tick.
+    pub fn animate(&self, camera: &mut Camera) {
+        let step = &self.steps[self.current];
+        let progress = self.progress();
+        step.camera.apply(camera, progress);
+        let (start, end) = step.delta;
+        self.exchange()
+            .set_delta(start * (end / start).powf(progress as f64));
+    }
+
+    pub fn render(&mut self, ui: &mut egui::Ui, locale: Locale) {
+        ui.separator();
+        let step = &self.steps[self.current];
+        let remaining = step.duration.saturating_sub(self.started.elapsed());
+        ui.label(format!(
+            "{}: {} ({} s)",
+            locale.text(Msg::Demo),
+            step.name,
+            remaining.as_secs()
+        ));
+        if ui.button(locale.text(Msg::DemoNext)).clicked() {
+            self.skip = true;
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use eframe::egui;
+
+use crate::{
+    batch_request::BatchRequest,
+    constants::{AU, DENSITY_COLOR_CLIP, M0},
+    objects::Objects,
+    ui::locale::{Locale, Msg},
+};
+
+/// Colors from the lowest density to the highest, evenly spaced on a log scale. The
+/// lowest is kept bright enough to see against the background.
+const DENSITY_COLORS: [[f32; 3]; 4] = [
+    [0.15, 0.2, 0.6],
+    [0.6, 0.15, 0.6],
+    [0.95, 0.4, 0.1],
+    [1.0, 0.95, 0.4],
+];
+
+/// Color for `t` between 0 and 1, interpolated between `DENSITY_COLORS`.
+fn density_color(t: f32) -> [f32; 3] {
+    let scaled = t.clamp(0.0, 1.0) * (DENSITY_COLORS.len() - 1) as f32;
+    let i = (scaled as usize).min(DENSITY_COLORS.len() - 2);
+    let f = scaled - i as f32;
+    let (a, b) = (DENSITY_COLORS[i], DENSITY_COLORS[i + 1]);
+    std::array::from_fn(|c| a[c] + (b[c] - a[c]) * f)
+}
+
+/// Colors the objects of a live simulation by the density around them, as estimated by
+/// the simulation at each sample.
+pub struct DensityPanel {
+    enabled: bool,
+    /// Densities given the lowest and highest color the last time, in kg/m^3.
+    range: Option<(f64, f64)>,
+}
+
+impl DensityPanel {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            range: None,
+        }
+    }
+
+    /// Recolor the objects with the densities estimated since the last frame.
+    pub fn update(&mut self, exchange: &BatchRequest, objects: &mut Objects) {
+        let Some(densities) = exchange.take_densities() else {
+            return;
+        };
+        if !self.enabled {
+            return;
+        }
+        let mut logs: Vec<_> = densities
+            .iter()
+            .filter(|d| d.is_finite() && **d > 0.0)
+            .map(|d| d.ln())
+            .collect();
+        if logs.is_empty() {
+            return;
+        }
+        logs.sort_unstable_by(f32::total_cmp);
+        let clip = (logs.len() as f64 * DENSITY_COLOR_CLIP) as usize;
+        let (low, high) = (logs[clip], logs[logs.len() - 1 - clip]);
+        let span = (high - low).max(f32::EPSILON);
+        for (idx, density) in densities.iter().enumerate().take(objects.num_objects()) {
+            // Objects with no massive neighbors at all are as sparse as it gets, and those
+            // on top of their neighbors as dense.
+            let t = match density {
+                d if *d <= 0.0 => 0.0,
+                d => (d.ln() - low) / span,
+            };
+            objects.set_color(idx, density_color(t));
+        }
+        let kg_m3 = M0 / (AU * AU * AU);
+        self.range = Some((low.exp() as f64 * kg_m3, high.exp() as f64 * kg_m3));
+    }
+
+    /// Returns whether the objects should get their own colors back.
+    pub fn render(&mut self, ui: &mut egui::Ui, exchange: &BatchRequest, locale: Locale) -> bool {
+        ui.separator();
+        let changed = ui
+            .checkbox(&mut self.enabled, locale.text(Msg::ColorDensity))
+            .on_hover_text(locale.text(Msg::ColorDensityHint))
+            .changed();
+        if changed {
+            exchange.set_densities_wanted(self.enabled);
+            self.range = None;
+        }
+        if let Some((low, high)) = self.range {
+            ui.label(format!(
+                "{} – {} kg/m³",
+                locale.scientific(low, 2),
+                locale.scientific(high, 2)
+            ));
+        }
+        changed && !self.enabled
+    }
+}
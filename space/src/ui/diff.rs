@@ -0,0 +1,128 @@
+use eframe::egui::{self, Color32, Pos2, Sense, Stroke, Vec2};
+
+use crate::{
+    diff::RecordingDiff,
+    objects::Objects,
+    replay::ReplayPlayer,
+    ui::locale::{Locale, Msg},
+};
+
+/// Divergences below this fraction of the largest divergence are drawn as zero.
+const DIFF_FLOOR: f32 = 1e-4;
+
+const LOW_COLOR: [f32; 3] = [0.2, 0.4, 1.0];
+const HIGH_COLOR: [f32; 3] = [1.0, 0.2, 0.1];
+
+/// Map a divergence to 0..1 on a log scale relative to `max`.
+fn scale(value: f32, max: f32) -> f32 {
+    let floor = max * DIFF_FLOOR;
+    if value <= floor || max <= 0.0 {
+        return 0.0;
+    }
+    ((value / floor).ln() / (1.0 / DIFF_FLOOR).ln()).min(1.0)
+}
+
+fn color_for(t: f32) -> [f32; 3] {
+    std::array::from_fn(|i| LOW_COLOR[i] + (HIGH_COLOR[i] - LOW_COLOR[i]) * t)
+}
+
+/// Shows how far objects in a replayed recording are from the same objects in
+/// a second recording. Objects are colored by their current divergence, and
+/// the divergence over the whole run is plotted below the replay controls.
+pub struct DiffPanel {
+    diff: RecordingDiff,
+    shown_frame: Option<usize>,
+    divergence: Vec<f32>,
+    error: Option<String>,
+}
+
+impl DiffPanel {
+    pub fn new(diff: RecordingDiff) -> Self {
+        Self {
+            diff,
+            shown_frame: None,
+            divergence: Vec::new(),
+            error: None,
+        }
+    }
+
+    /// Recolor objects by divergence whenever the player moves to a new frame.
+    pub fn update(&mut self, player: &ReplayPlayer, objects: &mut Objects) {
+        if self.shown_frame == Some(player.frame()) {
+            return;
+        }
+        self.shown_frame = Some(player.frame());
+
+        match self.diff.divergence(
+            player.current_ticks(),
+            player.current_positions(),
+            &mut self.divergence,
+        ) {
+            Ok(true) => {
+                let max = self.diff.max_divergence();
+                for (idx, d) in self.divergence.iter().enumerate() {
+                    objects.set_color(idx, color_for(scale(*d, max)));
+                }
+            }
+            Ok(false) => (),
+            Err(e) => self.error = Some(format!("{e:#}")),
+        }
+    }
+
+    pub fn render(&mut self, ui: &mut egui::Ui, player: &mut ReplayPlayer, locale: Locale) {
+        ui.separator();
+        ui.label(locale.text(Msg::Divergence));
+
+        let samples = self.diff.samples();
+        let max = self.diff.max_divergence();
+        ui.label(format!(
+            "{}: {} AU",
+            locale.text(Msg::LargestDivergence),
+            locale.scientific(max as f64, 3)
+        ));
+
+        let (response, painter) = ui.allocate_painter(
+            Vec2::new(ui.available_width(), 120.0),
+            Sense::click_and_drag(),
+        );
+        let rect = response.rect;
+        painter.rect_filled(rect, 0.0, Color32::from_gray(20));
+
+        let first = samples[0].tick as f32;
+        let span = (samples[samples.len() - 1].tick as f32 - first).max(1.0);
+        let to_screen = |tick: u64, value: f32| {
+            Pos2::new(
+                rect.left() + rect.width() * (tick as f32 - first) / span,
+                rect.bottom() - rect.height() * scale(value, max),
+            )
+        };
+
+        let max_line: Vec<_> = samples.iter().map(|s| to_screen(s.tick, s.max)).collect();
+        let mean_line: Vec<_> = samples.iter().map(|s| to_screen(s.tick, s.mean)).collect();
+        painter.add(egui::Shape::line(
+            max_line,
+            Stroke::new(1.0, Color32::LIGHT_RED),
+        ));
+        painter.add(egui::Shape::line(
+            mean_line,
+            Stroke::new(1.0, Color32::LIGHT_BLUE),
+        ));
+
+        let x = to_screen(player.current_ticks(), 0.0).x;
+        painter.vline(x, rect.y_range(), Stroke::new(1.0, Color32::WHITE));
+
+        if let Some(pos) = response.interact_pointer_pos() {
+            let frac = ((pos.x - rect.left()) / rect.width()).clamp(0.0, 1.0);
+            player.seek_tick((first + frac * span) as u64);
+        }
+
+        ui.horizontal(|ui| {
+            ui.colored_label(Color32::LIGHT_RED, locale.text(Msg::DivergenceMax));
+            ui.colored_label(Color32::LIGHT_BLUE, locale.text(Msg::DivergenceMean));
+        });
+
+        if let Some(error) = &self.error {
+            ui.colored_label(Color32::RED, error);
+        }
+    }
+}
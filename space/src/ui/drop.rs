@@ -0,0 +1,158 @@
+use std::{
+    fs::File,
+    io::{BufReader, Seek},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use eframe::egui::{self, Align2, Color32, FontId, Id, LayerId, Order};
+
+use crate::{
+    checkpoint, recording,
+    ui::locale::{Locale, Msg},
+};
+
+/// What a file dropped onto the window was recognized as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DroppedKind {
+    Recording,
+    /// A TOML or JSON scenario file, see `scenario_file`.
+    Scenario,
+    Checkpoint,
+}
+
+impl DroppedKind {
+    /// Recognize the file at `path` by its contents, rather than trusting the extension.
+    /// Scenario files are text, so they are recognized by their extension instead.
+    fn detect(path: &Path) -> anyhow::Result<Self> {
+        let file =
+            File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        let mut reader = BufReader::new(file);
+        if recording::is_recording(&mut reader) {
+            return Ok(Self::Recording);
+        }
+        reader.rewind()?;
+        if checkpoint::is_checkpoint(&mut reader) {
+            return Ok(Self::Checkpoint);
+        }
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml" | "json") => Ok(Self::Scenario),
+            _ => anyhow::bail!(
+                "{} is not a recording, a checkpoint or a scenario file",
+                path.display()
+            ),
+        }
+    }
+
+    fn describe(&self) -> Msg {
+        match self {
+            Self::Recording => Msg::DropReplay,
+            Self::Scenario => Msg::DropScenario,
+            Self::Checkpoint => Msg::DropCheckpoint,
+        }
+    }
+}
+
+/// Picks up files dropped onto the window, and asks before opening them.
+pub struct DropHandler {
+    pending: Option<(PathBuf, DroppedKind)>,
+    error: Option<String>,
+}
+
+impl DropHandler {
+    pub fn new() -> Self {
+        Self {
+            pending: None,
+            error: None,
+        }
+    }
+
+    /// Show a hint while files are dragged over the window, and a confirmation dialog
+    /// once one is dropped. Returns the file when the user chooses to open it.
+    pub fn update(
+        &mut self,
+        ctx: &egui::Context,
+        locale: Locale,
+    ) -> Option<(PathBuf, DroppedKind)> {
+        let (hovering, dropped) = ctx.input(|i| {
+            (
+                !i.raw.hovered_files.is_empty(),
+                i.raw.dropped_files.first().cloned(),
+            )
+        });
+
+        if hovering {
+            let painter = ctx.layer_painter(LayerId::new(Order::Foreground, Id::new("drop")));
+            let rect = ctx.screen_rect();
+            painter.rect_filled(rect, 0.0, Color32::from_black_alpha(160));
+            painter.text(
+                rect.center(),
+                Align2::CENTER_CENTER,
+                locale.text(Msg::DropHint),
+                FontId::proportional(24.0),
+                Color32::WHITE,
+            );
+        }
+
+        if let Some(file) = dropped {
+            self.pending = None;
+            self.error = None;
+            match file.path {
+                Some(path) => match DroppedKind::detect(&path) {
+                    Ok(kind) => self.pending = Some((path, kind)),
+                    Err(e) => self.error = Some(format!("{e:#}")),
+                },
+                None => {
+                    self.error = Some(format!(
+                        "{}: {}",
+                        locale.text(Msg::DroppedWithoutPath),
+                        file.name
+                    ))
+                }
+            }
+        }
+
+        let mut accepted = None;
+        if let Some((path, kind)) = &self.pending {
+            let mut close = false;
+            let modal = egui::Modal::new(Id::new("drop_confirm")).show(ctx, |ui| {
+                ui.heading(locale.text(Msg::OpenFile));
+                ui.label(path.file_name().unwrap_or_default().to_string_lossy());
+                ui.label(locale.text(kind.describe()));
+                ui.horizontal(|ui| {
+                    if ui.button(locale.text(Msg::Open)).clicked() {
+                        accepted = Some((path.clone(), *kind));
+                        close = true;
+                    }
+                    if ui.button(locale.text(Msg::Cancel)).clicked() {
+                        close = true;
+                    }
+                });
+            });
+            if close || modal.should_close() {
+                self.pending = None;
+            }
+        }
+
+        if let Some(error) = &self.error {
+            let mut close = false;
+            let modal = egui::Modal::new(Id::new("drop_error")).show(ctx, |ui| {
+                ui.heading(locale.text(Msg::CannotOpen));
+                ui.colored_label(Color32::RED, error);
+                if ui.button(locale.text(Msg::Ok)).clicked() {
+                    close = true;
+                }
+            });
+            if close || modal.should_close() {
+                self.error = None;
+            }
+        }
+
+        accepted
+    }
+
+    /// Report that an accepted file could not be opened after all.
+    pub fn set_error(&mut self, error: anyhow::Error) {
+        self.error = Some(format!("{error:#}"));
+    }
+}
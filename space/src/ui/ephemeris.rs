@@ -0,0 +1,115 @@
+use cgmath::{InnerSpace, Point3, Vector3};
+use eframe::egui::{self, Color32, Painter, Pos2, Rect, Stroke};
+
+use crate::{
+    camera::Camera,
+    constants::{AU, EPHEMERIS_MARKER_RADIUS},
+    ephemeris::Ephemeris,
+    objects::Objects,
+    sim::SimTime,
+    ui::locale::{Locale, Msg},
+};
+
+/// Shows where HORIZONS has the bodies it was given tables for, next to where the
+/// simulation has them.
+pub struct EphemerisPanel {
+    /// Reference positions, with the index of the object they are for.
+    tracks: Vec<(usize, Ephemeris)>,
+    show_markers: bool,
+}
+
+impl EphemerisPanel {
+    pub fn new(tracks: Vec<(usize, Ephemeris)>) -> Self {
+        Self {
+            tracks,
+            show_markers: true,
+        }
+    }
+
+    /// Position the view is centered on, the target object if there is one.
+    fn offset(objects: &Objects) -> Vector3<f64> {
+        objects
+            .target_object()
+            .map_or(Vector3::new(0.0, 0.0, 0.0), |t| {
+                Vector3::from(*objects.position_of(t)).cast().unwrap()
+            })
+    }
+
+    /// Mark the dates of each table that have been reached, and draw a line from where
+    /// each body should be at `time` to where it is. `rect` is where the view is shown.
+    pub fn paint(
+        &self,
+        painter: &Painter,
+        rect: Rect,
+        camera: &Camera,
+        objects: &Objects,
+        time: SimTime,
+    ) {
+        if !self.show_markers {
+            return;
+        }
+        let offset = Self::offset(objects);
+        let to_screen = |pos: Point3<f64>| {
+            let ndc = camera.project((pos - offset).cast().unwrap())?;
+            Some(Pos2::new(
+                rect.left() + (ndc.x + 1.0) / 2.0 * rect.width(),
+                rect.top() + (1.0 - ndc.y) / 2.0 * rect.height(),
+            ))
+        };
+        for (idx, ephemeris) in &self.tracks {
+            let Some(desc) = objects.descriptions().get(*idx) else {
+                continue;
+            };
+            let [r, g, b] = desc.color.map(|c| (c.clamp(0.0, 1.0) * 255.0) as u8);
+            let stroke = Stroke::new(1.0, Color32::from_rgb(r, g, b));
+            let reached = ephemeris
+                .records()
+                .iter()
+                .take_while(|record| record.seconds <= time.seconds);
+            for record in reached {
+                if let Some(pos) = to_screen(record.pos) {
+                    painter.circle_stroke(pos, EPHEMERIS_MARKER_RADIUS, stroke);
+                }
+            }
+            let simulated = Point3::from(*objects.position_of(*idx)).cast().unwrap();
+            if let Some(expected) = ephemeris.position_at(time.seconds)
+                && let (Some(from), Some(to)) = (to_screen(expected), to_screen(simulated))
+            {
+                painter.line_segment([from, to], stroke);
+            }
+        }
+    }
+
+    pub fn render(&mut self, ui: &mut egui::Ui, objects: &Objects, time: SimTime, locale: Locale) {
+        if self.tracks.is_empty() {
+            return;
+        }
+        ui.separator();
+        ui.label(locale.text(Msg::Ephemeris));
+        ui.checkbox(&mut self.show_markers, locale.text(Msg::EphemerisMarkers))
+            .on_hover_text(locale.text(Msg::EphemerisMarkersHint));
+        egui::Grid::new("ephemeris_deviation")
+            .striped(true)
+            .show(ui, |ui| {
+                ui.label(locale.text(Msg::PullingBody));
+                ui.label(locale.text(Msg::EphemerisDeviation));
+                ui.end_row();
+                for (idx, ephemeris) in &self.tracks {
+                    let Some(object) = objects.objects().get(*idx) else {
+                        continue;
+                    };
+                    ui.label(&object.name);
+                    let simulated: Point3<f64> =
+                        Point3::from(*objects.position_of(*idx)).cast().unwrap();
+                    match ephemeris.position_at(time.seconds) {
+                        Some(expected) => ui.label(format!(
+                            "{} km",
+                            locale.number((simulated - expected).magnitude() * AU / 1e3, 0)
+                        )),
+                        None => ui.label(locale.text(Msg::EphemerisOutside)),
+                    };
+                    ui.end_row();
+                }
+            });
+    }
+}
@@ -0,0 +1,128 @@
+use eframe::egui;
+
+use crate::{
+    batch_request::BatchRequest,
+    constants::{FOF_LINKING_FACTOR, FOF_LISTED_GROUPS},
+    objects::Objects,
+    sim::FofGroups,
+    ui::locale::{Locale, Msg},
+};
+
+/// Color of objects in no group while the groups are colored.
+const UNGROUPED_COLOR: [f32; 3] = [0.3, 0.3, 0.3];
+
+/// Color of the group at `index`. Hues are spread by the golden ratio, so that groups
+/// close in the list get colors far apart.
+fn group_color(index: usize) -> [f32; 3] {
+    let hue = (index as f32 * 0.618_034).fract() * 6.0;
+    let x = 1.0 - (hue % 2.0 - 1.0).abs();
+    match hue as u32 {
+        0 => [1.0, x, 0.0],
+        1 => [x, 1.0, 0.0],
+        2 => [0.0, 1.0, x],
+        3 => [0.0, x, 1.0],
+        4 => [x, 0.0, 1.0],
+        _ => [1.0, 0.0, x],
+    }
+}
+
+/// Finds friends-of-friends groups in a live simulation on request, and lists the
+/// largest of them. Objects can be colored by group.
+pub struct GroupsPanel {
+    linking_factor: f64,
+    groups: Option<FofGroups>,
+    color: bool,
+    /// Whether the objects need recoloring, since the groups or `color` changed.
+    recolor: bool,
+}
+
+impl GroupsPanel {
+    pub fn new() -> Self {
+        Self {
+            linking_factor: FOF_LINKING_FACTOR,
+            groups: None,
+            color: false,
+            recolor: false,
+        }
+    }
+
+    /// Take groups found since the last frame, and recolor the objects if needed. Returns
+    /// whether the objects should get their own colors back.
+    pub fn update(&mut self, exchange: &BatchRequest, objects: &mut Objects) -> bool {
+        if let Some(groups) = exchange.take_groups() {
+            self.groups = Some(groups);
+            self.recolor = true;
+        }
+        if !std::mem::take(&mut self.recolor) {
+            return false;
+        }
+        match &self.groups {
+            Some(groups) if self.color => {
+                for idx in 0..objects.num_objects() {
+                    let group = groups.membership.get(idx).copied().flatten();
+                    objects.set_color(idx, group.map_or(UNGROUPED_COLOR, group_color));
+                }
+                false
+            }
+            _ => true,
+        }
+    }
+
+    pub fn render(&mut self, ui: &mut egui::Ui, exchange: &BatchRequest, locale: Locale) {
+        ui.separator();
+        ui.label(locale.text(Msg::Groups));
+        ui.add(
+            egui::Slider::new(&mut self.linking_factor, 0.02..=1.0)
+                .logarithmic(true)
+                .text(locale.text(Msg::LinkingFactor)),
+        )
+        .on_hover_text(locale.text(Msg::LinkingFactorHint));
+        if ui
+            .button(locale.text(Msg::FindGroups))
+            .on_hover_text(locale.text(Msg::FindGroupsHint))
+            .clicked()
+        {
+            exchange.request_groups(self.linking_factor);
+        }
+
+        let Some(groups) = &self.groups else {
+            return;
+        };
+        ui.label(format!(
+            "{}: {}, {} {}",
+            locale.text(Msg::GroupsFound),
+            groups.groups.len(),
+            groups.grouped(),
+            locale.text(Msg::GroupedObjects)
+        ));
+        ui.label(format!(
+            "{}: {} AU",
+            locale.text(Msg::LinkingLength),
+            locale.scientific(groups.linking_length, 2)
+        ));
+        if ui
+            .checkbox(&mut self.color, locale.text(Msg::ColorGroups))
+            .changed()
+        {
+            self.recolor = true;
+        }
+        egui::Grid::new("fof_groups").striped(true).show(ui, |ui| {
+            for msg in [Msg::GroupMembers, Msg::NodeMass, Msg::GroupRadius] {
+                ui.label(locale.text(msg));
+            }
+            ui.end_row();
+            for (idx, group) in groups.groups.iter().take(FOF_LISTED_GROUPS).enumerate() {
+                let label = group.members.to_string();
+                if self.color {
+                    let [r, g, b] = group_color(idx).map(|c| (c * 255.0) as u8);
+                    ui.colored_label(egui::Color32::from_rgb(r, g, b), label);
+                } else {
+                    ui.label(label);
+                }
+                ui.label(locale.scientific(group.mass, 2));
+                ui.label(locale.scientific(group.radius, 2));
+                ui.end_row();
+            }
+        });
+    }
+}
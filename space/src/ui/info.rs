@@ -1,13 +1,64 @@
 use std::time::Instant;
 
+use cgmath::{InnerSpace, Point3, Vector3, Zero};
 use eframe::egui;
 
 use crate::{
+    batch_request::BatchRequest,
     camera::Camera,
+    constants::{AU, GRAVITY_BREAKDOWN_BODIES},
     objects::Objects,
-    sim::{ElapsedTime, compute_elapsed_time},
+    orbit::RelativeState,
+    sim::{ElapsedTime, FrameTime, SimTime, SolverKind, compute_elapsed_time},
+    ui::locale::{Locale, Msg},
 };
 
+fn solver_name(solver: Option<SolverKind>, locale: Locale) -> &'static str {
+    locale.text(match solver {
+        None => Msg::SolverAutomatic,
+        Some(SolverKind::BruteForce) => Msg::SolverBruteForce,
+        Some(SolverKind::BarnesHut) => Msg::SolverBarnesHut,
+        Some(SolverKind::Fmm) => Msg::SolverFmm,
+    })
+}
+
+fn position(objects: &Objects, idx: usize) -> Point3<f64> {
+    Point3::from(*objects.position_of(idx)).cast().unwrap()
+}
+
+/// Gravity on the object at `focus` from each of the others, at their latest sampled
+/// positions. Returns the net acceleration, and the largest pulls in order.
+fn gravity_breakdown(
+    objects: &Objects,
+    focus: usize,
+) -> (Vector3<f64>, Vec<(usize, Vector3<f64>)>) {
+    let infos = objects.objects();
+    let target = &infos[focus].dat;
+    let pos = position(objects, focus);
+    let mut pulls: Vec<_> = infos
+        .iter()
+        .enumerate()
+        .filter(|(idx, obj)| *idx != focus && obj.dat.gravitating_mass() > 0.0)
+        .map(|(idx, obj)| {
+            let rel = position(objects, idx) - pos;
+            let mut acc = Vector3::zero();
+            target.get_acc_towards_raw(obj.dat.gravitating_mass(), rel, rel.magnitude2(), &mut acc);
+            (idx, acc)
+        })
+        .collect();
+    let net = pulls.iter().map(|(_, acc)| acc).sum();
+
+    let by_size = |a: &(usize, Vector3<f64>), b: &(usize, Vector3<f64>)| {
+        b.1.magnitude2().total_cmp(&a.1.magnitude2())
+    };
+    if pulls.len() > GRAVITY_BREAKDOWN_BODIES {
+        pulls.select_nth_unstable_by(GRAVITY_BREAKDOWN_BODIES, by_size);
+        pulls.truncate(GRAVITY_BREAKDOWN_BODIES);
+    }
+    pulls.sort_by(by_size);
+    (net, pulls)
+}
+
 pub struct InfoPanel {
     pub last_tick: u64,
     pub last_update: Instant,
@@ -16,6 +67,20 @@ pub struct InfoPanel {
 
     pub last_time: ElapsedTime,
     pub last_time_per_second: ElapsedTime,
+    /// Simulated seconds per second of wall time.
+    pub last_real_time_factor: f64,
+    /// Steps per sample to use when the simulation is locked to the frame rate.
+    pub substeps: u64,
+    /// Number of simulation threads to use when it is set by hand.
+    pub fixed_threads: usize,
+
+    /// Object the gravity breakdown is for, the net gravity on it, and the largest pulls.
+    pub breakdown_focus: Option<usize>,
+    pub net_gravity: Vector3<f64>,
+    pub largest_pulls: Vec<(usize, Vector3<f64>)>,
+
+    /// State of the focused object relative to its dominant attractor, in a live run.
+    pub orbit: Option<RelativeState>,
 }
 
 impl InfoPanel {
@@ -28,6 +93,26 @@ impl InfoPanel {
 
             last_time: ElapsedTime::default(),
             last_time_per_second: ElapsedTime::default(),
+            last_real_time_factor: 0.0,
+            substeps: 100,
+            fixed_threads: std::thread::available_parallelism().map_or(1, |n| n.get()),
+
+            breakdown_focus: None,
+            net_gravity: Vector3::zero(),
+            largest_pulls: Vec::new(),
+
+            orbit: None,
+        }
+    }
+
+    /// Ask the simulation for the orbit of the focused object, and pick up the latest.
+    pub fn update_orbit(&mut self, exchange: &BatchRequest, focus: Option<usize>) {
+        if exchange.orbit_focus() != focus {
+            exchange.set_orbit_focus(focus);
+            self.orbit = None;
+        }
+        if let Some(orbit) = exchange.take_orbit() {
+            self.orbit = Some(orbit);
         }
     }
 
@@ -35,43 +120,342 @@ impl InfoPanel {
         &mut self,
         ui: &mut egui::Ui,
         objects: &Objects,
-        tick: u64,
+        time: SimTime,
         camera: &Camera,
-        ui_tick: u32,
-        delta: f64,
+        frame: FrameTime,
+        locale: Locale,
     ) {
         let upd_time = Instant::now();
         let elapsed = upd_time.duration_since(self.last_update);
-        let ticks_elapsed = tick - self.last_tick;
+        let ticks_elapsed = time.ticks.saturating_sub(self.last_tick);
 
         self.tick_rates[self.tick_rate_index] = (ticks_elapsed as f64) / elapsed.as_secs_f64();
         self.tick_rate_index = (self.tick_rate_index + 1) % self.tick_rates.len();
 
-        self.last_tick = tick;
+        self.last_tick = time.ticks;
         self.last_update = upd_time;
 
         let avg_tick_rate = self.tick_rates.iter().sum::<f64>() / self.tick_rates.len() as f64;
 
         ui.vertical(|ui| {
-            if ui_tick % 10 == 0 {
-                self.last_time = compute_elapsed_time(tick as f64, delta);
-                self.last_time_per_second = compute_elapsed_time(avg_tick_rate, delta);
+            if frame.every(10) {
+                self.last_time = time.elapsed();
+                self.last_time_per_second = compute_elapsed_time(avg_tick_rate, time.delta);
+                self.last_real_time_factor = avg_tick_rate * time.delta;
             }
-            ui.label(format!("Current time: {}", self.last_time));
             ui.label(format!(
-                "Simulated time per second: {}",
-                self.last_time_per_second
+                "{}: {}",
+                locale.text(Msg::CurrentTime),
+                locale.elapsed(&self.last_time)
+            ));
+            ui.label(format!(
+                "{}: {}",
+                locale.text(Msg::SimulatedTimePerSecond),
+                locale.elapsed(&self.last_time_per_second)
+            ));
+            ui.label(format!(
+                "{}: {}",
+                locale.text(Msg::RealTimeFactor),
+                locale.scientific(self.last_real_time_factor, 3)
             ));
             ui.label(format!(
-                "Current time per tick: {}",
-                compute_elapsed_time(1.0, delta)
+                "{}: {}",
+                locale.text(Msg::TimePerTick),
+                locale.elapsed(&compute_elapsed_time(1.0, time.delta))
             ));
 
             if let Some(focus) = camera.focus()
                 && let Some(desc) = objects.objects().get(focus as usize)
             {
-                ui.label(format!("Focused object: {}", desc.name));
+                ui.label(format!(
+                    "{}: {}",
+                    locale.text(Msg::FocusedObject),
+                    desc.name
+                ));
+                self.render_breakdown(ui, objects, focus as usize, frame, locale);
+                self.render_orbit(ui, objects, focus as usize, time, locale);
             }
         });
     }
+
+    /// Net gravity on the focused object, and how much of it comes from each of the
+    /// bodies pulling hardest.
+    fn render_breakdown(
+        &mut self,
+        ui: &mut egui::Ui,
+        objects: &Objects,
+        focus: usize,
+        frame: FrameTime,
+        locale: Locale,
+    ) {
+        if frame.every(10) || self.breakdown_focus != Some(focus) {
+            (self.net_gravity, self.largest_pulls) = gravity_breakdown(objects, focus);
+            self.breakdown_focus = Some(focus);
+        }
+
+        let net = self.net_gravity.magnitude();
+        ui.label(format!(
+            "{}: {} m/s²",
+            locale.text(Msg::NetGravity),
+            locale.scientific(net * AU, 3)
+        ))
+        .on_hover_text(locale.text(Msg::NetGravityHint));
+        if net == 0.0 {
+            return;
+        }
+
+        // Shares are the part of each pull along the net gravity, so they add up to 100 %.
+        let share = |acc: Vector3<f64>| acc.dot(self.net_gravity) / (net * net) * 100.0;
+        egui::Grid::new("gravity_breakdown")
+            .striped(true)
+            .show(ui, |ui| {
+                ui.label(locale.text(Msg::PullingBody));
+                ui.label("m/s²");
+                ui.label(locale.text(Msg::ShareOfGravity));
+                ui.end_row();
+                for (idx, acc) in &self.largest_pulls {
+                    // Left over from other objects, until the next update.
+                    let Some(body) = objects.objects().get(*idx) else {
+                        continue;
+                    };
+                    ui.label(&body.name);
+                    ui.label(locale.scientific(acc.magnitude() * AU, 2));
+                    ui.label(format!("{} %", locale.number(share(*acc), 1)));
+                    ui.end_row();
+                }
+                let listed: Vector3<f64> = self.largest_pulls.iter().map(|(_, acc)| acc).sum();
+                let rest = self.net_gravity - listed;
+                if rest.magnitude2() > 0.0 {
+                    ui.label(locale.text(Msg::OtherBodies));
+                    ui.label(locale.scientific(rest.magnitude() * AU, 2));
+                    ui.label(format!("{} %", locale.number(share(rest), 1)));
+                    ui.end_row();
+                }
+            });
+    }
+
+    /// Osculating orbital elements of the focused object, when they are known.
+    fn render_orbit(
+        &self,
+        ui: &mut egui::Ui,
+        objects: &Objects,
+        focus: usize,
+        time: SimTime,
+        locale: Locale,
+    ) {
+        let Some(orbit) = self.orbit.as_ref().filter(|orbit| orbit.object == focus) else {
+            return;
+        };
+        let Some(attractor) = objects.objects().get(orbit.attractor) else {
+            return;
+        };
+        let elements = orbit.elements(attractor.name.clone());
+        ui.label(format!(
+            "{}: {}",
+            locale.text(Msg::OrbitAround),
+            attractor.name
+        ))
+        .on_hover_text(locale.text(Msg::OrbitAroundHint));
+        egui::Grid::new("osculating_elements")
+            .striped(true)
+            .show(ui, |ui| {
+                let mut row = |msg: Msg, value: String| {
+                    ui.label(locale.text(msg));
+                    ui.label(value);
+                    ui.end_row();
+                };
+                row(
+                    Msg::SemiMajorAxis,
+                    format!("{} AU", locale.scientific(elements.semi_major_axis / AU, 4)),
+                );
+                row(Msg::Eccentricity, locale.number(elements.eccentricity, 4));
+                for (msg, degrees) in [
+                    (Msg::Inclination, elements.inclination),
+                    (Msg::LongAscNode, elements.long_asc_node),
+                    (Msg::ArgPeriapsis, elements.arg_periapsis),
+                    (Msg::TrueAnomaly, elements.true_anomaly(orbit.mu)),
+                ] {
+                    row(msg, format!("{}°", locale.number(degrees, 2)));
+                }
+                if let Some(period) = orbit.period() {
+                    row(
+                        Msg::OrbitalPeriod,
+                        locale.elapsed(&compute_elapsed_time(period / time.delta, time.delta)),
+                    );
+                }
+            });
+    }
+
+    /// Settings of a live simulation.
+    pub fn render_controls(
+        &mut self,
+        ui: &mut egui::Ui,
+        exchange: &BatchRequest,
+        objects: &Objects,
+        locale: Locale,
+    ) {
+        if let Some(stats) = exchange.step_stats() {
+            ui.label(format!(
+                "{}: {}",
+                locale.text(Msg::AdaptiveStep),
+                locale.elapsed(&compute_elapsed_time(1.0, stats.step))
+            ))
+            .on_hover_text(locale.text(Msg::AdaptiveStepHint));
+            ui.label(format!(
+                "{}: {} %",
+                locale.text(Msg::RejectedSteps),
+                locale.number(stats.rejection_rate * 100.0, 1)
+            ));
+        }
+        if let Some(stats) = exchange.cluster_stats() {
+            ui.label(format!(
+                "{}: {}",
+                locale.text(Msg::VirialRatio),
+                locale.number(stats.virial_ratio(), 3)
+            ))
+            .on_hover_text(locale.text(Msg::VirialRatioHint));
+            ui.label(format!(
+                "{}: {} AU",
+                locale.text(Msg::HalfMassRadius),
+                locale.number(stats.half_mass_radius, 3)
+            ));
+            ui.label(format!(
+                "{}: {} km/s",
+                locale.text(Msg::VelocityDispersion),
+                locale.number(stats.velocity_dispersion * AU / 1e3, 3)
+            ))
+            .on_hover_text(locale.text(Msg::VelocityDispersionHint));
+            // Also in ticks of the current time step, which is what it is picked by.
+            let delta = exchange.delta();
+            ui.label(format!(
+                "{}: {}",
+                locale.text(Msg::CrossingTime),
+                locale.elapsed(&compute_elapsed_time(stats.crossing_time() / delta, delta))
+            ))
+            .on_hover_text(locale.text(Msg::CrossingTimeHint));
+            if let Some(relaxation) = stats.relaxation_time() {
+                ui.label(format!(
+                    "{}: {}",
+                    locale.text(Msg::RelaxationTime),
+                    locale.elapsed(&compute_elapsed_time(relaxation / delta, delta))
+                ))
+                .on_hover_text(locale.text(Msg::RelaxationTimeHint));
+            }
+        }
+
+        let mut theta = exchange.theta();
+        if ui
+            .add(egui::Slider::new(&mut theta, 0.05..=1.5).text(locale.text(Msg::Theta)))
+            .on_hover_text(locale.text(Msg::ThetaHint))
+            .changed()
+        {
+            exchange.set_theta(theta);
+        }
+
+        let mut budget = exchange.cpu_budget() * 100.0;
+        if ui
+            .add(
+                egui::Slider::new(&mut budget, 5.0..=100.0)
+                    .suffix(" %")
+                    .text(locale.text(Msg::CpuBudget)),
+            )
+            .on_hover_text(locale.text(Msg::CpuBudgetHint))
+            .changed()
+        {
+            exchange.set_cpu_budget(budget / 100.0);
+        }
+        ui.label(format!(
+            "{}: {}",
+            locale.text(Msg::SimThreads),
+            exchange.sim_threads()
+        ));
+
+        let mut fixed = exchange.fixed_threads().is_some();
+        if let Some(threads) = exchange.fixed_threads() {
+            self.fixed_threads = threads;
+        }
+        let changed = ui
+            .horizontal(|ui| {
+                let toggled = ui
+                    .checkbox(&mut fixed, locale.text(Msg::FixedThreads))
+                    .on_hover_text(locale.text(Msg::FixedThreadsHint))
+                    .changed();
+                let edited = ui
+                    .add_enabled(
+                        fixed,
+                        egui::DragValue::new(&mut self.fixed_threads)
+                            .range(1..=1024)
+                            .speed(0.1),
+                    )
+                    .changed();
+                toggled || edited
+            })
+            .inner;
+        if changed {
+            exchange.set_fixed_threads(fixed.then_some(self.fixed_threads));
+        }
+
+        let mut solver = exchange.solver();
+        egui::ComboBox::from_label(locale.text(Msg::Solver))
+            .selected_text(solver_name(solver, locale))
+            .show_ui(ui, |ui| {
+                for option in std::iter::once(None).chain(SolverKind::ALL.map(Some)) {
+                    ui.selectable_value(&mut solver, option, solver_name(option, locale));
+                }
+            });
+        if solver != exchange.solver() {
+            exchange.set_solver(solver);
+        }
+
+        let mut reversed = exchange.reversed();
+        if ui
+            .checkbox(&mut reversed, locale.text(Msg::Rewind))
+            .on_hover_text(locale.text(Msg::RewindHint))
+            .changed()
+        {
+            exchange.set_reversed(reversed);
+        }
+
+        let mut locked = exchange.substeps().is_some();
+        let changed = ui
+            .horizontal(|ui| {
+                let toggled = ui
+                    .checkbox(&mut locked, locale.text(Msg::StepsPerFrame))
+                    .on_hover_text(locale.text(Msg::StepsPerFrameHint))
+                    .changed();
+                let edited = ui
+                    .add_enabled(
+                        locked,
+                        egui::DragValue::new(&mut self.substeps)
+                            .range(1..=1_000_000)
+                            .speed(1.0),
+                    )
+                    .changed();
+                toggled || edited
+            })
+            .inner;
+        if changed {
+            exchange.set_substeps(locked.then_some(self.substeps));
+        }
+
+        if ui
+            .button(locale.text(Msg::DumpTree))
+            .on_hover_text(locale.text(Msg::DumpTreeHint))
+            .clicked()
+        {
+            let ticks = exchange.current_time().ticks;
+            exchange.request_tree_dump(format!("octree-{ticks}.bin").into());
+        }
+        if ui
+            .button(locale.text(Msg::SaveScenario))
+            .on_hover_text(locale.text(Msg::SaveScenarioHint))
+            .clicked()
+        {
+            let ticks = exchange.current_time().ticks;
+            exchange.request_scenario_export(
+                format!("scenario-{ticks}.toml").into(),
+                objects.objects().to_vec(),
+            );
+        }
+    }
 }
@@ -0,0 +1,744 @@
+use crate::sim::ElapsedTime;
+
+/// Language of the strings shown in the app, and the number format that goes with it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Locale {
+    #[default]
+    English,
+    German,
+}
+
+/// Every string shown in the app that needs translating.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Msg {
+    Title,
+    Language,
+    CurrentTime,
+    SimulatedTimePerSecond,
+    RealTimeFactor,
+    TimePerTick,
+    FocusedObject,
+    NetGravity,
+    NetGravityHint,
+    PullingBody,
+    ShareOfGravity,
+    OtherBodies,
+    OrbitAround,
+    OrbitAroundHint,
+    SemiMajorAxis,
+    Eccentricity,
+    Inclination,
+    LongAscNode,
+    ArgPeriapsis,
+    TrueAnomaly,
+    OrbitalPeriod,
+    Theta,
+    ThetaHint,
+    CpuBudget,
+    CpuBudgetHint,
+    SimThreads,
+    FixedThreads,
+    FixedThreadsHint,
+    Solver,
+    SolverAutomatic,
+    SolverBruteForce,
+    SolverBarnesHut,
+    SolverFmm,
+    StepsPerFrame,
+    StepsPerFrameHint,
+    Rewind,
+    RewindHint,
+    AdaptiveStep,
+    AdaptiveStepHint,
+    RejectedSteps,
+    VirialRatio,
+    VirialRatioHint,
+    HalfMassRadius,
+    VelocityDispersion,
+    VelocityDispersionHint,
+    CrossingTime,
+    CrossingTimeHint,
+    RelaxationTime,
+    RelaxationTimeHint,
+    DumpTree,
+    DumpTreeHint,
+    SaveScenario,
+    SaveScenarioHint,
+    StartRecording,
+    StartRecordingHint,
+    StopRecording,
+    RecordedFrames,
+    ForceAccuracy,
+    Groups,
+    LinkingFactor,
+    LinkingFactorHint,
+    FindGroups,
+    FindGroupsHint,
+    GroupsFound,
+    GroupedObjects,
+    LinkingLength,
+    ColorGroups,
+    GroupMembers,
+    GroupRadius,
+    ColorDensity,
+    ColorDensityHint,
+    CheckForce,
+    CheckForceHint,
+    RelativeError,
+    Interactions,
+    WorstNodes,
+    WorstNodesHint,
+    NodeMass,
+    NodeDistance,
+    NodeSize,
+    NodeForce,
+    NodeError,
+    Accessibility,
+    Palette,
+    PaletteScenario,
+    PaletteColorblindSafe,
+    PaletteHighContrast,
+    UiScale,
+    Rendering,
+    Antialiasing,
+    AntialiasingHint,
+    AntialiasingOff,
+    Bloom,
+    BloomHint,
+    TrailOpacity,
+    TrailFade,
+    TrailFadeHint,
+    TrailFadeSamples,
+    TrailFadeLinear,
+    TrailFadeExponential,
+    TrailFadeTime,
+    TrailColor,
+    TrailColorHint,
+    TrailColorObject,
+    TrailColorSpeed,
+    TrailColorAcceleration,
+    Colormap,
+    FocusedTrail,
+    ShowAllTrails,
+    Demo,
+    DemoNext,
+    Resources,
+    TrailMemory,
+    TrailLength,
+    HistoryMemory,
+    HistoryInterval,
+    SimulationMemory,
+    SimulationMemoryHint,
+    GpuMemory,
+    TrailBudget,
+    TrailBudgetHint,
+    HistoryBudget,
+    HistoryBudgetHint,
+    Ephemeris,
+    EphemerisMarkers,
+    EphemerisMarkersHint,
+    EphemerisDeviation,
+    EphemerisOutside,
+    Replay,
+    Play,
+    Pause,
+    ReplaySpeed,
+    Viewing,
+    Timeline,
+    NoHistory,
+    Live,
+    ReturnToLive,
+    Divergence,
+    LargestDivergence,
+    DivergenceMax,
+    DivergenceMean,
+    DropHint,
+    OpenFile,
+    DropReplay,
+    DropScenario,
+    DropCheckpoint,
+    Open,
+    Cancel,
+    CannotOpen,
+    DroppedWithoutPath,
+    Ok,
+    Probes,
+    AttachToFocus,
+    AddFixedPoint,
+    Remove,
+    ProbeRadius,
+    Export,
+    Wrote,
+    NoSamples,
+    ProbePotential,
+    ProbeInside,
+    RocheLobes,
+    RochePrimary,
+    RocheSecondary,
+    RocheNone,
+    SetToFocus,
+    Clear,
+    RocheMassless,
+    MassRatio,
+    L1FromPrimary,
+    LobeRadii,
+}
+
+impl Locale {
+    pub const ALL: [Locale; 2] = [Locale::English, Locale::German];
+
+    /// Pick the locale from the usual environment variables, falling back to English.
+    pub fn from_env() -> Self {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .find(|v| !v.is_empty())
+            .map(|v| Self::from_tag(&v))
+            .unwrap_or_default()
+    }
+
+    /// Locale for a tag like `de_DE.UTF-8` or `de-AT`.
+    pub fn from_tag(tag: &str) -> Self {
+        match tag.split(['_', '-', '.']).next() {
+            Some("de") => Self::German,
+            _ => Self::English,
+        }
+    }
+
+    /// Name of the language, in the language itself.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::English => "English",
+            Self::German => "Deutsch",
+        }
+    }
+
+    pub fn text(self, msg: Msg) -> &'static str {
+        match self {
+            Self::English => match msg {
+                Msg::Title => "Neato space sim",
+                Msg::Language => "Language",
+                Msg::CurrentTime => "Current time",
+                Msg::SimulatedTimePerSecond => "Simulated time per second",
+                Msg::RealTimeFactor => "Real time factor",
+                Msg::TimePerTick => "Current time per tick",
+                Msg::FocusedObject => "Focused object",
+                Msg::NetGravity => "Net gravity",
+                Msg::NetGravityHint => {
+                    "Gravity on the focused object from the positions shown, with the bodies pulling hardest below. Each share is the part of a pull along the net gravity, so pulls in opposite directions cancel. Other forces are not included."
+                }
+                Msg::PullingBody => "Body",
+                Msg::ShareOfGravity => "Share",
+                Msg::OtherBodies => "Everything else",
+                Msg::OrbitAround => "Orbit around",
+                Msg::OrbitAroundHint => {
+                    "Osculating orbit around the heavier body the focused object is most tightly bound to, from its current position and velocity. Like every orbit in a simulation with more than two bodies, it changes over time."
+                }
+                Msg::SemiMajorAxis => "Semi-major axis",
+                Msg::Eccentricity => "Eccentricity",
+                Msg::Inclination => "Inclination",
+                Msg::LongAscNode => "Longitude of the ascending node",
+                Msg::ArgPeriapsis => "Argument of periapsis",
+                Msg::TrueAnomaly => "True anomaly",
+                Msg::OrbitalPeriod => "Period",
+                Msg::Theta => "Barnes-Hut theta",
+                Msg::ThetaHint => {
+                    "Smaller is more accurate, but slower. Only used by tree solvers."
+                }
+                Msg::CpuBudget => "CPU budget",
+                Msg::CpuBudgetHint => {
+                    "Share of the cores the simulation may use. Within it, the number of threads follows how fast the steps are."
+                }
+                Msg::SimThreads => "Threads",
+                Msg::FixedThreads => "Fixed threads",
+                Msg::FixedThreadsHint => {
+                    "Run the simulation on exactly this many threads, ignoring the CPU budget."
+                }
+                Msg::Solver => "Gravity solver",
+                Msg::SolverAutomatic => "Automatic",
+                Msg::SolverBruteForce => "Brute force",
+                Msg::SolverBarnesHut => "Barnes-Hut",
+                Msg::SolverFmm => "Fast multipole",
+                Msg::Rewind => "Rewind (R)",
+                Msg::RewindHint => {
+                    "Step backwards to where the run started, retracing the way here. Velocity dependent forces, escapes and injections are not undone."
+                }
+                Msg::AdaptiveStep => "Adaptive step",
+                Msg::AdaptiveStepHint => {
+                    "Length of the last step accepted by the adaptive integrator. Steps never cross the end of a tick."
+                }
+                Msg::RejectedSteps => "Rejected steps",
+                Msg::VirialRatio => "Virial ratio",
+                Msg::VirialRatioHint => {
+                    "Twice the kinetic energy over the magnitude of the potential energy, of every object that attracts others. A cluster in equilibrium has a ratio of one, it expands above that and collapses below."
+                }
+                Msg::HalfMassRadius => "Half-mass radius",
+                Msg::VelocityDispersion => "Velocity dispersion",
+                Msg::VelocityDispersionHint => {
+                    "Root mean square speed relative to the center of mass, weighted by mass, in three dimensions."
+                }
+                Msg::CrossingTime => "Crossing time",
+                Msg::CrossingTimeHint => {
+                    "Time to cross the half-mass radius at the speed of a cluster in equilibrium, with the number of ticks it takes at the current time step. A cluster usually needs at least a hundred ticks per crossing time to stay accurate. Systems dominated by one body, like a star with planets, need a step picked by their shortest orbit instead."
+                }
+                Msg::RelaxationTime => "Relaxation time",
+                Msg::RelaxationTimeHint => {
+                    "Estimated time for close encounters to change the orbits in the cluster completely, with the number of ticks it takes at the current time step. Assumes objects of similar mass."
+                }
+                Msg::DumpTree => "Dump octree",
+                Msg::DumpTreeHint => {
+                    "Write the Barnes-Hut octree of the latest step to octree-<tick>.bin in the working directory, for reproducing problems with the tree."
+                }
+                Msg::SaveScenario => "Save scenario",
+                Msg::SaveScenarioHint => {
+                    "Write the objects as they are now to scenario-<tick>.toml in the working directory, to be loaded again with --scenario."
+                }
+                Msg::StartRecording => "Record",
+                Msg::StartRecordingHint => {
+                    "Write every sample from now on to recording-<tick>.nbrec in the working directory, to be played back with --replay or by dropping it onto the window."
+                }
+                Msg::StopRecording => "Stop recording",
+                Msg::RecordedFrames => "frames recorded to",
+                Msg::ForceAccuracy => "Force accuracy",
+                Msg::Groups => "Friends-of-friends groups",
+                Msg::LinkingFactor => "linking length",
+                Msg::LinkingFactorHint => {
+                    "Objects closer than this fraction of their mean separation are friends, and groups are objects connected through friends. Around 0.2 finds the clumps that are well above the average density."
+                }
+                Msg::FindGroups => "Find groups",
+                Msg::FindGroupsHint => {
+                    "Find the groups at the current positions of the objects that attract others. Groups of only a few objects are left out."
+                }
+                Msg::GroupsFound => "Groups",
+                Msg::GroupedObjects => "objects in groups",
+                Msg::LinkingLength => "Linking length",
+                Msg::ColorGroups => "Color objects by group",
+                Msg::GroupMembers => "Objects",
+                Msg::GroupRadius => "Radius (AU)",
+                Msg::ColorDensity => "Color objects by density",
+                Msg::ColorDensityHint => {
+                    "Estimate the density around every object from its nearest massive neighbors at each sample, and color the objects from blue for the sparsest to yellow for the densest. Slows the simulation down while on."
+                }
+                Msg::CheckForce => "Check focused object",
+                Msg::CheckForceHint => {
+                    "Compute the gravity on the focused object with the Barnes-Hut tree at the current theta, and by summing over every object. Other forces are left out."
+                }
+                Msg::RelativeError => "Relative error",
+                Msg::Interactions => "Tree interactions",
+                Msg::WorstNodes => "Nodes with the largest error",
+                Msg::WorstNodesHint => {
+                    "Force and error of each node relative to the exact force, where the error is the difference from summing over the objects in the node."
+                }
+                Msg::NodeMass => "Mass (M⊕)",
+                Msg::NodeDistance => "Distance (AU)",
+                Msg::NodeSize => "Size (AU)",
+                Msg::NodeForce => "Force",
+                Msg::NodeError => "Error",
+                Msg::StepsPerFrame => "Steps per frame",
+                Msg::StepsPerFrameHint => {
+                    "Take exactly this many steps between frames, instead of running as fast as possible. Simulated time per frame is this times the time per tick."
+                }
+                Msg::Accessibility => "Accessibility",
+                Msg::Palette => "Colors",
+                Msg::PaletteScenario => "Scenario",
+                Msg::PaletteColorblindSafe => "Colorblind safe",
+                Msg::PaletteHighContrast => "High contrast",
+                Msg::UiScale => "UI scale",
+                Msg::Rendering => "Rendering",
+                Msg::Antialiasing => "Anti-aliasing",
+                Msg::AntialiasingHint => {
+                    "Samples per pixel, to smooth the edges of trails and circles. More samples cost more GPU memory and time."
+                }
+                Msg::AntialiasingOff => "Off",
+                Msg::Bloom => "Glow",
+                Msg::BloomHint => {
+                    "Strength of the glow around bright objects, which makes them stand out in dense clouds."
+                }
+                Msg::TrailOpacity => "Trail opacity",
+                Msg::TrailFade => "Trail fade",
+                Msg::TrailFadeHint => {
+                    "How trails fade towards their end: over the samples kept, or with the simulated time since they were drawn."
+                }
+                Msg::TrailFadeSamples => "By sample",
+                Msg::TrailFadeLinear => "Linear in time",
+                Msg::TrailFadeExponential => "Exponential in time",
+                Msg::TrailFadeTime => "Fade time",
+                Msg::TrailColor => "Trail color",
+                Msg::TrailColorHint => {
+                    "What the color of the trails shows. Speed and acceleration are on a logarithmic scale, from the lowest to the highest of the latest positions."
+                }
+                Msg::TrailColorObject => "Object color",
+                Msg::TrailColorSpeed => "Speed",
+                Msg::TrailColorAcceleration => "Acceleration",
+                Msg::Colormap => "Colormap",
+                Msg::FocusedTrail => "Trail of focused object",
+                Msg::ShowAllTrails => "Show all trails",
+                Msg::Demo => "Demo",
+                Msg::DemoNext => "Next scenario",
+                Msg::Resources => "Resources",
+                Msg::TrailMemory => "Trails",
+                Msg::TrailLength => "length",
+                Msg::HistoryMemory => "History",
+                Msg::HistoryInterval => "ticks between frames",
+                Msg::SimulationMemory => "Simulation",
+                Msg::SimulationMemoryHint => {
+                    "Objects, accelerations and the buffers kept by the gravity solver, like the arena of a tree."
+                }
+                Msg::GpuMemory => "GPU buffers",
+                Msg::TrailBudget => "Trail budget",
+                Msg::TrailBudgetHint => {
+                    "Shorten the trails until they fit, counting both the copy in memory and the one on the GPU."
+                }
+                Msg::HistoryBudget => "History budget",
+                Msg::HistoryBudgetHint => {
+                    "Record the history less often until it fits. Frames dropped to fit do not come back when the budget is raised."
+                }
+                Msg::Ephemeris => "Ephemerides",
+                Msg::EphemerisMarkers => "Show reference positions",
+                Msg::EphemerisMarkersHint => {
+                    "Mark where HORIZONS has each body at the dates reached so far, with a line from where it should be now to where it is."
+                }
+                Msg::EphemerisDeviation => "Deviation",
+                Msg::EphemerisOutside => "outside the table",
+                Msg::Replay => "Replay",
+                Msg::Play => "Play",
+                Msg::Pause => "Pause",
+                Msg::ReplaySpeed => "Speed",
+                Msg::Viewing => "Viewing",
+                Msg::Timeline => "Timeline",
+                Msg::NoHistory => "No history recorded yet",
+                Msg::Live => "Live",
+                Msg::ReturnToLive => "Return to live",
+                Msg::Divergence => "Divergence",
+                Msg::LargestDivergence => "Largest",
+                Msg::DivergenceMax => "max",
+                Msg::DivergenceMean => "mean",
+                Msg::DropHint => "Drop a recording, scenario or checkpoint to open it",
+                Msg::OpenFile => "Open file?",
+                Msg::DropReplay => "Replay this recording, replacing what is currently shown.",
+                Msg::DropScenario => "Simulate this scenario, replacing what is currently shown.",
+                Msg::DropCheckpoint => {
+                    "Simulate the objects shown from the state in this checkpoint, replacing the current simulation."
+                }
+                Msg::Open => "Open",
+                Msg::Cancel => "Cancel",
+                Msg::CannotOpen => "Cannot open file",
+                Msg::DroppedWithoutPath => "Dropped file without a path",
+                Msg::Ok => "Ok",
+                Msg::Probes => "Probes",
+                Msg::AttachToFocus => "Attach to focus",
+                Msg::AddFixedPoint => "Add fixed point",
+                Msg::Remove => "Remove",
+                Msg::ProbeRadius => "Radius (AU)",
+                Msg::Export => "Export",
+                Msg::Wrote => "Wrote",
+                Msg::NoSamples => "No samples yet",
+                Msg::ProbePotential => "Potential",
+                Msg::ProbeInside => "inside",
+                Msg::RocheLobes => "Roche lobes",
+                Msg::RochePrimary => "Primary",
+                Msg::RocheSecondary => "Secondary",
+                Msg::RocheNone => "none",
+                Msg::SetToFocus => "Set to focus",
+                Msg::Clear => "Clear",
+                Msg::RocheMassless => "Both bodies need mass to have Roche lobes",
+                Msg::MassRatio => "Mass ratio",
+                Msg::L1FromPrimary => "L1 from primary",
+                Msg::LobeRadii => "Lobe radii",
+            },
+            Self::German => match msg {
+                Msg::Title => "Weltraumsimulation",
+                Msg::Language => "Sprache",
+                Msg::CurrentTime => "Aktuelle Zeit",
+                Msg::SimulatedTimePerSecond => "Simulierte Zeit pro Sekunde",
+                Msg::RealTimeFactor => "Zeitraffer",
+                Msg::TimePerTick => "Zeit pro Schritt",
+                Msg::FocusedObject => "Verfolgtes Objekt",
+                Msg::NetGravity => "Gesamte Gravitation",
+                Msg::NetGravityHint => {
+                    "Gravitation auf das verfolgte Objekt bei den gezeigten Positionen, darunter die Körper mit der stärksten Anziehung. Jeder Anteil ist der Teil einer Anziehung entlang der gesamten Gravitation, sodass sich entgegengesetzte Anziehungen aufheben. Andere Kräfte sind nicht enthalten."
+                }
+                Msg::PullingBody => "Körper",
+                Msg::ShareOfGravity => "Anteil",
+                Msg::OtherBodies => "Alles andere",
+                Msg::OrbitAround => "Umlaufbahn um",
+                Msg::OrbitAroundHint => {
+                    "Oskulierende Bahn um den schwereren Körper, an den das verfolgte Objekt am stärksten gebunden ist, aus seiner aktuellen Position und Geschwindigkeit. Wie jede Bahn in einer Simulation mit mehr als zwei Körpern ändert sie sich mit der Zeit."
+                }
+                Msg::SemiMajorAxis => "Große Halbachse",
+                Msg::Eccentricity => "Exzentrizität",
+                Msg::Inclination => "Bahnneigung",
+                Msg::LongAscNode => "Länge des aufsteigenden Knotens",
+                Msg::ArgPeriapsis => "Argument der Periapsis",
+                Msg::TrueAnomaly => "Wahre Anomalie",
+                Msg::OrbitalPeriod => "Umlaufzeit",
+                Msg::Theta => "Barnes-Hut-Theta",
+                Msg::ThetaHint => {
+                    "Kleiner ist genauer, aber langsamer. Nur für Baumverfahren verwendet."
+                }
+                Msg::CpuBudget => "CPU-Budget",
+                Msg::CpuBudgetHint => {
+                    "Anteil der Kerne, den die Simulation nutzen darf. Innerhalb davon richtet sich die Zahl der Threads danach, wie schnell die Schritte sind."
+                }
+                Msg::SimThreads => "Threads",
+                Msg::FixedThreads => "Feste Threads",
+                Msg::FixedThreadsHint => {
+                    "Die Simulation läuft mit genau so vielen Threads, unabhängig vom CPU-Budget."
+                }
+                Msg::Solver => "Gravitationslöser",
+                Msg::SolverAutomatic => "Automatisch",
+                Msg::SolverBruteForce => "Direkte Summation",
+                Msg::SolverBarnesHut => "Barnes-Hut",
+                Msg::SolverFmm => "Schnelle Multipolmethode",
+                Msg::Rewind => "Zurückspulen (R)",
+                Msg::RewindHint => {
+                    "Rückwärts bis zum Anfang des Laufs rechnen, auf demselben Weg zurück. Geschwindigkeitsabhängige Kräfte, Entweichen und Einfügen werden nicht rückgängig gemacht."
+                }
+                Msg::AdaptiveStep => "Adaptiver Schritt",
+                Msg::AdaptiveStepHint => {
+                    "Länge des letzten Teilschritts, den der adaptive Integrator angenommen hat. Ein Teilschritt ist nie länger als die Zeit pro Schritt."
+                }
+                Msg::RejectedSteps => "Verworfene Schritte",
+                Msg::VirialRatio => "Virialverhältnis",
+                Msg::VirialRatioHint => {
+                    "Die doppelte kinetische Energie geteilt durch den Betrag der potentiellen Energie, über alle Objekte, die andere anziehen. Ein Haufen im Gleichgewicht hat ein Verhältnis von eins, darüber dehnt er sich aus, darunter zieht er sich zusammen."
+                }
+                Msg::HalfMassRadius => "Halbmassenradius",
+                Msg::VelocityDispersion => "Geschwindigkeitsdispersion",
+                Msg::VelocityDispersionHint => {
+                    "Nach Masse gewichteter quadratischer Mittelwert der Geschwindigkeit relativ zum Schwerpunkt, in drei Dimensionen."
+                }
+                Msg::CrossingTime => "Durchquerungszeit",
+                Msg::CrossingTimeHint => {
+                    "Zeit, um den Halbmassenradius mit der Geschwindigkeit eines Haufens im Gleichgewicht zu durchqueren, mit der Anzahl Schritte, die sie bei der aktuellen Zeit pro Schritt dauert. Ein Haufen braucht meist mindestens hundert Schritte pro Durchquerungszeit, um genau zu bleiben. Systeme, die von einem Körper beherrscht werden, wie ein Stern mit Planeten, brauchen stattdessen eine Zeit pro Schritt passend zur kürzesten Umlaufbahn."
+                }
+                Msg::RelaxationTime => "Relaxationszeit",
+                Msg::RelaxationTimeHint => {
+                    "Geschätzte Zeit, bis nahe Begegnungen die Bahnen im Haufen vollständig verändert haben, mit der Anzahl Schritte, die sie bei der aktuellen Zeit pro Schritt dauert. Nimmt Objekte ähnlicher Masse an."
+                }
+                Msg::DumpTree => "Octree speichern",
+                Msg::DumpTreeHint => {
+                    "Den Barnes-Hut-Octree des letzten Schritts als octree-<Schritt>.bin im Arbeitsverzeichnis speichern, um Probleme mit dem Baum nachzustellen."
+                }
+                Msg::SaveScenario => "Szenario speichern",
+                Msg::SaveScenarioHint => {
+                    "Die Objekte, wie sie gerade sind, als scenario-<Schritt>.toml im Arbeitsverzeichnis speichern, um sie später mit --scenario wieder zu laden."
+                }
+                Msg::StartRecording => "Aufnehmen",
+                Msg::StartRecordingHint => {
+                    "Ab jetzt jede Abtastung in recording-<Schritt>.nbrec im Arbeitsverzeichnis schreiben, um sie mit --replay oder durch Ziehen auf das Fenster abzuspielen."
+                }
+                Msg::StopRecording => "Aufnahme beenden",
+                Msg::RecordedFrames => "Bilder aufgenommen in",
+                Msg::ForceAccuracy => "Genauigkeit der Kräfte",
+                Msg::Groups => "Friends-of-Friends-Gruppen",
+                Msg::LinkingFactor => "Verknüpfungslänge",
+                Msg::LinkingFactorHint => {
+                    "Objekte, die näher als dieser Anteil ihres mittleren Abstands sind, sind Freunde, und Gruppen sind über Freunde verbundene Objekte. Um 0,2 findet die Klumpen, die deutlich dichter als der Durchschnitt sind."
+                }
+                Msg::FindGroups => "Gruppen finden",
+                Msg::FindGroupsHint => {
+                    "Die Gruppen an den aktuellen Positionen der Objekte finden, die andere anziehen. Gruppen aus nur wenigen Objekten werden weggelassen."
+                }
+                Msg::GroupsFound => "Gruppen",
+                Msg::GroupedObjects => "Objekte in Gruppen",
+                Msg::LinkingLength => "Verknüpfungslänge",
+                Msg::ColorGroups => "Objekte nach Gruppe färben",
+                Msg::GroupMembers => "Objekte",
+                Msg::GroupRadius => "Radius (AE)",
+                Msg::ColorDensity => "Objekte nach Dichte färben",
+                Msg::ColorDensityHint => {
+                    "Bei jeder Abtastung die Dichte um jedes Objekt aus seinen nächsten massiven Nachbarn schätzen und die Objekte von Blau für die dünnsten bis Gelb für die dichtesten färben. Verlangsamt die Simulation, solange es an ist."
+                }
+                Msg::CheckForce => "Fokussiertes Objekt prüfen",
+                Msg::CheckForceHint => {
+                    "Die Gravitation auf das fokussierte Objekt mit dem Barnes-Hut-Baum beim aktuellen Theta und durch Summieren über alle Objekte berechnen. Andere Kräfte werden weggelassen."
+                }
+                Msg::RelativeError => "Relativer Fehler",
+                Msg::Interactions => "Wechselwirkungen im Baum",
+                Msg::WorstNodes => "Knoten mit dem größten Fehler",
+                Msg::WorstNodesHint => {
+                    "Kraft und Fehler jedes Knotens relativ zur exakten Kraft, wobei der Fehler der Unterschied zum Summieren über die Objekte im Knoten ist."
+                }
+                Msg::NodeMass => "Masse (M⊕)",
+                Msg::NodeDistance => "Abstand (AE)",
+                Msg::NodeSize => "Größe (AE)",
+                Msg::NodeForce => "Kraft",
+                Msg::NodeError => "Fehler",
+                Msg::StepsPerFrame => "Schritte pro Bild",
+                Msg::StepsPerFrameHint => {
+                    "Genau so viele Schritte zwischen zwei Bildern rechnen, statt so schnell wie möglich. Die simulierte Zeit pro Bild ist diese Zahl mal die Zeit pro Schritt."
+                }
+                Msg::Accessibility => "Barrierefreiheit",
+                Msg::Palette => "Farben",
+                Msg::PaletteScenario => "Szenario",
+                Msg::PaletteColorblindSafe => "Farbenblind-tauglich",
+                Msg::PaletteHighContrast => "Hoher Kontrast",
+                Msg::UiScale => "Skalierung",
+                Msg::Rendering => "Darstellung",
+                Msg::Antialiasing => "Kantenglättung",
+                Msg::AntialiasingHint => {
+                    "Abtastungen pro Pixel, um die Ränder von Spuren und Kreisen zu glätten. Mehr Abtastungen kosten mehr GPU-Speicher und Zeit."
+                }
+                Msg::AntialiasingOff => "Aus",
+                Msg::Bloom => "Leuchten",
+                Msg::BloomHint => {
+                    "Stärke des Leuchtens um helle Objekte, das sie in dichten Wolken hervorhebt."
+                }
+                Msg::TrailOpacity => "Deckkraft der Spuren",
+                Msg::TrailFade => "Verblassen der Spuren",
+                Msg::TrailFadeHint => {
+                    "Wie Spuren zu ihrem Ende hin verblassen: über die gespeicherten Abtastungen oder mit der simulierten Zeit, seit sie gezeichnet wurden."
+                }
+                Msg::TrailFadeSamples => "Nach Abtastung",
+                Msg::TrailFadeLinear => "Linear in der Zeit",
+                Msg::TrailFadeExponential => "Exponentiell in der Zeit",
+                Msg::TrailFadeTime => "Dauer des Verblassens",
+                Msg::TrailColor => "Farbe der Spuren",
+                Msg::TrailColorHint => {
+                    "Was die Farbe der Spuren zeigt. Geschwindigkeit und Beschleunigung sind logarithmisch skaliert, vom niedrigsten bis zum höchsten Wert der letzten Positionen."
+                }
+                Msg::TrailColorObject => "Farbe des Objekts",
+                Msg::TrailColorSpeed => "Geschwindigkeit",
+                Msg::TrailColorAcceleration => "Beschleunigung",
+                Msg::Colormap => "Farbskala",
+                Msg::FocusedTrail => "Spur des fokussierten Objekts",
+                Msg::ShowAllTrails => "Alle Spuren zeigen",
+                Msg::Demo => "Vorführung",
+                Msg::DemoNext => "Nächstes Szenario",
+                Msg::Resources => "Ressourcen",
+                Msg::TrailMemory => "Spuren",
+                Msg::TrailLength => "Länge",
+                Msg::HistoryMemory => "Verlauf",
+                Msg::HistoryInterval => "Schritte zwischen Bildern",
+                Msg::SimulationMemory => "Simulation",
+                Msg::SimulationMemoryHint => {
+                    "Objekte, Beschleunigungen und die Puffer des Gravitationslösers, wie der Speicher eines Baums."
+                }
+                Msg::GpuMemory => "GPU-Puffer",
+                Msg::TrailBudget => "Budget für Spuren",
+                Msg::TrailBudgetHint => {
+                    "Die Spuren kürzen, bis sie hineinpassen, gezählt im Arbeitsspeicher und auf der GPU."
+                }
+                Msg::HistoryBudget => "Budget für den Verlauf",
+                Msg::HistoryBudgetHint => {
+                    "Den Verlauf seltener aufzeichnen, bis er hineinpasst. Dafür verworfene Bilder kommen nicht zurück, wenn das Budget erhöht wird."
+                }
+                Msg::Ephemeris => "Ephemeriden",
+                Msg::EphemerisMarkers => "Referenzpositionen zeigen",
+                Msg::EphemerisMarkersHint => {
+                    "Markiert, wo HORIZONS jeden Körper zu den bisher erreichten Zeitpunkten hat, mit einer Linie von der erwarteten zur simulierten Position."
+                }
+                Msg::EphemerisDeviation => "Abweichung",
+                Msg::EphemerisOutside => "außerhalb der Tabelle",
+                Msg::Replay => "Wiedergabe",
+                Msg::Play => "Abspielen",
+                Msg::Pause => "Pause",
+                Msg::ReplaySpeed => "Geschwindigkeit",
+                Msg::Viewing => "Angezeigt",
+                Msg::Timeline => "Zeitleiste",
+                Msg::NoHistory => "Noch kein Verlauf aufgezeichnet",
+                Msg::Live => "Live",
+                Msg::ReturnToLive => "Zurück zu live",
+                Msg::Divergence => "Abweichung",
+                Msg::LargestDivergence => "Größte",
+                Msg::DivergenceMax => "max",
+                Msg::DivergenceMean => "Mittel",
+                Msg::DropHint => {
+                    "Eine Aufnahme, ein Szenario oder einen Sicherungspunkt ablegen, um sie zu öffnen"
+                }
+                Msg::OpenFile => "Datei öffnen?",
+                Msg::DropReplay => {
+                    "Diese Aufnahme abspielen, anstelle dessen, was gerade gezeigt wird."
+                }
+                Msg::DropScenario => {
+                    "Dieses Szenario simulieren, anstelle dessen, was gerade gezeigt wird."
+                }
+                Msg::DropCheckpoint => {
+                    "Die gezeigten Objekte ab dem Zustand in diesem Sicherungspunkt simulieren, anstelle der aktuellen Simulation."
+                }
+                Msg::Open => "Öffnen",
+                Msg::Cancel => "Abbrechen",
+                Msg::CannotOpen => "Datei kann nicht geöffnet werden",
+                Msg::DroppedWithoutPath => "Abgelegte Datei ohne Pfad",
+                Msg::Ok => "OK",
+                Msg::Probes => "Sonden",
+                Msg::AttachToFocus => "Am Fokus anbringen",
+                Msg::AddFixedPoint => "Festen Punkt hinzufügen",
+                Msg::Remove => "Entfernen",
+                Msg::ProbeRadius => "Radius (AE)",
+                Msg::Export => "Exportieren",
+                Msg::Wrote => "Geschrieben:",
+                Msg::NoSamples => "Noch keine Abtastungen",
+                Msg::ProbePotential => "Potential",
+                Msg::ProbeInside => "darin",
+                Msg::RocheLobes => "Roche-Grenzen",
+                Msg::RochePrimary => "Hauptkörper",
+                Msg::RocheSecondary => "Begleiter",
+                Msg::RocheNone => "keiner",
+                Msg::SetToFocus => "Auf Fokus setzen",
+                Msg::Clear => "Leeren",
+                Msg::RocheMassless => "Beide Körper brauchen Masse, um Roche-Grenzen zu haben",
+                Msg::MassRatio => "Massenverhältnis",
+                Msg::L1FromPrimary => "L1 vom Hauptkörper",
+                Msg::LobeRadii => "Radien der Grenzen",
+            },
+        }
+    }
+
+    fn decimal_separator(self) -> char {
+        match self {
+            Self::English => '.',
+            Self::German => ',',
+        }
+    }
+
+    fn group_separator(self) -> char {
+        match self {
+            Self::English => ',',
+            Self::German => '.',
+        }
+    }
+
+    /// `value` with `decimals` decimals, and the integer part in groups of three.
+    pub fn number(self, value: f64, decimals: usize) -> String {
+        let formatted = format!("{:.*}", decimals, value.abs());
+        let (int, frac) = formatted.split_once('.').unwrap_or((&formatted, ""));
+        let mut out = String::new();
+        if value.is_sign_negative() && value != 0.0 {
+            out.push('-');
+        }
+        for (i, c) in int.chars().enumerate() {
+            if i > 0 && (int.len() - i).is_multiple_of(3) {
+                out.push(self.group_separator());
+            }
+            out.push(c);
+        }
+        if !frac.is_empty() {
+            out.push(self.decimal_separator());
+            out.push_str(frac);
+        }
+        out
+    }
+
+    /// `value` in scientific notation with `decimals` decimals.
+    pub fn scientific(self, value: f64, decimals: usize) -> String {
+        format!("{value:.decimals$e}").replace('.', &self.decimal_separator().to_string())
+    }
+
+    pub fn elapsed(self, time: &ElapsedTime) -> String {
+        let seconds =
+            format!("{:0>2}", time.seconds).replace('.', &self.decimal_separator().to_string());
+        let ticks = self.number(time.ticks, 0);
+        match self {
+            Self::English => format!(
+                "{}Y {}D {:0>2}:{:0>2}:{seconds} ({ticks} ticks)",
+                time.years, time.days, time.hours, time.minutes
+            ),
+            Self::German => format!(
+                "{} J. {} T. {:0>2}:{:0>2}:{seconds} ({ticks} Schritte)",
+                time.years, time.days, time.hours, time.minutes
+            ),
+        }
+    }
+}
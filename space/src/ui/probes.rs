@@ -0,0 +1,225 @@
+use cgmath::Point3;
+use eframe::egui::{self, Color32, Pos2, Sense, Stroke, Vec2};
+
+use crate::{
+    camera::Camera,
+    constants::PROBE_DEFAULT_RADIUS,
+    objects::Objects,
+    probes::{Probe, ProbeAnchor, ProbeSet},
+    sim::SimTime,
+    ui::locale::{Locale, Msg},
+};
+
+/// Scale `values` to 0..1 between their smallest and largest value.
+fn normalized(values: impl Iterator<Item = f64> + Clone) -> impl Iterator<Item = f32> {
+    let (min, max) = values
+        .clone()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), v| {
+            (min.min(v), max.max(v))
+        });
+    let span = max - min;
+    values.map(move |v| {
+        if span > 0.0 {
+            ((v - min) / span) as f32
+        } else {
+            0.5
+        }
+    })
+}
+
+/// Lets the user place probes, shows the series of the selected probe, and exports
+/// all recorded samples.
+pub struct ProbePanel {
+    probes: ProbeSet,
+    selected: Option<usize>,
+    next_id: usize,
+    export_path: String,
+    status: Option<Result<String, String>>,
+}
+
+impl ProbePanel {
+    pub fn new() -> Self {
+        Self {
+            probes: ProbeSet::new(),
+            selected: None,
+            next_id: 1,
+            export_path: "probes.csv".to_owned(),
+            status: None,
+        }
+    }
+
+    pub fn update(&mut self, time: SimTime, objects: &Objects) {
+        self.probes.update(time, objects);
+    }
+
+    fn add(&mut self, anchor: ProbeAnchor, objects: &Objects) {
+        let name = match anchor {
+            ProbeAnchor::Body(idx) => {
+                format!("probe_{}_{}", self.next_id, objects.objects()[idx].name)
+            }
+            ProbeAnchor::Point(_) => format!("probe_{}", self.next_id),
+        };
+        self.next_id += 1;
+        self.probes
+            .add(Probe::new(name, anchor, PROBE_DEFAULT_RADIUS));
+        self.selected = Some(self.probes.probes().len() - 1);
+    }
+
+    pub fn render(
+        &mut self,
+        ui: &mut egui::Ui,
+        objects: &Objects,
+        camera: &Camera,
+        locale: Locale,
+    ) {
+        ui.separator();
+        ui.label(locale.text(Msg::Probes));
+
+        let focus = camera
+            .focus()
+            .map(|f| f as usize)
+            .filter(|f| *f < objects.num_objects());
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(
+                    focus.is_some(),
+                    egui::Button::new(locale.text(Msg::AttachToFocus)),
+                )
+                .clicked()
+                && let Some(focus) = focus
+            {
+                self.add(ProbeAnchor::Body(focus), objects);
+            }
+            if ui.button(locale.text(Msg::AddFixedPoint)).clicked() {
+                // Place the probe where the focused object currently is, if any.
+                let pos = focus
+                    .map(|f| Point3::from(*objects.position_of(f)).cast().unwrap())
+                    .unwrap_or(Point3::new(0.0, 0.0, 0.0));
+                self.add(ProbeAnchor::Point(pos), objects);
+            }
+        });
+
+        let mut remove = None;
+        for (idx, probe) in self.probes.probes_mut().iter_mut().enumerate() {
+            let mut changed = false;
+            ui.horizontal(|ui| {
+                if ui
+                    .selectable_label(self.selected == Some(idx), &probe.name)
+                    .clicked()
+                {
+                    self.selected = Some(idx);
+                }
+                if ui.small_button(locale.text(Msg::Remove)).clicked() {
+                    remove = Some(idx);
+                }
+            });
+            if self.selected != Some(idx) {
+                continue;
+            }
+            if let ProbeAnchor::Point(pos) = &mut probe.anchor {
+                ui.horizontal(|ui| {
+                    for i in 0..3 {
+                        changed |= ui
+                            .add(egui::DragValue::new(&mut pos[i]).speed(0.01))
+                            .changed();
+                    }
+                });
+            }
+            changed |= ui
+                .add(
+                    egui::Slider::new(&mut probe.radius, 1e-4..=100.0)
+                        .logarithmic(true)
+                        .text(locale.text(Msg::ProbeRadius)),
+                )
+                .changed();
+            // Earlier samples were taken somewhere else.
+            if changed {
+                probe.clear();
+            }
+        }
+        if let Some(idx) = remove {
+            self.probes.remove(idx);
+            self.selected = None;
+        }
+
+        if let Some(probe) = self.selected.and_then(|s| self.probes.probes().get(s)) {
+            Self::plot(ui, probe, locale);
+        }
+
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut self.export_path);
+            if ui.button(locale.text(Msg::Export)).clicked() {
+                self.status = Some(
+                    self.probes
+                        .export_csv(&self.export_path)
+                        .map(|_| format!("{} {}", locale.text(Msg::Wrote), self.export_path))
+                        .map_err(|e| format!("{e:#}")),
+                );
+            }
+        });
+        match &self.status {
+            Some(Ok(msg)) => {
+                ui.label(msg);
+            }
+            Some(Err(error)) => {
+                ui.colored_label(Color32::RED, error);
+            }
+            None => (),
+        }
+    }
+
+    fn plot(ui: &mut egui::Ui, probe: &Probe, locale: Locale) {
+        let samples = probe.samples();
+        let Some(last) = samples.back() else {
+            ui.label(locale.text(Msg::NoSamples));
+            return;
+        };
+        ui.label(format!(
+            "{}: {} J/kg, {} {}, +{}/-{}",
+            locale.text(Msg::ProbePotential),
+            locale.scientific(last.potential, 4),
+            locale.number(last.inside as f64, 0),
+            locale.text(Msg::ProbeInside),
+            last.entered,
+            last.left
+        ));
+
+        let (response, painter) =
+            ui.allocate_painter(Vec2::new(ui.available_width(), 120.0), Sense::hover());
+        let rect = response.rect;
+        painter.rect_filled(rect, 0.0, Color32::from_gray(20));
+
+        let first = samples[0].time.seconds();
+        let span = (last.time.seconds() - first).max(f64::EPSILON);
+        let to_screen = |time: f64, value: f32| {
+            Pos2::new(
+                rect.left() + rect.width() * ((time - first) / span) as f32,
+                rect.bottom() - rect.height() * value,
+            )
+        };
+
+        let potential: Vec<_> = samples
+            .iter()
+            .zip(normalized(samples.iter().map(|s| s.potential)))
+            .map(|(s, v)| to_screen(s.time.seconds(), v))
+            .collect();
+        let inside: Vec<_> = samples
+            .iter()
+            .zip(normalized(samples.iter().map(|s| s.inside as f64)))
+            .map(|(s, v)| to_screen(s.time.seconds(), v))
+            .collect();
+        painter.add(egui::Shape::line(
+            potential,
+            Stroke::new(1.0, Color32::LIGHT_GREEN),
+        ));
+        painter.add(egui::Shape::line(
+            inside,
+            Stroke::new(1.0, Color32::LIGHT_YELLOW),
+        ));
+
+        ui.horizontal(|ui| {
+            ui.colored_label(Color32::LIGHT_GREEN, locale.text(Msg::ProbePotential));
+            ui.colored_label(Color32::LIGHT_YELLOW, locale.text(Msg::ProbeInside));
+        });
+    }
+}
@@ -0,0 +1,127 @@
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use eframe::egui;
+
+use crate::{
+    batch_request::BatchRequest,
+    constants::RECORDING_KEYFRAME_INTERVAL,
+    objects::Objects,
+    recording::{RecordedObject, RecordingWriter},
+    sim::SimTime,
+    ui::locale::{Locale, Msg},
+};
+
+struct Recording {
+    path: PathBuf,
+    writer: RecordingWriter<BufWriter<File>>,
+    frames: u64,
+}
+
+/// Writes the samples of a live simulation to a recording, to be replayed later.
+pub struct RecordPanel {
+    recording: Option<Recording>,
+    error: Option<String>,
+}
+
+impl RecordPanel {
+    pub fn new() -> Self {
+        Self {
+            recording: None,
+            error: None,
+        }
+    }
+
+    /// Record every sample of `objects` to `path` from now on, ending any recording in
+    /// progress.
+    pub fn start(&mut self, path: impl AsRef<Path>, objects: &Objects) -> anyhow::Result<()> {
+        self.stop();
+        let path = path.as_ref();
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create recording {}", path.display()))?;
+        let described: Vec<_> = objects
+            .objects()
+            .iter()
+            .map(RecordedObject::from_object)
+            .collect();
+        let writer = RecordingWriter::new(
+            BufWriter::new(file),
+            &described,
+            RECORDING_KEYFRAME_INTERVAL,
+        )?;
+        self.recording = Some(Recording {
+            path: path.to_owned(),
+            writer,
+            frames: 0,
+        });
+        self.error = None;
+        Ok(())
+    }
+
+    /// Write out what has been recorded, and stop recording.
+    pub fn stop(&mut self) {
+        if let Some(mut recording) = self.recording.take()
+            && let Err(e) = recording.writer.flush()
+        {
+            self.error = Some(format!("{e:#}"));
+        }
+    }
+
+    /// Add a sample to the recording, if there is one. Objects injected since recording
+    /// started have no description in the recording, and are left out.
+    pub fn record(&mut self, time: SimTime, positions: &[[f32; 3]]) {
+        let Some(recording) = &mut self.recording else {
+            return;
+        };
+        let described = recording.writer.num_objects().min(positions.len());
+        match recording.writer.write_frame(time, &positions[..described]) {
+            Ok(()) => recording.frames += 1,
+            Err(e) => {
+                self.error = Some(format!("{e:#}"));
+                self.stop();
+            }
+        }
+    }
+
+    pub fn render(
+        &mut self,
+        ui: &mut egui::Ui,
+        exchange: &BatchRequest,
+        objects: &Objects,
+        locale: Locale,
+    ) {
+        ui.separator();
+        match &self.recording {
+            Some(recording) => {
+                ui.label(format!(
+                    "{} {} {}",
+                    recording.frames,
+                    locale.text(Msg::RecordedFrames),
+                    recording.path.display()
+                ));
+                if ui.button(locale.text(Msg::StopRecording)).clicked() {
+                    self.stop();
+                }
+            }
+            None => {
+                if ui
+                    .button(locale.text(Msg::StartRecording))
+                    .on_hover_text(locale.text(Msg::StartRecordingHint))
+                    .clicked()
+                {
+                    let ticks = exchange.current_time().ticks;
+                    if let Err(e) = self.start(format!("recording-{ticks}.nbrec"), objects) {
+                        self.error = Some(format!("{e:#}"));
+                    }
+                }
+            }
+        }
+        if let Some(error) = &self.error {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+    }
+}
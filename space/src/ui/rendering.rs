@@ -0,0 +1,163 @@
+use eframe::egui;
+use egui_wgpu::RenderState;
+
+use crate::{
+    constants::TRAIL_FADE_DAYS_RANGE,
+    objects::Objects,
+    render::{Colormap, TrailColor, TrailFade, supported_sample_counts},
+    ui::{
+        SpaceViewWidget,
+        locale::{Locale, Msg},
+    },
+};
+
+const SECONDS_PER_DAY: f32 = 86_400.0;
+
+/// How the view is drawn, as far as the GPU allows.
+pub struct RenderingPanel {
+    sample_counts: Vec<u32>,
+}
+
+fn sample_count_name(sample_count: u32, locale: Locale) -> String {
+    if sample_count == 1 {
+        locale.text(Msg::AntialiasingOff).to_owned()
+    } else {
+        format!("{sample_count}× MSAA")
+    }
+}
+
+fn trail_fade_name(fade: TrailFade, locale: Locale) -> &'static str {
+    locale.text(match fade {
+        TrailFade::Samples => Msg::TrailFadeSamples,
+        TrailFade::Linear => Msg::TrailFadeLinear,
+        TrailFade::Exponential => Msg::TrailFadeExponential,
+    })
+}
+
+fn trail_color_name(color: TrailColor, locale: Locale) -> &'static str {
+    locale.text(match color {
+        TrailColor::Object => Msg::TrailColorObject,
+        TrailColor::Speed => Msg::TrailColorSpeed,
+        TrailColor::Acceleration => Msg::TrailColorAcceleration,
+    })
+}
+
+impl RenderingPanel {
+    pub fn new(state: &RenderState) -> Self {
+        Self {
+            sample_counts: supported_sample_counts(&state.adapter, &state.device),
+        }
+    }
+
+    pub fn render(
+        &self,
+        ui: &mut egui::Ui,
+        view: &mut SpaceViewWidget,
+        objects: &mut Objects,
+        locale: Locale,
+    ) {
+        ui.separator();
+        ui.label(locale.text(Msg::Rendering));
+
+        let mut sample_count = view.sample_count();
+        egui::ComboBox::from_label(locale.text(Msg::Antialiasing))
+            .selected_text(sample_count_name(sample_count, locale))
+            .show_ui(ui, |ui| {
+                for &count in &self.sample_counts {
+                    ui.selectable_value(&mut sample_count, count, sample_count_name(count, locale));
+                }
+            })
+            .response
+            .on_hover_text(locale.text(Msg::AntialiasingHint));
+        if sample_count != view.sample_count() {
+            view.set_sample_count(sample_count);
+        }
+
+        let mut bloom = view.bloom_intensity();
+        let response = ui
+            .add(egui::Slider::new(&mut bloom, 0.0..=3.0).text(locale.text(Msg::Bloom)))
+            .on_hover_text(locale.text(Msg::BloomHint));
+        if response.changed() {
+            view.set_bloom_intensity(bloom);
+        }
+
+        Self::render_trails(ui, view, objects, locale);
+    }
+
+    fn render_trails(
+        ui: &mut egui::Ui,
+        view: &mut SpaceViewWidget,
+        objects: &mut Objects,
+        locale: Locale,
+    ) {
+        let mut style = view.trail_style();
+        ui.add(
+            egui::Slider::new(&mut style.opacity, 0.0..=1.0).text(locale.text(Msg::TrailOpacity)),
+        );
+        egui::ComboBox::from_label(locale.text(Msg::TrailFade))
+            .selected_text(trail_fade_name(style.fade, locale))
+            .show_ui(ui, |ui| {
+                for fade in [
+                    TrailFade::Samples,
+                    TrailFade::Linear,
+                    TrailFade::Exponential,
+                ] {
+                    ui.selectable_value(&mut style.fade, fade, trail_fade_name(fade, locale));
+                }
+            })
+            .response
+            .on_hover_text(locale.text(Msg::TrailFadeHint));
+        if style.fade != TrailFade::Samples {
+            let mut days = style.fade_time / SECONDS_PER_DAY;
+            let (min, max) = TRAIL_FADE_DAYS_RANGE;
+            ui.add(
+                egui::Slider::new(&mut days, min..=max)
+                    .logarithmic(true)
+                    .text(format!("{} (d)", locale.text(Msg::TrailFadeTime))),
+            );
+            style.fade_time = days * SECONDS_PER_DAY;
+        }
+        egui::ComboBox::from_label(locale.text(Msg::TrailColor))
+            .selected_text(trail_color_name(style.color, locale))
+            .show_ui(ui, |ui| {
+                for color in [
+                    TrailColor::Object,
+                    TrailColor::Speed,
+                    TrailColor::Acceleration,
+                ] {
+                    ui.selectable_value(&mut style.color, color, trail_color_name(color, locale));
+                }
+            })
+            .response
+            .on_hover_text(locale.text(Msg::TrailColorHint));
+        if style.color != TrailColor::Object {
+            egui::ComboBox::from_label(locale.text(Msg::Colormap))
+                .selected_text(style.colormap.name())
+                .show_ui(ui, |ui| {
+                    for colormap in Colormap::ALL {
+                        ui.selectable_value(&mut style.colormap, colormap, colormap.name());
+                    }
+                });
+        }
+        if style != view.trail_style() {
+            view.set_trail_style(style);
+        }
+
+        if let Some(focus) = view.focus()
+            && focus < objects.num_objects()
+        {
+            let mut shown = objects.trails_shown()[focus];
+            if ui
+                .checkbox(&mut shown, locale.text(Msg::FocusedTrail))
+                .changed()
+            {
+                objects.set_trail_shown(focus, shown);
+            }
+        }
+        if !objects.trails_shown().iter().all(|shown| *shown)
+            && ui.button(locale.text(Msg::ShowAllTrails)).clicked()
+        {
+            objects.show_all_trails();
+        }
+    }
+}
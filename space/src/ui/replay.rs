@@ -0,0 +1,72 @@
+use eframe::egui;
+
+use crate::{
+    objects::Objects,
+    replay::ReplayPlayer,
+    ui::locale::{Locale, Msg},
+};
+
+/// Playback controls for a replayed recording.
+pub struct ReplayControls {
+    error: Option<String>,
+}
+
+impl ReplayControls {
+    pub fn new() -> Self {
+        Self { error: None }
+    }
+
+    /// Advance the player, pausing playback if the recording cannot be read.
+    pub fn update(&mut self, player: &mut ReplayPlayer, objects: &mut Objects) {
+        if let Err(e) = player.update(objects) {
+            self.error = Some(format!("{e:#}"));
+            player.set_playing(false);
+        }
+    }
+
+    pub fn render(&mut self, ui: &mut egui::Ui, player: &mut ReplayPlayer, locale: Locale) {
+        ui.separator();
+        ui.label(locale.text(Msg::Replay));
+
+        ui.horizontal(|ui| {
+            let label = locale.text(if player.is_playing() {
+                Msg::Pause
+            } else {
+                Msg::Play
+            });
+            if ui.button(label).clicked() {
+                if !player.is_playing() && player.frame() + 1 == player.num_frames() {
+                    player.seek(0);
+                }
+                player.set_playing(!player.is_playing());
+            }
+            ui.label(format!("{}/{}", player.frame() + 1, player.num_frames()));
+        });
+
+        let mut frame = player.frame();
+        if ui
+            .add(egui::Slider::new(&mut frame, 0..=player.num_frames() - 1).show_value(false))
+            .changed()
+        {
+            player.seek(frame);
+        }
+
+        let mut speed = player.speed();
+        if ui
+            .add(egui::Slider::new(&mut speed, -8.0..=8.0).text(locale.text(Msg::ReplaySpeed)))
+            .changed()
+        {
+            player.set_speed(speed);
+        }
+
+        ui.label(format!(
+            "{}: {}",
+            locale.text(Msg::Viewing),
+            locale.elapsed(&player.current_time().elapsed())
+        ));
+
+        if let Some(error) = &self.error {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+    }
+}
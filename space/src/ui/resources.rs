@@ -0,0 +1,135 @@
+use eframe::egui;
+
+use crate::{
+    constants::{HISTORY_MAX_FRAMES, TRAIL_MAX_LENGTH},
+    history::{History, HistoryFrame},
+    objects::{Objects, Vertex},
+    ui::locale::{Locale, Msg},
+};
+
+const MIB: f64 = 1024.0 * 1024.0;
+
+/// Memory used by the app, and budgets that shorten the trails and thin out the history
+/// when they are exceeded.
+pub struct ResourcesPanel {
+    trail_budget: f64,
+    trail_limited: bool,
+    history_budget: f64,
+    history_limited: bool,
+}
+
+fn mib(bytes: u64, locale: Locale) -> String {
+    format!("{} MiB", locale.number(bytes as f64 / MIB, 2))
+}
+
+/// Largest count of items of `item_bytes` each that fits in `budget` MiB, within `range`.
+fn fit(budget: f64, item_bytes: usize, range: (usize, usize)) -> usize {
+    let count = (budget * MIB / item_bytes.max(1) as f64) as usize;
+    count.clamp(range.0, range.1)
+}
+
+impl ResourcesPanel {
+    pub fn new() -> Self {
+        Self {
+            trail_budget: 16.0,
+            trail_limited: false,
+            history_budget: 64.0,
+            history_limited: false,
+        }
+    }
+
+    /// Shorten the trails and record history less often, until both fit their budgets.
+    pub fn update(&self, objects: &mut Objects, history: &mut History) {
+        let num_objects = objects.num_objects();
+
+        // Each trail sample is kept in memory and in a GPU buffer of the same size.
+        let sample_bytes = num_objects * std::mem::size_of::<Vertex>() * 2;
+        let trail_length = if self.trail_limited {
+            fit(self.trail_budget, sample_bytes, (2, TRAIL_MAX_LENGTH))
+        } else {
+            TRAIL_MAX_LENGTH
+        };
+        objects.set_trail_length(trail_length);
+
+        let frame_bytes =
+            std::mem::size_of::<HistoryFrame>() + num_objects * std::mem::size_of::<[f32; 3]>();
+        let max_frames = if self.history_limited {
+            fit(self.history_budget, frame_bytes, (2, HISTORY_MAX_FRAMES))
+        } else {
+            HISTORY_MAX_FRAMES
+        };
+        if max_frames != history.max_frames() {
+            history.set_max_frames(max_frames);
+        }
+    }
+
+    /// Show the memory in use. `sim_memory` is only known for live simulations.
+    pub fn render(
+        &mut self,
+        ui: &mut egui::Ui,
+        objects: &Objects,
+        history: &History,
+        sim_memory: Option<u64>,
+        gpu_memory: u64,
+        locale: Locale,
+    ) {
+        ui.separator();
+        ui.label(locale.text(Msg::Resources));
+
+        ui.label(format!(
+            "{}: {} ({}: {})",
+            locale.text(Msg::TrailMemory),
+            mib(objects.trail_memory() as u64, locale),
+            locale.text(Msg::TrailLength),
+            objects.trail_length()
+        ));
+        ui.label(format!(
+            "{}: {} ({}: {})",
+            locale.text(Msg::HistoryMemory),
+            mib(history.memory_usage() as u64, locale),
+            locale.text(Msg::HistoryInterval),
+            history.interval()
+        ));
+        if let Some(bytes) = sim_memory {
+            ui.label(format!(
+                "{}: {}",
+                locale.text(Msg::SimulationMemory),
+                mib(bytes, locale)
+            ))
+            .on_hover_text(locale.text(Msg::SimulationMemoryHint));
+        }
+        ui.label(format!(
+            "{}: {}",
+            locale.text(Msg::GpuMemory),
+            mib(gpu_memory, locale)
+        ));
+
+        budget(
+            ui,
+            &mut self.trail_limited,
+            &mut self.trail_budget,
+            locale.text(Msg::TrailBudget),
+            locale.text(Msg::TrailBudgetHint),
+        );
+        budget(
+            ui,
+            &mut self.history_limited,
+            &mut self.history_budget,
+            locale.text(Msg::HistoryBudget),
+            locale.text(Msg::HistoryBudgetHint),
+        );
+    }
+}
+
+fn budget(ui: &mut egui::Ui, limited: &mut bool, value: &mut f64, label: &str, hint: &str) {
+    ui.horizontal(|ui| {
+        ui.checkbox(limited, label).on_hover_text(hint);
+        ui.add_enabled(
+            *limited,
+            egui::DragValue::new(value)
+                .range(0.01..=65536.0)
+                .speed(0.1)
+                .suffix(" MiB"),
+        );
+    });
+}
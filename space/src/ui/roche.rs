@@ -0,0 +1,209 @@
+use cgmath::{InnerSpace, Point3, Vector3};
+use eframe::egui;
+
+use crate::{
+    camera::Camera,
+    constants::{ROCHE_ALPHA, ROCHE_LATITUDES, ROCHE_LONGITUDES},
+    mesh_pipeline::MeshVertex,
+    objects::Objects,
+    roche::{Binary, Lobe},
+    ui::locale::{Locale, Msg},
+};
+
+/// Eggleton's approximation of the volume equivalent radius of the lobe around a body
+/// with `mass`, relative to the separation.
+fn eggleton_radius(mass: f64, other_mass: f64) -> f64 {
+    let q = mass / other_mass;
+    let q_third = q.cbrt();
+    0.49 * q_third * q_third / (0.6 * q_third * q_third + (1.0 + q_third).ln())
+}
+
+/// Lets the user pick a binary pair, and builds the mesh for its Roche lobes.
+pub struct RochePanel {
+    primary: Option<usize>,
+    secondary: Option<usize>,
+    binary: Option<Binary>,
+    /// Separation at the previous update, used to find the orbital plane.
+    last_separation: Option<Vector3<f64>>,
+    normal: Vector3<f64>,
+    changed: bool,
+    positions: Vec<[f32; 3]>,
+    vertices: Vec<MeshVertex>,
+}
+
+impl RochePanel {
+    pub fn new() -> Self {
+        Self {
+            primary: None,
+            secondary: None,
+            binary: None,
+            last_separation: None,
+            normal: Vector3::unit_z(),
+            changed: false,
+            positions: Vec::new(),
+            vertices: Vec::new(),
+        }
+    }
+
+    fn position(objects: &Objects, idx: usize) -> Point3<f64> {
+        Point3::from(*objects.position_of(idx)).cast().unwrap()
+    }
+
+    /// Rebuild the lobes if the pair has moved. Returns the new mesh if it changed.
+    pub fn update(&mut self, objects: &Objects) -> Option<&[MeshVertex]> {
+        let (Some(primary), Some(secondary)) = (self.primary, self.secondary) else {
+            if self.binary.take().is_some() || self.changed {
+                self.changed = false;
+                self.vertices.clear();
+                return Some(&self.vertices);
+            }
+            return None;
+        };
+
+        let (pos1, pos2) = (
+            Self::position(objects, primary),
+            Self::position(objects, secondary),
+        );
+        if !self.changed
+            && let Some(binary) = &self.binary
+            && binary.primary == pos1
+            && binary.secondary == pos2
+        {
+            return None;
+        }
+        self.changed = false;
+
+        // The orbital plane is spanned by the separation at two points in time.
+        let separation = pos2 - pos1;
+        if let Some(last) = self.last_separation {
+            let normal = last.cross(separation);
+            if normal.magnitude2() > 0.0 {
+                self.normal = normal.normalize();
+            }
+        }
+        self.last_separation = Some(separation);
+
+        let infos = objects.objects();
+        let binary = Binary {
+            primary: pos1,
+            primary_mass: infos[primary].dat.mass,
+            secondary: pos2,
+            secondary_mass: infos[secondary].dat.mass,
+            normal: self.normal,
+        };
+
+        self.vertices.clear();
+        if binary.is_valid() {
+            for (lobe, idx) in [(Lobe::Primary, primary), (Lobe::Secondary, secondary)] {
+                self.positions.clear();
+                binary.lobe_triangles(lobe, ROCHE_LATITUDES, ROCHE_LONGITUDES, &mut self.positions);
+                let [r, g, b] = objects.descriptions()[idx].color;
+                self.vertices
+                    .extend(self.positions.iter().map(|pos| MeshVertex {
+                        pos: *pos,
+                        color: [r, g, b, ROCHE_ALPHA],
+                        ..Default::default()
+                    }));
+            }
+        }
+        self.binary = Some(binary);
+        Some(&self.vertices)
+    }
+
+    fn set(&mut self, primary: Option<usize>, secondary: Option<usize>) {
+        self.primary = primary;
+        self.secondary = secondary;
+        self.last_separation = None;
+        self.normal = Vector3::unit_z();
+        self.changed = true;
+    }
+
+    pub fn render(
+        &mut self,
+        ui: &mut egui::Ui,
+        objects: &Objects,
+        camera: &Camera,
+        locale: Locale,
+    ) {
+        ui.separator();
+        ui.label(locale.text(Msg::RocheLobes));
+
+        let focus = camera
+            .focus()
+            .map(|f| f as usize)
+            .filter(|f| *f < objects.num_objects());
+        let name = |idx: Option<usize>| {
+            idx.map(|i| objects.objects()[i].name.as_str())
+                .unwrap_or(locale.text(Msg::RocheNone))
+        };
+
+        ui.horizontal(|ui| {
+            ui.label(format!(
+                "{}: {}",
+                locale.text(Msg::RochePrimary),
+                name(self.primary)
+            ));
+            if ui
+                .add_enabled(
+                    focus.is_some(),
+                    egui::Button::new(locale.text(Msg::SetToFocus)),
+                )
+                .clicked()
+            {
+                self.set(focus, self.secondary.filter(|s| Some(*s) != focus));
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label(format!(
+                "{}: {}",
+                locale.text(Msg::RocheSecondary),
+                name(self.secondary)
+            ));
+            if ui
+                .add_enabled(
+                    focus.is_some(),
+                    egui::Button::new(locale.text(Msg::SetToFocus)),
+                )
+                .clicked()
+            {
+                self.set(self.primary.filter(|p| Some(*p) != focus), focus);
+            }
+        });
+        if (self.primary.is_some() || self.secondary.is_some())
+            && ui.button(locale.text(Msg::Clear)).clicked()
+        {
+            self.set(None, None);
+        }
+
+        let Some(binary) = &self.binary else {
+            return;
+        };
+        if !binary.is_valid() {
+            ui.label(locale.text(Msg::RocheMassless));
+            return;
+        }
+        let separation = (binary.secondary - binary.primary).magnitude();
+        ui.label(format!(
+            "{}: {}",
+            locale.text(Msg::MassRatio),
+            locale.number(binary.secondary_mass / binary.primary_mass, 4)
+        ));
+        ui.label(format!(
+            "{}: {} AU",
+            locale.text(Msg::L1FromPrimary),
+            locale.scientific((binary.l1() - binary.primary).magnitude(), 4)
+        ));
+        ui.label(format!(
+            "{}: {} AU, {} AU",
+            locale.text(Msg::LobeRadii),
+            locale.scientific(
+                separation * eggleton_radius(binary.primary_mass, binary.secondary_mass),
+                4
+            ),
+            locale.scientific(
+                separation * eggleton_radius(binary.secondary_mass, binary.primary_mass),
+                4
+            )
+        ));
+    }
+}
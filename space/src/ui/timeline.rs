@@ -0,0 +1,80 @@
+use eframe::egui;
+
+use crate::{
+    history::History,
+    objects::Objects,
+    ui::locale::{Locale, Msg},
+};
+
+/// Scrubber over the recorded history. While a past frame is selected, the view
+/// shows the reconstructed state instead of live samples. The simulation itself
+/// keeps running.
+pub struct Timeline {
+    selected_tick: Option<u64>,
+    restored_tick: Option<u64>,
+}
+
+impl Timeline {
+    pub fn new() -> Self {
+        Self {
+            selected_tick: None,
+            restored_tick: None,
+        }
+    }
+
+    pub fn is_live(&self) -> bool {
+        self.selected_tick.is_none()
+    }
+
+    pub fn render(
+        &mut self,
+        ui: &mut egui::Ui,
+        history: &History,
+        objects: &mut Objects,
+        locale: Locale,
+    ) {
+        ui.separator();
+        ui.label(locale.text(Msg::Timeline));
+
+        if history.is_empty() {
+            ui.label(locale.text(Msg::NoHistory));
+            return;
+        }
+
+        let last = history.len() - 1;
+        let mut idx = self
+            .selected_tick
+            .map(|tick| history.index_at(tick))
+            .unwrap_or(last);
+
+        if ui
+            .add(egui::Slider::new(&mut idx, 0..=last).show_value(false))
+            .changed()
+        {
+            self.selected_tick = history.get(idx).map(|f| f.time.ticks);
+        }
+
+        if let Some(frame) = history.get(idx) {
+            ui.label(format!(
+                "{}: {}",
+                locale.text(Msg::Viewing),
+                locale.elapsed(&frame.time.elapsed())
+            ));
+        }
+
+        if self.is_live() {
+            ui.label(locale.text(Msg::Live));
+        } else if ui.button(locale.text(Msg::ReturnToLive)).clicked() {
+            self.selected_tick = None;
+            self.restored_tick = None;
+            objects.clear();
+        }
+
+        if let Some(tick) = self.selected_tick
+            && self.restored_tick != Some(tick)
+        {
+            history.restore(history.index_at(tick), objects);
+            self.restored_tick = Some(tick);
+        }
+    }
+}
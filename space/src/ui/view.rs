@@ -0,0 +1,248 @@
+use eframe::egui::{self, Image, Key, TextureId, Vec2, load::SizedTexture};
+use egui_wgpu::RenderState;
+use wgpu::{FilterMode, TextureFormat, wgt::TextureViewDescriptor};
+use winit::dpi::PhysicalSize;
+
+use crate::{
+    camera::Camera,
+    constants::DEFAULT_SAMPLE_COUNT,
+    event_loop::KeyboardState,
+    mesh_pipeline::MeshVertex,
+    objects::Objects,
+    pipeline_cache,
+    render::{Renderer, TrailStyle},
+    sim::FrameTime,
+};
+
+/// The n-body viewer as an egui widget, with its own camera and keyboard controls. This
+/// can be embedded in any egui app running on the wgpu backend.
+///
+/// Each frame, call `handle_input` first, then update the objects, then `show`.
+pub struct SpaceViewWidget {
+    camera: Camera,
+    renderer: Renderer,
+    texture: IntermediateTexture,
+    keyboard_state: KeyboardState,
+    frame: FrameTime,
+}
+
+impl SpaceViewWidget {
+    pub fn new(render_state: &RenderState, objects: &mut Objects) -> Self {
+        let initial_size = PhysicalSize {
+            width: 300,
+            height: 300,
+        };
+        pipeline_cache::init(&render_state.device, &render_state.adapter.get_info());
+        let camera = Camera::new(initial_size, &render_state.device);
+        let renderer = Renderer::new(
+            &render_state.device,
+            TextureFormat::Bgra8Unorm,
+            DEFAULT_SAMPLE_COUNT,
+            initial_size,
+            &camera,
+            objects,
+        );
+        let texture = IntermediateTexture::new(&render_state.device, initial_size, render_state);
+
+        Self {
+            camera,
+            renderer,
+            texture,
+            keyboard_state: KeyboardState::default(),
+            frame: FrameTime::default(),
+        }
+    }
+
+    pub(crate) fn camera(&self) -> &Camera {
+        &self.camera
+    }
+
+    pub(crate) fn camera_mut(&mut self) -> &mut Camera {
+        &mut self.camera
+    }
+
+    pub(crate) fn keyboard_state(&mut self) -> &mut KeyboardState {
+        &mut self.keyboard_state
+    }
+
+    /// Number of frames shown so far.
+    pub fn frame(&self) -> FrameTime {
+        self.frame
+    }
+
+    /// Index of the object the camera is following, if any.
+    pub fn focus(&self) -> Option<usize> {
+        self.camera.focus().map(|f| f as usize)
+    }
+
+    /// Bytes allocated in GPU buffers, see `Renderer::gpu_memory`.
+    pub fn gpu_memory(&self) -> u64 {
+        self.renderer.gpu_memory()
+    }
+
+    /// Samples per pixel, see `Renderer::sample_count`.
+    pub fn sample_count(&self) -> u32 {
+        self.renderer.sample_count()
+    }
+
+    /// Draw with `sample_count` samples per pixel, see `Renderer::set_sample_count`.
+    pub fn set_sample_count(&mut self, sample_count: u32) {
+        self.renderer.set_sample_count(sample_count);
+    }
+
+    /// Strength of the glow around bright objects, see `Renderer::bloom_intensity`.
+    pub fn bloom_intensity(&self) -> f32 {
+        self.renderer.bloom_intensity()
+    }
+
+    pub fn set_bloom_intensity(&mut self, intensity: f32) {
+        self.renderer.set_bloom_intensity(intensity);
+    }
+
+    /// How trails fade and how opaque they are, see `Renderer::trail_style`.
+    pub(crate) fn trail_style(&self) -> TrailStyle {
+        self.renderer.trail_style()
+    }
+
+    pub(crate) fn set_trail_style(&mut self, style: TrailStyle) {
+        self.renderer.set_trail_style(style);
+    }
+
+    /// Set the translucent surfaces drawn over the objects, see `Renderer::set_mesh`.
+    pub(crate) fn set_mesh(&mut self, render_state: &RenderState, vertices: &[MeshVertex]) {
+        self.renderer
+            .set_mesh(&render_state.device, &render_state.queue, vertices);
+    }
+
+    /// Read the keyboard controls from this frame's input.
+    pub fn handle_input(&mut self, ui: &egui::Ui) {
+        let keys = &mut self.keyboard_state;
+        ui.input(|i| {
+            for evt in &i.events {
+                if let egui::Event::Key { key, pressed, .. } = evt {
+                    match key {
+                        Key::ArrowUp => keys.up = *pressed,
+                        Key::ArrowDown => keys.down = *pressed,
+                        Key::ArrowLeft => keys.left = *pressed,
+                        Key::ArrowRight => keys.right = *pressed,
+                        Key::Home => keys.home = *pressed,
+                        Key::PageUp => keys.pgup = *pressed,
+                        Key::Space => keys.space.event(*pressed),
+                        Key::W => keys.w = *pressed,
+                        Key::S => keys.s = *pressed,
+                        Key::A => keys.a = *pressed,
+                        Key::D => keys.d = *pressed,
+                        Key::Minus => keys.minus = *pressed,
+                        Key::Plus => keys.plus = *pressed,
+                        Key::F => keys.f.event(*pressed),
+                        Key::G => keys.g.event(*pressed),
+                        Key::H => keys.h.event(*pressed),
+                        Key::J => keys.j.event(*pressed),
+                        Key::R => keys.r.event(*pressed),
+                        Key::O => keys.o = *pressed,
+                        Key::L => keys.l = *pressed,
+                        _ => (),
+                    }
+                }
+            }
+        });
+    }
+
+    /// Move the camera, render `objects` and show the result with the given size.
+    pub fn show(
+        &mut self,
+        ui: &mut egui::Ui,
+        render_state: &RenderState,
+        objects: &mut Objects,
+        size: Vec2,
+    ) -> egui::Response {
+        self.frame.advance();
+
+        let psize = PhysicalSize {
+            width: size.x.max(1.0) as u32,
+            height: size.y.max(1.0) as u32,
+        };
+        self.camera.resize(psize);
+        self.renderer.resize(psize);
+        self.texture
+            .resize(&render_state.device, psize, render_state);
+
+        self.camera.move_relative(&self.keyboard_state);
+        self.camera.zoom(&self.keyboard_state);
+        self.camera.set_focus(&mut self.keyboard_state, objects);
+        self.camera.rot(&self.keyboard_state);
+
+        self.renderer.redraw(
+            self.frame,
+            &mut self.camera,
+            objects,
+            &render_state.queue,
+            &self.texture.texture,
+            &render_state.device,
+        );
+
+        ui.add(Image::new(SizedTexture::new(self.texture.id, size)))
+    }
+}
+
+#[derive(Clone)]
+struct IntermediateTexture {
+    texture: wgpu::Texture,
+    size: PhysicalSize<u32>,
+    id: TextureId,
+}
+
+impl IntermediateTexture {
+    pub fn new(device: &wgpu::Device, size: PhysicalSize<u32>, state: &RenderState) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Intermediate Texture"),
+            size: wgpu::Extent3d {
+                width: size.width,
+                height: size.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Bgra8Unorm,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let id = state.renderer.write().register_native_texture(
+            device,
+            &texture.create_view(&TextureViewDescriptor::default()),
+            FilterMode::Nearest,
+        );
+
+        Self { texture, id, size }
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, size: PhysicalSize<u32>, state: &RenderState) {
+        if self.size != size {
+            self.size = size;
+            self.texture.destroy();
+            self.texture = device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("Intermediate Texture"),
+                size: wgpu::Extent3d {
+                    width: size.width,
+                    height: size.height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Bgra8Unorm,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            });
+            let mut renderer = state.renderer.write();
+            renderer.free_texture(&self.id);
+            self.id = renderer.register_native_texture(
+                device,
+                &self.texture.create_view(&TextureViewDescriptor::default()),
+                FilterMode::Nearest,
+            );
+        }
+    }
+}
@@ -0,0 +1,605 @@
+//! Sanity checks for scenarios, run before starting a simulation.
+
+use std::{collections::HashMap, fmt::Display};
+
+use cgmath::InnerSpace;
+use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
+
+use crate::{
+    Object,
+    constants::{AU, G, M0, VALIDATE_MAX_DENSITY, VALIDATE_MAX_LISTED, VALIDATE_MIN_DENSITY},
+    parameters::{Anomaly, RelativeOrAbsolute, StandardParams, convert_params},
+    presets::Scenario,
+    sim::{ThrustAmount, ThrustDirection},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IssueKind {
+    /// Parameters that cannot be converted to a starting state.
+    Conversion,
+    /// Parents that do not exist, are listed too late or orbit their own children.
+    Hierarchy,
+    /// Non-finite or negative values.
+    Invalid,
+    Overlap,
+    /// Not bound to the body it orbits.
+    Unbound,
+    /// Declared to orbit one body, but within the sphere of influence of another.
+    WrongParent,
+    Density,
+}
+
+impl IssueKind {
+    const ALL: [IssueKind; 7] = [
+        IssueKind::Conversion,
+        IssueKind::Hierarchy,
+        IssueKind::Invalid,
+        IssueKind::Overlap,
+        IssueKind::Unbound,
+        IssueKind::WrongParent,
+        IssueKind::Density,
+    ];
+
+    fn title(&self) -> &'static str {
+        match self {
+            IssueKind::Conversion => "Parameter conversion",
+            IssueKind::Hierarchy => "Parents",
+            IssueKind::Invalid => "Invalid values",
+            IssueKind::Overlap => "Overlapping bodies",
+            IssueKind::Unbound => "Unbound orbits",
+            IssueKind::WrongParent => "Orbits around the wrong parent",
+            IssueKind::Density => "Unrealistic densities",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Issue {
+    pub kind: IssueKind,
+    pub severity: Severity,
+    pub object: String,
+    pub message: String,
+}
+
+#[derive(Debug, Default)]
+pub struct ValidationReport {
+    pub num_objects: usize,
+    /// In earth masses
+    pub total_mass: f64,
+    pub issues: Vec<Issue>,
+}
+
+impl ValidationReport {
+    pub fn has_errors(&self) -> bool {
+        self.issues.iter().any(|i| i.severity == Severity::Error)
+    }
+
+    fn count(&self, severity: Severity) -> usize {
+        self.issues
+            .iter()
+            .filter(|i| i.severity == severity)
+            .count()
+    }
+
+    fn push(
+        &mut self,
+        kind: IssueKind,
+        severity: Severity,
+        object: &str,
+        message: impl Into<String>,
+    ) {
+        self.issues.push(Issue {
+            kind,
+            severity,
+            object: object.to_owned(),
+            message: message.into(),
+        });
+    }
+}
+
+impl Display for ValidationReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Scenario: {} objects, total mass {:.3e} earth masses",
+            self.num_objects, self.total_mass
+        )?;
+        writeln!(
+            f,
+            "{} errors, {} warnings",
+            self.count(Severity::Error),
+            self.count(Severity::Warning)
+        )?;
+
+        for kind in IssueKind::ALL {
+            let issues: Vec<_> = self.issues.iter().filter(|i| i.kind == kind).collect();
+            if issues.is_empty() {
+                continue;
+            }
+            writeln!(f, "\n{} ({}):", kind.title(), issues.len())?;
+            for issue in issues.iter().take(VALIDATE_MAX_LISTED) {
+                let severity = match issue.severity {
+                    Severity::Error => "error",
+                    Severity::Warning => "warning",
+                };
+                writeln!(f, "  [{severity}] {}: {}", issue.object, issue.message)?;
+            }
+            if issues.len() > VALIDATE_MAX_LISTED {
+                writeln!(f, "  ... and {} more", issues.len() - VALIDATE_MAX_LISTED)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Check a scenario, without running it.
+pub fn validate(scenario: Scenario) -> ValidationReport {
+    match scenario {
+        Scenario::Params(params) => validate_params(params),
+        Scenario::Objects(objects) => {
+            let parents = vec![None; objects.len()];
+            validate_objects(&objects, &parents)
+        }
+    }
+}
+
+/// Check orbital parameters, then convert them and check the resulting objects
+/// against the declared parents.
+pub fn validate_params(params: Vec<StandardParams>) -> ValidationReport {
+    let report = check_params(&params);
+    if report.has_errors() {
+        return report;
+    }
+
+    let converted = convert_params(params);
+    let parents: Vec<_> = converted.iter().map(|p| p.parent_index()).collect();
+    let objects: Vec<Object> = converted.into_iter().map(|o| o.into()).collect();
+    validate_objects(&objects, &parents)
+}
+
+/// Check that orbital parameters can be converted to a starting state. Without errors
+/// here, `convert_params` succeeds.
+pub fn check_params(params: &[StandardParams]) -> ValidationReport {
+    let mut report = ValidationReport {
+        num_objects: params.len(),
+        total_mass: params.iter().map(|p| p.mass).sum(),
+        issues: Vec::new(),
+    };
+
+    // Index of the first object with each name.
+    let mut first = HashMap::new();
+    for (i, param) in params.iter().enumerate() {
+        if first.contains_key(param.name.as_str()) {
+            report.push(
+                IssueKind::Conversion,
+                Severity::Error,
+                &param.name,
+                "name is used by more than one object",
+            );
+        } else {
+            first.insert(param.name.as_str(), i);
+        }
+    }
+
+    for (i, param) in params.iter().enumerate() {
+        let mut values = vec![
+            ("mass", param.mass),
+            ("radius", param.radius as f64),
+            ("mass rate", param.mass_rate),
+        ];
+        match &param.coordinates {
+            RelativeOrAbsolute::Absolute(coords) => {
+                values.extend(coords.pos.map(|v| ("position", v)));
+                values.extend(coords.vel.map(|v| ("velocity", v)));
+            }
+            RelativeOrAbsolute::Relative(coords) => {
+                values.extend([
+                    ("semi-major axis", coords.semi_major_axis),
+                    ("eccentricity", coords.eccentricity),
+                    ("inclination", coords.inclination),
+                    ("argument of periapsis", coords.arg_periapsis),
+                    ("longitude of the ascending node", coords.long_asc_node),
+                ]);
+                match coords.anomaly {
+                    Anomaly::True(true_an) => values.push(("true anomaly", true_an)),
+                    Anomaly::Mean {
+                        mean_anomaly,
+                        time_since_epoch,
+                    } => values.extend([
+                        ("mean anomaly", mean_anomaly),
+                        ("time since epoch", time_since_epoch),
+                    ]),
+                }
+            }
+        }
+        for (name, value) in values {
+            if !value.is_finite() {
+                report.push(
+                    IssueKind::Invalid,
+                    Severity::Error,
+                    &param.name,
+                    format!("{name} {value} is not finite"),
+                );
+            }
+        }
+
+        let RelativeOrAbsolute::Relative(coords) = &param.coordinates else {
+            continue;
+        };
+        match first.get(coords.parent.as_str()) {
+            None => report.push(
+                IssueKind::Hierarchy,
+                Severity::Error,
+                &param.name,
+                format!("parent {} does not exist", coords.parent),
+            ),
+            Some(&parent) if parent >= i => match parent_cycle(params, &first, i) {
+                Some(cycle) => report.push(
+                    IssueKind::Hierarchy,
+                    Severity::Error,
+                    &param.name,
+                    format!("parents form a cycle: {}", cycle.join(" -> ")),
+                ),
+                None => report.push(
+                    IssueKind::Hierarchy,
+                    Severity::Error,
+                    &param.name,
+                    format!(
+                        "parent {} is listed after this object, move it before",
+                        coords.parent
+                    ),
+                ),
+            },
+            Some(_) => (),
+        }
+        let e = coords.eccentricity;
+        let a = coords.semi_major_axis;
+        // Non-finite values are reported above.
+        if !e.is_finite() {
+            continue;
+        }
+        if e < 0.0 {
+            report.push(
+                IssueKind::Conversion,
+                Severity::Error,
+                &param.name,
+                format!("eccentricity {e} is negative"),
+            );
+            continue;
+        }
+        let size_issue = if !a.is_finite() {
+            None
+        } else if e < 1.0 && a <= 0.0 {
+            Some(format!("semi-major axis {a} must be positive"))
+        } else if e == 1.0 && a <= 0.0 {
+            Some(format!(
+                "semi-major axis {a} must be positive, it is the periapsis distance for a parabolic orbit"
+            ))
+        } else if e > 1.0 && a >= 0.0 {
+            Some(format!(
+                "semi-major axis {a} must be negative for a hyperbolic orbit with eccentricity {e}, or give the periapsis instead"
+            ))
+        } else {
+            None
+        };
+        if let Some(issue) = size_issue {
+            report.push(IssueKind::Conversion, Severity::Error, &param.name, issue);
+        }
+        // Open orbits never reach the directions beyond their asymptotes. Mean anomalies
+        // always land on the orbit.
+        let Anomaly::True(true_an) = coords.anomaly else {
+            continue;
+        };
+        let wrapped = (true_an + 180.0).rem_euclid(360.0) - 180.0;
+        let max_true_an = coords.max_true_anomaly();
+        if e >= 1.0 && wrapped.abs() >= max_true_an {
+            report.push(
+                IssueKind::Conversion,
+                Severity::Error,
+                &param.name,
+                format!(
+                    "true anomaly {true_an} is not on the orbit, which only reaches to {max_true_an:.1} degrees either side of periapsis"
+                ),
+            );
+        }
+    }
+    report
+}
+
+/// Names along the chain of parents from the object at `start`, if it leads back to it.
+fn parent_cycle<'a>(
+    params: &'a [StandardParams],
+    first: &HashMap<&str, usize>,
+    start: usize,
+) -> Option<Vec<&'a str>> {
+    let mut chain = vec![start];
+    let mut current = start;
+    loop {
+        let RelativeOrAbsolute::Relative(coords) = &params[current].coordinates else {
+            return None;
+        };
+        let &next = first.get(coords.parent.as_str())?;
+        if next == start {
+            chain.push(start);
+            return Some(chain.iter().map(|i| params[*i].name.as_str()).collect());
+        }
+        // A cycle further up is reported for the objects on it.
+        if chain.contains(&next) {
+            return None;
+        }
+        chain.push(next);
+        current = next;
+    }
+}
+
+/// Check a list of objects. `parents` holds the index of the body each object is
+/// declared to orbit, if any.
+pub fn validate_objects(objects: &[Object], parents: &[Option<usize>]) -> ValidationReport {
+    let mut report = ValidationReport {
+        num_objects: objects.len(),
+        total_mass: objects.iter().map(|o| o.dat.mass).sum(),
+        issues: Vec::new(),
+    };
+
+    let mut valid = true;
+    for obj in objects {
+        let finite = obj.dat.pos.x.is_finite()
+            && obj.dat.pos.y.is_finite()
+            && obj.dat.pos.z.is_finite()
+            && obj.dat.vel.x.is_finite()
+            && obj.dat.vel.y.is_finite()
+            && obj.dat.vel.z.is_finite();
+        if !finite {
+            report.push(
+                IssueKind::Invalid,
+                Severity::Error,
+                &obj.name,
+                "position or velocity is not finite",
+            );
+        }
+        if obj.dat.mass.is_nan() || obj.dat.mass < 0.0 {
+            report.push(
+                IssueKind::Invalid,
+                Severity::Error,
+                &obj.name,
+                format!("mass {} is negative", obj.dat.mass),
+            );
+        }
+        if !obj.mass_rate.is_finite() {
+            report.push(
+                IssueKind::Invalid,
+                Severity::Error,
+                &obj.name,
+                format!("mass rate {} is not finite", obj.mass_rate),
+            );
+        }
+        if obj.radius.is_nan() || obj.radius < 0.0 {
+            report.push(
+                IssueKind::Invalid,
+                Severity::Error,
+                &obj.name,
+                format!("radius {} is negative", obj.radius),
+            );
+        }
+        valid &= finite;
+    }
+    // The remaining checks are meaningless with broken positions.
+    if !valid {
+        return report;
+    }
+
+    check_overlaps(objects, &mut report);
+    check_densities(objects, &mut report);
+    check_maneuvers(objects, &mut report);
+    check_orbits(objects, parents, &mut report);
+
+    report
+}
+
+fn check_overlaps(objects: &[Object], report: &mut ValidationReport) {
+    // Sweep along the x axis, only comparing objects whose extents overlap in x.
+    let mut order: Vec<_> = (0..objects.len()).collect();
+    let start = |i: usize| objects[i].dat.pos.x - objects[i].radius as f64;
+    order.sort_by(|a, b| start(*a).total_cmp(&start(*b)));
+
+    for (pos, &i) in order.iter().enumerate() {
+        let a = &objects[i];
+        let end = a.dat.pos.x + a.radius as f64;
+        for &j in order[pos + 1..].iter().take_while(|j| start(**j) <= end) {
+            let b = &objects[j];
+            let dist = (a.dat.pos - b.dat.pos).magnitude();
+            if dist < (a.radius + b.radius) as f64 {
+                report.push(
+                    IssueKind::Overlap,
+                    Severity::Error,
+                    &a.name,
+                    format!("overlaps {} ({:.3e} AU apart)", b.name, dist),
+                );
+            }
+        }
+    }
+}
+
+fn check_maneuvers(objects: &[Object], report: &mut ValidationReport) {
+    for (index, obj) in objects.iter().enumerate() {
+        for (i, maneuver) in obj.maneuvers.iter().enumerate() {
+            let mut problem = |message: String| {
+                report.push(
+                    IssueKind::Invalid,
+                    Severity::Error,
+                    &obj.name,
+                    format!("maneuver {i}: {message}"),
+                )
+            };
+            if !maneuver.start.is_finite()
+                || !maneuver.duration.is_finite()
+                || maneuver.duration < 0.0
+            {
+                problem(format!(
+                    "window from {} for {} s is invalid",
+                    maneuver.start, maneuver.duration
+                ));
+            }
+            if !maneuver.delta_v().is_finite() {
+                problem("thrust is not finite".to_owned());
+            }
+            match maneuver.reference {
+                Some(reference) if reference == index => {
+                    problem("is relative to the spacecraft itself".to_owned())
+                }
+                Some(reference) if reference >= objects.len() => {
+                    problem(format!("reference {reference} does not exist"))
+                }
+                _ => (),
+            }
+            if let ThrustDirection::Fixed(dir) = maneuver.direction
+                && (dir.magnitude2() == 0.0 || !dir.magnitude2().is_finite())
+            {
+                problem("fixed direction has no length".to_owned());
+            }
+            if maneuver.duration == 0.0 && matches!(maneuver.amount, ThrustAmount::Acceleration(_))
+            {
+                report.push(
+                    IssueKind::Invalid,
+                    Severity::Warning,
+                    &obj.name,
+                    format!("maneuver {i}: constant acceleration over no time does nothing"),
+                );
+            }
+        }
+    }
+}
+
+fn check_densities(objects: &[Object], report: &mut ValidationReport) {
+    for obj in objects {
+        // Massless test particles and point masses have no meaningful density.
+        if obj.dat.mass == 0.0 || obj.radius == 0.0 {
+            continue;
+        }
+        let radius = obj.radius as f64 * AU;
+        let density = obj.dat.mass * M0 / (4.0 / 3.0 * std::f64::consts::PI * radius.powi(3));
+        if !(VALIDATE_MIN_DENSITY..=VALIDATE_MAX_DENSITY).contains(&density) {
+            report.push(
+                IssueKind::Density,
+                Severity::Warning,
+                &obj.name,
+                format!("density is {density:.3e} kg/m^3"),
+            );
+        }
+    }
+}
+
+/// Index of the body with the strongest pull on each object without a declared parent.
+fn dominant_attractors(objects: &[Object], parents: &[Option<usize>]) -> Vec<Option<usize>> {
+    objects
+        .par_iter()
+        .zip(parents.par_iter())
+        .enumerate()
+        .map(|(i, (obj, parent))| {
+            if parent.is_some() {
+                return *parent;
+            }
+            let mut best = None;
+            let mut best_acc = 0.0;
+            for (j, other) in objects.iter().enumerate() {
+                if i == j || other.dat.mass == 0.0 {
+                    continue;
+                }
+                let dist_sq = (other.dat.pos - obj.dat.pos).magnitude2();
+                let acc = other.dat.mass / dist_sq;
+                if acc > best_acc {
+                    best_acc = acc;
+                    best = Some(j);
+                }
+            }
+            best
+        })
+        .collect()
+}
+
+/// Radius of the sphere of influence of each object around its declared parent.
+/// Objects without a parent dominate everywhere.
+fn spheres_of_influence(objects: &[Object], parents: &[Option<usize>]) -> Vec<f64> {
+    objects
+        .iter()
+        .zip(parents.iter())
+        .map(|(obj, parent)| match parent {
+            Some(p) if objects[*p].dat.mass > 0.0 => {
+                let parent = &objects[*p];
+                (obj.dat.pos - parent.dat.pos).magnitude()
+                    * (obj.dat.mass / parent.dat.mass).powf(0.4)
+            }
+            Some(_) => 0.0,
+            None => f64::INFINITY,
+        })
+        .collect()
+}
+
+fn check_orbits(objects: &[Object], parents: &[Option<usize>], report: &mut ValidationReport) {
+    let attractors = dominant_attractors(objects, parents);
+    let soi = spheres_of_influence(objects, parents);
+
+    for (i, obj) in objects.iter().enumerate() {
+        // Without a declared parent, check against whatever the object is pulled towards.
+        let Some(parent) = attractors[i] else {
+            continue;
+        };
+        let other = &objects[parent];
+        let rel = obj.dat.pos - other.dat.pos;
+        let rel_vel = obj.dat.vel - other.dat.vel;
+        let energy =
+            rel_vel.magnitude2() / 2.0 - G * (obj.dat.mass + other.dat.mass) / rel.magnitude();
+        if energy >= 0.0 {
+            report.push(
+                IssueKind::Unbound,
+                Severity::Warning,
+                &obj.name,
+                format!("not bound to {}", other.name),
+            );
+        }
+
+        if parents[i].is_none() {
+            continue;
+        }
+        if rel.magnitude() > soi[parent] {
+            report.push(
+                IssueKind::WrongParent,
+                Severity::Warning,
+                &obj.name,
+                format!(
+                    "declared to orbit {}, but is outside its sphere of influence",
+                    other.name
+                ),
+            );
+            continue;
+        }
+        // Look for a smaller sphere of influence the object is inside of.
+        let inside = objects
+            .iter()
+            .enumerate()
+            .filter(|(j, o)| {
+                *j != i
+                    && *j != parent
+                    && soi[*j] < soi[parent]
+                    && (o.dat.pos - obj.dat.pos).magnitude() < soi[*j]
+            })
+            .min_by(|(a, _), (b, _)| soi[*a].total_cmp(&soi[*b]));
+        if let Some((_, closer)) = inside {
+            report.push(
+                IssueKind::WrongParent,
+                Severity::Warning,
+                &obj.name,
+                format!(
+                    "declared to orbit {}, but is inside the sphere of influence of {}",
+                    other.name, closer.name
+                ),
+            );
+        }
+    }
+}
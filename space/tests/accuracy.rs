@@ -0,0 +1,199 @@
+use cgmath::{InnerSpace, Point3, Vector3, Zero};
+use space::{
+    BruteForceSim, DualTreeSim, FmmSim, KdTreeSim, ObjectInfo, SimulationImpl,
+    SymmetricBruteForceSim,
+    constants::BARNES_HUT_COEFF,
+    presets::{Imf, plummer_cluster},
+    rng::RngService,
+    validate_against_direct,
+};
+
+/// A clumpy disk of objects, with a heavy one in the middle, a few test particles and a
+/// frozen object.
+fn objects() -> Vec<ObjectInfo> {
+    let mut objects: Vec<_> = (0..500)
+        .map(|i| {
+            let t = i as f64 * 0.61;
+            let r = 1.0 + (i % 13) as f64 * 0.4;
+            ObjectInfo {
+                pos: Point3::new(t.cos() * r, t.sin() * r, (t * 0.3).sin() * 0.1),
+                vel: Vector3::new(0.0, 0.0, 0.0),
+                mass: 1.0 + (i % 5) as f64,
+                pinned: false,
+                test_particle: i % 50 == 0,
+            }
+        })
+        .collect();
+    objects.push(ObjectInfo {
+        pos: Point3::new(0.0, 0.0, 0.0),
+        vel: Vector3::new(0.0, 0.0, 0.0),
+        mass: 1000.0,
+        pinned: true,
+        test_particle: false,
+    });
+    objects.push(ObjectInfo {
+        pos: Point3::new(100.0, 0.0, 0.0),
+        vel: Vector3::new(0.0, 0.0, 0.0),
+        mass: 0.0,
+        pinned: true,
+        test_particle: true,
+    });
+    objects
+}
+
+#[test]
+fn zero_theta_matches_direct_summation() {
+    let comparison = validate_against_direct(&objects(), 0.0);
+    assert!(
+        comparison.rms_relative_error() < 1e-12,
+        "{}",
+        comparison.rms_relative_error()
+    );
+}
+
+#[test]
+fn error_grows_with_theta() {
+    let objects = objects();
+    let errors: Vec<_> = [0.2, 0.5, 1.0]
+        .into_iter()
+        .map(|theta| validate_against_direct(&objects, theta).rms_relative_error())
+        .collect();
+    assert!(errors[0] < errors[1] && errors[1] < errors[2], "{errors:?}");
+}
+
+#[test]
+fn default_theta_is_accurate() {
+    let comparison = validate_against_direct(&objects(), BARNES_HUT_COEFF);
+    assert!(comparison.rms_relative_error() < 1e-2);
+    let (_, max) = comparison.max_relative_error().unwrap();
+    assert!(max < 0.1, "{max}");
+}
+
+#[test]
+fn frozen_objects_are_left_out() {
+    let objects = objects();
+    let comparison = validate_against_direct(&objects, BARNES_HUT_COEFF);
+    assert_eq!(comparison.errors.len(), objects.len());
+    assert_eq!(comparison.relative_error(objects.len() - 1), None);
+}
+
+/// A Plummer cluster, crowded in the middle.
+fn cluster() -> Vec<ObjectInfo> {
+    let imf = Imf::Kroupa {
+        min: 0.1,
+        max: 10.0,
+    };
+    plummer_cluster(4000, 1.0, imf, &RngService::new(7))
+        .into_iter()
+        .map(|obj| obj.dat)
+        .collect()
+}
+
+/// Root mean square of the error in the gravity `sim` gives each simulated object in
+/// `objects`, relative to direct summation.
+fn rms_error_against_direct(sim: &mut impl SimulationImpl, mut objects: Vec<ObjectInfo>) -> f64 {
+    let mut approx = vec![Vector3::zero(); objects.len()];
+    let mut exact = vec![Vector3::zero(); objects.len()];
+    sim.iter(&mut objects, &mut approx);
+    BruteForceSim::new().iter(&mut objects, &mut exact);
+
+    let errors: Vec<_> = objects
+        .iter()
+        .zip(approx.iter().zip(&exact))
+        .filter(|(obj, _)| !obj.is_frozen())
+        .map(|(_, (approx, exact))| (approx - exact).magnitude2() / exact.magnitude2())
+        .collect();
+    (errors.iter().sum::<f64>() / errors.len() as f64).sqrt()
+}
+
+#[test]
+fn fmm_matches_direct_summation() {
+    // One solver for both, so the second run must build its tree from scratch.
+    let mut sim = FmmSim::new(BARNES_HUT_COEFF);
+    let error = rms_error_against_direct(&mut sim, objects());
+    assert!(error < 1e-2, "{error}");
+    let error = rms_error_against_direct(&mut sim, cluster());
+    assert!(error < 1e-2, "{error}");
+    let error = rms_error_against_direct(&mut FmmSim::new(0.0), objects());
+    assert!(error < 1e-12, "{error}");
+}
+
+#[test]
+fn parallel_fmm_matches_single_threaded() {
+    let mut objects = cluster();
+    let mut single = vec![Vector3::zero(); objects.len()];
+    FmmSim::new(BARNES_HUT_COEFF).iter_single_threaded(&mut objects, &mut single);
+
+    // Several threads, so that the node pairs are split between tasks even here.
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+    let mut par = vec![Vector3::zero(); objects.len()];
+    pool.install(|| FmmSim::new(BARNES_HUT_COEFF).iter(&mut objects, &mut par));
+    for (par, single) in par.iter().zip(&single) {
+        assert!(
+            (par - single).magnitude() <= 1e-10 * single.magnitude(),
+            "{par:?} != {single:?}"
+        );
+    }
+}
+
+#[test]
+fn kd_tree_matches_direct_summation() {
+    let error = rms_error_against_direct(&mut KdTreeSim::new(BARNES_HUT_COEFF), objects());
+    assert!(error < 1e-2, "{error}");
+    let error = rms_error_against_direct(&mut KdTreeSim::new(BARNES_HUT_COEFF), cluster());
+    assert!(error < 1e-2, "{error}");
+    let error = rms_error_against_direct(&mut KdTreeSim::new(0.0), objects());
+    assert!(error < 1e-12, "{error}");
+}
+
+#[test]
+fn dual_tree_matches_direct_summation() {
+    let error = rms_error_against_direct(&mut DualTreeSim::new(BARNES_HUT_COEFF), objects());
+    assert!(error < 1e-2, "{error}");
+    let error = rms_error_against_direct(&mut DualTreeSim::new(BARNES_HUT_COEFF), cluster());
+    assert!(error < 1e-2, "{error}");
+    let error = rms_error_against_direct(&mut DualTreeSim::new(0.0), objects());
+    assert!(error < 1e-12, "{error}");
+}
+
+#[test]
+fn symmetric_brute_force_matches_direct_summation() {
+    let mut objects = objects();
+    let mut exact = vec![Vector3::zero(); objects.len()];
+    BruteForceSim::new().iter_single_threaded(&mut objects, &mut exact);
+
+    let mut sim = SymmetricBruteForceSim::new();
+    let mut single = vec![Vector3::zero(); objects.len()];
+    sim.iter_single_threaded(&mut objects, &mut single);
+    let mut par = vec![Vector3::zero(); objects.len()];
+    sim.iter(&mut objects, &mut par);
+
+    for acc in [single, par] {
+        for (acc, exact) in acc.iter().zip(&exact) {
+            assert!(
+                (acc - exact).magnitude() <= 1e-10 * exact.magnitude(),
+                "{acc:?} != {exact:?}"
+            );
+        }
+    }
+}
+
+#[test]
+fn reused_brute_force_follows_test_particles() {
+    // The first call finds the massive objects, which the second must find again.
+    let mut sim = BruteForceSim::new();
+    let mut objects = objects();
+    let mut acc = vec![Vector3::zero(); objects.len()];
+    sim.iter(&mut objects, &mut acc);
+
+    objects[1].test_particle = !objects[1].test_particle;
+    objects[50].test_particle = !objects[50].test_particle;
+    let mut reused = vec![Vector3::zero(); objects.len()];
+    sim.iter(&mut objects, &mut reused);
+    let mut fresh = vec![Vector3::zero(); objects.len()];
+    BruteForceSim::new().iter(&mut objects, &mut fresh);
+    assert_eq!(reused, fresh);
+}
@@ -0,0 +1,43 @@
+use space::{
+    SolverKind,
+    constants::{BARNES_HUT_CUTOFF, SOLVER_HYSTERESIS},
+};
+
+/// Counts just inside and just outside the band around the cutoff where the solver is
+/// kept, as (below the band, inside below, inside above, above the band).
+fn band() -> (usize, usize, usize, usize) {
+    let cutoff = BARNES_HUT_CUTOFF as f64;
+    let low = cutoff / (1.0 + SOLVER_HYSTERESIS);
+    let high = cutoff * (1.0 + SOLVER_HYSTERESIS);
+    (
+        low.floor() as usize - 1,
+        low.ceil() as usize + 1,
+        high.floor() as usize - 1,
+        high.ceil() as usize + 1,
+    )
+}
+
+#[test]
+fn count_hovering_around_the_cutoff_keeps_the_solver() {
+    let (_, inside_low, inside_high, _) = band();
+    for kind in [SolverKind::BruteForce, SolverKind::BarnesHut] {
+        for n in [
+            inside_low,
+            BARNES_HUT_CUTOFF - 1,
+            BARNES_HUT_CUTOFF,
+            BARNES_HUT_CUTOFF + 1,
+            inside_high,
+        ] {
+            assert_eq!(kind.next(n), kind, "{n} objects");
+        }
+    }
+}
+
+#[test]
+fn count_well_past_the_cutoff_switches_solver() {
+    let (below, _, _, above) = band();
+    assert_eq!(SolverKind::BruteForce.next(above), SolverKind::BarnesHut);
+    assert_eq!(SolverKind::BarnesHut.next(below), SolverKind::BruteForce);
+    assert_eq!(SolverKind::BruteForce.next(below), SolverKind::BruteForce);
+    assert_eq!(SolverKind::BarnesHut.next(above), SolverKind::BarnesHut);
+}
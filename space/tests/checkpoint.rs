@@ -0,0 +1,66 @@
+use std::io::Cursor;
+
+use cgmath::{Point3, Vector3};
+use space::{
+    ObjectInfo, SimTime,
+    checkpoint::{Checkpoint, is_checkpoint, write_checkpoint},
+};
+
+fn objects() -> Vec<ObjectInfo> {
+    (0..4)
+        .map(|i| ObjectInfo {
+            pos: Point3::new(i as f64, -0.5 * i as f64, 1e-9),
+            vel: Vector3::new(1e-8, 2e-8 * i as f64, -3e-8),
+            mass: 1.5 * i as f64,
+            pinned: i == 1,
+            test_particle: i >= 2,
+        })
+        .collect()
+}
+
+fn write() -> Vec<u8> {
+    let time = SimTime {
+        ticks: 1234,
+        delta: 60.0,
+        seconds: 70_000.5,
+    };
+    let mut bytes = Vec::new();
+    write_checkpoint(&mut bytes, time, &objects(), &[0, 3]).unwrap();
+    bytes
+}
+
+#[test]
+fn round_trip() {
+    assert!(is_checkpoint(Cursor::new(write())));
+    let checkpoint = Checkpoint::read(Cursor::new(write())).unwrap();
+    assert_eq!(
+        checkpoint.time,
+        SimTime {
+            ticks: 1234,
+            delta: 60.0,
+            seconds: 70_000.5,
+        }
+    );
+    assert_eq!(checkpoint.pending_injections, [0, 3]);
+    assert_eq!(checkpoint.objects.len(), 4);
+    for (read, written) in checkpoint.objects.iter().zip(objects()) {
+        assert_eq!(read.pos, written.pos);
+        assert_eq!(read.vel, written.vel);
+        assert_eq!(read.mass, written.mass);
+        assert_eq!(read.pinned, written.pinned);
+        assert_eq!(read.test_particle, written.test_particle);
+    }
+}
+
+#[test]
+fn corrupt_checkpoints_are_refused() {
+    let bytes = write();
+    assert!(!is_checkpoint(Cursor::new(&bytes[..4])));
+    assert!(Checkpoint::read(Cursor::new(bytes[..60].to_vec())).is_err());
+
+    // Far more objects than the file could hold. The count follows the magic, version,
+    // tick, delta and seconds.
+    let mut huge = bytes;
+    huge[36..40].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert!(Checkpoint::read(Cursor::new(huge)).is_err());
+}
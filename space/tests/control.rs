@@ -0,0 +1,27 @@
+use std::path::Path;
+
+use space::control::snapshot_path;
+
+#[test]
+fn snapshots_stay_in_their_directory() {
+    let dir = Path::new("snapshots");
+    assert_eq!(
+        snapshot_path(dir, "step_100.parquet").unwrap(),
+        dir.join("step_100.parquet")
+    );
+    for name in [
+        "",
+        ".",
+        "..",
+        "../escape.parquet",
+        "/tmp/escape.parquet",
+        "sub/dir.parquet",
+        "./here.parquet",
+        "trailing/",
+    ] {
+        assert!(
+            snapshot_path(dir, name).is_err(),
+            "{name:?} should be refused"
+        );
+    }
+}
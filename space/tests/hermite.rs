@@ -0,0 +1,72 @@
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector3};
+use space::{
+    Hermite, ObjectInfo,
+    constants::{G, SOLAR_MASS},
+};
+
+const PLANET_MASS: f64 = 1.0;
+const ECCENTRICITY: f64 = 0.5;
+
+fn body(pos: Vector3<f64>, vel: Vector3<f64>, mass: f64) -> ObjectInfo {
+    ObjectInfo {
+        pos: Point3::from_vec(pos),
+        vel,
+        mass,
+        pinned: false,
+        test_particle: false,
+    }
+}
+
+/// A planet at perihelion of an orbit with a semi major axis of 1 AU, and the sun, with
+/// the center of mass at rest.
+fn sun_and_planet() -> Vec<ObjectInfo> {
+    let mu = G * (SOLAR_MASS + PLANET_MASS);
+    let dist = 1.0 - ECCENTRICITY;
+    let speed = (mu * (1.0 + ECCENTRICITY) / dist).sqrt();
+    let sun_share = PLANET_MASS / (SOLAR_MASS + PLANET_MASS);
+    let rel = Vector3::new(dist, 0.0, 0.0);
+    let rel_vel = Vector3::new(0.0, speed, 0.0);
+    vec![
+        body(-rel * sun_share, -rel_vel * sun_share, SOLAR_MASS),
+        body(
+            rel * (1.0 - sun_share),
+            rel_vel * (1.0 - sun_share),
+            PLANET_MASS,
+        ),
+    ]
+}
+
+fn energy(objects: &[ObjectInfo]) -> f64 {
+    let kinetic: f64 = objects
+        .iter()
+        .map(|obj| obj.mass * obj.vel.magnitude2() / 2.0)
+        .sum();
+    let (a, b) = (&objects[0], &objects[1]);
+    kinetic - G * a.mass * b.mass / (b.pos - a.pos).magnitude()
+}
+
+/// Relative energy error after one orbit, integrated with `accuracy`.
+fn energy_error(accuracy: f64) -> f64 {
+    let mut objects = sun_and_planet();
+    let start = energy(&objects);
+    let mut hermite = Hermite::new(accuracy);
+    let mu = G * (SOLAR_MASS + PLANET_MASS);
+    let ticks = 100;
+    let delta = 2.0 * std::f64::consts::PI / mu.sqrt() / ticks as f64;
+    for _ in 0..ticks {
+        hermite.advance(&mut objects, None, delta);
+    }
+    ((energy(&objects) - start) / start).abs()
+}
+
+#[test]
+fn energy_error_shrinks_with_accuracy() {
+    let errors: Vec<_> = [0.03, 0.01, 0.003, 0.001]
+        .into_iter()
+        .map(energy_error)
+        .collect();
+    assert!(errors[3] < 1e-8, "{errors:?}");
+    for pair in errors.windows(2) {
+        assert!(pair[1] < pair[0] / 2.0, "{errors:?}");
+    }
+}
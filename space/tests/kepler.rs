@@ -0,0 +1,132 @@
+use cgmath::{InnerSpace, Point3, Vector3};
+use space::{
+    BruteForceSim, KeplerPairs, ObjectInfo, SimulationImpl,
+    constants::{AU, G, M0},
+    kepler_drift,
+};
+
+const SUN_MASS: f64 = 1.989e30 / M0;
+const PLANET_MASS: f64 = 1.0;
+const SEMI_MAJOR_AXIS: f64 = 1.0;
+const ECCENTRICITY: f64 = 0.5;
+const TIME_STEP: f64 = 60.0;
+
+fn mu() -> f64 {
+    G * (SUN_MASS + PLANET_MASS)
+}
+
+fn period() -> f64 {
+    2.0 * std::f64::consts::PI * (SEMI_MAJOR_AXIS.powi(3) / mu()).sqrt()
+}
+
+/// Relative position and velocity of the planet at perihelion.
+fn perihelion() -> (Vector3<f64>, Vector3<f64>) {
+    let dist = SEMI_MAJOR_AXIS * (1.0 - ECCENTRICITY);
+    let speed = (mu() * (1.0 + ECCENTRICITY) / dist).sqrt();
+    (Vector3::new(dist, 0.0, 0.0), Vector3::new(0.0, speed, 0.0))
+}
+
+#[test]
+fn bound_orbit_returns_after_one_period() {
+    let (rel, rel_vel) = perihelion();
+    // A whole period in one go is cut short before solving, so go round in pieces.
+    let steps = 7;
+    let (mut pos, mut vel) = (rel, rel_vel);
+    for _ in 0..steps {
+        (pos, vel) = kepler_drift(mu(), pos, vel, period() / steps as f64);
+    }
+    assert!(
+        (pos - rel).magnitude() < 1e-9 * rel.magnitude(),
+        "Ended at {pos:?}, started at {rel:?}"
+    );
+    assert!(
+        (vel - rel_vel).magnitude() < 1e-9 * rel_vel.magnitude(),
+        "Ended with {vel:?}, started with {rel_vel:?}"
+    );
+
+    // Half way round is the aphelion.
+    let (pos, _) = kepler_drift(mu(), rel, rel_vel, period() / 2.0);
+    let aphelion = SEMI_MAJOR_AXIS * (1.0 + ECCENTRICITY);
+    assert!(
+        (pos + Vector3::new(aphelion, 0.0, 0.0)).magnitude() < 1e-9 * aphelion,
+        "Aphelion at {pos:?}"
+    );
+}
+
+#[test]
+fn matches_integrated_orbit_for_small_steps() {
+    let (rel, rel_vel) = perihelion();
+    let mut objects = vec![
+        ObjectInfo {
+            pos: Point3::new(0.0, 0.0, 0.0),
+            vel: Vector3::new(0.0, 0.0, 0.0),
+            mass: SUN_MASS,
+            pinned: false,
+            test_particle: false,
+        },
+        ObjectInfo {
+            pos: Point3::new(rel.x, rel.y, rel.z),
+            vel: rel_vel,
+            mass: PLANET_MASS,
+            pinned: false,
+            test_particle: false,
+        },
+    ];
+    let mut acc = vec![Vector3::new(0.0, 0.0, 0.0); objects.len()];
+    // Past the perihelion and a bit further, where the orbit bends the most.
+    let steps = (period() / 8.0 / TIME_STEP).round() as usize;
+    for _ in 0..steps {
+        BruteForceSim::new().iter_single_threaded(&mut objects, &mut acc);
+        for (obj, acc) in objects.iter_mut().zip(acc.iter_mut()) {
+            obj.vel += *acc * TIME_STEP;
+            obj.pos += obj.vel * TIME_STEP;
+            *acc = Vector3::new(0.0, 0.0, 0.0);
+        }
+    }
+
+    let (pos, vel) = kepler_drift(mu(), rel, rel_vel, steps as f64 * TIME_STEP);
+    let integrated = objects[1].pos - objects[0].pos;
+    let integrated_vel = objects[1].vel - objects[0].vel;
+    assert!(
+        (integrated - pos).magnitude() < 1e-4 * pos.magnitude(),
+        "Integrated to {integrated:?}, analytic {pos:?} ({} km apart)",
+        (integrated - pos).magnitude() * AU / 1000.0
+    );
+    assert!(
+        (integrated_vel - vel).magnitude() < 1e-4 * vel.magnitude(),
+        "Integrated to {integrated_vel:?}, analytic {vel:?}"
+    );
+}
+
+#[test]
+fn coincident_bodies_move_in_a_straight_line() {
+    let rel_vel = Vector3::new(0.0, 1e-8, 0.0);
+    let (pos, vel) = kepler_drift(mu(), Vector3::new(0.0, 0.0, 0.0), rel_vel, TIME_STEP);
+    assert_eq!(pos, rel_vel * TIME_STEP);
+    assert_eq!(vel, rel_vel);
+}
+
+fn body(pos: Vector3<f64>, vel: Vector3<f64>, mass: f64) -> ObjectInfo {
+    ObjectInfo {
+        pos: Point3::new(pos.x, pos.y, pos.z),
+        vel,
+        mass,
+        pinned: false,
+        test_particle: false,
+    }
+}
+
+#[test]
+fn two_body_systems_are_paired_unless_they_collide() {
+    let zero = Vector3::new(0.0, 0.0, 0.0);
+    let (rel, rel_vel) = perihelion();
+    let mut kepler = KeplerPairs::two_body();
+    kepler.update(&[body(zero, zero, SUN_MASS), body(rel, rel_vel, PLANET_MASS)]);
+    assert_eq!(kepler.pairs(), &[(0, 1)]);
+
+    // Falling straight into the sun, which only the softened force solver survives.
+    let falling = rel_vel.magnitude() * -rel.normalize();
+    let mut kepler = KeplerPairs::two_body();
+    kepler.update(&[body(zero, zero, SUN_MASS), body(rel, falling, PLANET_MASS)]);
+    assert!(kepler.pairs().is_empty());
+}
@@ -0,0 +1,131 @@
+use cgmath::{Basis3, EuclideanSpace, InnerSpace, Point3, Rad, Rotation, Rotation3, Vector3, Zero};
+use space::{BarnesHutSim, FmmTree, ObjectInfo, SimulationImpl, constants::BARNES_HUT_COEFF};
+
+fn object(pos: Point3<f64>, mass: f64) -> ObjectInfo {
+    ObjectInfo {
+        pos,
+        vel: Vector3::new(0.0, 0.0, 0.0),
+        mass,
+        pinned: false,
+        test_particle: false,
+    }
+}
+
+/// Objects spread over a spiral, with a few of them on top of each other, which is the
+/// case where the tree stops subdividing.
+fn objects() -> Vec<ObjectInfo> {
+    let mut objects: Vec<_> = (0..200)
+        .map(|i| {
+            let t = i as f64 * 0.37;
+            object(
+                Point3::new(t.cos() * t, t.sin() * t, (t * 0.1).sin()),
+                1.0 + (i % 7) as f64,
+            )
+        })
+        .collect();
+    objects.extend((0..3).map(|_| object(Point3::new(0.5, 0.5, 0.5), 2.0)));
+    objects.push(object(Point3::new(10.0, 0.0, 0.0), 0.0));
+    objects
+}
+
+/// Build the tree the way a step of the simulation would.
+fn build_tree(objects: &mut [ObjectInfo]) -> FmmTree {
+    let mut sim = BarnesHutSim::new(BARNES_HUT_COEFF);
+    let mut acc = vec![Vector3::new(0.0, 0.0, 0.0); objects.len()];
+    sim.iter(objects, &mut acc);
+    sim.tree
+}
+
+fn dump(tree: &FmmTree) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    tree.write_to(&mut bytes).unwrap();
+    bytes
+}
+
+#[test]
+fn dump_round_trips() {
+    let mut objects = objects();
+    let tree = build_tree(&mut objects);
+    let loaded = FmmTree::read_from(dump(&tree).as_slice()).unwrap();
+    assert_eq!(tree, loaded);
+
+    let total_mass: f64 = objects.iter().map(|o| o.mass).sum();
+    let (_, root) = loaded.get(loaded.root_id());
+    assert!((root.mass - total_mass).abs() < 1e-9 * total_mass);
+}
+
+#[test]
+fn dump_round_trips_through_file() {
+    let mut objects = objects();
+    let tree = build_tree(&mut objects);
+    let path = std::env::temp_dir().join(format!("octree-{}.bin", std::process::id()));
+    tree.save(&path).unwrap();
+    let loaded = FmmTree::load(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(tree, loaded.unwrap());
+}
+
+#[test]
+fn empty_tree_round_trips() {
+    let tree = build_tree(&mut []);
+    let loaded = FmmTree::read_from(dump(&tree).as_slice()).unwrap();
+    assert_eq!(tree, loaded);
+    assert!(loaded.is_empty());
+}
+
+#[test]
+fn broken_dumps_are_rejected() {
+    let mut objects = objects();
+    let bytes = dump(&build_tree(&mut objects));
+
+    assert!(FmmTree::read_from(&bytes[..bytes.len() - 1]).is_err());
+    assert!(FmmTree::read_from(&b"not an octree"[..]).is_err());
+
+    // The first child of the root, pointing back at the root.
+    let mut looped = bytes.clone();
+    let child = 8 + 4 + 8 + 4 * 8 + 1 + 7 * 8;
+    looped[child..child + 8].copy_from_slice(&0u64.to_le_bytes());
+    assert!(FmmTree::read_from(looped.as_slice()).is_err());
+}
+
+/// Relative RMS difference between two sets of accelerations.
+fn rms_difference(a: &[Vector3<f64>], b: &[Vector3<f64>]) -> f64 {
+    let (diff, norm) = a.iter().zip(b).fold((0.0, 0.0), |(diff, norm), (a, b)| {
+        (diff + (a - b).magnitude2(), norm + b.magnitude2())
+    });
+    (diff / norm).sqrt()
+}
+
+#[test]
+fn updated_tree_matches_fresh_tree() {
+    for theta in [0.0, BARNES_HUT_COEFF] {
+        let mut objects = objects();
+        let mut updated = BarnesHutSim::new(theta).with_incremental_tree();
+        let mut acc = vec![Vector3::zero(); objects.len()];
+        let mut fresh = vec![Vector3::zero(); objects.len()];
+        // Wind the spiral up, faster towards the middle like a disk, so that objects cross
+        // the octant boundaries inside the root and nodes are sheared apart. Then spread
+        // it out so that they leave the root region.
+        for step in 0..30 {
+            for obj in &mut objects {
+                let r = obj.pos.to_vec().magnitude();
+                let (angle, scale) = if step < 20 {
+                    (0.5 / (1.0 + r), 1.0)
+                } else {
+                    (0.0, 1.05)
+                };
+                let rotation = Basis3::from_angle_z(Rad(angle));
+                obj.pos = Point3::from_vec(rotation.rotate_vector(obj.pos.to_vec()) * scale);
+            }
+            acc.fill(Vector3::zero());
+            fresh.fill(Vector3::zero());
+            updated.iter(&mut objects, &mut acc);
+            BarnesHutSim::new(theta).iter(&mut objects, &mut fresh);
+            // Without approximations both trees sum every pair, otherwise their cells
+            // differ by the margin of the updated tree.
+            let tolerance = if theta == 0.0 { 1e-12 } else { 1e-4 };
+            let error = rms_difference(&acc, &fresh);
+            assert!(error < tolerance, "theta {theta}, step {step}: {error}");
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use cgmath::Vector3;
+use space::{
+    Object,
+    constants::{AU, G_ABS, M0},
+    parameters::{
+        AbsoluteCoords, Anomaly, RelativeCoords, RelativeOrAbsolute, StandardParams,
+        compute_orbital_params, convert_params,
+    },
+};
+
+const SUN_MASS: f64 = 333_000.0;
+const BODY_MASS: f64 = 1.0;
+
+fn params(name: &str, coordinates: RelativeOrAbsolute, mass: f64) -> StandardParams {
+    StandardParams {
+        name: name.to_owned(),
+        coordinates,
+        mass,
+        radius: 1.0,
+        color: [1.0, 1.0, 1.0],
+        oblateness: None,
+        radiation: None,
+        maneuvers: Vec::new(),
+        mass_rate: 0.0,
+        texture: None,
+    }
+}
+
+fn mu() -> f64 {
+    G_ABS * (SUN_MASS + BODY_MASS) * M0
+}
+
+/// Convert `coords` around a sun, and give back the position and velocity of the body
+/// relative to it, in m and m/s.
+fn convert(coords: RelativeCoords, mass: f64) -> (Vector3<f64>, Vector3<f64>) {
+    let sun = params(
+        "sun",
+        RelativeOrAbsolute::Absolute(AbsoluteCoords {
+            pos: [0.0; 3],
+            vel: [0.0; 3],
+        }),
+        SUN_MASS,
+    );
+    let body = params("body", RelativeOrAbsolute::Relative(coords), mass);
+    let objects: Vec<Object> = convert_params([sun, body])
+        .into_iter()
+        .map(Object::from)
+        .collect();
+    for obj in &objects {
+        assert!(
+            obj.dat.pos.x.is_finite() && obj.dat.vel.x.is_finite(),
+            "{obj:?}"
+        );
+    }
+    (
+        (objects[1].dat.pos - objects[0].dat.pos) * AU,
+        (objects[1].dat.vel - objects[0].dat.vel) * AU,
+    )
+}
+
+fn elements(semi_major_axis: f64, eccentricity: f64, angles: [f64; 4]) -> RelativeCoords {
+    RelativeCoords {
+        parent: "sun".to_owned(),
+        semi_major_axis,
+        eccentricity,
+        inclination: angles[0],
+        arg_periapsis: angles[1],
+        long_asc_node: angles[2],
+        anomaly: Anomaly::True(angles[3]),
+    }
+}
+
+fn assert_angle(found: f64, expected: f64, what: &str) {
+    let diff = (found - expected).rem_euclid(360.0);
+    assert!(
+        diff.min(360.0 - diff) < 1e-6,
+        "{what}: found {found}, expected {expected}"
+    );
+}
+
+/// Convert elements to a state and back, and check that they are the same.
+fn round_trip(semi_major_axis: f64, eccentricity: f64, angles: [f64; 4]) {
+    let (pos, vel) = convert(elements(semi_major_axis, eccentricity, angles), BODY_MASS);
+    let found = compute_orbital_params("sun".to_owned(), mu(), pos, vel);
+
+    assert!(
+        (found.eccentricity - eccentricity).abs() < 1e-9,
+        "Eccentricity {} for {eccentricity}",
+        found.eccentricity
+    );
+    // The semi-major axis of a parabola is infinite, and its periapsis is lost in
+    // rounding, so only the distance along the orbit is checked.
+    if eccentricity == 1.0 {
+        let expected = 2.0 * semi_major_axis / (1.0 + angles[3].to_radians().cos());
+        assert!((pos.x.hypot(pos.y).hypot(pos.z) - expected).abs() < 1e-9 * expected);
+    } else {
+        assert!(
+            (found.semi_major_axis - semi_major_axis).abs() < 1e-9 * semi_major_axis.abs(),
+            "Semi-major axis {} for {semi_major_axis}",
+            found.semi_major_axis
+        );
+    }
+    assert_angle(found.inclination, angles[0], "Inclination");
+    assert_angle(found.arg_periapsis, angles[1], "Argument of periapsis");
+    assert_angle(
+        found.long_asc_node,
+        angles[2],
+        "Longitude of the ascending node",
+    );
+    let Anomaly::True(true_anomaly) = found.anomaly else {
+        panic!("Expected a true anomaly");
+    };
+    assert_angle(true_anomaly, angles[3], "True anomaly");
+}
+
+#[test]
+fn elliptic_round_trip() {
+    round_trip(1.5e11, 0.3, [25.0, 40.0, 70.0, 110.0]);
+    round_trip(4.0e10, 0.9, [170.0, 300.0, 15.0, 250.0]);
+}
+
+#[test]
+fn parabolic_round_trip() {
+    round_trip(1.0e11, 1.0, [60.0, 200.0, 300.0, 280.0]);
+}
+
+#[test]
+fn hyperbolic_round_trip() {
+    round_trip(-2.0e11, 1.8, [130.0, 10.0, 150.0, 60.0]);
+}
+
+#[test]
+fn massless_bodies_keep_their_orbit() {
+    let (pos, vel) = convert(elements(1.5e11, 0.3, [25.0, 40.0, 70.0, 110.0]), 0.0);
+    let found = compute_orbital_params("sun".to_owned(), G_ABS * SUN_MASS * M0, pos, vel);
+    assert!((found.eccentricity - 0.3).abs() < 1e-9);
+    assert_angle(found.inclination, 25.0, "Inclination");
+}
+
+/// Mean anomaly in degrees at true anomaly `true_anomaly`, from the closed forms of
+/// Kepler's and Barker's equations.
+fn mean_anomaly(eccentricity: f64, true_anomaly: f64) -> f64 {
+    let e = eccentricity;
+    let half = (true_anomaly.to_radians() / 2.0).tan();
+    let mean = if e < 1.0 {
+        let ecc = 2.0 * (((1.0 - e) / (1.0 + e)).sqrt() * half).atan();
+        ecc - e * ecc.sin()
+    } else if e == 1.0 {
+        half + half.powi(3) / 3.0
+    } else {
+        let hyp = 2.0 * (((e - 1.0) / (e + 1.0)).sqrt() * half).atanh();
+        e * hyp.sinh() - hyp
+    };
+    mean.to_degrees()
+}
+
+/// Place a body by its mean anomaly, and check that it ends up at `angles[3]`.
+fn mean_anomaly_round_trip(semi_major_axis: f64, eccentricity: f64, angles: [f64; 4]) {
+    let mut coords = elements(semi_major_axis, eccentricity, angles);
+    coords.anomaly = Anomaly::Mean {
+        mean_anomaly: mean_anomaly(eccentricity, angles[3]),
+        time_since_epoch: 0.0,
+    };
+    let (pos, vel) = convert(coords, BODY_MASS);
+    let found = compute_orbital_params("sun".to_owned(), mu(), pos, vel);
+    let Anomaly::True(true_anomaly) = found.anomaly else {
+        panic!("Expected a true anomaly");
+    };
+    assert_angle(true_anomaly, angles[3], "True anomaly");
+}
+
+#[test]
+fn elliptic_mean_anomaly_round_trip() {
+    mean_anomaly_round_trip(1.5e11, 0.3, [25.0, 40.0, 70.0, 110.0]);
+    mean_anomaly_round_trip(4.0e10, 0.95, [170.0, 300.0, 15.0, 250.0]);
+    mean_anomaly_round_trip(8.0e11, 0.0, [5.0, 0.0, 0.0, 30.0]);
+}
+
+#[test]
+fn parabolic_mean_anomaly_round_trip() {
+    mean_anomaly_round_trip(1.0e11, 1.0, [60.0, 200.0, 300.0, 280.0]);
+    mean_anomaly_round_trip(1.0e11, 1.0, [10.0, 20.0, 30.0, 170.0]);
+}
+
+#[test]
+fn hyperbolic_mean_anomaly_round_trip() {
+    mean_anomaly_round_trip(-2.0e11, 1.8, [130.0, 10.0, 150.0, 60.0]);
+    mean_anomaly_round_trip(-5.0e10, 4.0, [30.0, 80.0, 210.0, 290.0]);
+}
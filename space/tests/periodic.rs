@@ -0,0 +1,101 @@
+use cgmath::{InnerSpace, Point3, Vector3, Zero};
+use space::{
+    BarnesHutSim, ObjectInfo, PeriodicBox, PeriodicBruteForceSim, SimulationImpl,
+    SymmetricBruteForceSim, constants::G,
+};
+
+const SIZE: f64 = 10.0;
+
+fn object(x: f64, y: f64, z: f64, mass: f64) -> ObjectInfo {
+    ObjectInfo {
+        pos: Point3::new(x, y, z),
+        vel: Vector3::new(0.0, 0.0, 0.0),
+        mass,
+        pinned: false,
+        test_particle: false,
+    }
+}
+
+/// Objects spread through the whole box, some close to its faces and corners.
+fn objects() -> Vec<ObjectInfo> {
+    (0..300)
+        .map(|i| {
+            let t = i as f64;
+            let coord = |f: f64| ((t * f).sin() * 0.499) * SIZE;
+            object(coord(0.71), coord(1.37), coord(2.03), 1.0 + (i % 3) as f64)
+        })
+        .collect()
+}
+
+#[test]
+fn pulled_towards_the_nearest_copy() {
+    let mut objects = vec![
+        object(-0.45 * SIZE, 0.0, 0.0, 1.0),
+        object(0.45 * SIZE, 0.0, 0.0, 1.0),
+    ];
+    let mut acc = vec![Vector3::zero(); 2];
+    PeriodicBruteForceSim::new(PeriodicBox::new(SIZE)).iter(&mut objects, &mut acc);
+
+    // The copy of the other object one box to the left is a tenth of a box away, and
+    // the original is nine tenths away.
+    let expected = G / (0.1 * SIZE).powi(2);
+    assert!(acc[0].x < 0.0 && acc[1].x > 0.0, "{acc:?}");
+    for acc in acc {
+        assert!(
+            (acc.magnitude() - expected).abs() < 1e-10 * expected,
+            "{acc:?}"
+        );
+    }
+}
+
+#[test]
+fn forces_do_not_depend_on_where_the_box_starts() {
+    let bounds = PeriodicBox::new(SIZE);
+    let mut objects = objects();
+    let mut acc = vec![Vector3::zero(); objects.len()];
+    PeriodicBruteForceSim::new(bounds).iter(&mut objects, &mut acc);
+
+    let offset = Vector3::new(0.3, -0.7, 0.45) * SIZE;
+    let mut shifted: Vec<_> = objects
+        .iter()
+        .map(|obj| ObjectInfo {
+            pos: bounds.wrap(obj.pos + offset),
+            ..obj.clone()
+        })
+        .collect();
+    let mut shifted_acc = vec![Vector3::zero(); shifted.len()];
+    PeriodicBruteForceSim::new(bounds).iter(&mut shifted, &mut shifted_acc);
+
+    for (acc, shifted) in acc.iter().zip(&shifted_acc) {
+        assert!(
+            (acc - shifted).magnitude() <= 1e-9 * acc.magnitude(),
+            "{acc:?} != {shifted:?}"
+        );
+    }
+}
+
+#[test]
+fn periodic_solvers_agree() {
+    let bounds = PeriodicBox::new(SIZE);
+    let mut objects = objects();
+    let mut exact = vec![Vector3::zero(); objects.len()];
+    PeriodicBruteForceSim::new(bounds).iter(&mut objects, &mut exact);
+
+    let mut symmetric = vec![Vector3::zero(); objects.len()];
+    SymmetricBruteForceSim::new()
+        .with_periodic_box(bounds)
+        .iter(&mut objects, &mut symmetric);
+    let mut tree = vec![Vector3::zero(); objects.len()];
+    BarnesHutSim::new(0.0)
+        .with_periodic_box(bounds)
+        .iter(&mut objects, &mut tree);
+
+    for acc in [symmetric, tree] {
+        for (acc, exact) in acc.iter().zip(&exact) {
+            assert!(
+                (acc - exact).magnitude() <= 1e-10 * exact.magnitude(),
+                "{acc:?} != {exact:?}"
+            );
+        }
+    }
+}
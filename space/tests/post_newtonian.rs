@@ -0,0 +1,105 @@
+use cgmath::{InnerSpace, Point3, Vector3};
+use space::{
+    BruteForceSim, ForceProvider, ForcesSim, ObjectInfo, PostNewtonianForce, SimulationImpl,
+    constants::{AU, C, G, M0},
+};
+
+const SUN_MASS: f64 = 1.989e30 / M0;
+const MERCURY_MASS: f64 = 3.301e23 / M0;
+const SEMI_MAJOR_AXIS: f64 = 5.7909e10 / AU;
+const ECCENTRICITY: f64 = 0.2056;
+const TIME_STEP: f64 = 300.0;
+const ORBITS: usize = 20;
+
+const ARCSEC_PER_RAD: f64 = 180.0 * 3600.0 / std::f64::consts::PI;
+const SEC_PER_CENTURY: f64 = 100.0 * 365.25 * 24.0 * 3600.0;
+
+/// Sun and Mercury, with Mercury at perihelion and zero total momentum.
+fn sun_mercury() -> Vec<ObjectInfo> {
+    let mu = G * (SUN_MASS + MERCURY_MASS);
+    let perihelion = SEMI_MAJOR_AXIS * (1.0 - ECCENTRICITY);
+    let speed = (mu * (1.0 + ECCENTRICITY) / perihelion).sqrt();
+    let ratio = MERCURY_MASS / (SUN_MASS + MERCURY_MASS);
+    vec![
+        ObjectInfo {
+            pos: Point3::new(-perihelion * ratio, 0.0, 0.0),
+            vel: Vector3::new(0.0, -speed * ratio, 0.0),
+            mass: SUN_MASS,
+            pinned: false,
+            test_particle: false,
+        },
+        ObjectInfo {
+            pos: Point3::new(perihelion * (1.0 - ratio), 0.0, 0.0),
+            vel: Vector3::new(0.0, speed * (1.0 - ratio), 0.0),
+            mass: MERCURY_MASS,
+            pinned: false,
+            test_particle: false,
+        },
+    ]
+}
+
+fn period() -> f64 {
+    let mu = G * (SUN_MASS + MERCURY_MASS);
+    2.0 * std::f64::consts::PI * (SEMI_MAJOR_AXIS.powi(3) / mu).sqrt()
+}
+
+/// Direction of the perihelion, from the Laplace-Runge-Lenz vector of the relative orbit.
+fn perihelion_angle(objects: &[ObjectInfo]) -> f64 {
+    let mu = G * (SUN_MASS + MERCURY_MASS);
+    let rel = objects[1].pos - objects[0].pos;
+    let vel = objects[1].vel - objects[0].vel;
+    let lrl = vel.cross(rel.cross(vel)) - rel.normalize() * mu;
+    lrl.y.atan2(lrl.x)
+}
+
+/// Integrate for a number of whole orbits, the same way the simulation loop does.
+fn run(sim: &mut impl SimulationImpl) -> f64 {
+    let mut objects = sun_mercury();
+    let mut acc = vec![Vector3::new(0.0, 0.0, 0.0); objects.len()];
+    let steps = (ORBITS as f64 * period() / TIME_STEP).round() as usize;
+    for _ in 0..steps {
+        sim.iter_single_threaded(&mut objects, &mut acc);
+        for (obj, acc) in objects.iter_mut().zip(acc.iter_mut()) {
+            obj.vel += *acc * TIME_STEP;
+            obj.pos += obj.vel * TIME_STEP;
+            *acc = Vector3::new(0.0, 0.0, 0.0);
+        }
+    }
+    perihelion_angle(&objects)
+}
+
+#[test]
+fn mercury_perihelion_precession() {
+    // The integrator itself also makes the orbit precess, so compare against the same
+    // run without the correction.
+    let newtonian = run(&mut BruteForceSim::new());
+    let relativistic = run(&mut ForcesSim::new(vec![
+        Box::new(BruteForceSim::new()),
+        Box::new(PostNewtonianForce::new()),
+    ]));
+    let elapsed = (TIME_STEP * (ORBITS as f64 * period() / TIME_STEP).round()) / SEC_PER_CENTURY;
+    let precession = (relativistic - newtonian) * ARCSEC_PER_RAD / elapsed;
+
+    let expected = 6.0 * std::f64::consts::PI * G * SUN_MASS
+        / (C * C * SEMI_MAJOR_AXIS * (1.0 - ECCENTRICITY * ECCENTRICITY))
+        * ARCSEC_PER_RAD
+        * SEC_PER_CENTURY
+        / period();
+    assert!(
+        (precession - expected).abs() < 0.02 * expected,
+        "Precession {precession}\"/century, expected {expected}\"/century"
+    );
+    assert!(
+        (precession - 43.0).abs() < 1.0,
+        "Precession {precession}\"/century"
+    );
+}
+
+#[test]
+fn test_particles_are_not_sources() {
+    let mut objects = sun_mercury();
+    objects[0].test_particle = true;
+    let mut acc = vec![Vector3::new(0.0, 0.0, 0.0); objects.len()];
+    PostNewtonianForce::new().add_acc_single_threaded(&mut objects, &mut acc);
+    assert!(acc.iter().all(|acc| acc.magnitude2() == 0.0), "{acc:?}");
+}
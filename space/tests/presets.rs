@@ -0,0 +1,61 @@
+use space::{
+    Object,
+    parameters::{Anomaly, RelativeOrAbsolute, StandardParams},
+    presets::{Imf, asteroid_belt, fixed_cloud},
+    rng::RngService,
+};
+
+/// Everything random about each asteroid.
+fn orbits(asteroids: &[StandardParams]) -> Vec<[f64; 8]> {
+    asteroids
+        .iter()
+        .map(|ast| {
+            let RelativeOrAbsolute::Relative(coords) = &ast.coordinates else {
+                panic!("Asteroids orbit the sun");
+            };
+            let Anomaly::True(true_anomaly) = coords.anomaly else {
+                panic!("Asteroids are placed by their true anomaly");
+            };
+            [
+                coords.semi_major_axis,
+                coords.eccentricity,
+                coords.inclination,
+                coords.arg_periapsis,
+                coords.long_asc_node,
+                true_anomaly,
+                ast.mass,
+                ast.radius as f64,
+            ]
+        })
+        .collect()
+}
+
+fn masses(objects: &[Object]) -> Vec<f64> {
+    objects.iter().map(|obj| obj.dat.mass).collect()
+}
+
+fn cloud(seed: u64) -> Vec<Object> {
+    let imf = Imf::Kroupa {
+        min: 0.1,
+        max: 10.0,
+    };
+    fixed_cloud(100, imf, &RngService::new(seed))
+}
+
+#[test]
+fn asteroid_belt_follows_the_seed() {
+    let belt = |seed| orbits(&asteroid_belt(50, &RngService::new(seed)));
+    assert_eq!(belt(1), belt(1));
+    assert_ne!(belt(1), belt(2));
+    // Each asteroid has a stream of its own.
+    assert_eq!(
+        orbits(&asteroid_belt(20, &RngService::new(1))),
+        belt(1)[..20]
+    );
+}
+
+#[test]
+fn fixed_cloud_follows_the_seed() {
+    assert_eq!(masses(&cloud(1)), masses(&cloud(1)));
+    assert_ne!(masses(&cloud(1)), masses(&cloud(2)));
+}
@@ -0,0 +1,102 @@
+use std::io::Cursor;
+
+use space::{
+    SimTime,
+    recording::{RecordedObject, RecordingReader, RecordingWriter},
+};
+
+const TOLERANCE: f32 = 1e-3;
+const FRAMES: usize = 10;
+
+fn objects() -> Vec<RecordedObject> {
+    (0..3)
+        .map(|i| RecordedObject {
+            name: format!("object_{i}"),
+            color: [1.0, 0.5, 0.25],
+            radius: 0.1 * i as f32,
+            tolerance: TOLERANCE,
+        })
+        .collect()
+}
+
+fn positions(frame: usize) -> Vec<[f32; 3]> {
+    (0..3)
+        .map(|i| {
+            let t = frame as f32 * 0.37 + i as f32;
+            [t.cos() * 5.0, t.sin() * 5.0, -0.01 * frame as f32]
+        })
+        .collect()
+}
+
+fn write(frames: &[Vec<[f32; 3]>], keyframe_interval: u32) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let mut writer = RecordingWriter::new(&mut bytes, &objects(), keyframe_interval).unwrap();
+    let mut time = SimTime::new(0, 60.0);
+    for positions in frames {
+        writer.write_frame(time, positions).unwrap();
+        time.advance();
+    }
+    writer.flush().unwrap();
+    drop(writer);
+    bytes
+}
+
+#[test]
+fn round_trip_with_seeking() {
+    let frames: Vec<_> = (0..FRAMES).map(positions).collect();
+    let mut reader = RecordingReader::new(Cursor::new(write(&frames, 4))).unwrap();
+    assert_eq!(reader.num_frames(), FRAMES);
+    assert_eq!(reader.objects()[2].name, "object_2");
+    assert_eq!(reader.objects()[2].radius, 0.2);
+
+    // In order, then backwards across keyframes, then forwards within a span.
+    let mut out = Vec::new();
+    for idx in (0..FRAMES).chain([9, 2, 7, 0, 5, 6, 3]) {
+        reader.read_frame(idx, &mut out).unwrap();
+        assert_eq!(reader.frame_info(idx).unwrap().tick, idx as u64);
+        for (read, written) in out.iter().zip(&frames[idx]) {
+            for i in 0..3 {
+                assert!(
+                    (read[i] - written[i]).abs() <= TOLERANCE / 2.0 + 1e-6,
+                    "Frame {idx}: read {read:?}, wrote {written:?}"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn saturated_values_round_trip() {
+    // Quantizing these saturates, and the deltas between them overflow.
+    let frames: Vec<_> = (0..FRAMES)
+        .map(|frame| {
+            let sign = if frame % 2 == 0 { 1.0 } else { -1.0 };
+            vec![[sign * f32::MAX, -sign * f32::MAX, 0.0]; 3]
+        })
+        .collect();
+    let mut deltas = RecordingReader::new(Cursor::new(write(&frames, 4))).unwrap();
+    let mut keyframes = RecordingReader::new(Cursor::new(write(&frames, 1))).unwrap();
+    let (mut a, mut b) = (Vec::new(), Vec::new());
+    for idx in 0..FRAMES {
+        deltas.read_frame(idx, &mut a).unwrap();
+        keyframes.read_frame(idx, &mut b).unwrap();
+        assert_eq!(a, b, "Frame {idx}");
+    }
+}
+
+#[test]
+fn corrupt_headers_are_refused() {
+    let bytes = write(&[positions(0)], 4);
+    // Cut off in the middle of the objects.
+    assert!(RecordingReader::new(Cursor::new(bytes[..30].to_vec())).is_err());
+
+    // Far more objects than the file could hold.
+    let mut huge = bytes.clone();
+    huge[12..16].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert!(RecordingReader::new(Cursor::new(huge)).is_err());
+
+    // A name longer than the file.
+    let mut long_name = bytes;
+    long_name[40..44].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert!(RecordingReader::new(Cursor::new(long_name)).is_err());
+}
@@ -0,0 +1,95 @@
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector3};
+use space::{
+    BruteForceSim, DormandPrince, ObjectInfo,
+    constants::{AU, G, SOLAR_MASS},
+};
+
+const PLANET_MASS: f64 = 1.0;
+const TOLERANCE: f64 = 1e-10;
+
+fn body(pos: Vector3<f64>, vel: Vector3<f64>, mass: f64) -> ObjectInfo {
+    ObjectInfo {
+        pos: Point3::from_vec(pos),
+        vel,
+        mass,
+        pinned: false,
+        test_particle: false,
+    }
+}
+
+fn period() -> f64 {
+    2.0 * std::f64::consts::PI / (G * (SOLAR_MASS + PLANET_MASS)).sqrt()
+}
+
+/// A planet on a circular orbit of 1 AU around the sun, with the center of mass at rest.
+fn sun_and_planet() -> Vec<ObjectInfo> {
+    let mu = G * (SOLAR_MASS + PLANET_MASS);
+    let sun_share = PLANET_MASS / (SOLAR_MASS + PLANET_MASS);
+    let rel = Vector3::new(1.0, 0.0, 0.0);
+    let rel_vel = Vector3::new(0.0, mu.sqrt(), 0.0);
+    vec![
+        body(-rel * sun_share, -rel_vel * sun_share, SOLAR_MASS),
+        body(
+            rel * (1.0 - sun_share),
+            rel_vel * (1.0 - sun_share),
+            PLANET_MASS,
+        ),
+    ]
+}
+
+#[test]
+fn circular_orbit_returns_after_one_period() {
+    let mut objects = sun_and_planet();
+    let start = objects.clone();
+    let mut sim = BruteForceSim::new();
+    let mut rk45 = DormandPrince::new(TOLERANCE);
+    let ticks = 100;
+    let delta = period() / ticks as f64;
+    for _ in 0..ticks {
+        rk45.advance(&mut objects, &mut sim, delta);
+    }
+
+    for (obj, start) in objects.iter().zip(&start) {
+        let miss = (obj.pos - start.pos).magnitude();
+        assert!(miss < 1e-6, "Missed the start by {miss} AU");
+        let miss = (obj.vel - start.vel).magnitude() / start.vel.magnitude();
+        assert!(miss < 1e-6, "Velocity off by {miss}");
+    }
+    let stats = rk45.take_stats();
+    assert!(stats.step > 0.0 && stats.step <= delta, "{stats:?}");
+}
+
+#[test]
+fn close_encounter_rejects_steps() {
+    // A comet passing the sun at 15 000 km, a day's travel from where it starts out.
+    let speed = 50e3 / AU;
+    let mut objects = vec![
+        body(
+            Vector3::new(0.0, 0.0, 0.0),
+            Vector3::new(0.0, 0.0, 0.0),
+            SOLAR_MASS,
+        ),
+        body(
+            Vector3::new(-1.0, 1e-4, 0.0),
+            Vector3::new(speed, 0.0, 0.0),
+            1.0,
+        ),
+    ];
+    let mut sim = BruteForceSim::new();
+    let mut rk45 = DormandPrince::new(TOLERANCE);
+    let delta = 24.0 * 3600.0;
+    // Settle on a step size far from the sun, past the first guess of a whole tick.
+    for _ in 0..10 {
+        rk45.advance(&mut objects, &mut sim, delta);
+    }
+    let far = rk45.take_stats();
+    assert_eq!(far.rejection_rate, 0.0, "{far:?}");
+
+    let encounter = (1.0 / speed / delta) as usize;
+    for _ in 10..encounter + 10 {
+        rk45.advance(&mut objects, &mut sim, delta);
+    }
+    let close = rk45.take_stats();
+    assert!(close.rejection_rate > 0.0, "{close:?}");
+    assert!(close.step > 0.0 && close.step < delta, "{close:?}");
+}
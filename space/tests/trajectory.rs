@@ -0,0 +1,45 @@
+use cgmath::{Point3, Vector3};
+use space::{Object, ObjectInfo, SimTime, trajectory::TrajectoryLog};
+
+fn object(name: &str, x: f64) -> Object {
+    Object {
+        name: name.to_owned(),
+        dat: ObjectInfo {
+            pos: Point3::new(x, 0.0, 0.0),
+            vel: Vector3::new(0.0, 1e-7, 0.0),
+            mass: 1.0,
+            pinned: false,
+            test_particle: false,
+        },
+        color: Vector3::new(1.0, 1.0, 1.0),
+        radius: 1.0,
+        oblateness: None,
+        radiation: None,
+        maneuvers: Vec::new(),
+        mass_rate: 0.0,
+        texture: None,
+    }
+}
+
+#[test]
+fn names_are_quoted_like_rfc_4180() {
+    let objects = vec![object("Sun", 0.0), object("Ceres, \"the\" dwarf", 2.77)];
+    let names: Vec<_> = objects.iter().map(|obj| obj.name.clone()).collect();
+    let path = std::env::temp_dir().join(format!("trajectory-{}.csv", std::process::id()));
+    let mut log = TrajectoryLog::create(&path, &names, &objects, 1).unwrap();
+    let infos: Vec<_> = objects.iter().map(|obj| obj.dat.clone()).collect();
+    log.log(SimTime::new(0, 60.0), &infos).unwrap();
+    log.flush().unwrap();
+    let csv = std::fs::read_to_string(&path);
+    std::fs::remove_file(&path).unwrap();
+
+    let csv = csv.unwrap();
+    let rows: Vec<_> = csv.lines().collect();
+    assert_eq!(rows.len(), 3, "{csv}");
+    assert!(rows[1].starts_with("Sun,0,0,"), "{}", rows[1]);
+    assert!(
+        rows[2].starts_with("\"Ceres, \"\"the\"\" dwarf\",0,0,2.77,"),
+        "{}",
+        rows[2]
+    );
+}